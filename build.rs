@@ -0,0 +1,10 @@
+// Compile proto/meshmind.proto for the gRPC service. protox is a pure-Rust protobuf
+// compiler, so building does not require protoc to be installed.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/meshmind.proto");
+    let descriptors = protox::compile(["proto/meshmind.proto"], ["proto"])?;
+    tonic_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)?;
+    Ok(())
+}
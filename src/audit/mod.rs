@@ -0,0 +1,78 @@
+// Append-only audit log of policy decisions (moderation blocks and flags, ...), one JSON object
+// per line in audit.log (AUDIT_LOG_PATH to override). GET /api/audit returns the newest entries.
+use actix_web::{get, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub event: String,
+    // Who caused it: a chat sender, peer IP or "system"
+    pub actor: String,
+    pub details: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    event: Option<String>,
+    limit: Option<usize>,
+}
+
+lazy_static! {
+    // Serializes appends so concurrent entries never interleave
+    static ref AUDIT_LOCK: Arc<Mutex<()>> = Arc::new(Mutex::new(()));
+}
+
+fn log_path() -> String {
+    std::env::var("AUDIT_LOG_PATH").unwrap_or_else(|_| "audit.log".to_string())
+}
+
+pub async fn record(event: &str, actor: &str, details: serde_json::Value) {
+    let entry = AuditEntry { timestamp: Utc::now(), event: event.to_string(), actor: actor.to_string(), details };
+    let mut line = match serde_json::to_string(&entry) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("AUDIT: Failed to serialize entry: {}", e);
+            return;
+        }
+    };
+    line.push('\n');
+    let _guard = AUDIT_LOCK.lock().await;
+    let file = tokio::fs::OpenOptions::new().create(true).append(true).open(log_path()).await;
+    let result = match file {
+        Ok(mut f) => f.write_all(line.as_bytes()).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("AUDIT: Failed to write {}: {}", log_path(), e);
+    }
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can read the audit log" }))
+}
+
+#[get("/audit")]
+pub async fn list_audit(query: web::Query<AuditQuery>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let content = {
+        let _guard = AUDIT_LOCK.lock().await;
+        tokio::fs::read_to_string(log_path()).await.unwrap_or_default()
+    };
+    let entries: Vec<AuditEntry> = content
+        .lines()
+        .rev()
+        .filter_map(|l| serde_json::from_str::<AuditEntry>(l).ok())
+        .filter(|e| query.event.as_deref().is_none_or(|ev| e.event == ev))
+        .take(limit)
+        .collect();
+    Ok(HttpResponse::Ok().json(entries))
+}
@@ -0,0 +1,187 @@
+// Bandwidth accounting for /api/analytics/network. Bytes this node sends (up) and receives (down)
+// are counted where they cross the network: TCP frames to and from peers, the HTTP proxies that
+// fetch a peer's files for our users, and the file endpoints (downloads, uploads, media streams,
+// replica segments and dedup chunks, served or fetched). Each count lands in per-second slots
+// covering the last five minutes, kept for all traffic, per channel and per peer, from which the
+// rates over each window are worked out in bits per second. Traffic with a browser or another
+// client is in the totals and its channel but not under any peer.
+use actix_web::HttpRequest;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// Name and length in seconds of the windows rates are reported over; the first is the current rate
+pub const WINDOWS: [(&str, u64); 3] = [("10s", 10), ("1m", 60), ("5m", 300)];
+const SLOTS: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Tcp,
+    Proxy,
+    Files,
+}
+
+impl Channel {
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Tcp => "tcp",
+            Channel::Proxy => "proxy",
+            Channel::Files => "files",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    second: u64,
+    up: u64,
+    down: u64,
+}
+
+// Bytes per second over the last SLOTS seconds, plus the totals since startup
+#[derive(Debug, Clone)]
+pub struct Meter {
+    slots: Vec<Slot>,
+    up_bytes: u64,
+    down_bytes: u64,
+}
+
+impl Default for Meter {
+    fn default() -> Meter {
+        Meter { slots: vec![Slot::default(); SLOTS], up_bytes: 0, down_bytes: 0 }
+    }
+}
+
+impl Meter {
+    pub fn add(&mut self, second: u64, direction: Direction, bytes: u64) {
+        let slot = &mut self.slots[(second % SLOTS as u64) as usize];
+        if slot.second != second {
+            *slot = Slot { second, up: 0, down: 0 };
+        }
+        match direction {
+            Direction::Up => {
+                slot.up += bytes;
+                self.up_bytes += bytes;
+            }
+            Direction::Down => {
+                slot.down += bytes;
+                self.down_bytes += bytes;
+            }
+        }
+    }
+
+    // Average (up, down) bits per second over the `window` seconds up to and including `second`
+    pub fn rates(&self, second: u64, window: u64) -> (u64, u64) {
+        let window = window.clamp(1, SLOTS as u64);
+        let (up, down) = self
+            .slots
+            .iter()
+            .filter(|s| s.second <= second && second - s.second < window && (s.up > 0 || s.down > 0))
+            .fold((0, 0), |(up, down), s| (up + s.up, down + s.down));
+        (up * 8 / window, down * 8 / window)
+    }
+
+    pub fn report(&self, second: u64) -> serde_json::Value {
+        let (up_bps, down_bps) = self.rates(second, WINDOWS[0].1);
+        let windows: serde_json::Map<String, serde_json::Value> = WINDOWS
+            .iter()
+            .map(|(name, secs)| {
+                let (up, down) = self.rates(second, *secs);
+                (name.to_string(), serde_json::json!({ "up_bps": up, "down_bps": down }))
+            })
+            .collect();
+        serde_json::json!({
+            "up_bps": up_bps,
+            "down_bps": down_bps,
+            "windows": windows,
+            "up_bytes": self.up_bytes,
+            "down_bytes": self.down_bytes
+        })
+    }
+}
+
+struct Accounts {
+    started: Instant,
+    total: Meter,
+    channels: HashMap<Channel, Meter>,
+    peers: HashMap<String, Meter>,
+}
+
+impl Accounts {
+    fn second(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+}
+
+static ACCOUNTS: Lazy<Mutex<Accounts>> = Lazy::new(|| {
+    Mutex::new(Accounts { started: Instant::now(), total: Meter::default(), channels: HashMap::new(), peers: HashMap::new() })
+});
+
+// Count bytes sent or received; `peer` is the mesh peer on the other end, if any
+pub fn record(channel: Channel, peer: Option<&str>, direction: Direction, bytes: usize) {
+    if bytes == 0 {
+        return;
+    }
+    let mut accounts = ACCOUNTS.lock().unwrap();
+    let second = accounts.second();
+    let bytes = bytes as u64;
+    accounts.total.add(second, direction, bytes);
+    accounts.channels.entry(channel).or_default().add(second, direction, bytes);
+    if let Some(peer) = peer {
+        accounts.peers.entry(peer.to_string()).or_default().add(second, direction, bytes);
+    }
+}
+
+// The peer behind an HTTP request, for requests peers make for their users (see popularity::Via)
+pub fn requesting_peer(req: &HttpRequest) -> Option<String> {
+    match crate::popularity::Via::of(req) {
+        crate::popularity::Via::Peer => crate::proxy::request_client_ip(req).map(|ip| ip.to_string()),
+        crate::popularity::Via::Local => None,
+    }
+}
+
+// Rates and totals for all traffic, per channel and per peer
+pub fn report() -> serde_json::Value {
+    let accounts = ACCOUNTS.lock().unwrap();
+    let second = accounts.second();
+    let mut report = accounts.total.report(second);
+    let channels: serde_json::Map<String, serde_json::Value> = [Channel::Tcp, Channel::Proxy, Channel::Files]
+        .iter()
+        .map(|c| (c.name().to_string(), accounts.channels.get(c).cloned().unwrap_or_default().report(second)))
+        .collect();
+    let peers: serde_json::Map<String, serde_json::Value> = accounts.peers.iter().map(|(ip, meter)| (ip.clone(), meter.report(second))).collect();
+    report["by_channel"] = serde_json::Value::Object(channels);
+    report["peers"] = serde_json::Value::Object(peers);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Meter};
+
+    // Bandwidth rates average the per-second counts over each window; older seconds fall out
+    #[test]
+    fn bandwidth_rates_over_sliding_windows() {
+        let mut meter = Meter::default();
+        meter.add(100, Direction::Up, 1000);
+        meter.add(100, Direction::Down, 500);
+        meter.add(105, Direction::Up, 250);
+        assert_eq!(meter.rates(105, 10), (1250 * 8 / 10, 500 * 8 / 10));
+        assert_eq!(meter.rates(105, 5), (250 * 8 / 5, 0), "second 100 is outside the last 5s");
+        assert_eq!(meter.rates(399, 300), (1250 * 8 / 300, 500 * 8 / 300));
+        // A slot reused 300 seconds on holds only the new second's bytes
+        meter.add(400, Direction::Up, 80);
+        assert_eq!(meter.rates(400, 10), (64, 0));
+        assert_eq!(meter.rates(400, 300), ((250 + 80) * 8 / 300, 0));
+        let report = meter.report(400);
+        assert_eq!(report["up_bytes"], 1330);
+        assert_eq!(report["windows"]["1m"]["up_bps"], 80 * 8 / 60);
+    }
+}
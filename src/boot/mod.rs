@@ -0,0 +1,115 @@
+// Node identity and capabilities put into the embedded UI's index.html as it is served, so the UI
+// can show which node it talks to and what it offers without asking /api/status first. The page
+// gets a `window.__MESHMIND__` object, its `<html lang>` set to the browser's preferred language,
+// and the placeholders %NODE_NAME%, %NODE_ID%, %NODE_VERSION% and %LOCALE% replaced wherever the
+// bundle uses them (e.g. in the <title>).
+use actix_web::HttpRequest;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BootConfig {
+    pub node_id: String,
+    pub node_name: String,
+    pub version: String,
+    // The browser's preferred language from Accept-Language, e.g. "de-AT"
+    pub locale: String,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Features {
+    pub llm_host: bool,
+    pub tls: bool,
+    pub oidc: bool,
+    pub desktop: bool,
+    pub base_path: String,
+    // Optional protocol features this node offers its peers, see tcp::capability
+    pub capabilities: Vec<&'static str>,
+}
+
+const DEFAULT_LOCALE: &str = "en";
+
+// The first language in an Accept-Language header, ignoring "*" and quality values; tags are
+// kept only when made of letters, digits and hyphens so they can go into the page as they are
+pub fn locale(accept_language: Option<&str>) -> String {
+    let mut tags: Vec<(&str, f32)> = accept_language
+        .unwrap_or("")
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.split(';');
+            let tag = pieces.next()?.trim();
+            let quality = pieces
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            let valid = !tag.is_empty()
+                && tag.len() <= 35
+                && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && tag.chars().next().is_some_and(|c| c.is_ascii_alphabetic());
+            (valid && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable, so equally weighted languages keep the browser's order
+    tags.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    tags.first().map(|(tag, _)| tag.to_string()).unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+}
+
+// What this node tells the UI loaded from `req`
+pub async fn config(req: &HttpRequest) -> BootConfig {
+    let accept_language = req.headers().get("accept-language").and_then(|v| v.to_str().ok());
+    BootConfig {
+        node_id: crate::persistence::node_id().to_string(),
+        node_name: hostname::get().map(|h| h.to_string_lossy().to_string()).unwrap_or_default(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        locale: locale(accept_language),
+        features: Features {
+            llm_host: crate::tcp::is_llm_available().await,
+            tls: crate::tls::enabled(),
+            oidc: crate::users::oidc::enabled(),
+            desktop: cfg!(feature = "desktop"),
+            base_path: crate::proxy::base_path().to_string(),
+            capabilities: crate::tcp::local_capabilities(),
+        },
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// index.html with `config` filled in; None when the page is not UTF-8
+pub fn inject(body: &[u8], config: &BootConfig) -> Option<Vec<u8>> {
+    let mut text = String::from_utf8(body.to_vec()).ok()?;
+    for (placeholder, value) in [
+        ("%NODE_NAME%", &config.node_name),
+        ("%NODE_ID%", &config.node_id),
+        ("%NODE_VERSION%", &config.version),
+        ("%LOCALE%", &config.locale),
+    ] {
+        text = text.replace(placeholder, &escape_html(value));
+    }
+    if let Some(start) = text.find("<html") {
+        let end = text[start..].find('>').map(|i| start + i).unwrap_or(text.len());
+        let lang = format!("lang=\"{}\"", escape_html(&config.locale));
+        let tag = &text[start..end];
+        let new_tag = match tag.find("lang=\"") {
+            Some(at) => {
+                let value_end = tag[at + 6..].find('"').map(|i| at + 6 + i + 1).unwrap_or(tag.len());
+                format!("{}{}{}", &tag[..at], lang, &tag[value_end..])
+            }
+            None => format!("{} {}", tag, lang),
+        };
+        text.replace_range(start..end, &new_tag);
+    }
+    if let Some(head) = text.find("<head>") {
+        // "<" escaped so no value can close the script element
+        let json = serde_json::to_string(config).ok()?.replace('<', "\\u003c");
+        text.insert_str(head + "<head>".len(), &format!("<script>window.__MESHMIND__={};</script>", json));
+    }
+    Some(text.into_bytes())
+}
@@ -0,0 +1,57 @@
+// Peer file catalogs over TCP. /api/files merges in the files connected peers hold; each one is
+// asked with a FLSQ: frame on the authenticated peer connection and answers with a FLSR: frame
+// listing its own uploads and the peer binaries it received, limited to files shared with the
+// mesh and, for files scoped to a group, to peers in that group. Nothing goes through the peers'
+// HTTP API, so a peer's list can't be had by anyone sending the x-peer-llm header. A peer does not
+// pass on the lists it got from others; every node asks its own peers.
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex};
+use crate::persistence::FileInfo;
+
+// Most files one answer lists, newest uploads first
+pub const MAX_FILES: usize = 10_000;
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(6);
+
+// Request id -> (peer asked, where its answer goes)
+type PendingRequests = HashMap<u64, (String, oneshot::Sender<Vec<FileInfo>>)>;
+
+static PENDING: Lazy<Mutex<PendingRequests>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// What we answer a peer's FLSQ: with
+pub async fn listing(peer_ip: &str) -> Vec<FileInfo> {
+    let mut files = crate::persistence::list_uploaded_files().await.unwrap_or_default();
+    files.extend(crate::persistence::list_received_files().await.unwrap_or_default());
+    files.retain(|f| f.visible_to(None) && crate::groups::shares_with(peer_ip, f.group.as_deref()));
+    files.truncate(MAX_FILES);
+    files
+}
+
+// A FLSR: answer; ignored unless it answers our request to that peer
+pub async fn response_received(peer_ip: &str, request_id: u64, files: Vec<FileInfo>) {
+    let mut pending = PENDING.lock().await;
+    if pending.get(&request_id).is_some_and(|(peer, _)| peer == peer_ip) {
+        if let Some((_, sender)) = pending.remove(&request_id) {
+            let _ = sender.send(files);
+        }
+    }
+}
+
+// The files a connected peer lists
+pub async fn fetch(peer_ip: &str) -> std::io::Result<Vec<FileInfo>> {
+    let request_id: u64 = rand::random();
+    let (sender, receiver) = oneshot::channel();
+    PENDING.lock().await.insert(request_id, (peer_ip.to_string(), sender));
+    if let Err(e) = crate::tcp::request_file_list(peer_ip, request_id).await {
+        PENDING.lock().await.remove(&request_id);
+        return Err(e);
+    }
+    match tokio::time::timeout(RESPONSE_TIMEOUT, receiver).await {
+        Ok(Ok(files)) => Ok(files),
+        _ => {
+            PENDING.lock().await.remove(&request_id);
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("Peer {} did not answer", peer_ip)))
+        }
+    }
+}
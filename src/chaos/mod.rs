@@ -0,0 +1,231 @@
+// Fault injection for resilience testing, off unless configured at startup and only in debug
+// builds. A share of the TCP frames and UDP announcements this node sends is dropped, held back or
+// corrupted, so retries, acknowledgements and conversation merging can be tried out without
+// tampering with the network. CHAOS_DROP_PERCENT, CHAOS_DELAY_PERCENT and CHAOS_CORRUPT_PERCENT
+// set the shares (together at most 100), CHAOS_DELAY_MS the longest delay (default 1000) and
+// CHAOS_TARGETS what is disturbed (`tcp`, `udp` or both, the default). A TCP frame is disturbed as
+// it goes on the wire, after compression, and a corrupted one has a single bit flipped in its
+// payload; its header stays intact so the peer reads the frame whole. Only what this node sends
+// is touched: enable it on every node to disturb both directions.
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEFAULT_MAX_DELAY: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Tcp,
+    Udp,
+}
+
+// What happens to one frame or packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    Drop,
+    Delay(Duration),
+    Corrupt,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Config {
+    pub drop_percent: f64,
+    pub delay_percent: f64,
+    pub corrupt_percent: f64,
+    #[serde(serialize_with = "as_millis")]
+    pub max_delay: Duration,
+    pub tcp: bool,
+    pub udp: bool,
+}
+
+fn as_millis<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(d.as_millis() as u64)
+}
+
+fn percent(lookup: &impl Fn(&str) -> Option<String>, key: &str) -> Result<f64, String> {
+    match lookup(key).filter(|v| !v.trim().is_empty()) {
+        None => Ok(0.0),
+        Some(v) => match v.trim().parse::<f64>() {
+            Ok(p) if (0.0..=100.0).contains(&p) => Ok(p),
+            _ => Err(format!("{} must be a percentage from 0 to 100, got {:?}", key, v)),
+        },
+    }
+}
+
+impl Config {
+    // None when no fault is configured
+    pub fn parse(lookup: impl Fn(&str) -> Option<String>) -> Result<Option<Config>, String> {
+        let drop_percent = percent(&lookup, "CHAOS_DROP_PERCENT")?;
+        let delay_percent = percent(&lookup, "CHAOS_DELAY_PERCENT")?;
+        let corrupt_percent = percent(&lookup, "CHAOS_CORRUPT_PERCENT")?;
+        if drop_percent + delay_percent + corrupt_percent > 100.0 {
+            return Err("CHAOS_DROP_PERCENT, CHAOS_DELAY_PERCENT and CHAOS_CORRUPT_PERCENT add up to more than 100".to_string());
+        }
+        if drop_percent + delay_percent + corrupt_percent == 0.0 {
+            return Ok(None);
+        }
+        let max_delay = match lookup("CHAOS_DELAY_MS").filter(|v| !v.trim().is_empty()) {
+            None => DEFAULT_MAX_DELAY,
+            Some(v) => Duration::from_millis(v.trim().parse().map_err(|_| format!("CHAOS_DELAY_MS must be milliseconds, got {:?}", v))?),
+        };
+        let (mut tcp, mut udp) = (false, false);
+        for target in lookup("CHAOS_TARGETS").unwrap_or_else(|| "tcp,udp".to_string()).split(',').map(|t| t.trim().to_lowercase()) {
+            match target.as_str() {
+                "tcp" => tcp = true,
+                "udp" => udp = true,
+                "" => {}
+                other => return Err(format!("unknown CHAOS_TARGETS entry {:?} (expected tcp or udp)", other)),
+            }
+        }
+        Ok(Some(Config { drop_percent, delay_percent, corrupt_percent, max_delay, tcp, udp }))
+    }
+
+    // The fault for a frame given a roll in [0, 100) and a fraction in [0, 1) picking the delay
+    pub fn fault(&self, channel: Channel, roll: f64, delay_fraction: f64) -> Option<Fault> {
+        let targeted = match channel {
+            Channel::Tcp => self.tcp,
+            Channel::Udp => self.udp,
+        };
+        if !targeted {
+            return None;
+        }
+        if roll < self.drop_percent {
+            Some(Fault::Drop)
+        } else if roll < self.drop_percent + self.delay_percent {
+            Some(Fault::Delay(self.max_delay.mul_f64(delay_fraction)))
+        } else if roll < self.drop_percent + self.delay_percent + self.corrupt_percent {
+            Some(Fault::Corrupt)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    dropped: AtomicU64,
+    delayed: AtomicU64,
+    corrupted: AtomicU64,
+}
+
+impl Counters {
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "dropped": self.dropped.load(Ordering::Relaxed),
+            "delayed": self.delayed.load(Ordering::Relaxed),
+            "corrupted": self.corrupted.load(Ordering::Relaxed)
+        })
+    }
+}
+
+static TCP: OnceLock<Counters> = OnceLock::new();
+static UDP: OnceLock<Counters> = OnceLock::new();
+
+fn counters(channel: Channel) -> &'static Counters {
+    match channel {
+        Channel::Tcp => TCP.get_or_init(Counters::default),
+        Channel::Udp => UDP.get_or_init(Counters::default),
+    }
+}
+
+pub fn config() -> Option<&'static Config> {
+    static CONFIG: OnceLock<Option<Config>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let config = Config::parse(|key| std::env::var(key).ok()).unwrap_or_else(|e| {
+                eprintln!("CHAOS: {}, fault injection off", e);
+                None
+            })?;
+            if !cfg!(debug_assertions) {
+                eprintln!("CHAOS: Fault injection is only available in debug builds, ignoring CHAOS_* settings");
+                return None;
+            }
+            eprintln!(
+                "CHAOS: Fault injection on: dropping {}%, delaying {}% (up to {}ms) and corrupting {}% of {} traffic",
+                config.drop_percent,
+                config.delay_percent,
+                config.max_delay.as_millis(),
+                config.corrupt_percent,
+                match (config.tcp, config.udp) {
+                    (true, true) => "TCP and UDP",
+                    (true, false) => "TCP",
+                    (false, true) => "UDP",
+                    (false, false) => "no",
+                },
+            );
+            Some(config)
+        })
+        .as_ref()
+}
+
+// Disturb an outgoing frame or packet: false when it is to be dropped, otherwise it is sent once
+// any delay is over, possibly corrupted
+pub async fn disturb(channel: Channel, payload: &mut [u8]) -> bool {
+    let Some(config) = config() else {
+        return true;
+    };
+    let counters = counters(channel);
+    match config.fault(channel, rand::random::<f64>() * 100.0, rand::random::<f64>()) {
+        None => true,
+        Some(Fault::Drop) => {
+            counters.dropped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+        Some(Fault::Delay(delay)) => {
+            counters.delayed.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(delay).await;
+            true
+        }
+        Some(Fault::Corrupt) => {
+            if !payload.is_empty() {
+                let bit = rand::random::<usize>() % (payload.len() * 8);
+                payload[bit / 8] ^= 1 << (bit % 8);
+                counters.corrupted.fetch_add(1, Ordering::Relaxed);
+            }
+            true
+        }
+    }
+}
+
+// The configuration and what was disturbed so far, for /api/status; null when off
+pub fn status() -> serde_json::Value {
+    match config() {
+        Some(config) => serde_json::json!({
+            "config": config,
+            "tcp": counters(Channel::Tcp).snapshot(),
+            "udp": counters(Channel::Udp).snapshot()
+        }),
+        None => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Channel, Config, Duration, Fault};
+
+    // Fault injection shares split one roll: drops first, then delays, then corruption
+    #[test]
+    fn chaos_faults_follow_the_configured_shares() {
+        let env = |vars: &'static [(&'static str, &'static str)]| move |key: &str| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string());
+        assert_eq!(Config::parse(env(&[])), Ok(None));
+        assert!(Config::parse(env(&[("CHAOS_DROP_PERCENT", "60"), ("CHAOS_CORRUPT_PERCENT", "50")])).is_err());
+        assert!(Config::parse(env(&[("CHAOS_DROP_PERCENT", "-1")])).is_err());
+        assert!(Config::parse(env(&[("CHAOS_DROP_PERCENT", "1"), ("CHAOS_TARGETS", "http")])).is_err());
+
+        let config = Config::parse(env(&[
+            ("CHAOS_DROP_PERCENT", "10"),
+            ("CHAOS_DELAY_PERCENT", "20"),
+            ("CHAOS_CORRUPT_PERCENT", "5"),
+            ("CHAOS_DELAY_MS", "400"),
+            ("CHAOS_TARGETS", "tcp"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.fault(Channel::Tcp, 9.9, 0.0), Some(Fault::Drop));
+        assert_eq!(config.fault(Channel::Tcp, 10.0, 0.5), Some(Fault::Delay(Duration::from_millis(200))));
+        assert_eq!(config.fault(Channel::Tcp, 34.9, 0.0), Some(Fault::Corrupt));
+        assert_eq!(config.fault(Channel::Tcp, 35.0, 0.0), None);
+        assert_eq!(config.fault(Channel::Udp, 0.0, 0.0), None, "UDP is not targeted");
+    }
+}
@@ -0,0 +1,172 @@
+// Content-defined chunk store used to deduplicate transfers of repeatedly updated files.
+// Files are split with FastCDC so an edit only changes the chunks around it; chunks are stored
+// by SHA-256 under chunks/ and a receiver only downloads the ones it does not already have.
+// Chunks are written atomically and checked against their hash when read; chunks no held file is
+// made of any more are removed by a periodic sweep.
+use actix_web::{get, web, HttpRequest, HttpResponse, Error};
+use fastcdc::v2020::FastCDC;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::persistence::{sha256_hex, write_atomic};
+
+pub const CHUNKS_DIR: &str = "chunks";
+const MIN_CHUNK: u32 = 16 * 1024;
+const AVG_CHUNK: u32 = 64 * 1024;
+const MAX_CHUNK: u32 = 256 * 1024;
+// For each chunk fetched from a peer
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(30);
+// Smaller files are sent whole; chunk bookkeeping would cost more than it saves
+pub const MIN_DEDUP_FILE_SIZE: usize = 1024 * 1024;
+// Unreferenced chunks younger than this may belong to a transfer still being sent or assembled
+const GC_GRACE: Duration = Duration::from_secs(60 * 60);
+const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkRef {
+    pub hash: String,
+    pub len: u32,
+}
+
+// Peers must all understand FILE_META v2 before this is turned on
+pub fn dedup_enabled() -> bool {
+    matches!(
+        std::env::var("DEDUP_TRANSFERS").ok().as_deref().map(|v| v.trim().to_lowercase()).as_deref(),
+        Some("1") | Some("true") | Some("yes")
+    )
+}
+
+fn chunk_path(hash: &str) -> Option<PathBuf> {
+    if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(Path::new(CHUNKS_DIR).join(hash.to_lowercase()))
+    } else {
+        None
+    }
+}
+
+// The chunks content splits into, with their hashes
+fn split(content: &[u8]) -> impl Iterator<Item = (String, &[u8])> {
+    FastCDC::new(content, MIN_CHUNK, AVG_CHUNK, MAX_CHUNK).map(move |chunk| {
+        let data = &content[chunk.offset..chunk.offset + chunk.length];
+        (sha256_hex(data), data)
+    })
+}
+
+// Split content into chunks and persist any not yet stored. Returns the chunk index.
+pub async fn store_chunks(content: &[u8]) -> std::io::Result<Vec<ChunkRef>> {
+    tokio::fs::create_dir_all(CHUNKS_DIR).await?;
+    let mut refs = Vec::new();
+    for (hash, data) in split(content) {
+        if let Some(path) = chunk_path(&hash) {
+            if !path.exists() {
+                write_atomic(&path, data).await?;
+            }
+        }
+        refs.push(ChunkRef { hash, len: data.len() as u32 });
+    }
+    Ok(refs)
+}
+
+// A stored chunk; one whose content no longer matches its hash is removed and reads as missing
+pub async fn get_chunk(hash: &str) -> std::io::Result<Option<Vec<u8>>> {
+    let Some(path) = chunk_path(hash).filter(|p| p.exists()) else {
+        return Ok(None);
+    };
+    let data = tokio::fs::read(&path).await?;
+    if !sha256_hex(&data).eq_ignore_ascii_case(hash) {
+        eprintln!("CHUNKS: Removing corrupt chunk {}", hash);
+        tokio::fs::remove_file(&path).await?;
+        return Ok(None);
+    }
+    Ok(Some(data))
+}
+
+// Rebuild a file from its chunk index, downloading only missing chunks from the sending peer.
+// Returns the content and how many chunks had to be fetched.
+pub async fn assemble_from_peer(peer_ip: &str, chunks: &[ChunkRef]) -> Result<(Vec<u8>, usize), String> {
+    tokio::fs::create_dir_all(CHUNKS_DIR).await.map_err(|e| e.to_string())?;
+    let mut content = Vec::with_capacity(chunks.iter().map(|c| c.len as usize).sum());
+    let mut fetched = 0usize;
+    for c in chunks {
+        let path = chunk_path(&c.hash).ok_or_else(|| format!("invalid chunk hash {}", c.hash))?;
+        let data = match get_chunk(&c.hash).await.map_err(|e| e.to_string())? {
+            Some(d) => d,
+            None => {
+                let url = format!("{}/api/chunks/{}", crate::tls::peer_origin(peer_ip, 8080), c.hash);
+                let resp = crate::client::peer()
+                    .get(&url)
+                    .timeout(CHUNK_TIMEOUT)
+                    .header(crate::tcp::PEER_REQUEST_HEADER, crate::tcp::sign_peer_request("GET", &url).await)
+                    .send()
+                    .await
+                    .map_err(|e| format!("chunk {} from {}: {}", c.hash, peer_ip, e))?;
+                if !resp.status().is_success() {
+                    return Err(format!("chunk {} from {} returned {}", c.hash, peer_ip, resp.status()));
+                }
+                let d = resp.bytes().await.map_err(|e| e.to_string())?.to_vec();
+                crate::bandwidth::record(crate::bandwidth::Channel::Files, Some(peer_ip), crate::bandwidth::Direction::Down, d.len());
+                if !sha256_hex(&d).eq_ignore_ascii_case(&c.hash) {
+                    return Err(format!("chunk {} from {} failed hash check", c.hash, peer_ip));
+                }
+                write_atomic(&path, &d).await.map_err(|e| e.to_string())?;
+                fetched += 1;
+                d
+            }
+        };
+        content.extend_from_slice(&data);
+    }
+    Ok((content, fetched))
+}
+
+// Delete chunks that no held file is made of any more, e.g. after the file was deleted, and
+// leftovers of interrupted writes. Returns how many were removed.
+pub async fn collect_garbage() -> std::io::Result<usize> {
+    let dir = Path::new(CHUNKS_DIR);
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut live = HashSet::new();
+    for path in crate::persistence::held_file_paths().await? {
+        if tokio::fs::metadata(&path).await?.len() < MIN_DEDUP_FILE_SIZE as u64 {
+            continue;
+        }
+        live.extend(split(&tokio::fs::read(&path).await?).map(|(hash, _)| hash));
+    }
+    let mut removed = 0;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let age = entry.metadata().await?.modified()?.elapsed().unwrap_or_default();
+        if live.contains(&name) || age < GC_GRACE {
+            continue;
+        }
+        tokio::fs::remove_file(entry.path()).await?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+// Sweep chunks/ every hour
+pub async fn gc_loop() {
+    loop {
+        match collect_garbage().await {
+            Ok(0) => {}
+            Ok(n) => println!("CHUNKS: Removed {} unreferenced chunk(s)", n),
+            Err(e) => eprintln!("CHUNKS: Garbage collection failed: {}", e),
+        }
+        tokio::time::sleep(GC_INTERVAL).await;
+    }
+}
+
+#[get("/chunks/{hash}")]
+pub async fn get_chunk_handler(req: HttpRequest, path: web::Path<String>) -> Result<HttpResponse, Error> {
+    match get_chunk(&path.into_inner()).await {
+        Ok(Some(data)) => {
+            crate::bandwidth::record(crate::bandwidth::Channel::Files, crate::bandwidth::requesting_peer(&req).as_deref(), crate::bandwidth::Direction::Up, data.len());
+            Ok(HttpResponse::Ok().content_type("application/octet-stream").body(data))
+        }
+        Ok(None) => Ok(HttpResponse::NotFound().finish()),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
@@ -0,0 +1,39 @@
+// Shared HTTP clients. A reqwest::Client keeps a pool of open connections, so one built per call
+// connects (and for HTTPS shakes hands) afresh every time; these are built once, and a clone shares
+// the pool. There is one client per purpose:
+//  - peer: other nodes' APIs. Their self-signed certificates are accepted (see
+//    tls::peer_client_builder), requests leave from BIND_IP and never go through a proxy.
+//  - local: moderation and transcription services, which run next to us; no proxy either.
+//  - external: the LLM backend (which may be a hosted API), OIDC providers, schedule webhooks and
+//    release feeds, through HTTP(S)_PROXY when set.
+// All of them send USER_AGENT and give up connecting after CONNECT_TIMEOUT. How long a whole
+// request may take depends on the call, so none has an overall timeout; each call sets its own
+// with RequestBuilder::timeout.
+use once_cell::sync::Lazy;
+use std::time::Duration;
+
+pub const USER_AGENT: &str = concat!("instance/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn build(purpose: &str, builder: reqwest::ClientBuilder) -> reqwest::Client {
+    builder.user_agent(USER_AGENT).connect_timeout(CONNECT_TIMEOUT).build().unwrap_or_else(|e| {
+        eprintln!("HTTP: Cannot build the {} client, using defaults: {}", purpose, e);
+        reqwest::Client::new()
+    })
+}
+
+static PEER: Lazy<reqwest::Client> = Lazy::new(|| build("peer", crate::tls::peer_client_builder().no_proxy()));
+static LOCAL: Lazy<reqwest::Client> = Lazy::new(|| build("local", reqwest::Client::builder().no_proxy()));
+static EXTERNAL: Lazy<reqwest::Client> = Lazy::new(|| build("external", reqwest::Client::builder()));
+
+pub fn peer() -> &'static reqwest::Client {
+    &PEER
+}
+
+pub fn local() -> &'static reqwest::Client {
+    &LOCAL
+}
+
+pub fn external() -> &'static reqwest::Client {
+    &EXTERNAL
+}
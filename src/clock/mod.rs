@@ -0,0 +1,154 @@
+// Clock offsets of peers. A peer's AUTH: handshake carries its clock in seconds, which gives a
+// first estimate as soon as it connects; after that each side exchanges TIMQ:/TIMR: frames (at
+// connect and with every periodic share) and estimates the offset NTP-style from the round trip.
+// The estimate from the fastest recent round trip wins. /api/peers/info shows the skew per peer,
+// and timestamps in conversations, forks and votes from a peer whose clock is off by more than
+// MIN_CORRECTED_SKEW are moved onto our clock when they arrive, so merged timelines line up.
+//
+// Wall clocks alone cannot order messages reliably, so each message also carries a Lamport time:
+// every new message takes the next tick of a counter that jumps past whatever a peer's messages
+// carried when they arrive. Sorting by it (see ChatMessage::causal_cmp) puts an answer after the
+// question it answers on every node, however far the clocks drift.
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Mutex;
+use crate::conversation::Conversation;
+
+// Round trips kept per peer
+const SAMPLES_PER_PEER: usize = 8;
+// Handshake timestamps only have whole seconds
+const HANDSHAKE_UNCERTAINTY_MS: i64 = 1000;
+// Smaller offsets are within measuring noise and left alone
+const MIN_CORRECTED_SKEW_MS: i64 = 2000;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    offset_ms: i64,
+    rtt_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClockSkew {
+    // How far the peer's clock is ahead of ours; negative when it is behind
+    pub offset_ms: i64,
+    // Round trip of the sample the estimate comes from; the true offset is within half of it
+    pub rtt_ms: i64,
+    // Timestamps from this peer are shifted by -offset_ms on arrival
+    pub corrected: bool,
+    pub measured_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct PeerClock {
+    samples: VecDeque<Sample>,
+    measured_at: Option<DateTime<Utc>>,
+}
+
+impl PeerClock {
+    fn add(&mut self, sample: Sample) {
+        if self.samples.len() == SAMPLES_PER_PEER {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.measured_at = Some(Utc::now());
+    }
+
+    fn best(&self) -> Option<Sample> {
+        self.samples.iter().min_by_key(|s| s.rtt_ms).copied()
+    }
+}
+
+static CLOCKS: Lazy<Mutex<HashMap<String, PeerClock>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// Lamport time of the last message we wrote or saw
+static LAMPORT: AtomicU64 = AtomicU64::new(0);
+
+// Lamport time for a message we are about to write
+pub fn lamport_tick() -> u64 {
+    LAMPORT.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+// Move our Lamport clock past a time seen on a message from elsewhere (or saved on disk)
+pub fn lamport_observe(time: u64) {
+    LAMPORT.fetch_max(time, Ordering::SeqCst);
+}
+
+pub fn lamport_observe_conversation(conversation: &Conversation) {
+    if let Some(max) = conversation.messages.iter().map(|m| m.lamport).max() {
+        lamport_observe(max);
+    }
+}
+
+pub fn now_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+// A peer's handshake, signed at `timestamp` (unix seconds) on its clock
+pub async fn handshake_received(peer_ip: &str, timestamp: i64) {
+    let offset_ms = timestamp * 1000 - now_ms();
+    CLOCKS.lock().await.entry(peer_ip.to_string()).or_default().add(Sample { offset_ms, rtt_ms: HANDSHAKE_UNCERTAINTY_MS });
+}
+
+// A TIMR: answer: we asked at `origin_ms`, the peer's clock read `peer_ms` when it answered
+pub async fn reply_received(peer_ip: &str, origin_ms: i64, peer_ms: i64) {
+    let now = now_ms();
+    let rtt_ms = now - origin_ms;
+    if rtt_ms < 0 {
+        return;
+    }
+    let offset_ms = peer_ms - (origin_ms + now) / 2;
+    CLOCKS.lock().await.entry(peer_ip.to_string()).or_default().add(Sample { offset_ms, rtt_ms });
+}
+
+pub async fn skew(peer_ip: &str) -> Option<ClockSkew> {
+    let clocks = CLOCKS.lock().await;
+    let clock = clocks.get(peer_ip)?;
+    let best = clock.best()?;
+    Some(ClockSkew {
+        offset_ms: best.offset_ms,
+        rtt_ms: best.rtt_ms,
+        corrected: best.offset_ms.abs() >= MIN_CORRECTED_SKEW_MS,
+        measured_at: clock.measured_at?,
+    })
+}
+
+// The shift that moves a timestamp from the peer's clock onto ours, when it is worth making
+pub async fn correction_ms(peer_ip: &str) -> Option<i64> {
+    skew(peer_ip).await.filter(|s| s.corrected).map(|s| -s.offset_ms)
+}
+
+// Move the message timestamps of a conversation received from a peer onto our clock
+pub async fn correct_conversation(peer_ip: &str, conversation: &mut Conversation) {
+    if let Some(shift) = correction_ms(peer_ip).await {
+        let shift = chrono::Duration::milliseconds(shift);
+        for m in &mut conversation.messages {
+            m.timestamp += shift;
+        }
+        if let Some(origin) = &mut conversation.forked_from {
+            origin.forked_at += shift;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{correction_ms, handshake_received, now_ms, reply_received};
+
+    // The fastest round trip gives the offset; the handshake's whole seconds only until then
+    #[tokio::test]
+    async fn clock_skew_prefers_the_fastest_round_trip() {
+        let ip = "198.51.100.7";
+        let now = now_ms();
+        handshake_received(ip, now / 1000 + 60).await;
+        let skew = super::skew(ip).await.expect("skew");
+        assert!((skew.offset_ms - 60_000).abs() <= 1_500, "{:?}", skew);
+        reply_received(ip, now - 200, now - 100 + 5_000).await;
+        reply_received(ip, now - 20, now - 10 + 3_000).await;
+        let skew = super::skew(ip).await.expect("skew");
+        assert!((skew.offset_ms - 3_000).abs() <= 50, "{:?}", skew);
+        assert!(skew.corrected);
+        assert_eq!(correction_ms(ip).await, Some(-skew.offset_ms));
+    }
+}
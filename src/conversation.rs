@@ -8,11 +8,116 @@ use hostname;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatMessage {
+    // Stable id; messages saved before ids existed get one assigned on load
+    #[serde(default)]
+    pub id: String,
     pub content: String,
     pub timestamp: DateTime<Utc>,
+    // Lamport time: larger than that of every message the writing node had seen, so sorting by it
+    // keeps cause before effect whatever the nodes' clocks say; 0 on messages from older builds
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub lamport: u64,
     pub sender: String,
     pub message_type: MessageType,
     pub host_info: HostInfo,
+    // File the question was asked about (if any), kept so exports can reference it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<String>,
+    // For regenerated answers: id of the question they answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alternative_of: Option<String>,
+    // The message this one replies to: for answers the question they answer, for questions the
+    // message they follow up on. None on top-level questions and on messages from older builds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    // The answer picked among a question's alternatives
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub preferred: bool,
+    // For questions: ISO 639-3 code of the language the answer was requested in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    // Part of the content was removed by a redaction rule
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+    // Moderation labels that flagged this message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    // For answers: the model that wrote it, when the answering node reported one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    // When `content`, `preferred` and `pinned` last changed after the message was written
+    #[serde(default, skip_serializing_if = "FieldStamps::is_empty")]
+    pub stamps: FieldStamps,
+}
+
+// Version of a last-writer-wins field: the writer's Lamport time, then its node id to break ties
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Stamp {
+    pub lamport: u64,
+    pub node: String,
+}
+
+impl Stamp {
+    pub fn now() -> Stamp {
+        Stamp { lamport: crate::clock::lamport_tick(), node: crate::persistence::node_id().to_string() }
+    }
+}
+
+// None until the field is first changed, which loses against any change
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FieldStamps {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<Stamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred: Option<Stamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<Stamp>,
+}
+
+impl FieldStamps {
+    fn is_empty(&self) -> bool {
+        self.content.is_none() && self.preferred.is_none() && self.pinned.is_none()
+    }
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+impl ChatMessage {
+    // Causal order: Lamport time, then wall clock, then writer and id so every node agrees on ties
+    pub fn causal_cmp(&self, other: &ChatMessage) -> std::cmp::Ordering {
+        (self.lamport, self.timestamp, &self.host_info.ip_address, &self.id).cmp(&(
+            other.lamport,
+            other.timestamp,
+            &other.host_info.ip_address,
+            &other.id,
+        ))
+    }
+
+    // Take every field the other copy of this message changed more recently
+    fn merge(&mut self, other: &ChatMessage) {
+        if other.stamps.content > self.stamps.content {
+            self.content = other.content.clone();
+            self.redacted = other.redacted;
+            self.flags = other.flags.clone();
+            self.stamps.content = other.stamps.content.clone();
+        }
+        if other.stamps.preferred > self.stamps.preferred {
+            self.preferred = other.preferred;
+            self.stamps.preferred = other.stamps.preferred.clone();
+        }
+        if other.stamps.pinned > self.stamps.pinned {
+            self.pinned = other.pinned;
+            self.stamps.pinned = other.stamps.pinned.clone();
+        }
+    }
+}
+
+pub fn new_message_id() -> String {
+    hex::encode(rand::random::<[u8; 8]>())
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -26,6 +131,31 @@ pub struct HostInfo {
     pub hostname: String,
     pub ip_address: String,
     pub is_llm_host: bool,
+    // The signed-in user a question was asked by, and its answers written for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+// Who gets to see a conversation: `private` ones never leave this node, `mesh` ones (the default)
+// go to the peers we are connected to, and `broadcast` ones are also pushed as soon as a peer
+// connects and relayed by peers to nodes that joined after us
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Privacy {
+    Private,
+    #[default]
+    Mesh,
+    Broadcast,
+}
+
+impl Privacy {
+    fn is_mesh(&self) -> bool {
+        *self == Privacy::Mesh
+    }
+
+    pub fn is_shared(&self) -> bool {
+        *self != Privacy::Private
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -33,11 +163,288 @@ pub struct Conversation {
     pub id: String,
     pub messages: Vec<ChatMessage>,
     pub host_info: HostInfo,
+    // Set on forks: the conversation and message the fork was seeded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_from: Option<ForkOrigin>,
+    #[serde(default, skip_serializing_if = "Privacy::is_mesh")]
+    pub privacy: Privacy,
+    // Set on broadcast conversations a peer passes on: the IP of the node they came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relayed_from: Option<String>,
+    // Set on conversations scoped to a group (see crate::groups): only peers in it get them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    // Short human title, generated after the first exchange (see crate::llm::titles) or set by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+// A message with the replies to it, for the threaded view
+#[derive(Debug, Serialize)]
+pub struct ThreadNode {
+    #[serde(flatten)]
+    pub message: ChatMessage,
+    pub replies: Vec<ThreadNode>,
+}
+
+// A node that contributed messages to a conversation
+#[derive(Debug, Serialize, Clone)]
+pub struct Participant {
+    pub hostname: String,
+    pub ip_address: String,
+    pub is_llm_host: bool,
+    // Distinct senders seen from this node, in order of first appearance
+    pub senders: Vec<String>,
+    pub message_count: usize,
+    pub last_active: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForkOrigin {
+    pub conversation_id: String,
+    pub message_id: String,
+    pub forked_at: DateTime<Utc>,
+}
+
+// Fork ids look like "fork-<16 hex digits>"; they are also the file name (plus .json) forks sync under
+pub fn is_fork_id(id: &str) -> bool {
+    id.strip_prefix("fork-").is_some_and(|h| h.len() == 16 && h.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+pub fn fork_id_from_file(name: &str) -> Option<&str> {
+    name.strip_suffix(".json").filter(|id| is_fork_id(id))
+}
+
+impl Conversation {
+    // New conversation holding this one's history up to and including `message_id`
+    pub fn fork(&self, message_id: &str) -> Option<Conversation> {
+        let pos = self.messages.iter().position(|m| m.id == message_id)?;
+        Some(Conversation {
+            id: format!("fork-{}", new_message_id()),
+            messages: self.messages[..=pos].to_vec(),
+            host_info: self.host_info.clone(),
+            forked_from: Some(ForkOrigin {
+                conversation_id: self.id.clone(),
+                message_id: message_id.to_string(),
+                forked_at: Utc::now(),
+            }),
+            // A fork of a private conversation stays private
+            privacy: if self.privacy.is_shared() { Privacy::Mesh } else { Privacy::Private },
+            relayed_from: None,
+            group: self.group.clone(),
+            // Titled again after its own first exchange
+            title: None,
+        })
+    }
+
+    // Nodes that wrote messages here, most recently active first
+    pub fn participants(&self) -> Vec<Participant> {
+        let mut out: Vec<Participant> = Vec::new();
+        for m in &self.messages {
+            let pos = out
+                .iter()
+                .position(|p| p.hostname == m.host_info.hostname && p.ip_address == m.host_info.ip_address);
+            let p = match pos {
+                Some(i) => &mut out[i],
+                None => {
+                    out.push(Participant {
+                        hostname: m.host_info.hostname.clone(),
+                        ip_address: m.host_info.ip_address.clone(),
+                        is_llm_host: false,
+                        senders: Vec::new(),
+                        message_count: 0,
+                        last_active: m.timestamp,
+                    });
+                    out.last_mut().expect("just pushed")
+                }
+            };
+            p.is_llm_host |= m.host_info.is_llm_host;
+            if !p.senders.contains(&m.sender) {
+                p.senders.push(m.sender.clone());
+            }
+            p.message_count += 1;
+            p.last_active = p.last_active.max(m.timestamp);
+        }
+        out.sort_by_key(|p| std::cmp::Reverse(p.last_active));
+        out
+    }
+
+    // Give id-less messages a deterministic id so it is the same on every load. Returns true if any changed.
+    pub fn ensure_message_ids(&mut self) -> bool {
+        let mut changed = false;
+        for (i, m) in self.messages.iter_mut().enumerate() {
+            if m.id.is_empty() {
+                m.id = format!("{}-{}", m.timestamp.timestamp_millis(), i);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    // The question a message belongs to: itself for a question, otherwise the question it answers
+    pub fn question_for(&self, message_id: &str) -> Option<&ChatMessage> {
+        let pos = self.messages.iter().position(|m| m.id == message_id)?;
+        let msg = &self.messages[pos];
+        match msg.message_type {
+            MessageType::Question => Some(msg),
+            MessageType::Response => match &msg.alternative_of {
+                Some(qid) => self.messages.iter().find(|m| &m.id == qid),
+                None => self.messages[..pos].iter().rev().find(|m| matches!(m.message_type, MessageType::Question)),
+            },
+        }
+    }
+
+    // Every answer to a question: the original reply that followed it plus any regenerated alternatives
+    pub fn answers_to(&self, question_id: &str) -> Vec<&ChatMessage> {
+        let pos = match self.messages.iter().position(|m| m.id == question_id) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+        let original = self.messages.get(pos + 1).filter(|m| {
+            matches!(m.message_type, MessageType::Response) && m.alternative_of.is_none()
+        });
+        original
+            .into_iter()
+            .chain(self.messages.iter().filter(|m| m.alternative_of.as_deref() == Some(question_id)))
+            .collect()
+    }
+
+    // One signed-in user's view: their questions and every answer to them
+    pub fn for_user(&self, user: &str) -> Conversation {
+        let mut last_question: Option<&ChatMessage> = None;
+        let mut messages = Vec::new();
+        for m in &self.messages {
+            let question = match m.message_type {
+                MessageType::Question => {
+                    last_question = Some(m);
+                    Some(m)
+                }
+                MessageType::Response => match &m.alternative_of {
+                    Some(qid) => self.messages.iter().find(|q| &q.id == qid),
+                    None => last_question,
+                },
+            };
+            if question.is_some_and(|q| q.host_info.user.as_deref() == Some(user)) {
+                messages.push(m.clone());
+            }
+        }
+        Conversation { messages, ..self.clone() }
+    }
+
+    // The message `message_id` hangs under in the threaded view: what it replies to when that is in
+    // the conversation, otherwise (answers from older builds) the question it answers
+    pub fn parent_of(&self, message_id: &str) -> Option<&ChatMessage> {
+        let msg = self.messages.iter().find(|m| m.id == message_id)?;
+        if let Some(parent) = msg.reply_to.as_deref().and_then(|id| self.messages.iter().find(|m| m.id == id)) {
+            return Some(parent);
+        }
+        match msg.message_type {
+            MessageType::Question => None,
+            MessageType::Response => self.question_for(message_id),
+        }
+    }
+
+    // Messages nested under what they reply to, each level in conversation order: questions at the
+    // top, their answers and regenerations below them and follow-up questions below the answer
+    // they follow up on. Messages whose replies form a loop are listed at the top.
+    pub fn thread(&self) -> Vec<ThreadNode> {
+        fn build(messages: &[ChatMessage], children: &HashMap<&str, Vec<usize>>, placed: &mut [bool], i: usize) -> ThreadNode {
+            placed[i] = true;
+            let mut replies = Vec::new();
+            for &child in children.get(messages[i].id.as_str()).into_iter().flatten() {
+                if !placed[child] {
+                    replies.push(build(messages, children, placed, child));
+                }
+            }
+            ThreadNode { message: messages[i].clone(), replies }
+        }
+        let mut children: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut roots = Vec::new();
+        for (i, m) in self.messages.iter().enumerate() {
+            match self.parent_of(&m.id).filter(|p| p.id != m.id) {
+                Some(parent) => children.entry(parent.id.as_str()).or_default().push(i),
+                None => roots.push(i),
+            }
+        }
+        let mut placed = vec![false; self.messages.len()];
+        let mut out: Vec<ThreadNode> = roots.into_iter().map(|i| build(&self.messages, &children, &mut placed, i)).collect();
+        while let Some(i) = placed.iter().position(|p| !p) {
+            out.push(build(&self.messages, &children, &mut placed, i));
+        }
+        out
+    }
+
+    // Mark one answer as preferred and clear the flag on its siblings
+    pub fn prefer_answer(&mut self, answer_id: &str) -> Option<ChatMessage> {
+        let question_id = self.question_for(answer_id)?.id.clone();
+        let siblings: Vec<String> = self.answers_to(&question_id).iter().map(|m| m.id.clone()).collect();
+        if !siblings.iter().any(|id| id == answer_id) {
+            return None;
+        }
+        let stamp = Stamp::now();
+        let mut chosen = None;
+        for m in self.messages.iter_mut().filter(|m| siblings.contains(&m.id)) {
+            m.preferred = m.id == answer_id;
+            m.stamps.preferred = Some(stamp.clone());
+            if m.preferred {
+                chosen = Some(m.clone());
+            }
+        }
+        chosen
+    }
+
+    // Replace a message's text; `redacted` and `flags` describe the new text
+    pub fn edit_message(&mut self, message_id: &str, content: String, redacted: bool, flags: Vec<String>) -> Option<ChatMessage> {
+        let m = self.messages.iter_mut().find(|m| m.id == message_id)?;
+        m.content = content;
+        m.redacted = redacted;
+        m.flags = flags;
+        m.stamps.content = Some(Stamp::now());
+        Some(m.clone())
+    }
+
+    pub fn pin_message(&mut self, message_id: &str, pinned: bool) -> Option<ChatMessage> {
+        let m = self.messages.iter_mut().find(|m| m.id == message_id)?;
+        m.pinned = pinned;
+        m.stamps.pinned = Some(Stamp::now());
+        Some(m.clone())
+    }
+
+    // Conversations are a CRDT: messages form a grow-only set keyed by id, and the fields that can
+    // change later (content, preferred, pinned) are last-writer-wins registers. Merging copies in
+    // any order and any number of times ends in the same messages and field values.
+    pub fn merge(&mut self, other: Conversation) {
+        let mut index: HashMap<String, usize> = self.messages.iter().enumerate().map(|(i, m)| (m.id.clone(), i)).collect();
+        for m in other.messages {
+            match index.get(&m.id) {
+                Some(&i) => self.messages[i].merge(&m),
+                None => {
+                    index.insert(m.id.clone(), self.messages.len());
+                    self.messages.push(m);
+                }
+            }
+        }
+        // Stable, so messages from before Lamport times keep their relative order
+        self.messages.sort_by_key(|m| (m.lamport, m.timestamp));
+        self.host_info = other.host_info;
+        // Only the owner changes it, and its latest copy carries it
+        self.privacy = other.privacy;
+        self.group = other.group;
+        if other.title.is_some() {
+            self.title = other.title;
+        }
+        if other.forked_from.is_some() {
+            self.forked_from = other.forked_from;
+        }
+    }
 }
 
 pub struct ConversationStore {
     local_conversation: Mutex<Option<Conversation>>,
     peer_conversations: Mutex<HashMap<String, Conversation>>,
+    // Our forks by fork id, and forks received from each peer IP
+    forks: Mutex<HashMap<String, Conversation>>,
+    peer_forks: Mutex<HashMap<String, HashMap<String, Conversation>>>,
 }
 
 impl ConversationStore {
@@ -45,6 +452,8 @@ impl ConversationStore {
         ConversationStore {
             local_conversation: Mutex::new(None),
             peer_conversations: Mutex::new(HashMap::new()),
+            forks: Mutex::new(HashMap::new()),
+            peer_forks: Mutex::new(HashMap::new()),
         }
     }
 
@@ -54,15 +463,17 @@ impl ConversationStore {
             
             if let Some(conversation) = local.as_mut() {
                 conversation.messages.push(message.clone());
+                if let Err(e) = persistence::append_local_message(conversation, &message).await {
+                    eprintln!("Error saving local conversation: {}", e);
+                }
             } else {
                 // Create new local conversation
                 let hostname = hostname::get()
                     .map(|h| h.to_string_lossy().to_string())
                     .unwrap_or_else(|_| "Unknown".to_string());
                 
-                let ip_address = std::net::TcpStream::connect("8.8.8.8:53")
-                    .and_then(|s| s.local_addr())
-                    .map(|addr| addr.ip().to_string())
+                let ip_address = crate::ip::primary_ip()
+                    .map(|ip| ip.to_string())
                     .unwrap_or_else(|_| "Unknown".to_string());
                 
                 let conversation = Conversation {
@@ -72,28 +483,175 @@ impl ConversationStore {
                         hostname,
                         ip_address,
                         is_llm_host: message.host_info.is_llm_host,
+                        user: None,
                     },
+                    forked_from: None,
+                    privacy: Privacy::Mesh,
+                    relayed_from: None,
+                    group: None,
+                    title: None,
                 };
-                *local = Some(conversation.clone());
-            }
-
-            // Save local conversation
-            if let Some(conversation) = local.as_ref() {
-                if let Err(e) = persistence::save_local_conversation(conversation).await {
+                if let Err(e) = persistence::save_local_conversation(&conversation).await {
                     eprintln!("Error saving local conversation: {}", e);
                 }
+                *local = Some(conversation);
+            }
+        } else {
+            let mut forks = self.forks.lock().await;
+            if let Some(fork) = forks.get_mut(&conversation_id) {
+                fork.messages.push(message);
+                if let Err(e) = persistence::save_fork(None, fork).await {
+                    eprintln!("Error saving fork {}: {}", conversation_id, e);
+                }
+            }
+        }
+    }
+
+    pub async fn add_fork(&self, fork: Conversation) {
+        if let Err(e) = persistence::save_fork(None, &fork).await {
+            eprintln!("Error saving fork {}: {}", fork.id, e);
+        }
+        self.forks.lock().await.insert(fork.id.clone(), fork);
+    }
+
+    pub async fn get_fork(&self, id: &str) -> Option<Conversation> {
+        self.forks.lock().await.get(id).cloned()
+    }
+
+    pub async fn get_forks(&self) -> Vec<Conversation> {
+        self.forks.lock().await.values().cloned().collect()
+    }
+
+    // Change who sees one of our conversations ("local" or one of our forks) and persist it
+    pub async fn set_privacy(&self, id: &str, privacy: Privacy) -> Option<Conversation> {
+        if id == "local" {
+            return self.update_local(|conversation| {
+                conversation.privacy = privacy;
+                conversation.clone()
+            }).await;
+        }
+        let mut forks = self.forks.lock().await;
+        let fork = forks.get_mut(id)?;
+        fork.privacy = privacy;
+        if let Err(e) = persistence::save_fork(None, fork).await {
+            eprintln!("Error saving fork {}: {}", id, e);
+        }
+        Some(fork.clone())
+    }
+
+    // Scope one of our conversations to a group, or to the whole mesh again with None, and persist it
+    pub async fn set_group(&self, id: &str, group: Option<String>) -> Option<Conversation> {
+        if id == "local" {
+            return self.update_local(|conversation| {
+                conversation.group = group;
+                conversation.clone()
+            }).await;
+        }
+        let mut forks = self.forks.lock().await;
+        let fork = forks.get_mut(id)?;
+        fork.group = group;
+        if let Err(e) = persistence::save_fork(None, fork).await {
+            eprintln!("Error saving fork {}: {}", id, e);
+        }
+        Some(fork.clone())
+    }
+
+    // Name one of our conversations, or clear its title with None, and persist it
+    pub async fn set_title(&self, id: &str, title: Option<String>) -> Option<Conversation> {
+        if id == "local" {
+            return self.update_local(|conversation| {
+                conversation.title = title;
+                conversation.clone()
+            }).await;
+        }
+        let mut forks = self.forks.lock().await;
+        let fork = forks.get_mut(id)?;
+        fork.title = title;
+        if let Err(e) = persistence::save_fork(None, fork).await {
+            eprintln!("Error saving fork {}: {}", id, e);
+        }
+        Some(fork.clone())
+    }
+
+    // Broadcast conversations a joining peer should get from us: our own, then those peers
+    // passed to us, each marked with the node it came from
+    pub async fn broadcast_conversations(&self) -> Vec<Conversation> {
+        let mut out: Vec<Conversation> = self.get_local_conversation().await.into_iter().collect();
+        out.extend(self.get_forks().await);
+        out.retain(|c| c.privacy == Privacy::Broadcast);
+        let relayed = |ip: &String, c: &Conversation| {
+            (c.privacy == Privacy::Broadcast).then(|| Conversation { relayed_from: Some(ip.clone()), ..c.clone() })
+        };
+        out.extend(self.peer_conversations.lock().await.iter().filter_map(|(ip, c)| relayed(ip, c)));
+        for (ip, forks) in self.peer_forks.lock().await.iter() {
+            out.extend(forks.values().filter_map(|c| relayed(ip, c)));
+        }
+        out
+    }
+
+    pub async fn add_peer_fork(&self, peer_ip: String, mut fork: Conversation) {
+        fork.ensure_message_ids();
+        crate::clock::lamport_observe_conversation(&fork);
+        let mut peer_forks = self.peer_forks.lock().await;
+        let forks = peer_forks.entry(peer_ip.clone()).or_default();
+        let fork = match forks.remove(&fork.id) {
+            Some(mut previous) => {
+                previous.merge(fork);
+                previous
             }
+            None => fork,
+        };
+        if let Err(e) = persistence::save_fork(Some(&peer_ip), &fork).await {
+            eprintln!("Error saving fork {} from {}: {}", fork.id, peer_ip, e);
         }
+        forks.insert(fork.id.clone(), fork);
     }
 
-    pub async fn add_peer_conversation(&self, peer_ip: String, conversation: Conversation) {
+    pub async fn get_peer_forks(&self) -> HashMap<String, Vec<Conversation>> {
+        let peer_forks = self.peer_forks.lock().await;
+        peer_forks.iter().map(|(ip, forks)| (ip.clone(), forks.values().cloned().collect())).collect()
+    }
+
+    // Store a peer's conversation, returning the messages that were not in the previous copy
+    pub async fn add_peer_conversation(&self, peer_ip: String, mut conversation: Conversation) -> Vec<ChatMessage> {
+        conversation.ensure_message_ids();
+        crate::clock::lamport_observe_conversation(&conversation);
         let mut peer_conversations = self.peer_conversations.lock().await;
+        let new_messages: Vec<ChatMessage> = match peer_conversations.get(&peer_ip) {
+            Some(previous) => conversation
+                .messages
+                .iter()
+                .filter(|m| !previous.messages.iter().any(|p| p.id == m.id))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        // Merged into what we had, so a stale or partial copy cannot undo newer changes
+        let conversation = match peer_conversations.remove(&peer_ip) {
+            Some(mut previous) => {
+                previous.merge(conversation);
+                previous
+            }
+            None => conversation,
+        };
         peer_conversations.insert(peer_ip.clone(), conversation.clone());
         
         // Save to disk
         if let Err(e) = persistence::save_peer_conversation(&peer_ip, &conversation).await {
             eprintln!("Error saving peer conversation: {}", e);
         }
+        new_messages
+    }
+
+    // Apply a change to the local conversation and persist it
+    pub async fn update_local<R>(&self, f: impl FnOnce(&mut Conversation) -> R) -> Option<R> {
+        let mut local = self.local_conversation.lock().await;
+        let conversation = local.as_mut()?;
+        let result = f(conversation);
+        if let Err(e) = persistence::save_local_conversation(conversation).await {
+            eprintln!("Error saving local conversation: {}", e);
+        }
+        Some(result)
     }
 
     pub async fn get_local_conversation(&self) -> Option<Conversation> {
@@ -105,18 +663,43 @@ impl ConversationStore {
         println!("Loading saved conversations...");
         
         // Load local conversation
-        if let Ok(Some(local)) = persistence::load_local_conversation().await {
+        if let Ok(Some(mut local)) = persistence::load_local_conversation().await {
             println!("Loaded local conversation");
+            if local.ensure_message_ids() {
+                if let Err(e) = persistence::save_local_conversation(&local).await {
+                    eprintln!("Error saving local conversation: {}", e);
+                }
+            }
+            crate::clock::lamport_observe_conversation(&local);
             let mut local_lock = self.local_conversation.lock().await;
             *local_lock = Some(local);
         }
 
+        match persistence::load_forks().await {
+            Ok(forks) => {
+                forks.values().for_each(crate::clock::lamport_observe_conversation);
+                *self.forks.lock().await = forks;
+            }
+            Err(e) => eprintln!("Error loading forks: {}", e),
+        }
+        match persistence::load_peer_forks().await {
+            Ok(peer_forks) => {
+                peer_forks.values().flat_map(|forks| forks.values()).for_each(crate::clock::lamport_observe_conversation);
+                *self.peer_forks.lock().await = peer_forks;
+            }
+            Err(e) => eprintln!("Error loading peer forks: {}", e),
+        }
+
         // Load peer conversations
         match persistence::load_all_peer_conversations().await {
             Ok(peers) => {
                 println!("Successfully loaded {} peer conversations", peers.len());
                 let mut peers_lock = self.peer_conversations.lock().await;
                 *peers_lock = peers;
+                for conv in peers_lock.values_mut() {
+                    conv.ensure_message_ids();
+                    crate::clock::lamport_observe_conversation(conv);
+                }
                 for (peer, conv) in &*peers_lock {
                     println!("Loaded conversation for peer {} with {} messages", peer, conv.messages.len());
                 }
@@ -134,8 +717,262 @@ impl ConversationStore {
         let peers = self.peer_conversations.lock().await;
         peers.clone()
     }
+
+    // Every conversation we hold: ours, peers', and both sides' forks
+    pub async fn all_conversations(&self) -> Vec<Conversation> {
+        let mut all: Vec<Conversation> = self.get_local_conversation().await.into_iter().collect();
+        all.extend(self.peer_conversations.lock().await.values().cloned());
+        all.extend(self.forks.lock().await.values().cloned());
+        all.extend(self.peer_forks.lock().await.values().flat_map(|forks| forks.values().cloned()));
+        all
+    }
+
+    // Our conversation and every peer's merged into one list of (conversation id, message) in
+    // causal order; a message held in more than one copy appears once. With `user` only that
+    // user's questions and their answers are kept.
+    pub async fn timeline(&self, user: Option<&str>) -> Vec<(String, ChatMessage)> {
+        let mut seen = std::collections::HashSet::new();
+        let mut merged: Vec<(String, ChatMessage)> = Vec::new();
+        let local = self.get_local_conversation().await.map(|c| ("local".to_string(), c));
+        let peers = self.get_peer_conversations().await;
+        for (id, conversation) in local.into_iter().chain(peers) {
+            let conversation = match user {
+                Some(user) => conversation.for_user(user),
+                None => conversation,
+            };
+            for m in conversation.messages {
+                if seen.insert(m.id.clone()) {
+                    merged.push((id.clone(), m));
+                }
+            }
+        }
+        merged.sort_by(|a, b| a.1.causal_cmp(&b.1));
+        merged
+    }
+
+    // Look up a conversation by id: "local" for ours, a fork id (ours or a peer's), otherwise the
+    // peer IP it was received from
+    pub async fn get_conversation(&self, id: &str) -> Option<Conversation> {
+        if id == "local" {
+            return self.get_local_conversation().await;
+        }
+        if is_fork_id(id) {
+            if let Some(fork) = self.forks.lock().await.get(id) {
+                return Some(fork.clone());
+            }
+            let peer_forks = self.peer_forks.lock().await;
+            return peer_forks.values().find_map(|forks| forks.get(id).cloned());
+        }
+        let peers = self.peer_conversations.lock().await;
+        peers.get(id).cloned()
+    }
 }
 
 lazy_static! {
     pub static ref CONVERSATION_STORE: ConversationStore = ConversationStore::new();
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::{ChatMessage, Conversation, HostInfo, MessageType, Privacy, Stamp, ThreadNode};
+    use crate::llm::titles::{clean, MAX_TITLE_CHARS};
+
+    fn chat_message(id: &str, lamport: u64, secs: i64) -> ChatMessage {
+        ChatMessage {
+            id: id.into(),
+            content: String::new(),
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            lamport,
+            sender: "x".into(),
+            message_type: MessageType::Question,
+            host_info: HostInfo { hostname: "h".into(), ip_address: "10.0.0.1".into(), is_llm_host: false, user: None },
+            attachment: None,
+            alternative_of: None,
+            reply_to: None,
+            preferred: false,
+            language: None,
+            redacted: false,
+            flags: Vec::new(),
+            model: None,
+            pinned: false,
+            stamps: Default::default(),
+        }
+    }
+
+    // Privacy travels with the conversation: mesh is left out of the JSON, forks of a private
+    // conversation stay private and a merge takes the owner's latest setting
+    #[test]
+    fn conversation_privacy_is_kept() {
+        let mut private = Conversation {
+            id: "local".into(),
+            messages: vec![chat_message("a", 1, 100)],
+            host_info: HostInfo { hostname: "h".into(), ip_address: "10.0.0.1".into(), is_llm_host: false, user: None },
+            forked_from: None,
+            privacy: Privacy::Private,
+            relayed_from: None,
+            group: None,
+            title: None,
+        };
+        assert_eq!(private.fork("a").expect("fork").privacy, Privacy::Private);
+        let shared = Conversation { privacy: Privacy::Mesh, ..private.clone() };
+        assert!(!serde_json::to_string(&shared).unwrap().contains("privacy"));
+        assert_eq!(shared.fork("a").expect("fork").privacy, Privacy::Mesh);
+        let decoded: Conversation = serde_json::from_str(&serde_json::to_string(&private).unwrap()).unwrap();
+        assert_eq!(decoded.privacy, Privacy::Private);
+        private.merge(Conversation { privacy: Privacy::Broadcast, ..shared });
+        assert_eq!(private.privacy, Privacy::Broadcast);
+    }
+
+    // A user's view keeps their questions and every answer to them, including regenerated ones
+    #[test]
+    fn conversation_for_user_keeps_their_questions_and_answers() {
+        let asked_by = |id: &str, lamport: u64, user: &str| {
+            let mut m = chat_message(id, lamport, lamport as i64);
+            m.host_info.user = Some(user.into());
+            m
+        };
+        let answer = |id: &str, lamport: u64, alternative_of: Option<&str>| {
+            let mut m = chat_message(id, lamport, lamport as i64);
+            m.message_type = MessageType::Response;
+            m.alternative_of = alternative_of.map(Into::into);
+            m
+        };
+        let conversation = Conversation {
+            id: "local".into(),
+            messages: vec![
+                asked_by("q1", 1, "alice"),
+                answer("a1", 2, None),
+                asked_by("q2", 3, "bob"),
+                answer("a2", 4, None),
+                answer("a1b", 5, Some("q1")),
+            ],
+            host_info: HostInfo { hostname: "h".into(), ip_address: "10.0.0.1".into(), is_llm_host: false, user: None },
+            forked_from: None,
+            privacy: Default::default(),
+            relayed_from: None,
+            group: None,
+            title: None,
+        };
+        let ids = |user: &str| conversation.for_user(user).messages.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids("alice"), ["q1", "a1", "a1b"]);
+        assert_eq!(ids("bob"), ["q2", "a2"]);
+        assert!(ids("carol").is_empty());
+    }
+
+    // Answers and regenerations nest under their question and follow-ups under the message they reply
+    // to; answers from older builds without reply_to still find their question, and loops do not hide
+    // messages
+    #[test]
+    fn conversation_thread_nests_replies() {
+        let message = |id: &str, lamport: u64, answer: bool, reply_to: Option<&str>| {
+            let mut m = chat_message(id, lamport, lamport as i64);
+            if answer {
+                m.message_type = MessageType::Response;
+            }
+            m.reply_to = reply_to.map(Into::into);
+            m
+        };
+        let mut regenerated = message("a1b", 4, true, Some("q1"));
+        regenerated.alternative_of = Some("q1".into());
+        let conversation = Conversation {
+            id: "local".into(),
+            messages: vec![
+                message("q1", 1, false, None),
+                message("a1", 2, true, Some("q1")),
+                message("q2", 3, false, Some("a1")),
+                regenerated,
+                message("a2", 5, true, None),
+                message("q3", 6, false, None),
+                message("x", 7, false, Some("y")),
+                message("y", 8, false, Some("x")),
+            ],
+            host_info: HostInfo { hostname: "h".into(), ip_address: "10.0.0.1".into(), is_llm_host: false, user: None },
+            forked_from: None,
+            privacy: Default::default(),
+            relayed_from: None,
+            group: None,
+            title: None,
+        };
+        fn shape(nodes: &[ThreadNode]) -> String {
+            nodes
+                .iter()
+                .map(|n| if n.replies.is_empty() { n.message.id.clone() } else { format!("{}({})", n.message.id, shape(&n.replies)) })
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+        assert_eq!(shape(&conversation.thread()), "q1(a1(q2(a2)) a1b) q3 x(y)");
+    }
+
+    // An answer written after seeing a peer's question sorts after it even when our clock is behind
+    #[test]
+    fn lamport_time_orders_across_skewed_clocks() {
+        crate::clock::lamport_observe(1_000);
+        let tick = crate::clock::lamport_tick();
+        assert!(tick > 1_000);
+        let question = chat_message("q", 1_000, 1_700_000_100);
+        let answer = chat_message("a", tick, 1_700_000_000);
+        assert_eq!(question.causal_cmp(&answer), std::cmp::Ordering::Less);
+        // Equal Lamport times fall back to the wall clock
+        assert_eq!(chat_message("b", 5, 2).causal_cmp(&chat_message("c", 5, 1)), std::cmp::Ordering::Greater);
+    }
+
+    // Copies of a conversation that saw different messages and changes converge in any merge order
+    #[test]
+    fn conversation_merge_converges() {
+        let conversation = |messages: Vec<ChatMessage>| Conversation {
+            id: "local".into(),
+            messages,
+            host_info: HostInfo { hostname: "h".into(), ip_address: "10.0.0.1".into(), is_llm_host: false, user: None },
+            forked_from: None,
+            privacy: Default::default(),
+            relayed_from: None,
+            group: None,
+            title: None,
+        };
+        let mut edited = chat_message("a", 1, 100);
+        edited.content = "new".into();
+        edited.stamps.content = Some(Stamp { lamport: 5, node: "n1".into() });
+        let mut pinned = chat_message("a", 1, 100);
+        pinned.pinned = true;
+        pinned.stamps.pinned = Some(Stamp { lamport: 3, node: "n2".into() });
+        let x = conversation(vec![edited, chat_message("c", 3, 300)]);
+        let y = conversation(vec![pinned, chat_message("b", 2, 200)]);
+
+        let mut xy = x.clone();
+        xy.merge(y.clone());
+        let mut yx = y.clone();
+        yx.merge(x.clone());
+        yx.merge(x);
+        for merged in [&xy, &yx] {
+            let ids: Vec<&str> = merged.messages.iter().map(|m| m.id.as_str()).collect();
+            assert_eq!(ids, ["a", "b", "c"]);
+            assert_eq!((merged.messages[0].content.as_str(), merged.messages[0].pinned), ("new", true));
+        }
+    }
+
+    // Titles come from whatever the model answered, cleaned to one short line; a merge keeps the title
+    // when the copy merged in has none yet, and forks are titled on their own
+    #[test]
+    fn conversation_titles() {
+        assert_eq!(clean("Title: \"Rust borrow   checker tips.\"\nMore text").as_deref(), Some("Rust borrow checker tips"));
+        assert_eq!(clean("\n  **Übersicht über Lamport-Uhren**  ").as_deref(), Some("Übersicht über Lamport-Uhren"));
+        assert_eq!(clean(" \"\" \n"), None);
+        assert_eq!(clean(&"é".repeat(100)).map(|t| t.chars().count()), Some(MAX_TITLE_CHARS));
+
+        let mut conversation = Conversation {
+            id: "local".into(),
+            messages: vec![chat_message("a", 1, 100)],
+            host_info: HostInfo { hostname: "h".into(), ip_address: "10.0.0.1".into(), is_llm_host: false, user: None },
+            forked_from: None,
+            privacy: Default::default(),
+            relayed_from: None,
+            group: None,
+            title: Some("Lamport clocks".into()),
+        };
+        assert_eq!(conversation.fork("a").expect("fork").title, None);
+        conversation.merge(Conversation { title: None, ..conversation.clone() });
+        assert_eq!(conversation.title.as_deref(), Some("Lamport clocks"));
+        conversation.merge(Conversation { title: Some("Vector clocks".into()), ..conversation.clone() });
+        assert_eq!(conversation.title.as_deref(), Some("Vector clocks"));
+    }
+}
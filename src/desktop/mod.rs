@@ -0,0 +1,124 @@
+// Desktop integration, built only with `--features desktop`: a tray icon showing the peer count
+// with "Open MeshMind" and "Quit" entries, and native notifications for files and chat messages
+// arriving from peers. The tray runs its own event loop on a dedicated thread; on macOS, where
+// tray icons must live on the main thread (taken by the server), only notifications are shown.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+const ICON_SIZE: u32 = 32;
+// Longest chat preview shown in a notification, in characters
+const PREVIEW_CHARS: usize = 120;
+
+static PEER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// Filled circle in the UI's accent color, with a transparent background
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+fn icon_rgba() -> Vec<u8> {
+    let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+    let radius = ICON_SIZE as f32 / 2.0 - 1.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let d = ((x as f32 - center).powi(2) + (y as f32 - center).powi(2)).sqrt();
+            let alpha = if d <= radius { 255 } else { 0 };
+            rgba.extend_from_slice(&[79, 70, 229, alpha]);
+        }
+    }
+    rgba
+}
+
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+fn tooltip(peers: usize) -> String {
+    match peers {
+        1 => "MeshMind - 1 peer online".to_string(),
+        n => format!("MeshMind - {} peers online", n),
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn run_tray() -> Result<(), String> {
+    use std::time::Instant;
+    use tao::event_loop::{ControlFlow, EventLoopBuilder};
+    #[cfg(not(target_os = "windows"))]
+    use tao::platform::unix::EventLoopBuilderExtUnix;
+    #[cfg(target_os = "windows")]
+    use tao::platform::windows::EventLoopBuilderExtWindows;
+    use tray_icon::menu::{Menu, MenuEvent, MenuItem};
+    use tray_icon::{Icon, TrayIconBuilder};
+
+    // The tray icon has to be created on the thread that runs its event loop
+    let event_loop = EventLoopBuilder::new().with_any_thread(true).build();
+    let open_item = MenuItem::new("Open MeshMind", true, None);
+    let quit_item = MenuItem::new("Quit", true, None);
+    let menu = Menu::new();
+    menu.append_items(&[&open_item, &quit_item]).map_err(|e| e.to_string())?;
+    let icon = Icon::from_rgba(icon_rgba(), ICON_SIZE, ICON_SIZE).map_err(|e| e.to_string())?;
+    let tray = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip(tooltip(0))
+        .with_icon(icon)
+        .build()
+        .map_err(|e| e.to_string())?;
+    println!("DESKTOP: Tray icon ready");
+
+    let mut shown_peers = 0;
+    event_loop.run(move |_event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_secs(1));
+        while let Ok(event) = MenuEvent::receiver().try_recv() {
+            if event.id == *open_item.id() {
+                let _ = open::that(crate::tls::ui_url());
+            } else if event.id == *quit_item.id() {
+                println!("DESKTOP: Quit from tray menu");
+                std::process::exit(0);
+            }
+        }
+        let peers = PEER_COUNT.load(Ordering::Relaxed);
+        if peers != shown_peers {
+            let _ = tray.set_tooltip(Some(tooltip(peers)));
+            shown_peers = peers;
+        }
+    })
+}
+
+pub fn start() {
+    tokio::spawn(async {
+        loop {
+            PEER_COUNT.store(crate::tcp::connected_peer_ips().await.len(), Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    });
+    #[cfg(not(target_os = "macos"))]
+    std::thread::spawn(|| {
+        if let Err(e) = run_tray() {
+            eprintln!("DESKTOP: Tray icon unavailable: {}", e);
+        }
+    });
+}
+
+fn preview(text: &str) -> String {
+    let mut out: String = text.chars().take(PREVIEW_CHARS).collect();
+    if text.chars().count() > PREVIEW_CHARS {
+        out.push_str("...");
+    }
+    out
+}
+
+// Subscriber of events::publish: a native notification for events worth interrupting someone for
+pub fn notify_event(kind: &str, payload: &serde_json::Value) {
+    let peer = payload["peer"].as_str().unwrap_or("a peer");
+    let (title, body) = match (kind, payload["event"].as_str()) {
+        ("file", Some("received")) => (format!("New file from {}", peer), payload["filename"].as_str().unwrap_or_default().to_string()),
+        ("chat", Some("peer_messages")) => {
+            let count = payload["count"].as_u64().unwrap_or(1);
+            let title = if count == 1 { format!("New message from {}", peer) } else { format!("{} new messages from {}", count, peer) };
+            (title, preview(payload["last"].as_str().unwrap_or_default()))
+        }
+        _ => return,
+    };
+    // Showing a notification can block (D-Bus, WinRT), so keep it off the async runtime
+    std::thread::spawn(move || {
+        if let Err(e) = notify_rust::Notification::new().appname("MeshMind").summary(&title).body(&body).show() {
+            eprintln!("DESKTOP: Notification failed: {}", e);
+        }
+    });
+}
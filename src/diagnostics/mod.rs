@@ -0,0 +1,161 @@
+// Remote diagnostics: GET /api/peers/{ip}/diagnostics asks a connected peer over TCP (DIAQ:) for
+// its version, a summary of its configuration and its recent events, and waits for the DIAR:
+// answer. Only peers holding the mesh secret can ask, and a node answers only when its
+// `diagnostics` settings allow it; otherwise it sends back a refusal. The summary leaves out
+// anything secret (keys, URLs, addresses of other services).
+use actix_web::{get, web, HttpResponse, Error};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex};
+use crate::events::{self, Category};
+
+// Most events a peer may ask for in one request
+pub const MAX_EVENTS: u32 = 200;
+const DEFAULT_EVENTS: u32 = 50;
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticsSettings {
+    // Answer diagnostics requests from peers at all
+    pub share_with_peers: bool,
+    // Peer IPs that may ask; empty allows every authenticated peer
+    pub allowed_peers: Vec<String>,
+}
+
+impl DiagnosticsSettings {
+    pub fn normalize(&mut self) {
+        let mut seen = HashSet::new();
+        self.allowed_peers = self.allowed_peers.iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty() && seen.insert(p.clone())).collect();
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(bad) = self.allowed_peers.iter().find(|p| p.parse::<IpAddr>().is_err()) {
+            return Err(format!("diagnostics.allowed_peers takes IP addresses: {:?}", bad));
+        }
+        Ok(())
+    }
+
+    fn allows(&self, peer_ip: &str) -> bool {
+        self.share_with_peers && (self.allowed_peers.is_empty() || self.allowed_peers.iter().any(|p| p == peer_ip))
+    }
+}
+
+// Request id -> (peer asked, where its answer goes)
+type PendingRequests = HashMap<u64, (String, oneshot::Sender<String>)>;
+
+static PENDING: Lazy<Mutex<PendingRequests>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// The JSON answer to a peer's request for up to `limit` events, or a refusal
+pub async fn report(peer_ip: &str, limit: u32) -> String {
+    let settings = crate::settings::current().await;
+    if !settings.diagnostics.allows(peer_ip) {
+        events::warn(
+            Category::Security,
+            format!("Refused diagnostics request from {}", peer_ip),
+            serde_json::json!({ "peer": peer_ip }),
+        );
+        return serde_json::json!({ "shared": false, "message": "This node does not share diagnostics with you" }).to_string();
+    }
+    let mut connected = crate::tcp::connected_peer_ips().await;
+    connected.sort();
+    let mut report = serde_json::json!({
+        "shared": true,
+        "node_id": crate::persistence::node_id(),
+        "hostname": hostname::get().map(|h| h.to_string_lossy().to_string()).unwrap_or_default(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "protocol": crate::tcp::PROTOCOL_VERSION,
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "is_llm_host": crate::tcp::is_llm_available().await,
+        "connected_peers": connected,
+        "config": {
+            "allowed_file_types": settings.allowed_file_types.len(),
+            "max_file_size": settings.max_file_size,
+            "tls": crate::tls::enabled(),
+            "llm_backend": settings.backend.kind,
+            "scan": settings.scan.clamd.is_some(),
+            "moderation": settings.moderation.enabled,
+            "update": settings.update.enabled,
+            "public_page": settings.public.enabled
+        },
+        "events": events::recent(limit.min(MAX_EVENTS) as usize)
+    });
+    // Drop the oldest events until the answer fits in one frame
+    let mut text = report.to_string();
+    while text.len() > crate::tcp::MAX_DIAGNOSTICS_SIZE - 32 {
+        match report["events"].as_array_mut() {
+            Some(list) if !list.is_empty() => {
+                list.pop();
+            }
+            _ => break,
+        }
+        text = report.to_string();
+    }
+    events::info(Category::Security, format!("Shared diagnostics with {}", peer_ip), serde_json::json!({ "peer": peer_ip }));
+    text
+}
+
+// A DIAR: answer from a peer; ignored unless it answers our request to that peer
+pub async fn response_received(peer_ip: &str, request_id: u64, report: String) {
+    let mut pending = PENDING.lock().await;
+    if pending.get(&request_id).is_some_and(|(peer, _)| peer == peer_ip) {
+        if let Some((_, sender)) = pending.remove(&request_id) {
+            let _ = sender.send(report);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DiagnosticsQuery {
+    // Recent events to include
+    pub limit: Option<u32>,
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can ask peers for diagnostics" }))
+}
+
+#[get("/peers/{ip}/diagnostics")]
+pub async fn peer_diagnostics(path: web::Path<String>, query: web::Query<DiagnosticsQuery>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let ip = path.into_inner();
+    if ip.parse::<IpAddr>().is_err() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": "Invalid peer IP" })));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_EVENTS).min(MAX_EVENTS);
+    let request_id: u64 = rand::random();
+    let (sender, receiver) = oneshot::channel();
+    PENDING.lock().await.insert(request_id, (ip.clone(), sender));
+
+    if let Err(e) = crate::tcp::request_diagnostics(&ip, request_id, limit).await {
+        PENDING.lock().await.remove(&request_id);
+        let mut status = if e.kind() == std::io::ErrorKind::NotConnected {
+            HttpResponse::NotFound()
+        } else {
+            HttpResponse::BadGateway()
+        };
+        return Ok(status.json(serde_json::json!({ "success": false, "message": e.to_string() })));
+    }
+    let report = match tokio::time::timeout(RESPONSE_TIMEOUT, receiver).await {
+        Ok(Ok(report)) => report,
+        _ => {
+            PENDING.lock().await.remove(&request_id);
+            return Ok(HttpResponse::GatewayTimeout().json(serde_json::json!({
+                "success": false,
+                "message": format!("Peer {} did not answer", ip)
+            })));
+        }
+    };
+    let report: serde_json::Value = serde_json::from_str(&report).unwrap_or_default();
+    if report["shared"] != true {
+        let message = report["message"].as_str().unwrap_or("Peer refused the request").to_string();
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": message })));
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "peer": ip, "diagnostics": report })))
+}
@@ -0,0 +1,167 @@
+// Drop-folder ingestion: files written into DROP_FOLDER (by scp/sftp, a sync tool, or by hand)
+// are imported into the file store and broadcast to peers, then moved to DROP_FOLDER/imported
+// (or DROP_FOLDER/rejected when the store refuses them). Disabled unless DROP_FOLDER is set.
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+
+const IMPORTED_DIR: &str = "imported";
+const REJECTED_DIR: &str = "rejected";
+// How long a file's size must stay unchanged before it is considered fully written
+const SETTLE_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_SETTLE_CHECKS: u32 = 600;
+
+fn drop_folder() -> Option<PathBuf> {
+    std::env::var("DROP_FOLDER")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+// Skip hidden files and the temp names transfer tools write before renaming into place
+fn is_candidate(path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(n) => n.to_string_lossy().to_lowercase(),
+        None => return false,
+    };
+    !(name.starts_with('.')
+        || name.starts_with('~')
+        || name.ends_with(".part")
+        || name.ends_with(".partial")
+        || name.ends_with(".filepart")
+        || name.ends_with(".tmp")
+        || name.ends_with(".crdownload"))
+}
+
+async fn wait_until_settled(path: &Path) -> bool {
+    let mut last_size = None;
+    for _ in 0..MAX_SETTLE_CHECKS {
+        let size = match tokio::fs::metadata(path).await {
+            Ok(m) if m.is_file() => m.len(),
+            _ => return false,
+        };
+        if last_size == Some(size) {
+            return true;
+        }
+        last_size = Some(size);
+        tokio::time::sleep(SETTLE_INTERVAL).await;
+    }
+    false
+}
+
+async fn move_into(path: &Path, root: &Path, subdir: &str) {
+    let dir = root.join(subdir);
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        eprintln!("[DROP] Failed to create {}: {}", dir.display(), e);
+        return;
+    }
+    let name = match path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return,
+    };
+    let target = dir.join(format!("{}_{}", chrono::Utc::now().format("%Y%m%d_%H%M%S"), name));
+    if let Err(e) = tokio::fs::rename(path, &target).await {
+        eprintln!("[DROP] Failed to move {} to {}: {}", path.display(), target.display(), e);
+        // Never import the same file twice
+        let _ = tokio::fs::remove_file(path).await;
+    }
+}
+
+async fn import_file(path: PathBuf, root: PathBuf) {
+    if !wait_until_settled(&path).await {
+        return;
+    }
+    let filename = match path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return,
+    };
+    let content = match tokio::fs::read(&path).await {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("[DROP] Failed to read {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let file_type = mime_guess::from_path(&path).first_or_octet_stream().to_string();
+    let local_ip = crate::ip::primary_ip()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string());
+
+    match crate::persistence::save_uploaded_file(&filename, &file_type, &content, &local_ip).await {
+        Ok(info) => {
+            println!("[DROP] Imported {} ({} bytes)", filename, content.len());
+            crate::tcp::broadcast_file_to_peers(filename.clone(), file_type, content).await;
+            crate::events::publish("file", serde_json::json!({ "event": "uploaded", "source": "drop_folder", "file": info }));
+            move_into(&path, &root, IMPORTED_DIR).await;
+        }
+        Err(e) => {
+            eprintln!("[DROP] Rejected {}: {}", filename, e);
+            move_into(&path, &root, REJECTED_DIR).await;
+        }
+    }
+}
+
+pub async fn watch_drop_folder() {
+    let root = match drop_folder() {
+        Some(r) => r,
+        None => return,
+    };
+    if let Err(e) = tokio::fs::create_dir_all(&root).await {
+        eprintln!("[DROP] Cannot create drop folder {}: {}", root.display(), e);
+        return;
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+    let event_tx = tx.clone();
+    let mut watcher = match notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) => {
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                for path in event.paths {
+                    let _ = event_tx.send(path);
+                }
+            }
+        }
+        Err(e) => eprintln!("[DROP] Watch error: {}", e),
+    }) {
+        Ok(w) => w,
+        Err(e) => {
+            eprintln!("[DROP] Failed to start watcher: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = watcher.watch(&root, RecursiveMode::NonRecursive) {
+        eprintln!("[DROP] Failed to watch {}: {}", root.display(), e);
+        return;
+    }
+    println!("[DROP] Watching {} for files to import", root.display());
+
+    // Pick up anything dropped while the node was offline
+    if let Ok(mut rd) = tokio::fs::read_dir(&root).await {
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            let _ = tx.send(entry.path());
+        }
+    }
+
+    // A file produces many events while it is written; import each path once at a time
+    let in_flight: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
+    while let Some(path) = rx.recv().await {
+        if path.parent() != Some(root.as_path()) || !path.is_file() || !is_candidate(&path) {
+            continue;
+        }
+        if !in_flight.lock().await.insert(path.clone()) {
+            continue;
+        }
+        let in_flight = in_flight.clone();
+        let root = root.clone();
+        tokio::spawn(async move {
+            import_file(path.clone(), root).await;
+            in_flight.lock().await.remove(&path);
+        });
+    }
+    // Keep the watcher alive for as long as the loop runs
+    drop(watcher);
+}
@@ -0,0 +1,394 @@
+// Log of operational events (peers connecting and leaving, files stored, failed authentication,
+// ...) with a category and severity. Each event is printed like the other log lines, kept in
+// memory, pushed to WebSocket clients (/api/ws) as {"type": "event", ...} and appended to
+// events.log, which rolls over to events.log.1 so the log on disk stays bounded; the newest events
+// are loaded back on start. GET /api/events queries them.
+//
+// Identical events (same category, severity and message) repeating within the coalescing window
+// are held back after the first and summed up once the window ends, e.g. "Discovered peer
+// 10.0.0.5 (3 more times in the last 5 min)", so periodic discovery does not flood the log. The
+// `events` settings pick the window and a verbosity per category: `quiet` drops informational
+// events, `normal` coalesces and `verbose` keeps every event.
+//
+// Separately, publish() hands app events (peers connecting, files uploaded or received, chat
+// messages) to the parts that act on them: file pipelines, the MQTT bridge and, when built with
+// the `desktop` feature, native notifications.
+use actix_web::{get, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+const LOG_FILE: &str = "events.log";
+const PREVIOUS_LOG_FILE: &str = "events.log.1";
+// events.log is rolled over past this size
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+const MAX_EVENTS_IN_MEMORY: usize = 1000;
+const DEFAULT_COALESCE_WINDOW_SECS: u64 = 300;
+const MAX_COALESCE_WINDOW_SECS: u64 = 24 * 3600;
+// Distinct repeating events tracked at once; past that, new ones are not held back
+const MAX_TRACKED_REPEATS: usize = 1000;
+// How often held-back repeats whose window ended are summed up
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Peer,
+    // UDP announcements and polling peers for their file lists
+    Discovery,
+    File,
+    Transfer,
+    Security,
+    System,
+}
+
+impl Category {
+    fn prefix(self) -> &'static str {
+        match self {
+            Category::Peer => "PEER",
+            Category::Discovery => "DISCOVERY",
+            Category::File => "FILE",
+            Category::Transfer => "TRANSFER",
+            Category::Security => "SECURITY",
+            Category::System => "SYSTEM",
+        }
+    }
+}
+
+// Ordered, so a query for "warning" also returns errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub category: Category,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub details: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    // Warnings and errors only
+    Quiet,
+    // Repeats within the window are coalesced
+    #[default]
+    Normal,
+    // Every event, repeats included
+    Verbose,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventSettings {
+    // Repeats of an event within this many seconds are summed up; 0 turns coalescing off
+    pub coalesce_window_secs: u64,
+    // Per category, e.g. {"discovery": "quiet"}; unlisted categories are `normal`
+    pub verbosity: BTreeMap<Category, Verbosity>,
+}
+
+impl Default for EventSettings {
+    fn default() -> Self {
+        EventSettings { coalesce_window_secs: DEFAULT_COALESCE_WINDOW_SECS, verbosity: BTreeMap::new() }
+    }
+}
+
+impl EventSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.coalesce_window_secs > MAX_COALESCE_WINDOW_SECS {
+            return Err(format!("events.coalesce_window_secs must be at most {}", MAX_COALESCE_WINDOW_SECS));
+        }
+        Ok(())
+    }
+
+    fn verbosity(&self, category: Category) -> Verbosity {
+        self.verbosity.get(&category).copied().unwrap_or_default()
+    }
+}
+
+type RepeatKey = (Category, Severity, String);
+
+#[derive(Debug)]
+struct Repeat {
+    since: Instant,
+    // Occurrences held back since the one that was recorded
+    held_back: u32,
+    details: serde_json::Value,
+}
+
+// A repeated event's summary, due once its window has ended
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub category: Category,
+    pub severity: Severity,
+    pub message: String,
+    pub held_back: u32,
+    pub details: serde_json::Value,
+}
+
+// Holds back repeats of identical events within a window
+#[derive(Debug, Default)]
+pub struct Coalescer {
+    repeats: HashMap<RepeatKey, Repeat>,
+}
+
+impl Coalescer {
+    // Whether an event should be recorded now, plus the summary of the previous window's repeats
+    // when this event starts a new one
+    pub fn admit(&mut self, category: Category, severity: Severity, message: &str, details: &serde_json::Value, now: Instant, window: Duration) -> (bool, Option<Summary>) {
+        let key = (category, severity, message.to_string());
+        if let Some(repeat) = self.repeats.get_mut(&key) {
+            if now.duration_since(repeat.since) < window {
+                repeat.held_back += 1;
+                repeat.details = details.clone();
+                return (false, None);
+            }
+        }
+        if self.repeats.len() >= MAX_TRACKED_REPEATS && !self.repeats.contains_key(&key) {
+            self.repeats.retain(|_, r| now.duration_since(r.since) < window);
+            if self.repeats.len() >= MAX_TRACKED_REPEATS {
+                return (true, None);
+            }
+        }
+        let previous = self.repeats.insert(key.clone(), Repeat { since: now, held_back: 0, details: serde_json::Value::Null });
+        (true, previous.and_then(|r| summary(key, r)))
+    }
+
+    // Summaries of every window that has ended; those events are forgotten
+    pub fn due(&mut self, now: Instant, window: Duration) -> Vec<Summary> {
+        let ended: Vec<RepeatKey> = self.repeats.iter().filter(|(_, r)| now.duration_since(r.since) >= window).map(|(k, _)| k.clone()).collect();
+        ended.into_iter().filter_map(|k| self.repeats.remove(&k).and_then(|r| summary(k, r))).collect()
+    }
+}
+
+fn summary((category, severity, message): RepeatKey, repeat: Repeat) -> Option<Summary> {
+    (repeat.held_back > 0).then_some(Summary { category, severity, message, held_back: repeat.held_back, details: repeat.details })
+}
+
+fn describe_window(window: Duration) -> String {
+    match window.as_secs() {
+        s if s % 3600 == 0 => format!("{} h", s / 3600),
+        s if s % 60 == 0 => format!("{} min", s / 60),
+        s => format!("{} s", s),
+    }
+}
+
+static EVENTS: Lazy<StdMutex<VecDeque<Event>>> = Lazy::new(|| StdMutex::new(VecDeque::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+// Lines for the task appending to events.log, started by init()
+static WRITER: OnceCell<mpsc::UnboundedSender<String>> = OnceCell::new();
+static SETTINGS: Lazy<StdMutex<EventSettings>> = Lazy::new(|| StdMutex::new(EventSettings::default()));
+static REPEATS: Lazy<StdMutex<Coalescer>> = Lazy::new(|| StdMutex::new(Coalescer::default()));
+
+// Apply the `events` settings; called at startup and whenever the settings change
+pub fn configure(settings: &EventSettings) {
+    *SETTINGS.lock().unwrap() = settings.clone();
+}
+
+// An app event of `kind` ("peer", "file" or "chat"), payload["event"] saying what happened
+pub fn publish(kind: &str, payload: serde_json::Value) {
+    #[cfg(feature = "desktop")]
+    crate::desktop::notify_event(kind, &payload);
+    crate::pipelines::on_event(kind, &payload);
+    crate::mqtt::publish(kind, payload);
+}
+
+pub fn info(category: Category, message: impl Into<String>, details: serde_json::Value) {
+    record(category, Severity::Info, message, details);
+}
+
+pub fn warn(category: Category, message: impl Into<String>, details: serde_json::Value) {
+    record(category, Severity::Warning, message, details);
+}
+
+pub fn error(category: Category, message: impl Into<String>, details: serde_json::Value) {
+    record(category, Severity::Error, message, details);
+}
+
+pub fn record(category: Category, severity: Severity, message: impl Into<String>, details: serde_json::Value) {
+    let message = message.into();
+    let (verbosity, window) = {
+        let settings = SETTINGS.lock().unwrap();
+        (settings.verbosity(category), Duration::from_secs(settings.coalesce_window_secs))
+    };
+    if verbosity == Verbosity::Quiet && severity == Severity::Info {
+        return;
+    }
+    if verbosity == Verbosity::Normal && !window.is_zero() {
+        let (admitted, previous) = REPEATS.lock().unwrap().admit(category, severity, &message, &details, Instant::now(), window);
+        if let Some(previous) = previous {
+            record_summary(previous, window);
+        }
+        if !admitted {
+            return;
+        }
+    }
+    push(category, severity, message, details);
+}
+
+fn record_summary(summary: Summary, window: Duration) {
+    push(
+        summary.category,
+        summary.severity,
+        format!("{} ({} more times in the last {})", summary.message, summary.held_back, describe_window(window)),
+        serde_json::json!({ "repeated": summary.held_back, "window_secs": window.as_secs(), "details": summary.details }),
+    );
+}
+
+fn push(category: Category, severity: Severity, message: String, details: serde_json::Value) {
+    let event = Event {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        timestamp: Utc::now(),
+        category,
+        severity,
+        message,
+        details,
+    };
+    if severity == Severity::Info {
+        println!("{}: {}", category.prefix(), event.message);
+    } else {
+        eprintln!("{}: {}", category.prefix(), event.message);
+    }
+    let mut ws_event = serde_json::to_value(&event).unwrap_or_default();
+    ws_event["type"] = "event".into();
+    crate::presence::publish(ws_event);
+    if let (Some(writer), Ok(line)) = (WRITER.get(), serde_json::to_string(&event)) {
+        let _ = writer.send(line);
+    }
+    let mut events = EVENTS.lock().unwrap();
+    events.push_back(event);
+    while events.len() > MAX_EVENTS_IN_MEMORY {
+        events.pop_front();
+    }
+}
+
+// Load the newest events from disk and start writing new ones; called once at startup before
+// anything is recorded
+pub async fn init() {
+    configure(&crate::settings::current().await.events);
+    let mut saved: Vec<Event> = Vec::new();
+    for path in [PREVIOUS_LOG_FILE, LOG_FILE] {
+        let content = tokio::fs::read_to_string(path).await.unwrap_or_default();
+        saved.extend(content.lines().filter_map(|l| serde_json::from_str::<Event>(l).ok()));
+    }
+    NEXT_ID.store(saved.iter().map(|e| e.id).max().unwrap_or(0) + 1, Ordering::Relaxed);
+    let skip = saved.len().saturating_sub(MAX_EVENTS_IN_MEMORY);
+    EVENTS.lock().unwrap().extend(saved.into_iter().skip(skip));
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    if WRITER.set(sender).is_ok() {
+        tokio::spawn(write_events(receiver));
+        tokio::spawn(flush_repeats());
+    }
+}
+
+// Sum up held-back repeats once their window ends, even if the event does not come again
+async fn flush_repeats() {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let window = Duration::from_secs(SETTINGS.lock().unwrap().coalesce_window_secs);
+        let due = REPEATS.lock().unwrap().due(Instant::now(), window);
+        for summary in due {
+            record_summary(summary, window);
+        }
+    }
+}
+
+async fn write_events(mut receiver: mpsc::UnboundedReceiver<String>) {
+    while let Some(mut line) = receiver.recv().await {
+        line.push('\n');
+        if tokio::fs::metadata(LOG_FILE).await.is_ok_and(|m| m.len() + line.len() as u64 > MAX_LOG_BYTES) {
+            if let Err(e) = tokio::fs::rename(LOG_FILE, PREVIOUS_LOG_FILE).await {
+                eprintln!("EVENTS: Failed to roll over {}: {}", LOG_FILE, e);
+            }
+        }
+        let file = tokio::fs::OpenOptions::new().create(true).append(true).open(LOG_FILE).await;
+        let result = match file {
+            Ok(mut f) => f.write_all(line.as_bytes()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!("EVENTS: Failed to write {}: {}", LOG_FILE, e);
+        }
+    }
+}
+
+// The newest `limit` events, newest first
+pub fn recent(limit: usize) -> Vec<Event> {
+    EVENTS.lock().unwrap().iter().rev().take(limit).cloned().collect()
+}
+
+#[derive(Deserialize)]
+pub struct EventQuery {
+    pub category: Option<Category>,
+    // Lowest severity to include
+    pub severity: Option<Severity>,
+    pub since: Option<DateTime<Utc>>,
+    // Only events with a larger id, for polling
+    pub after: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+// Newest events first
+#[get("/events")]
+pub async fn list_events(query: web::Query<EventQuery>) -> Result<HttpResponse, Error> {
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_EVENTS_IN_MEMORY);
+    let events: Vec<Event> = EVENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .filter(|e| query.category.is_none_or(|c| e.category == c))
+        .filter(|e| query.severity.is_none_or(|s| e.severity >= s))
+        .filter(|e| query.since.is_none_or(|t| e.timestamp >= t))
+        .filter(|e| query.after.is_none_or(|id| e.id > id))
+        .take(limit)
+        .cloned()
+        .collect();
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Category, Coalescer, Duration, Instant, Severity};
+
+    // Repeats within the window are held back and summed up once, when the window ends or the event
+    // comes again after it
+    #[test]
+    fn repeated_events_are_coalesced() {
+        let mut coalescer = Coalescer::default();
+        let window = Duration::from_secs(300);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let details = serde_json::Value::Null;
+        let mut admit = |message: &str, secs| coalescer.admit(Category::Discovery, Severity::Info, message, &details, at(secs), window);
+        assert_eq!(admit("seen 10.0.0.5", 0), (true, None));
+        assert_eq!(admit("seen 10.0.0.5", 30), (false, None));
+        assert_eq!(admit("seen 10.0.0.5", 60), (false, None));
+        assert_eq!(admit("seen 10.0.0.6", 60), (true, None));
+        let (admitted, summary) = admit("seen 10.0.0.5", 300);
+        assert!(admitted);
+        assert_eq!(summary.map(|s| (s.message, s.held_back)), Some(("seen 10.0.0.5".to_string(), 2)));
+
+        assert_eq!(coalescer.admit(Category::Discovery, Severity::Info, "seen 10.0.0.6", &details, at(90), window), (false, None));
+        let due = coalescer.due(at(360), window);
+        assert_eq!(due.iter().map(|s| (s.message.as_str(), s.held_back)).collect::<Vec<_>>(), [("seen 10.0.0.6", 1)]);
+        assert!(coalescer.due(at(900), window).is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+// Export module: renders a conversation into a shareable Markdown or PDF document
+use actix_web::{get, web, HttpResponse, Error};
+use serde::Deserialize;
+use crate::conversation::{Conversation, MessageType, CONVERSATION_STORE};
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: Option<String>,
+}
+
+fn sender_label(sender: &str, message_type: &MessageType) -> String {
+    match message_type {
+        MessageType::Question => format!("{} (question)", sender),
+        MessageType::Response => format!("{} (response)", sender),
+    }
+}
+
+// Collect the distinct files referenced by messages, in order of first mention
+fn file_references(conv: &Conversation) -> Vec<String> {
+    let mut refs: Vec<String> = Vec::new();
+    for m in &conv.messages {
+        if let Some(name) = &m.attachment {
+            if !refs.contains(name) {
+                refs.push(name.clone());
+            }
+        }
+    }
+    refs
+}
+
+pub fn render_markdown(conv: &Conversation) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("# Conversation `{}`\n\n", conv.id));
+    out.push_str(&format!(
+        "- Host: {} ({})\n- LLM host: {}\n- Messages: {}\n- Exported: {}\n\n",
+        conv.host_info.hostname,
+        conv.host_info.ip_address,
+        if conv.host_info.is_llm_host { "yes" } else { "no" },
+        conv.messages.len(),
+        chrono::Utc::now().to_rfc3339()
+    ));
+
+    let refs = file_references(conv);
+    if !refs.is_empty() {
+        out.push_str("## Referenced files\n\n");
+        for name in &refs {
+            out.push_str(&format!("- `{}`\n", name));
+        }
+        out.push('\n');
+    }
+
+    out.push_str("## Messages\n\n");
+    for m in &conv.messages {
+        out.push_str(&format!(
+            "### {} — {}\n\n",
+            sender_label(&m.sender, &m.message_type),
+            m.timestamp.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+        if let Some(name) = &m.attachment {
+            out.push_str(&format!("> File: `{}`\n\n", name));
+        }
+        out.push_str(m.content.trim_end());
+        out.push_str("\n\n---\n\n");
+    }
+    out
+}
+
+// Plain-text rendering used as the PDF body (one logical line per entry, wrapped later)
+fn render_plain_lines(conv: &Conversation) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    lines.push(format!("Conversation {}", conv.id));
+    lines.push(format!("Host: {} ({})", conv.host_info.hostname, conv.host_info.ip_address));
+    lines.push(format!("Messages: {}", conv.messages.len()));
+    lines.push(format!("Exported: {}", chrono::Utc::now().to_rfc3339()));
+    lines.push(String::new());
+
+    let refs = file_references(conv);
+    if !refs.is_empty() {
+        lines.push("Referenced files:".to_string());
+        for name in &refs {
+            lines.push(format!("  - {}", name));
+        }
+        lines.push(String::new());
+    }
+
+    for m in &conv.messages {
+        lines.push(format!(
+            "[{}] {}",
+            m.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            sender_label(&m.sender, &m.message_type)
+        ));
+        if let Some(name) = &m.attachment {
+            lines.push(format!("File: {}", name));
+        }
+        for l in m.content.lines() {
+            lines.push(l.to_string());
+        }
+        lines.push(String::new());
+    }
+    lines
+}
+
+// Wrap a line to at most `width` characters, breaking on whitespace where possible
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.len() <= width {
+        return vec![line.to_string()];
+    }
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + width).min(chars.len());
+        if end < chars.len() {
+            if let Some(space) = chars[start..end].iter().rposition(|c| c.is_whitespace()) {
+                if space > 0 {
+                    end = start + space + 1;
+                }
+            }
+        }
+        out.push(chars[start..end].iter().collect::<String>().trim_end().to_string());
+        start = end;
+    }
+    out
+}
+
+// Escape text for a PDF literal string; characters outside Latin-1 are replaced with '?'
+fn pdf_escape(text: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push(b'\\');
+                out.push(c as u8);
+            }
+            '\t' => out.extend_from_slice(b"    "),
+            c if (c as u32) < 0x20 => {}
+            c if (c as u32) <= 0xFF => out.push(c as u32 as u8),
+            _ => out.push(b'?'),
+        }
+    }
+    out
+}
+
+// Minimal PDF 1.4 writer: A4 pages, built-in Helvetica, text only.
+pub fn render_pdf(conv: &Conversation) -> Vec<u8> {
+    const PAGE_W: u32 = 595;
+    const PAGE_H: u32 = 842;
+    const MARGIN: u32 = 50;
+    const FONT_SIZE: u32 = 10;
+    const LEADING: u32 = 13;
+    const WRAP_CHARS: usize = 95;
+
+    let mut wrapped: Vec<String> = Vec::new();
+    for line in render_plain_lines(conv) {
+        wrapped.extend(wrap_line(&line, WRAP_CHARS));
+    }
+    let lines_per_page = ((PAGE_H - 2 * MARGIN) / LEADING) as usize;
+    let pages: Vec<&[String]> = if wrapped.is_empty() {
+        vec![&[]]
+    } else {
+        wrapped.chunks(lines_per_page).collect()
+    };
+
+    // Object layout: 1 catalog, 2 pages, 3 font, then (page, content) pairs
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 4 + i * 2)).collect();
+    objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).into_bytes());
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec());
+
+    for (i, page_lines) in pages.iter().enumerate() {
+        let content_id = 5 + i * 2;
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            PAGE_W, PAGE_H, content_id
+        ).into_bytes());
+
+        let mut stream: Vec<u8> = Vec::new();
+        stream.extend_from_slice(format!("BT /F1 {} Tf {} TL {} {} Td\n", FONT_SIZE, LEADING, MARGIN, PAGE_H - MARGIN).as_bytes());
+        for line in page_lines.iter() {
+            stream.push(b'(');
+            stream.extend_from_slice(&pdf_escape(line));
+            stream.extend_from_slice(b") Tj T*\n");
+        }
+        stream.extend_from_slice(b"ET");
+
+        let mut obj = format!("<< /Length {} >>\nstream\n", stream.len()).into_bytes();
+        obj.extend_from_slice(&stream);
+        obj.extend_from_slice(b"\nendstream");
+        objects.push(obj);
+    }
+
+    let mut out: Vec<u8> = b"%PDF-1.4\n".to_vec();
+    let mut offsets: Vec<usize> = Vec::with_capacity(objects.len());
+    for (i, obj) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        out.extend_from_slice(obj);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+    let xref_start = out.len();
+    out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for off in offsets {
+        out.extend_from_slice(format!("{:010} 00000 n \n", off).as_bytes());
+    }
+    out.extend_from_slice(format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_start
+    ).as_bytes());
+    out
+}
+
+#[get("/conversations/{id}/export")]
+pub async fn export_conversation(path: web::Path<String>, query: web::Query<ExportQuery>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    // Like /api/local, a signed-in user exports only their own questions and the answers to them
+    let user = crate::users::view(user.map(|u| u.into_inner()), None).ok().flatten();
+    let conv = match CONVERSATION_STORE.get_conversation(&id).await {
+        Some(c) => match user.as_deref() {
+            Some(user) => c.for_user(user),
+            None => c,
+        },
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": format!("Conversation {} not found", id)
+            })));
+        }
+    };
+
+    // Keep the download name filesystem-friendly (peer ids are IPs, which contain dots)
+    let safe_id: String = id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' }).collect();
+    let format = query.format.as_deref().unwrap_or("md").to_lowercase();
+    match format.as_str() {
+        "md" | "markdown" => Ok(HttpResponse::Ok()
+            .content_type("text/markdown; charset=utf-8")
+            .append_header(("Content-Disposition", format!("attachment; filename=\"conversation-{}.md\"", safe_id)))
+            .body(render_markdown(&conv))),
+        "pdf" => Ok(HttpResponse::Ok()
+            .content_type("application/pdf")
+            .append_header(("Content-Disposition", format!("attachment; filename=\"conversation-{}.pdf\"", safe_id)))
+            .body(render_pdf(&conv))),
+        other => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Unsupported export format '{}', expected md or pdf", other)
+        }))),
+    }
+}
+
+// ---------------- Automatic transcript sharing ----------------
+// When TRANSCRIPT_INTERVAL_SECS is set, the recent local conversation is rendered to
+// transcript-<hostname>.md, stored in the file store and broadcast like any other upload.
+const DEFAULT_TRANSCRIPT_MESSAGES: usize = 50;
+
+fn transcript_interval() -> Option<std::time::Duration> {
+    std::env::var("TRANSCRIPT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(std::time::Duration::from_secs)
+}
+
+fn transcript_max_messages() -> usize {
+    std::env::var("TRANSCRIPT_MAX_MESSAGES")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_TRANSCRIPT_MESSAGES)
+}
+
+pub async fn periodic_transcript_share() {
+    let interval = match transcript_interval() {
+        Some(i) => i,
+        None => return,
+    };
+    let max_messages = transcript_max_messages();
+    let hostname = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "node".to_string());
+    let transcript_name = format!("transcript-{}.md", hostname);
+    println!("[TRANSCRIPT] Publishing {} every {}s (last {} messages)", transcript_name, interval.as_secs(), max_messages);
+
+    let mut ticker = tokio::time::interval(interval);
+    let mut last_published_count: Option<usize> = None;
+    loop {
+        ticker.tick().await;
+
+        // The transcript is shared with peers, so it gets the same redaction as conversation sync
+        let mut conv = match CONVERSATION_STORE.get_local_conversation().await {
+            Some(c) if c.privacy.is_shared() => crate::redact::for_peers(&c).await,
+            _ => continue,
+        };
+        // Only republish when the conversation has changed since the last run
+        let total = conv.messages.len();
+        if last_published_count == Some(total) {
+            continue;
+        }
+        if total > max_messages {
+            conv.messages.drain(..total - max_messages);
+        }
+        let content = render_markdown(&conv).into_bytes();
+
+        let local_ip = crate::ip::primary_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|_| "127.0.0.1".to_string());
+
+        // Replace the previous transcript instead of accumulating timestamped copies
+        if let Err(e) = crate::persistence::remove_uploaded_file(&transcript_name).await {
+            eprintln!("[TRANSCRIPT] Failed to remove previous transcript: {}", e);
+        }
+        match crate::persistence::save_uploaded_file(&transcript_name, "text/markdown", &content, &local_ip).await {
+            Ok(_) => {
+                crate::tcp::broadcast_file_to_peers(transcript_name.clone(), "text/markdown".to_string(), content).await;
+                println!("[TRANSCRIPT] Published {} ({} messages)", transcript_name, conv.messages.len());
+                last_published_count = Some(total);
+            }
+            Err(e) => eprintln!("[TRANSCRIPT] Failed to save transcript: {}", e),
+        }
+    }
+}
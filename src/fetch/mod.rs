@@ -0,0 +1,299 @@
+// Multi-source (segmented) download: a file held by several peers is split into fixed-size
+// segments that are fetched concurrently from different replicas over HTTP Range requests,
+// each checked against a per-segment SHA-256 manifest and assembled locally.
+//
+// Every node serves its stored copies by content hash at /api/replica/{sha256} so any peer that
+// received a file can act as a source, not just the original uploader. Peers sign these requests
+// with the P2P secret (tcp::sign_peer_request).
+use actix_web::{post, route, get, web, HttpRequest, HttpResponse, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use crate::persistence::{self, RECEIVED_DIR};
+
+const DEFAULT_SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+const MIN_SEGMENT_SIZE: u64 = 64 * 1024;
+// Failures tolerated per source before it is dropped from the download
+const MAX_SOURCE_FAILURES: u32 = 3;
+// For each request to a source, a segment included
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+pub struct SegmentQuery {
+    size: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SegmentManifest {
+    file_size: u64,
+    segment_size: u64,
+    segments: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FetchRequest {
+    filename: String,
+    // Optional when the file was announced with its hash (FILE_META)
+    #[serde(default)]
+    sha256: Option<String>,
+    // Peer the file belongs to; the download is stored under received/<uploader_ip>/
+    #[serde(default)]
+    uploader_ip: Option<String>,
+    // Restrict sources; defaults to every connected peer
+    #[serde(default)]
+    peers: Option<Vec<String>>,
+}
+
+fn is_hex_sha256(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Parse a single "bytes=start-end" range against a file length
+pub fn parse_range(header: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || len == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let n: u64 = suffix.parse().ok()?;
+            (len.saturating_sub(n), len - 1)
+        }
+        (s, "") => (s.parse().ok()?, len - 1),
+        (s, e) => (s.parse().ok()?, e.parse::<u64>().ok()?.min(len - 1)),
+    };
+    if start > end || start >= len {
+        return None;
+    }
+    Some((start, end))
+}
+
+async fn read_replica(sha: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+    if !is_hex_sha256(sha) {
+        return Ok(None);
+    }
+    // Verified update binaries are served the same way so peers can upgrade from each other
+    match persistence::find_file_by_hash(sha).await? {
+        Some(path) => Ok(Some(tokio::fs::read(path).await?)),
+        None => match crate::update::binary_for_hash(sha).await {
+            Some(path) => Ok(Some(tokio::fs::read(path).await?)),
+            None => Ok(None),
+        },
+    }
+}
+
+#[route("/replica/{sha}", method = "GET", method = "HEAD")]
+pub async fn get_replica(req: HttpRequest, path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let sha = path.into_inner();
+    let content = match read_replica(&sha).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return Ok(HttpResponse::NotFound().finish()),
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    };
+    let len = content.len() as u64;
+    let peer = crate::bandwidth::requesting_peer(&req);
+    let sent = |bytes: usize| {
+        if req.method() != actix_web::http::Method::HEAD {
+            crate::bandwidth::record(crate::bandwidth::Channel::Files, peer.as_deref(), crate::bandwidth::Direction::Up, bytes);
+        }
+    };
+    if let Some(range) = req.headers().get("range").and_then(|v| v.to_str().ok()) {
+        return Ok(match parse_range(range, len) {
+            Some((start, end)) => {
+                sent((end + 1 - start) as usize);
+                HttpResponse::PartialContent()
+                    .insert_header(("Content-Range", format!("bytes {}-{}/{}", start, end, len)))
+                    .insert_header(("Accept-Ranges", "bytes"))
+                    .content_type("application/octet-stream")
+                    .body(content[start as usize..=end as usize].to_vec())
+            }
+            None => HttpResponse::RangeNotSatisfiable()
+                .insert_header(("Content-Range", format!("bytes */{}", len)))
+                .finish(),
+        });
+    }
+    sent(content.len());
+    Ok(HttpResponse::Ok()
+        .insert_header(("Accept-Ranges", "bytes"))
+        .content_type("application/octet-stream")
+        .body(content))
+}
+
+#[get("/replica/{sha}/segments")]
+pub async fn get_segments(path: web::Path<String>, query: web::Query<SegmentQuery>) -> Result<HttpResponse, Error> {
+    let sha = path.into_inner();
+    let content = match read_replica(&sha).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return Ok(HttpResponse::NotFound().finish()),
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    };
+    let segment_size = query.size.unwrap_or(DEFAULT_SEGMENT_SIZE).max(MIN_SEGMENT_SIZE);
+    let segments = content.chunks(segment_size as usize).map(persistence::sha256_hex).collect();
+    Ok(HttpResponse::Ok().json(SegmentManifest { file_size: content.len() as u64, segment_size, segments }))
+}
+
+// Sources that report holding the hash
+async fn find_sources(client: &reqwest::Client, sha: &str, candidates: Vec<String>) -> Vec<String> {
+    let mut sources = Vec::new();
+    for ip in candidates {
+        let url = format!("{}/api/replica/{}", crate::tls::peer_origin(&ip, 8080), sha);
+        match client.head(&url).timeout(REQUEST_TIMEOUT).header(crate::tcp::PEER_REQUEST_HEADER, crate::tcp::sign_peer_request("HEAD", &url).await).send().await {
+            Ok(resp) if resp.status().is_success() => sources.push(ip),
+            _ => {}
+        }
+    }
+    sources
+}
+
+async fn fetch_manifest(client: &reqwest::Client, sources: &[String], sha: &str) -> Result<SegmentManifest, String> {
+    for ip in sources {
+        let url = format!("{}/api/replica/{}/segments?size={}", crate::tls::peer_origin(ip, 8080), sha, DEFAULT_SEGMENT_SIZE);
+        if let Ok(resp) = client.get(&url).timeout(REQUEST_TIMEOUT).header(crate::tcp::PEER_REQUEST_HEADER, crate::tcp::sign_peer_request("GET", &url).await).send().await {
+            if let Ok(manifest) = resp.json::<SegmentManifest>().await {
+                return Ok(manifest);
+            }
+        }
+    }
+    Err("no source returned a segment manifest".to_string())
+}
+
+async fn fetch_segment(client: &reqwest::Client, ip: &str, sha: &str, start: u64, end: u64) -> Result<Vec<u8>, String> {
+    let url = format!("{}/api/replica/{}", crate::tls::peer_origin(ip, 8080), sha);
+    let resp = client
+        .get(&url)
+        .timeout(REQUEST_TIMEOUT)
+        .header(crate::tcp::PEER_REQUEST_HEADER, crate::tcp::sign_peer_request("GET", &url).await)
+        .header("Range", format!("bytes={}-{}", start, end))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(format!("unexpected status {}", resp.status()));
+    }
+    let segment = resp.bytes().await.map_err(|e| e.to_string())?.to_vec();
+    crate::bandwidth::record(crate::bandwidth::Channel::Files, Some(ip), crate::bandwidth::Direction::Down, segment.len());
+    Ok(segment)
+}
+
+// Fetch the content with hash `sha` in segments from whichever of `candidates` hold it. `check_size`
+// sees the size from the manifest before anything is downloaded. Returns the verified content and
+// the sources that held it.
+pub async fn fetch_segmented(
+    sha: &str,
+    candidates: Vec<String>,
+    label: &str,
+    check_size: impl FnOnce(u64) -> Result<(), String>,
+) -> Result<(Vec<u8>, Vec<String>), String> {
+    let client = crate::client::peer();
+    let sources = find_sources(client, sha, candidates).await;
+    if sources.is_empty() {
+        return Err(format!("no peer holds {}", label));
+    }
+    let manifest = fetch_manifest(client, &sources, sha).await?;
+    check_size(manifest.file_size)?;
+    let segment_count = manifest.segments.len();
+    println!("FETCH: {} ({} bytes) in {} segments from {} sources", label, manifest.file_size, segment_count, sources.len());
+
+    let manifest = Arc::new(manifest);
+    let queue: Arc<Mutex<VecDeque<usize>>> = Arc::new(Mutex::new((0..segment_count).collect()));
+    let parts: Arc<Mutex<Vec<Option<Vec<u8>>>>> = Arc::new(Mutex::new(vec![None; segment_count]));
+
+    // One worker per source pulls segments from the shared queue; a bad segment goes back for another source
+    let mut workers = Vec::new();
+    for ip in sources.clone() {
+        let (client, sha, manifest, queue, parts) = (client, sha.to_string(), manifest.clone(), queue.clone(), parts.clone());
+        workers.push(tokio::spawn(async move {
+            let mut failures = 0u32;
+            while failures < MAX_SOURCE_FAILURES {
+                let index = match queue.lock().await.pop_front() {
+                    Some(i) => i,
+                    None => break,
+                };
+                let start = index as u64 * manifest.segment_size;
+                let end = (start + manifest.segment_size).min(manifest.file_size) - 1;
+                match fetch_segment(client, &ip, &sha, start, end).await {
+                    Ok(data) if persistence::sha256_hex(&data).eq_ignore_ascii_case(&manifest.segments[index]) => {
+                        parts.lock().await[index] = Some(data);
+                    }
+                    Ok(_) => {
+                        eprintln!("FETCH: Segment {} from {} failed hash check", index, ip);
+                        failures += 1;
+                        queue.lock().await.push_back(index);
+                    }
+                    Err(e) => {
+                        eprintln!("FETCH: Segment {} from {} failed: {}", index, ip, e);
+                        failures += 1;
+                        queue.lock().await.push_back(index);
+                    }
+                }
+            }
+        }));
+    }
+    for w in workers {
+        let _ = w.await;
+    }
+
+    let parts = std::mem::take(&mut *parts.lock().await);
+    let missing = parts.iter().filter(|p| p.is_none()).count();
+    if missing > 0 {
+        return Err(format!("{} of {} segments could not be fetched", missing, segment_count));
+    }
+    let content: Vec<u8> = parts.into_iter().flatten().flatten().collect();
+    if !persistence::sha256_hex(&content).eq_ignore_ascii_case(sha) {
+        return Err("assembled file does not match its SHA-256".to_string());
+    }
+    Ok((content, sources))
+}
+
+async fn download(req: FetchRequest, sha: String) -> Result<String, String> {
+    let candidates = match req.peers {
+        Some(p) if !p.is_empty() => p,
+        _ => crate::tcp::connected_peer_ips().await,
+    };
+    let file_type = mime_guess::from_path(&req.filename).first_or_octet_stream().to_string();
+    let settings = crate::settings::current().await;
+    let (content, sources) =
+        fetch_segmented(&sha, candidates, &req.filename, |size| settings.check_file(&file_type, size)).await?;
+    let segment_count = content.len().div_ceil(DEFAULT_SEGMENT_SIZE as usize);
+
+    let owner = req.uploader_ip.unwrap_or_else(|| sources[0].clone());
+    let name = Path::new(&req.filename)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| "invalid filename".to_string())?;
+    crate::scan::check(&name, &content, &owner).await?;
+    let dir = Path::new(RECEIVED_DIR).join(&owner);
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+    crate::persistence::write_atomic(&dir.join(&name), &content).await.map_err(|e| e.to_string())?;
+    crate::transcribe::queue(dir.join(&name), &name, &file_type).await;
+    Ok(format!("fetched {} ({} bytes, {} segments from {} peers)", name, content.len(), segment_count, sources.len()))
+}
+
+#[post("/files/fetch")]
+pub async fn fetch_file(body: web::Json<FetchRequest>) -> Result<HttpResponse, Error> {
+    let req = body.into_inner();
+    let sha = match req.sha256.clone() {
+        Some(s) => Some(s),
+        None => crate::tcp::get_announced_files()
+            .await
+            .into_iter()
+            .find(|f| f.filename == req.filename && req.uploader_ip.as_ref().map(|ip| &f.uploader_ip == ip).unwrap_or(true))
+            .and_then(|f| f.sha256),
+    };
+    let sha = match sha {
+        Some(s) if is_hex_sha256(&s) => s.to_lowercase(),
+        _ => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "sha256 is required (not known from announcements)"
+            })));
+        }
+    };
+    let description = format!("multi-source fetch of {}", req.filename);
+    let job_id = crate::jobs::submit("fetch", description, download(req, sha)).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
@@ -0,0 +1,93 @@
+// Conversation forks: a new conversation seeded with another's history up to a chosen message, so
+// an alternative line of questioning can be explored without touching the original thread. Chat
+// into a fork with {"conversation_id": "<fork-id>"}; forks are pushed to peers like the main
+// conversation.
+use actix_web::{get, post, web, HttpResponse, Error};
+use serde::Deserialize;
+use crate::conversation::{Conversation, CONVERSATION_STORE};
+
+#[derive(Deserialize)]
+pub struct ForkQuery {
+    // Last message to carry over; defaults to the newest one
+    from_message: Option<String>,
+}
+
+fn summary(conv: &Conversation) -> serde_json::Value {
+    serde_json::json!({
+        "id": conv.id,
+        "title": conv.title,
+        "forked_from": conv.forked_from,
+        "message_count": conv.messages.len(),
+        "last_message_at": conv.messages.last().map(|m| m.timestamp),
+    })
+}
+
+#[post("/conversations/{id}/fork")]
+pub async fn fork_conversation(path: web::Path<String>, query: web::Query<ForkQuery>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let source = match CONVERSATION_STORE.get_conversation(&id).await {
+        Some(c) => c,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": format!("Conversation {} not found", id)
+            })));
+        }
+    };
+    let from = match query.into_inner().from_message.or_else(|| source.messages.last().map(|m| m.id.clone())) {
+        Some(from) => from,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "Conversation has no messages to fork from"
+            })));
+        }
+    };
+    let mut fork = match source.fork(&from) {
+        Some(f) => f,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": format!("Message {} not found in conversation {}", from, id)
+            })));
+        }
+    };
+    // The fork belongs to this node even when it starts from a peer's conversation
+    if id != "local" {
+        if let Some(local) = CONVERSATION_STORE.get_local_conversation().await {
+            fork.host_info = local.host_info;
+        }
+    }
+    CONVERSATION_STORE.add_fork(fork.clone()).await;
+    crate::tcp::broadcast_fork(&fork).await;
+    println!("API: Forked conversation {} at {} into {}", id, from, fork.id);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "conversation": fork })))
+}
+
+#[get("/conversations/forks")]
+pub async fn list_forks() -> Result<HttpResponse, Error> {
+    let local: Vec<serde_json::Value> = CONVERSATION_STORE.get_forks().await.iter().map(summary).collect();
+    let peers: serde_json::Map<String, serde_json::Value> = CONVERSATION_STORE
+        .get_peer_forks()
+        .await
+        .iter()
+        .map(|(ip, forks)| (ip.clone(), forks.iter().map(summary).collect()))
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "local": local, "peers": peers })))
+}
+
+#[get("/conversations/forks/{id}")]
+pub async fn get_fork(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let user = crate::users::view(user.map(|u| u.into_inner()), None).ok().flatten();
+    match CONVERSATION_STORE.get_conversation(&id).await.filter(|c| c.forked_from.is_some()) {
+        Some(fork) => Ok(HttpResponse::Ok().json(match user.as_deref() {
+            Some(user) => fork.for_user(user),
+            None => fork,
+        })),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": format!("Fork {} not found", id)
+        }))),
+    }
+}
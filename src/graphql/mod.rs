@@ -0,0 +1,253 @@
+// GraphQL endpoint: one query can fetch conversations, peers, files and analytics with
+// exactly the fields a view needs. Lists take offset/limit for pagination. Queries see what the
+// REST API shows the signed-in user: their own questions and answers, and the files visible to them.
+use actix_web::{get, post, web, HttpResponse};
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use std::collections::BTreeMap;
+use crate::conversation::{self, CONVERSATION_STORE};
+use crate::persistence;
+use crate::users::SessionUser;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+pub type MeshSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn build_schema() -> MeshSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(8)
+        .limit_complexity(1000)
+        .finish()
+}
+
+fn page<T: Clone>(items: &[T], offset: Option<i32>, limit: Option<i32>) -> Vec<T> {
+    let offset = offset.unwrap_or(0).max(0) as usize;
+    let limit = limit.map(|l| l.max(0) as usize).unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    items.iter().skip(offset).take(limit).cloned().collect()
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct HostInfo {
+    hostname: String,
+    ip_address: String,
+    is_llm_host: bool,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct Message {
+    content: String,
+    timestamp: String,
+    sender: String,
+    message_type: String,
+    attachment: Option<String>,
+    host_info: HostInfo,
+}
+
+#[derive(Clone)]
+pub struct Conversation {
+    inner: conversation::Conversation,
+}
+
+fn session(ctx: &Context<'_>) -> Option<SessionUser> {
+    ctx.data_opt::<SessionUser>().cloned()
+}
+
+// The conversation as the signed-in user may see it: only their own questions and the answers to
+// them unless they are the admin (see crate::users::view)
+fn scoped(ctx: &Context<'_>, inner: conversation::Conversation) -> Conversation {
+    match crate::users::view(session(ctx), None).ok().flatten() {
+        Some(user) => Conversation { inner: inner.for_user(&user) },
+        None => Conversation { inner },
+    }
+}
+
+fn to_host_info(h: &conversation::HostInfo) -> HostInfo {
+    HostInfo {
+        hostname: h.hostname.clone(),
+        ip_address: h.ip_address.clone(),
+        is_llm_host: h.is_llm_host,
+    }
+}
+
+#[Object]
+impl Conversation {
+    async fn id(&self) -> &str {
+        &self.inner.id
+    }
+
+    async fn title(&self) -> Option<&str> {
+        self.inner.title.as_deref()
+    }
+
+    async fn host_info(&self) -> HostInfo {
+        to_host_info(&self.inner.host_info)
+    }
+
+    async fn message_count(&self) -> usize {
+        self.inner.messages.len()
+    }
+
+    // Messages in chronological order; `latest: true` returns the last `limit` messages instead
+    async fn messages(&self, offset: Option<i32>, limit: Option<i32>, latest: Option<bool>) -> Vec<Message> {
+        let all: Vec<Message> = self.inner.messages.iter().map(|m| Message {
+            content: m.content.clone(),
+            timestamp: m.timestamp.to_rfc3339(),
+            sender: m.sender.clone(),
+            message_type: format!("{:?}", m.message_type),
+            attachment: m.attachment.clone(),
+            host_info: to_host_info(&m.host_info),
+        }).collect();
+        if latest.unwrap_or(false) {
+            let limit = limit.map(|l| l.max(0) as usize).unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+            let start = all.len().saturating_sub(limit);
+            return all[start..].to_vec();
+        }
+        page(&all, offset, limit)
+    }
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct File {
+    filename: String,
+    file_type: String,
+    file_size: u64,
+    uploader_ip: String,
+    upload_time: String,
+}
+
+#[derive(SimpleObject)]
+pub struct FilePage {
+    total: usize,
+    items: Vec<File>,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct Peer {
+    ip: String,
+    connected: bool,
+    has_llm: bool,
+    message_count: usize,
+}
+
+#[derive(SimpleObject)]
+pub struct DayCount {
+    date: String,
+    count: usize,
+}
+
+#[derive(SimpleObject)]
+pub struct UserCount {
+    user: String,
+    count: usize,
+}
+
+#[derive(SimpleObject)]
+pub struct FileTypeStat {
+    file_type: String,
+    count: u64,
+    total_bytes: u64,
+}
+
+pub struct Analytics;
+
+#[Object]
+impl Analytics {
+    async fn messages_per_day(&self) -> Vec<DayCount> {
+        let (per_day, _) = crate::chat_activity().await;
+        per_day.into_iter().map(|(date, count)| DayCount { date, count }).collect()
+    }
+
+    async fn top_users(&self, limit: Option<i32>) -> Vec<UserCount> {
+        let (_, users) = crate::chat_activity().await;
+        let users: Vec<UserCount> = users.into_iter().map(|(user, count)| UserCount { user, count }).collect();
+        let limit = limit.map(|l| l.max(0) as usize).unwrap_or(10);
+        users.into_iter().take(limit).collect()
+    }
+
+    async fn file_types(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<FileTypeStat>> {
+        let user = session(ctx);
+        let mut files = persistence::list_uploaded_files().await?;
+        files.retain(|f| f.visible_to(user.as_ref()));
+        Ok(crate::file_type_totals(&files)
+            .into_iter()
+            .map(|(file_type, count, total_bytes)| FileTypeStat { file_type, count, total_bytes })
+            .collect())
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    // "local" plus one conversation per peer
+    async fn conversations(&self, ctx: &Context<'_>, offset: Option<i32>, limit: Option<i32>) -> Vec<Conversation> {
+        let mut all: Vec<conversation::Conversation> = Vec::new();
+        if let Some(local) = CONVERSATION_STORE.get_local_conversation().await {
+            all.push(local);
+        }
+        let peers: BTreeMap<String, conversation::Conversation> = CONVERSATION_STORE.get_peer_conversations().await.into_iter().collect();
+        all.extend(peers.into_values());
+        page(&all, offset, limit).into_iter().map(|c| scoped(ctx, c)).collect()
+    }
+
+    async fn conversation(&self, ctx: &Context<'_>, id: String) -> Option<Conversation> {
+        CONVERSATION_STORE.get_conversation(&id).await.map(|inner| scoped(ctx, inner))
+    }
+
+    async fn peers(&self) -> Vec<Peer> {
+        let connected = crate::tcp::connected_peer_ips().await;
+        let llm_peers = crate::tcp::llm_peer_ips().await;
+        let conversations = CONVERSATION_STORE.get_peer_conversations().await;
+        let mut peers: BTreeMap<String, Peer> = BTreeMap::new();
+        for ip in connected.iter().chain(llm_peers.iter()).chain(conversations.keys()) {
+            peers.entry(ip.clone()).or_insert_with(|| Peer {
+                ip: ip.clone(),
+                connected: connected.contains(ip),
+                has_llm: llm_peers.contains(ip),
+                message_count: conversations.get(ip).map(|c| c.messages.len()).unwrap_or(0),
+            });
+        }
+        peers.into_values().collect()
+    }
+
+    // Aggregated file list (same sources and visibility as /api/files), optionally filtered by uploader
+    async fn files(&self, ctx: &Context<'_>, offset: Option<i32>, limit: Option<i32>, uploader_ip: Option<String>) -> async_graphql::Result<FilePage> {
+        let user = session(ctx);
+        let query = persistence::FileQuery { uploader: uploader_ip, ..Default::default() };
+        let mut files = crate::aggregate_files_matching(&query, |f| f.visible_to(user.as_ref())).await?;
+        query.sort(&mut files);
+        let all: Vec<File> = files
+            .into_iter()
+            .map(|f| File {
+                filename: f.filename,
+                file_type: f.file_type,
+                file_size: f.file_size,
+                uploader_ip: f.uploader_ip,
+                upload_time: f.upload_time.to_rfc3339(),
+            })
+            .collect();
+        Ok(FilePage { total: all.len(), items: page(&all, offset, limit) })
+    }
+
+    async fn analytics(&self) -> Analytics {
+        Analytics
+    }
+}
+
+#[post("/graphql")]
+pub async fn graphql_handler(schema: web::Data<MeshSchema>, req: GraphQLRequest, user: Option<web::ReqData<SessionUser>>) -> GraphQLResponse {
+    let mut request = req.into_inner();
+    if let Some(user) = user {
+        request = request.data(user.into_inner());
+    }
+    schema.execute(request).await.into()
+}
+
+#[get("/graphql")]
+pub async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/api/graphql").finish())
+}
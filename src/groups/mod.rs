@@ -0,0 +1,196 @@
+// Named peer groups ("research", "ops"). The admin joins and leaves groups with POST and DELETE
+// /api/groups/{name}; membership is kept in groups.json. Peers are told over a GRPS: frame right
+// after connecting and whenever it changes, so every node knows which groups its connected peers
+// are in. A conversation or upload scoped to a group only goes to peers that said they are in it,
+// and a node drops group-scoped conversations for groups it has not joined. Builds that do not
+// know GRPS: skip it and count as being in no group.
+use actix_web::{delete, get, post, web, Error, HttpResponse};
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex as StdMutex;
+
+const GROUPS_FILE: &str = "groups.json";
+pub const MAX_NAME_LEN: usize = 32;
+// Most groups a node can be in, and so the most a GRPS: frame lists
+pub const MAX_GROUPS: usize = 32;
+
+static LOCAL: Lazy<StdMutex<BTreeSet<String>>> = Lazy::new(|| StdMutex::new(BTreeSet::new()));
+// What each peer last told us
+static PEERS: Lazy<StdMutex<HashMap<String, BTreeSet<String>>>> = Lazy::new(|| StdMutex::new(HashMap::new()));
+
+// Lowercase ASCII letters, digits, '-' and '_', starting with a letter or digit
+pub fn validate(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(format!("group names are 1 to {} characters", MAX_NAME_LEN));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return Err(format!("invalid group name {:?}; use lowercase letters, digits, '-' and '_'", name));
+    }
+    Ok(())
+}
+
+// A group list from a peer or groups.json
+pub fn validate_list(groups: &[String]) -> Result<(), String> {
+    if groups.len() > MAX_GROUPS {
+        return Err(format!("more than {} groups", MAX_GROUPS));
+    }
+    groups.iter().try_for_each(|g| validate(g))
+}
+
+pub async fn load() {
+    let groups: Vec<String> = match tokio::fs::read_to_string(GROUPS_FILE).await {
+        Ok(s) => match serde_json::from_str(&s) {
+            Ok(groups) => groups,
+            Err(e) => {
+                eprintln!("GROUPS: Failed to parse {}: {}", GROUPS_FILE, e);
+                return;
+            }
+        },
+        Err(_) => return,
+    };
+    if let Err(e) = validate_list(&groups) {
+        eprintln!("GROUPS: Ignoring {}: {}", GROUPS_FILE, e);
+        return;
+    }
+    println!("GROUPS: Member of {}", if groups.is_empty() { "no group".to_string() } else { groups.join(", ") });
+    *LOCAL.lock().unwrap() = groups.into_iter().collect();
+}
+
+async fn save(groups: &[String]) {
+    match serde_json::to_vec_pretty(groups) {
+        Ok(s) => {
+            if let Err(e) = crate::persistence::write_atomic(std::path::Path::new(GROUPS_FILE), &s).await {
+                eprintln!("GROUPS: Failed to save {}: {}", GROUPS_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("GROUPS: Failed to serialize groups: {}", e),
+    }
+}
+
+// The groups we are in, as told to peers
+pub fn local() -> Vec<String> {
+    LOCAL.lock().unwrap().iter().cloned().collect()
+}
+
+pub fn is_member(group: &str) -> bool {
+    LOCAL.lock().unwrap().contains(group)
+}
+
+// A peer's GRPS: replaces whatever it said before
+pub fn peer_groups_received(peer_ip: &str, groups: Vec<String>) {
+    println!("GROUPS: {} is in {}", peer_ip, if groups.is_empty() { "no group".to_string() } else { groups.join(", ") });
+    PEERS.lock().unwrap().insert(peer_ip.to_string(), groups.into_iter().collect());
+}
+
+pub fn peer_in(peer_ip: &str, group: &str) -> bool {
+    PEERS.lock().unwrap().get(peer_ip).is_some_and(|g| g.contains(group))
+}
+
+// Whether something scoped to `group` (None for the whole mesh) may go to `peer_ip`
+pub fn shares_with(peer_ip: &str, group: Option<&str>) -> bool {
+    group.is_none_or(|g| peer_in(peer_ip, g))
+}
+
+// Whether we keep something a peer scoped to `group`
+pub fn accepts(group: Option<&str>) -> bool {
+    group.is_none_or(is_member)
+}
+
+// Connected peers in a group
+pub async fn members(group: &str) -> Vec<String> {
+    crate::tcp::connected_peer_ips().await.into_iter().filter(|ip| peer_in(ip, group)).collect()
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can change group membership" }))
+}
+
+// Our groups, and for every group we know of the connected peers in it
+#[get("/groups")]
+pub async fn get_groups() -> Result<HttpResponse, Error> {
+    let connected = crate::tcp::connected_peer_ips().await;
+    let mut members: BTreeMap<String, Vec<String>> = local().into_iter().map(|g| (g, Vec::new())).collect();
+    for (ip, groups) in PEERS.lock().unwrap().iter().filter(|(ip, _)| connected.contains(ip)) {
+        for group in groups {
+            members.entry(group.clone()).or_default().push(ip.clone());
+        }
+    }
+    for peers in members.values_mut() {
+        peers.sort();
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "groups": local(), "members": members })))
+}
+
+#[post("/groups/{name}")]
+pub async fn join_group(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let name = path.into_inner();
+    if let Err(e) = validate(&name) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": e })));
+    }
+    let groups = {
+        let mut local = LOCAL.lock().unwrap();
+        if !local.contains(&name) && local.len() >= MAX_GROUPS {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": format!("A node can be in at most {} groups", MAX_GROUPS)
+            })));
+        }
+        local.insert(name.clone());
+        local.iter().cloned().collect::<Vec<_>>()
+    };
+    save(&groups).await;
+    crate::events::info(crate::events::Category::Peer, format!("Joined group {}", name), serde_json::json!({ "group": name }));
+    crate::tcp::broadcast_groups().await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "groups": groups })))
+}
+
+#[delete("/groups/{name}")]
+pub async fn leave_group(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let name = path.into_inner();
+    let groups = {
+        let mut local = LOCAL.lock().unwrap();
+        if !local.remove(&name) {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": format!("Not a member of {}", name)
+            })));
+        }
+        local.iter().cloned().collect::<Vec<_>>()
+    };
+    save(&groups).await;
+    crate::events::info(crate::events::Category::Peer, format!("Left group {}", name), serde_json::json!({ "group": name }));
+    crate::tcp::broadcast_groups().await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "groups": groups })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{peer_groups_received, shares_with, validate, validate_list, MAX_GROUPS};
+
+    // Group names are lowercase slugs, and something scoped to a group only goes to peers that said
+    // they are in it
+    #[test]
+    fn groups_scope_sharing_to_members() {
+        assert!(validate("ops").is_ok());
+        assert!(validate("research-2_b").is_ok());
+        for bad in ["", "Ops", "-ops", "a b", "ops|x", &"a".repeat(33)] {
+            assert!(validate(bad).is_err(), "{:?} should be refused", bad);
+        }
+        assert!(validate_list(&vec!["g".to_string(); MAX_GROUPS + 1]).is_err());
+        peer_groups_received("192.0.2.41", vec!["ops".into()]);
+        assert!(shares_with("192.0.2.41", None));
+        assert!(shares_with("192.0.2.41", Some("ops")));
+        assert!(!shares_with("192.0.2.41", Some("research")));
+        assert!(!shares_with("192.0.2.42", Some("ops")));
+        peer_groups_received("192.0.2.41", Vec::new());
+        assert!(!shares_with("192.0.2.41", Some("ops")));
+    }
+}
@@ -0,0 +1,205 @@
+// gRPC service (tonic) exposing chat, files and peers next to the REST API.
+// Enabled by GRPC_PORT; clients authenticate with the session JWT from /api/auth/login
+// sent as "authorization: Bearer <token>" metadata. It listens on the same host as the HTTP
+// server (BIND_IP) and, when the web UI uses TLS, with the same certificate.
+use futures_util::StreamExt;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::{Request, Response, Status};
+use crate::conversation::CONVERSATION_STORE;
+
+// A client that has not finished its TLS handshake by then is dropped
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub mod proto {
+    tonic::include_proto!("meshmind.v1");
+}
+
+use proto::mesh_mind_server::{MeshMind, MeshMindServer};
+
+#[derive(Default)]
+pub struct MeshMindService;
+
+fn to_proto_file(f: crate::persistence::FileInfo) -> proto::FileInfo {
+    proto::FileInfo {
+        filename: f.filename,
+        file_type: f.file_type,
+        file_size: f.file_size,
+        uploader_ip: f.uploader_ip,
+        upload_time: f.upload_time.to_rfc3339(),
+    }
+}
+
+#[tonic::async_trait]
+impl MeshMind for MeshMindService {
+    async fn chat(&self, request: Request<proto::ChatRequest>) -> Result<Response<proto::ChatReply>, Status> {
+        let req = request.into_inner();
+        if req.message.trim().is_empty() {
+            return Err(Status::invalid_argument("message must not be empty"));
+        }
+        let chat_req = crate::llm::ChatRequest {
+            message: req.message,
+            sender: req.sender,
+            filename: req.filename,
+            ..Default::default()
+        };
+        match crate::llm::run_chat(&chat_req).await {
+            Ok(msg) => Ok(Response::new(proto::ChatReply {
+                content: msg.content,
+                timestamp: msg.timestamp.to_rfc3339(),
+                sender: msg.sender,
+                host_info: Some(proto::HostInfo {
+                    hostname: msg.host_info.hostname,
+                    ip_address: msg.host_info.ip_address,
+                    is_llm_host: msg.host_info.is_llm_host,
+                }),
+            })),
+            Err(crate::llm::ChatError::Blocked(labels)) => {
+                Err(Status::permission_denied(format!("Blocked by moderation policy: {}", labels.join(", "))))
+            }
+            Err(details) => Err(Status::unavailable(format!("No available LLM service: {}", details))),
+        }
+    }
+
+    async fn list_files(&self, _request: Request<proto::ListFilesRequest>) -> Result<Response<proto::ListFilesReply>, Status> {
+        match crate::aggregate_files().await {
+            Ok(files) => Ok(Response::new(proto::ListFilesReply {
+                files: files.into_iter().map(to_proto_file).collect(),
+            })),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+
+    async fn upload_file(&self, request: Request<proto::UploadFileRequest>) -> Result<Response<proto::UploadFileReply>, Status> {
+        let uploader_ip = request
+            .remote_addr()
+            .map(|a| a.ip().to_string())
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let req = request.into_inner();
+        if req.filename.trim().is_empty() {
+            return Err(Status::invalid_argument("filename must not be empty"));
+        }
+        let file_type = if req.file_type.trim().is_empty() {
+            mime_guess::from_path(&req.filename).first_or_octet_stream().to_string()
+        } else {
+            req.file_type
+        };
+        match crate::persistence::save_uploaded_file(&req.filename, &file_type, &req.content, &uploader_ip).await {
+            Ok(info) => {
+                println!("GRPC: File uploaded successfully: {}", req.filename);
+                crate::tcp::broadcast_file_to_peers(req.filename.clone(), file_type, req.content).await;
+                Ok(Response::new(proto::UploadFileReply { file_info: Some(to_proto_file(info)) }))
+            }
+            Err(e) => Err(Status::invalid_argument(e.to_string())),
+        }
+    }
+
+    async fn list_peers(&self, _request: Request<proto::ListPeersRequest>) -> Result<Response<proto::ListPeersReply>, Status> {
+        let connected = crate::tcp::connected_peer_ips().await;
+        let llm_peers = crate::tcp::llm_peer_ips().await;
+        let conversations = CONVERSATION_STORE.get_peer_conversations().await;
+
+        let mut peers: BTreeMap<String, proto::PeerInfo> = BTreeMap::new();
+        let ips = connected.iter().chain(llm_peers.iter()).chain(conversations.keys());
+        for ip in ips {
+            peers.entry(ip.clone()).or_insert_with(|| proto::PeerInfo {
+                ip: ip.clone(),
+                connected: connected.contains(ip),
+                has_llm: llm_peers.contains(ip),
+                message_count: conversations.get(ip).map(|c| c.messages.len() as u32).unwrap_or(0),
+            });
+        }
+        Ok(Response::new(proto::ListPeersReply { peers: peers.into_values().collect() }))
+    }
+}
+
+// tonic interceptors must return Status as the error type
+#[allow(clippy::result_large_err)]
+pub async fn serve(jwt_secret: String, tls: Option<rustls::ServerConfig>) {
+    let port: u16 = match std::env::var("GRPC_PORT").ok().and_then(|v| v.trim().parse().ok()) {
+        Some(p) => p,
+        None => return,
+    };
+    let host = crate::ip::listen_host();
+
+    let check_auth = move |req: Request<()>| -> Result<Request<()>, Status> {
+        let token = req
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+        // The service reaches every file whatever its visibility, so it is for the admin only
+        match crate::session_user(&jwt_secret, token) {
+            Some(user) if user.admin => Ok(req),
+            Some(_) => Err(Status::permission_denied("admin session required")),
+            None => Err(Status::unauthenticated("invalid session token")),
+        }
+    };
+
+    let listener = match tokio::net::TcpListener::bind((host.as_str(), port)).await {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("GRPC: Failed to listen on {}:{}: {}", host, port, e);
+            return;
+        }
+    };
+    let server = tonic::transport::Server::builder().add_service(MeshMindServer::with_interceptor(MeshMindService, check_auth));
+    let result = match tls {
+        Some(mut config) => {
+            println!("GRPC: Listening on {}:{} (TLS)", host, port);
+            // gRPC runs over HTTP/2 only
+            config.alpn_protocols = vec![b"h2".to_vec()];
+            server.serve_with_incoming(tls_incoming(listener, tokio_rustls::TlsAcceptor::from(Arc::new(config)))).await
+        }
+        None => {
+            println!("GRPC: Listening on {}:{}", host, port);
+            match tonic::transport::server::TcpIncoming::from_listener(listener, true, None) {
+                Ok(incoming) => server.serve_with_incoming(incoming).await,
+                Err(e) => {
+                    eprintln!("GRPC: Failed to listen on {}:{}: {}", host, port, e);
+                    return;
+                }
+            }
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("GRPC: Server error: {}", e);
+    }
+}
+
+// Connections that completed the TLS handshake; handshakes run concurrently so a slow client does
+// not hold up the others
+fn tls_incoming(
+    listener: tokio::net::TcpListener,
+    acceptor: tokio_rustls::TlsAcceptor,
+) -> impl futures_util::Stream<Item = std::io::Result<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>> {
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("GRPC: Accept failed: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let (acceptor, accepted) = (acceptor.clone(), tx.clone());
+            tokio::spawn(async move {
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(tls)) => {
+                        let _ = accepted.unbounded_send(tls);
+                    }
+                    Ok(Err(e)) => eprintln!("GRPC: TLS handshake failed: {}", e),
+                    Err(_) => eprintln!("GRPC: TLS handshake timed out"),
+                }
+            });
+            if tx.is_closed() {
+                break;
+            }
+        }
+    });
+    rx.map(Ok)
+}
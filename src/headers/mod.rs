@@ -0,0 +1,102 @@
+// Security headers added to every HTTP response, the embedded SPA as well as the API: a
+// Content-Security-Policy, X-Frame-Options, Referrer-Policy and, on TLS listeners only,
+// Strict-Transport-Security. Values come from `security_headers` in the settings; an empty value
+// leaves that header out, and headers a handler already set are kept.
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::{HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, X_FRAME_OPTIONS};
+use serde::{Deserialize, Serialize};
+
+// Fits the SPA build: bundled scripts only, inline styles from React, recordings as blob: URLs and
+// the presence websocket
+const DEFAULT_CSP: &str = "default-src 'self'; script-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data: blob:; media-src 'self' blob:; connect-src 'self' ws: wss:; object-src 'none'; base-uri 'self'; frame-ancestors 'none'";
+
+const REFERRER_POLICIES: [&str; 8] = [
+    "no-referrer",
+    "no-referrer-when-downgrade",
+    "origin",
+    "origin-when-cross-origin",
+    "same-origin",
+    "strict-origin",
+    "strict-origin-when-cross-origin",
+    "unsafe-url",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityHeaderSettings {
+    pub enabled: bool,
+    pub content_security_policy: String,
+    // "DENY" or "SAMEORIGIN"
+    pub frame_options: String,
+    pub referrer_policy: String,
+    // Strict-Transport-Security max-age, sent only over TLS; 0 leaves the header out
+    pub hsts_max_age_secs: u64,
+    pub hsts_include_subdomains: bool,
+}
+
+impl Default for SecurityHeaderSettings {
+    fn default() -> Self {
+        SecurityHeaderSettings {
+            enabled: true,
+            content_security_policy: DEFAULT_CSP.to_string(),
+            frame_options: "DENY".to_string(),
+            referrer_policy: "same-origin".to_string(),
+            hsts_max_age_secs: 31_536_000,
+            hsts_include_subdomains: false,
+        }
+    }
+}
+
+impl SecurityHeaderSettings {
+    pub fn normalize(&mut self) {
+        self.content_security_policy = self.content_security_policy.trim().to_string();
+        self.frame_options = self.frame_options.trim().to_uppercase();
+        self.referrer_policy = self.referrer_policy.trim().to_lowercase();
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if HeaderValue::from_str(&self.content_security_policy).is_err() {
+            return Err("security_headers.content_security_policy is not a valid header value".to_string());
+        }
+        if !["", "DENY", "SAMEORIGIN"].contains(&self.frame_options.as_str()) {
+            return Err(format!("security_headers.frame_options must be DENY or SAMEORIGIN, not {:?}", self.frame_options));
+        }
+        if !self.referrer_policy.is_empty() && !REFERRER_POLICIES.contains(&self.referrer_policy.as_str()) {
+            return Err(format!("unknown security_headers.referrer_policy {:?}", self.referrer_policy));
+        }
+        Ok(())
+    }
+
+    fn headers(&self, tls: bool) -> Vec<(HeaderName, String)> {
+        let mut headers = vec![
+            (CONTENT_SECURITY_POLICY, self.content_security_policy.clone()),
+            (X_FRAME_OPTIONS, self.frame_options.clone()),
+            (REFERRER_POLICY, self.referrer_policy.clone()),
+        ];
+        if tls && self.hsts_max_age_secs > 0 {
+            let subdomains = if self.hsts_include_subdomains { "; includeSubDomains" } else { "" };
+            headers.push((STRICT_TRANSPORT_SECURITY, format!("max-age={}{}", self.hsts_max_age_secs, subdomains)));
+        }
+        headers
+    }
+}
+
+// Add the configured headers to a finished response
+pub async fn apply<B>(mut res: ServiceResponse<B>) -> ServiceResponse<B> {
+    let settings = crate::settings::current().await.security_headers;
+    if !settings.enabled {
+        return res;
+    }
+    // Listeners bound with TLS count, and X-Forwarded-Proto only when a trusted proxy sent it
+    let tls = res.request().app_config().secure() || crate::proxy::forwarded_https(res.request());
+    let headers = res.headers_mut();
+    for (name, value) in settings.headers(tls) {
+        if value.is_empty() || headers.contains_key(&name) {
+            continue;
+        }
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    }
+    res
+}
@@ -0,0 +1,80 @@
+// On-demand download of a peer's conversation. Peers push their conversation every 30s; right after
+// a peer is discovered, GET /api/peers/{ip}/conversation?since= asks for it at once with a scoped
+// SYNC: frame and waits for the CONV: answer, which is merged into our copy like a pushed one.
+// `since` (RFC 3339, on our clock) leaves out older messages; without it the whole conversation
+// comes back. A peer whose conversation is private answers with nothing.
+use actix_web::{get, web, Error, HttpResponse};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex};
+use crate::conversation::Conversation;
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Request id -> (peer asked, where its answer goes)
+type PendingRequests = HashMap<u64, (String, oneshot::Sender<Option<Conversation>>)>;
+
+static PENDING: Lazy<Mutex<PendingRequests>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// A CONV: answer from a peer, already on our clock; ignored unless it answers our request to that peer
+pub async fn response_received(peer_ip: &str, request_id: u64, conversation: Option<Conversation>) {
+    let mut pending = PENDING.lock().await;
+    if pending.get(&request_id).is_some_and(|(peer, _)| peer == peer_ip) {
+        if let Some((_, sender)) = pending.remove(&request_id) {
+            let _ = sender.send(conversation);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ConversationQuery {
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[get("/peers/{ip}/conversation")]
+pub async fn peer_conversation(path: web::Path<String>, query: web::Query<ConversationQuery>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let ip = path.into_inner();
+    if ip.parse::<IpAddr>().is_err() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": "Invalid peer IP" })));
+    }
+    let since_ms = query.since.map_or(0, |t| t.timestamp_millis().max(0));
+    let request_id: u64 = rand::random();
+    let (sender, receiver) = oneshot::channel();
+    PENDING.lock().await.insert(request_id, (ip.clone(), sender));
+
+    if let Err(e) = crate::tcp::request_conversation(&ip, request_id, since_ms).await {
+        PENDING.lock().await.remove(&request_id);
+        let mut status = match e.kind() {
+            std::io::ErrorKind::NotConnected => HttpResponse::NotFound(),
+            std::io::ErrorKind::Unsupported => HttpResponse::NotImplemented(),
+            _ => HttpResponse::BadGateway(),
+        };
+        return Ok(status.json(serde_json::json!({ "success": false, "message": e.to_string() })));
+    }
+    let conversation = match tokio::time::timeout(RESPONSE_TIMEOUT, receiver).await {
+        Ok(Ok(conversation)) => conversation,
+        _ => {
+            PENDING.lock().await.remove(&request_id);
+            return Ok(HttpResponse::GatewayTimeout().json(serde_json::json!({
+                "success": false,
+                "message": format!("Peer {} did not answer", ip)
+            })));
+        }
+    };
+    // A signed-in user gets only their own questions and the answers to them, as from /api/local
+    let messages = match crate::users::view(user.map(|u| u.into_inner()), None).ok().flatten() {
+        Some(user) => conversation.map(|c| c.for_user(&user).messages),
+        None => conversation.map(|c| c.messages),
+    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "peer": ip,
+        "since": query.since,
+        "shared": messages.is_some(),
+        "messages": messages.unwrap_or_default(),
+    })))
+}
@@ -1,7 +1,40 @@
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::OnceLock;
 use ipconfig::get_adapters;
 
+// BIND_IP: the one address this node listens on and connects from, so several nodes can share a
+// machine on different loopback addresses (see `simulate`); unset means every interface
+pub fn bind_ip() -> Option<Ipv4Addr> {
+    static BIND_IP: OnceLock<Option<Ipv4Addr>> = OnceLock::new();
+    *BIND_IP.get_or_init(|| {
+        let value = std::env::var("BIND_IP").ok().filter(|v| !v.trim().is_empty())?;
+        match value.trim().parse() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                eprintln!("BIND_IP {:?} is not an IPv4 address, listening on all interfaces", value);
+                None
+            }
+        }
+    })
+}
+
+// Host part of listener addresses
+pub fn listen_host() -> String {
+    bind_ip().map(|ip| ip.to_string()).unwrap_or_else(|| "0.0.0.0".to_string())
+}
+
+// Address peers know us by: BIND_IP when set, otherwise the interface that routes outward
+pub fn primary_ip() -> std::io::Result<IpAddr> {
+    if let Some(ip) = bind_ip() {
+        return Ok(IpAddr::V4(ip));
+    }
+    std::net::TcpStream::connect("8.8.8.8:53").and_then(|s| s.local_addr()).map(|a| a.ip())
+}
+
 pub fn is_my_ip(ip: &str) -> bool {
+    if bind_ip().is_some_and(|b| b.to_string() == ip) {
+        return true;
+    }
     if let Ok(adapters) = get_adapters() {
         for adapter in adapters {
             for ip_addr in adapter.ip_addresses() {
@@ -14,4 +47,124 @@ pub fn is_my_ip(ip: &str) -> bool {
         }
     }
     false
-}
\ No newline at end of file
+}
+
+// IPv4 addresses of every adapter that is up
+pub fn local_ipv4s() -> Vec<Ipv4Addr> {
+    let mut out = Vec::new();
+    if let Ok(adapters) = get_adapters() {
+        for adapter in adapters.iter().filter(|a| a.oper_status() == ipconfig::OperStatus::IfOperStatusUp) {
+            for ip_addr in adapter.ip_addresses() {
+                if let IpAddr::V4(ipv4) = ip_addr {
+                    out.push(*ipv4);
+                }
+            }
+        }
+    }
+    out
+}
+
+// An IPv4 address of an adapter discovery runs on
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Interface {
+    pub name: String,
+    pub ip: Ipv4Addr,
+    pub prefix_len: u8,
+    pub broadcast: Ipv4Addr,
+}
+
+impl Interface {
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+        u32::from(self.ip) & mask == u32::from(ip) & mask
+    }
+}
+
+// INTERFACES / EXCLUDE_INTERFACES: comma-separated adapter names (as shown by the OS, compared
+// without case) or CIDR ranges. Only the listed adapters are used when INTERFACES is set, in its
+// order of preference; excluded ones never are.
+fn interface_list(var: &str) -> &'static [String] {
+    static INCLUDE: OnceLock<Vec<String>> = OnceLock::new();
+    static EXCLUDE: OnceLock<Vec<String>> = OnceLock::new();
+    let cell = if var == "INTERFACES" { &INCLUDE } else { &EXCLUDE };
+    cell.get_or_init(|| {
+        std::env::var(var)
+            .unwrap_or_default()
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect()
+    })
+}
+
+// Position of the first entry naming the adapter or covering the address
+fn list_position(list: &[String], names: &[&str], ip: Ipv4Addr) -> Option<usize> {
+    list.iter().position(|entry| {
+        names.iter().any(|n| n.eq_ignore_ascii_case(entry))
+            || (entry.contains('/') && crate::proxy::Net::parse(entry).is_ok_and(|net| net.contains(IpAddr::V4(ip))))
+    })
+}
+
+// Subnet length of `ip` from the adapter's on-link prefixes; /24 when the OS reports none
+fn prefix_len(prefixes: &[(IpAddr, u32)], ip: Ipv4Addr) -> u8 {
+    prefixes
+        .iter()
+        .filter_map(|(net, len)| match net {
+            IpAddr::V4(net) if (1..32).contains(len) => Some((*net, *len as u8)),
+            _ => None,
+        })
+        .filter(|(net, len)| {
+            let mask = u32::MAX << (32 - *len as u32);
+            u32::from(*net) & mask == u32::from(ip) & mask
+        })
+        .map(|(_, len)| len)
+        .max()
+        .unwrap_or(24)
+}
+
+// The adapters discovery announces on and peers are preferred through, most preferred first:
+// in INTERFACES order when set, otherwise in the order the OS lists them. Loopback and
+// link-local addresses are left out, as is everything but BIND_IP when that is set.
+pub fn interfaces() -> Vec<Interface> {
+    let include = interface_list("INTERFACES");
+    let exclude = interface_list("EXCLUDE_INTERFACES");
+    let mut out: Vec<(usize, Interface)> = Vec::new();
+    if let Ok(adapters) = get_adapters() {
+        for adapter in adapters.iter().filter(|a| a.oper_status() == ipconfig::OperStatus::IfOperStatusUp) {
+            let names = [adapter.friendly_name(), adapter.adapter_name(), adapter.description()];
+            for ip_addr in adapter.ip_addresses() {
+                let IpAddr::V4(ip) = *ip_addr else {
+                    continue;
+                };
+                match bind_ip() {
+                    Some(b) if b != ip => continue,
+                    None if ip.is_loopback() || ip.is_link_local() => continue,
+                    _ => {}
+                }
+                if list_position(exclude, &names, ip).is_some() {
+                    continue;
+                }
+                let rank = match list_position(include, &names, ip) {
+                    Some(rank) => rank,
+                    None if include.is_empty() => out.len(),
+                    None => continue,
+                };
+                let prefix_len = prefix_len(adapter.prefixes(), ip);
+                let broadcast = Ipv4Addr::from(u32::from(ip) | !(u32::MAX << (32 - prefix_len as u32)));
+                out.push((rank, Interface { name: adapter.friendly_name().to_string(), ip, prefix_len, broadcast }));
+            }
+        }
+    }
+    // Stable, so addresses of one adapter keep the OS order
+    out.sort_by_key(|(rank, _)| *rank);
+    out.into_iter().map(|(_, i)| i).collect()
+}
+
+// How much we prefer reaching a peer at `ip`: the position of our interface on its subnet, with
+// addresses on none of them (e.g. across a VPN's routed network) last
+pub fn preference(interfaces: &[Interface], ip: &str) -> usize {
+    ip.parse::<Ipv4Addr>()
+        .ok()
+        .and_then(|ip| interfaces.iter().position(|i| i.contains(ip)))
+        .unwrap_or(interfaces.len())
+}
@@ -0,0 +1,126 @@
+// Background job runner: long-running work (replication, batch tasks) is submitted here,
+// runs with bounded concurrency (JOB_CONCURRENCY, default 2) and is visible via /api/jobs.
+use actix_web::{get, web, HttpResponse, Error};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore};
+
+// Finished jobs beyond this are dropped from the history (oldest first)
+const MAX_JOB_HISTORY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub kind: String,
+    pub description: String,
+    pub status: JobStatus,
+    pub detail: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+lazy_static! {
+    static ref JOBS: Arc<Mutex<VecDeque<JobInfo>>> = Arc::new(Mutex::new(VecDeque::new()));
+    static ref SLOTS: Arc<Semaphore> = Arc::new(Semaphore::new(
+        std::env::var("JOB_CONCURRENCY").ok().and_then(|v| v.trim().parse().ok()).filter(|n| *n > 0).unwrap_or(2)
+    ));
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+async fn update(id: u64, f: impl FnOnce(&mut JobInfo)) {
+    let mut jobs = JOBS.lock().await;
+    if let Some(job) = jobs.iter_mut().find(|j| j.id == id) {
+        f(job);
+    }
+}
+
+// Queue a job. The future's Ok/Err string becomes the job's detail. Returns the job id.
+pub async fn submit<F>(kind: &str, description: String, fut: F) -> u64
+where
+    F: Future<Output = Result<String, String>> + Send + 'static,
+{
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    {
+        let mut jobs = JOBS.lock().await;
+        jobs.push_back(JobInfo {
+            id,
+            kind: kind.to_string(),
+            description: description.clone(),
+            status: JobStatus::Queued,
+            detail: None,
+            created_at: chrono::Utc::now(),
+            started_at: None,
+            finished_at: None,
+        });
+        while jobs.len() > MAX_JOB_HISTORY {
+            match jobs.iter().position(|j| matches!(j.status, JobStatus::Succeeded | JobStatus::Failed)) {
+                Some(pos) => { jobs.remove(pos); }
+                None => break,
+            }
+        }
+    }
+    println!("JOBS: Queued #{} {} ({})", id, kind, description);
+
+    tokio::spawn(async move {
+        let _permit = match SLOTS.clone().acquire_owned().await {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+        update(id, |j| {
+            j.status = JobStatus::Running;
+            j.started_at = Some(chrono::Utc::now());
+        }).await;
+        let result = fut.await;
+        match &result {
+            Ok(detail) => println!("JOBS: #{} succeeded: {}", id, detail),
+            Err(e) => eprintln!("JOBS: #{} failed: {}", id, e),
+        }
+        update(id, |j| {
+            let (status, detail) = match result {
+                Ok(d) => (JobStatus::Succeeded, d),
+                Err(e) => (JobStatus::Failed, e),
+            };
+            j.status = status;
+            j.detail = Some(detail);
+            j.finished_at = Some(chrono::Utc::now());
+        }).await;
+    });
+    id
+}
+
+pub async fn get_job_info(id: u64) -> Option<JobInfo> {
+    JOBS.lock().await.iter().find(|j| j.id == id).cloned()
+}
+
+#[get("/jobs")]
+pub async fn list_jobs() -> Result<HttpResponse, Error> {
+    let mut jobs: Vec<JobInfo> = JOBS.lock().await.iter().cloned().collect();
+    jobs.reverse();
+    Ok(HttpResponse::Ok().json(jobs))
+}
+
+#[get("/jobs/{id}")]
+pub async fn get_job(path: web::Path<u64>) -> Result<HttpResponse, Error> {
+    match get_job_info(path.into_inner()).await {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Job not found"
+        }))),
+    }
+}
@@ -0,0 +1,467 @@
+// Mesh-wide knowledge base of accepted answers. Saving an answer (POST /api/messages/{id}/knowledge)
+// stores it with its question as an entry in knowledge.json, indexes the question and sends the
+// entry to every connected peer as a KNOW: frame; peers also swap all their entries right after
+// connecting, so nodes that were offline catch up. Before a chat question goes to the LLM the
+// knowledge base is consulted: a saved question close enough to it is answered with the saved
+// answer and no LLM call, and less close ones are passed to the model as context. Removing an
+// entry keeps a tombstone so the removal syncs too; the newest version of an entry wins.
+use actix_web::{delete, get, post, web, Error, HttpResponse};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+use crate::conversation::{MessageType, CONVERSATION_STORE};
+
+const KNOWLEDGE_FILE: &str = "knowledge.json";
+pub const MAX_QUESTION_BYTES: usize = 16 * 1024;
+pub const MAX_ANSWER_BYTES: usize = 64 * 1024;
+const MAX_NAME_LEN: usize = 255;
+// Most entries per KNOW: frame, keeping each frame well under the conversation size limit
+pub const BATCH: usize = 100;
+// How much of each saved answer a prompt quotes as context
+const CONTEXT_CHARS: usize = 2000;
+// Model name recorded on answers the knowledge base gave instead of an LLM
+pub const MODEL: &str = "knowledge-base";
+
+// Words too common to tell questions apart
+const STOPWORDS: [&str; 20] = [
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "in", "is", "it", "of", "on", "or", "that", "the", "this", "to", "with",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: String,
+    pub question: String,
+    pub answer: String,
+    // Model that wrote the answer, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // Hostname of the node it was saved on, and the signed-in user who saved it there
+    pub node: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_by: Option<String>,
+    // The answer message it was saved from
+    pub message_id: String,
+    pub saved_at: DateTime<Utc>,
+    // When it was saved or removed; the newest version of an entry wins
+    pub updated_at: DateTime<Utc>,
+    // Removed: question and answer are emptied and the tombstone syncs like any entry
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+}
+
+impl Entry {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.id.len() == 16 && self.id.chars().all(|c| c.is_ascii_hexdigit())) {
+            return Err(format!("invalid knowledge entry id {:?}", self.id));
+        }
+        if self.question.len() > MAX_QUESTION_BYTES || self.answer.len() > MAX_ANSWER_BYTES {
+            return Err(format!("knowledge entry {} is too large", self.id));
+        }
+        if self.node.len() > MAX_NAME_LEN
+            || self.message_id.len() > MAX_NAME_LEN
+            || self.model.as_ref().is_some_and(|m| m.len() > MAX_NAME_LEN)
+            || self.saved_by.as_ref().is_some_and(|u| u.len() > MAX_NAME_LEN)
+        {
+            return Err(format!("knowledge entry {} has an overlong name", self.id));
+        }
+        Ok(())
+    }
+}
+
+// A KNOW: frame from a peer
+pub fn validate_batch(entries: &[Entry]) -> Result<(), String> {
+    if entries.len() > BATCH {
+        return Err(format!("more than {} knowledge entries in one frame", BATCH));
+    }
+    entries.iter().try_for_each(Entry::validate)
+}
+
+// Keep `entry` unless we hold a version at least as new; true when it was taken
+pub fn merge(entries: &mut HashMap<String, Entry>, entry: Entry) -> bool {
+    if entries.get(&entry.id).is_some_and(|existing| existing.updated_at >= entry.updated_at) {
+        return false;
+    }
+    entries.insert(entry.id.clone(), entry);
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KnowledgeSettings {
+    // Consult the knowledge base before asking the LLM
+    pub enabled: bool,
+    // Answer with a saved answer, without the LLM, when its question is at least this similar (0 to 1)
+    pub reuse_threshold: f32,
+    // Pass saved answers at least this similar to the model as context
+    pub context_threshold: f32,
+    // Most saved answers passed as context; 0 passes none
+    pub max_context: usize,
+}
+
+impl Default for KnowledgeSettings {
+    fn default() -> Self {
+        KnowledgeSettings { enabled: true, reuse_threshold: 0.9, context_threshold: 0.3, max_context: 3 }
+    }
+}
+
+impl KnowledgeSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.reuse_threshold) || !(0.0..=1.0).contains(&self.context_threshold) {
+            return Err("knowledge thresholds must be between 0 and 1".to_string());
+        }
+        if self.max_context > 10 {
+            return Err("knowledge.max_context must be at most 10".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Lowercased words of two or more characters, stopwords left out
+pub fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1)
+        .map(str::to_lowercase)
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+// TF-IDF vectors of the saved questions. Rebuilt whenever an entry changes; the knowledge base
+// is small enough for that to be cheap.
+#[derive(Debug, Default)]
+pub struct Index {
+    // Live entry ids with their question's term weights, normalized to unit length
+    docs: Vec<(String, HashMap<String, f32>)>,
+    idf: HashMap<String, f32>,
+    // idf of a term no saved question has
+    unseen_idf: f32,
+}
+
+impl Index {
+    pub fn build<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Index {
+        let live: Vec<(&str, HashMap<String, usize>)> = entries
+            .into_iter()
+            .filter(|e| !e.deleted)
+            .map(|e| (e.id.as_str(), counts(&e.question)))
+            .collect();
+        let mut df: HashMap<String, usize> = HashMap::new();
+        for term in live.iter().flat_map(|(_, c)| c.keys()) {
+            *df.entry(term.clone()).or_default() += 1;
+        }
+        let n = live.len() as f32;
+        let idf = df.into_iter().map(|(t, d)| (t, ((n + 1.0) / (d as f32 + 1.0)).ln() + 1.0)).collect();
+        let unseen_idf = (n + 1.0).ln() + 1.0;
+        let mut index = Index { docs: Vec::new(), idf, unseen_idf };
+        index.docs = live.into_iter().map(|(id, c)| (id.to_string(), index.weigh(c))).collect();
+        index
+    }
+
+    fn weigh(&self, counts: HashMap<String, usize>) -> HashMap<String, f32> {
+        let mut weights: HashMap<String, f32> = counts
+            .into_iter()
+            .map(|(t, c)| {
+                let idf = self.idf.get(&t).copied().unwrap_or(self.unseen_idf);
+                (t, (1.0 + (c as f32).ln()) * idf)
+            })
+            .collect();
+        let norm = weights.values().map(|w| w * w).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            weights.values_mut().for_each(|w| *w /= norm);
+        }
+        weights
+    }
+
+    // Entry ids by how similar their question is to `query` (cosine, 0 to 1), best first
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(String, f32)> {
+        let query = self.weigh(counts(query));
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut hits: Vec<(String, f32)> = self
+            .docs
+            .iter()
+            .map(|(id, doc)| (id.clone(), query.iter().map(|(t, w)| w * doc.get(t).copied().unwrap_or(0.0)).sum::<f32>()))
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+fn counts(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for term in terms(text) {
+        *counts.entry(term).or_default() += 1;
+    }
+    counts
+}
+
+#[derive(Default)]
+struct Knowledge {
+    entries: HashMap<String, Entry>,
+    index: Index,
+}
+
+impl Knowledge {
+    fn reindex(&mut self) {
+        self.index = Index::build(self.entries.values());
+    }
+
+    // Live entries, newest first
+    fn live(&self) -> Vec<Entry> {
+        let mut live: Vec<Entry> = self.entries.values().filter(|e| !e.deleted).cloned().collect();
+        live.sort_by_key(|e| std::cmp::Reverse(e.saved_at));
+        live
+    }
+}
+
+static KNOWLEDGE: Lazy<StdMutex<Knowledge>> = Lazy::new(|| StdMutex::new(Knowledge::default()));
+
+pub async fn load() {
+    let entries: Vec<Entry> = match tokio::fs::read_to_string(KNOWLEDGE_FILE).await {
+        Ok(s) => match serde_json::from_str(&s) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("KNOWLEDGE: Failed to parse {}: {}", KNOWLEDGE_FILE, e);
+                return;
+            }
+        },
+        Err(_) => return,
+    };
+    let mut knowledge = KNOWLEDGE.lock().unwrap();
+    knowledge.entries = entries.into_iter().map(|e| (e.id.clone(), e)).collect();
+    knowledge.reindex();
+    println!("KNOWLEDGE: {} saved answers", knowledge.live().len());
+}
+
+async fn save() {
+    let entries: Vec<Entry> = KNOWLEDGE.lock().unwrap().entries.values().cloned().collect();
+    match serde_json::to_vec_pretty(&entries) {
+        Ok(s) => {
+            if let Err(e) = crate::persistence::write_atomic(std::path::Path::new(KNOWLEDGE_FILE), &s).await {
+                eprintln!("KNOWLEDGE: Failed to save {}: {}", KNOWLEDGE_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("KNOWLEDGE: Failed to serialize entries: {}", e),
+    }
+}
+
+// Every entry, tombstones included, as sent to a peer that just connected
+pub fn all() -> Vec<Entry> {
+    KNOWLEDGE.lock().unwrap().entries.values().cloned().collect()
+}
+
+// A KNOW: frame: keep what is newer than our copy
+pub async fn received(peer_ip: &str, entries: Vec<Entry>) {
+    let taken = {
+        let mut knowledge = KNOWLEDGE.lock().unwrap();
+        let taken = entries.into_iter().filter(|e| merge(&mut knowledge.entries, e.clone())).count();
+        if taken > 0 {
+            knowledge.reindex();
+        }
+        taken
+    };
+    if taken > 0 {
+        println!("KNOWLEDGE: {} entries updated from {}", taken, peer_ip);
+        save().await;
+    }
+}
+
+async fn store(entry: Entry) {
+    {
+        let mut knowledge = KNOWLEDGE.lock().unwrap();
+        merge(&mut knowledge.entries, entry.clone());
+        knowledge.reindex();
+    }
+    save().await;
+    crate::tcp::broadcast_knowledge(vec![entry]).await;
+}
+
+// What the knowledge base has for a question: a saved answer to give instead of asking the LLM,
+// or saved answers to pass to it as context
+#[derive(Debug, Default)]
+pub struct Consulted {
+    pub answer: Option<Entry>,
+    pub context: Vec<Entry>,
+}
+
+pub async fn consult(question: &str) -> Consulted {
+    let settings = crate::settings::current().await.knowledge;
+    if !settings.enabled {
+        return Consulted::default();
+    }
+    let knowledge = KNOWLEDGE.lock().unwrap();
+    let hits = knowledge.index.search(question, settings.max_context.max(1));
+    if let Some((id, _)) = hits.first().filter(|(_, score)| *score >= settings.reuse_threshold) {
+        return Consulted { answer: knowledge.entries.get(id).cloned(), context: Vec::new() };
+    }
+    let context = hits
+        .iter()
+        .filter(|(_, score)| *score >= settings.context_threshold)
+        .take(settings.max_context)
+        .filter_map(|(id, _)| knowledge.entries.get(id).cloned())
+        .collect();
+    Consulted { answer: None, context }
+}
+
+// The prompt with saved answers to similar questions put in front of it
+pub fn augment(prompt: &str, context: &[Entry]) -> String {
+    if context.is_empty() {
+        return prompt.to_string();
+    }
+    let mut out = String::from("Answers from the mesh knowledge base to similar questions; use them where they help:\n\n");
+    for entry in context {
+        let answer = crate::llm::text::truncate_chars(&entry.answer, CONTEXT_CHARS);
+        out.push_str(&format!("Q: {}\nA: {}\n\n", entry.question, answer));
+    }
+    out.push_str(prompt);
+    out
+}
+
+#[derive(Deserialize)]
+pub struct KnowledgeQuery {
+    q: Option<String>,
+    limit: Option<usize>,
+}
+
+// Saved answers, newest first, or with `q` the ones whose questions are most similar to it
+#[get("/knowledge")]
+pub async fn list_knowledge(query: web::Query<KnowledgeQuery>) -> Result<HttpResponse, Error> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let knowledge = KNOWLEDGE.lock().unwrap();
+    let entries: Vec<serde_json::Value> = match query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        Some(q) => knowledge
+            .index
+            .search(q, limit)
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let entry = knowledge.entries.get(&id)?;
+                let mut value = serde_json::to_value(entry).ok()?;
+                value["score"] = serde_json::json!(score);
+                Some(value)
+            })
+            .collect(),
+        None => knowledge.live().iter().take(limit).filter_map(|e| serde_json::to_value(e).ok()).collect(),
+    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "entries": entries })))
+}
+
+// Save an answer, with the question it answers, to the knowledge base
+#[post("/messages/{id}/knowledge")]
+pub async fn save_answer(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    if let Some(existing) = KNOWLEDGE.lock().unwrap().entries.values().find(|e| e.message_id == id && !e.deleted) {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "entry": existing })));
+    }
+    let conversations = CONVERSATION_STORE.all_conversations().await;
+    let Some((conversation, message)) = conversations.iter().find_map(|c| c.messages.iter().find(|m| m.id == id).map(|m| (c, m))) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Message not found" })));
+    };
+    let bad_request = |message: &str| HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": message }));
+    if !matches!(message.message_type, MessageType::Response) || message.model.as_deref() == Some(MODEL) {
+        return Ok(bad_request("Only answers from a model can be saved"));
+    }
+    // Everything saved goes to the whole mesh
+    if !conversation.privacy.is_shared() || conversation.group.is_some() {
+        return Ok(bad_request("Answers in private or group conversations can't be shared with the whole mesh"));
+    }
+    let Some(question) = conversation.question_for(&id) else {
+        return Ok(bad_request("The question this answers is not in the conversation"));
+    };
+    if question.attachment.is_some() {
+        return Ok(bad_request("Answers about an attached file can't be saved"));
+    }
+    if question.content.len() > MAX_QUESTION_BYTES || message.content.len() > MAX_ANSWER_BYTES {
+        return Ok(bad_request("The question or answer is too long for the knowledge base"));
+    }
+    let now = Utc::now();
+    let entry = Entry {
+        id: crate::conversation::new_message_id(),
+        question: question.content.clone(),
+        answer: message.content.clone(),
+        model: message.model.clone(),
+        node: hostname::get().map(|h| h.to_string_lossy().to_string()).unwrap_or_else(|_| "Unknown".to_string()),
+        saved_by: crate::users::name(user),
+        message_id: id.clone(),
+        saved_at: now,
+        updated_at: now,
+        deleted: false,
+    };
+    store(entry.clone()).await;
+    println!("KNOWLEDGE: Saved answer {} as {}", id, entry.id);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "entry": entry })))
+}
+
+// Remove an entry from every node; the admin or whoever saved it may
+#[delete("/knowledge/{id}")]
+pub async fn delete_entry(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let Some(entry) = KNOWLEDGE.lock().unwrap().entries.get(&id).filter(|e| !e.deleted).cloned() else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": format!("Knowledge entry {} not found", id) })));
+    };
+    let user = user.map(|u| u.into_inner());
+    if !user.is_some_and(|u| u.admin || entry.saved_by.as_deref() == Some(u.name.as_str())) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Only the admin or whoever saved an entry can remove it"
+        })));
+    }
+    store(Entry { question: String::new(), answer: String::new(), updated_at: Utc::now(), deleted: true, ..entry }).await;
+    println!("KNOWLEDGE: Removed {}", id);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "id": id })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{augment, merge, terms, Entry, Index};
+
+    fn entry(id: &str, question: &str, secs: i64) -> Entry {
+        Entry {
+            id: id.into(),
+            question: question.into(),
+            answer: "A counter | ✓".into(),
+            model: Some("llama3".into()),
+            node: "laptop".into(),
+            saved_by: None,
+            message_id: "fedcba9876543210".into(),
+            saved_at: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            updated_at: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            deleted: false,
+        }
+    }
+
+    // Saved questions are found by similarity: the same question scores near 1, related ones lower
+    // and unrelated ones not at all; removed entries drop out and the newest version of an entry wins
+    #[test]
+    fn knowledge_base_search_and_merge() {
+        assert_eq!(terms("What is the Lamport-clock, a timestamp?"), vec!["what", "lamport", "clock", "timestamp"]);
+        let entries = vec![
+            entry("0000000000000001", "What is a Lamport clock?", 100),
+            entry("0000000000000002", "How do vector clocks order events?", 100),
+            entry("0000000000000003", "How do I bake sourdough bread?", 100),
+        ];
+        let index = Index::build(&entries);
+        let hits = index.search("what is a lamport clock", 3);
+        assert_eq!(hits[0].0, "0000000000000001");
+        assert!(hits[0].1 > 0.99);
+        let hits = index.search("Lamport clocks versus vector clocks", 3);
+        assert!(hits.iter().all(|(id, score)| id != "0000000000000003" && *score < 0.9));
+        assert!(index.search("the a of", 3).is_empty());
+
+        let removed = Entry { deleted: true, ..entries[0].clone() };
+        assert!(Index::build([&removed, &entries[1]]).search("what is a lamport clock", 3).iter().all(|(id, _)| id != &removed.id));
+
+        let mut store = std::collections::HashMap::new();
+        assert!(merge(&mut store, entries[0].clone()));
+        let newer = Entry { updated_at: chrono::DateTime::from_timestamp(200, 0).unwrap(), ..removed };
+        assert!(merge(&mut store, newer));
+        assert!(!merge(&mut store, entries[0].clone()), "an older version does not bring it back");
+        assert!(store["0000000000000001"].deleted);
+
+        assert_eq!(augment("Q?", &[]), "Q?");
+        let prompt = augment("Q?", &entries[..1]);
+        assert!(prompt.starts_with("Answers from the mesh knowledge base") && prompt.contains("A: A counter | ✓") && prompt.ends_with("Q?"));
+    }
+}
@@ -0,0 +1,111 @@
+// Round-trip times to each peer, for finding the slow links in a mesh. Two kinds of samples: the
+// TCP Ping frames every connection already sends (timed until the Pong comes back) and a periodic
+// HTTP probe of the peer's /api/status, which includes its web server. The last SAMPLES_PER_PEER of
+// each (fewer in the low-power profile) are kept in memory and summarised by GET
+// /api/analytics/latency.
+use actix_web::{get, Error, HttpResponse};
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const SAMPLES_PER_PEER: usize = 120;
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+// Pings unanswered for this long are forgotten
+const PING_EXPIRY: Duration = Duration::from_secs(120);
+
+#[derive(Default)]
+struct PeerSamples {
+    tcp_ms: VecDeque<i64>,
+    http_ms: VecDeque<i64>,
+    http_failures: u64,
+}
+
+fn push(samples: &mut VecDeque<i64>, ms: i64) {
+    if samples.len() >= crate::power::retention(SAMPLES_PER_PEER) {
+        samples.pop_front();
+    }
+    samples.push_back(ms);
+}
+
+static SAMPLES: Lazy<Mutex<HashMap<String, PeerSamples>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// Ping token -> (peer, when it was sent)
+static PENDING_PINGS: Lazy<Mutex<HashMap<u64, (String, Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Note a Ping we just sent to a peer
+pub async fn ping_sent(ip: &str, token: u64) {
+    let mut pending = PENDING_PINGS.lock().await;
+    pending.retain(|_, (_, sent)| sent.elapsed() < PING_EXPIRY);
+    pending.insert(token, (ip.to_string(), Instant::now()));
+}
+
+// Record the round trip if the Pong answers one of our Pings from that peer
+pub async fn pong_received(ip: &str, token: u64) {
+    let sent = match PENDING_PINGS.lock().await.remove(&token) {
+        Some((peer, sent)) if peer == ip => sent,
+        _ => return,
+    };
+    let ms = sent.elapsed().as_millis() as i64;
+    push(&mut SAMPLES.lock().await.entry(ip.to_string()).or_default().tcp_ms, ms);
+}
+
+// Median of the recent TCP round trips to a peer
+pub async fn tcp_median_ms(ip: &str) -> Option<i64> {
+    let samples = SAMPLES.lock().await;
+    let v: Vec<i64> = samples.get(ip)?.tcp_ms.iter().copied().collect();
+    crate::percentile_ms(&v, 50.0)
+}
+
+// Time a request to each connected peer's /api/status every PROBE_INTERVAL
+pub async fn http_prober() {
+    let mut interval = tokio::time::interval(crate::power::interval(PROBE_INTERVAL));
+    loop {
+        interval.tick().await;
+        for ip in crate::tcp::connected_peer_ips().await {
+            let url = format!("{}/api/status", crate::tls::peer_origin(&ip, 8080));
+            let started = Instant::now();
+            let ok = crate::client::peer().get(&url).timeout(PROBE_TIMEOUT).send().await.map(|r| r.status().is_success()).unwrap_or(false);
+            let ms = started.elapsed().as_millis() as i64;
+            let mut samples = SAMPLES.lock().await;
+            let peer = samples.entry(ip).or_default();
+            if ok {
+                push(&mut peer.http_ms, ms);
+            } else {
+                peer.http_failures += 1;
+            }
+        }
+    }
+}
+
+fn summary(samples: &VecDeque<i64>) -> serde_json::Value {
+    let v: Vec<i64> = samples.iter().copied().collect();
+    serde_json::json!({
+        "p50_ms": crate::percentile_ms(&v, 50.0),
+        "p95_ms": crate::percentile_ms(&v, 95.0),
+        "last_ms": v.last(),
+        "samples": v.len()
+    })
+}
+
+#[get("/analytics/latency")]
+pub async fn analytics_latency() -> Result<HttpResponse, Error> {
+    let connected = crate::tcp::connected_peer_ips().await;
+    let samples = SAMPLES.lock().await;
+    let mut ips: Vec<&String> = samples.keys().collect();
+    ips.sort();
+    let peers: Vec<serde_json::Value> = ips
+        .into_iter()
+        .map(|ip| {
+            let peer = &samples[ip];
+            serde_json::json!({
+                "ip": ip,
+                "connected": connected.contains(ip),
+                "tcp": summary(&peer.tcp_ms),
+                "http": summary(&peer.http_ms),
+                "http_failures": peer.http_failures
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "peers": peers })))
+}
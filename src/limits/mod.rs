@@ -0,0 +1,203 @@
+// Global request limits, so a node on a small device (e.g. a Raspberry Pi) is not overrun: JSON
+// bodies above `max_json_body_bytes` get 413, and uploads or WebSocket connections beyond their
+// concurrency limits get 503 with Retry-After. Enforced by a middleware in main; counts of what
+// is in use and what was turned away are at /api/analytics/limits.
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::error::{JsonPayloadError, PayloadError};
+use actix_web::http::{header, Method};
+use actix_web::{get, web, Error, HttpResponse};
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex as StdMutex;
+
+// Highest JSON body limit that can be configured; the JSON extractor is set up with it
+const MAX_JSON_BODY_CEILING: usize = 64 * 1024 * 1024;
+const RETRY_AFTER_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitSettings {
+    pub max_json_body_bytes: usize,
+    // Uploads through /api/upload and WebDAV PUT handled at once; 0 means no limit
+    pub max_concurrent_uploads: usize,
+    // Open /api/ws connections; 0 means no limit
+    pub max_websocket_connections: usize,
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        LimitSettings { max_json_body_bytes: 2 * 1024 * 1024, max_concurrent_uploads: 4, max_websocket_connections: 64 }
+    }
+}
+
+impl LimitSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_json_body_bytes < 1024 || self.max_json_body_bytes > MAX_JSON_BODY_CEILING {
+            return Err(format!("limits.max_json_body_bytes must be between 1024 and {}", MAX_JSON_BODY_CEILING));
+        }
+        Ok(())
+    }
+}
+
+// One limited resource: how many are in use and how many requests it turned away
+struct Gauge {
+    in_use: AtomicUsize,
+    rejected: AtomicU64,
+}
+
+static SETTINGS: Lazy<StdMutex<LimitSettings>> = Lazy::new(|| StdMutex::new(LimitSettings::default()));
+static UPLOADS: Gauge = Gauge { in_use: AtomicUsize::new(0), rejected: AtomicU64::new(0) };
+static WEBSOCKETS: Gauge = Gauge { in_use: AtomicUsize::new(0), rejected: AtomicU64::new(0) };
+static JSON_REJECTED: AtomicU64 = AtomicU64::new(0);
+
+// Apply the `limits` settings; called at startup and whenever the settings change
+pub fn configure(settings: &LimitSettings) {
+    *SETTINGS.lock().unwrap() = settings.clone();
+}
+
+fn settings() -> LimitSettings {
+    SETTINGS.lock().unwrap().clone()
+}
+
+// A place taken in a limited resource, given back when dropped. A WebSocket connection's slot is
+// put into the request by `check`, for the handler to keep while the connection is open.
+#[derive(Debug)]
+pub struct Slot(&'static AtomicUsize);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn acquire(gauge: &'static Gauge, max: usize) -> Option<Slot> {
+    let taken = gauge.in_use.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (max == 0 || n < max).then_some(n + 1));
+    if taken.is_err() {
+        gauge.rejected.fetch_add(1, Ordering::Relaxed);
+        return None;
+    }
+    Some(Slot(&gauge.in_use))
+}
+
+fn is_upload(req: &ServiceRequest) -> bool {
+    (req.method() == Method::POST && req.path() == "/api/upload") || (req.method() == Method::PUT && req.path().starts_with("/webdav"))
+}
+
+fn is_websocket(req: &ServiceRequest) -> bool {
+    req.path() == "/api/ws" && req.headers().get(header::UPGRADE).and_then(|v| v.to_str().ok()).is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+}
+
+fn is_json(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(';').next().is_some_and(|t| t.trim().eq_ignore_ascii_case("application/json")))
+}
+
+fn too_large(max: usize) -> HttpResponse {
+    JSON_REJECTED.fetch_add(1, Ordering::Relaxed);
+    HttpResponse::PayloadTooLarge().json(serde_json::json!({ "success": false, "message": format!("Request body is larger than {} bytes", max) }))
+}
+
+fn busy(what: &str, max: usize) -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS.to_string()))
+        .json(serde_json::json!({ "success": false, "message": format!("This node is already handling {} {}, try again shortly", max, what) }))
+}
+
+// Hold `req` to the limits: Err is the response to send instead, Ok an upload's slot for the
+// caller to keep until the response is ready
+#[allow(clippy::result_large_err)]
+pub fn check(req: &mut ServiceRequest) -> Result<Option<Slot>, HttpResponse> {
+    let limits = settings();
+    if is_json(req) {
+        let max = limits.max_json_body_bytes;
+        let length = req.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok());
+        match length {
+            Some(length) if length > max as u64 => return Err(too_large(max)),
+            Some(_) => {}
+            // Without a length the body is cut off once it passes the limit
+            None => {
+                let mut read = 0usize;
+                let limited = req.parts_mut().1.take().map(move |chunk| {
+                    let chunk = chunk?;
+                    read += chunk.len();
+                    if read > max {
+                        return Err(PayloadError::Overflow);
+                    }
+                    Ok(chunk)
+                });
+                req.set_payload(Payload::Stream { payload: Box::pin(limited) });
+            }
+        }
+    }
+    if is_upload(req) {
+        return acquire(&UPLOADS, limits.max_concurrent_uploads).map(Some).ok_or_else(|| busy("uploads", limits.max_concurrent_uploads));
+    }
+    if is_websocket(req) {
+        let slot = acquire(&WEBSOCKETS, limits.max_websocket_connections).ok_or_else(|| busy("WebSocket connections", limits.max_websocket_connections))?;
+        actix_web::HttpMessage::extensions_mut(req).insert(slot);
+    }
+    Ok(None)
+}
+
+// The JSON extractor's configuration: bodies cut off by `check` get the same 413 as those refused
+// by their length
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().limit(MAX_JSON_BODY_CEILING).error_handler(|err, _| match err {
+        JsonPayloadError::Payload(PayloadError::Overflow) | JsonPayloadError::Overflow { .. } => {
+            let max = settings().max_json_body_bytes;
+            actix_web::error::InternalError::from_response(err, too_large(max)).into()
+        }
+        err => err.into(),
+    })
+}
+
+#[get("/analytics/limits")]
+pub async fn analytics_limits() -> Result<HttpResponse, Error> {
+    let limits = settings();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "json_body": { "max_bytes": limits.max_json_body_bytes, "rejected": JSON_REJECTED.load(Ordering::Relaxed) },
+        "uploads": {
+            "max_concurrent": limits.max_concurrent_uploads,
+            "in_progress": UPLOADS.in_use.load(Ordering::SeqCst),
+            "rejected": UPLOADS.rejected.load(Ordering::Relaxed),
+        },
+        "websockets": {
+            "max_connections": limits.max_websocket_connections,
+            "open": WEBSOCKETS.in_use.load(Ordering::SeqCst),
+            "rejected": WEBSOCKETS.rejected.load(Ordering::Relaxed),
+        },
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, configure, LimitSettings};
+    use actix_web::test::TestRequest;
+
+    // Oversized JSON bodies get 413, and uploads beyond the concurrency limit 503 until one finishes
+    #[test]
+    fn limits_reject_large_bodies_and_excess_uploads() {
+        let settings = LimitSettings { max_json_body_bytes: 1024, max_concurrent_uploads: 1, max_websocket_connections: 0 };
+        assert!(settings.validate().is_ok());
+        assert!(LimitSettings { max_json_body_bytes: 10, ..settings.clone() }.validate().is_err());
+        configure(&settings);
+
+        let json = |len: usize| TestRequest::post().uri("/api/chat").insert_header(("content-type", "application/json")).insert_header(("content-length", len.to_string())).to_srv_request();
+        assert_eq!(check(&mut json(2048)).unwrap_err().status(), 413);
+        assert!(check(&mut json(512)).unwrap().is_none());
+
+        let upload = || TestRequest::post().uri("/api/upload").to_srv_request();
+        let first = check(&mut upload()).unwrap();
+        assert!(first.is_some());
+        let busy = check(&mut upload()).unwrap_err();
+        assert_eq!(busy.status(), 503);
+        assert!(busy.headers().contains_key("retry-after"));
+        drop(first);
+        assert!(check(&mut upload()).unwrap().is_some());
+        configure(&LimitSettings::default());
+    }
+}
@@ -0,0 +1,631 @@
+// The model server that answers prompts on this node. Ollama is the default; nodes running LM
+// Studio, vLLM or another OpenAI-compatible server, or a llama.cpp server, pick theirs with the
+// `backend` settings and serve the mesh the same way. Everything above this module builds a
+// BackendRequest (system prompt, earlier turns, prompt and generation options) and leaves the wire
+// format to the backend.
+//
+// Loading a model takes a while, so POST /api/llm/preload (or `preload_on_start`) loads it ahead of
+// the first prompt, and `keep_alive` sets how long Ollama keeps it in memory once it is idle.
+use actix_web::{post, web, HttpResponse, Error};
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use super::Answer;
+use crate::events::{self, Category};
+use crate::settings::GenerationOptions;
+
+const AVAILABILITY_TIMEOUT: Duration = Duration::from_secs(2);
+// Ollama model used when none is configured
+const DEFAULT_OLLAMA_MODEL: &str = "llama2";
+// Loading a large model from disk can take minutes
+const PRELOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Ollama,
+    // LM Studio, vLLM and anything else speaking /v1/chat/completions
+    OpenAi,
+    LlamaCpp,
+}
+
+impl BackendKind {
+    fn default_base_url(self) -> &'static str {
+        match self {
+            BackendKind::Ollama => "http://127.0.0.1:11434",
+            BackendKind::OpenAi => "http://127.0.0.1:1234/v1",
+            // llama.cpp defaults to 8080, which MeshMind itself listens on
+            BackendKind::LlamaCpp => "http://127.0.0.1:8081",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendSettings {
+    pub kind: BackendKind,
+    // Root of the server's API; each kind has a local default
+    pub base_url: Option<String>,
+    // Model to ask for; Ollama falls back to llama2, OpenAI-compatible servers to the first model listed
+    pub model: Option<String>,
+    // Sent as a bearer token to OpenAI-compatible servers
+    pub api_key: Option<String>,
+    // How long Ollama keeps the model loaded after the last request: seconds ("-1" forever, "0"
+    // unloads right away) or a duration such as "10m"; unset leaves Ollama's default of 5 minutes
+    pub keep_alive: Option<String>,
+    // Load the model when the node starts instead of on the first prompt
+    pub preload_on_start: bool,
+}
+
+impl BackendSettings {
+    pub fn normalize(&mut self) {
+        let trim = |v: &mut Option<String>| *v = v.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+        trim(&mut self.base_url);
+        trim(&mut self.model);
+        trim(&mut self.api_key);
+        trim(&mut self.keep_alive);
+        if let Some(url) = &mut self.base_url {
+            while url.ends_with('/') {
+                url.pop();
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.base_url {
+            match Url::parse(url) {
+                Ok(u) if matches!(u.scheme(), "http" | "https") && u.host().is_some() => {}
+                _ => return Err(format!("backend.base_url must be an http(s) URL: {:?}", url)),
+            }
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            keep_alive_value(keep_alive).map_err(|e| format!("backend.keep_alive: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn base_url(&self) -> String {
+        self.base_url.clone().unwrap_or_else(|| self.kind.default_base_url().to_string())
+    }
+}
+
+// A keep_alive setting as Ollama takes it: a number of seconds or a duration string like "1h30m"
+pub fn keep_alive_value(keep_alive: &str) -> Result<serde_json::Value, String> {
+    if let Ok(secs) = keep_alive.parse::<i64>() {
+        return Ok(secs.into());
+    }
+    let mut rest = keep_alive;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+        let unit_len = ["ms", "h", "m", "s"].iter().find(|u| rest[digits..].starts_with(*u)).map(|u| u.len());
+        match unit_len {
+            Some(len) if digits > 0 && rest[..digits].parse::<f64>().is_ok() => rest = &rest[digits + len..],
+            _ => return Err(format!("{:?} is neither seconds nor a duration like \"10m\"", keep_alive)),
+        }
+    }
+    Ok(keep_alive.into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PromptMessage {
+    pub role: String,
+    pub content: String,
+}
+
+// What every backend is asked: the conversation so far, ending with the prompt
+#[derive(Serialize, Debug, Clone)]
+pub struct BackendRequest {
+    pub messages: Vec<PromptMessage>,
+    #[serde(flatten)]
+    pub options: GenerationOptions,
+    // Asked for instead of the configured model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl BackendRequest {
+    pub fn prompt(&self) -> &str {
+        self.messages.last().map(|m| m.content.as_str()).unwrap_or("")
+    }
+}
+
+pub trait LlmBackend {
+    fn name(&self) -> &'static str;
+    // Root of the server's API
+    fn base_url(&self) -> &str;
+    // Whether the server is up and ready to answer
+    async fn available(&self) -> bool;
+    async fn chat(&self, request: &BackendRequest) -> Result<Answer, String>;
+    // Load `model` (the configured one when None) so the first prompt does not wait for it;
+    // returns the model loaded
+    async fn preload(&self, model: Option<&str>, keep_alive: Option<&str>) -> Result<String, PreloadError>;
+}
+
+#[derive(Debug)]
+pub enum PreloadError {
+    // The server loads models on its own terms
+    Unsupported(&'static str),
+    Failed(String),
+}
+
+impl std::fmt::Display for PreloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreloadError::Unsupported(backend) => write!(f, "The {} backend cannot preload models", backend),
+            PreloadError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+pub struct Ollama {
+    base_url: String,
+    model: String,
+    keep_alive: Option<String>,
+}
+
+// keep_alive for a request: the one given, else the configured one
+fn keep_alive_or(keep_alive: Option<&str>, configured: &Option<String>) -> Option<serde_json::Value> {
+    keep_alive.or(configured.as_deref()).and_then(|k| keep_alive_value(k).ok())
+}
+
+#[derive(Serialize)]
+struct OllamaRequest<'a> {
+    model: &'a str,
+    messages: &'a [PromptMessage],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+// A /api/generate request without a prompt only loads the model
+#[derive(Serialize)]
+struct OllamaLoadRequest<'a> {
+    model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+    stream: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaResponse {
+    message: PromptMessage,
+    done: bool,
+}
+
+// Join the lines of a streamed Ollama /api/chat answer
+pub fn process_ollama_response(body: &str) -> Result<String, String> {
+    let mut full_response = String::new();
+    let mut response_complete = false;
+
+    for line in body.lines() {
+        if let Ok(resp) = serde_json::from_str::<OllamaResponse>(line) {
+            full_response.push_str(&resp.message.content);
+            if resp.done {
+                response_complete = true;
+            }
+        }
+    }
+
+    if !response_complete {
+        return Err("Incomplete response from LLM".to_string());
+    }
+
+    if full_response.trim().is_empty() {
+        return Err("Empty response from LLM".to_string());
+    }
+
+    Ok(full_response)
+}
+
+async fn is_up(request: RequestBuilder) -> bool {
+    matches!(request.timeout(AVAILABILITY_TIMEOUT).send().await, Ok(r) if r.status().is_success())
+}
+
+impl LlmBackend for Ollama {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn available(&self) -> bool {
+        is_up(crate::client::external().get(format!("{}/api/tags", self.base_url))).await
+    }
+
+    async fn chat(&self, request: &BackendRequest) -> Result<Answer, String> {
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        let body = OllamaRequest {
+            model,
+            messages: &request.messages,
+            options: request.options.to_ollama(),
+            keep_alive: keep_alive_or(None, &self.keep_alive),
+        };
+        let response = crate::client::external()
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to connect to local LLM: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Local LLM error: {}", response.status()));
+        }
+        let body = response.text().await.map_err(|e| format!("Failed to get local LLM response: {}", e))?;
+        let content = process_ollama_response(&body)?;
+        Ok(Answer { content, host_info: None, model: Some(model.to_string()) })
+    }
+
+    async fn preload(&self, model: Option<&str>, keep_alive: Option<&str>) -> Result<String, PreloadError> {
+        let model = model.unwrap_or(&self.model);
+        let body = OllamaLoadRequest { model, keep_alive: keep_alive_or(keep_alive, &self.keep_alive), stream: false };
+        let response = crate::client::external()
+            .post(format!("{}/api/generate", self.base_url))
+            .timeout(PRELOAD_TIMEOUT)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| PreloadError::Failed(format!("Failed to connect to local LLM: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let detail = response.text().await.unwrap_or_default();
+            return Err(PreloadError::Failed(format!("Local LLM error: {} {}", status, detail.trim())));
+        }
+        Ok(model.to_string())
+    }
+}
+
+// The /v1/chat/completions dialect spoken by OpenAI-compatible servers and llama.cpp
+#[derive(Serialize)]
+struct CompletionRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    messages: &'a [PromptMessage],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    #[serde(default)]
+    model: Option<String>,
+    choices: Vec<CompletionChoice>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChoice {
+    message: PromptMessage,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+fn with_key(request: RequestBuilder, api_key: Option<&str>) -> RequestBuilder {
+    match api_key {
+        Some(key) => request.bearer_auth(key),
+        None => request,
+    }
+}
+
+// POST {chat_url}; the context size (num_ctx) is fixed when these servers load the model
+async fn chat_completion(chat_url: &str, api_key: Option<&str>, model: Option<&str>, request: &BackendRequest) -> Result<Answer, String> {
+    let body = CompletionRequest {
+        model,
+        messages: &request.messages,
+        temperature: request.options.temperature,
+        top_p: request.options.top_p,
+        max_tokens: request.options.max_tokens,
+        stream: false,
+    };
+    let response = with_key(crate::client::external().post(chat_url), api_key)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to connect to local LLM: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Local LLM error: {}", response.status()));
+    }
+    let completion: CompletionResponse = response.json().await.map_err(|e| format!("Failed to read local LLM response: {}", e))?;
+    let content = completion.choices.into_iter().next().map(|c| c.message.content).unwrap_or_default();
+    if content.trim().is_empty() {
+        return Err("Empty response from LLM".to_string());
+    }
+    Ok(Answer { content, host_info: None, model: completion.model.or(model.map(str::to_string)) })
+}
+
+pub struct OpenAiCompatible {
+    base_url: String,
+    model: Option<String>,
+    api_key: Option<String>,
+}
+
+impl OpenAiCompatible {
+    async fn models(&self) -> Result<Vec<String>, String> {
+        let response = with_key(crate::client::external().get(format!("{}/models", self.base_url)), self.api_key.as_deref())
+            .timeout(AVAILABILITY_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to connect to local LLM: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Local LLM error: {}", response.status()));
+        }
+        let list: ModelList = response.json().await.map_err(|e| format!("Failed to read model list: {}", e))?;
+        Ok(list.data.into_iter().map(|m| m.id).collect())
+    }
+}
+
+impl LlmBackend for OpenAiCompatible {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn available(&self) -> bool {
+        self.models().await.is_ok_and(|models| !models.is_empty())
+    }
+
+    async fn chat(&self, request: &BackendRequest) -> Result<Answer, String> {
+        // vLLM only answers for the model it serves, so ask for one by name
+        let model = match request.model.as_ref().or(self.model.as_ref()) {
+            Some(model) => model.clone(),
+            None => self.models().await?.into_iter().next().ok_or("The LLM server lists no models")?,
+        };
+        chat_completion(&format!("{}/chat/completions", self.base_url), self.api_key.as_deref(), Some(&model), request).await
+    }
+
+    async fn preload(&self, _model: Option<&str>, _keep_alive: Option<&str>) -> Result<String, PreloadError> {
+        Err(PreloadError::Unsupported(self.name()))
+    }
+}
+
+pub struct LlamaCpp {
+    base_url: String,
+    model: Option<String>,
+}
+
+impl LlmBackend for LlamaCpp {
+    fn name(&self) -> &'static str {
+        "llamacpp"
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    // /health answers 503 while the model is still loading
+    async fn available(&self) -> bool {
+        is_up(crate::client::external().get(format!("{}/health", self.base_url))).await
+    }
+
+    // The server applies the model's own chat template to the messages
+    async fn chat(&self, request: &BackendRequest) -> Result<Answer, String> {
+        let model = request.model.as_deref().or(self.model.as_deref());
+        chat_completion(&format!("{}/v1/chat/completions", self.base_url), None, model, request).await
+    }
+
+    // llama.cpp serves the one model it was started with, loaded before /health reports ready
+    async fn preload(&self, _model: Option<&str>, _keep_alive: Option<&str>) -> Result<String, PreloadError> {
+        Err(PreloadError::Unsupported(self.name()))
+    }
+}
+
+// The backend the settings select
+pub enum Backend {
+    Ollama(Ollama),
+    OpenAi(OpenAiCompatible),
+    LlamaCpp(LlamaCpp),
+}
+
+impl Backend {
+    pub fn from_settings(settings: &BackendSettings) -> Backend {
+        let base_url = settings.base_url();
+        match settings.kind {
+            BackendKind::Ollama => Backend::Ollama(Ollama {
+                base_url,
+                model: settings.model.clone().unwrap_or_else(|| DEFAULT_OLLAMA_MODEL.to_string()),
+                keep_alive: settings.keep_alive.clone(),
+            }),
+            BackendKind::OpenAi => Backend::OpenAi(OpenAiCompatible { base_url, model: settings.model.clone(), api_key: settings.api_key.clone() }),
+            BackendKind::LlamaCpp => Backend::LlamaCpp(LlamaCpp { base_url, model: settings.model.clone() }),
+        }
+    }
+}
+
+impl LlmBackend for Backend {
+    fn name(&self) -> &'static str {
+        match self {
+            Backend::Ollama(b) => b.name(),
+            Backend::OpenAi(b) => b.name(),
+            Backend::LlamaCpp(b) => b.name(),
+        }
+    }
+
+    fn base_url(&self) -> &str {
+        match self {
+            Backend::Ollama(b) => b.base_url(),
+            Backend::OpenAi(b) => b.base_url(),
+            Backend::LlamaCpp(b) => b.base_url(),
+        }
+    }
+
+    async fn available(&self) -> bool {
+        match self {
+            Backend::Ollama(b) => b.available().await,
+            Backend::OpenAi(b) => b.available().await,
+            Backend::LlamaCpp(b) => b.available().await,
+        }
+    }
+
+    async fn chat(&self, request: &BackendRequest) -> Result<Answer, String> {
+        match self {
+            Backend::Ollama(b) => b.chat(request).await,
+            Backend::OpenAi(b) => b.chat(request).await,
+            Backend::LlamaCpp(b) => b.chat(request).await,
+        }
+    }
+
+    async fn preload(&self, model: Option<&str>, keep_alive: Option<&str>) -> Result<String, PreloadError> {
+        match self {
+            Backend::Ollama(b) => b.preload(model, keep_alive).await,
+            Backend::OpenAi(b) => b.preload(model, keep_alive).await,
+            Backend::LlamaCpp(b) => b.preload(model, keep_alive).await,
+        }
+    }
+}
+
+// This node's backend as currently configured
+pub async fn configured() -> Backend {
+    Backend::from_settings(&crate::settings::current().await.backend)
+}
+
+// Whether this node's backend is up
+pub async fn available() -> bool {
+    configured().await.available().await
+}
+
+// Load the configured model at startup when `preload_on_start` is set
+pub async fn preload_on_start() {
+    let settings = crate::settings::current().await.backend;
+    if !settings.preload_on_start {
+        return;
+    }
+    let backend = Backend::from_settings(&settings);
+    match backend.preload(None, None).await {
+        Ok(model) => events::info(Category::System, format!("Preloaded model {}", model), serde_json::json!({ "model": model })),
+        Err(e) => events::warn(Category::System, format!("Could not preload the model: {}", e), serde_json::json!({ "backend": backend.name() })),
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct PreloadRequest {
+    // Defaults to the configured model
+    #[serde(default)]
+    pub model: Option<String>,
+    // Overrides the configured keep_alive for this load
+    #[serde(default)]
+    pub keep_alive: Option<String>,
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can preload models" }))
+}
+
+#[post("/llm/preload")]
+pub async fn preload(body: Option<web::Json<PreloadRequest>>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let req = body.map(|b| b.into_inner()).unwrap_or_default();
+    let model = req.model.as_deref().map(str::trim).filter(|m| !m.is_empty());
+    let keep_alive = req.keep_alive.as_deref().map(str::trim).filter(|k| !k.is_empty());
+    if let Some(Err(e)) = keep_alive.map(keep_alive_value) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": format!("keep_alive: {}", e) })));
+    }
+    let backend = configured().await;
+    match backend.preload(model, keep_alive).await {
+        Ok(model) => {
+            events::info(Category::System, format!("Preloaded model {}", model), serde_json::json!({ "model": model, "keep_alive": keep_alive }));
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "backend": backend.name(), "model": model })))
+        }
+        Err(e @ PreloadError::Unsupported(_)) => Ok(HttpResponse::NotImplemented().json(serde_json::json!({ "success": false, "message": e.to_string() }))),
+        Err(e) => Ok(HttpResponse::BadGateway().json(serde_json::json!({ "success": false, "message": e.to_string() }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keep_alive_value, Backend, BackendKind, BackendRequest, BackendSettings, GenerationOptions, LlmBackend, PreloadError, PromptMessage};
+
+    // Serves one HTTP request with `body` as JSON and hands back the request it got
+    async fn stub_http_server(body: &'static str) -> (u16, tokio::task::JoinHandle<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            // Headers, then as much body as Content-Length says
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head.lines().find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap())).unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            let response = format!("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (port, server)
+    }
+
+    // An OpenAI-compatible backend posts the conversation to /chat/completions and reads the first choice
+    #[tokio::test]
+    async fn openai_compatible_backend_chat() {
+        let (port, server) = stub_http_server(r#"{"model":"served","choices":[{"message":{"role":"assistant","content":"pong"}}]}"#).await;
+
+        let backend = Backend::from_settings(&BackendSettings {
+            kind: BackendKind::OpenAi,
+            base_url: Some(format!("http://127.0.0.1:{}/v1", port)),
+            model: Some("m".into()),
+            api_key: Some("k".into()),
+            ..Default::default()
+        });
+        let request = BackendRequest {
+            messages: vec![PromptMessage { role: "user".into(), content: "ping".into() }],
+            options: GenerationOptions { max_tokens: Some(8), ..Default::default() },
+            model: None,
+        };
+        let answer = backend.chat(&request).await.expect("answer");
+        assert_eq!((answer.content.as_str(), answer.model.as_deref()), ("pong", Some("served")));
+        let seen = server.await.unwrap();
+        assert!(seen.starts_with("POST /v1/chat/completions "), "{}", seen);
+        assert!(seen.to_lowercase().contains("authorization: bearer k"), "{}", seen);
+        assert!(seen.contains(r#""model":"m""#) && seen.contains(r#""max_tokens":8"#) && seen.contains(r#""stream":false"#), "{}", seen);
+    }
+
+    // Preloading asks Ollama's /api/generate for the model without a prompt, with the keep_alive given
+    #[tokio::test]
+    async fn ollama_preload_sends_keep_alive() {
+        assert_eq!(keep_alive_value("-1"), Ok(serde_json::json!(-1)));
+        assert_eq!(keep_alive_value("1h30m"), Ok(serde_json::json!("1h30m")));
+        assert!(keep_alive_value("10 minutes").is_err());
+        assert!(keep_alive_value("m").is_err());
+
+        let (port, server) = stub_http_server(r#"{"model":"mistral","done":true,"done_reason":"load"}"#).await;
+        let backend = Backend::from_settings(&BackendSettings {
+            base_url: Some(format!("http://127.0.0.1:{}", port)),
+            keep_alive: Some("5m".into()),
+            ..Default::default()
+        });
+        assert_eq!(backend.preload(Some("mistral"), Some("-1")).await.expect("preload"), "mistral");
+        let seen = server.await.unwrap();
+        assert!(seen.starts_with("POST /api/generate "), "{}", seen);
+        assert!(seen.ends_with(r#"{"model":"mistral","keep_alive":-1,"stream":false}"#), "{}", seen);
+
+        let llamacpp = Backend::from_settings(&BackendSettings { kind: BackendKind::LlamaCpp, ..Default::default() });
+        assert!(matches!(llamacpp.preload(None, None).await, Err(PreloadError::Unsupported(_))));
+    }
+}
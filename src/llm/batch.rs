@@ -0,0 +1,157 @@
+// Batch chat for scripted workloads: many prompts in one request, run as a single background job.
+// Prompts share a small pool of LLM slots (BATCH_CONCURRENCY, default 2) across all batches, so a
+// bulk run cannot starve interactive chat, and each prompt's status can be polled by job id.
+use actix_web::{get, post, web, HttpResponse, Error};
+use futures::future::join_all;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore};
+use crate::jobs::JobStatus;
+use super::{prepare, run_chat, ChatRequest};
+
+const MAX_BATCH_ITEMS: usize = 500;
+// Batches whose item status is kept for polling (oldest dropped first)
+const MAX_BATCH_HISTORY: usize = 50;
+// Characters of each prompt echoed back in the status
+const PROMPT_PREVIEW_CHARS: usize = 200;
+
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    // Each item takes the same fields as POST /api/chat
+    items: Vec<ChatRequest>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchItem {
+    pub index: usize,
+    pub prompt: String,
+    pub status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+type BatchItems = Arc<Mutex<Vec<BatchItem>>>;
+
+lazy_static! {
+    static ref SLOTS: Arc<Semaphore> = Arc::new(Semaphore::new(
+        std::env::var("BATCH_CONCURRENCY").ok().and_then(|v| v.trim().parse().ok()).filter(|n| *n > 0).unwrap_or(2)
+    ));
+    // Item status per batch job id
+    static ref BATCHES: Arc<Mutex<VecDeque<(u64, BatchItems)>>> = Arc::new(Mutex::new(VecDeque::new()));
+}
+
+async fn run_item(items: BatchItems, index: usize, req: ChatRequest) -> bool {
+    let _permit = match SLOTS.clone().acquire_owned().await {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    items.lock().await[index].status = JobStatus::Running;
+    let result = run_chat(&req).await;
+    let mut items = items.lock().await;
+    let item = &mut items[index];
+    match result {
+        Ok(message) => {
+            item.status = JobStatus::Succeeded;
+            item.answer_id = Some(message.id);
+            item.answer = Some(message.content);
+            true
+        }
+        Err(e) => {
+            item.status = JobStatus::Failed;
+            item.error = Some(e.to_string());
+            false
+        }
+    }
+}
+
+#[post("/chat/batch")]
+pub async fn submit_batch(body: web::Json<BatchRequest>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let mut requests = body.into_inner().items;
+    let user = crate::users::name(user);
+    if requests.is_empty() || requests.len() > MAX_BATCH_ITEMS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("A batch needs 1-{} items", MAX_BATCH_ITEMS)
+        })));
+    }
+    for (index, req) in requests.iter_mut().enumerate() {
+        req.set_user(user.clone());
+        if let Err((status, message)) = prepare(req).await {
+            return Ok(HttpResponse::build(status).json(serde_json::json!({
+                "success": false,
+                "message": format!("Item {}: {}", index, message)
+            })));
+        }
+    }
+
+    let items: BatchItems = Arc::new(Mutex::new(
+        requests
+            .iter()
+            .enumerate()
+            .map(|(index, req)| BatchItem {
+                index,
+                prompt: super::text::truncate_chars(&req.message, PROMPT_PREVIEW_CHARS).to_string(),
+                status: JobStatus::Queued,
+                answer_id: None,
+                answer: None,
+                error: None,
+            })
+            .collect(),
+    ));
+    let total = requests.len();
+    let job_items = items.clone();
+    let job_id = crate::jobs::submit("chat-batch", format!("{} prompts", total), async move {
+        let results = join_all(requests.into_iter().enumerate().map(|(index, req)| run_item(job_items.clone(), index, req))).await;
+        let answered = results.iter().filter(|ok| **ok).count();
+        if answered == 0 {
+            Err(format!("none of {} prompts were answered", total))
+        } else {
+            Ok(format!("{} of {} prompts answered", answered, total))
+        }
+    })
+    .await;
+    {
+        let mut batches = BATCHES.lock().await;
+        batches.push_back((job_id, items));
+        while batches.len() > MAX_BATCH_HISTORY {
+            batches.pop_front();
+        }
+    }
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "success": true,
+        "job_id": job_id,
+        "items": total
+    })))
+}
+
+#[get("/chat/batch/{id}")]
+pub async fn get_batch(path: web::Path<u64>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let items = BATCHES.lock().await.iter().find(|(job_id, _)| *job_id == id).map(|(_, items)| items.clone());
+    match (items, crate::jobs::get_job_info(id).await) {
+        (Some(items), Some(job)) => {
+            let items = items.lock().await.clone();
+            let count = |status: JobStatus| items.iter().filter(|i| i.status == status).count();
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "job": job,
+                "summary": {
+                    "queued": count(JobStatus::Queued),
+                    "running": count(JobStatus::Running),
+                    "succeeded": count(JobStatus::Succeeded),
+                    "failed": count(JobStatus::Failed)
+                },
+                "items": items
+            })))
+        }
+        _ => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Batch not found"
+        }))),
+    }
+}
@@ -0,0 +1,182 @@
+// Fan-out chat: the same prompt goes to our model and to every peer that granted us LLM access at
+// once. Each answer is stored as an alternative to the one question, tagged with the node and model
+// that wrote it, so they can be compared side by side (also via GET /api/messages/{id}/alternatives).
+// A node whose routing is local_only only asks its own model.
+use actix_web::{post, web, HttpResponse, Error};
+use chrono::Utc;
+use futures::future::join_all;
+use serde::Serialize;
+use crate::conversation::{ChatMessage, CONVERSATION_STORE, HostInfo, MessageType};
+use crate::moderation::Stage;
+use crate::presence::Activity;
+use super::routing::{Routing, RoutingMode};
+use super::{ask_peer, apply_template, build_prompt, chat_error_response, check_options, language, local_host_info, moderate, backend_request, titles, try_local_llm, ChatError, ChatRequest};
+
+// A node that was asked but produced no stored answer
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub node: String,
+    pub error: String,
+}
+
+pub struct Fanout {
+    pub question: ChatMessage,
+    pub answers: Vec<ChatMessage>,
+    pub failures: Vec<Failure>,
+}
+
+// Store the question in the local conversation, ask every LLM concurrently and store each answer.
+// Fails only when no node answered.
+pub async fn run_fanout(req: &ChatRequest) -> Result<Fanout, ChatError> {
+    let host_info = HostInfo { user: req.user.clone(), ..local_host_info().await };
+    let prompt = build_prompt(req).await;
+    let settings = crate::settings::current().await;
+    let language = language::resolve(req.language.as_deref(), &req.message, &settings.language);
+
+    let (prompt, prompt_matches) = crate::redact::redact(&prompt).await;
+    if !prompt_matches.is_empty() {
+        println!("REDACT: Removed {} from a prompt by {}", prompt_matches.join(", "), req.sender);
+    }
+    let prompt_flags = moderate(Stage::Prompt, &prompt, &req.sender, "local").await?;
+    let question = ChatMessage {
+        id: crate::conversation::new_message_id(),
+        content: prompt.clone(),
+        timestamp: Utc::now(),
+        lamport: crate::clock::lamport_tick(),
+        sender: req.sender.clone(),
+        message_type: MessageType::Question,
+        host_info: host_info.clone(),
+        attachment: req.filename.clone(),
+        alternative_of: None,
+        reply_to: req.reply_to.clone(),
+        preferred: false,
+        language: language.clone(),
+        redacted: !prompt_matches.is_empty(),
+        flags: prompt_flags,
+        model: None,
+        pinned: false,
+        stamps: Default::default(),
+    };
+    CONVERSATION_STORE.add_message("local".to_string(), question.clone()).await;
+
+    let options = req.options.or(&settings.generation);
+    let backend_req = backend_request(prompt.clone(), &[], &options, language.as_deref(), None).await;
+    let peers: Vec<(String, (String, i32))> = if settings.routing.mode == Some(RoutingMode::LocalOnly) {
+        Vec::new()
+    } else {
+        crate::tcp::llm_connections()
+    };
+    println!("API: Fan-out chat from {} to {} peer(s){}", req.sender, peers.len(), if host_info.is_llm_host { " and the local model" } else { "" });
+
+    crate::presence::set_local("local", "LLM", Activity::Generating).await;
+    let local = async {
+        if !host_info.is_llm_host {
+            return None;
+        }
+        let answer = try_local_llm(&backend_req).await;
+        Some(("local".to_string(), answer))
+    };
+    let remote = join_all(peers.iter().map(|(peer, (host, port))| {
+        let (prompt, options, language) = (&prompt, &options, language.as_deref());
+        async move { (peer.clone(), ask_peer(peer, (host, *port), prompt, &req.sender, options, language, &Routing::local_only()).await) }
+    }));
+    let (local, remote) = tokio::join!(local, remote);
+    crate::presence::set_local("local", "LLM", Activity::Idle).await;
+
+    let mut answers = Vec::new();
+    let mut failures = Vec::new();
+    for (node, result) in local.into_iter().chain(remote) {
+        let answer = match result {
+            Ok(answer) => answer,
+            Err(error) => {
+                failures.push(Failure { node, error });
+                continue;
+            }
+        };
+        let (content, matches) = crate::redact::redact(&answer.content).await;
+        let flags = match moderate(Stage::Response, &content, &req.sender, "local").await {
+            Ok(flags) => flags,
+            Err(e) => {
+                failures.push(Failure { node, error: e.to_string() });
+                continue;
+            }
+        };
+        let origin = match answer.host_info {
+            Some(info) => info,
+            None if node == "local" => host_info.clone(),
+            None => HostInfo { hostname: node.clone(), ip_address: node.clone(), is_llm_host: true, user: req.user.clone() },
+        };
+        let message = ChatMessage {
+            id: crate::conversation::new_message_id(),
+            content,
+            timestamp: Utc::now(),
+            lamport: crate::clock::lamport_tick(),
+            sender: "LLM".to_string(),
+            message_type: MessageType::Response,
+            host_info: origin,
+            attachment: None,
+            alternative_of: Some(question.id.clone()),
+            reply_to: Some(question.id.clone()),
+            preferred: false,
+            language: None,
+            redacted: !matches.is_empty(),
+            flags,
+            model: answer.model,
+            pinned: false,
+            stamps: Default::default(),
+        };
+        CONVERSATION_STORE.add_message("local".to_string(), message.clone()).await;
+        answers.push(message);
+    }
+
+    if answers.is_empty() {
+        let details = failures.iter().map(|f| format!("{}: {}", f.node, f.error)).collect::<Vec<_>>().join("; ");
+        return Err(ChatError::Unavailable(if details.is_empty() { "No local or remote LLM available".to_string() } else { details }));
+    }
+    crate::tcp::broadcast_local_conversation().await;
+    titles::spawn("local");
+    crate::events::publish("chat", serde_json::json!({
+        "event": "fanout",
+        "sender": req.sender,
+        "question": crate::redact::redact(&req.message).await.0,
+        "nodes": answers.iter().map(|a| a.host_info.ip_address.clone()).collect::<Vec<_>>(),
+        "failed": failures.len()
+    }));
+    Ok(Fanout { question, answers, failures })
+}
+
+// Same body as POST /api/chat; answers always go to the local conversation
+#[post("/chat/fanout")]
+pub async fn chat_fanout(req: web::Json<ChatRequest>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let mut req = req.into_inner();
+    req.set_user(crate::users::name(user));
+    if let Err(message) = check_options(&req) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": message })));
+    }
+    if req.conversation_id.as_deref().is_some_and(|id| id != "local") || req.target_peer.is_some() || !req.routing.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Fan-out asks every LLM in the local conversation; conversation_id, target_peer and routing do not apply"
+        })));
+    }
+    if let Some(reply_to) = req.reply_to.as_deref() {
+        if !CONVERSATION_STORE.get_local_conversation().await.is_some_and(|c| c.messages.iter().any(|m| m.id == reply_to)) {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": format!("Message {} not found in the local conversation", reply_to)
+            })));
+        }
+    }
+    if let Err(message) = apply_template(&mut req).await {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": message })));
+    }
+    match run_fanout(&req).await {
+        Ok(fanout) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "question": fanout.question,
+            "answers": fanout.answers,
+            "failures": fanout.failures
+        }))),
+        Err(e) => Ok(chat_error_response(e)),
+    }
+}
@@ -0,0 +1,44 @@
+// Language of a chat question. Detected with whatlang (ISO 639-3 codes such as "deu" or "spa")
+// unless the request names one; picks the node's localized system prompt for that language and
+// tells the model to answer in it.
+use whatlang::Lang;
+use crate::settings::LanguageSettings;
+
+// Questions shorter than this are too short to classify reliably
+const MIN_DETECT_CHARS: usize = 12;
+
+pub fn is_known(code: &str) -> bool {
+    Lang::from_code(code).is_some()
+}
+
+// ISO 639-3 code of the question's language, if it can be told with confidence
+pub fn detect(text: &str) -> Option<String> {
+    if text.trim().chars().count() < MIN_DETECT_CHARS {
+        return None;
+    }
+    let info = whatlang::detect(text)?;
+    info.is_reliable().then(|| info.lang().code().to_string())
+}
+
+// Language to answer in: the request's choice, then a node-wide override, then detection
+pub fn resolve(requested: Option<&str>, question: &str, settings: &LanguageSettings) -> Option<String> {
+    requested
+        .or(settings.response_language.as_deref())
+        .map(|code| code.to_lowercase())
+        .or_else(|| if settings.auto_detect { detect(question) } else { None })
+}
+
+// Localized system prompt (or `default`) plus an instruction to answer in the given language
+pub fn system_prompt(code: Option<&str>, default: &str, settings: &LanguageSettings) -> String {
+    let lang = match code.and_then(Lang::from_code) {
+        Some(lang) => lang,
+        None => return default.to_string(),
+    };
+    let base = settings.system_prompts.get(lang.code()).map(String::as_str).unwrap_or(default);
+    format!(
+        "{}\n\nAlways respond in {} ({}), the language of the user's question, even when the attached content is in another language.",
+        base,
+        lang.eng_name(),
+        lang.name()
+    )
+}
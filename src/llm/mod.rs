@@ -1,250 +1,303 @@
 // LLM module for language model related functionality
-use actix_web::{post, web, HttpResponse, Error};
+use actix_web::{get, http::StatusCode, post, put, web, HttpResponse, Error};
 use serde::{Deserialize, Serialize};
-use reqwest::Client;
 use chrono::Utc;
 use crate::conversation::{ChatMessage, CONVERSATION_STORE, HostInfo, MessageType};
-use crate::tcp::LLM_CONNECTIONS;
-use std::time::Duration;
+use crate::moderation::{Stage, Verdict};
+use crate::presence::Activity;
+use crate::settings::GenerationOptions;
 use hostname;
+use backend::{BackendRequest, LlmBackend, PromptMessage};
+use routing::{Route, Routing, RoutingMode};
+use crate::users::SessionUser;
+
+pub mod backend;
+pub mod batch;
+pub mod fanout;
+pub mod language;
+pub mod policy;
+pub mod routing;
+pub mod schedules;
+pub mod templates;
+pub mod text;
+pub mod titles;
+pub mod tokens;
+
+// An answer and where it came from: `host_info` is set when a peer wrote it, `model` when known
+pub struct Answer {
+    pub content: String,
+    pub host_info: Option<HostInfo>,
+    pub model: Option<String>,
+}
 
-// Always treat this as the local Ollama base URL
-fn local_ollama_base() -> String {
-    "http://127.0.0.1:11434".to_string()
+#[derive(Serialize)]
+struct RemoteChatReq<'a> {
+    message: &'a str,
+    sender: &'a str,
+    #[serde(flatten)]
+    options: &'a GenerationOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_peer: Option<&'a str>,
+    #[serde(skip_serializing_if = "Routing::is_empty")]
+    routing: &'a Routing,
 }
 
-// Call a remote peer's /api/chat endpoint using our ChatRequest shape.
-// This is required because remote instances expect ChatRequest, not OllamaRequest.
-async fn try_remote_peer_chat(message: &str, sender: &str) -> Result<String, String> {
-    let connections = LLM_CONNECTIONS.lock().await;
-    if connections.is_empty() {
-        return Err("No remote LLM connections available".to_string());
-    }
+// Call one peer's /api/chat endpoint using our ChatRequest shape.
+// This is required because remote instances expect ChatRequest, not a BackendRequest.
+// `routing` goes along: with local_only the peer answers with its own model rather than pass the
+// prompt on, and a model it names is the only one whose answer is kept.
+// Timeouts, retries and the circuit breaker follow the `llm_calls.remote` policy.
+async fn ask_peer(peer: &str, (host, port): (&str, i32), message: &str, sender: &str, options: &GenerationOptions, language: Option<&str>, routing: &Routing) -> Result<Answer, String> {
+    let policy = crate::settings::current().await.llm_calls.remote;
+    let started = std::time::Instant::now();
+    let answer = policy::call(peer, &policy, || ask_peer_once(policy.timeout(), peer, (host, port), message, sender, options, language, routing)).await?;
+    routing::observe(peer, started.elapsed());
+    routing::check_model(answer, routing.model.as_deref())
+}
 
-    #[derive(Serialize)]
-    struct RemoteChatReq<'a> { message: &'a str, sender: &'a str }
-
-    for (peer, (host, port)) in connections.iter() {
-        let client = Client::builder()
-            .timeout(REMOTE_REQUEST_TIMEOUT)
-            .build()
-            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
-        let remote_url = format!("http://{}:{}/api/chat", host, port);
-        println!("Attempting to use remote LLM at {}", remote_url);
-
-        match client.post(&remote_url)
-            .header("x-peer-llm", "1")
-            .json(&RemoteChatReq { message, sender })
-            .send()
-            .await {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        let body = response.text().await
-                            .map_err(|e| format!("Failed to get remote chat response: {}", e))?;
-                        // Remote instance returns our ChatMessage JSON
-                        if let Ok(msg) = serde_json::from_str::<crate::conversation::ChatMessage>(&body) {
-                            if !msg.content.trim().is_empty() {
-                                println!("Successfully used remote LLM from peer {} (ChatMessage)", peer);
-                                return Ok(msg.content);
-                            }
-                        }
-                        // Fallback to Ollama stream parsing just in case
-                        match process_ollama_response(&body) {
-                            Ok(result) => {
-                                println!("Successfully used remote LLM from peer {} (Ollama stream)", peer);
-                                return Ok(result)
-                            },
-                            Err(e) => println!("Failed to process remote chat response from {}: {}", peer, e),
-                        }
-                    } else {
-                        println!("Remote LLM {} returned error status: {}", peer, response.status());
-                    }
-                },
-                Err(e) => println!("Failed to connect to remote LLM {}: {}", peer, e),
-            }
+#[allow(clippy::too_many_arguments)]
+async fn ask_peer_once(timeout: std::time::Duration, peer: &str, (host, port): (&str, i32), message: &str, sender: &str, options: &GenerationOptions, language: Option<&str>, routing: &Routing) -> Result<Answer, String> {
+    let remote_url = format!("{}/api/chat", crate::tls::peer_origin(host, port));
+    println!("Attempting to use remote LLM at {}", remote_url);
+
+    // Peers from before `routing` only understand target_peer
+    let target_peer = (routing.mode == Some(RoutingMode::LocalOnly)).then_some(routing::LOCAL);
+    let response = crate::client::peer().post(&remote_url)
+        .timeout(timeout)
+        .header("x-peer-llm", "1")
+        .json(&RemoteChatReq { message, sender, options, language, target_peer, routing })
+        .send()
+        .await
+        .map_err(|e| format!("Failed to connect to remote LLM {}: {}", peer, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Remote LLM {} returned error status: {}", peer, response.status()));
+    }
+    let body = response.text().await
+        .map_err(|e| format!("Failed to get remote chat response: {}", e))?;
+    // Remote instance returns our ChatMessage JSON
+    if let Ok(msg) = serde_json::from_str::<ChatMessage>(&body) {
+        if !msg.content.trim().is_empty() {
+            println!("Successfully used remote LLM from peer {} (ChatMessage)", peer);
+            return Ok(Answer { content: msg.content, host_info: Some(msg.host_info), model: msg.model });
+        }
+    }
+    // Fallback to Ollama stream parsing just in case
+    match backend::process_ollama_response(&body) {
+        Ok(content) => {
+            println!("Successfully used remote LLM from peer {} (Ollama stream)", peer);
+            Ok(Answer { content, host_info: None, model: None })
+        }
+        Err(e) => Err(format!("Failed to process remote chat response from {}: {}", peer, e)),
     }
-    Err("No available LLM connections responded successfully".to_string())
 }
 
-const REMOTE_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Ask one peer that granted us LLM access
+async fn try_remote_peer_chat(peer: &str, message: &str, sender: &str, options: &GenerationOptions, language: Option<&str>, routing: &Routing) -> Result<Answer, String> {
+    let Some((host, port)) = crate::tcp::llm_connection(peer) else {
+        return Err(format!("Peer {} has not granted us LLM access", peer));
+    };
+    ask_peer(peer, (&host, port), message, sender, options, language, routing).await
+}
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct ChatRequest {
-    message: String,
-    sender: String,
+    pub message: String,
+    pub sender: String,
     #[serde(default)]
-    filename: Option<String>,
+    pub filename: Option<String>,
+    // Prompt template id or name; rendered with `variables` (and {{message}}) before sending
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub variables: std::collections::HashMap<String, String>,
+    // temperature, top_p, num_ctx and max_tokens; unset values fall back to the node settings
+    #[serde(flatten)]
+    pub options: GenerationOptions,
+    // ISO 639-3 code to answer in; detected from the message when omitted
+    #[serde(default)]
+    pub language: Option<String>,
+    // One of our forks to continue instead of the main conversation; its history is sent along
+    #[serde(default)]
+    pub conversation_id: Option<String>,
+    // Id of the message in that conversation this question follows up on, for the threaded view
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    // Run the prompt on this peer's model (by IP) instead of local-first; "local" means ours only
+    #[serde(default)]
+    pub target_peer: Option<String>,
+    // `mode` (cheapest, fastest, local_only, remote_only) and `model`; unset parts use the node's routing
+    #[serde(default)]
+    pub routing: Routing,
+    // The signed-in user who asked, from the session rather than the body
+    #[serde(skip)]
+    pub user: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct OllamaMessage {
-    role: String,
-    content: String,
+impl ChatRequest {
+    // Attribute the request to the signed-in user, whose name also becomes its sender
+    pub fn set_user(&mut self, user: Option<String>) {
+        if let Some(user) = user {
+            self.sender = user.clone();
+            self.user = Some(user);
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct OllamaRequest {
-    model: String,
-    messages: Vec<OllamaMessage>,
+// Ask this node's own backend under the `llm_calls.local` policy
+async fn try_local_llm(req: &BackendRequest) -> Result<Answer, String> {
+    let backend = backend::configured().await;
+    let policy = crate::settings::current().await.llm_calls.local;
+    let started = std::time::Instant::now();
+    let answer = policy::call(routing::LOCAL, &policy, || backend.chat(req)).await?;
+    routing::observe(routing::LOCAL, started.elapsed());
+    routing::check_model(answer, req.model.as_deref())
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct OllamaResponse {
-    model: String,
-    created_at: String,
-    message: OllamaMessage,
-    done: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    done_reason: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    context: Option<Vec<i32>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    total_duration: Option<i64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    load_duration: Option<i64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    prompt_eval_count: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    eval_count: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    eval_duration: Option<i64>,
+#[post("/chat")]
+pub async fn chat(req: web::Json<ChatRequest>, user: Option<web::ReqData<SessionUser>>) -> Result<HttpResponse, Error> {
+    let mut req = req.into_inner();
+    req.set_user(crate::users::name(user));
+    if let Err((status, message)) = prepare(&mut req).await {
+        return Ok(HttpResponse::build(status).json(serde_json::json!({ "success": false, "message": message })));
+    }
+    match run_chat(&req).await {
+        Ok(response_message) => Ok(HttpResponse::Ok().json(response_message)),
+        Err(e) => Ok(chat_error_response(e)),
+    }
 }
 
-// Check localhost only for local availability
-async fn is_local_ollama_available() -> bool {
-    if let Ok(client) = Client::builder()
-        .timeout(Duration::from_secs(2))
-        .build() 
-    {
-        let url = format!("{}/api/tags", local_ollama_base());
-        match client.get(&url).send().await {
-            Ok(response) => response.status().is_success(),
-            Err(_) => false,
+// Validate a chat request and render its template; the error carries the HTTP status to answer with
+async fn prepare(req: &mut ChatRequest) -> Result<(), (StatusCode, String)> {
+    check_options(req).map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+    if let Some(id) = req.conversation_id.as_deref().filter(|id| *id != "local") {
+        if CONVERSATION_STORE.get_fork(id).await.is_none() {
+            return Err((StatusCode::NOT_FOUND, format!("Fork {} not found", id)));
         }
-    } else {
-        false
     }
-}
-
-async fn try_local_llm(req: &OllamaRequest) -> Result<String, String> {
-    let client = Client::new();
-    let url = local_ollama_base();
-    let response = client
-        .post(format!("{}/api/chat", url))
-        .json(&req)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to connect to local LLM: {}", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!("Local LLM error: {}", response.status()));
+    if let Some(reply_to) = req.reply_to.as_deref() {
+        let conversation = CONVERSATION_STORE.get_conversation(req.conversation_id.as_deref().unwrap_or("local")).await;
+        if !conversation.is_some_and(|c| c.messages.iter().any(|m| m.id == reply_to)) {
+            return Err((StatusCode::NOT_FOUND, format!("Message {} not found in the conversation", reply_to)));
+        }
     }
-
-    let body = response.text().await
-        .map_err(|e| format!("Failed to get local LLM response: {}", e))?;
-
-    process_ollama_response(&body)
-}
-
-async fn try_remote_llm(req: &OllamaRequest) -> Result<String, String> {
-    let connections = LLM_CONNECTIONS.lock().await;
-    
-    if connections.is_empty() {
-        return Err("No remote LLM connections available".to_string());
+    if let Some(target) = req.target_peer.as_deref().filter(|t| *t != "local") {
+        if crate::tcp::llm_connection(target).is_none() {
+            return Err((StatusCode::BAD_REQUEST, format!("Peer {} is not an LLM host that granted us access", target)));
+        }
     }
+    if let Some(filename) = req.filename.as_deref() {
+        // Attachments follow the file's visibility, like downloads
+        let user = req.user.clone().map(|name| SessionUser { admin: crate::users::is_admin(&name), name });
+        if crate::persistence::get_file_info(filename).await.ok().flatten().is_some_and(|f| !f.visible_to(user.as_ref())) {
+            return Err((StatusCode::NOT_FOUND, format!("File {} not found", filename)));
+        }
+    }
+    let node = crate::settings::current().await.routing;
+    routing::resolve(&req.routing, &node, req.target_peer.as_deref()).map_err(|message| (StatusCode::FORBIDDEN, message))?;
+    apply_template(req).await.map_err(|message| (StatusCode::BAD_REQUEST, message))
+}
 
-    // Try each known LLM connection
-    for (peer, (host, port)) in connections.iter() {
-        let client = Client::builder()
-            .timeout(REMOTE_REQUEST_TIMEOUT)
-            .build()
-            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
-        let remote_url = format!("http://{}:{}/api/chat", host, port);
-        
-        println!("Attempting to use remote LLM at {}", remote_url);
-        
-        match client.post(&remote_url)
-            .json(&req)
-            .send()
-            .await {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        let body = response.text().await
-                            .map_err(|e| format!("Failed to get remote LLM response: {}", e))?;
-
-                        // First try parsing as our app's ChatMessage JSON (when calling peer's /api/chat)
-                        if let Ok(msg) = serde_json::from_str::<crate::conversation::ChatMessage>(&body) {
-                            if !msg.content.trim().is_empty() {
-                                println!("Successfully used remote LLM from peer {} (ChatMessage)", peer);
-                                return Ok(msg.content);
-                            }
-                        }
-
-                        // Fallback: handle direct Ollama streaming JSON lines (if remote proxied raw)
-                        match process_ollama_response(&body) {
-                            Ok(result) => {
-                                println!("Successfully used remote LLM from peer {} (Ollama stream)", peer);
-                                return Ok(result)
-                            },
-                            Err(e) => println!("Failed to process remote response from {}: {}", peer, e),
-                        }
-                    } else {
-                        println!("Remote LLM {} returned error status: {}", peer, response.status());
-                    }
-                },
-                Err(e) => println!("Failed to connect to remote LLM {}: {}", peer, e),
-            }
+// Language and generation options a chat request may carry
+fn check_options(req: &ChatRequest) -> Result<(), String> {
+    if let Some(code) = req.language.as_deref().filter(|c| !language::is_known(c)) {
+        return Err(format!("Unknown language code {:?} (expected ISO 639-3, e.g. \"deu\")", code));
     }
-    
-    Err("No available LLM connections responded successfully".to_string())
+    req.routing.validate()?;
+    req.options.validate()
 }
 
-fn process_ollama_response(body: &str) -> Result<String, String> {
-    let mut full_response = String::new();
-    let mut response_complete = false;
+// Why a chat request produced no answer
+#[derive(Debug)]
+pub enum ChatError {
+    // No LLM, local or remote, could answer
+    Unavailable(String),
+    // Refused by the moderation policy; holds the labels that matched
+    Blocked(Vec<String>),
+}
 
-    for line in body.lines() {
-        if let Ok(resp) = serde_json::from_str::<OllamaResponse>(line) {
-            full_response.push_str(&resp.message.content);
-            if resp.done {
-                response_complete = true;
-            }
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatError::Unavailable(details) => write!(f, "{}", details),
+            ChatError::Blocked(labels) => write!(f, "blocked by moderation policy ({})", labels.join(", ")),
         }
     }
+}
 
-    if !response_complete {
-        return Err("Incomplete response from LLM".to_string());
+fn chat_error_response(e: ChatError) -> HttpResponse {
+    match e {
+        ChatError::Unavailable(details) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "No available LLM service",
+            "details": details
+        })),
+        ChatError::Blocked(labels) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Blocked by moderation policy",
+            "labels": labels
+        })),
     }
+}
 
-    if full_response.trim().is_empty() {
-        return Err("Empty response from LLM".to_string());
+// Moderation labels to flag a message with, or the error that blocks it
+async fn moderate(stage: Stage, text: &str, sender: &str, conversation_id: &str) -> Result<Vec<String>, ChatError> {
+    match crate::moderation::check(stage, text, sender, conversation_id).await {
+        Verdict::Allow => Ok(Vec::new()),
+        Verdict::Flag(labels) => Ok(labels),
+        Verdict::Block(labels) => Err(ChatError::Blocked(labels)),
     }
+}
 
-    Ok(full_response)
+// Replace the message with the rendered template when the request names one
+pub async fn apply_template(req: &mut ChatRequest) -> Result<(), String> {
+    let key = match req.template.take() {
+        Some(key) => key,
+        None => return Ok(()),
+    };
+    let template = templates::find(&key).await.ok_or_else(|| format!("Unknown prompt template {:?}", key))?;
+    req.variables.entry("message".to_string()).or_insert_with(|| req.message.clone());
+    req.message = templates::render(&template.template, &req.variables)?;
+    Ok(())
 }
 
-#[post("/chat")]
-pub async fn chat(req: web::Json<ChatRequest>) -> Result<HttpResponse, Error> {
+// Our hostname and outward-facing IP, as stamped on the messages we store
+async fn local_host_info() -> HostInfo {
     let hostname = hostname::get()
         .map(|h| h.to_string_lossy().to_string())
         .unwrap_or_else(|_| "Unknown".to_string());
     
-    let ip_address = std::net::TcpStream::connect("8.8.8.8:53")
-        .and_then(|s| s.local_addr())
-        .map(|addr| addr.ip().to_string())
+    let ip_address = crate::ip::primary_ip()
+        .map(|ip| ip.to_string())
         .unwrap_or_else(|_| "Unknown".to_string());
 
-    let host_info = HostInfo {
-        hostname: hostname.clone(),
-        ip_address: ip_address.clone(),
-        is_llm_host: is_local_ollama_available().await,
-    };
+    HostInfo {
+        hostname,
+        ip_address,
+        is_llm_host: backend::available().await,
+        user: None,
+    }
+}
 
+// The message with any attached file (or its transcript) prepended
+async fn build_prompt(req: &ChatRequest) -> String {
     // If filename is provided, load file content and prepend to prompt
     let mut prompt = req.message.clone();
-    if let Some(filename) = &req.filename {
+    // File content gets what the model's context leaves after the system prompt and the question
+    let settings = crate::settings::current().await;
+    let model = req.routing.model.as_deref().or(settings.routing.model.as_deref()).or(settings.backend.model.as_deref());
+    let budget = settings.context.budget(model, &req.options.or(&settings.generation));
+    let file_budget = tokens::file_budget(budget, DEFAULT_SYSTEM_PROMPT, &req.message)
+        .min(settings.context.max_file_tokens.map_or(usize::MAX, |n| n as usize));
+    let binary_preview = settings.context.binary_preview_bytes as usize;
+    // Audio files with a Whisper transcript are discussed through their transcript
+    let transcript = match &req.filename {
+        Some(filename) => crate::transcribe::load_transcript(filename).await,
+        None => None,
+    };
+    if let (Some(filename), Some(text)) = (&req.filename, &transcript) {
+        let preview = tokens::condense(text, file_budget);
+        prompt = format!("Transcript of audio file '{}':\n{}\n\n{}", filename, preview, req.message);
+    } else if let Some(filename) = &req.filename {
         match crate::persistence::get_file_content(filename).await {
             Ok(Some(content)) => {
                 // Safer handling: treat PDFs and unreadable binaries via base64 preview
@@ -252,7 +305,8 @@ pub async fn chat(req: web::Json<ChatRequest>) -> Result<HttpResponse, Error> {
                 if file_extension == "pdf" {
                     use base64::engine::general_purpose::STANDARD;
                     use base64::Engine;
-                    let preview_len = content.len().min(8 * 1024); // 8KB preview
+                    // base64 takes about 8 tokens per 15 bytes
+                    let preview_len = content.len().min(binary_preview).min(file_budget * 15 / 8);
                     let b64 = STANDARD.encode(&content[..preview_len]);
                     prompt = format!(
                         "You are analyzing a PDF file named '{}'. The following is a base64 preview of the first {} bytes. If exact content is needed, infer structure (title, sections, abstracts, headings) and provide a high-level analysis based on this preview.\n\n[PDF_BASE64_PREVIEW]\n{}\n[/PDF_BASE64_PREVIEW]\n\n{}",
@@ -267,11 +321,11 @@ pub async fn chat(req: web::Json<ChatRequest>) -> Result<HttpResponse, Error> {
                     if file_text.is_empty() || file_text.contains('\u{FFFD}') {
                         use base64::engine::general_purpose::STANDARD;
                         use base64::Engine;
-                        let preview_len = content.len().min(8 * 1024);
+                        let preview_len = content.len().min(binary_preview).min(file_budget * 15 / 8);
                         let b64 = STANDARD.encode(&content[..preview_len]);
                         prompt = format!("File '{}' appears binary. Base64 preview ({} bytes):\n{}\n\n{}", filename, preview_len, b64, req.message);
                     } else {
-                        let preview = if file_text.len() > 4000 { &file_text[..4000] } else { &file_text };
+                        let preview = tokens::condense(&file_text, file_budget);
                         prompt = format!("File content (analyzing file '{}'):\n{}\n\n{}", filename, preview, req.message);
                     }
                 }
@@ -284,28 +338,140 @@ pub async fn chat(req: web::Json<ChatRequest>) -> Result<HttpResponse, Error> {
             }
         }
     }
+    prompt
+}
+
+// Run a chat request end to end: build the prompt, store the question, query an LLM
+// (local first, then peers) and store the answer. Shared by the HTTP and gRPC surfaces.
+pub async fn run_chat(req: &ChatRequest) -> Result<ChatMessage, ChatError> {
+    let host_info = HostInfo { user: req.user.clone(), ..local_host_info().await };
+    let prompt = build_prompt(req).await;
+
+    // Continuing a fork: answer with its history and store the exchange there
+    let fork = match req.conversation_id.as_deref().filter(|id| *id != "local") {
+        Some(id) => Some(CONVERSATION_STORE.get_fork(id).await.ok_or_else(|| ChatError::Unavailable(format!("Fork {} not found", id)))?),
+        None => None,
+    };
+    let conversation_id = fork.as_ref().map(|f| f.id.clone()).unwrap_or_else(|| "local".to_string());
+    let history = fork.map(|f| f.messages).unwrap_or_default();
+
+    let settings = crate::settings::current().await;
+    let language = language::resolve(req.language.as_deref(), &req.message, &settings.language);
+    let route = routing::resolve(&req.routing, &settings.routing, req.target_peer.as_deref()).map_err(ChatError::Unavailable)?;
+
+    // Secrets never reach the model or the stored conversation
+    let (prompt, prompt_matches) = crate::redact::redact(&prompt).await;
+    if !prompt_matches.is_empty() {
+        println!("REDACT: Removed {} from a prompt by {}", prompt_matches.join(", "), req.sender);
+    }
+    let prompt_flags = moderate(Stage::Prompt, &prompt, &req.sender, &conversation_id).await?;
 
     // Create user question message
+    let question_id = crate::conversation::new_message_id();
     let question_message = ChatMessage {
+        id: question_id.clone(),
         content: prompt.clone(),
         timestamp: Utc::now(),
+        lamport: crate::clock::lamport_tick(),
         sender: req.sender.clone(),
         message_type: MessageType::Question,
         host_info: host_info.clone(),
+        attachment: req.filename.clone(),
+        alternative_of: None,
+        reply_to: req.reply_to.clone(),
+        preferred: false,
+        language: language.clone(),
+        redacted: !prompt_matches.is_empty(),
+        flags: prompt_flags,
+        model: None,
+        pinned: false,
+        stamps: Default::default(),
     };
 
     // Save the question
-    CONVERSATION_STORE.add_message("local".to_string(), question_message).await;
+    CONVERSATION_STORE.add_message(conversation_id.clone(), question_message).await;
+
+    let options = req.options.or(&settings.generation);
+    // A question the knowledge base already holds an answer to is answered from it; similar saved
+    // answers go to the model as context. Questions about a file are always asked.
+    let knowledge = match &req.filename {
+        Some(_) => crate::knowledge::Consulted::default(),
+        None => crate::knowledge::consult(&prompt).await,
+    };
+    crate::presence::set_local(&conversation_id, "LLM", Activity::Generating).await;
+    let response = match knowledge.answer {
+        Some(entry) => {
+            println!("KNOWLEDGE: Answered a question by {} from entry {}", req.sender, entry.id);
+            Ok(Answer { content: entry.answer, host_info: None, model: Some(crate::knowledge::MODEL.to_string()) })
+        }
+        None => {
+            let prompt = crate::knowledge::augment(&prompt, &knowledge.context);
+            generate(prompt, &history, &req.sender, &options, language.as_deref(), req.target_peer.as_deref(), &route).await
+        }
+    };
+    crate::presence::set_local(&conversation_id, "LLM", Activity::Idle).await;
+    let answer = response.map_err(ChatError::Unavailable)?;
+    let (response, response_matches) = crate::redact::redact(&answer.content).await;
+    let response_flags = moderate(Stage::Response, &response, &req.sender, &conversation_id).await?;
 
-    // Use llama2 model - Ollama will handle optimization automatically
-    let model_name = "llama2".to_string();
-    
-    let ollama_req = OllamaRequest {
-        model: model_name,
-        messages: vec![
-            OllamaMessage {
-                role: "system".to_string(),
-                content: "You are an expert file analysis assistant specializing in PDF and academic document analysis. Your capabilities include:
+    // Create response message with host info
+    let response_message = ChatMessage {
+        id: crate::conversation::new_message_id(),
+        content: response,
+        timestamp: Utc::now(),
+        lamport: crate::clock::lamport_tick(),
+        sender: "LLM".to_string(),
+        message_type: MessageType::Response,
+        host_info,
+        attachment: None,
+        alternative_of: None,
+        reply_to: Some(question_id),
+        preferred: false,
+        language: None,
+        redacted: !response_matches.is_empty(),
+        flags: response_flags,
+        model: answer.model,
+        pinned: false,
+        stamps: Default::default(),
+    };
+
+    // Save the response
+    CONVERSATION_STORE.add_message(conversation_id.clone(), response_message.clone()).await;
+    if let Some(fork) = CONVERSATION_STORE.get_fork(&conversation_id).await {
+        crate::tcp::broadcast_fork(&fork).await;
+    }
+    titles::spawn(&conversation_id);
+    crate::events::publish("chat", serde_json::json!({
+        "event": "response",
+        "sender": req.sender,
+        "question": crate::redact::redact(&req.message).await.0,
+        "answer": response_message.content,
+        "attachment": req.filename
+    }));
+
+    Ok(response_message)
+}
+
+// Answer a request without storing it in any conversation, for automated callers (pipelines).
+// The same redaction and moderation apply as for chat.
+pub async fn complete(req: &ChatRequest) -> Result<String, ChatError> {
+    let prompt = build_prompt(req).await;
+    let settings = crate::settings::current().await;
+    let language = language::resolve(req.language.as_deref(), &req.message, &settings.language);
+    let route = routing::resolve(&req.routing, &settings.routing, req.target_peer.as_deref()).map_err(ChatError::Unavailable)?;
+    let (prompt, _) = crate::redact::redact(&prompt).await;
+    moderate(Stage::Prompt, &prompt, &req.sender, "local").await?;
+    let options = req.options.or(&settings.generation);
+    let answer = generate(prompt, &[], &req.sender, &options, language.as_deref(), req.target_peer.as_deref(), &route)
+        .await
+        .map_err(ChatError::Unavailable)?;
+    let (response, _) = crate::redact::redact(&answer.content).await;
+    moderate(Stage::Response, &response, &req.sender, "local").await?;
+    Ok(response)
+}
+
+// System prompt used when no localized one is configured for the question's language
+const DEFAULT_SYSTEM_PROMPT: &str = "You are an expert file analysis assistant specializing in PDF and academic document analysis. Your capabilities include:
                 1. PDF Analysis: Extract and interpret key information from PDF content, focusing on academic and technical details
                 2. Research Paper Analysis: Identify methodology, findings, and conclusions
                 3. Technical Document Processing: Handle complex technical content and diagrams
@@ -318,61 +484,257 @@ pub async fn chat(req: web::Json<ChatRequest>) -> Result<HttpResponse, Error> {
                 - If content is incomplete, focus on visible patterns and structure
                 - For PDFs about neural networks or medical imaging, pay special attention to methodology and technical details
                 
-                Maintain a professional and technical tone, and be clear about any limitations in the analysis.".to_string(),
+                Maintain a professional and technical tone, and be clear about any limitations in the analysis.";
+
+// Earlier turns sent along when continuing a fork
+const MAX_HISTORY_MESSAGES: usize = 20;
+
+fn history_messages(history: &[ChatMessage]) -> Vec<PromptMessage> {
+    // Regenerated answers only count when they were picked as the preferred one
+    let turns: Vec<&ChatMessage> = history.iter().filter(|m| m.alternative_of.is_none() || m.preferred).collect();
+    turns[turns.len().saturating_sub(MAX_HISTORY_MESSAGES)..]
+        .iter()
+        .map(|m| PromptMessage {
+            role: match m.message_type {
+                MessageType::Question => "user".to_string(),
+                MessageType::Response => "assistant".to_string(),
             },
-            OllamaMessage {
-                role: "user".to_string(),
-                content: prompt,
-            }
-        ],
-    };
+            content: m.content.clone(),
+        })
+        .collect()
+}
 
-    // Check if we have local Ollama first
-    let has_local_llm = is_local_ollama_available().await;
-    
-    let response = if has_local_llm {
-        // Try local first if available
-        match try_local_llm(&ollama_req).await {
-            Ok(response) => response,
-            Err(local_error) => {
-                // If local fails, try remote
-                match try_remote_peer_chat(&ollama_req.messages.last().unwrap().content, &req.sender).await {
-                    Ok(response) => response,
-                    Err(remote_error) => {
-                        return Ok(HttpResponse::ServiceUnavailable()
-                            .json(serde_json::json!({
-                                "error": "No available LLM service",
-                                "details": format!("Local error: {}. Remote error: {}", local_error, remote_error)
-                            })));
-                    }
-                }
-            }
-        }
-    } else {
-        // No local LLM, try remote directly
-        match try_remote_peer_chat(&ollama_req.messages.last().unwrap().content, &req.sender).await {
-            Ok(response) => response,
-            Err(remote_error) => {
-                return Ok(HttpResponse::ServiceUnavailable()
-                    .json(serde_json::json!({
-                        "error": "No available LLM service",
-                        "details": format!("No local LLM available. Remote error: {}", remote_error)
-                    })));
+// Ask an LLM to answer a fully built prompt, after any earlier turns: the hosts `route` allows, in
+// its order, unless `target_peer` picks one
+async fn generate(prompt: String, history: &[ChatMessage], sender: &str, options: &GenerationOptions, language: Option<&str>, target_peer: Option<&str>, route: &Route) -> Result<Answer, String> {
+    let request = backend_request(prompt, history, options, language, route.model.as_deref()).await;
+    // A chosen peer answers itself; otherwise peers may pass the prompt on under their own routing
+    let forward = Routing { mode: target_peer.map(|_| RoutingMode::LocalOnly), model: route.model.clone() };
+
+    match target_peer {
+        Some(routing::LOCAL) => return try_local_llm(&request).await,
+        Some(peer) => return try_remote_peer_chat(peer, request.prompt(), sender, options, language, &forward).await,
+        None => {}
+    }
+
+    let has_local_llm = route.mode != RoutingMode::RemoteOnly && backend::available().await;
+    let peers = crate::tcp::llm_connections().into_iter().map(|(peer, _)| peer).collect();
+    let hosts = routing::order(route.mode, has_local_llm, peers).await;
+    if hosts.is_empty() {
+        return Err(match route.mode {
+            RoutingMode::LocalOnly => "No local LLM available and routing is local_only".to_string(),
+            RoutingMode::RemoteOnly => "No remote LLM connections available".to_string(),
+            _ => "No local LLM available and no remote LLM connections available".to_string(),
+        });
+    }
+    let mut errors = Vec::new();
+    for host in &hosts {
+        let answer = if host == routing::LOCAL {
+            try_local_llm(&request).await
+        } else {
+            try_remote_peer_chat(host, request.prompt(), sender, options, language, &forward).await
+        };
+        match answer {
+            Ok(answer) => return Ok(answer),
+            Err(e) => {
+                println!("LLM: {} could not answer: {}", host, e);
+                errors.push(format!("{}: {}", host, e));
             }
         }
-    };
+    }
+    Err(errors.join(". "))
+}
 
-    // Create response message with host info
-    let response_message = ChatMessage {
-        content: response.clone(),
+// The backend request for a fully built prompt: system prompt, earlier turns, then the prompt,
+// within the context budget of `model` (the configured one when None)
+async fn backend_request(prompt: String, history: &[ChatMessage], options: &GenerationOptions, language: Option<&str>, model: Option<&str>) -> BackendRequest {
+    let settings = crate::settings::current().await;
+    let mut messages = vec![
+        PromptMessage {
+            role: "system".to_string(),
+            content: language::system_prompt(language, DEFAULT_SYSTEM_PROMPT, &settings.language),
+        },
+    ];
+    messages.extend(history_messages(history));
+    messages.push(PromptMessage {
+        role: "user".to_string(),
+        content: prompt,
+    });
+    let budget = settings.context.budget(model.or(settings.backend.model.as_deref()), options);
+    let (dropped, condensed) = tokens::fit_messages(&mut messages, budget);
+    if dropped > 0 || condensed {
+        println!(
+            "LLM: Prompt over its {}-token budget; left out {} earlier messages{}",
+            budget,
+            dropped,
+            if condensed { " and condensed the prompt" } else { "" }
+        );
+    }
+    BackendRequest {
+        messages,
+        options: options.clone(),
+        model: model.map(str::to_string),
+    }
+}
+
+// Answer a question again with the same prompt (including any file context) and store the result
+// as an alternative linked to the question. `message_id` may be the question or one of its answers.
+pub async fn regenerate(message_id: &str) -> Result<Option<ChatMessage>, ChatError> {
+    let question = match CONVERSATION_STORE.get_local_conversation().await {
+        Some(conv) => match conv.question_for(message_id) {
+            Some(q) => q.clone(),
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    let settings = crate::settings::current().await;
+    let route = routing::resolve(&Routing::default(), &settings.routing, None).map_err(ChatError::Unavailable)?;
+    crate::presence::set_local("local", "LLM", Activity::Generating).await;
+    let response = generate(question.content.clone(), &[], &question.sender, &settings.generation, question.language.as_deref(), None, &route).await;
+    crate::presence::set_local("local", "LLM", Activity::Idle).await;
+    let answer = response.map_err(ChatError::Unavailable)?;
+    let (response, matches) = crate::redact::redact(&answer.content).await;
+    let flags = moderate(Stage::Response, &response, &question.sender, "local").await?;
+    let alternative = ChatMessage {
+        id: crate::conversation::new_message_id(),
+        content: response,
         timestamp: Utc::now(),
+        lamport: crate::clock::lamport_tick(),
         sender: "LLM".to_string(),
         message_type: MessageType::Response,
-        host_info,
+        host_info: HostInfo {
+            is_llm_host: backend::available().await,
+            ..question.host_info.clone()
+        },
+        attachment: None,
+        alternative_of: Some(question.id.clone()),
+        reply_to: Some(question.id.clone()),
+        preferred: false,
+        language: None,
+        redacted: !matches.is_empty(),
+        flags,
+        model: answer.model,
+        pinned: false,
+        stamps: Default::default(),
     };
+    CONVERSATION_STORE.add_message("local".to_string(), alternative.clone()).await;
+    crate::tcp::broadcast_local_conversation().await;
+    Ok(Some(alternative))
+}
 
-    // Save the response
-    CONVERSATION_STORE.add_message("local".to_string(), response_message.clone()).await;
+#[post("/messages/{id}/regenerate")]
+pub async fn regenerate_message(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    match regenerate(&path.into_inner()).await {
+        Ok(Some(message)) => Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "message": message }))),
+        Ok(None) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Message not found in the local conversation"
+        }))),
+        Err(e) => Ok(chat_error_response(e)),
+    }
+}
 
-    Ok(HttpResponse::Ok().json(response_message))
-}
\ No newline at end of file
+#[get("/messages/{id}/alternatives")]
+pub async fn list_alternatives(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let conv = CONVERSATION_STORE.get_local_conversation().await;
+    match conv.as_ref().and_then(|c| c.question_for(&id).map(|q| (q, c.answers_to(&q.id)))) {
+        Some((question, answers)) => {
+            let tallies = crate::votes::tallies().await;
+            let votes: std::collections::HashMap<&str, crate::votes::Tally> =
+                answers.iter().map(|a| (a.id.as_str(), tallies.get(&a.id).copied().unwrap_or_default())).collect();
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "question": question,
+                "best": crate::votes::best_answer(&answers, &tallies),
+                "votes": votes,
+                "answers": answers
+            })))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Message not found in the local conversation"
+        }))),
+    }
+}
+
+#[post("/messages/{id}/prefer")]
+pub async fn prefer_message(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    match CONVERSATION_STORE.update_local(|c| c.prefer_answer(&id)).await.flatten() {
+        Some(message) => {
+            crate::tcp::broadcast_local_conversation().await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "message": message })))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Answer not found in the local conversation"
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EditMessageRequest {
+    pub content: String,
+}
+
+// Change the text of a message in the local conversation; peers keep the newest edit
+#[put("/messages/{id}")]
+pub async fn edit_message(path: web::Path<String>, body: web::Json<EditMessageRequest>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let original = CONVERSATION_STORE
+        .get_local_conversation()
+        .await
+        .and_then(|c| c.messages.into_iter().find(|m| m.id == id));
+    let original = match original {
+        Some(m) => m,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "Message not found in the local conversation"
+            })));
+        }
+    };
+    if body.content.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": "Content must not be empty" })));
+    }
+    let (content, matches) = crate::redact::redact(&body.content).await;
+    let stage = match original.message_type {
+        MessageType::Question => Stage::Prompt,
+        MessageType::Response => Stage::Response,
+    };
+    let flags = match moderate(stage, &content, &original.sender, "local").await {
+        Ok(flags) => flags,
+        Err(e) => return Ok(chat_error_response(e)),
+    };
+    let redacted = !matches.is_empty();
+    match CONVERSATION_STORE.update_local(|c| c.edit_message(&id, content, redacted, flags)).await.flatten() {
+        Some(message) => {
+            crate::tcp::broadcast_local_conversation().await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "message": message })))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Message not found in the local conversation"
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PinMessageRequest {
+    pub pinned: bool,
+}
+
+#[post("/messages/{id}/pin")]
+pub async fn pin_message(path: web::Path<String>, body: web::Json<PinMessageRequest>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    match CONVERSATION_STORE.update_local(|c| c.pin_message(&id, body.pinned)).await.flatten() {
+        Some(message) => {
+            crate::tcp::broadcast_local_conversation().await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "message": message })))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Message not found in the local conversation"
+        }))),
+    }
+}
@@ -0,0 +1,185 @@
+// How LLM calls are attempted: a timeout per attempt, retries with exponential backoff and a
+// circuit breaker per target ("local" for this node's backend, else the peer's IP). After
+// `breaker_threshold` failed calls in a row the target is skipped for `breaker_cooldown_secs`;
+// the first call after that is a trial that either closes the circuit or opens it again. The
+// `llm_calls` settings hold one policy for the local backend and one for peers.
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+const MAX_TIMEOUT_SECS: u64 = 3600;
+const MAX_RETRIES: u32 = 5;
+const MAX_BACKOFF_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TargetPolicy {
+    // Per attempt, including the whole answer
+    pub timeout_secs: u64,
+    // Further attempts after a failed one
+    pub retries: u32,
+    // Wait before the first retry; doubled for each one after it
+    pub backoff_ms: u64,
+    // Failed calls in a row that open the circuit; 0 never opens it
+    pub breaker_threshold: u32,
+    pub breaker_cooldown_secs: u64,
+}
+
+impl TargetPolicy {
+    fn local_default() -> Self {
+        // A local model may take minutes on long prompts, but it is not worth retrying at once
+        TargetPolicy { timeout_secs: 300, retries: 0, backoff_ms: 500, breaker_threshold: 3, breaker_cooldown_secs: 30 }
+    }
+
+    fn remote_default() -> Self {
+        TargetPolicy { timeout_secs: 30, retries: 1, backoff_ms: 500, breaker_threshold: 3, breaker_cooldown_secs: 60 }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if !(1..=MAX_TIMEOUT_SECS).contains(&self.timeout_secs) {
+            return Err(format!("llm_calls.{}.timeout_secs must be between 1 and {}", name, MAX_TIMEOUT_SECS));
+        }
+        if self.retries > MAX_RETRIES {
+            return Err(format!("llm_calls.{}.retries must be at most {}", name, MAX_RETRIES));
+        }
+        if self.backoff_ms > MAX_BACKOFF_MS {
+            return Err(format!("llm_calls.{}.backoff_ms must be at most {}", name, MAX_BACKOFF_MS));
+        }
+        if self.breaker_threshold > 0 && !(1..=MAX_TIMEOUT_SECS).contains(&self.breaker_cooldown_secs) {
+            return Err(format!("llm_calls.{}.breaker_cooldown_secs must be between 1 and {}", name, MAX_TIMEOUT_SECS));
+        }
+        Ok(())
+    }
+}
+
+impl Default for TargetPolicy {
+    fn default() -> Self {
+        TargetPolicy::remote_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CallPolicy {
+    // This node's own backend
+    pub local: TargetPolicy,
+    // Each peer asked to answer
+    pub remote: TargetPolicy,
+}
+
+impl Default for CallPolicy {
+    fn default() -> Self {
+        CallPolicy { local: TargetPolicy::local_default(), remote: TargetPolicy::remote_default() }
+    }
+}
+
+impl CallPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        self.local.validate("local")?;
+        self.remote.validate("remote")
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    // Failed calls since the last success
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+static BREAKERS: Lazy<StdMutex<HashMap<String, Breaker>>> = Lazy::new(|| StdMutex::new(HashMap::new()));
+
+// Seconds until a target's open circuit lets a call through again; None when it is closed
+pub fn open_for(target: &str) -> Option<u64> {
+    let breakers = BREAKERS.lock().unwrap();
+    let until = breakers.get(target)?.open_until?;
+    let left = until.checked_duration_since(Instant::now())?;
+    Some(left.as_secs().max(1))
+}
+
+fn record(target: &str, policy: &TargetPolicy, ok: bool) {
+    let mut breakers = BREAKERS.lock().unwrap();
+    if ok {
+        breakers.remove(target);
+        return;
+    }
+    let breaker = breakers.entry(target.to_string()).or_default();
+    breaker.failures += 1;
+    if policy.breaker_threshold > 0 && breaker.failures >= policy.breaker_threshold {
+        breaker.open_until = Some(Instant::now() + Duration::from_secs(policy.breaker_cooldown_secs));
+        crate::events::warn(
+            crate::events::Category::Peer,
+            format!("LLM calls to {} paused for {}s after {} failures", target, policy.breaker_cooldown_secs, breaker.failures),
+            serde_json::json!({ "target": target, "failures": breaker.failures }),
+        );
+    }
+}
+
+// Run `attempt` against `target` under `policy`: refused while the target's circuit is open,
+// otherwise retried with backoff until it succeeds or the retries run out
+pub async fn call<T, F, Fut>(target: &str, policy: &TargetPolicy, mut attempt: F) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    if let Some(secs) = open_for(target) {
+        return Err(format!("LLM calls to {} are paused for {}s after repeated failures", target, secs));
+    }
+    let mut backoff = Duration::from_millis(policy.backoff_ms);
+    let mut tries = 0;
+    loop {
+        tries += 1;
+        let error = match tokio::time::timeout(policy.timeout(), attempt()).await {
+            Ok(Ok(value)) => {
+                record(target, policy, true);
+                return Ok(value);
+            }
+            Ok(Err(e)) => e,
+            Err(_) => format!("{} did not answer within {}s", target, policy.timeout_secs),
+        };
+        if tries > policy.retries {
+            record(target, policy, false);
+            return Err(error);
+        }
+        println!("LLM: Attempt {} at {} failed, retrying in {}ms: {}", tries, target, backoff.as_millis(), error);
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{call, open_for, TargetPolicy};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    // Failed attempts are retried, and after enough failed calls the target is skipped until the
+    // cooldown ends
+    #[tokio::test]
+    async fn llm_calls_retry_then_open_the_circuit() {
+        let policy = TargetPolicy { timeout_secs: 1, retries: 2, backoff_ms: 1, breaker_threshold: 2, breaker_cooldown_secs: 60 };
+        let attempts = AtomicU32::new(0);
+        let flaky = || async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err("busy".to_string()),
+                _ => Ok("answer"),
+            }
+        };
+        assert_eq!(call("198.51.100.31", &policy, flaky).await, Ok("answer"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let failing = || async { Err::<(), _>("down".to_string()) };
+        assert!(call("198.51.100.32", &policy, failing).await.is_err());
+        assert_eq!(open_for("198.51.100.32"), None);
+        assert!(call("198.51.100.32", &policy, failing).await.is_err());
+        assert!(open_for("198.51.100.32").is_some());
+        let refused = call("198.51.100.32", &policy, || async { Ok(()) }).await.expect_err("circuit open");
+        assert!(refused.contains("paused"), "{}", refused);
+    }
+}
@@ -0,0 +1,171 @@
+// Where a prompt may be answered. `cheapest` (the default) asks this node's model first, since it
+// costs the mesh nothing, then peers from least to most loaded; `fastest` asks whichever host has
+// answered quickest lately first; `remote_only` leaves our model free and `local_only` never lets a
+// prompt leave the machine. `model` names the one model that may answer: our backend and peers are
+// asked for it and answers from any other model are dropped. Each chat request may carry its own
+// `routing`; the node's `routing` setting fills in what it leaves out. A node set to `local_only`
+// keeps every prompt local, whatever the request asks for.
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+use super::Answer;
+
+// Name of this node's backend among the hosts to ask
+pub const LOCAL: &str = "local";
+const MAX_MODEL_NAME_LEN: usize = 256;
+// Weight of the newest answer time in a host's average
+const LATENCY_WEIGHT: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingMode {
+    #[default]
+    Cheapest,
+    Fastest,
+    LocalOnly,
+    RemoteOnly,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Routing {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<RoutingMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl Routing {
+    // What a peer is sent when it has to answer with its own model
+    pub fn local_only() -> Routing {
+        Routing { mode: Some(RoutingMode::LocalOnly), model: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mode.is_none() && self.model.is_none()
+    }
+
+    pub fn normalize(&mut self) {
+        self.model = self.model.take().map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.model {
+            Some(model) if model.trim().is_empty() || model.len() > MAX_MODEL_NAME_LEN => {
+                Err(format!("routing.model must be 1 to {} bytes", MAX_MODEL_NAME_LEN))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// A request's routing once the node's defaults are applied
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Route {
+    pub mode: RoutingMode,
+    pub model: Option<String>,
+}
+
+// Apply the node's routing to a request's, refusing requests that would break it or that
+// contradict their own `target_peer`
+pub fn resolve(request: &Routing, node: &Routing, target_peer: Option<&str>) -> Result<Route, String> {
+    let mode = request.mode.or(node.mode).unwrap_or_default();
+    if node.mode == Some(RoutingMode::LocalOnly) && mode != RoutingMode::LocalOnly {
+        return Err("This node's routing is local_only: prompts may not leave it".to_string());
+    }
+    match target_peer {
+        Some(LOCAL) if request.mode == Some(RoutingMode::RemoteOnly) => {
+            return Err("target_peer \"local\" contradicts routing remote_only".to_string());
+        }
+        Some(peer) if peer != LOCAL && mode == RoutingMode::LocalOnly => {
+            return Err(format!("Routing local_only keeps the prompt on this node; it cannot go to {}", peer));
+        }
+        _ => {}
+    }
+    let model = request.model.clone().or_else(|| node.model.clone()).map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+    Ok(Route { mode, model })
+}
+
+// Average time to a full answer per host (LOCAL or a peer IP)
+static LATENCY: Lazy<StdMutex<HashMap<String, Duration>>> = Lazy::new(|| StdMutex::new(HashMap::new()));
+
+pub fn observe(host: &str, took: Duration) {
+    let mut latency = LATENCY.lock().unwrap();
+    let average = match latency.get(host) {
+        Some(previous) => previous.mul_f64(1.0 - LATENCY_WEIGHT) + took.mul_f64(LATENCY_WEIGHT),
+        None => took,
+    };
+    latency.insert(host.to_string(), average);
+}
+
+pub fn latency(host: &str) -> Option<Duration> {
+    LATENCY.lock().unwrap().get(host).copied()
+}
+
+// Hosts to ask in turn under `mode`: LOCAL when our backend may answer, then `peers`
+pub async fn order(mode: RoutingMode, local: bool, peers: Vec<String>) -> Vec<String> {
+    let mut hosts = Vec::new();
+    if local && mode != RoutingMode::RemoteOnly {
+        hosts.push(LOCAL.to_string());
+    }
+    if mode != RoutingMode::LocalOnly {
+        hosts.extend(crate::telemetry::by_load(peers).await);
+    }
+    if mode == RoutingMode::Fastest {
+        // Stable, so hosts never timed keep the cheapest order after the timed ones
+        let latency = LATENCY.lock().unwrap();
+        hosts.sort_by_key(|host| latency.get(host).copied().unwrap_or(Duration::MAX));
+    }
+    hosts
+}
+
+// Drop answers not written by the model the route asks for; Ollama reports "name:latest" for "name"
+pub fn check_model(answer: Answer, wanted: Option<&str>) -> Result<Answer, String> {
+    let Some(wanted) = wanted else {
+        return Ok(answer);
+    };
+    let base = |name: &str| name.strip_suffix(":latest").unwrap_or(name).to_string();
+    match answer.model.as_deref() {
+        Some(model) if base(model) == base(wanted) => Ok(answer),
+        Some(model) => Err(format!("Answered by {} rather than {}", model, wanted)),
+        None => Err(format!("Could not confirm the answer came from {}", wanted)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_model, observe, order, resolve, Answer, Routing, RoutingMode, LOCAL};
+    use std::time::Duration;
+
+    // Requests fill in the node's routing but may not send prompts away from a local_only node; the
+    // fastest mode puts hosts that answered quickest first
+    #[tokio::test]
+    async fn routing_applies_node_defaults_and_privacy() {
+        let node = Routing { mode: None, model: Some("mistral".into()) };
+        let route = resolve(&Routing { mode: Some(RoutingMode::Fastest), model: None }, &node, None).expect("route");
+        assert_eq!((route.mode, route.model.as_deref()), (RoutingMode::Fastest, Some("mistral")));
+        assert_eq!(resolve(&Routing::default(), &Routing::default(), None).expect("route").mode, RoutingMode::Cheapest);
+
+        let private = Routing { mode: Some(RoutingMode::LocalOnly), model: None };
+        assert_eq!(resolve(&Routing::default(), &private, None).expect("route").mode, RoutingMode::LocalOnly);
+        assert!(resolve(&Routing { mode: Some(RoutingMode::RemoteOnly), model: None }, &private, None).is_err());
+        assert!(resolve(&Routing::default(), &private, Some("198.51.100.41")).is_err());
+        assert!(resolve(&Routing { mode: Some(RoutingMode::RemoteOnly), model: None }, &Routing::default(), Some(LOCAL)).is_err());
+
+        observe("198.51.100.42", Duration::from_millis(900));
+        observe("198.51.100.43", Duration::from_millis(200));
+        let peers = ["198.51.100.42", "198.51.100.43", "198.51.100.44"].map(String::from).to_vec();
+        assert_eq!(order(RoutingMode::Fastest, false, peers.clone()).await, ["198.51.100.43", "198.51.100.42", "198.51.100.44"]);
+        assert_eq!(order(RoutingMode::Cheapest, true, peers.clone()).await[0], LOCAL);
+        assert_eq!(order(RoutingMode::LocalOnly, true, peers.clone()).await, [LOCAL]);
+        assert!(!order(RoutingMode::RemoteOnly, true, peers).await.contains(&LOCAL.to_string()));
+
+        let answer = |model: Option<&str>| Answer { content: "a".into(), host_info: None, model: model.map(String::from) };
+        assert!(check_model(answer(Some("mistral:latest")), Some("mistral")).is_ok());
+        assert!(check_model(answer(Some("llama2")), Some("mistral")).is_err());
+        assert!(check_model(answer(None), Some("mistral")).is_err());
+        assert!(check_model(answer(None), None).is_ok());
+    }
+}
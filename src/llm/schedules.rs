@@ -0,0 +1,361 @@
+// Scheduled prompts: recurring LLM tasks at cron expressions (local time), e.g. a weekday standup
+// digest at "0 9 * * 1-5" over {{recent}}, the messages since the previous run. Each run is a job;
+// the answer is written into the schedule's conversation (ours or one of our forks) and optionally
+// POSTed to a webhook. Schedules live in prompt_schedules.json and are managed via /api/llm/schedules.
+use actix_web::{delete, get, post, web, HttpResponse, Error};
+use chrono::{DateTime, Local, Utc};
+use croner::Cron;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use crate::conversation::{ChatMessage, CONVERSATION_STORE, MessageType};
+use super::{complete, local_host_info, templates, titles, ChatRequest};
+
+const SCHEDULES_FILE: &str = "prompt_schedules.json";
+const SCHEDULER_TICK: Duration = Duration::from_secs(30);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(15);
+// Placeholders a scheduled prompt may use
+const VARIABLES: [&str; 2] = ["date", "recent"];
+// {{recent}} is cut to this many characters, keeping the newest messages
+const MAX_RECENT_CHARS: usize = 12_000;
+// How far back {{recent}} reaches on a schedule's first run
+const FIRST_RUN_LOOKBACK_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub name: String,
+    // Five-field cron expression in local time, e.g. "0 9 * * 1-5"
+    pub cron: String,
+    pub prompt: String,
+    // "local" or one of our fork ids
+    #[serde(default = "default_conversation")]
+    pub conversation_id: String,
+    // Receives {"schedule", "name", "ran_at", "answer"} as JSON after each run
+    #[serde(default)]
+    pub webhook: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_job_id: Option<u64>,
+}
+
+fn default_conversation() -> String { "local".to_string() }
+fn default_enabled() -> bool { true }
+
+#[derive(Deserialize)]
+pub struct NewSchedule {
+    name: String,
+    cron: String,
+    prompt: String,
+    #[serde(default = "default_conversation")]
+    conversation_id: String,
+    #[serde(default)]
+    webhook: Option<String>,
+}
+
+lazy_static! {
+    static ref SCHEDULES: Arc<Mutex<Option<Vec<Schedule>>>> = Arc::new(Mutex::new(None));
+}
+
+async fn load() -> Vec<Schedule> {
+    match tokio::fs::read_to_string(SCHEDULES_FILE).await {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("LLM: Failed to parse {}: {}", SCHEDULES_FILE, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn save(schedules: &[Schedule]) {
+    match serde_json::to_string_pretty(schedules) {
+        Ok(s) => {
+            if let Err(e) = tokio::fs::write(SCHEDULES_FILE, s).await {
+                eprintln!("LLM: Failed to save {}: {}", SCHEDULES_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("LLM: Failed to serialize schedules: {}", e),
+    }
+}
+
+// Run `f` on the loaded schedules, saving them afterwards when `f` reports a change
+async fn with_schedules<R>(f: impl FnOnce(&mut Vec<Schedule>) -> (R, bool)) -> R {
+    let mut guard = SCHEDULES.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    let schedules = guard.get_or_insert_with(Vec::new);
+    let (result, changed) = f(schedules);
+    if changed {
+        save(schedules).await;
+    }
+    result
+}
+
+fn parse_cron(expr: &str) -> Result<Cron, String> {
+    Cron::new(expr.trim()).parse().map_err(|e| format!("invalid cron expression {:?}: {}", expr, e))
+}
+
+// When the schedule is next due: the first cron time after its last run (or creation)
+fn next_run(schedule: &Schedule) -> Option<DateTime<Utc>> {
+    let cron = parse_cron(&schedule.cron).ok()?;
+    let after = schedule.last_run.unwrap_or(schedule.created_at).with_timezone(&Local);
+    cron.find_next_occurrence(&after, false).ok().map(|t| t.with_timezone(&Utc))
+}
+
+async fn validate(body: &NewSchedule) -> Result<(), String> {
+    if body.name.trim().is_empty() || body.prompt.trim().is_empty() {
+        return Err("name and prompt are required".to_string());
+    }
+    parse_cron(&body.cron)?;
+    let unknown: Vec<String> = templates::placeholders(&body.prompt)?.into_iter().filter(|p| !VARIABLES.contains(&p.as_str())).collect();
+    if !unknown.is_empty() {
+        return Err(format!("prompt may only use {{{{date}}}} and {{{{recent}}}}, not {}", unknown.join(", ")));
+    }
+    if body.conversation_id != "local" && CONVERSATION_STORE.get_fork(&body.conversation_id).await.is_none() {
+        return Err(format!("conversation_id must be \"local\" or one of our forks, not {:?}", body.conversation_id));
+    }
+    if let Some(url) = &body.webhook {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err("webhook must be an http(s) URL".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Messages from every conversation since `since`, oldest first, leaving out earlier scheduled runs
+async fn recent_messages(since: DateTime<Utc>) -> String {
+    let mut lines: Vec<(DateTime<Utc>, String)> = Vec::new();
+    // Forks repeat their parent's messages
+    let mut seen: HashSet<String> = HashSet::new();
+    for conversation in CONVERSATION_STORE.all_conversations().await {
+        for m in conversation.messages.iter().filter(|m| m.timestamp > since) {
+            if !seen.insert(m.id.clone()) {
+                continue;
+            }
+            let from_schedule = conversation.question_for(&m.id).is_some_and(|q| q.sender.starts_with("schedule:"));
+            if from_schedule {
+                continue;
+            }
+            let time = m.timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+            lines.push((m.timestamp, format!("[{}] {} ({}): {}", time, m.sender, m.host_info.hostname, m.content)));
+        }
+    }
+    lines.sort_by_key(|(t, _)| *t);
+    let mut out = String::new();
+    for (_, line) in lines.iter().rev() {
+        if out.chars().count() + line.chars().count() > MAX_RECENT_CHARS {
+            break;
+        }
+        out = format!("{}\n{}", line, out);
+    }
+    if out.is_empty() { "(no messages)".to_string() } else { out }
+}
+
+async fn run(schedule: Schedule, previous_run: Option<DateTime<Utc>>) -> Result<String, String> {
+    let since = previous_run.unwrap_or_else(|| Utc::now() - chrono::Duration::hours(FIRST_RUN_LOOKBACK_HOURS));
+    let mut values = HashMap::from([("date".to_string(), Local::now().format("%Y-%m-%d").to_string())]);
+    if templates::placeholders(&schedule.prompt)?.iter().any(|p| p == "recent") {
+        values.insert("recent".to_string(), recent_messages(since).await);
+    }
+    let sender = format!("schedule:{}", schedule.name);
+    let req = ChatRequest {
+        message: templates::render(&schedule.prompt, &values)?,
+        sender: sender.clone(),
+        ..Default::default()
+    };
+    let answer = complete(&req).await.map_err(|e| e.to_string())?;
+    let ran_at = Utc::now();
+
+    // The stored question is the schedule's prompt as written; {{recent}} can be long
+    let host_info = local_host_info().await;
+    let question = ChatMessage {
+        id: crate::conversation::new_message_id(),
+        content: schedule.prompt.clone(),
+        timestamp: ran_at,
+        lamport: crate::clock::lamport_tick(),
+        sender,
+        message_type: MessageType::Question,
+        host_info: host_info.clone(),
+        attachment: None,
+        alternative_of: None,
+        reply_to: None,
+        preferred: false,
+        language: None,
+        redacted: false,
+        flags: Vec::new(),
+        model: None,
+        pinned: false,
+        stamps: Default::default(),
+    };
+    let response = ChatMessage {
+        id: crate::conversation::new_message_id(),
+        content: answer.clone(),
+        timestamp: ran_at,
+        lamport: crate::clock::lamport_tick(),
+        sender: "LLM".to_string(),
+        message_type: MessageType::Response,
+        host_info,
+        attachment: None,
+        alternative_of: None,
+        reply_to: Some(question.id.clone()),
+        preferred: false,
+        language: None,
+        redacted: false,
+        flags: Vec::new(),
+        model: None,
+        pinned: false,
+        stamps: Default::default(),
+    };
+    CONVERSATION_STORE.add_message(schedule.conversation_id.clone(), question).await;
+    CONVERSATION_STORE.add_message(schedule.conversation_id.clone(), response).await;
+    match CONVERSATION_STORE.get_fork(&schedule.conversation_id).await {
+        Some(fork) => crate::tcp::broadcast_fork(&fork).await,
+        None => crate::tcp::broadcast_local_conversation().await,
+    }
+    titles::spawn(&schedule.conversation_id);
+
+    let summary = format!("wrote {} chars to {}", answer.len(), schedule.conversation_id);
+    let url = match &schedule.webhook {
+        Some(url) => url,
+        None => return Ok(summary),
+    };
+    let payload = serde_json::json!({ "schedule": schedule.id, "name": schedule.name, "ran_at": ran_at, "answer": answer });
+    match crate::client::external().post(url).timeout(WEBHOOK_TIMEOUT).json(&payload).send().await {
+        Ok(resp) if resp.status().is_success() => Ok(format!("{}; delivered to webhook", summary)),
+        Ok(resp) => Err(format!("{}; webhook returned {}", summary, resp.status())),
+        Err(e) => Err(format!("{}; webhook failed: {}", summary, e)),
+    }
+}
+
+// Mark the schedule as run now and queue the run as a job
+async fn start(schedule: &mut Schedule) -> u64 {
+    let previous_run = schedule.last_run;
+    schedule.last_run = Some(Utc::now());
+    let description = format!("scheduled prompt '{}'", schedule.name);
+    let job_id = crate::jobs::submit("schedule", description, run(schedule.clone(), previous_run)).await;
+    schedule.last_job_id = Some(job_id);
+    job_id
+}
+
+pub async fn scheduler() {
+    let mut interval = tokio::time::interval(SCHEDULER_TICK);
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+        let due: Vec<Schedule> = with_schedules(|schedules| {
+            let due = schedules.iter().filter(|s| s.enabled && next_run(s).is_some_and(|next| next <= now)).cloned().collect();
+            (due, false)
+        })
+        .await;
+        for mut schedule in due {
+            let job_id = start(&mut schedule).await;
+            with_schedules(|schedules| match schedules.iter_mut().find(|s| s.id == schedule.id) {
+                Some(s) => {
+                    s.last_run = schedule.last_run;
+                    s.last_job_id = Some(job_id);
+                    ((), true)
+                }
+                None => ((), false),
+            })
+            .await;
+        }
+    }
+}
+
+fn with_next_run(schedule: &Schedule) -> serde_json::Value {
+    let mut value = serde_json::to_value(schedule).unwrap_or_default();
+    value["next_run"] = serde_json::json!(if schedule.enabled { next_run(schedule) } else { None });
+    value
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can manage scheduled prompts" }))
+}
+
+#[get("/llm/schedules")]
+pub async fn list_schedules() -> Result<HttpResponse, Error> {
+    let schedules = with_schedules(|s| (s.clone(), false)).await;
+    Ok(HttpResponse::Ok().json(schedules.iter().map(with_next_run).collect::<Vec<_>>()))
+}
+
+#[post("/llm/schedules")]
+pub async fn create_schedule(body: web::Json<NewSchedule>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let body = body.into_inner();
+    if let Err(message) = validate(&body).await {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": message })));
+    }
+    let schedule = Schedule {
+        id: format!("sched-{}", Utc::now().timestamp_millis()),
+        name: body.name.trim().to_string(),
+        cron: body.cron.trim().to_string(),
+        prompt: body.prompt,
+        conversation_id: body.conversation_id,
+        webhook: body.webhook,
+        enabled: true,
+        created_at: Utc::now(),
+        last_run: None,
+        last_job_id: None,
+    };
+    with_schedules(|s| {
+        s.push(schedule.clone());
+        ((), true)
+    })
+    .await;
+    println!("LLM: Added schedule {} ({}) at \"{}\"", schedule.id, schedule.name, schedule.cron);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "schedule": with_next_run(&schedule) })))
+}
+
+#[delete("/llm/schedules/{id}")]
+pub async fn delete_schedule(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let removed = with_schedules(|s| {
+        let before = s.len();
+        s.retain(|s| s.id != id);
+        let removed = s.len() != before;
+        (removed, removed)
+    })
+    .await;
+    if removed {
+        Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Schedule not found" })))
+    }
+}
+
+// Run a schedule immediately, outside its cron times
+#[post("/llm/schedules/{id}/run")]
+pub async fn run_schedule_now(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let mut schedule = match with_schedules(|s| (s.iter().find(|s| s.id == id).cloned(), false)).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Schedule not found" }))),
+    };
+    let job_id = start(&mut schedule).await;
+    with_schedules(|s| match s.iter_mut().find(|s| s.id == id) {
+        Some(s) => {
+            s.last_run = schedule.last_run;
+            s.last_job_id = Some(job_id);
+            ((), true)
+        }
+        None => ((), false),
+    })
+    .await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
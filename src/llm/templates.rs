@@ -0,0 +1,213 @@
+// Prompt templates with {{variable}} placeholders, stored in prompt_templates.json. A chat request
+// naming a template has it rendered with the supplied variables (plus {{message}}) before it is
+// sent to the LLM, e.g. "Summarize {{file}} as {{count}} bullet points".
+use actix_web::{delete, get, post, put, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const TEMPLATES_FILE: &str = "prompt_templates.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub template: String,
+    // Placeholder names found in the template, in order of first use
+    #[serde(default)]
+    pub variables: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct TemplateBody {
+    name: String,
+    #[serde(default)]
+    description: String,
+    template: String,
+}
+
+lazy_static! {
+    static ref TEMPLATES: Arc<Mutex<Option<Vec<PromptTemplate>>>> = Arc::new(Mutex::new(None));
+}
+
+fn valid_variable(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Placeholder names in order of first use; errors on unterminated or malformed placeholders
+pub fn placeholders(template: &str) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| "unterminated {{ placeholder".to_string())?;
+        let name = after[..end].trim();
+        if !valid_variable(name) {
+            return Err(format!("invalid placeholder name {:?}", name));
+        }
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+        rest = &after[end + 2..];
+    }
+    Ok(names)
+}
+
+// Substitute every placeholder; all of them must have a value
+pub fn render(template: &str, values: &HashMap<String, String>) -> Result<String, String> {
+    let missing: Vec<String> = placeholders(template)?.into_iter().filter(|n| !values.contains_key(n)).collect();
+    if !missing.is_empty() {
+        return Err(format!("missing template variables: {}", missing.join(", ")));
+    }
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").unwrap_or(after.len());
+        out.push_str(&values[after[..end].trim()]);
+        rest = after.get(end + 2..).unwrap_or("");
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+async fn load() -> Vec<PromptTemplate> {
+    match tokio::fs::read_to_string(TEMPLATES_FILE).await {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("LLM: Failed to parse {}: {}", TEMPLATES_FILE, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn save(templates: &[PromptTemplate]) -> std::io::Result<()> {
+    tokio::fs::write(TEMPLATES_FILE, serde_json::to_string_pretty(templates)?).await
+}
+
+// Run `f` on the loaded template list
+async fn with_templates<R>(f: impl FnOnce(&mut Vec<PromptTemplate>) -> R) -> R {
+    let mut guard = TEMPLATES.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    f(guard.get_or_insert_with(Vec::new))
+}
+
+// Look a template up by id or name
+pub async fn find(key: &str) -> Option<PromptTemplate> {
+    with_templates(|t| t.iter().find(|t| t.id == key || t.name == key).cloned()).await
+}
+
+fn validate(body: &TemplateBody) -> Result<Vec<String>, String> {
+    if body.name.trim().is_empty() || body.template.trim().is_empty() {
+        return Err("name and template are required".to_string());
+    }
+    placeholders(&body.template)
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": message }))
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can change prompt templates" }))
+}
+
+#[get("/llm/templates")]
+pub async fn list_templates() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(with_templates(|t| t.clone()).await))
+}
+
+#[post("/llm/templates")]
+pub async fn create_template(body: web::Json<TemplateBody>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let body = body.into_inner();
+    let variables = match validate(&body) {
+        Ok(v) => v,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let template = PromptTemplate {
+        id: format!("tpl-{}", Utc::now().timestamp_millis()),
+        name: body.name.trim().to_string(),
+        description: body.description,
+        template: body.template,
+        variables,
+        created_at: Utc::now(),
+    };
+    let result = with_templates(|list| {
+        if list.iter().any(|t| t.name == template.name) {
+            return Err(format!("a template named {:?} already exists", template.name));
+        }
+        list.push(template.clone());
+        Ok(list.clone())
+    })
+    .await;
+    match result {
+        Ok(list) => {
+            save(&list).await?;
+            println!("LLM: Added prompt template {} ({})", template.id, template.name);
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "template": template })))
+        }
+        Err(e) => Ok(bad_request(e)),
+    }
+}
+
+#[put("/llm/templates/{id}")]
+pub async fn update_template(path: web::Path<String>, body: web::Json<TemplateBody>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let body = body.into_inner();
+    let variables = match validate(&body) {
+        Ok(v) => v,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let updated = with_templates(|list| {
+        let t = list.iter_mut().find(|t| t.id == id)?;
+        t.name = body.name.trim().to_string();
+        t.description = body.description;
+        t.template = body.template;
+        t.variables = variables;
+        Some((t.clone(), list.clone()))
+    })
+    .await;
+    match updated {
+        Some((template, list)) => {
+            save(&list).await?;
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "template": template })))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Template not found" }))),
+    }
+}
+
+#[delete("/llm/templates/{id}")]
+pub async fn delete_template(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let removed = with_templates(|list| {
+        let before = list.len();
+        list.retain(|t| t.id != id);
+        (before != list.len()).then(|| list.clone())
+    })
+    .await;
+    match removed {
+        Some(list) => {
+            save(&list).await?;
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true })))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Template not found" }))),
+    }
+}
@@ -0,0 +1,89 @@
+// Cutting text for prompts, titles and previews without breaking characters apart. Strings are
+// only ever cut at char boundaries, and not inside what a reader sees as one character: an
+// accent combined with its letter, an emoji sequence joined with ZWJ or carrying a skin tone or
+// variation selector, or a flag made of two regional indicators.
+fn is_extender(c: char) -> bool {
+    matches!(c as u32,
+        0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0xFE20..=0xFE2F // combining marks
+        | 0xFE00..=0xFE0F | 0xE0100..=0xE01EF // variation selectors
+        | 0x1F3FB..=0x1F3FF // skin tones
+        | 0x200D // zero width joiner
+        | 0xE0020..=0xE007F // emoji tag sequences
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    (0x1F1E6..=0x1F1FF).contains(&(c as u32))
+}
+
+// Whether `text` may be cut before byte `i` (a char boundary) without splitting a character
+pub fn is_cluster_boundary(text: &str, i: usize) -> bool {
+    if i == 0 || i >= text.len() {
+        return true;
+    }
+    let (before, after) = text.split_at(i);
+    let (Some(prev), Some(next)) = (before.chars().next_back(), after.chars().next()) else {
+        return true;
+    };
+    if is_extender(next) || prev == '\u{200D}' {
+        return false;
+    }
+    // Flags pair up regional indicators from the start of the run
+    if is_regional_indicator(prev) && is_regional_indicator(next) {
+        return before.chars().rev().take_while(|c| is_regional_indicator(*c)).count() % 2 == 0;
+    }
+    true
+}
+
+// The longest start of `text` of at most `max` chars that ends between characters
+pub fn truncate_chars(text: &str, max: usize) -> &str {
+    let Some((mut end, _)) = text.char_indices().nth(max) else {
+        return text;
+    };
+    while end > 0 && !is_cluster_boundary(text, end) {
+        end = text[..end].char_indices().next_back().map_or(0, |(i, _)| i);
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_cluster_boundary, truncate_chars};
+    use crate::llm::tokens::{condense, estimate, truncate, ContextSettings};
+
+    // Prompts, titles and previews are cut between characters: never inside an accent, an emoji
+    // sequence or a flag, however long the unbroken text
+    #[test]
+    fn prompt_slicing_keeps_characters_whole() {
+        let accents = "e\u{301}e\u{301}";
+        assert_eq!(truncate_chars(accents, 1), "");
+        assert_eq!(truncate_chars(accents, 3), "e\u{301}");
+        assert_eq!(truncate_chars(accents, 4), accents);
+        let family = "a\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}b";
+        assert_eq!(truncate_chars(family, 3), "a");
+        assert_eq!(truncate_chars(family, 6), "a\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}");
+        let flags = "\u{1F1EF}\u{1F1F5}\u{1F1EB}\u{1F1F7}";
+        assert_eq!(truncate_chars(flags, 3), "\u{1F1EF}\u{1F1F5}");
+        assert!(!is_cluster_boundary(flags, 4) && is_cluster_boundary(flags, 8));
+        assert_eq!(truncate_chars("日本語のテキスト", 2), "日本");
+        assert_eq!(crate::llm::titles::clean(&"\u{1F44D}\u{1F3FD}".repeat(40)).unwrap().chars().count(), 60);
+
+        // One long line with no breaks to condense along
+        let line = format!("{}{}", "e\u{301}".repeat(3000), "\u{1F1EF}\u{1F1F5}".repeat(500));
+        let cut = truncate(&line, 100);
+        assert!(estimate(cut) <= 100 && cut.ends_with('\u{301}'));
+        let condensed = condense(&line, 200);
+        assert!(estimate(&condensed) <= 200 && condensed.ends_with('\u{301}'));
+        let cjk = "中文文本".repeat(1000);
+        let cut = truncate(&cjk, 50);
+        assert!(cut.chars().all(|c| "中文本".contains(c)) && estimate(cut) <= 50 && !cut.is_empty());
+
+        let mut settings = ContextSettings { binary_preview_bytes: 0, ..Default::default() };
+        assert!(settings.validate().is_err());
+        settings.binary_preview_bytes = 4096;
+        settings.max_file_tokens = Some(0);
+        assert!(settings.validate().is_err());
+        settings.max_file_tokens = Some(1000);
+        assert!(settings.validate().is_ok());
+    }
+}
@@ -0,0 +1,111 @@
+// Conversation titles: once one of our conversations ("local" or a fork) has its first exchange,
+// the LLM is asked in the background for a few-word title. It is stored on the conversation, so it
+// shows up wherever conversations are listed and reaches peers with the next sync. A fork gets its
+// own title from its first exchange after the fork point. AUTO_TITLES=0 turns this off; titles
+// can always be set by hand with PUT /api/conversations/{id}/title.
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::sync::Mutex as StdMutex;
+use crate::conversation::{Conversation, MessageType, CONVERSATION_STORE};
+use crate::settings::GenerationOptions;
+use super::{complete, ChatRequest};
+
+pub const MAX_TITLE_CHARS: usize = 60;
+// How much of the question and the answer the title prompt quotes
+const EXCERPT_CHARS: usize = 1000;
+
+// Conversations a title is being generated for, so a burst of answers asks only once
+static PENDING: Lazy<StdMutex<HashSet<String>>> = Lazy::new(|| StdMutex::new(HashSet::new()));
+
+fn enabled() -> bool {
+    !matches!(
+        std::env::var("AUTO_TITLES").ok().as_deref().map(|v| v.trim().to_lowercase()).as_deref(),
+        Some("0" | "false" | "off")
+    )
+}
+
+// One line of plain text from whatever the model answered: no "Title:" prefix, quotes, markdown
+// or trailing full stop, whitespace collapsed and cut to MAX_TITLE_CHARS. None if nothing is left.
+pub fn clean(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line
+        .strip_prefix("Title:")
+        .or_else(|| line.strip_prefix("title:"))
+        .unwrap_or(line)
+        .trim_start_matches(|c: char| matches!(c, '"' | '\'' | '*' | '#' | '`') || c.is_whitespace())
+        .trim_end_matches(|c: char| matches!(c, '"' | '\'' | '*' | '`' | '.') || c.is_whitespace());
+    let title = line.split_whitespace().collect::<Vec<_>>().join(" ");
+    let title = super::text::truncate_chars(&title, MAX_TITLE_CHARS).trim_end();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+fn excerpt(text: &str) -> String {
+    super::text::truncate_chars(text, EXCERPT_CHARS).to_string()
+}
+
+// The first question with an answer, after the fork point for forks
+fn first_exchange(conversation: &Conversation) -> Option<(String, String)> {
+    let start = conversation
+        .forked_from
+        .as_ref()
+        .and_then(|origin| conversation.messages.iter().position(|m| m.id == origin.message_id))
+        .map_or(0, |pos| pos + 1);
+    let messages = &conversation.messages[start..];
+    messages.iter().filter(|m| matches!(m.message_type, MessageType::Question)).find_map(|q| {
+        let answer = conversation.answers_to(&q.id).into_iter().next()?;
+        Some((q.content.clone(), answer.content.clone()))
+    })
+}
+
+// Title one of our conversations in the background if it has none yet
+pub fn spawn(conversation_id: &str) {
+    if !enabled() || !PENDING.lock().unwrap().insert(conversation_id.to_string()) {
+        return;
+    }
+    let id = conversation_id.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = generate(&id).await {
+            eprintln!("TITLES: Could not title conversation {}: {}", id, e);
+        }
+        PENDING.lock().unwrap().remove(&id);
+    });
+}
+
+async fn generate(id: &str) -> Result<(), String> {
+    let Some(conversation) = CONVERSATION_STORE.get_conversation(id).await else {
+        return Ok(());
+    };
+    if conversation.title.is_some() {
+        return Ok(());
+    }
+    let Some((question, answer)) = first_exchange(&conversation) else {
+        return Ok(());
+    };
+    let req = ChatRequest {
+        message: format!(
+            "Write a short title (at most 6 words) for a conversation that starts with this exchange. \
+             Reply with the title only, no quotes or punctuation at the end.\n\nQuestion: {}\n\nAnswer: {}",
+            excerpt(&question),
+            excerpt(&answer)
+        ),
+        sender: "titles".to_string(),
+        options: GenerationOptions { temperature: Some(0.2), max_tokens: Some(24), ..Default::default() },
+        ..Default::default()
+    };
+    let raw = complete(&req).await.map_err(|e| e.to_string())?;
+    let title = clean(&raw).ok_or_else(|| "the model returned an empty title".to_string())?;
+    // Named by hand while the model was thinking
+    if CONVERSATION_STORE.get_conversation(id).await.is_some_and(|c| c.title.is_some()) {
+        return Ok(());
+    }
+    let Some(conversation) = CONVERSATION_STORE.set_title(id, Some(title.clone())).await else {
+        return Ok(());
+    };
+    println!("TITLES: Conversation {} is \"{}\"", id, title);
+    if id == "local" {
+        crate::tcp::broadcast_local_conversation().await;
+    } else {
+        crate::tcp::broadcast_fork(&conversation).await;
+    }
+    Ok(())
+}
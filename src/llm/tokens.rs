@@ -0,0 +1,350 @@
+// Token estimates and context budgets. Prompts are sized in tokens rather than characters: text is
+// split the way BPE tokenizers pre-tokenize it (runs of letters, digits, punctuation and spaces)
+// and each piece is counted by how such pieces usually tokenize, which lands within about 10% of
+// real tokenizers for prose and code. Every model has a context window, from `context.windows` in
+// the settings (longest matching name prefix) or the request's num_ctx, and a prompt may fill it
+// up to what is reserved for the answer. File content that does not fit is condensed to its start,
+// its end and the headings in between; earlier turns are dropped oldest first.
+use actix_web::{post, web, Error, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::settings::GenerationOptions;
+use super::backend::PromptMessage;
+
+// Per message: role and separators in the chat template
+const MESSAGE_OVERHEAD: usize = 4;
+// Share of the start and the end of condensed text; the rest is for the outline of the middle
+const HEAD_SHARE: usize = 60;
+const TAIL_SHARE: usize = 25;
+// The "[... N lines left out ...]" note condensed text carries
+const NOTE_TOKENS: usize = 32;
+// Share of what the system prompt and question leave that file content may take; the rest is
+// for earlier turns
+const FILE_SHARE: usize = 80;
+const MAX_BINARY_PREVIEW_BYTES: u32 = 1024 * 1024;
+// The lines around file content in a prompt ("File content (analyzing file ...)")
+const FILE_FRAMING_TOKENS: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextSettings {
+    // Context window in tokens by model name prefix, e.g. {"llama3": 8192}; the longest match wins
+    pub windows: BTreeMap<String, u32>,
+    // Window of models not listed
+    pub default_window: u32,
+    // Tokens kept free for the answer when the request sets no max_tokens
+    pub reserve_tokens: u32,
+    // Most tokens of a file or transcript a prompt quotes; unset quotes as much as the context leaves
+    pub max_file_tokens: Option<u32>,
+    // Bytes of a PDF or binary file shown to the model as base64
+    pub binary_preview_bytes: u32,
+}
+
+impl Default for ContextSettings {
+    fn default() -> Self {
+        let windows = [
+            ("llama2", 4096),
+            ("llama3", 8192),
+            ("llama3.1", 131_072),
+            ("llama3.2", 131_072),
+            ("mistral", 32_768),
+            ("gemma", 8192),
+            ("phi3", 4096),
+            ("qwen2", 32_768),
+            ("gpt-3.5", 16_385),
+            ("gpt-4o", 128_000),
+        ];
+        ContextSettings {
+            windows: windows.iter().map(|(m, w)| (m.to_string(), *w)).collect(),
+            default_window: 4096,
+            reserve_tokens: 512,
+            max_file_tokens: None,
+            binary_preview_bytes: 8 * 1024,
+        }
+    }
+}
+
+impl ContextSettings {
+    pub fn normalize(&mut self) {
+        self.windows = std::mem::take(&mut self.windows).into_iter().map(|(m, w)| (m.trim().to_lowercase(), w)).filter(|(m, _)| !m.is_empty()).collect();
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some((model, _)) = self.windows.iter().find(|(_, w)| **w <= self.reserve_tokens) {
+            return Err(format!("context window of {} must be larger than context.reserve_tokens", model));
+        }
+        if self.default_window <= self.reserve_tokens || self.reserve_tokens == 0 {
+            return Err("context.default_window must be larger than context.reserve_tokens, which must be above zero".to_string());
+        }
+        if self.max_file_tokens == Some(0) || !(1..=MAX_BINARY_PREVIEW_BYTES).contains(&self.binary_preview_bytes) {
+            return Err(format!(
+                "context.max_file_tokens must be above zero and context.binary_preview_bytes between 1 and {}",
+                MAX_BINARY_PREVIEW_BYTES
+            ));
+        }
+        Ok(())
+    }
+
+    // Context window of `model`: num_ctx when the request sets it, else the longest listed prefix
+    pub fn window(&self, model: Option<&str>, options: &GenerationOptions) -> usize {
+        if let Some(n) = options.num_ctx {
+            return n as usize;
+        }
+        let model = model.unwrap_or("").to_lowercase();
+        self.windows
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, w)| *w)
+            .unwrap_or(self.default_window) as usize
+    }
+
+    // Tokens a prompt (system prompt, earlier turns and question) may take with `model`
+    pub fn budget(&self, model: Option<&str>, options: &GenerationOptions) -> usize {
+        let reserve = options.max_tokens.unwrap_or(self.reserve_tokens) as usize;
+        self.window(model, options).saturating_sub(reserve)
+    }
+}
+
+// Tokens one pre-tokenized piece is likely to take
+fn piece_tokens(piece: &str) -> usize {
+    let first = piece.chars().next().unwrap_or(' ');
+    let chars = piece.chars().count();
+    if first.is_whitespace() {
+        // A single space joins the word after it; line breaks take a token and long indentation
+        // one per few spaces
+        let newlines = piece.chars().filter(|c| *c == '\n').count();
+        return newlines.min(1) + chars.saturating_sub(1 + newlines).div_ceil(4);
+    }
+    if first.is_ascii_digit() {
+        return chars.div_ceil(3);
+    }
+    if !first.is_alphanumeric() {
+        return chars;
+    }
+    if !piece.is_ascii() {
+        // Scripts without spaces (CJK) take about a token per character, accented Latin a bit
+        // over one per three
+        let wide = piece.chars().filter(|c| (*c as u32) >= 0x2E80).count();
+        return wide + (chars - wide).div_ceil(3);
+    }
+    // Identifiers and encodings such as base64 mix case and digits and split much finer than words
+    let mixed = piece.chars().skip(1).any(|c| c.is_ascii_uppercase()) || piece.chars().any(|c| c.is_ascii_digit());
+    if mixed && chars > 8 {
+        (chars * 2).div_ceil(5)
+    } else {
+        // Common words are one token; longer ones split into pieces of about four letters
+        1 + chars.saturating_sub(6).div_ceil(4)
+    }
+}
+
+// Estimated tokens in `text`
+pub fn estimate(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut start = 0;
+    let mut kind = None;
+    for (i, c) in text.char_indices() {
+        let k = if c.is_whitespace() { 0 } else if c.is_ascii_digit() { 1 } else if c.is_alphanumeric() { 2 } else { 3 };
+        if kind.is_some_and(|prev| prev != k || k == 3) {
+            tokens += piece_tokens(&text[start..i]);
+            start = i;
+        }
+        kind = Some(k);
+    }
+    if start < text.len() {
+        tokens += piece_tokens(&text[start..]);
+    }
+    tokens
+}
+
+pub fn estimate_messages(messages: &[PromptMessage]) -> usize {
+    messages.iter().map(|m| estimate(&m.content) + MESSAGE_OVERHEAD).sum()
+}
+
+// The longest start of `text` within `budget` tokens, cut between characters
+pub fn truncate(text: &str, budget: usize) -> &str {
+    if estimate(text) <= budget {
+        return text;
+    }
+    // Binary search over the places the text may be cut
+    let bounds: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .filter(|i| super::text::is_cluster_boundary(text, *i))
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let (mut lo, mut hi) = (0, bounds.len() - 1);
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if estimate(&text[..bounds[mid]]) <= budget {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    &text[..bounds[lo]]
+}
+
+// Markdown headings, numbered sections ("2.1 Methods") and short all-caps lines
+fn is_heading(line: &str) -> bool {
+    let line = line.trim();
+    if line.is_empty() || line.chars().count() > 80 {
+        return false;
+    }
+    let numbered = line.split_once(' ').is_some_and(|(n, rest)| {
+        rest.starts_with(char::is_uppercase) && n.trim_end_matches('.').split('.').all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+    });
+    let caps = line.chars().any(char::is_alphabetic) && !line.chars().any(char::is_lowercase);
+    line.starts_with('#') || numbered || caps
+}
+
+// `text` cut to about `budget` tokens along whole lines: its start and end, and for the lines left
+// out a note of how many there were followed by the headings among them
+pub fn condense(text: &str, budget: usize) -> String {
+    if estimate(text) <= budget {
+        return text.to_string();
+    }
+    let budget = budget.saturating_sub(NOTE_TOKENS);
+    let lines: Vec<&str> = text.lines().collect();
+    let cost = |line: &str| estimate(line) + 1;
+    let head_budget = budget * HEAD_SHARE / 100;
+    let tail_budget = budget * TAIL_SHARE / 100;
+
+    let mut head = 0;
+    let mut used = 0;
+    while head < lines.len() && used + cost(lines[head]) <= head_budget {
+        used += cost(lines[head]);
+        head += 1;
+    }
+    // One huge line (minified or unbroken text): keep the start of it
+    if head == 0 {
+        return truncate(text, budget).to_string();
+    }
+    let mut tail = lines.len();
+    used = 0;
+    while tail > head && used + cost(lines[tail - 1]) <= tail_budget {
+        used += cost(lines[tail - 1]);
+        tail -= 1;
+    }
+
+    let omitted = &lines[head..tail];
+    let mut outline_budget = budget.saturating_sub(head_budget + tail_budget);
+    let mut outline = Vec::new();
+    for line in omitted.iter().filter(|l| is_heading(l)) {
+        if cost(line) > outline_budget {
+            break;
+        }
+        outline_budget -= cost(line);
+        outline.push(line.trim());
+    }
+
+    let mut out = lines[..head].join("\n");
+    out.push_str(&format!("\n[... {} lines left out", omitted.len()));
+    if outline.is_empty() {
+        out.push_str(" ...]\n");
+    } else {
+        out.push_str("; their headings:\n");
+        out.push_str(&outline.join("\n"));
+        out.push_str("\n...]\n");
+    }
+    out.push_str(&lines[tail..].join("\n"));
+    out
+}
+
+// Tokens file content may take in a prompt with this system prompt and question
+pub fn file_budget(budget: usize, system_prompt: &str, question: &str) -> usize {
+    let fixed = estimate(system_prompt) + estimate(question) + 2 * MESSAGE_OVERHEAD + FILE_FRAMING_TOKENS;
+    budget.saturating_sub(fixed) * FILE_SHARE / 100
+}
+
+// Fit a backend request's messages (system prompt, earlier turns, prompt last) into `budget`:
+// drop earlier turns oldest first, then condense the prompt into what is left. Returns how many
+// turns were dropped and whether the prompt was cut.
+pub fn fit_messages(messages: &mut Vec<PromptMessage>, budget: usize) -> (usize, bool) {
+    let mut dropped = 0;
+    while estimate_messages(messages) > budget && messages.len() > 2 {
+        messages.remove(1);
+        dropped += 1;
+    }
+    let total = estimate_messages(messages);
+    if total <= budget {
+        return (dropped, false);
+    }
+    let Some(last) = messages.last_mut() else {
+        return (dropped, false);
+    };
+    let room = budget.saturating_sub(total - estimate(&last.content));
+    last.content = condense(&last.content, room);
+    (dropped, true)
+}
+
+#[derive(Deserialize)]
+pub struct TokensRequest {
+    text: String,
+    // Model whose window to report; the configured one when unset
+    #[serde(default)]
+    model: Option<String>,
+}
+
+// Estimated tokens in `text`, and the context window and prompt budget of the model
+#[post("/llm/tokens")]
+pub async fn count_tokens(body: web::Json<TokensRequest>) -> Result<HttpResponse, Error> {
+    let settings = crate::settings::current().await;
+    let model = body.model.as_deref().or(settings.routing.model.as_deref()).or(settings.backend.model.as_deref());
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "tokens": estimate(&body.text),
+        "model": model,
+        "window": settings.context.window(model, &settings.generation),
+        "budget": settings.context.budget(model, &settings.generation)
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{condense, estimate, estimate_messages, fit_messages, truncate, ContextSettings, GenerationOptions, PromptMessage};
+
+    // Token estimates are in the range real tokenizers give, long files are condensed to their start,
+    // end and headings within the budget, and requests drop earlier turns before cutting the prompt
+    #[test]
+    fn prompts_fit_the_context_budget() {
+        assert_eq!(estimate(""), 0);
+        let prose = "The quick brown fox jumps over the lazy dog near the riverbank.";
+        assert!((12..=18).contains(&estimate(prose)), "{}", estimate(prose));
+        assert!(estimate("aGVsbG8gd29ybGQgZnJvbSBNZXNoTWluZA==") > estimate("hello world from MeshMind"));
+        assert!(estimate("日本語のテキスト") >= 8);
+        assert_eq!(truncate("héllo wörld", 2).trim_end(), "héllo");
+
+        let mut text = String::from("# Report\nIntro line\n");
+        for i in 0..400 {
+            text.push_str(&format!("Detail number {} about the experiment and its results.\n", i));
+            if i == 200 {
+                text.push_str("## Methods\n");
+            }
+        }
+        text.push_str("Final conclusion line");
+        let condensed = condense(&text, 500);
+        assert!(estimate(&condensed) <= 500, "{}", estimate(&condensed));
+        assert!(condensed.starts_with("# Report\nIntro line"));
+        assert!(condensed.ends_with("Final conclusion line"));
+        assert!(condensed.contains("lines left out; their headings:\n## Methods"));
+        assert_eq!(condense("short", 500), "short");
+
+        let settings = ContextSettings::default();
+        let options = GenerationOptions::default();
+        assert_eq!(settings.window(Some("llama3.1:8b"), &options), 131_072);
+        assert_eq!(settings.window(Some("llama3:8b"), &options), 8192);
+        assert_eq!(settings.window(Some("unknown"), &options), 4096);
+        let ctx = GenerationOptions { num_ctx: Some(2048), max_tokens: Some(256), ..Default::default() };
+        assert_eq!(settings.budget(Some("llama3"), &ctx), 1792);
+
+        let message = |role: &str, content: &str| PromptMessage { role: role.into(), content: content.into() };
+        let mut messages = vec![message("system", "Be brief."), message("user", &text), message("assistant", "ok"), message("user", "And the conclusion?")];
+        let (dropped, condensed) = fit_messages(&mut messages, 100);
+        assert_eq!((dropped, condensed), (1, false));
+        assert_eq!(messages.last().unwrap().content, "And the conclusion?");
+        let mut messages = vec![message("system", "Be brief."), message("user", &text)];
+        let (dropped, condensed) = fit_messages(&mut messages, 300);
+        assert_eq!((dropped, condensed), (0, true));
+        assert!(estimate_messages(&messages) <= 300);
+    }
+}
@@ -4,8 +4,8 @@
 #[get("/peer-file/{ip}/{filename}")]
 async fn proxy_peer_file(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
     let (ip, filename) = path.into_inner();
-    // Build http://{ip}:8080/api/files/{filename} with proper encoding
-    let mut url = match reqwest::Url::parse(&format!("http://{}:8080", ip)) {
+    // Build http(s)://{ip}:8080/api/files/{filename} with proper encoding
+    let mut url = match reqwest::Url::parse(&crate::tls::peer_origin(&ip, 8080)) {
         Ok(u) => u,
         Err(e) => {
             return Ok(HttpResponse::BadRequest().json(serde_json::json!({
@@ -20,8 +20,7 @@ async fn proxy_peer_file(path: web::Path<(String, String)>) -> Result<HttpRespon
         segs.push("files");
         segs.push(&filename);
     }
-    let client = reqwest::Client::new();
-    match client
+    match crate::client::peer()
         .get(url)
         .header("x-peer-llm", "1")
         .send()
@@ -36,9 +35,13 @@ async fn proxy_peer_file(path: web::Path<(String, String)>) -> Result<HttpRespon
                 .unwrap_or("application/octet-stream")
                 .to_string();
             match resp.bytes().await {
-                Ok(bytes) => Ok(HttpResponse::build(status)
-                    .content_type(ct)
-                    .body(bytes)),
+                Ok(bytes) => {
+                    bandwidth::record(bandwidth::Channel::Proxy, Some(&ip), bandwidth::Direction::Down, bytes.len());
+                    bandwidth::record(bandwidth::Channel::Proxy, None, bandwidth::Direction::Up, bytes.len());
+                    Ok(HttpResponse::build(status)
+                        .content_type(ct)
+                        .body(bytes))
+                }
                 Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
                     "success": false,
                     "message": format!("Failed to read peer response: {}", e)
@@ -55,10 +58,91 @@ async fn proxy_peer_file(path: web::Path<(String, String)>) -> Result<HttpRespon
 #[get("/status")]
 async fn api_status() -> Result<HttpResponse, Error> {
     let peer_count = CONVERSATION_STORE.get_peer_conversations().await.len();
-    let is_llm_host = crate::tcp::is_ollama_available().await;
+    let is_llm_host = crate::tcp::is_llm_available().await;
+    let mut online_peers = crate::tcp::connected_peer_ips().await;
+    online_peers.sort();
+    let mut llm_peers = crate::tcp::llm_peer_ips().await;
+    llm_peers.sort();
+
+    // Shared files without asking peers over HTTP: ours plus what peers announced or sent us
+    let local_files = list_uploaded_files().await.unwrap_or_default();
+    let mut seen: HashSet<(String, String)> = local_files.iter().map(|f| (f.filename.clone(), f.uploader_ip.clone())).collect();
+    let mut peer_files = 0usize;
+    let mut peer_bytes = 0u64;
+    let received = list_received_files().await.unwrap_or_default();
+    for f in get_announced_files().await.into_iter().chain(received) {
+        if seen.insert((f.filename.clone(), f.uploader_ip.clone())) {
+            peer_files += 1;
+            peer_bytes += f.file_size;
+        }
+    }
+    let local_bytes: u64 = local_files.iter().map(|f| f.file_size).sum();
+
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "peer_count": peer_count,
-        "is_llm_host": is_llm_host
+        "is_llm_host": is_llm_host,
+        "node_id": crate::persistence::node_id(),
+        "hostname": hostname::get().map(|h| h.to_string_lossy().to_string()).unwrap_or_default(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "power_profile": power::profile().name(),
+        "online_peers": online_peers.len(),
+        "online_peer_ips": online_peers,
+        "llm_hosts": llm_peers,
+        "files": {
+            "local_count": local_files.len(),
+            "local_bytes": local_bytes,
+            "peer_count": peer_files,
+            "peer_bytes": peer_bytes,
+            "total_count": local_files.len() + peer_files,
+            "total_bytes": local_bytes + peer_bytes
+        },
+        "discovery": crate::udp::discovery_health().await,
+        "chaos": chaos::status()
+    })))
+}
+
+// Versions peers reported during the handshake, with an advisory where this node or the peer
+// should be upgraded; peers that never negotiated (older builds) show up with "version": null.
+// `clock_skew` is how far each peer's clock is off from ours (see crate::clock).
+#[get("/peers/info")]
+async fn api_peers_info() -> Result<HttpResponse, Error> {
+    let versions = crate::tcp::peer_versions().await;
+    let connected: HashSet<String> = crate::tcp::connected_peer_ips().await.into_iter().collect();
+    let llm_hosts: HashSet<String> = crate::tcp::llm_peer_ips().await.into_iter().collect();
+    let dials = crate::tcp::dial_states();
+    let connections = crate::tcp::connections();
+    let mut ips: Vec<String> = connected.iter().chain(versions.keys()).chain(dials.keys()).cloned().collect::<HashSet<_>>().into_iter().collect();
+    ips.sort();
+    let mut skews = HashMap::new();
+    for ip in &ips {
+        skews.insert(ip.clone(), crate::clock::skew(ip).await);
+    }
+    let peers: Vec<serde_json::Value> = ips
+        .iter()
+        .map(|ip| {
+            serde_json::json!({
+                "ip": ip,
+                "connected": connected.contains(ip),
+                "llm_host": llm_hosts.contains(ip),
+                "version": versions.get(ip),
+                "clock_skew": skews.get(ip),
+                "dial": dials.get(ip),
+                "connection": connections.get(ip),
+            })
+        })
+        .collect();
+    let mismatches = versions.values().filter(|v| v.advisory.is_some()).count();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "local": {
+            "crate_version": env!("CARGO_PKG_VERSION"),
+            "protocol_version": crate::tcp::PROTOCOL_VERSION,
+            "min_protocol_version": crate::tcp::MIN_PROTOCOL_VERSION,
+            "capabilities": crate::tcp::local_capabilities(),
+            "admission": crate::tcp::admission_status()
+        },
+        "mismatches": mismatches,
+        "peers": peers,
+        "announced_files": crate::tcp::announced_file_stats().await
     })))
 }
 
@@ -98,11 +182,63 @@ mod tcp;
 mod llm;
 mod conversation;
 mod persistence;
+mod export;
+mod mqtt;
+mod grpc;
+mod graphql;
+mod webdav;
+mod dropfolder;
+mod jobs;
+mod replication;
+mod fetch;
+mod chunkstore;
+mod settings;
+mod media;
+mod transcribe;
+mod fork;
+mod redact;
+mod audit;
+mod moderation;
+mod update;
+mod service;
+mod migrate;
+mod simulate;
+mod presence;
+mod votes;
+mod pipelines;
+mod scan;
+mod headers;
+mod tls;
+mod latency;
+mod transfers;
+mod public;
+mod events;
+mod diagnostics;
+mod topology;
+mod clock;
+mod telemetry;
+mod users;
+mod proxy;
+mod limits;
+mod power;
+mod popularity;
+mod storage;
+mod maintenance;
+mod client;
+mod history;
+mod catalog;
+mod chaos;
+mod bandwidth;
+mod boot;
+mod groups;
+mod knowledge;
+#[cfg(feature = "desktop")]
+mod desktop;
 
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::sync::{Mutex as StdMutex, OnceLock};
-use actix_web::{get, post, App, HttpResponse, HttpServer, Responder, web, Error};
+use actix_web::{get, post, put, App, HttpMessage, HttpResponse, HttpServer, Responder, web, Error};
 use actix_web::cookie::{Cookie, SameSite, time::Duration as CookieDuration};
 use jsonwebtoken::{encode, decode, EncodingKey, DecodingKey, Header, Validation, Algorithm};
 use actix_web::dev::Service;
@@ -115,7 +251,7 @@ use tokio::sync::Mutex;
 use udp::{periodic_broadcast, receive_broadcast};
 use tcp::{connect_to_peers, listen_for_connections};
 use conversation::CONVERSATION_STORE;
-use persistence::{save_uploaded_file, list_uploaded_files, get_file_content, list_received_files, FileInfo, RECEIVED_DIR};
+use persistence::{list_uploaded_files, get_file_content, list_received_files, FileInfo};
 use actix_multipart::Multipart;
 use futures_util::TryStreamExt;
 use futures_util::future::{Either, ready};
@@ -127,7 +263,13 @@ use chrono::{Datelike, Duration as ChronoDuration, Utc};
 struct NodeAuth { username: String, password: String }
 
 #[derive(serde::Serialize, serde::Deserialize)]
-struct Claims { sub: String, exp: usize }
+struct Claims {
+    sub: String,
+    exp: usize,
+    // Set for single sign-on sessions, whose user has no local account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<users::Role>,
+}
 
 fn load_node_creds() -> NodeAuth {
     // Username
@@ -148,39 +290,50 @@ fn jwt_keys(secret: &str) -> (EncodingKey, DecodingKey) {
     (EncodingKey::from_secret(secret.as_bytes()), DecodingKey::from_secret(secret.as_bytes()))
 }
 
-#[derive(serde::Deserialize)]
-struct LoginRequest { username: String, password: String }
-
-#[post("/auth/login")]
-async fn auth_login(auth: web::Data<NodeAuth>, body: web::Json<LoginRequest>) -> Result<HttpResponse, Error> {
-    if body.username != auth.username || body.password != auth.password {
-        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({"error":"invalid_credentials"})));
-    }
+// A 24h session cookie for `username`; `role` is given for single sign-on users
+fn session_cookie(auth: &NodeAuth, username: &str, role: Option<users::Role>) -> Result<Cookie<'static>, Error> {
     let exp = (Utc::now() + ChronoDuration::hours(24)).timestamp() as usize;
-    let claims = Claims { sub: auth.username.clone(), exp };
+    let claims = Claims { sub: username.to_string(), exp, role };
     let (ek, _) = jwt_keys(&auth.password);
     let token = encode(&Header::new(Algorithm::HS256), &claims, &ek).map_err(|_| actix_web::error::ErrorInternalServerError("jwt"))?;
-
-    let cookie = Cookie::build("session", token)
+    Ok(Cookie::build("session", token)
         .path("/")
         .http_only(true)
         .same_site(SameSite::Lax)
+        .secure(tls::enabled())
         .max_age(CookieDuration::hours(24))
-        .finish();
+        .finish())
+}
 
-    Ok(HttpResponse::Ok().cookie(cookie).json(serde_json::json!({"authenticated": true, "username": auth.username})))
+// The user a session token signed with `secret` belongs to, while it is still valid
+fn session_user(secret: &str, token: &str) -> Option<users::SessionUser> {
+    let (_, dk) = jwt_keys(secret);
+    let claims = decode::<Claims>(token, &dk, &Validation::new(Algorithm::HS256)).ok()?.claims;
+    users::session(claims.sub, claims.role)
+}
+
+#[derive(serde::Deserialize)]
+struct LoginRequest { username: String, password: String }
+
+#[post("/auth/login")]
+async fn auth_login(auth: web::Data<NodeAuth>, body: web::Json<LoginRequest>) -> Result<HttpResponse, Error> {
+    // The node credentials sign in the admin; accounts the admin added sign in other users
+    let is_node_admin = body.username == auth.username && body.password == auth.password;
+    if !is_node_admin && !users::verify(&body.username, &body.password) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({"error":"invalid_credentials"})));
+    }
+    let cookie = session_cookie(&auth, &body.username, None)?;
+    Ok(HttpResponse::Ok().cookie(cookie).json(serde_json::json!({"authenticated": true, "username": body.username, "admin": is_node_admin})))
 }
 
 #[get("/auth/status")]
 async fn auth_status(req: actix_web::HttpRequest, auth: web::Data<NodeAuth>) -> Result<HttpResponse, Error> {
-    let cookie = req.cookie("session");
-    if let Some(c) = cookie {
-        let (_, dk) = jwt_keys(&auth.password);
-        if decode::<Claims>(c.value(), &dk, &Validation::new(Algorithm::HS256)).is_ok() {
-            return Ok(HttpResponse::Ok().json(serde_json::json!({"authenticated": true, "username": auth.username})));
-        }
+    // `oidc` tells the login page whether to offer single sign-on
+    let oidc = users::oidc::enabled();
+    if let Some(user) = req.cookie("session").and_then(|c| session_user(&auth.password, c.value())) {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({"authenticated": true, "username": user.name, "admin": user.admin, "oidc": oidc})));
     }
-    Ok(HttpResponse::Ok().json(serde_json::json!({"authenticated": false})))
+    Ok(HttpResponse::Ok().json(serde_json::json!({"authenticated": false, "oidc": oidc})))
 }
 
 #[post("/auth/logout")]
@@ -189,6 +342,7 @@ async fn auth_logout() -> Result<HttpResponse, Error> {
         .path("/")
         .http_only(true)
         .same_site(SameSite::Lax)
+        .secure(tls::enabled())
         .max_age(CookieDuration::seconds(0))
         .finish();
     Ok(HttpResponse::Ok().cookie(cookie).json(serde_json::json!({"ok": true})))
@@ -198,29 +352,45 @@ async fn auth_logout() -> Result<HttpResponse, Error> {
 #[folder = "./webpage/build/"]
 struct WebAssets;
 
-fn send_file_or_default(path: String) -> HttpResponse {
+// A UI file from the signed bundle peers shared, if one is being served (see update::ui), else embedded
+fn ui_asset(path: &str) -> Option<std::borrow::Cow<'static, [u8]>> {
+    update::ui::asset(path).map(Into::into).or_else(|| WebAssets::get(path).map(|file| file.data))
+}
+
+// index.html with the node's identity and features filled in (see boot), then rebased
+async fn render_index(req: &actix_web::HttpRequest, data: std::borrow::Cow<'static, [u8]>) -> Vec<u8> {
+    let config = boot::config(req).await;
+    let page = boot::inject(&data, &config).unwrap_or_else(|| data.into_owned());
+    proxy::rebase(&page).unwrap_or(page)
+}
+
+async fn send_file_or_default(req: &actix_web::HttpRequest, path: String) -> HttpResponse {
     let path = if path.starts_with("assets/") {
         path
     } else {
         path.trim_start_matches("/app/").to_string()
     };
     
-    let asset = WebAssets::get(path.as_str());
+    let asset = ui_asset(&path).filter(|_| path != "index.html");
     match asset {
-        Some(file) => {
+        Some(data) => {
             let mime_type = mime_guess::from_path(&path).first_or_octet_stream();
+            let body = match mime_type.subtype().as_str() {
+                "html" | "javascript" | "css" => proxy::rebase(&data).map(Into::into).unwrap_or(data),
+                _ => data,
+            };
             HttpResponse::Ok()
                 .content_type(mime_type.to_string())
-                .body(file.data)
+                .body(body)
         }
         None => {
-            let index_asset = WebAssets::get("index.html");
+            let index_asset = ui_asset("index.html");
             match index_asset {
-                Some(index_file) => {
+                Some(index_data) => {
                     let mime_type = mime_guess::from_path("index.html").first_or_octet_stream();
                     HttpResponse::Ok()
                         .content_type(mime_type.to_string())
-                        .body(index_file.data)
+                        .body(render_index(req, index_data).await)
                 }
                 None => HttpResponse::NotFound().body("Not Found"),
             }
@@ -233,7 +403,10 @@ fn send_file_or_default(path: String) -> HttpResponse {
 struct RouteStats {
     durations_ms: Vec<i64>,
     req_count: u64,
+    // 5xx answers
     error_count: u64,
+    // 4xx answers: refused logins and sessions, missing files and the like
+    client_error_count: u64,
 }
 
 #[derive(Default, Clone)]
@@ -241,6 +414,17 @@ struct TotalsStats {
     durations_ms: Vec<i64>,
     req_count: u64,
     error_count: u64,
+    client_error_count: u64,
+}
+
+// Most routes tracked separately; requests to any further route share one entry per method
+const MAX_PERF_ROUTES: usize = 200;
+
+// Stats key for a request: its method and route pattern ("/api/files/{filename}"), so every file
+// or peer does not get an entry of its own. Paths no route matched, the UI bundle among them,
+// share one entry.
+fn perf_route_key(method: &str, pattern: Option<String>) -> String {
+    format!("{} {}", method, pattern.unwrap_or_else(|| "(unmatched)".to_string()))
 }
 
 #[derive(Default)]
@@ -329,19 +513,37 @@ async fn analytics_engagement() -> Result<HttpResponse, Error> {
 async fn analytics_perf(state: web::Data<tokio::sync::Mutex<PerfState>>) -> Result<HttpResponse, Error> {
     let state = state.lock().await;
 
+    let rate = |count: u64, total: u64| if total == 0 { 0.0 } else { count as f64 / total as f64 };
     let mut per_route_vec: Vec<serde_json::Value> = Vec::new();
     for (route, stats) in state.per_route.iter() {
         let p95 = percentile_ms(&stats.durations_ms, 95.0).unwrap_or(0);
-        let err_rate = if stats.req_count == 0 { 0.0 } else { stats.error_count as f64 / stats.req_count as f64 };
         per_route_vec.push(serde_json::json!({
             "route": route,
+            "req_count": stats.req_count,
             "p95_ms": p95,
-            "error_rate": err_rate
+            "error_rate": rate(stats.error_count, stats.req_count),
+            "client_error_rate": rate(stats.client_error_count, stats.req_count)
         }));
     }
+    // Routes answering with the most errors, 4xx and 5xx together
+    let mut error_routes: Vec<(&String, &RouteStats)> =
+        state.per_route.iter().filter(|(_, s)| s.error_count + s.client_error_count > 0).collect();
+    error_routes.sort_by(|a, b| (b.1.error_count + b.1.client_error_count).cmp(&(a.1.error_count + a.1.client_error_count)).then(a.0.cmp(b.0)));
+    let top_error_routes: Vec<serde_json::Value> = error_routes
+        .into_iter()
+        .take(10)
+        .map(|(route, s)| serde_json::json!({"route": route, "error_count": s.error_count, "client_error_count": s.client_error_count}))
+        .collect();
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "per_route": per_route_vec,
-        "totals": {"req_count": state.totals.req_count, "error_count": state.totals.error_count}
+        "top_error_routes": top_error_routes,
+        "totals": {
+            "req_count": state.totals.req_count,
+            "error_count": state.totals.error_count,
+            "client_error_count": state.totals.client_error_count,
+            "error_rate": rate(state.totals.error_count, state.totals.req_count),
+            "client_error_rate": rate(state.totals.client_error_count, state.totals.req_count)
+        }
     })))
 }
 
@@ -354,12 +556,30 @@ async fn analytics_network(state: web::Data<tokio::sync::Mutex<PerfState>>) -> R
     let p99 = percentile_ms(&state.totals.durations_ms, 99.0);
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "latency_ms": {"p50": p50, "p95": p95, "p99": p99},
-        "bandwidth": {"up_bps": serde_json::Value::Null, "down_bps": serde_json::Value::Null}
+        "bandwidth": bandwidth::report()
     })))
 }
 
 #[get("/analytics/chat")]
 async fn analytics_chat() -> Result<HttpResponse, Error> {
+    let (per_day_vec, top_users_vec) = chat_activity().await;
+    let messages_per_day: Vec<serde_json::Value> = per_day_vec
+        .into_iter()
+        .map(|(date, count)| serde_json::json!({"date": date, "count": count}))
+        .collect();
+    let top_users: Vec<serde_json::Value> = top_users_vec
+        .into_iter()
+        .map(|(user, count)| serde_json::json!({"user": user, "count": count}))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "messages_per_day": messages_per_day,
+        "top_users": top_users
+    })))
+}
+
+// Messages per day (sorted by date) and per-user message counts (most active first)
+async fn chat_activity() -> (Vec<(String, usize)>, Vec<(String, usize)>) {
     // Aggregate messages per day and top users from store
     let mut per_day: HashMap<String, usize> = HashMap::new();
     let mut user_counts: HashMap<String, usize> = HashMap::new();
@@ -388,44 +608,41 @@ async fn analytics_chat() -> Result<HttpResponse, Error> {
     // Convert maps to vecs sorted by key/count
     let mut per_day_vec: Vec<(String, usize)> = per_day.into_iter().collect();
     per_day_vec.sort_by(|a, b| a.0.cmp(&b.0));
-    let messages_per_day: Vec<serde_json::Value> = per_day_vec
-        .into_iter()
-        .map(|(date, count)| serde_json::json!({"date": date, "count": count}))
-        .collect();
-
     let mut top_users_vec: Vec<(String, usize)> = user_counts.into_iter().collect();
     top_users_vec.sort_by(|a, b| b.1.cmp(&a.1));
-    let top_users: Vec<serde_json::Value> = top_users_vec
-        .into_iter()
-        .map(|(user, count)| serde_json::json!({"user": user, "count": count}))
-        .collect();
+    (per_day_vec, top_users_vec)
+}
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "messages_per_day": messages_per_day,
-        "top_users": top_users
-    })))
+// Aggregate by top-level type (e.g., application, image) -> (type, count, total_bytes)
+fn file_type_totals(files: &[FileInfo]) -> Vec<(String, u64, u64)> {
+    let mut types: HashMap<String, (u64, u64)> = HashMap::new(); // type -> (count, total_bytes)
+    for f in files {
+        let t = f
+            .file_type
+            .split('/')
+            .next()
+            .unwrap_or("other")
+            .to_string();
+        let entry = types.entry(t).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += f.file_size;
+    }
+    types.into_iter().map(|(t, (count, total_bytes))| (t, count, total_bytes)).collect()
+}
+
+#[derive(serde::Deserialize)]
+struct FileAnalyticsQuery {
+    // Files not downloaded for this many days are cleanup candidates
+    idle_days: Option<i64>,
 }
 
 #[get("/analytics/files")]
-async fn analytics_files() -> Result<HttpResponse, Error> {
+async fn analytics_files(query: web::Query<FileAnalyticsQuery>) -> Result<HttpResponse, Error> {
+    let idle_days = query.idle_days.unwrap_or(popularity::DEFAULT_IDLE_DAYS).clamp(0, 3650);
     match list_uploaded_files().await {
         Ok(files) => {
-            // Aggregate by top-level type (e.g., application, image)
-            let mut types: HashMap<String, (u64, u64)> = HashMap::new(); // type -> (count, total_bytes)
-            for f in &files {
-                let t = f
-                    .file_type
-                    .split('/')
-                    .next()
-                    .unwrap_or("other")
-                    .to_string();
-                let entry = types.entry(t).or_insert((0, 0));
-                entry.0 += 1;
-                entry.1 += f.file_size as u64;
-            }
-
             let mut types_vec: Vec<serde_json::Value> = Vec::new();
-            for (t, (count, total_bytes)) in types.into_iter() {
+            for (t, count, total_bytes) in file_type_totals(&files) {
                 types_vec.push(serde_json::json!({
                     "type": t,
                     "count": count,
@@ -447,9 +664,50 @@ async fn analytics_files() -> Result<HttpResponse, Error> {
                 }))
                 .collect();
 
+            // Downloads by our users and by peers' users, most downloaded first (top 10)
+            let access = popularity::snapshot().await;
+            let stats = |f: &FileInfo| access.get(&f.filename).cloned().unwrap_or_default();
+            let mut by_downloads: Vec<(&FileInfo, popularity::Access)> = files.iter().map(|f| (f, stats(f))).filter(|(_, a)| a.downloads() > 0).collect();
+            by_downloads.sort_by(|a, b| b.1.downloads().cmp(&a.1.downloads()).then_with(|| b.1.last_access.cmp(&a.1.last_access)));
+            let most_downloaded: Vec<serde_json::Value> = by_downloads
+                .into_iter()
+                .take(10)
+                .map(|(f, a)| serde_json::json!({
+                    "filename": f.filename,
+                    "bytes": f.file_size,
+                    "downloads": a.downloads(),
+                    "local_downloads": a.local_downloads,
+                    "peer_downloads": a.peer_downloads,
+                    "last_access": a.last_access
+                }))
+                .collect();
+            let mut never: Vec<&FileInfo> = files.iter().filter(|f| stats(f).downloads() == 0).collect();
+            never.sort_by_key(|f| f.upload_time);
+            let never_accessed: Vec<serde_json::Value> = never
+                .into_iter()
+                .map(|f| serde_json::json!({ "filename": f.filename, "bytes": f.file_size, "upload_time": f.upload_time }))
+                .collect();
+            let candidates = popularity::cleanup_candidates(&files, &access, ChronoDuration::days(idle_days), Utc::now());
+            let cleanup: Vec<serde_json::Value> = candidates
+                .iter()
+                .map(|f| serde_json::json!({
+                    "filename": f.filename,
+                    "bytes": f.file_size,
+                    "upload_time": f.upload_time,
+                    "last_access": stats(f).last_access
+                }))
+                .collect();
+
             Ok(HttpResponse::Ok().json(serde_json::json!({
                 "types": types_vec,
-                "largest": largest
+                "largest": largest,
+                "most_downloaded": most_downloaded,
+                "never_accessed": never_accessed,
+                "cleanup_candidates": {
+                    "idle_days": idle_days,
+                    "files": cleanup,
+                    "reclaimable_bytes": candidates.iter().map(|f| f.file_size).sum::<u64>()
+                }
             })))
         }
         Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
@@ -460,20 +718,24 @@ async fn analytics_files() -> Result<HttpResponse, Error> {
 }
 
 #[get("/app/")]
-async fn get_index() -> impl Responder {
-    send_file_or_default("index.html".to_string())
+async fn get_index(req: actix_web::HttpRequest) -> impl Responder {
+    send_file_or_default(&req, "index.html".to_string()).await
 }
 
 #[get("/app/{path:.*}")]
-async fn get_root_files(path: actix_web::web::Path<String>) -> impl Responder {
+async fn get_root_files(req: actix_web::HttpRequest, path: actix_web::web::Path<String>) -> impl Responder {
     let path = path.into_inner();
-    send_file_or_default(path)
+    send_file_or_default(&req, path).await
 }
 
 #[get("/peers")]
-async fn get_peers() -> Result<HttpResponse, actix_web::Error> {
+async fn get_peers(session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, actix_web::Error> {
     println!("API: Received request for peer conversations");
-    let peer_conversations = CONVERSATION_STORE.get_peer_conversations().await;
+    let mut peer_conversations = CONVERSATION_STORE.get_peer_conversations().await;
+    // Like /api/local, a signed-in user sees only their own questions and the answers to them
+    if let Some(user) = users::view(session.map(|s| s.into_inner()), None).ok().flatten() {
+        peer_conversations.values_mut().for_each(|c| *c = c.for_user(&user));
+    }
     println!("API: Found {} peer conversations", peer_conversations.len());
     for (peer, conv) in &peer_conversations {
         println!("API: Peer {} has {} messages", peer, conv.messages.len());
@@ -481,36 +743,260 @@ async fn get_peers() -> Result<HttpResponse, actix_web::Error> {
     Ok(HttpResponse::Ok().json(peer_conversations))
 }
 
-#[get("/api/local")]
-async fn get_local() -> Result<HttpResponse, actix_web::Error> {
+#[derive(serde::Deserialize)]
+struct UserQuery {
+    // Only this signed-in user's questions and their answers (or uploads)
+    user: Option<String>,
+}
+
+fn forbidden_view(message: String) -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": message }))
+}
+
+// A signed-in user's default conversation is their own questions and the answers to them; the
+// admin sees everyone's unless asking for one user (see crate::users)
+#[get("/local")]
+async fn get_local(query: web::Query<UserQuery>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, actix_web::Error> {
     println!("API: Received request for local conversation");
+    let user = match users::view(session.map(|s| s.into_inner()), query.into_inner().user) {
+        Ok(user) => user,
+        Err(message) => return Ok(forbidden_view(message)),
+    };
     let local = CONVERSATION_STORE.get_local_conversation().await;
+    let local = match user.as_deref() {
+        Some(user) => local.map(|c| c.for_user(user)),
+        None => local,
+    };
     match local {
         Some(conv) => Ok(HttpResponse::Ok().json(conv)),
         None => Ok(HttpResponse::Ok().json(serde_json::json!(null))),
     }
 }
 
+#[derive(serde::Deserialize)]
+struct TimelineQuery {
+    limit: Option<usize>,
+    user: Option<String>,
+}
+
+// Our conversation and every peer's merged in causal order (Lamport time, see crate::clock); the
+// newest `limit` messages, each tagged with the conversation it came from. With `user` only that
+// user's questions and their answers are listed, whichever node they were asked on.
+#[get("/timeline")]
+async fn get_timeline(query: web::Query<TimelineQuery>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let user = match users::view(session.map(|s| s.into_inner()), query.user.clone()) {
+        Ok(user) => user,
+        Err(message) => return Ok(forbidden_view(message)),
+    };
+    let timeline = CONVERSATION_STORE.timeline(user.as_deref()).await;
+    let skip = timeline.len().saturating_sub(query.limit.unwrap_or(500));
+    let messages: Vec<serde_json::Value> = timeline
+        .into_iter()
+        .skip(skip)
+        .map(|(conversation, m)| {
+            let mut v = serde_json::to_value(m).unwrap_or_default();
+            v["conversation"] = conversation.into();
+            v
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "messages": messages })))
+}
+
+// A conversation as a tree for the UI: each question with its answers and regenerations nested
+// under it, and follow-up questions under the message they reply to. `user` narrows it like /local.
+#[get("/conversations/{id}/thread")]
+async fn get_thread(path: web::Path<String>, query: web::Query<UserQuery>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let user = match users::view(session.map(|s| s.into_inner()), query.into_inner().user) {
+        Ok(user) => user,
+        Err(message) => return Ok(forbidden_view(message)),
+    };
+    let Some(conversation) = CONVERSATION_STORE.get_conversation(&id).await else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": format!("Conversation {} not found", id)
+        })));
+    };
+    let conversation = match user.as_deref() {
+        Some(user) => conversation.for_user(user),
+        None => conversation,
+    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "id": id, "thread": conversation.thread() })))
+}
+
+#[derive(serde::Deserialize)]
+struct PrivacyRequest {
+    privacy: conversation::Privacy,
+}
+
+// Who sees one of our conversations ("local" or one of our forks): private, mesh or broadcast.
+// Copies peers already received stay with them.
+#[put("/conversations/{id}/privacy")]
+async fn set_conversation_privacy(path: web::Path<String>, body: web::Json<PrivacyRequest>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !session.is_some_and(|u| u.admin) {
+        return Ok(forbidden_view("Only the admin can change who sees a conversation".to_string()));
+    }
+    let id = path.into_inner();
+    let Some(conversation) = CONVERSATION_STORE.set_privacy(&id, body.privacy).await else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": format!("Conversation {} is not one of ours", id)
+        })));
+    };
+    if id == "local" {
+        tcp::broadcast_local_conversation().await;
+    } else {
+        tcp::broadcast_fork(&conversation).await;
+    }
+    println!("API: Conversation {} is now {:?}", id, conversation.privacy);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "id": id, "privacy": conversation.privacy })))
+}
+
+#[derive(serde::Deserialize)]
+struct GroupRequest {
+    group: Option<String>,
+}
+
+// Scope one of our conversations to a group we are in, or back to everyone it is shared with
+// when `group` is null. Copies peers outside the group already received stay with them.
+#[put("/conversations/{id}/group")]
+async fn set_conversation_group(path: web::Path<String>, body: web::Json<GroupRequest>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !session.is_some_and(|u| u.admin) {
+        return Ok(forbidden_view("Only the admin can change who sees a conversation".to_string()));
+    }
+    let id = path.into_inner();
+    let group = body.into_inner().group.map(|g| g.trim().to_string()).filter(|g| !g.is_empty());
+    if let Some(group) = &group {
+        if let Err(message) = groups::validate(group) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": message })));
+        }
+        if !groups::is_member(group) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": format!("This node is not in group {}", group)
+            })));
+        }
+    }
+    let Some(conversation) = CONVERSATION_STORE.set_group(&id, group).await else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": format!("Conversation {} is not one of ours", id)
+        })));
+    };
+    if id == "local" {
+        tcp::broadcast_local_conversation().await;
+    } else {
+        tcp::broadcast_fork(&conversation).await;
+    }
+    println!("API: Conversation {} is now scoped to {}", id, conversation.group.as_deref().unwrap_or("everyone"));
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "id": id, "group": conversation.group })))
+}
+
+#[derive(serde::Deserialize)]
+struct TitleRequest {
+    title: Option<String>,
+}
+
+// Name one of our conversations by hand, replacing the generated title; a null or empty `title`
+// clears it, and a new one is generated after the next exchange
+#[put("/conversations/{id}/title")]
+async fn set_conversation_title(path: web::Path<String>, body: web::Json<TitleRequest>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    // Titles go to peers with the conversation, which every user of the node shares
+    if !session.is_some_and(|u| u.admin) {
+        return Ok(forbidden_view("Only the admin can rename a conversation".to_string()));
+    }
+    let id = path.into_inner();
+    let title = body.into_inner().title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+    if title.as_ref().is_some_and(|t| t.chars().count() > llm::titles::MAX_TITLE_CHARS || t.contains('\n')) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Titles are one line of at most {} characters", llm::titles::MAX_TITLE_CHARS)
+        })));
+    }
+    let Some(conversation) = CONVERSATION_STORE.set_title(&id, title).await else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": format!("Conversation {} is not one of ours", id)
+        })));
+    };
+    if id == "local" {
+        tcp::broadcast_local_conversation().await;
+    } else {
+        tcp::broadcast_fork(&conversation).await;
+    }
+    println!("API: Conversation {} is now titled {:?}", id, conversation.title);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "id": id, "title": conversation.title })))
+}
+
 #[post("/upload")]
-async fn upload_file(req: actix_web::HttpRequest, mut payload: Multipart) -> Result<HttpResponse, Error> {
-    // Determine client IP: prefer X-Forwarded-For, fallback to peer_addr
-    let client_ip = req
-        .headers()
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.split(',').next().map(|ip| ip.trim().to_string()))
-        .or_else(|| req.peer_addr().map(|sa| sa.ip().to_string()))
+async fn upload_file(req: actix_web::HttpRequest, mut payload: Multipart, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let owner = users::name(session);
+    // Determine client IP: X-Forwarded-For only counts when it comes from a trusted proxy
+    let client_ip = proxy::request_client_ip(&req)
+        .map(|ip| ip.to_string())
         .unwrap_or_else(|| "127.0.0.1".to_string());
     // If loopback, attempt to resolve our LAN IP to be more meaningful in UI
     let client_ip = if client_ip == "127.0.0.1" || client_ip == "::1" {
-        std::net::TcpStream::connect("8.8.8.8:53")
-            .and_then(|s| s.local_addr())
-            .map(|a| a.ip().to_string())
+        crate::ip::primary_ip()
+            .map(|ip| ip.to_string())
             .unwrap_or(client_ip)
     } else { client_ip };
     
+    // The file, and optionally the peers to send it to instead of all connected ones, who may see it
+    // and the group it is scoped to
+    let mut upload: Option<(String, String, Vec<u8>)> = None;
+    let mut peers: Option<Vec<String>> = None;
+    let mut visibility = persistence::Visibility::Mesh;
+    let mut group: Option<String> = None;
     while let Some(mut field) = payload.try_next().await? {
-        if field.name() == "file" {
+        if field.name() == "visibility" {
+            let mut value = Vec::new();
+            while let Some(chunk) = field.try_next().await? {
+                value.extend_from_slice(&chunk);
+            }
+            let value = String::from_utf8_lossy(&value).trim().to_string();
+            match serde_json::from_value(serde_json::Value::String(value.clone())) {
+                Ok(v) => visibility = v,
+                Err(_) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "success": false,
+                    "message": format!("Unknown visibility {}; use private, node_users or mesh", value)
+                }))),
+            }
+        } else if field.name() == "group" {
+            let mut value = Vec::new();
+            while let Some(chunk) = field.try_next().await? {
+                value.extend_from_slice(&chunk);
+            }
+            let value = String::from_utf8_lossy(&value).trim().to_string();
+            if value.is_empty() {
+                continue;
+            }
+            if let Err(message) = groups::validate(&value) {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "success": false,
+                    "message": message
+                })));
+            }
+            if !groups::is_member(&value) {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "success": false,
+                    "message": format!("This node is not in group {}", value)
+                })));
+            }
+            group = Some(value);
+        } else if field.name() == "peers" {
+            let mut value = Vec::new();
+            while let Some(chunk) = field.try_next().await? {
+                value.extend_from_slice(&chunk);
+            }
+            match parse_peer_list(&String::from_utf8_lossy(&value)) {
+                Ok(list) => peers = Some(list),
+                Err(message) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "success": false,
+                    "message": message
+                }))),
+            }
+        } else if field.name() == "file" {
             let filename = field.content_disposition()
                 .get_filename()
                 .unwrap_or("unknown")
@@ -523,99 +1009,111 @@ async fn upload_file(req: actix_web::HttpRequest, mut payload: Multipart) -> Res
             // Collect file data
             let mut file_data = Vec::new();
             while let Some(chunk) = field.try_next().await? {
+                bandwidth::record(bandwidth::Channel::Files, None, bandwidth::Direction::Down, chunk.len());
                 file_data.extend_from_slice(&chunk);
             }
-            // Enforce 50 MB upload limit
-            const MAX_UPLOAD_BYTES: usize = 50 * 1024 * 1024;
-            if file_data.len() > MAX_UPLOAD_BYTES {
-                println!("API: File too large ({} bytes), rejecting > 50MB", file_data.len());
+            // Enforce the configured upload limit
+            let max_upload_bytes = settings::current().await.max_file_size;
+            if file_data.len() as u64 > max_upload_bytes {
+                println!("API: File too large ({} bytes), rejecting > {} bytes", file_data.len(), max_upload_bytes);
                 return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
                     "success": false,
-                    "message": "File exceeds 50MB limit"
+                    "message": format!("File exceeds {} byte limit", max_upload_bytes)
                 })));
             }
-            
-            // Save file
-            // After save_uploaded_file(...)
-            match save_uploaded_file(&filename, &content_type, &file_data, &client_ip).await {
-                Ok(file_info) => {
-                    println!("API: File uploaded successfully: {}", filename);
-                    // Broadcast file to all peers (all types)
-                    let _ = broadcast_file_to_peers(filename.clone(), content_type.clone(), file_data.clone()).await;
-                    return Ok(HttpResponse::Ok().json(serde_json::json!({
-                        "success": true,
-                        "message": "File uploaded successfully",
-                        "file_info": file_info
-                    })));
-                }
-                Err(e) => {
-                    println!("API: File upload failed: {}", e);
-                    return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                        "success": false,
-                        "message": e.to_string()
-                    })));
-                }
+            upload = Some((filename, content_type, file_data));
+        }
+    }
+    let (filename, content_type, file_data) = match upload {
+        Some(u) => u,
+        None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "No file provided"
+        }))),
+    };
+
+    if peers.as_ref().is_some_and(|p| !p.is_empty()) && !visibility.is_mesh() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Only files shared with the mesh can be sent to peers"
+        })));
+    }
+    if let Some(group) = &group {
+        if !visibility.is_mesh() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "Only files shared with the mesh can be scoped to a group"
+            })));
+        }
+        if let Some(outsider) = peers.iter().flatten().find(|p| !groups::peer_in(p, group)) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": format!("Peer {} is not in group {}", outsider, group)
+            })));
+        }
+    }
+    // Save file
+    match persistence::save_uploaded_file_for(&filename, &content_type, &file_data, &client_ip, owner.as_deref(), visibility, group.as_deref()).await {
+        Ok(file_info) => {
+            events::info(
+                events::Category::File,
+                format!("Stored upload {} ({} bytes) from {}", filename, file_data.len(), client_ip),
+                serde_json::json!({ "filename": filename, "bytes": file_data.len(), "client": client_ip }),
+            );
+            events::publish("file", serde_json::json!({ "event": "uploaded", "source": "http", "file": file_info }));
+            // Send the file to the chosen peers, or broadcast it to all (all types) or to its group;
+            // files kept to this node go nowhere
+            match (peers, &group) {
+                _ if !visibility.is_mesh() => {}
+                (Some(peers), _) => tcp::send_file_to_peers(peers, filename.clone(), content_type.clone(), file_data.clone()).await,
+                (None, Some(group)) => tcp::broadcast_file_to_group(group, filename.clone(), content_type.clone(), file_data.clone()).await,
+                (None, None) => broadcast_file_to_peers(filename.clone(), content_type.clone(), file_data.clone()).await,
             }
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "File uploaded successfully",
+                "file_info": file_info
+            })))
+        }
+        Err(e) => {
+            println!("API: File upload failed: {}", e);
+            Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": e.to_string()
+            })))
         }
     }
-    
-    Ok(HttpResponse::BadRequest().json(serde_json::json!({
-        "success": false,
-        "message": "No file provided"
-    })))
 }
 
+// Peer IPs separated by commas or whitespace; an empty list keeps a file to ourselves
+fn parse_peer_list(value: &str) -> Result<Vec<String>, String> {
+    let mut peers = Vec::new();
+    for peer in value.split(|c: char| c == ',' || c.is_whitespace()).filter(|p| !p.is_empty()) {
+        let ip: std::net::IpAddr = peer.parse().map_err(|_| format!("Invalid peer address: {}", peer))?;
+        if !peers.contains(&ip.to_string()) {
+            peers.push(ip.to_string());
+        }
+    }
+    Ok(peers)
+}
+
+// The files a request may see (see persistence::Visibility): peers get only those shared with the
+// mesh, signed-in users also this node's files and their own private ones, and the admin all.
+// `user` narrows the list to one user's uploads, e.g. a user's own file space. Sorting, filters and
+// paging come from persistence::FileQuery; the body is the page and X-Total-Count how many matched.
 #[get("/files")]
-async fn get_files() -> Result<HttpResponse, Error> {
-    match list_uploaded_files().await {
+async fn get_files(query: web::Query<UserQuery>, files_query: web::Query<persistence::FileQuery>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let session = session.map(|s| s.into_inner());
+    let user = query.into_inner().user;
+    let files_query = files_query.into_inner();
+    if let Err(message) = files_query.validate() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": message })));
+    }
+    let keep = |f: &FileInfo| f.visible_to(session.as_ref()) && user.as_ref().is_none_or(|u| f.owner.as_ref() == Some(u));
+    match aggregate_files_matching(&files_query, keep).await {
         Ok(mut files) => {
-            // Merge announced peer files (from FILE_META) without duplicates
-            let mut set: std::collections::HashSet<(String, String)> = files
-                .iter()
-                .map(|f| (f.filename.clone(), f.uploader_ip.clone()))
-                .collect();
-            let local_count = files.len();
-            let announced = get_announced_files().await;
-            let mut announced_added = 0usize;
-            for af in announced {
-                let key = (af.filename.clone(), af.uploader_ip.clone());
-                if !set.contains(&key) {
-                    files.push(af);
-                    set.insert(key);
-                    announced_added += 1;
-                }
-            }
-            // Also merge in files physically present under received/<peer-ip>/ (peer binaries)
-            if let Ok(received) = list_received_files().await {
-                let mut received_added = 0usize;
-                for rf in received {
-                    let key = (rf.filename.clone(), rf.uploader_ip.clone());
-                    if !set.contains(&key) {
-                        files.push(rf);
-                        set.insert(key);
-                        received_added += 1;
-                    }
-                }
-                println!("API: Merged {} received files from disk", received_added);
-            }
-            // Opportunistically fetch remote peer file lists and merge
-            if let Ok(mut remote) = fetch_remote_files().await {
-                let mut remote_added = 0usize;
-                for rf in remote.drain(..) {
-                    let key = (rf.filename.clone(), rf.uploader_ip.clone());
-                    if !set.contains(&key) {
-                        files.push(rf);
-                        set.insert(key);
-                        remote_added += 1;
-                    }
-                }
-                println!("API: Merged {} files from remote peers", remote_added);
-            }
-            println!(
-                "API: Listed {} files (local={}, announced_added={}, received_added logged above, remote_added logged above)",
-                files.len(), local_count, announced_added
-            );
-            Ok(HttpResponse::Ok().json(files))
+            let total = files_query.page(&mut files);
+            Ok(HttpResponse::Ok().insert_header(("X-Total-Count", total.to_string())).json(files))
         }
         Err(e) => {
             println!("API: Failed to list files: {}", e);
@@ -627,7 +1125,53 @@ async fn get_files() -> Result<HttpResponse, Error> {
     }
 }
 
-// Helper: fetch remote /api/files from known peers (derived from received/<peer-ip>/)
+// Every file aggregate_files_matching finds, newest first. Shared by the gRPC service.
+async fn aggregate_files() -> std::io::Result<Vec<FileInfo>> {
+    let query = persistence::FileQuery::default();
+    let mut files = aggregate_files_matching(&query, |_| true).await?;
+    query.sort(&mut files);
+    Ok(files)
+}
+
+// Merge local uploads, announced peer files, received binaries and live peer lists,
+// de-duplicated by (filename, uploader_ip), taking only files `query` and `keep` match. Local
+// uploads are filtered in the metadata cache, and the sources `query.source` leaves out are not
+// read at all, so nothing is listed in full only to be thrown away. Unsorted; see FileQuery::page.
+async fn aggregate_files_matching(query: &persistence::FileQuery, keep: impl Fn(&FileInfo) -> bool) -> std::io::Result<Vec<FileInfo>> {
+    let mut files = if query.wants_local() { persistence::query_uploaded_files(query, &keep).await? } else { Vec::new() };
+    let local_count = files.len();
+    if !query.wants_peers() {
+        return Ok(files);
+    }
+    // Announced peer files (from FILE_META), files physically present under received/<peer-ip>/
+    // (peer binaries) and, opportunistically, the lists connected peers send
+    let announced = get_announced_files().await;
+    let received = list_received_files().await.unwrap_or_default();
+    let remote = fetch_remote_files().await.unwrap_or_default();
+    let mut set: std::collections::HashSet<(String, String)> = files
+        .iter()
+        .map(|f| (f.filename.clone(), f.uploader_ip.clone()))
+        .collect();
+    let mut merge = |source: Vec<FileInfo>, files: &mut Vec<FileInfo>| {
+        let mut added = 0usize;
+        for f in source.into_iter().filter(|f| query.matches(f) && keep(f)) {
+            if set.insert((f.filename.clone(), f.uploader_ip.clone())) {
+                files.push(f);
+                added += 1;
+            }
+        }
+        added
+    };
+    let announced_added = merge(announced, &mut files);
+    let received_added = merge(received, &mut files);
+    let remote_added = merge(remote, &mut files);
+    println!(
+        "API: Listed {} files (local={}, announced_added={}, received_added={}, remote_added={})",
+        files.len(), local_count, announced_added, received_added, remote_added
+    );
+    Ok(files)
+}
+
 async fn fetch_remote_files() -> Result<Vec<FileInfo>, ()> {
     // --- Simple throttle/cache to avoid spamming peers and logs ---
     struct RemoteCache { last: std::time::Instant, data: Vec<FileInfo>, fetching: bool }
@@ -636,7 +1180,7 @@ async fn fetch_remote_files() -> Result<Vec<FileInfo>, ()> {
     {
         let mut c = cache.lock().unwrap();
         let age = c.last.elapsed();
-        if age < std::time::Duration::from_secs(15) || c.fetching {
+        if age < power::interval(std::time::Duration::from_secs(15)) || c.fetching {
             // Return cached data to throttle calls
             return Ok(c.data.clone());
         }
@@ -645,80 +1189,27 @@ async fn fetch_remote_files() -> Result<Vec<FileInfo>, ()> {
     }
 
     let mut out: Vec<FileInfo> = Vec::new();
-    // Build a unique set of peer IPs from received/ and from conversation store
-    let mut peer_ips: std::collections::HashSet<String> = std::collections::HashSet::new();
-    let base = std::path::Path::new(RECEIVED_DIR);
-    if base.exists() {
-        if let Ok(mut rd) = tokio::fs::read_dir(base).await {
-            while let Ok(Some(entry)) = rd.next_entry().await {
-                if let Ok(ft) = entry.file_type().await {
-                    if ft.is_dir() {
-                        peer_ips.insert(entry.file_name().to_string_lossy().to_string());
-                    }
-                }
+    // Each connected peer is asked over its TCP connection, see catalog
+    for ip in tcp::connected_peer_ips().await {
+        match catalog::fetch(&ip).await {
+            Ok(mut list) => {
+                let count = list.len();
+                events::info(
+                    events::Category::Discovery,
+                    format!("Peer {} lists {} files", ip, count),
+                    serde_json::json!({ "peer": ip, "files": count }),
+                );
+                out.append(&mut list);
             }
-        }
-    }
-    // Also add peers known from conversations
-    let peers_map = CONVERSATION_STORE.get_peer_conversations().await;
-    for (peer_ip, _conv) in peers_map.iter() {
-        peer_ips.insert(peer_ip.clone());
-    }
-
-    let client = reqwest::Client::builder()
-        .no_proxy()
-        .timeout(std::time::Duration::from_secs(6))
-        .build()
-        .map_err(|_| ())?;
-    for ip in peer_ips.into_iter() {
-        let url = format!("http://{}:8080/api/files", ip);
-        println!("API: fetch_remote_files: contacting peer {} at {}", ip, url);
-        let mut attempt = 0;
-        let max_attempts = 2;
-        let mut success = false;
-        while attempt < max_attempts {
-            attempt += 1;
-            let req = client
-                .get(&url)
-                .header("x-peer-llm", "1")
-                .header("Connection", "close");
-            match req.send().await {
-                Ok(resp) => {
-                    let status = resp.status();
-                    match resp.json::<Vec<FileInfo>>().await {
-                        Ok(mut list) => {
-                            let count = list.len();
-                            println!(
-                                "API: fetch_remote_files: peer {} responded {} with {} files (attempt {})",
-                                ip, status, count, attempt
-                            );
-                            out.append(&mut list);
-                            success = true;
-                        }
-                        Err(e) => {
-                            println!(
-                                "API: fetch_remote_files: failed to parse JSON from {} (status {}, attempt {}): {}",
-                                ip, status, attempt, e
-                            );
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!(
-                        "API: fetch_remote_files: error contacting {} (attempt {}): {}",
-                        ip, attempt, e
-                    );
-                }
+            // Peers without file lists have nothing to merge
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {}
+            Err(e) => {
+                events::warn(
+                    events::Category::Discovery,
+                    format!("Could not fetch the file list of {}", ip),
+                    serde_json::json!({ "peer": ip, "error": e.to_string() }),
+                );
             }
-            if success { break; }
-            // simple backoff
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-        }
-        if !success {
-            println!(
-                "API: fetch_remote_files: giving up on {} after {} attempts",
-                ip, max_attempts
-            );
         }
     }
     // update cache
@@ -732,11 +1223,25 @@ async fn fetch_remote_files() -> Result<Vec<FileInfo>, ()> {
 }
 
 #[get("/files/{filename}")]
-async fn download_file(path: web::Path<String>) -> Result<HttpResponse, Error> {
+async fn download_file(req: actix_web::HttpRequest, path: web::Path<String>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
     let filename = path.into_inner();
-    
+    // Files the caller may not see, or a peer outside the file's group, are reported missing rather
+    // than forbidden
+    let session = session.map(|s| s.into_inner());
+    let peer = bandwidth::requesting_peer(&req);
+    if persistence::get_file_info(&filename).await.ok().flatten().is_some_and(|f| {
+        !f.visible_to(session.as_ref()) || peer.as_deref().is_some_and(|ip| !groups::shares_with(ip, f.group.as_deref()))
+    }) {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "File not found"
+        })));
+    }
+
     match get_file_content(&filename).await {
         Ok(Some(content)) => {
+            popularity::record(&filename, popularity::Via::of(&req)).await;
+            bandwidth::record(bandwidth::Channel::Files, bandwidth::requesting_peer(&req).as_deref(), bandwidth::Direction::Up, content.len());
             // Get file info for content type
             if let Ok(Some(file_info)) = persistence::get_file_info(&filename).await {
                 Ok(HttpResponse::Ok()
@@ -764,8 +1269,178 @@ async fn download_file(path: web::Path<String>) -> Result<HttpResponse, Error> {
     }
 }
 
+#[derive(serde::Deserialize)]
+struct TagsBody { tags: Vec<String> }
+
+#[put("/files/{filename}/tags")]
+async fn set_file_tags(path: web::Path<String>, body: web::Json<TagsBody>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let filename = path.into_inner();
+    let session = session.map(|s| s.into_inner());
+    let info = persistence::get_file_info(&filename).await.ok().flatten();
+    let Some(info) = info.filter(|f| f.visible_to(session.as_ref())) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "File not found"
+        })));
+    };
+    // Tags pick what replication rules send, so only the owner and the admin may change them
+    if !info.managed_by(session.as_ref()) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Only the file's owner or the admin can tag it"
+        })));
+    }
+    let mut tags: Vec<String> = body.into_inner().tags
+        .into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    match persistence::set_file_tags(&filename, tags).await {
+        Ok(Some(file_info)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "file_info": file_info
+        }))),
+        Ok(None) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "File not found"
+        }))),
+        Err(e) => {
+            println!("API: Failed to tag file {}: {}", filename, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": e.to_string()
+            })))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct VisibilityBody { visibility: persistence::Visibility }
+
+// Change who may see one of our uploads; only its owner and the admin may. Copies peers already
+// received stay with them.
+#[put("/files/{filename}/visibility")]
+async fn set_file_visibility(path: web::Path<String>, body: web::Json<VisibilityBody>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let filename = path.into_inner();
+    let session = session.map(|s| s.into_inner());
+    let info = persistence::get_file_info(&filename).await.ok().flatten();
+    let Some(info) = info.filter(|f| f.visible_to(session.as_ref())) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "File not found"
+        })));
+    };
+    if !info.managed_by(session.as_ref()) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Only the file's owner or the admin can change who sees it"
+        })));
+    }
+    match persistence::set_file_visibility(&filename, body.visibility).await {
+        Ok(Some(file_info)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "file_info": file_info
+        }))),
+        Ok(None) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "File not found"
+        }))),
+        Err(e) => {
+            println!("API: Failed to set visibility of {}: {}", filename, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": e.to_string()
+            })))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ShareBody { peers: Vec<String> }
+
+// Send one of our uploads to specific peers, e.g. one that was uploaded for nobody or a peer that
+// was offline at the time
+#[post("/files/{filename}/share")]
+async fn share_file(path: web::Path<String>, body: web::Json<ShareBody>, session: Option<web::ReqData<users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let filename = path.into_inner();
+    let session = session.map(|s| s.into_inner());
+    let peers = match parse_peer_list(&body.peers.join(",")) {
+        Ok(peers) if !peers.is_empty() => peers,
+        Ok(_) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "No peers given"
+        }))),
+        Err(message) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": message
+        }))),
+    };
+    let info = persistence::list_uploaded_files().await.ok().and_then(|files| files.into_iter().find(|f| f.filename == filename));
+    if info.as_ref().is_some_and(|f| !f.visible_to(session.as_ref())) {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "File not found"
+        })));
+    }
+    if info.as_ref().is_some_and(|f| !f.managed_by(session.as_ref())) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Only the file's owner or the admin can send it to peers"
+        })));
+    }
+    if info.as_ref().is_some_and(|f| !f.visibility.is_mesh()) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Only files shared with the mesh can be sent to peers"
+        })));
+    }
+    if let Some(group) = info.as_ref().and_then(|f| f.group.as_deref()) {
+        if let Some(outsider) = peers.iter().find(|p| !groups::peer_in(p, group)) {
+            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+                "success": false,
+                "message": format!("Peer {} is not in group {}", outsider, group)
+            })));
+        }
+    }
+    let content = match persistence::get_file_content(&filename).await {
+        Ok(Some(content)) => content,
+        Ok(None) => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "File not found"
+        }))),
+        Err(e) => {
+            println!("API: Failed to read file {} for sharing: {}", filename, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": e.to_string()
+            })));
+        }
+    };
+    let file_type = info
+        .map(|f| f.file_type)
+        .unwrap_or_else(|| mime_guess::from_path(&filename).first_or_octet_stream().to_string());
+    println!("API: Sharing {} with {}", filename, peers.join(", "));
+    tcp::send_file_to_peers(peers.clone(), filename, file_type, content).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "peers": peers
+    })))
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // install-service / uninstall-service / migrate / simulate / pack-ui run instead of the node
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(code) = service::handle_command(&args)
+        .or_else(|| migrate::handle_command(&args))
+        .or_else(|| simulate::handle_command(&args))
+        .or_else(|| update::ui::handle_command(&args))
+    {
+        std::process::exit(code);
+    }
+    simulate::exit_with_simulator();
     println!("[DEBUG] Starting backend...");
     // Initialize conversations directory silently
     if let Err(e) = persistence::init_conversations_dir().await {
@@ -773,6 +1448,13 @@ async fn main() -> std::io::Result<()> {
         return Err(e);
     }
     println!("[DEBUG] Conversations directory initialized.");
+    events::init().await;
+    events::info(events::Category::System, format!("Starting version {}", env!("CARGO_PKG_VERSION")), serde_json::Value::Null);
+    match persistence::remove_orphan_temp_files().await {
+        Ok(0) => {}
+        Ok(n) => println!("[DEBUG] Removed {} temporary file(s) left by an interrupted write.", n),
+        Err(e) => eprintln!("[DEBUG] Error scanning for temporary files: {}", e),
+    }
 
     // Load saved conversations
     match CONVERSATION_STORE.load_saved_conversations().await {
@@ -785,6 +1467,12 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    // Pick the power profile and fault injection settings before any background loop reads them
+    power::profile();
+    chaos::config();
+    // Peers are told our groups as soon as they connect
+    groups::load().await;
+    knowledge::load().await;
     let received_ips = Arc::new(Mutex::new(HashSet::new()));
     let received_ips_clone = received_ips.clone();
 
@@ -809,15 +1497,50 @@ async fn main() -> std::io::Result<()> {
     let received_ips_clone = received_ips.clone();
     tokio::spawn(connect_to_peers(received_ips_clone));
 
-    println!("[DEBUG] Opening web browser...");
-    // Open web browser silently
-    let _ = open::that("http://localhost:8080/app/");
+    // Start MQTT bridge (no-op unless MQTT_BROKER is set)
+    tokio::spawn(mqtt::start());
+
+    // Start transcript publisher (no-op unless TRANSCRIPT_INTERVAL_SECS is set)
+    tokio::spawn(export::periodic_transcript_share());
+
+    // Start drop-folder import (no-op unless DROP_FOLDER is set)
+    tokio::spawn(dropfolder::watch_drop_folder());
+
+    // Start replication scheduler (runs rules from replication_rules.json as jobs)
+    tokio::spawn(replication::scheduler());
+    tokio::spawn(llm::schedules::scheduler());
+    tokio::spawn(llm::backend::preload_on_start());
+    tokio::spawn(tcp::announced_files_gc());
+    tokio::spawn(chunkstore::gc_loop());
+    tokio::spawn(popularity::retention_loop());
+    tokio::spawn(tcp::delivery_checker());
+    tokio::spawn(latency::http_prober());
+    tokio::spawn(storage::recorder());
+
+    // Tray icon and native notifications (only built with --features desktop)
+    #[cfg(feature = "desktop")]
+    desktop::start();
+
+    // Start update checker (idle unless enabled in settings)
+    update::cleanup_previous();
+    update::ui::load().await;
+    tokio::spawn(update::checker());
+
+    // Open web browser silently, unless running as a service (NO_BROWSER=1)
+    if env::var("NO_BROWSER").map(|v| v != "1").unwrap_or(true) {
+        println!("[DEBUG] Opening web browser...");
+        let _ = open::that(tls::ui_url());
+    }
     
-    println!("[DEBUG] Starting HTTP server on 0.0.0.0:8080...");
+    println!("[DEBUG] Starting {} server on {}:8080...", tls::scheme().to_uppercase(), ip::listen_host());
     // Prepare shared state and secrets
     let perf_state = web::Data::new(tokio::sync::Mutex::new(PerfState::default()));
+    let graphql_schema = web::Data::new(graphql::build_schema());
     // Load node auth creds
     let node_auth = load_node_creds();
+    users::set_admin(&node_auth.username);
+    users::oidc::configure(&settings::current().await.oidc);
+    limits::configure(&settings::current().await.limits);
     let node_auth_data = web::Data::new(node_auth.clone());
     let p2p_secret_string = match get_or_create_hmac_secret().await {
         Ok(s) => s,
@@ -834,7 +1557,14 @@ async fn main() -> std::io::Result<()> {
     let p2p_secret = web::Data::new(p2p_secret_string.clone());
     // Provide secret to TCP module for HMAC verification/creation
     set_p2p_secret(p2p_secret_string.clone()).await;
-    HttpServer::new(move || {
+    let tls_config = if tls::enabled() {
+        Some(tls::server_config().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("TLS: {}", e)))?)
+    } else {
+        None
+    };
+    // Start gRPC service alongside HTTP (no-op unless GRPC_PORT is set)
+    tokio::spawn(grpc::serve(node_auth.password.clone(), tls_config.clone()));
+    let server = HttpServer::new(move || {
         let perf_state_clone = perf_state.clone();
         let p2p_secret_clone = p2p_secret.clone();
         let node_auth_clone = node_auth_data.clone();
@@ -842,6 +1572,20 @@ async fn main() -> std::io::Result<()> {
             .app_data(perf_state_clone.clone())
             .app_data(p2p_secret_clone.clone())
             .app_data(node_auth_clone.clone())
+            .app_data(graphql_schema.clone())
+            .app_data(limits::json_config())
+            // Body size and concurrency limits; registered first so only signed-in requests count
+            .wrap_fn(|mut req, srv| match limits::check(&mut req) {
+                Ok(slot) => {
+                    let fut = srv.call(req);
+                    Either::Right(async move {
+                        let res = fut.await;
+                        drop(slot);
+                        res
+                    })
+                }
+                Err(resp) => Either::Left(ready(Ok(req.into_response(resp.map_into_boxed_body())))),
+            })
             // Auth guard middleware
             .wrap_fn(move |req, srv| {
                 let path = req.path().to_string();
@@ -853,32 +1597,56 @@ async fn main() -> std::io::Result<()> {
                     let is_internal_peer_chat = path == "/api/chat"
                         && req.method() == actix_web::http::Method::POST
                         && req.headers().get("x-peer-llm").map(|v| v == "1" || v == "yes").unwrap_or(false);
-                    // Allow internal peer FILE fetches: GET /api/files and /api/files/<name> with header x-peer-llm
-                    let is_internal_peer_file = (path == "/api/files" || path.starts_with("/api/files/"))
+                    // Allow internal peer FILE fetches: GET /api/files/<name> with header x-peer-llm (file lists go over TCP, see catalog)
+                    let is_internal_peer_file = path.starts_with("/api/files/")
                         && req.method() == actix_web::http::Method::GET
                         && req.headers().get("x-peer-llm").map(|v| v == "1" || v == "yes").unwrap_or(false);
                     // Allow internal peer proxy downloads: GET /api/peer-file/<ip>/<filename> with header x-peer-llm
                     let is_internal_peer_proxy = path.starts_with("/api/peer-file/")
                         && req.method() == actix_web::http::Method::GET
                         && req.headers().get("x-peer-llm").map(|v| v == "1" || v == "yes").unwrap_or(false);
-                    if is_internal_peer_chat || is_internal_peer_file || is_internal_peer_proxy {
+                    // Peer routes below need a request signed with the P2P secret (tcp::sign_peer_request);
+                    // anyone can send x-peer-llm
+                    let signed_by_peer = || {
+                        let header = req.headers().get(tcp::PEER_REQUEST_HEADER).and_then(|v| v.to_str().ok());
+                        tcp::peer_request_authentic(&p2p_secret_clone, req.method().as_str(), &path, header)
+                    };
+                    // Allow replicating peers to list our file hashes: signed GET /api/replication/hashes
+                    let is_internal_peer_hashes = path == "/api/replication/hashes"
+                        && req.method() == actix_web::http::Method::GET
+                        && signed_by_peer();
+                    // Allow segmented downloads between peers: signed GET/HEAD /api/replica/<sha256>[/segments]
+                    let is_internal_peer_replica = path.starts_with("/api/replica/")
+                        && (req.method() == actix_web::http::Method::GET || req.method() == actix_web::http::Method::HEAD)
+                        && signed_by_peer();
+                    // Allow dedup transfers to pull missing chunks: signed GET /api/chunks/<sha256>
+                    let is_internal_peer_chunk = path.starts_with("/api/chunks/")
+                        && req.method() == actix_web::http::Method::GET
+                        && signed_by_peer();
+                    // Allow peers to stream recordings for /api/peer-media: signed GET /api/media/<name>
+                    let is_internal_peer_media = path.starts_with("/api/media/")
+                        && req.method() == actix_web::http::Method::GET
+                        && signed_by_peer();
+                    // Allow peers to ask for our verified update build and UI bundle: signed GET /api/update/release|ui
+                    let is_internal_peer_update = (path == "/api/update/release" || path == "/api/update/ui")
+                        && req.method() == actix_web::http::Method::GET
+                        && signed_by_peer();
+                    if is_internal_peer_chat || is_internal_peer_file || is_internal_peer_proxy || is_internal_peer_hashes || is_internal_peer_replica || is_internal_peer_chunk || is_internal_peer_media || is_internal_peer_update {
                         return Either::Right(srv.call(req));
                     }
-                    let ok = req.cookie("session").and_then(|c| {
-                        let (_, dk) = jwt_keys(&node_auth_clone.password);
-                        decode::<Claims>(c.value(), &dk, &Validation::new(Algorithm::HS256)).ok()
-                    }).is_some();
-                    if !ok {
+                    // Sessions of removed accounts end with the account
+                    let user = req.cookie("session").and_then(|c| session_user(&node_auth_clone.password, c.value()));
+                    let Some(user) = user else {
                         let resp = HttpResponse::Unauthorized().json(serde_json::json!({"error": "unauthorized"}));
                         return Either::Left(ready(Ok(req.into_response(resp.map_into_boxed_body()))));
-                    }
+                    };
+                    req.extensions_mut().insert(user);
                 }
                 Either::Right(srv.call(req))
             })
             .wrap_fn(move |req, srv| {
-                let path = req.path().to_string();
                 let method = req.method().to_string();
-                let key = format!("{} {}", method, path);
+                let mut key = perf_route_key(&method, req.match_pattern());
                 let start = Instant::now();
                 let state = perf_state_clone.clone();
                 let fut = srv.call(req);
@@ -889,20 +1657,30 @@ async fn main() -> std::io::Result<()> {
                     let resp_status = res.status();
                     {
                         let mut ps = state.lock().await;
+                        if ps.per_route.len() >= MAX_PERF_ROUTES && !ps.per_route.contains_key(&key) {
+                            key = format!("{} (other)", method);
+                        }
                         let entry = ps.per_route.entry(key).or_insert_with(RouteStats::default);
                         entry.durations_ms.push(ms);
-                        if entry.durations_ms.len() > 1000 { entry.durations_ms.remove(0); }
+                        if entry.durations_ms.len() > power::retention(1000) { entry.durations_ms.remove(0); }
                         entry.req_count += 1;
-                        if resp_status.as_u16() >= 500 { entry.error_count += 1; }
+                        if resp_status.is_server_error() { entry.error_count += 1; }
+                        if resp_status.is_client_error() { entry.client_error_count += 1; }
 
                         ps.totals.durations_ms.push(ms);
-                        if ps.totals.durations_ms.len() > 5000 { ps.totals.durations_ms.remove(0); }
+                        if ps.totals.durations_ms.len() > power::retention(5000) { ps.totals.durations_ms.remove(0); }
                         ps.totals.req_count += 1;
-                        if resp_status.as_u16() >= 500 { ps.totals.error_count += 1; }
+                        if resp_status.is_server_error() { ps.totals.error_count += 1; }
+                        if resp_status.is_client_error() { ps.totals.client_error_count += 1; }
                     }
                     Ok(res)
                 }
             })
+            // Security headers; registered after the auth guard so its rejections get them too
+            .wrap_fn(|req, srv| {
+                let fut = srv.call(req);
+                async move { Ok(headers::apply(fut.await?).await) }
+            })
             .wrap(
                 Cors::default()
                     .allow_any_origin()
@@ -911,27 +1689,159 @@ async fn main() -> std::io::Result<()> {
                 .expose_headers(["content-type", "content-length"])
                 .max_age(3600)
         )
+            // Outermost, so everything inside routes as if the node were served at the root
+            .wrap_fn(|mut req, srv| {
+                proxy::strip_base_path(&mut req);
+                srv.call(req)
+            })
             .service(web::scope("/api")
                 .service(llm::chat)
+                .service(llm::fanout::chat_fanout)
+                .service(llm::batch::submit_batch)
+                .service(llm::batch::get_batch)
+                .service(llm::regenerate_message)
+                .service(llm::list_alternatives)
+                .service(llm::prefer_message)
+                .service(llm::edit_message)
+                .service(llm::pin_message)
+                .service(votes::vote_message)
+                .service(fork::list_forks)
+                .service(fork::get_fork)
+                .service(fork::fork_conversation)
+                .service(presence::get_participants)
+                .service(presence::websocket)
+                .service(audit::list_audit)
+                .service(update::update_status)
+                .service(update::check_update)
+                .service(update::apply_update)
+                .service(update::get_release)
+                .service(update::ui::get_ui)
+                .service(update::ui::publish_ui)
+                .service(update::ui::remove_ui)
+                .service(redact::list_rules)
+                .service(redact::create_rule)
+                .service(redact::delete_rule)
+                .service(redact::test_rules)
+                .service(llm::templates::list_templates)
+                .service(llm::templates::create_template)
+                .service(llm::templates::update_template)
+                .service(llm::templates::delete_template)
+                .service(llm::schedules::list_schedules)
+                .service(llm::schedules::create_schedule)
+                .service(llm::schedules::delete_schedule)
+                .service(llm::schedules::run_schedule_now)
                 .service(upload_file)
                 .service(get_files)
                 .service(api_status)
+                .service(api_peers_info)
+                .service(get_local)
+                .service(get_timeline)
+                .service(set_conversation_privacy)
+                .service(set_conversation_group)
+                .service(set_conversation_title)
+                .service(get_thread)
+                .service(users::list_users)
+                .service(users::add_user)
+                .service(users::remove_user)
+                .service(diagnostics::peer_diagnostics)
+                .service(history::peer_conversation)
+                .service(topology::mesh_topology)
+                .service(telemetry::llm_peers)
+                .service(llm::backend::preload)
+                .service(llm::tokens::count_tokens)
                 .service(download_file)
+                .service(set_file_tags)
+                .service(set_file_visibility)
+                .service(share_file)
                 .service(proxy_peer_file)
                 .service(analytics_chat)
+                .service(votes::model_leaderboard)
                 .service(analytics_files)
                 .service(analytics_engagement)
                 .service(analytics_perf)
                 .service(analytics_network)
+                .service(latency::analytics_latency)
+                .service(limits::analytics_limits)
+                .service(storage::analytics_storage)
+                .service(maintenance::get_maintenance)
+                .service(maintenance::announce_maintenance)
+                .service(maintenance::cancel_maintenance)
+                .service(groups::get_groups)
+                .service(groups::join_group)
+                .service(groups::leave_group)
+                .service(knowledge::list_knowledge)
+                .service(knowledge::save_answer)
+                .service(knowledge::delete_entry)
                 .service(auth_login)
                 .service(auth_status)
-                .service(auth_logout))
+                .service(auth_logout)
+                .service(users::oidc::oidc_login)
+                .service(users::oidc::oidc_callback)
+                .service(export::export_conversation)
+                .service(graphql::graphql_handler)
+                .service(graphql::graphiql)
+                .service(jobs::list_jobs)
+                .service(events::list_events)
+                .service(transfers::list_transfers)
+                .service(transfers::pause_transfer)
+                .service(transfers::resume_transfer)
+                .service(transfers::cancel_transfer)
+                .service(jobs::get_job)
+                .service(replication::list_rules)
+                .service(replication::create_rule)
+                .service(replication::delete_rule)
+                .service(replication::run_rule_now)
+                .service(replication::list_hashes)
+                .service(pipelines::list_pipelines)
+                .service(pipelines::create_pipeline)
+                .service(pipelines::delete_pipeline)
+                .service(pipelines::run_pipeline_now)
+                .service(fetch::get_segments)
+                .service(fetch::get_replica)
+                .service(fetch::fetch_file)
+                .service(chunkstore::get_chunk_handler)
+                .service(settings::get_settings)
+                .service(settings::put_settings)
+                .service(media::media_info)
+                .service(media::stream_media)
+                .service(media::proxy_peer_media)
+                .service(transcribe::get_transcript)
+                .service(transcribe::create_transcript))
+            .configure(webdav::configure)
             .service(get_peers)
-            .service(get_local)
+            .service(public::public_page)
+            .service(public::public_data)
+            .service(public::public_file)
             .service(get_index)
             .service(get_root_files)
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
+    });
+    let result = match tls_config {
+        Some(config) => server.bind_rustls_0_23((crate::ip::listen_host(), 8080), config)?.run().await,
+        None => server.bind((crate::ip::listen_host(), 8080))?.run().await,
+    };
+    // The server stopped on a signal: let peers mark us offline now
+    tcp::say_goodbye().await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, TestRequest};
+
+    // Only the admin renames a conversation; the check comes before the title is even looked at
+    #[actix_web::test]
+    async fn only_the_admin_renames_conversations() {
+        let app = init_service(App::new().service(set_conversation_title)).await;
+        let rename = |user: Option<users::SessionUser>| {
+            let req = TestRequest::put().uri("/conversations/local/title").set_json(serde_json::json!({ "title": "x\ny" })).to_request();
+            if let Some(user) = user {
+                req.extensions_mut().insert(user);
+            }
+            req
+        };
+        assert_eq!(call_service(&app, rename(None)).await.status(), 403);
+        assert_eq!(call_service(&app, rename(Some(users::SessionUser { name: "bob".into(), admin: false }))).await.status(), 403);
+        assert_eq!(call_service(&app, rename(Some(users::SessionUser { name: "root".into(), admin: true }))).await.status(), 400);
+    }
 }
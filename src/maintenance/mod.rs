@@ -0,0 +1,223 @@
+// Planned downtime and graceful shutdown. The admin announces a maintenance window with POST
+// /api/maintenance (when it starts, when the node should be back, and a note). Connected peers
+// get it at once over a MNTN: frame, and peers that connect later get it after the handshake. On
+// a graceful shutdown the node sends every peer a GBYE: frame carrying the window's return time,
+// so peers mark it offline straight away instead of waiting for the connection to time out.
+// GET /api/maintenance lists our window and what peers announced or said when leaving, so the
+// UI can show "back at 14:00".
+use actix_web::{delete, get, post, web, Error, HttpResponse};
+use chrono::{DateTime, TimeZone, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+
+const MAX_NOTE_LEN: usize = 280;
+
+// A maintenance window; times are unix milliseconds on the sender's clock
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    pub starts_at_ms: i64,
+    // None when the node cannot say when it will be back
+    pub back_at_ms: Option<i64>,
+    pub note: String,
+}
+
+// Sent to every peer when the node stops gracefully
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Goodbye {
+    pub back_at_ms: Option<i64>,
+    pub note: String,
+}
+
+pub fn validate_note(note: &str) -> Result<(), String> {
+    if note.chars().count() > MAX_NOTE_LEN {
+        return Err(format!("note is longer than {} characters", MAX_NOTE_LEN));
+    }
+    if note.chars().any(|c| c.is_control()) {
+        return Err("note may not contain control characters".to_string());
+    }
+    Ok(())
+}
+
+impl Window {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_note(&self.note)?;
+        if self.back_at_ms.is_some_and(|back| back <= self.starts_at_ms) {
+            return Err("back_at must be after starts_at".to_string());
+        }
+        Ok(())
+    }
+
+    // Whether the window is over at `now_ms`
+    pub fn is_over(&self, now_ms: i64) -> bool {
+        self.back_at_ms.is_some_and(|back| back <= now_ms)
+    }
+}
+
+// What a peer told us about its downtime; times are on our clock
+#[derive(Debug, Clone, Default)]
+struct PeerNotice {
+    window: Option<Window>,
+    // When it said goodbye, and what it said
+    goodbye: Option<(i64, Goodbye)>,
+}
+
+static LOCAL: Lazy<StdMutex<Option<Window>>> = Lazy::new(|| StdMutex::new(None));
+static PEERS: Lazy<StdMutex<HashMap<String, PeerNotice>>> = Lazy::new(|| StdMutex::new(HashMap::new()));
+
+// Our announced window, unless it is over
+pub fn local() -> Option<Window> {
+    LOCAL.lock().unwrap().clone().filter(|w| !w.is_over(crate::clock::now_ms()))
+}
+
+// What we tell peers on a graceful shutdown
+pub fn goodbye() -> Goodbye {
+    match local() {
+        Some(window) => Goodbye { back_at_ms: window.back_at_ms, note: window.note },
+        None => Goodbye { back_at_ms: None, note: String::new() },
+    }
+}
+
+fn shift(ms: i64, correction: Option<i64>) -> i64 {
+    ms + correction.unwrap_or(0)
+}
+
+// A peer connected: whatever it announced before is replaced by what it sends now
+pub fn peer_connected(peer_ip: &str) {
+    PEERS.lock().unwrap().remove(peer_ip);
+}
+
+// A peer announced (Some) or called off (None) a maintenance window
+pub async fn window_received(peer_ip: &str, window: Option<Window>) {
+    let correction = crate::clock::correction_ms(peer_ip).await;
+    let window = window.map(|w| Window {
+        starts_at_ms: shift(w.starts_at_ms, correction),
+        back_at_ms: w.back_at_ms.map(|b| shift(b, correction)),
+        note: w.note,
+    });
+    match &window {
+        Some(w) => println!("MAINTENANCE: {} plans maintenance from {} until {}", peer_ip, w.starts_at_ms, w.back_at_ms.map_or("unknown".to_string(), |b| b.to_string())),
+        None => println!("MAINTENANCE: {} called off its maintenance", peer_ip),
+    }
+    PEERS.lock().unwrap().entry(peer_ip.to_string()).or_default().window = window;
+}
+
+pub async fn goodbye_received(peer_ip: &str, goodbye: Goodbye) {
+    let correction = crate::clock::correction_ms(peer_ip).await;
+    let goodbye = Goodbye { back_at_ms: goodbye.back_at_ms.map(|b| shift(b, correction)), note: goodbye.note };
+    PEERS.lock().unwrap().entry(peer_ip.to_string()).or_default().goodbye = Some((crate::clock::now_ms(), goodbye));
+}
+
+fn time(ms: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(ms).single()
+}
+
+fn window_json(window: &Window) -> serde_json::Value {
+    serde_json::json!({
+        "starts_at": time(window.starts_at_ms),
+        "back_at": window.back_at_ms.and_then(time),
+        "note": window.note,
+    })
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can announce maintenance" }))
+}
+
+#[get("/maintenance")]
+pub async fn get_maintenance() -> Result<HttpResponse, Error> {
+    let online = crate::tcp::connected_peer_ips().await;
+    let now = crate::clock::now_ms();
+    let mut peers: Vec<(String, PeerNotice)> = PEERS.lock().unwrap().iter().map(|(ip, n)| (ip.clone(), n.clone())).collect();
+    peers.sort_by(|a, b| a.0.cmp(&b.0));
+    let peers: Vec<serde_json::Value> = peers
+        .into_iter()
+        .filter(|(_, n)| n.goodbye.is_some() || n.window.as_ref().is_some_and(|w| !w.is_over(now)))
+        .map(|(ip, notice)| {
+            let window = notice.window.filter(|w| !w.is_over(now));
+            let (left_at, back_at, note) = match &notice.goodbye {
+                Some((left_at, goodbye)) => (time(*left_at), goodbye.back_at_ms.and_then(time), Some(goodbye.note.clone())),
+                None => (None, None, None),
+            };
+            serde_json::json!({
+                "peer": ip,
+                "online": online.contains(&ip),
+                "maintenance": window.as_ref().map(window_json),
+                "left_at": left_at,
+                "back_at": back_at.or_else(|| window.as_ref().and_then(|w| w.back_at_ms).and_then(time)),
+                "note": note,
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "maintenance": local().as_ref().map(window_json),
+        "peers": peers,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct Announcement {
+    // Defaults to now
+    starts_at: Option<DateTime<Utc>>,
+    back_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    note: String,
+}
+
+#[post("/maintenance")]
+pub async fn announce_maintenance(body: web::Json<Announcement>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let body = body.into_inner();
+    let window = Window {
+        starts_at_ms: body.starts_at.map_or_else(crate::clock::now_ms, |t| t.timestamp_millis()),
+        back_at_ms: body.back_at.map(|t| t.timestamp_millis()),
+        note: body.note.trim().to_string(),
+    };
+    if let Err(e) = window.validate() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": e })));
+    }
+    if window.is_over(crate::clock::now_ms()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": "back_at is in the past" })));
+    }
+    *LOCAL.lock().unwrap() = Some(window.clone());
+    crate::events::info(crate::events::Category::System, "Maintenance announced", window_json(&window));
+    crate::tcp::broadcast_maintenance(Some(window.clone())).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "maintenance": window_json(&window) })))
+}
+
+#[delete("/maintenance")]
+pub async fn cancel_maintenance(user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    if LOCAL.lock().unwrap().take().is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "No maintenance is announced" })));
+    }
+    crate::events::info(crate::events::Category::System, "Maintenance called off", serde_json::Value::Null);
+    crate::tcp::broadcast_maintenance(None).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_note, Window};
+
+    #[test]
+    fn maintenance_windows_are_checked() {
+        let window = |starts_at_ms, back_at_ms, note: &str| Window { starts_at_ms, back_at_ms, note: note.into() };
+        assert!(window(1_000, Some(2_000), "back at 14:00").validate().is_ok());
+        assert!(window(1_000, None, "").validate().is_ok());
+        assert!(window(2_000, Some(2_000), "").validate().is_err());
+        assert!(window(1_000, Some(2_000), "a\nb").validate().is_err());
+        assert!(validate_note(&"é".repeat(280)).is_ok());
+        assert!(validate_note(&"é".repeat(281)).is_err());
+
+        assert!(!window(1_000, Some(2_000), "").is_over(1_999));
+        assert!(window(1_000, Some(2_000), "").is_over(2_000));
+        // Without a return time the window lasts until it is called off
+        assert!(!window(1_000, None, "").is_over(i64::MAX));
+    }
+}
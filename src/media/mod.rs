@@ -0,0 +1,402 @@
+// Audio/video preview: stored recordings are streamed with HTTP Range support so browsers can seek
+// without downloading the whole file, and /info reports duration and codecs. Metadata comes from
+// ffprobe when it is installed (FFPROBE_PATH, default "ffprobe"), otherwise from the WAV or MP4
+// headers directly.
+use actix_web::{get, web, HttpRequest, HttpResponse, Error};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use crate::persistence::{self, RECEIVED_DIR};
+use crate::users::SessionUser;
+
+const STREAM_CHUNK: usize = 64 * 1024;
+// moov boxes larger than this are not parsed
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct MediaQuery {
+    // Serve the copy received from this peer instead of a local upload
+    from: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MediaInfo {
+    pub container: Option<String>,
+    pub duration_secs: Option<f64>,
+    pub audio_codec: Option<String>,
+    pub video_codec: Option<String>,
+    pub source: &'static str,
+}
+
+// Local upload `user` may see, or a file received from `from`
+async fn resolve(filename: &str, from: Option<&str>, user: Option<&SessionUser>) -> std::io::Result<Option<(PathBuf, String)>> {
+    if from.is_none() && persistence::get_file_info(filename).await?.is_some_and(|f| !f.visible_to(user)) {
+        return Ok(None);
+    }
+    let path = match from {
+        Some(ip) => {
+            let name = match Path::new(filename).file_name() {
+                Some(n) if ip.parse::<std::net::IpAddr>().is_ok() => n.to_owned(),
+                _ => return Ok(None),
+            };
+            let p = Path::new(RECEIVED_DIR).join(ip).join(name);
+            if !p.is_file() {
+                return Ok(None);
+            }
+            p
+        }
+        None => match persistence::get_file_path(filename).await? {
+            Some(p) => p,
+            None => return Ok(None),
+        },
+    };
+    let file_type = match from {
+        None => persistence::get_file_info(filename).await?.map(|i| i.file_type),
+        Some(_) => None,
+    }
+    .unwrap_or_else(|| mime_guess::from_path(filename).first_or_octet_stream().to_string());
+    Ok(Some((path, file_type)))
+}
+
+// Stream [start, end] of a file in fixed-size reads
+async fn stream_file(path: &Path, start: u64, end: u64) -> std::io::Result<impl futures::Stream<Item = Result<web::Bytes, Error>>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let remaining = end + 1 - start;
+    Ok(futures::stream::unfold((file, remaining), |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return None;
+        }
+        let mut buf = vec![0u8; remaining.min(STREAM_CHUNK as u64) as usize];
+        match file.read_exact(&mut buf).await {
+            Ok(_) => {
+                let left = remaining - buf.len() as u64;
+                Some((Ok(web::Bytes::from(buf)), (file, left)))
+            }
+            Err(e) => Some((Err(e.into()), (file, 0))),
+        }
+    }))
+}
+
+#[get("/media/{filename}")]
+pub async fn stream_media(req: HttpRequest, path: web::Path<String>, query: web::Query<MediaQuery>, user: Option<web::ReqData<SessionUser>>) -> Result<HttpResponse, Error> {
+    let filename = path.into_inner();
+    let user = user.map(|u| u.into_inner());
+    let (file_path, file_type) = match resolve(&filename, query.from.as_deref(), user.as_ref()).await {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "File not found"
+            })));
+        }
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(serde_json::json!({ "success": false, "message": e.to_string() }))),
+    };
+    let len = tokio::fs::metadata(&file_path).await?.len();
+    let range = req.headers().get("range").and_then(|v| v.to_str().ok());
+    // Players fetch a recording in many ranges; only its start counts as an access
+    if query.from.is_none() && range.and_then(|r| crate::fetch::parse_range(r, len)).is_none_or(|(start, _)| start == 0) {
+        crate::popularity::record(&filename, crate::popularity::Via::of(&req)).await;
+    }
+    let (start, end, mut resp) = match range {
+        Some(r) => match crate::fetch::parse_range(r, len) {
+            Some((start, end)) => {
+                let mut resp = HttpResponse::PartialContent();
+                resp.insert_header(("Content-Range", format!("bytes {}-{}/{}", start, end, len)));
+                (start, end, resp)
+            }
+            None => {
+                return Ok(HttpResponse::RangeNotSatisfiable()
+                    .insert_header(("Content-Range", format!("bytes */{}", len)))
+                    .finish());
+            }
+        },
+        None => (0, len.saturating_sub(1), HttpResponse::Ok()),
+    };
+    resp.insert_header(("Accept-Ranges", "bytes")).content_type(file_type);
+    if len == 0 {
+        return Ok(resp.finish());
+    }
+    resp.insert_header(("Content-Length", (end + 1 - start).to_string()));
+    let peer = crate::bandwidth::requesting_peer(&req);
+    let stream = stream_file(&file_path, start, end).await?.inspect_ok(move |chunk| {
+        crate::bandwidth::record(crate::bandwidth::Channel::Files, peer.as_deref(), crate::bandwidth::Direction::Up, chunk.len());
+    });
+    Ok(resp.streaming(stream))
+}
+
+#[get("/media/{filename}/info")]
+pub async fn media_info(path: web::Path<String>, query: web::Query<MediaQuery>, user: Option<web::ReqData<SessionUser>>) -> Result<HttpResponse, Error> {
+    let filename = path.into_inner();
+    let user = user.map(|u| u.into_inner());
+    let (file_path, file_type) = match resolve(&filename, query.from.as_deref(), user.as_ref()).await {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "File not found"
+            })));
+        }
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(serde_json::json!({ "success": false, "message": e.to_string() }))),
+    };
+    let file_size = tokio::fs::metadata(&file_path).await?.len();
+    let info = web::block(move || probe(&file_path)).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "filename": filename,
+        "file_type": file_type,
+        "file_size": file_size,
+        "media": info
+    })))
+}
+
+// Range-aware proxy so a browser can play a recording held by another peer
+#[get("/peer-media/{ip}/{filename}")]
+pub async fn proxy_peer_media(req: HttpRequest, path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (ip, filename) = path.into_inner();
+    let mut url = match reqwest::Url::parse(&crate::tls::peer_origin(&ip, 8080)) {
+        Ok(u) => u,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": format!("Invalid peer IP/URL: {}", e)
+            })));
+        }
+    };
+    url.path_segments_mut()
+        .map_err(|_| actix_web::error::ErrorInternalServerError("url"))?
+        .extend(["api", "media", filename.as_str()]);
+    let signature = crate::tcp::sign_peer_request("GET", url.as_str()).await;
+    let mut upstream = crate::client::peer().get(url).header(crate::tcp::PEER_REQUEST_HEADER, signature);
+    if let Some(range) = req.headers().get("range").and_then(|v| v.to_str().ok()) {
+        upstream = upstream.header("Range", range);
+    }
+    let resp = match upstream.send().await {
+        Ok(r) => r,
+        Err(e) => {
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to fetch from peer {}: {}", ip, e)
+            })));
+        }
+    };
+    let status = actix_web::http::StatusCode::from_u16(resp.status().as_u16()).unwrap_or(actix_web::http::StatusCode::BAD_GATEWAY);
+    let mut out = HttpResponse::build(status);
+    for name in ["content-type", "content-range", "content-length", "accept-ranges"] {
+        if let Some(v) = resp.headers().get(name).and_then(|v| v.to_str().ok()) {
+            out.insert_header((name, v.to_string()));
+        }
+    }
+    Ok(out.streaming(resp.bytes_stream().map_err(actix_web::error::ErrorBadGateway).inspect_ok(move |chunk| {
+        crate::bandwidth::record(crate::bandwidth::Channel::Proxy, Some(&ip), crate::bandwidth::Direction::Down, chunk.len());
+        crate::bandwidth::record(crate::bandwidth::Channel::Proxy, None, crate::bandwidth::Direction::Up, chunk.len());
+    })))
+}
+
+pub fn probe(path: &Path) -> MediaInfo {
+    if let Some(info) = probe_ffprobe(path) {
+        return info;
+    }
+    match probe_headers(path) {
+        Ok(Some(info)) => info,
+        _ => MediaInfo { source: "none", ..Default::default() },
+    }
+}
+
+fn probe_ffprobe(path: &Path) -> Option<MediaInfo> {
+    let ffprobe = std::env::var("FFPROBE_PATH").unwrap_or_else(|_| "ffprobe".to_string());
+    let output = std::process::Command::new(ffprobe)
+        .args(["-v", "quiet", "-print_format", "json", "-show_format", "-show_streams"])
+        .arg(path)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let v: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let streams = v["streams"].as_array().cloned().unwrap_or_default();
+    let codec = |kind: &str| {
+        streams
+            .iter()
+            .find(|s| s["codec_type"] == kind)
+            .and_then(|s| s["codec_name"].as_str())
+            .map(|s| s.to_string())
+    };
+    Some(MediaInfo {
+        container: v["format"]["format_name"].as_str().map(|s| s.to_string()),
+        duration_secs: v["format"]["duration"].as_str().and_then(|d| d.parse().ok()),
+        audio_codec: codec("audio"),
+        video_codec: codec("video"),
+        source: "ffprobe",
+    })
+}
+
+fn probe_headers(path: &Path) -> std::io::Result<Option<MediaInfo>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut head = [0u8; 12];
+    if file.read_exact(&mut head).is_err() {
+        return Ok(None);
+    }
+    if &head[0..4] == b"RIFF" && &head[8..12] == b"WAVE" {
+        return probe_wav(&mut file);
+    }
+    if &head[4..8] == b"ftyp" {
+        file.seek(SeekFrom::Start(0))?;
+        return probe_mp4(&mut file);
+    }
+    Ok(None)
+}
+
+// RIFF chunks after the 12-byte header: "fmt " gives the codec and byte rate, "data" the length
+fn probe_wav(file: &mut std::fs::File) -> std::io::Result<Option<MediaInfo>> {
+    let (mut format_tag, mut byte_rate, mut data_len) = (None, None, None);
+    let mut header = [0u8; 8];
+    while file.read_exact(&mut header).is_ok() {
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        match &header[0..4] {
+            b"fmt " if size >= 16 => {
+                let mut fmt = [0u8; 16];
+                file.read_exact(&mut fmt)?;
+                format_tag = Some(u16::from_le_bytes([fmt[0], fmt[1]]));
+                byte_rate = Some(u32::from_le_bytes([fmt[8], fmt[9], fmt[10], fmt[11]]));
+                file.seek(SeekFrom::Current((size - 16 + (size & 1)) as i64))?;
+            }
+            b"data" => {
+                data_len = Some(size);
+                break;
+            }
+            _ => {
+                file.seek(SeekFrom::Current((size + (size & 1)) as i64))?;
+            }
+        }
+    }
+    let codec = format_tag.map(|tag| match tag {
+        1 => "pcm".to_string(),
+        3 => "pcm_float".to_string(),
+        6 => "alaw".to_string(),
+        7 => "mulaw".to_string(),
+        other => format!("wav_0x{:04x}", other),
+    });
+    let duration_secs = match (data_len, byte_rate) {
+        (Some(len), Some(rate)) if rate > 0 => Some(len as f64 / rate as f64),
+        _ => None,
+    };
+    Ok(Some(MediaInfo { container: Some("wav".to_string()), duration_secs, audio_codec: codec, video_codec: None, source: "header" }))
+}
+
+// Walk ISO BMFF boxes in `data`, calling `f(type, body)` for each
+fn for_each_box(data: &[u8], f: &mut dyn FnMut(&[u8], &[u8])) {
+    let mut pos = 0usize;
+    while pos + 8 <= data.len() {
+        let mut size = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as u64;
+        let mut header = 8u64;
+        if size == 1 {
+            if pos + 16 > data.len() {
+                return;
+            }
+            let mut large = [0u8; 8];
+            large.copy_from_slice(&data[pos + 8..pos + 16]);
+            size = u64::from_be_bytes(large);
+            header = 16;
+        } else if size == 0 {
+            size = (data.len() - pos) as u64;
+        }
+        if size < header || pos as u64 + size > data.len() as u64 {
+            return;
+        }
+        let end = pos + size as usize;
+        f(&data[pos + 4..pos + 8], &data[pos + header as usize..end]);
+        pos = end;
+    }
+}
+
+// Find the top-level moov box by skipping over the others (mdat can be huge), then read
+// the movie duration from mvhd and the sample entry formats from each track's stsd
+fn probe_mp4(file: &mut std::fs::File) -> std::io::Result<Option<MediaInfo>> {
+    let file_len = file.metadata()?.len();
+    let mut pos = 0u64;
+    let moov = loop {
+        if pos + 8 > file_len {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8u64;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = file_len - pos;
+        }
+        if size < header_len {
+            return Ok(None);
+        }
+        if &header[4..8] == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_SIZE {
+                return Ok(None);
+            }
+            let mut body = vec![0u8; body_len as usize];
+            file.read_exact(&mut body)?;
+            break body;
+        }
+        pos += size;
+    };
+
+    let mut info = MediaInfo { container: Some("mp4".to_string()), source: "header", ..Default::default() };
+    for_each_box(&moov, &mut |kind, body| match kind {
+        b"mvhd" if !body.is_empty() => {
+            let (scale, duration) = if body[0] == 1 && body.len() >= 32 {
+                let mut d = [0u8; 8];
+                d.copy_from_slice(&body[24..32]);
+                (u32::from_be_bytes([body[20], body[21], body[22], body[23]]), u64::from_be_bytes(d))
+            } else if body.len() >= 20 {
+                (u32::from_be_bytes([body[12], body[13], body[14], body[15]]), u32::from_be_bytes([body[16], body[17], body[18], body[19]]) as u64)
+            } else {
+                (0, 0)
+            };
+            if scale > 0 {
+                info.duration_secs = Some(duration as f64 / scale as f64);
+            }
+        }
+        b"trak" => {
+            let mut handler = None;
+            let mut format = None;
+            for_each_box(body, &mut |kind, body| {
+                if kind == b"mdia" {
+                    for_each_box(body, &mut |kind, body| match kind {
+                        // version/flags(4) pre_defined(4) handler_type(4)
+                        b"hdlr" if body.len() >= 12 => handler = Some(body[8..12].to_vec()),
+                        b"minf" => for_each_box(body, &mut |kind, body| {
+                            if kind == b"stbl" {
+                                for_each_box(body, &mut |kind, body| {
+                                    // version/flags(4) entry_count(4), then sample entries
+                                    if kind == b"stsd" && body.len() > 8 {
+                                        for_each_box(&body[8..], &mut |entry, _| {
+                                            if format.is_none() {
+                                                format = Some(String::from_utf8_lossy(entry).trim().to_string());
+                                            }
+                                        });
+                                    }
+                                });
+                            }
+                        }),
+                        _ => {}
+                    });
+                }
+            });
+            match handler.as_deref() {
+                Some(b"soun") if info.audio_codec.is_none() => info.audio_codec = format,
+                Some(b"vide") if info.video_codec.is_none() => info.video_codec = format,
+                _ => {}
+            }
+        }
+        _ => {}
+    });
+    Ok(Some(info))
+}
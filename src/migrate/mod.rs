@@ -0,0 +1,230 @@
+// `migrate` subcommand: upgrades data written by older builds to the current on-disk formats, so a
+// long-running node does not depend on load-time fallbacks forever. Every file it rewrites is first
+// copied to migration-backups/<timestamp>/, and `migrate --rollback` puts the latest backup back.
+// Run it in the node's working directory while the node is stopped.
+//
+// Steps:
+//   message-ids  conversations whose messages were saved before ids existed get the same ids
+//                ensure_message_ids assigns on load (local, peer and fork conversations)
+//   file-hashes  upload metadata (files/*.meta) written before hashes were recorded gets the
+//                sha256 of its data file, which dedup and FILE_META announcements rely on
+use std::path::{Path, PathBuf};
+use crate::conversation::Conversation;
+use crate::persistence::{FileInfo, CONVERSATIONS_DIR, FILES_DIR, RECEIVED_DIR};
+
+const USAGE: &str = "usage: instance migrate [--dry-run]
+       instance migrate --rollback [BACKUP]
+
+  --dry-run   only list what would change
+  --rollback  restore the files saved by the latest migration (or the named backup directory)";
+
+const BACKUP_DIR: &str = "migration-backups";
+// Relative paths of the files a backup holds, one per line
+const MANIFEST: &str = "manifest.txt";
+
+// One file to rewrite
+struct Change {
+    step: &'static str,
+    path: PathBuf,
+    content: Vec<u8>,
+    note: String,
+}
+
+fn json_files(dir: &Path) -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| p.extension().is_some_and(|e| e == "json")).collect())
+        .unwrap_or_default();
+    out.sort();
+    out
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect())
+        .unwrap_or_default();
+    out.sort();
+    out
+}
+
+// Every conversation file: ours, each peer's, and both kinds of forks
+fn conversation_files() -> Vec<PathBuf> {
+    let conversations = Path::new(CONVERSATIONS_DIR);
+    let mut files = vec![conversations.join("local.json")];
+    files.extend(subdirs(Path::new(RECEIVED_DIR)).into_iter().map(|d| d.join("local.json")));
+    files.extend(json_files(&conversations.join("forks")));
+    for peer in subdirs(&conversations.join("peer-forks")) {
+        files.extend(json_files(&peer));
+    }
+    files.retain(|p| p.is_file());
+    files
+}
+
+fn plan_message_ids(changes: &mut Vec<Change>, problems: &mut Vec<String>) {
+    for path in conversation_files() {
+        let parsed = std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|s| serde_json::from_str::<Conversation>(&s).map_err(|e| e.to_string()));
+        let mut conversation = match parsed {
+            Ok(c) => c,
+            Err(e) => {
+                problems.push(format!("{}: {}", path.display(), e));
+                continue;
+            }
+        };
+        let missing = conversation.messages.iter().filter(|m| m.id.is_empty()).count();
+        if !conversation.ensure_message_ids() {
+            continue;
+        }
+        match serde_json::to_string_pretty(&conversation) {
+            Ok(json) => changes.push(Change {
+                step: "message-ids",
+                path,
+                content: json.into_bytes(),
+                note: format!("{} message(s) without an id", missing),
+            }),
+            Err(e) => problems.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+}
+
+fn plan_file_hashes(changes: &mut Vec<Change>, problems: &mut Vec<String>) {
+    let mut metas: Vec<PathBuf> = std::fs::read_dir(FILES_DIR)
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| p.to_string_lossy().ends_with(".meta")).collect())
+        .unwrap_or_default();
+    metas.sort();
+    for meta in metas {
+        let mut info: FileInfo = match std::fs::read_to_string(&meta).map_err(|e| e.to_string()).and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string())) {
+            Ok(info) => info,
+            Err(e) => {
+                problems.push(format!("{}: {}", meta.display(), e));
+                continue;
+            }
+        };
+        if info.sha256.is_some() {
+            continue;
+        }
+        let data_path = meta.with_extension("");
+        let data = match std::fs::read(&data_path) {
+            Ok(d) => d,
+            Err(e) => {
+                problems.push(format!("{}: data file {}: {}", meta.display(), data_path.display(), e));
+                continue;
+            }
+        };
+        info.sha256 = Some(crate::persistence::sha256_hex(&data));
+        match serde_json::to_string_pretty(&info) {
+            Ok(json) => changes.push(Change {
+                step: "file-hashes",
+                path: meta,
+                content: json.into_bytes(),
+                note: format!("hash of {}", info.filename),
+            }),
+            Err(e) => problems.push(format!("{}: {}", meta.display(), e)),
+        }
+    }
+}
+
+// Copy the current version of every file about to change, then write the new versions
+fn apply(changes: &[Change]) -> Result<PathBuf, String> {
+    let backup = Path::new(BACKUP_DIR).join(chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string());
+    if backup.exists() {
+        return Err(format!("backup {} already exists, try again in a second", backup.display()));
+    }
+    let mut manifest = String::new();
+    for change in changes {
+        let target = backup.join(&change.path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        std::fs::copy(&change.path, &target).map_err(|e| format!("backing up {}: {}", change.path.display(), e))?;
+        manifest.push_str(&change.path.to_string_lossy());
+        manifest.push('\n');
+    }
+    std::fs::write(backup.join(MANIFEST), manifest).map_err(|e| e.to_string())?;
+
+    for (done, change) in changes.iter().enumerate() {
+        // Write beside the target and rename, so an interrupted run never leaves half a file
+        let tmp = change.path.with_extension("migrating");
+        let written = std::fs::write(&tmp, &change.content).and_then(|_| std::fs::rename(&tmp, &change.path));
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(format!(
+                "writing {}: {} ({} of {} files migrated; run `instance migrate --rollback` to undo)",
+                change.path.display(), e, done, changes.len()
+            ));
+        }
+    }
+    Ok(backup)
+}
+
+fn migrate(dry_run: bool) -> Result<(), String> {
+    let mut changes = Vec::new();
+    let mut problems = Vec::new();
+    plan_message_ids(&mut changes, &mut problems);
+    plan_file_hashes(&mut changes, &mut problems);
+
+    for problem in &problems {
+        println!("MIGRATE: skipped {}", problem);
+    }
+    if changes.is_empty() {
+        println!("MIGRATE: Nothing to migrate");
+        return Ok(());
+    }
+    for change in &changes {
+        println!("MIGRATE: [{}] {}: {}", change.step, change.path.display(), change.note);
+    }
+    if dry_run {
+        println!("MIGRATE: Dry run, {} file(s) would change", changes.len());
+        return Ok(());
+    }
+    let backup = apply(&changes)?;
+    println!("MIGRATE: Migrated {} file(s); originals saved in {}", changes.len(), backup.display());
+    Ok(())
+}
+
+fn latest_backup() -> Result<PathBuf, String> {
+    let mut backups = subdirs(Path::new(BACKUP_DIR));
+    backups.retain(|d| d.extension().is_none() && d.join(MANIFEST).is_file());
+    backups.pop().ok_or_else(|| format!("no backups in {}", BACKUP_DIR))
+}
+
+fn rollback(backup: Option<&String>) -> Result<(), String> {
+    let backup = match backup {
+        Some(b) => PathBuf::from(b),
+        None => latest_backup()?,
+    };
+    let manifest = std::fs::read_to_string(backup.join(MANIFEST)).map_err(|e| format!("{}: {}", backup.display(), e))?;
+    let mut restored = 0usize;
+    for rel in manifest.lines().filter(|l| !l.is_empty()) {
+        std::fs::copy(backup.join(rel), rel).map_err(|e| format!("restoring {}: {}", rel, e))?;
+        restored += 1;
+    }
+    // Renamed rather than deleted, so a rollback can itself be inspected
+    let done = backup.with_extension("rolled-back");
+    std::fs::rename(&backup, &done).map_err(|e| e.to_string())?;
+    println!("MIGRATE: Restored {} file(s) from {}", restored, done.display());
+    Ok(())
+}
+
+// Runs `migrate` and returns the exit code, or None for any other command
+pub fn handle_command(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    if command != "migrate" {
+        return None;
+    }
+    let result = match rest.first().map(|s| s.as_str()) {
+        None => migrate(false),
+        Some("--dry-run") if rest.len() == 1 => migrate(true),
+        Some("--rollback") if rest.len() <= 2 => rollback(rest.get(1)),
+        Some("help" | "--help" | "-h") => {
+            println!("{}", USAGE);
+            return Some(0);
+        }
+        _ => Err(format!("unexpected arguments: {}", rest.join(" "))),
+    };
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("MIGRATE: {}\n\n{}", e, USAGE);
+            Some(1)
+        }
+    }
+}
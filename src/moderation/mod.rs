@@ -0,0 +1,177 @@
+// Moderation stage for chat. Prompts and answers are checked against the node policy in settings:
+// regex rules and, optionally, a local classifier speaking the OpenAI /v1/moderations format.
+// Hits either block the message or let it through with `flags` set; every hit is written to the
+// audit log.
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationAction {
+    Flag,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationStage {
+    Prompt,
+    Response,
+    #[default]
+    Both,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationRule {
+    pub label: String,
+    pub pattern: String,
+    pub action: ModerationAction,
+    #[serde(default)]
+    pub stage: ModerationStage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModerationSettings {
+    pub enabled: bool,
+    pub rules: Vec<ModerationRule>,
+    // e.g. "http://127.0.0.1:8080/v1/moderations"; categories it reports use classifier_action
+    pub classifier_url: Option<String>,
+    pub classifier_action: ModerationAction,
+}
+
+impl Default for ModerationSettings {
+    fn default() -> Self {
+        ModerationSettings { enabled: false, rules: Vec::new(), classifier_url: None, classifier_action: ModerationAction::Flag }
+    }
+}
+
+impl ModerationSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        for rule in &self.rules {
+            if rule.label.trim().is_empty() {
+                return Err("moderation rules need a label".to_string());
+            }
+            Regex::new(&rule.pattern).map_err(|e| format!("invalid moderation pattern for {}: {}", rule.label, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Flag(Vec<String>),
+    Block(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Prompt,
+    Response,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Prompt => "prompt",
+            Stage::Response => "response",
+        }
+    }
+}
+
+fn applies(rule: &ModerationRule, stage: Stage) -> bool {
+    match rule.stage {
+        ModerationStage::Both => true,
+        ModerationStage::Prompt => stage == Stage::Prompt,
+        ModerationStage::Response => stage == Stage::Response,
+    }
+}
+
+// Categories the classifier flagged, from an OpenAI-style {"results": [{"flagged", "categories"}]}
+async fn classify(url: &str, text: &str) -> Result<Vec<String>, String> {
+    let resp = crate::client::local()
+        .post(url)
+        .timeout(Duration::from_secs(10))
+        .json(&serde_json::json!({ "input": text }))
+        .send()
+        .await
+        .map_err(|e| format!("classifier request failed: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("classifier returned {}", resp.status()));
+    }
+    let body: serde_json::Value = resp.json().await.map_err(|e| format!("invalid classifier response: {}", e))?;
+    let result = &body["results"][0];
+    if !result["flagged"].as_bool().unwrap_or(false) {
+        return Ok(Vec::new());
+    }
+    let mut categories: Vec<String> = result["categories"]
+        .as_object()
+        .map(|c| c.iter().filter(|(_, v)| v.as_bool() == Some(true)).map(|(k, _)| k.clone()).collect())
+        .unwrap_or_default();
+    if categories.is_empty() {
+        categories.push("flagged".to_string());
+    }
+    Ok(categories)
+}
+
+async fn evaluate(settings: &ModerationSettings, stage: Stage, text: &str) -> Verdict {
+    let mut blocked = Vec::new();
+    let mut flagged = Vec::new();
+    for rule in settings.rules.iter().filter(|r| applies(r, stage)) {
+        match Regex::new(&rule.pattern) {
+            Ok(re) if re.is_match(text) => match rule.action {
+                ModerationAction::Block => blocked.push(rule.label.clone()),
+                ModerationAction::Flag => flagged.push(rule.label.clone()),
+            },
+            Ok(_) => {}
+            Err(e) => eprintln!("MODERATION: Skipping rule {}: {}", rule.label, e),
+        }
+    }
+    if let Some(url) = settings.classifier_url.as_deref().filter(|u| !u.trim().is_empty()) {
+        match classify(url, text).await {
+            Ok(categories) => match settings.classifier_action {
+                ModerationAction::Block => blocked.extend(categories),
+                ModerationAction::Flag => flagged.extend(categories),
+            },
+            // A broken classifier must not take chat down with it; regex rules still apply
+            Err(e) => eprintln!("MODERATION: Classifier unavailable: {}", e),
+        }
+    }
+    if !blocked.is_empty() {
+        Verdict::Block(blocked)
+    } else if !flagged.is_empty() {
+        Verdict::Flag(flagged)
+    } else {
+        Verdict::Allow
+    }
+}
+
+// Check a prompt or answer against the node policy, recording any hit in the audit log
+pub async fn check(stage: Stage, text: &str, sender: &str, conversation_id: &str) -> Verdict {
+    let settings = crate::settings::current().await.moderation;
+    if !settings.enabled {
+        return Verdict::Allow;
+    }
+    let verdict = evaluate(&settings, stage, text).await;
+    let (action, labels) = match &verdict {
+        Verdict::Allow => return verdict,
+        Verdict::Flag(labels) => ("flag", labels),
+        Verdict::Block(labels) => ("block", labels),
+    };
+    println!("MODERATION: {} {} from {} ({})", action, stage.name(), sender, labels.join(", "));
+    crate::audit::record(
+        "moderation",
+        sender,
+        serde_json::json!({
+            "stage": stage.name(),
+            "action": action,
+            "labels": labels,
+            "conversation_id": conversation_id,
+            "length": text.chars().count(),
+        }),
+    )
+    .await;
+    verdict
+}
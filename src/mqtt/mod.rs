@@ -0,0 +1,168 @@
+// MQTT bridge: publishes peer/file/chat events (see events::publish) to a broker and optionally
+// listens for commands. Disabled unless MQTT_BROKER (host or host:port) is set.
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEFAULT_MQTT_PORT: u16 = 1883;
+
+struct MqttBridge {
+    client: AsyncClient,
+    topic_prefix: String,
+}
+
+static BRIDGE: OnceLock<MqttBridge> = OnceLock::new();
+
+#[derive(serde::Deserialize)]
+struct Command {
+    action: String,
+    #[serde(default)]
+    path: Option<String>,
+}
+
+fn env_non_empty(key: &str) -> Option<String> {
+    std::env::var(key).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn parse_broker(broker: &str) -> (String, u16) {
+    match broker.rsplit_once(':') {
+        Some((host, port)) => match port.parse::<u16>() {
+            Ok(p) => (host.to_string(), p),
+            Err(_) => (broker.to_string(), DEFAULT_MQTT_PORT),
+        },
+        None => (broker.to_string(), DEFAULT_MQTT_PORT),
+    }
+}
+
+// Publish an event under <prefix>/events/<kind>. Never blocks; drops the event if the queue is full.
+pub fn publish(kind: &str, payload: serde_json::Value) {
+    if let Some(bridge) = BRIDGE.get() {
+        let topic = format!("{}/events/{}", bridge.topic_prefix, kind);
+        let body = match serde_json::to_vec(&payload) {
+            Ok(b) => b,
+            Err(_) => return,
+        };
+        if let Err(e) = bridge.client.try_publish(topic, QoS::AtLeastOnce, false, body) {
+            eprintln!("[MQTT] Dropped {} event: {}", kind, e);
+        }
+    }
+}
+
+pub async fn start() {
+    let broker = match env_non_empty("MQTT_BROKER") {
+        Some(b) => b,
+        None => return,
+    };
+    let hostname = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "node".to_string());
+    let (host, port) = parse_broker(&broker);
+    let client_id = env_non_empty("MQTT_CLIENT_ID").unwrap_or_else(|| format!("meshmind-{}", hostname));
+    let topic_prefix = env_non_empty("MQTT_TOPIC_PREFIX").unwrap_or_else(|| format!("meshmind/{}", hostname));
+    let command_topic = env_non_empty("MQTT_COMMAND_TOPIC");
+
+    let mut options = MqttOptions::new(client_id, host.clone(), port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let (Some(user), Some(pass)) = (env_non_empty("MQTT_USERNAME"), env_non_empty("MQTT_PASSWORD")) {
+        options.set_credentials(user, pass);
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+    if let Some(topic) = &command_topic {
+        if let Err(e) = client.subscribe(topic.clone(), QoS::AtLeastOnce).await {
+            eprintln!("[MQTT] Failed to subscribe to {}: {}", topic, e);
+        }
+    }
+    if BRIDGE.set(MqttBridge { client, topic_prefix: topic_prefix.clone() }).is_err() {
+        return;
+    }
+    println!("[MQTT] Publishing events to {}:{} under {}/events", host, port, topic_prefix);
+
+    // The event loop drives the connection (and reconnects); it must be polled continuously.
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                if command_topic.as_deref() == Some(publish.topic.as_str()) {
+                    handle_command(&publish.payload).await;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("[MQTT] Connection error: {} (retrying in 5s)", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+// Commands: {"action":"announce"} or {"action":"upload","path":"<file inside MQTT_UPLOAD_DIR>"}
+async fn handle_command(payload: &[u8]) {
+    let cmd: Command = match serde_json::from_slice(payload) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("[MQTT] Ignoring malformed command: {}", e);
+            return;
+        }
+    };
+    match cmd.action.as_str() {
+        "announce" => {
+            println!("[MQTT] Command: announce");
+            crate::udp::announce_now().await;
+        }
+        "upload" => {
+            let path = match cmd.path {
+                Some(p) => p,
+                None => {
+                    eprintln!("[MQTT] Upload command missing path");
+                    return;
+                }
+            };
+            match resolve_upload_path(&path) {
+                Some(resolved) => upload_from_disk(&resolved).await,
+                None => eprintln!("[MQTT] Refusing upload of {} (outside MQTT_UPLOAD_DIR or upload disabled)", path),
+            }
+        }
+        other => eprintln!("[MQTT] Unknown command action: {}", other),
+    }
+}
+
+// Only files inside MQTT_UPLOAD_DIR may be uploaded by remote commands.
+fn resolve_upload_path(path: &str) -> Option<PathBuf> {
+    let root = std::fs::canonicalize(env_non_empty("MQTT_UPLOAD_DIR")?).ok()?;
+    let candidate = Path::new(path);
+    let candidate = if candidate.is_absolute() { candidate.to_path_buf() } else { root.join(candidate) };
+    let resolved = std::fs::canonicalize(candidate).ok()?;
+    if resolved.starts_with(&root) && resolved.is_file() {
+        Some(resolved)
+    } else {
+        None
+    }
+}
+
+async fn upload_from_disk(path: &Path) {
+    let filename = match path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return,
+    };
+    let content = match tokio::fs::read(path).await {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("[MQTT] Failed to read {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let file_type = mime_guess::from_path(path).first_or_octet_stream().to_string();
+    let local_ip = crate::ip::primary_ip()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string());
+
+    match crate::persistence::save_uploaded_file(&filename, &file_type, &content, &local_ip).await {
+        Ok(info) => {
+            println!("[MQTT] Command: uploaded {}", filename);
+            crate::tcp::broadcast_file_to_peers(filename.clone(), file_type, content).await;
+            crate::events::publish("file", serde_json::json!({ "event": "uploaded", "source": "mqtt", "file": info }));
+        }
+        Err(e) => eprintln!("[MQTT] Upload of {} failed: {}", filename, e),
+    }
+}
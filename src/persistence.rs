@@ -1,14 +1,48 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex as StdMutex, OnceLock};
+use std::time::SystemTime;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use serde_json;
-use crate::conversation::Conversation;
-use std::collections::HashMap;
+use crate::conversation::{ChatMessage, Conversation};
+use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
 use chrono;
 
 pub const CONVERSATIONS_DIR: &str = "conversations";
 pub const RECEIVED_DIR: &str = "received";
 pub const FILES_DIR: &str = "files";
-pub const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024; // 50MB
+// Sidecar holding an audio file's transcript, see transcribe
+pub const TRANSCRIPT_SUFFIX: &str = ".transcript.txt";
+pub const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024; // 50MB default, see settings
+// The local conversation is a snapshot plus a log of messages appended since it was written
+const LOCAL_SNAPSHOT: &str = "local.json";
+const LOCAL_LOG: &str = "local.jsonl";
+// Appended messages after which the log is folded into a new snapshot
+const LOCAL_LOG_COMPACT_AT: usize = 200;
+static LOCAL_LOG_ENTRIES: AtomicUsize = AtomicUsize::new(0);
+// Suffix of files still being written by write_atomic; any found at startup were cut off by a crash
+pub const TMP_SUFFIX: &str = ".writing";
+
+// Stable identifier of this node, generated once and kept in node_id.txt
+pub fn node_id() -> &'static str {
+    static NODE_ID: OnceLock<String> = OnceLock::new();
+    NODE_ID.get_or_init(|| {
+        let path = "node_id.txt";
+        if let Ok(existing) = std::fs::read_to_string(path) {
+            let existing = existing.trim().to_string();
+            if !existing.is_empty() {
+                return existing;
+            }
+        }
+        let id = hex::encode(rand::random::<[u8; 8]>());
+        if let Err(e) = std::fs::write(path, &id) {
+            eprintln!("Failed to save node id to {}: {}", path, e);
+        }
+        id
+    })
+}
 
 pub async fn init_conversations_dir() -> std::io::Result<()> {
     let conversations_path = Path::new(CONVERSATIONS_DIR);
@@ -27,10 +61,83 @@ pub async fn init_conversations_dir() -> std::io::Result<()> {
     Ok(())
 }
 
+// Write to a temporary file beside `path` and rename it over, so a crash never leaves half a file
+pub async fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(TMP_SUFFIX);
+    let tmp = path.with_file_name(tmp_name);
+    let mut file = fs::File::create(&tmp).await?;
+    file.write_all(content).await?;
+    file.sync_all().await?;
+    drop(file);
+    fs::rename(&tmp, path).await
+}
+
+// Delete temporary files a crash left behind: unfinished atomic writes everywhere, plus chunked
+// transfers (.part) from peers, which restart from the first chunk anyway. Returns how many.
+pub async fn remove_orphan_temp_files() -> std::io::Result<usize> {
+    let conversations = Path::new(CONVERSATIONS_DIR);
+    let received = Path::new(RECEIVED_DIR);
+    let mut dirs = vec![conversations.to_path_buf(), conversations.join("forks"), Path::new(FILES_DIR).to_path_buf()];
+    // Per-peer directories
+    for base in [conversations.join("peer-forks"), received.to_path_buf()] {
+        if !base.exists() {
+            continue;
+        }
+        let mut entries = fs::read_dir(&base).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+    }
+
+    let mut removed = 0usize;
+    for dir in dirs {
+        if !dir.exists() {
+            continue;
+        }
+        let is_received = dir.starts_with(received);
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if (name.ends_with(TMP_SUFFIX) || (is_received && name.ends_with(".part"))) && entry.file_type().await?.is_file() {
+                match fs::remove_file(entry.path()).await {
+                    Ok(()) => removed += 1,
+                    Err(e) => eprintln!("Failed to remove temporary file {}: {}", entry.path().display(), e),
+                }
+            }
+        }
+    }
+    Ok(removed)
+}
+
+// Write the whole local conversation as a new snapshot and drop the log it supersedes
 pub async fn save_local_conversation(conversation: &Conversation) -> std::io::Result<()> {
-    let file_path = Path::new(CONVERSATIONS_DIR).join("local.json");
+    let dir = Path::new(CONVERSATIONS_DIR);
     let json = serde_json::to_string_pretty(conversation)?;
-    fs::write(file_path, json).await?;
+    write_atomic(&dir.join(LOCAL_SNAPSHOT), json.as_bytes()).await?;
+    match fs::remove_file(dir.join(LOCAL_LOG)).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    LOCAL_LOG_ENTRIES.store(0, Ordering::Relaxed);
+    Ok(())
+}
+
+// Record a message just added to the local conversation by appending one line to the log instead
+// of rewriting the snapshot; every LOCAL_LOG_COMPACT_AT messages the log is compacted.
+// The caller holds the conversation lock, so appends never interleave.
+pub async fn append_local_message(conversation: &Conversation, message: &ChatMessage) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    let mut log = fs::OpenOptions::new().create(true).append(true).open(Path::new(CONVERSATIONS_DIR).join(LOCAL_LOG)).await?;
+    log.write_all(&line).await?;
+    log.sync_data().await?;
+    if LOCAL_LOG_ENTRIES.fetch_add(1, Ordering::Relaxed) + 1 >= LOCAL_LOG_COMPACT_AT {
+        save_local_conversation(conversation).await?;
+    }
     Ok(())
 }
 
@@ -42,18 +149,42 @@ pub async fn save_peer_conversation(peer_ip: &str, conversation: &Conversation)
     
     let file_path = peer_dir.join("local.json");
     let json = serde_json::to_string_pretty(conversation)?;
-    fs::write(file_path, json).await?;
+    write_atomic(&file_path, json.as_bytes()).await?;
     Ok(())
 }
 
+// Snapshot plus logged messages, compacted into a fresh snapshot when the log had any
 pub async fn load_local_conversation() -> std::io::Result<Option<Conversation>> {
-    let file_path = Path::new(CONVERSATIONS_DIR).join("local.json");
+    let dir = Path::new(CONVERSATIONS_DIR);
+    let file_path = dir.join(LOCAL_SNAPSHOT);
     if !file_path.exists() {
         return Ok(None);
     }
-    
+
     let content = fs::read_to_string(file_path).await?;
-    let conversation = serde_json::from_str(&content)?;
+    let mut conversation: Conversation = serde_json::from_str(&content)?;
+    let log = match fs::read_to_string(dir.join(LOCAL_LOG)).await {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Some(conversation)),
+        Err(e) => return Err(e),
+    };
+    let mut replayed = 0usize;
+    for (i, line) in log.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        match serde_json::from_str::<ChatMessage>(line) {
+            // A crash between snapshot and log removal leaves messages that are already in the snapshot
+            Ok(message) if !message.id.is_empty() && conversation.messages.iter().any(|m| m.id == message.id) => {}
+            Ok(message) => {
+                conversation.messages.push(message);
+                replayed += 1;
+            }
+            // Most likely the last line, torn by a crash while it was written
+            Err(e) => eprintln!("Skipping unreadable line {} of {}: {}", i + 1, LOCAL_LOG, e),
+        }
+    }
+    if replayed > 0 {
+        println!("Replayed {} logged message(s) into the local conversation", replayed);
+    }
+    save_local_conversation(&conversation).await?;
     Ok(Some(conversation))
 }
 
@@ -115,6 +246,82 @@ pub async fn load_all_peer_conversations() -> std::io::Result<HashMap<String, Co
     Ok(peer_conversations)
 }
 
+// Forks of conversations live beside the main one: ours in conversations/forks/<fork-id>.json,
+// peers' in conversations/peer-forks/<peer-ip>/<fork-id>.json
+fn fork_dir(peer_ip: Option<&str>) -> PathBuf {
+    match peer_ip {
+        Some(ip) => Path::new(CONVERSATIONS_DIR).join("peer-forks").join(ip),
+        None => Path::new(CONVERSATIONS_DIR).join("forks"),
+    }
+}
+
+pub async fn save_fork(peer_ip: Option<&str>, conversation: &Conversation) -> std::io::Result<()> {
+    let dir = fork_dir(peer_ip);
+    fs::create_dir_all(&dir).await?;
+    let json = serde_json::to_string_pretty(conversation)?;
+    write_atomic(&dir.join(format!("{}.json", conversation.id)), json.as_bytes()).await?;
+    Ok(())
+}
+
+async fn load_fork_dir(dir: &Path) -> std::io::Result<HashMap<String, Conversation>> {
+    let mut forks = HashMap::new();
+    if !dir.exists() {
+        return Ok(forks);
+    }
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        match serde_json::from_str::<Conversation>(&fs::read_to_string(&path).await?) {
+            Ok(conversation) => {
+                forks.insert(conversation.id.clone(), conversation);
+            }
+            Err(e) => eprintln!("Failed to parse fork {}: {}", path.display(), e),
+        }
+    }
+    Ok(forks)
+}
+
+pub async fn load_forks() -> std::io::Result<HashMap<String, Conversation>> {
+    load_fork_dir(&fork_dir(None)).await
+}
+
+// Peer forks keyed by peer IP, then fork id
+pub async fn load_peer_forks() -> std::io::Result<HashMap<String, HashMap<String, Conversation>>> {
+    let mut all = HashMap::new();
+    let base = Path::new(CONVERSATIONS_DIR).join("peer-forks");
+    if !base.exists() {
+        return Ok(all);
+    }
+    let mut peers = fs::read_dir(&base).await?;
+    while let Some(entry) = peers.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            let peer_ip = entry.file_name().to_string_lossy().to_string();
+            all.insert(peer_ip, load_fork_dir(&entry.path()).await?);
+        }
+    }
+    Ok(all)
+}
+
+// Who may see an upload: only its owner and the admin, anyone signed in to this node, or the whole
+// mesh. Only mesh files are sent to peers, listed for them or served to them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Private,
+    NodeUsers,
+    #[default]
+    Mesh,
+}
+
+impl Visibility {
+    pub fn is_mesh(&self) -> bool {
+        *self == Visibility::Mesh
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct FileInfo {
     pub filename: String,
@@ -122,6 +329,149 @@ pub struct FileInfo {
     pub file_size: u64,
     pub uploader_ip: String,
     pub upload_time: chrono::DateTime<chrono::Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    // Virus scan verdict, for uploads stored while scanning was on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan: Option<crate::scan::ScanVerdict>,
+    // Signed-in user who uploaded it here; uploads from peers and node services have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Visibility::is_mesh")]
+    pub visibility: Visibility,
+    // Group the upload is scoped to (see crate::groups): only peers in it get it or see it listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl FileInfo {
+    // Whether a request from `user` may list or download it; None is a peer or another session-less caller
+    pub fn visible_to(&self, user: Option<&crate::users::SessionUser>) -> bool {
+        match (user, self.visibility) {
+            (_, Visibility::Mesh) => true,
+            (None, _) => false,
+            (Some(_), Visibility::NodeUsers) => true,
+            (Some(user), Visibility::Private) => user.admin || self.owner.as_deref() == Some(user.name.as_str()),
+        }
+    }
+
+    // Whether `user` may change it (tags, visibility) or send it to peers: its owner or the admin
+    pub fn managed_by(&self, user: Option<&crate::users::SessionUser>) -> bool {
+        user.is_some_and(|u| u.admin || self.owner.as_deref() == Some(u.name.as_str()))
+    }
+}
+
+// Order of a file listing; newest first unless asked otherwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSort {
+    #[default]
+    Date,
+    Name,
+    Size,
+    Uploader,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSource {
+    #[default]
+    All,
+    // Uploads stored on this node
+    Local,
+    // Files peers announced, sent us or list
+    Peer,
+}
+
+// Sorting, filters and a page of a file listing (GET /api/files?sort=size&order=asc&type=image&...)
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct FileQuery {
+    pub sort: FileSort,
+    // Dates and sizes default to descending, names and uploaders to ascending
+    pub order: Option<SortOrder>,
+    // MIME type ("image/png") or its top-level type ("image")
+    #[serde(rename = "type")]
+    pub file_type: Option<String>,
+    pub uploader: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub source: FileSource,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+pub const MAX_FILE_PAGE: usize = 1000;
+
+impl FileQuery {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_size.zip(self.max_size).is_some_and(|(min, max)| min > max) {
+            return Err("min_size must not be above max_size".to_string());
+        }
+        if self.limit.is_some_and(|l| l == 0 || l > MAX_FILE_PAGE) {
+            return Err(format!("limit must be between 1 and {}", MAX_FILE_PAGE));
+        }
+        Ok(())
+    }
+
+    pub fn wants_local(&self) -> bool {
+        self.source != FileSource::Peer
+    }
+
+    pub fn wants_peers(&self) -> bool {
+        self.source != FileSource::Local
+    }
+
+    pub fn matches(&self, file: &FileInfo) -> bool {
+        let type_matches = self.file_type.as_deref().is_none_or(|t| {
+            let t = t.trim().to_lowercase();
+            let file_type = file.file_type.to_lowercase();
+            file_type == t || (!t.contains('/') && file_type.split('/').next() == Some(t.as_str()))
+        });
+        type_matches
+            && self.uploader.as_deref().is_none_or(|u| file.uploader_ip == u)
+            && self.min_size.is_none_or(|min| file.file_size >= min)
+            && self.max_size.is_none_or(|max| file.file_size <= max)
+    }
+
+    pub fn sort(&self, files: &mut [FileInfo]) {
+        let descending = match self.order {
+            Some(order) => order == SortOrder::Desc,
+            None => matches!(self.sort, FileSort::Date | FileSort::Size),
+        };
+        files.sort_by(|a, b| {
+            let ord = match self.sort {
+                FileSort::Date => a.upload_time.cmp(&b.upload_time),
+                FileSort::Name => a.filename.to_lowercase().cmp(&b.filename.to_lowercase()),
+                FileSort::Size => a.file_size.cmp(&b.file_size),
+                FileSort::Uploader => a.uploader_ip.cmp(&b.uploader_ip),
+            }
+            // Newest first among equals, whatever the order
+            .then_with(|| if descending { a.upload_time.cmp(&b.upload_time) } else { b.upload_time.cmp(&a.upload_time) });
+            if descending { ord.reverse() } else { ord }
+        });
+    }
+
+    // Sort `files` and keep the requested page; returns how many there were before paging
+    pub fn page(&self, files: &mut Vec<FileInfo>) -> usize {
+        self.sort(files);
+        let total = files.len();
+        let offset = self.offset.unwrap_or(0).min(total);
+        files.drain(..offset);
+        if let Some(limit) = self.limit {
+            files.truncate(limit);
+        }
+        total
+    }
 }
 
 pub async fn save_uploaded_file(
@@ -130,44 +480,67 @@ pub async fn save_uploaded_file(
     content: &[u8],
     uploader_ip: &str,
 ) -> std::io::Result<FileInfo> {
-    // Validate file size
-    if content.len() as u64 > MAX_FILE_SIZE {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!("File too large. Maximum size is {} bytes", MAX_FILE_SIZE),
-        ));
-    }
-
-    // Validate file type
-    let allowed_types = [
-        "image/jpeg",
-        "image/png",
-        "image/gif",
-        "image/webp",
-        "text/plain",
-        "text/markdown",
-        "application/pdf",
-        "application/octet-stream",
-        "application/x-msdownload",
-        "application/zip",
-        "application/x-zip-compressed",
-        "application/x-7z-compressed",
-        "application/x-rar-compressed",
-    ];
-    if !allowed_types.contains(&file_type) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "File type not allowed",
-        ));
+    save_uploaded_file_for(filename, file_type, content, uploader_ip, None, Visibility::Mesh, None).await
+}
+
+// Store an upload in `owner`'s file space (see crate::users), seen by whom `visibility` allows and,
+// among peers, only by those in `group`
+pub async fn save_uploaded_file_for(
+    filename: &str,
+    file_type: &str,
+    content: &[u8],
+    uploader_ip: &str,
+    owner: Option<&str>,
+    visibility: Visibility,
+    group: Option<&str>,
+) -> std::io::Result<FileInfo> {
+    store_upload(filename, file_type, content, uploader_ip, owner, visibility, group).await.map(|(_, info)| info)
+}
+
+// Store new content for an upload, keeping its other copies until the new one is written, so a
+// failed save leaves the file as it was
+pub async fn replace_uploaded_file(
+    filename: &str,
+    file_type: &str,
+    content: &[u8],
+    uploader_ip: &str,
+    owner: Option<&str>,
+    visibility: Visibility,
+    group: Option<&str>,
+) -> std::io::Result<FileInfo> {
+    let (data_name, file_info) = store_upload(filename, file_type, content, uploader_ip, owner, visibility, group).await?;
+    for old in copies_of(filename).await?.into_iter().filter(|n| *n != data_name) {
+        remove_copy(&old).await?;
+    }
+    Ok(file_info)
+}
+
+// Write an upload and its metadata; returns the data file name with the metadata
+async fn store_upload(
+    filename: &str,
+    file_type: &str,
+    content: &[u8],
+    uploader_ip: &str,
+    owner: Option<&str>,
+    visibility: Visibility,
+    group: Option<&str>,
+) -> std::io::Result<(String, FileInfo)> {
+    // Validate type and size against the configured file policy
+    if let Err(e) = crate::settings::current().await.check_file(file_type, content.len() as u64) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
     }
 
+    let scan = crate::scan::check(filename, content, uploader_ip)
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::PermissionDenied, e))?;
+
     // Create unique filename to avoid conflicts
     let timestamp = chrono::Utc::now().timestamp();
     let safe_filename = filename.replace(" ", "_").replace("/", "_");
     let unique_filename = format!("{}_{}", timestamp, safe_filename);
     
     let file_path = Path::new(FILES_DIR).join(&unique_filename);
-    fs::write(&file_path, content).await?;
+    write_atomic(&file_path, content).await?;
 
     let file_info = FileInfo {
         filename: filename.to_string(),
@@ -175,78 +548,250 @@ pub async fn save_uploaded_file(
         file_size: content.len() as u64,
         uploader_ip: uploader_ip.to_string(),
         upload_time: chrono::Utc::now(),
+        tags: Vec::new(),
+        sha256: Some(sha256_hex(content)),
+        scan,
+        owner: owner.map(str::to_string),
+        visibility,
+        group: group.map(str::to_string),
     };
 
     // Save file metadata
     let metadata_path = Path::new(FILES_DIR).join(format!("{}.meta", unique_filename));
     let metadata_json = serde_json::to_string_pretty(&file_info)?;
-    fs::write(metadata_path, metadata_json).await?;
+    write_atomic(&metadata_path, metadata_json.as_bytes()).await?;
+    if let Some(cache) = meta_cache().lock().await.as_mut() {
+        cache.insert(unique_filename.clone(), file_info.clone());
+    }
 
-    Ok(file_info)
+    crate::transcribe::queue(file_path, filename, file_type).await;
+
+    Ok((unique_filename, file_info))
 }
 
-pub async fn get_file_info(filename: &str) -> std::io::Result<Option<FileInfo>> {
+// Metadata of local uploads keyed by data file name, read from the .meta files once and then kept
+// up to date by every function here that writes or removes one
+type MetaCache = tokio::sync::Mutex<Option<HashMap<String, FileInfo>>>;
+static META_CACHE: OnceLock<MetaCache> = OnceLock::new();
+
+fn meta_cache() -> &'static MetaCache {
+    META_CACHE.get_or_init(|| tokio::sync::Mutex::new(None))
+}
+
+async fn read_meta_files() -> std::io::Result<HashMap<String, FileInfo>> {
+    let mut out = HashMap::new();
     let files_path = Path::new(FILES_DIR);
+    if !files_path.exists() {
+        return Ok(out);
+    }
     let mut entries = fs::read_dir(files_path).await?;
-    
     while let Some(entry) = entries.next_entry().await? {
-        let file_name = entry.file_name().to_string_lossy().to_string();
-        if file_name.ends_with(".meta") {
-            let content = fs::read_to_string(entry.path()).await?;
-            if let Ok(file_info) = serde_json::from_str::<FileInfo>(&content) {
-                if file_info.filename == filename {
-                    return Ok(Some(file_info));
-                }
-            }
+        let meta_name = entry.file_name().to_string_lossy().to_string();
+        let data_name = match meta_name.strip_suffix(".meta") {
+            Some(n) => n.to_string(),
+            None => continue,
+        };
+        let content = fs::read_to_string(entry.path()).await?;
+        if let Ok(file_info) = serde_json::from_str::<FileInfo>(&content) {
+            out.insert(data_name, file_info);
         }
     }
-    
-    Ok(None)
+    Ok(out)
 }
 
-pub async fn get_file_content(filename: &str) -> std::io::Result<Option<Vec<u8>>> {
+// Run `f` on the cached metadata, loading it on first use
+async fn with_meta_cache<R>(f: impl FnOnce(&mut HashMap<String, FileInfo>) -> R) -> std::io::Result<R> {
+    let mut guard = meta_cache().lock().await;
+    if guard.is_none() {
+        *guard = Some(read_meta_files().await?);
+    }
+    Ok(f(guard.get_or_insert_with(HashMap::new)))
+}
+
+// Data file names of every stored copy of an upload
+async fn copies_of(filename: &str) -> std::io::Result<Vec<String>> {
+    with_meta_cache(|cache| cache.iter().filter(|(_, info)| info.filename == filename).map(|(name, _)| name.clone()).collect()).await
+}
+
+// Remove every stored copy of an uploaded file (data + .meta). Returns how many copies were removed.
+pub async fn remove_uploaded_file(filename: &str) -> std::io::Result<usize> {
+    let mut removed = 0usize;
+    for data_name in copies_of(filename).await? {
+        remove_copy(&data_name).await?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+// Remove one stored copy: data, transcript and .meta
+async fn remove_copy(data_name: &str) -> std::io::Result<()> {
     let files_path = Path::new(FILES_DIR);
-    let mut entries = fs::read_dir(files_path).await?;
-    
-    while let Some(entry) = entries.next_entry().await? {
-        let file_name = entry.file_name().to_string_lossy().to_string();
-        if !file_name.ends_with(".meta") {
-            // Check if this file matches our filename
-            if let Some(file_info) = get_file_info(filename).await? {
-                let timestamp = file_info.upload_time.timestamp();
-                let safe_filename = filename.replace(" ", "_").replace("/", "_");
-                let expected_name = format!("{}_{}", timestamp, safe_filename);
-                
-                if file_name == expected_name {
-                    let content = fs::read(entry.path()).await?;
-                    return Ok(Some(content));
-                }
-            }
+    let _ = fs::remove_file(files_path.join(data_name)).await;
+    let _ = fs::remove_file(files_path.join(format!("{}{}", data_name, TRANSCRIPT_SUFFIX))).await;
+    let meta_path = files_path.join(format!("{}.meta", data_name));
+    if let Err(e) = fs::remove_file(&meta_path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(e);
         }
     }
-    
-    Ok(None)
+    with_meta_cache(|cache| cache.remove(data_name)).await?;
+    Ok(())
 }
 
-pub async fn list_uploaded_files() -> std::io::Result<Vec<FileInfo>> {
+// Replace the tags of every stored copy of an uploaded file. Returns the updated info, if found.
+pub async fn set_file_tags(filename: &str, tags: Vec<String>) -> std::io::Result<Option<FileInfo>> {
     let files_path = Path::new(FILES_DIR);
-    let mut files = Vec::new();
-    
-    if !files_path.exists() {
-        return Ok(files);
+    let mut updated = None;
+    for data_name in copies_of(filename).await? {
+        let file_info = match with_meta_cache(|cache| cache.get(&data_name).cloned()).await? {
+            Some(mut info) => {
+                info.tags = tags.clone();
+                info
+            }
+            None => continue,
+        };
+        write_atomic(&files_path.join(format!("{}.meta", data_name)), serde_json::to_string_pretty(&file_info)?.as_bytes()).await?;
+        with_meta_cache(|cache| cache.insert(data_name, file_info.clone())).await?;
+        updated = Some(file_info);
     }
-    
-    let mut entries = fs::read_dir(files_path).await?;
-    while let Some(entry) = entries.next_entry().await? {
-        let file_name = entry.file_name().to_string_lossy().to_string();
-        if file_name.ends_with(".meta") {
-            let content = fs::read_to_string(entry.path()).await?;
-            if let Ok(file_info) = serde_json::from_str::<FileInfo>(&content) {
-                files.push(file_info);
+    Ok(updated)
+}
+
+pub async fn set_file_visibility(filename: &str, visibility: Visibility) -> std::io::Result<Option<FileInfo>> {
+    let files_path = Path::new(FILES_DIR);
+    let mut updated = None;
+    for data_name in copies_of(filename).await? {
+        let file_info = match with_meta_cache(|cache| cache.get(&data_name).cloned()).await? {
+            Some(mut info) => {
+                info.visibility = visibility;
+                info
             }
+            None => continue,
+        };
+        write_atomic(&files_path.join(format!("{}.meta", data_name)), serde_json::to_string_pretty(&file_info)?.as_bytes()).await?;
+        with_meta_cache(|cache| cache.insert(data_name, file_info.clone())).await?;
+        updated = Some(file_info);
+    }
+    Ok(updated)
+}
+
+pub fn sha256_hex(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    hex::encode(hasher.finalize())
+}
+
+// Hashes keyed by path, reused while size and mtime are unchanged
+type HashCache = StdMutex<HashMap<PathBuf, (u64, Option<SystemTime>, String)>>;
+static HASH_CACHE: OnceLock<HashCache> = OnceLock::new();
+
+async fn cached_file_sha256(path: &Path) -> std::io::Result<String> {
+    let meta = fs::metadata(path).await?;
+    let key = (meta.len(), meta.modified().ok());
+    let cache = HASH_CACHE.get_or_init(|| StdMutex::new(HashMap::new()));
+    if let Some((len, mtime, sha)) = cache.lock().unwrap().get(path) {
+        if (*len, *mtime) == key {
+            return Ok(sha.clone());
         }
     }
-    
+    let sha = sha256_hex(&fs::read(path).await?);
+    cache.lock().unwrap().insert(path.to_path_buf(), (key.0, key.1, sha.clone()));
+    Ok(sha)
+}
+
+// Paths of every file this node holds: local uploads plus binaries received from peers
+pub async fn held_file_paths() -> std::io::Result<Vec<PathBuf>> {
+    let mut dirs = vec![Path::new(FILES_DIR).to_path_buf()];
+    let received = Path::new(RECEIVED_DIR);
+    if received.exists() {
+        let mut peers = fs::read_dir(received).await?;
+        while let Some(peer_entry) = peers.next_entry().await? {
+            if peer_entry.file_type().await?.is_dir() {
+                dirs.push(peer_entry.path());
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+    for dir in dirs {
+        if !dir.exists() {
+            continue;
+        }
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == "local.json" || name.ends_with(".meta") || name.ends_with(".part") || name.ends_with(TRANSCRIPT_SUFFIX) || name.ends_with(TMP_SUFFIX) {
+                continue;
+            }
+            if entry.file_type().await?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+    }
+    Ok(paths)
+}
+
+// Stored files peers may fetch: received ones and uploads shared with the mesh
+async fn stored_file_paths() -> std::io::Result<Vec<PathBuf>> {
+    let unshared: HashSet<String> =
+        with_meta_cache(|cache| cache.iter().filter(|(_, info)| !info.visibility.is_mesh()).map(|(name, _)| name.clone()).collect()).await?;
+    let mut paths = held_file_paths().await?;
+    paths.retain(|path| {
+        let upload = path.parent() == Some(Path::new(FILES_DIR));
+        !(upload && path.file_name().is_some_and(|name| unshared.contains(name.to_string_lossy().as_ref())))
+    });
+    Ok(paths)
+}
+
+// SHA-256 (hex) of every file this node holds. Used by replication to skip files the target already has.
+pub async fn stored_file_hashes() -> std::io::Result<HashSet<String>> {
+    let mut hashes = HashSet::new();
+    for path in stored_file_paths().await? {
+        hashes.insert(cached_file_sha256(&path).await?);
+    }
+    Ok(hashes)
+}
+
+// Locate a stored copy peers may fetch (received, or an upload shared with the mesh) with the given SHA-256
+pub async fn find_file_by_hash(sha256: &str) -> std::io::Result<Option<PathBuf>> {
+    for path in stored_file_paths().await? {
+        if cached_file_sha256(&path).await?.eq_ignore_ascii_case(sha256) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+// Metadata of a local upload; with several copies, the newest
+pub async fn get_file_info(filename: &str) -> std::io::Result<Option<FileInfo>> {
+    with_meta_cache(|cache| cache.values().filter(|info| info.filename == filename).max_by_key(|info| info.upload_time).cloned()).await
+}
+
+// On-disk path of a local upload, found through its metadata
+pub async fn get_file_path(filename: &str) -> std::io::Result<Option<PathBuf>> {
+    let mut copies: Vec<(chrono::DateTime<chrono::Utc>, String)> = with_meta_cache(|cache| {
+        cache.iter().filter(|(_, info)| info.filename == filename).map(|(name, info)| (info.upload_time, name.clone())).collect()
+    })
+    .await?;
+    // Newest copy first
+    copies.sort_by(|a, b| b.cmp(a));
+    Ok(copies.into_iter().map(|(_, name)| Path::new(FILES_DIR).join(name)).find(|p| p.exists()))
+}
+
+pub async fn get_file_content(filename: &str) -> std::io::Result<Option<Vec<u8>>> {
+    match get_file_path(filename).await? {
+        Some(path) => Ok(Some(fs::read(path).await?)),
+        None => Ok(None),
+    }
+}
+
+// Local uploads `keep` and `query` take, filtered in the metadata cache so only they are copied
+pub async fn query_uploaded_files(query: &FileQuery, keep: impl Fn(&FileInfo) -> bool) -> std::io::Result<Vec<FileInfo>> {
+    with_meta_cache(|cache| cache.values().filter(|f| query.matches(f) && keep(f)).cloned().collect()).await
+}
+
+pub async fn list_uploaded_files() -> std::io::Result<Vec<FileInfo>> {
+    let mut files: Vec<FileInfo> = with_meta_cache(|cache| cache.values().cloned().collect()).await?;
     // Sort by upload time (newest first)
     files.sort_by(|a, b| b.upload_time.cmp(&a.upload_time));
     Ok(files)
@@ -271,7 +816,7 @@ pub async fn list_received_files() -> std::io::Result<Vec<FileInfo>> {
         while let Some(file) = dir.next_entry().await? {
             let name = file.file_name().to_string_lossy().to_string();
             // Skip conversation JSON and obvious metadata files
-            if name == "local.json" || name.ends_with(".meta") { continue; }
+            if name == "local.json" || name.ends_with(".meta") || name.ends_with(".part") || name.ends_with(TRANSCRIPT_SUFFIX) || name.ends_with(TMP_SUFFIX) { continue; }
 
             // Determine size and modified time
             if let Ok(meta) = fs::metadata(file.path()).await {
@@ -291,6 +836,12 @@ pub async fn list_received_files() -> std::io::Result<Vec<FileInfo>> {
                     file_size: size as u64,
                     uploader_ip: peer_ip.clone(),
                     upload_time,
+                    tags: Vec::new(),
+                    sha256: None,
+                    scan: None,
+                    owner: None,
+                    visibility: Visibility::Mesh,
+                    group: None,
                 });
             }
         }
@@ -299,4 +850,98 @@ pub async fn list_received_files() -> std::io::Result<Vec<FileInfo>> {
     // Newest first for consistency
     out.sort_by(|a, b| b.upload_time.cmp(&a.upload_time));
     Ok(out)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileInfo, FileQuery, FileSort, Visibility};
+    use crate::users::SessionUser;
+
+    // /api/files query parameters: filters on type, uploader and size, sorting both ways and paging,
+    // with the total counted before the page is cut
+    #[test]
+    fn file_listing_sorts_filters_and_pages() {
+        let now = chrono::Utc::now();
+        let file = |name: &str, file_type: &str, size: u64, uploader: &str, days_ago: i64| FileInfo {
+            filename: name.into(),
+            file_type: file_type.into(),
+            file_size: size,
+            uploader_ip: uploader.into(),
+            upload_time: now - chrono::Duration::days(days_ago),
+            tags: Vec::new(),
+            sha256: None,
+            scan: None,
+            owner: None,
+            visibility: Default::default(),
+            group: None,
+        };
+        let files = vec![
+            file("b.png", "image/png", 300, "10.0.0.2", 3),
+            file("A.jpg", "image/jpeg", 50, "10.0.0.1", 1),
+            file("notes.txt", "text/plain", 10, "10.0.0.1", 2),
+            file("c.gif", "image/gif", 5000, "10.0.0.3", 4),
+        ];
+        let query = |q: &str| actix_web::web::Query::<FileQuery>::from_query(q).unwrap().into_inner();
+        let names = |files: &[FileInfo]| files.iter().map(|f| f.filename.clone()).collect::<Vec<_>>();
+
+        let mut listed = files.clone();
+        assert_eq!(query("").page(&mut listed), 4);
+        assert_eq!(names(&listed), ["A.jpg", "notes.txt", "b.png", "c.gif"]);
+
+        let q = query("sort=size&type=image&min_size=60&max_size=1000");
+        assert_eq!(q.sort, FileSort::Size);
+        let mut listed: Vec<_> = files.iter().filter(|f| q.matches(f)).cloned().collect();
+        assert_eq!(q.page(&mut listed), 1);
+        assert_eq!(names(&listed), ["b.png"]);
+
+        let mut listed = files.clone();
+        assert_eq!(query("sort=name&offset=1&limit=2").page(&mut listed), 4);
+        assert_eq!(names(&listed), ["b.png", "c.gif"]);
+        let mut listed = files.clone();
+        query("sort=size&order=asc").page(&mut listed);
+        assert_eq!(names(&listed), ["notes.txt", "A.jpg", "b.png", "c.gif"]);
+
+        let q = query("uploader=10.0.0.1&type=text/plain&source=local");
+        assert!(q.wants_local() && !q.wants_peers());
+        assert_eq!(files.iter().filter(|f| q.matches(f)).count(), 1);
+        assert!(query("source=peer").wants_peers() && !query("source=peer").wants_local());
+
+        assert!(actix_web::web::Query::<FileQuery>::from_query("order=sideways").is_err());
+        assert!(query("min_size=10&max_size=5").validate().is_err());
+        assert!(query("limit=0").validate().is_err());
+        assert!(query("limit=5000").validate().is_err());
+        assert!(actix_web::web::Query::<FileQuery>::from_query("sort=color").is_err());
+    }
+
+    // Private files are for their owner and the admin, node_users files for anyone signed in here,
+    // and only mesh files for peers
+    #[test]
+    fn file_visibility_limits_who_sees_it() {
+        let file = |visibility| FileInfo {
+            filename: "f.txt".into(),
+            file_type: "text/plain".into(),
+            file_size: 1,
+            uploader_ip: "10.0.0.1".into(),
+            upload_time: chrono::DateTime::from_timestamp(0, 0).unwrap(),
+            tags: Vec::new(),
+            sha256: None,
+            scan: None,
+            owner: Some("alice".into()),
+            visibility,
+            group: None,
+        };
+        let alice = SessionUser { name: "alice".into(), admin: false };
+        let bob = SessionUser { name: "bob".into(), admin: false };
+        let admin = SessionUser { name: "root".into(), admin: true };
+        let who = |f: &FileInfo| [None, Some(&alice), Some(&bob), Some(&admin)].map(|u| f.visible_to(u));
+        assert_eq!(who(&file(Visibility::Private)), [false, true, false, true]);
+        assert_eq!(who(&file(Visibility::NodeUsers)), [false, true, true, true]);
+        assert_eq!(who(&file(Visibility::Mesh)), [true, true, true, true]);
+        // Only the owner and the admin tag it, change its visibility or send it to peers
+        assert_eq!([None, Some(&alice), Some(&bob), Some(&admin)].map(|u| file(Visibility::Mesh).managed_by(u)), [false, true, false, true]);
+        assert!(!FileInfo { owner: None, ..file(Visibility::Mesh) }.managed_by(Some(&alice)));
+        let json = serde_json::to_string(&file(Visibility::NodeUsers)).unwrap();
+        assert!(json.contains(r#""visibility":"node_users""#));
+        assert!(!serde_json::to_string(&file(Visibility::Mesh)).unwrap().contains("visibility"));
+    }
+}
@@ -0,0 +1,366 @@
+// File-analysis pipelines: rules that run an LLM action when an event happens, e.g. "when a PDF is
+// uploaded, summarize it, store the summary as <name>.summary.md and broadcast it". Rules live in
+// pipelines.json and are managed via /api/pipelines; every run is a job in the job runner. Files a
+// pipeline stores are themselves uploads, but they never trigger pipelines again. What a pipeline
+// stores gets the owner, visibility and group of the file it was made from, so a private upload's
+// summary stays private and is never broadcast.
+use actix_web::{delete, get, post, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::llm::templates;
+use crate::persistence::FileInfo;
+
+const PIPELINES_FILE: &str = "pipelines.json";
+// Events a pipeline can be triggered by
+const EVENTS: [&str; 1] = ["file.uploaded"];
+// Placeholders available in the prompt and in the output name
+const PROMPT_VARIABLES: [&str; 2] = ["filename", "file_type"];
+const OUTPUT_VARIABLES: [&str; 2] = ["filename", "stem"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub id: String,
+    pub name: String,
+    pub event: String,
+    // MIME types or prefixes the file must match ("application/pdf", "image/"); empty matches any
+    #[serde(default)]
+    pub file_types: Vec<String>,
+    // Sent to the LLM with the file attached, as in POST /api/chat
+    pub prompt: String,
+    // Name to store the answer under, e.g. "{{filename}}.summary.md". Without one the question and
+    // answer go into the local conversation instead.
+    #[serde(default)]
+    pub output: Option<String>,
+    // Send the stored answer file to peers like any upload
+    #[serde(default = "default_true")]
+    pub broadcast: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_job_id: Option<u64>,
+}
+
+fn default_true() -> bool { true }
+
+#[derive(Deserialize)]
+pub struct NewPipeline {
+    name: String,
+    #[serde(default = "default_event")]
+    event: String,
+    #[serde(default)]
+    file_types: Vec<String>,
+    prompt: String,
+    #[serde(default)]
+    output: Option<String>,
+    #[serde(default = "default_true")]
+    broadcast: bool,
+}
+
+fn default_event() -> String { EVENTS[0].to_string() }
+
+#[derive(Deserialize)]
+pub struct RunBody {
+    filename: String,
+}
+
+lazy_static! {
+    static ref PIPELINES: Arc<Mutex<Option<Vec<Pipeline>>>> = Arc::new(Mutex::new(None));
+}
+
+async fn load() -> Vec<Pipeline> {
+    match tokio::fs::read_to_string(PIPELINES_FILE).await {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("PIPELINE: Failed to parse {}: {}", PIPELINES_FILE, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn save(pipelines: &[Pipeline]) {
+    match serde_json::to_string_pretty(pipelines) {
+        Ok(s) => {
+            if let Err(e) = tokio::fs::write(PIPELINES_FILE, s).await {
+                eprintln!("PIPELINE: Failed to save {}: {}", PIPELINES_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("PIPELINE: Failed to serialize pipelines: {}", e),
+    }
+}
+
+// Run `f` on the loaded pipeline list, saving it afterwards when `f` reports a change
+async fn with_pipelines<R>(f: impl FnOnce(&mut Vec<Pipeline>) -> (R, bool)) -> R {
+    let mut guard = PIPELINES.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    let pipelines = guard.get_or_insert_with(Vec::new);
+    let (result, changed) = f(pipelines);
+    if changed {
+        save(pipelines).await;
+    }
+    result
+}
+
+fn matches_type(pipeline: &Pipeline, file_type: &str) -> bool {
+    pipeline.file_types.is_empty()
+        || pipeline.file_types.iter().any(|t| {
+            let t = t.trim().to_lowercase();
+            if t.ends_with('/') { file_type.starts_with(&t) } else { file_type == t }
+        })
+}
+
+fn check_placeholders(template: &str, allowed: &[&str], what: &str) -> Result<(), String> {
+    let unknown: Vec<String> = templates::placeholders(template)?.into_iter().filter(|p| !allowed.contains(&p.as_str())).collect();
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} may only use {{{{{}}}}}, not {}", what, allowed.join("}}, {{"), unknown.join(", ")))
+    }
+}
+
+fn validate(body: &NewPipeline) -> Result<(), String> {
+    if body.name.trim().is_empty() || body.prompt.trim().is_empty() {
+        return Err("name and prompt are required".to_string());
+    }
+    if !EVENTS.contains(&body.event.as_str()) {
+        return Err(format!("event must be one of: {}", EVENTS.join(", ")));
+    }
+    check_placeholders(&body.prompt, &PROMPT_VARIABLES, "prompt")?;
+    if let Some(output) = &body.output {
+        check_placeholders(output, &OUTPUT_VARIABLES, "output")?;
+        if output.trim().is_empty() || output.contains(['/', '\\']) || output.contains("..") {
+            return Err("output must be a plain file name".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn local_ip() -> String {
+    crate::ip::primary_ip()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string())
+}
+
+async fn run(pipeline: Pipeline, file: FileInfo) -> Result<String, String> {
+    let mut values = HashMap::from([
+        ("filename".to_string(), file.filename.clone()),
+        ("file_type".to_string(), file.file_type.clone()),
+    ]);
+    let req = crate::llm::ChatRequest {
+        message: templates::render(&pipeline.prompt, &values)?,
+        sender: format!("pipeline:{}", pipeline.name),
+        filename: Some(file.filename.clone()),
+        ..Default::default()
+    };
+    let output = match &pipeline.output {
+        Some(output) => output,
+        None => {
+            let answer = crate::llm::run_chat(&req).await.map_err(|e| e.to_string())?;
+            return Ok(format!("answered {} in the local conversation ({} chars)", file.filename, answer.content.len()));
+        }
+    };
+    let answer = crate::llm::complete(&req).await.map_err(|e| e.to_string())?;
+
+    let stem = file.filename.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&file.filename).to_string();
+    values.insert("stem".to_string(), stem);
+    let name = templates::render(output, &values)?.replace(['/', '\\'], "_");
+    let file_type = mime_guess::from_path(&name).first_or_text_plain().to_string();
+    let info = crate::persistence::save_uploaded_file_for(
+        &name,
+        &file_type,
+        answer.as_bytes(),
+        &local_ip(),
+        file.owner.as_deref(),
+        file.visibility,
+        file.group.as_deref(),
+    )
+    .await
+    .map_err(|e| format!("storing {} failed: {}", name, e))?;
+    if pipeline.broadcast && file.visibility.is_mesh() {
+        match &file.group {
+            Some(group) => crate::tcp::broadcast_file_to_group(group, name.clone(), file_type, answer.into_bytes()).await,
+            None => crate::tcp::broadcast_file_to_peers(name.clone(), file_type, answer.into_bytes()).await,
+        }
+    }
+    crate::events::publish("file", serde_json::json!({ "event": "uploaded", "source": "pipeline", "pipeline": pipeline.id, "file": info }));
+    Ok(format!("stored {} for {}", name, file.filename))
+}
+
+// Queue a run in the job runner and remember its job id on the pipeline
+async fn start(pipeline: &Pipeline, file: FileInfo) -> u64 {
+    let description = format!("{} on {}", pipeline.name, file.filename);
+    let job_id = crate::jobs::submit("pipeline", description, run(pipeline.clone(), file)).await;
+    with_pipelines(|pipelines| match pipelines.iter_mut().find(|p| p.id == pipeline.id) {
+        Some(p) => {
+            p.last_job_id = Some(job_id);
+            ((), true)
+        }
+        None => ((), false),
+    })
+    .await;
+    job_id
+}
+
+// Whether a run on `file` keeps its answer among those who may see the file. Answers without an
+// output name go into the local conversation, which every peer gets.
+fn keeps_scope(pipeline: &Pipeline, file: &FileInfo) -> bool {
+    pipeline.output.is_some() || (file.visibility.is_mesh() && file.group.is_none())
+}
+
+async fn trigger(event: &str, file: FileInfo) {
+    let matching: Vec<Pipeline> = with_pipelines(|pipelines| {
+        let matching = pipelines
+            .iter()
+            .filter(|p| p.enabled && p.event == event && matches_type(p, &file.file_type) && keeps_scope(p, &file))
+            .cloned()
+            .collect();
+        (matching, false)
+    })
+    .await;
+    for pipeline in matching {
+        start(&pipeline, file.clone()).await;
+    }
+}
+
+// Subscriber of events::publish; starts the pipelines an event triggers in the background
+pub fn on_event(kind: &str, payload: &serde_json::Value) {
+    if kind != "file" || payload["event"] != "uploaded" || payload["source"] == "pipeline" {
+        return;
+    }
+    let file: FileInfo = match serde_json::from_value(payload["file"].clone()) {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+    tokio::spawn(trigger("file.uploaded", file));
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can manage pipelines" }))
+}
+
+#[get("/pipelines")]
+pub async fn list_pipelines() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(with_pipelines(|p| (p.clone(), false)).await))
+}
+
+#[post("/pipelines")]
+pub async fn create_pipeline(body: web::Json<NewPipeline>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let body = body.into_inner();
+    if let Err(message) = validate(&body) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": message })));
+    }
+    let pipeline = Pipeline {
+        id: format!("pipe-{}", Utc::now().timestamp_millis()),
+        name: body.name.trim().to_string(),
+        event: body.event,
+        file_types: body.file_types.iter().map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()).collect(),
+        prompt: body.prompt,
+        output: body.output.map(|o| o.trim().to_string()),
+        broadcast: body.broadcast,
+        enabled: true,
+        created_at: Utc::now(),
+        last_job_id: None,
+    };
+    with_pipelines(|p| {
+        p.push(pipeline.clone());
+        ((), true)
+    })
+    .await;
+    println!("PIPELINE: Added {} ({}) on {}", pipeline.id, pipeline.name, pipeline.event);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "pipeline": pipeline })))
+}
+
+#[delete("/pipelines/{id}")]
+pub async fn delete_pipeline(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let removed = with_pipelines(|p| {
+        let before = p.len();
+        p.retain(|p| p.id != id);
+        let removed = p.len() != before;
+        (removed, removed)
+    })
+    .await;
+    if removed {
+        Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Pipeline not found" })))
+    }
+}
+
+// Run a pipeline on an already uploaded file, e.g. to backfill summaries
+#[post("/pipelines/{id}/run")]
+pub async fn run_pipeline_now(path: web::Path<String>, body: web::Json<RunBody>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let file = match crate::persistence::get_file_info(&body.filename).await? {
+        Some(f) => f,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "File not found" }))),
+    };
+    let pipeline = match with_pipelines(|p| (p.iter().find(|p| p.id == id).cloned(), false)).await {
+        Some(p) => p,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Pipeline not found" }))),
+    };
+    if !keeps_scope(&pipeline, &file) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "This pipeline answers in the shared conversation, and the file is not shared with the whole mesh"
+        })));
+    }
+    let job_id = start(&pipeline, file).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::Visibility;
+
+    // Answers that go into the shared conversation only come from files the whole mesh may see
+    #[test]
+    fn answers_stay_within_the_files_scope() {
+        let pipeline = |output: Option<&str>| Pipeline {
+            id: "pipe-1".into(),
+            name: "summary".into(),
+            event: EVENTS[0].into(),
+            file_types: Vec::new(),
+            prompt: "Summarize {{filename}}".into(),
+            output: output.map(Into::into),
+            broadcast: true,
+            enabled: true,
+            created_at: Utc::now(),
+            last_job_id: None,
+        };
+        let file = |visibility, group: Option<&str>| FileInfo {
+            filename: "report.pdf".into(),
+            file_type: "application/pdf".into(),
+            file_size: 1,
+            uploader_ip: "10.0.0.1".into(),
+            upload_time: Utc::now(),
+            tags: Vec::new(),
+            sha256: None,
+            scan: None,
+            owner: Some("alice".into()),
+            visibility,
+            group: group.map(Into::into),
+        };
+        assert!(keeps_scope(&pipeline(None), &file(Visibility::Mesh, None)));
+        assert!(!keeps_scope(&pipeline(None), &file(Visibility::Private, None)));
+        assert!(!keeps_scope(&pipeline(None), &file(Visibility::NodeUsers, None)));
+        assert!(!keeps_scope(&pipeline(None), &file(Visibility::Mesh, Some("ops"))));
+        assert!(keeps_scope(&pipeline(Some("{{stem}}.md")), &file(Visibility::Private, None)));
+    }
+}
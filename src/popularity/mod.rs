@@ -0,0 +1,189 @@
+// How often each uploaded file is downloaded: by users of this node (the download API, media
+// streaming and WebDAV) and by peers fetching it for their users through /api/peer-file. Counts
+// and the last access are kept in file_access.json and summarised in GET /api/analytics/files,
+// which also lists files nobody has opened and those idle long enough to be cleaned up. With
+// RETENTION_IDLE_DAYS set, retention_loop deletes those candidates every hour.
+use actix_web::HttpRequest;
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::persistence::FileInfo;
+
+const ACCESS_FILE: &str = "file_access.json";
+// Files untouched this long are cleanup candidates unless the request asks for another age
+pub const DEFAULT_IDLE_DAYS: i64 = 30;
+const RETENTION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Via {
+    Local,
+    Peer,
+}
+
+impl Via {
+    // Peers fetch files for their users with the x-peer-llm header (see /api/peer-file), and
+    // recordings, replicas and chunks with a signed request (see tcp::sign_peer_request)
+    pub fn of(req: &HttpRequest) -> Via {
+        if req.headers().contains_key(crate::tcp::PEER_REQUEST_HEADER) {
+            return Via::Peer;
+        }
+        match req.headers().get("x-peer-llm") {
+            Some(v) if v == "1" || v == "yes" => Via::Peer,
+            _ => Via::Local,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Access {
+    pub local_downloads: u64,
+    pub peer_downloads: u64,
+    pub last_access: Option<DateTime<Utc>>,
+}
+
+impl Access {
+    pub fn downloads(&self) -> u64 {
+        self.local_downloads + self.peer_downloads
+    }
+}
+
+lazy_static! {
+    static ref ACCESS: Arc<Mutex<Option<HashMap<String, Access>>>> = Arc::new(Mutex::new(None));
+}
+
+async fn load() -> HashMap<String, Access> {
+    match tokio::fs::read_to_string(ACCESS_FILE).await {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("POPULARITY: Failed to parse {}: {}", ACCESS_FILE, e);
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    }
+}
+
+async fn update(change: impl FnOnce(&mut HashMap<String, Access>)) {
+    let mut guard = ACCESS.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    let access = guard.get_or_insert_with(HashMap::new);
+    change(access);
+    match serde_json::to_string_pretty(access) {
+        Ok(json) => {
+            if let Err(e) = crate::persistence::write_atomic(std::path::Path::new(ACCESS_FILE), json.as_bytes()).await {
+                eprintln!("POPULARITY: Failed to save {}: {}", ACCESS_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("POPULARITY: Failed to serialize access counts: {}", e),
+    }
+}
+
+// Count a download of one of our uploads
+pub async fn record(filename: &str, via: Via) {
+    update(|access| {
+        let entry = access.entry(filename.to_string()).or_default();
+        match via {
+            Via::Local => entry.local_downloads += 1,
+            Via::Peer => entry.peer_downloads += 1,
+        }
+        entry.last_access = Some(Utc::now());
+    })
+    .await;
+}
+
+// Drop a deleted file's counts
+pub async fn forget(filename: &str) {
+    update(|access| {
+        access.remove(filename);
+    })
+    .await;
+}
+
+pub async fn snapshot() -> HashMap<String, Access> {
+    let mut guard = ACCESS.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    guard.clone().unwrap_or_default()
+}
+
+// Files not downloaded within `idle` that were uploaded at least that long ago, largest first:
+// what retention_loop removes
+pub fn cleanup_candidates<'a>(files: &'a [FileInfo], access: &HashMap<String, Access>, idle: Duration, now: DateTime<Utc>) -> Vec<&'a FileInfo> {
+    let cutoff = now - idle;
+    let mut candidates: Vec<&FileInfo> = files
+        .iter()
+        .filter(|f| f.upload_time <= cutoff)
+        .filter(|f| access.get(&f.filename).and_then(|a| a.last_access).is_none_or(|t| t <= cutoff))
+        .collect();
+    candidates.sort_by(|a, b| b.file_size.cmp(&a.file_size).then_with(|| a.upload_time.cmp(&b.upload_time)));
+    candidates
+}
+
+// Days a file may sit idle before retention_loop deletes it; unset or 0 keeps everything
+fn retention_idle_days() -> Option<i64> {
+    std::env::var("RETENTION_IDLE_DAYS").ok().and_then(|v| v.trim().parse().ok()).filter(|n: &i64| *n > 0)
+}
+
+// Delete the cleanup candidates of RETENTION_IDLE_DAYS every hour, as a WebDAV DELETE would
+pub async fn retention_loop() {
+    let Some(idle_days) = retention_idle_days() else { return };
+    println!("RETENTION: Deleting files idle for {} day(s)", idle_days);
+    let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+    loop {
+        interval.tick().await;
+        let files = match crate::persistence::list_uploaded_files().await {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("RETENTION: Could not list files: {}", e);
+                continue;
+            }
+        };
+        let access = snapshot().await;
+        for f in cleanup_candidates(&files, &access, Duration::days(idle_days), Utc::now()) {
+            match crate::persistence::remove_uploaded_file(&f.filename).await {
+                Ok(0) => {}
+                Ok(_) => {
+                    println!("RETENTION: Deleted {} ({} bytes)", f.filename, f.file_size);
+                    forget(&f.filename).await;
+                    crate::tcp::broadcast_file_deleted(&f.filename).await;
+                }
+                Err(e) => eprintln!("RETENTION: Could not delete {}: {}", f.filename, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cleanup_candidates, Access, FileInfo};
+
+    // Files idle for the whole window are cleanup candidates, largest first; recent uploads are not
+    #[test]
+    fn idle_files_are_cleanup_candidates() {
+        let now = chrono::Utc::now();
+        let file = |name: &str, size: u64, days_ago: i64| FileInfo {
+            filename: name.into(),
+            file_type: "text/plain".into(),
+            file_size: size,
+            uploader_ip: "10.0.0.1".into(),
+            upload_time: now - chrono::Duration::days(days_ago),
+            tags: Vec::new(),
+            sha256: None,
+            scan: None,
+            owner: None,
+            visibility: Default::default(),
+            group: None,
+        };
+        let files = vec![file("old-small", 10, 90), file("old-big", 1000, 60), file("old-used", 500, 90), file("new", 5000, 2)];
+        let mut access = std::collections::HashMap::new();
+        access.insert("old-used".to_string(), Access { local_downloads: 1, peer_downloads: 2, last_access: Some(now - chrono::Duration::days(3)) });
+        access.insert("old-small".to_string(), Access { local_downloads: 4, peer_downloads: 0, last_access: Some(now - chrono::Duration::days(45)) });
+        let names: Vec<&str> = cleanup_candidates(&files, &access, chrono::Duration::days(30), now).iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(names, ["old-big", "old-small"]);
+        assert_eq!(access["old-used"].downloads(), 3);
+    }
+}
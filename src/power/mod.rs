@@ -0,0 +1,93 @@
+// Power profile, chosen at startup with POWER_PROFILE. `normal` is the default; `low` is for
+// Raspberry Pis and other small devices: discovery broadcasts, conversation syncs, peer file list
+// refreshes and latency probes run LOW_POWER_FACTOR times less often, uploads are no longer pushed
+// to every peer (peers list them and pull them when asked for), and analytics keep fewer samples.
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const LOW_POWER_FACTOR: u32 = 4;
+const LOW_POWER_RETENTION_DIVISOR: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Normal,
+    Low,
+}
+
+impl Profile {
+    pub fn parse(value: &str) -> Result<Profile, String> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "normal" => Ok(Profile::Normal),
+            "low" | "low-power" | "low_power" => Ok(Profile::Low),
+            other => Err(format!("unknown profile {:?} (expected normal or low)", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Normal => "normal",
+            Profile::Low => "low",
+        }
+    }
+
+    // How long to wait between runs of a background loop that normally runs every `normal`
+    pub fn interval(self, normal: Duration) -> Duration {
+        match self {
+            Profile::Normal => normal,
+            Profile::Low => normal * LOW_POWER_FACTOR,
+        }
+    }
+
+    // How many samples to keep where `normal` are kept normally
+    pub fn retention(self, normal: usize) -> usize {
+        match self {
+            Profile::Normal => normal,
+            Profile::Low => (normal / LOW_POWER_RETENTION_DIVISOR).max(1),
+        }
+    }
+}
+
+pub fn profile() -> Profile {
+    static PROFILE: OnceLock<Profile> = OnceLock::new();
+    *PROFILE.get_or_init(|| {
+        let profile = Profile::parse(&std::env::var("POWER_PROFILE").unwrap_or_default()).unwrap_or_else(|e| {
+            eprintln!("POWER_PROFILE: {}, using normal", e);
+            Profile::Normal
+        });
+        if profile == Profile::Low {
+            println!("POWER: Low-power profile, background activity reduced and uploads are pull-only");
+        }
+        profile
+    })
+}
+
+pub fn interval(normal: Duration) -> Duration {
+    profile().interval(normal)
+}
+
+pub fn retention(normal: usize) -> usize {
+    profile().retention(normal)
+}
+
+// Whether new files are pushed to every connected peer; in the low-power profile peers pull them
+pub fn push_files() -> bool {
+    profile() == Profile::Normal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Duration, Profile};
+
+    // The low-power profile stretches background intervals and trims analytics retention
+    #[test]
+    fn low_power_profile_reduces_background_activity() {
+        assert_eq!(Profile::parse("").unwrap(), Profile::Normal);
+        assert_eq!(Profile::parse(" LOW ").unwrap(), Profile::Low);
+        assert!(Profile::parse("turbo").is_err());
+        assert_eq!(Profile::Normal.interval(Duration::from_secs(30)), Duration::from_secs(30));
+        assert_eq!(Profile::Low.interval(Duration::from_secs(30)), Duration::from_secs(120));
+        assert_eq!(Profile::Normal.retention(1000), 1000);
+        assert_eq!(Profile::Low.retention(1000), 200);
+        assert_eq!(Profile::Low.retention(3), 1);
+    }
+}
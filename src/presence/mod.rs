@@ -0,0 +1,211 @@
+// Who is active in a conversation right now: people typing and LLMs generating an answer. Local
+// changes are pushed to WebSocket clients (/api/ws) and to peers as TYPE: frames, and indicators
+// from peers come back the same way. Nothing is persisted; an indicator that is not refreshed
+// expires on its own. The participant list, by contrast, is derived from the stored messages.
+use actix_web::{get, web, HttpMessage, HttpRequest, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+use crate::conversation::CONVERSATION_STORE;
+
+// Typing indicators are refreshed by clients while the user types
+const TYPING_TTL_SECS: i64 = 8;
+// Long enough for a slow model; an idle update normally clears it first
+const GENERATING_TTL_SECS: i64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Activity {
+    Typing,
+    Generating,
+    Idle,
+}
+
+impl Activity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Activity::Typing => "typing",
+            Activity::Generating => "generating",
+            Activity::Idle => "idle",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Activity> {
+        match s {
+            "typing" => Some(Activity::Typing),
+            "generating" => Some(Activity::Generating),
+            "idle" => Some(Activity::Idle),
+            _ => None,
+        }
+    }
+
+    fn ttl(self) -> chrono::Duration {
+        match self {
+            Activity::Generating => chrono::Duration::seconds(GENERATING_TTL_SECS),
+            _ => chrono::Duration::seconds(TYPING_TTL_SECS),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Indicator {
+    pub conversation_id: String,
+    // "local" or the IP of the peer it came from
+    pub node: String,
+    pub sender: String,
+    pub activity: Activity,
+    pub updated_at: DateTime<Utc>,
+}
+
+// What WebSocket clients may send: their own typing state
+#[derive(Deserialize)]
+struct ClientEvent {
+    conversation_id: String,
+    sender: String,
+    activity: Activity,
+}
+
+// (conversation, node, sender)
+type IndicatorKey = (String, String, String);
+
+lazy_static! {
+    static ref INDICATORS: Arc<Mutex<HashMap<IndicatorKey, Indicator>>> = Arc::new(Mutex::new(HashMap::new()));
+    // Serialized events for WebSocket clients; slow clients miss events rather than hold anything up
+    static ref EVENTS: broadcast::Sender<String> = broadcast::channel(256).0;
+}
+
+async fn record(indicator: Indicator) {
+    let key = (indicator.conversation_id.clone(), indicator.node.clone(), indicator.sender.clone());
+    {
+        let mut indicators = INDICATORS.lock().await;
+        if indicator.activity == Activity::Idle {
+            indicators.remove(&key);
+        } else {
+            indicators.insert(key, indicator.clone());
+        }
+    }
+    let mut event = serde_json::to_value(&indicator).unwrap_or_default();
+    event["type"] = "activity".into();
+    let _ = EVENTS.send(event.to_string());
+}
+
+// Send another kind of event, e.g. file transfer progress, to WebSocket clients
+pub fn publish(event: serde_json::Value) {
+    let _ = EVENTS.send(event.to_string());
+}
+
+// Our own activity: shown to local clients and sent to every connected peer
+pub async fn set_local(conversation_id: &str, sender: &str, activity: Activity) {
+    record(Indicator {
+        conversation_id: conversation_id.to_string(),
+        node: "local".to_string(),
+        sender: sender.to_string(),
+        activity,
+        updated_at: Utc::now(),
+    })
+    .await;
+    crate::tcp::broadcast_activity(conversation_id, sender, activity).await;
+}
+
+// Activity reported by a peer. Its "local" conversation is the one we keep under its IP.
+pub async fn receive_from_peer(peer_ip: &str, conversation_id: &str, sender: &str, activity: Activity) {
+    let conversation_id = if conversation_id == "local" { peer_ip } else { conversation_id };
+    record(Indicator {
+        conversation_id: conversation_id.to_string(),
+        node: peer_ip.to_string(),
+        sender: sender.to_string(),
+        activity,
+        updated_at: Utc::now(),
+    })
+    .await;
+}
+
+// Current indicators, optionally for one conversation; expired ones are dropped
+pub async fn active(conversation_id: Option<&str>) -> Vec<Indicator> {
+    let now = Utc::now();
+    let mut indicators = INDICATORS.lock().await;
+    indicators.retain(|_, i| now - i.updated_at < i.activity.ttl());
+    let mut out: Vec<Indicator> = indicators
+        .values()
+        .filter(|i| conversation_id.is_none_or(|id| i.conversation_id == id))
+        .cloned()
+        .collect();
+    out.sort_by_key(|i| i.updated_at);
+    out
+}
+
+fn valid_client_event(e: &ClientEvent) -> bool {
+    let sender = e.sender.trim();
+    !sender.is_empty() && sender.len() <= 64 && !e.conversation_id.is_empty() && e.conversation_id.len() <= 64
+}
+
+#[get("/conversations/{id}/participants")]
+pub async fn get_participants(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    match CONVERSATION_STORE.get_conversation(&id).await {
+        Some(conversation) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "conversation_id": id,
+            "participants": conversation.participants(),
+            "active": active(Some(&id)).await
+        }))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Conversation not found"
+        }))),
+    }
+}
+
+// Live activity feed. The server sends {"type": "activity", ...} events (after a snapshot of the
+// current ones); clients send {"conversation_id", "sender", "activity": "typing"|"idle"}.
+#[get("/ws")]
+pub async fn websocket(req: HttpRequest, body: web::Payload) -> Result<HttpResponse, Error> {
+    let (response, mut session, mut stream) = actix_ws::handle(&req, body)?;
+    let mut events = EVENTS.subscribe();
+    // Counts against limits.max_websocket_connections until the connection closes
+    let slot = req.extensions_mut().remove::<crate::limits::Slot>();
+    actix_web::rt::spawn(async move {
+        let _slot = slot;
+        for indicator in active(None).await {
+            let mut event = serde_json::to_value(&indicator).unwrap_or_default();
+            event["type"] = "activity".into();
+            if session.text(event.to_string()).await.is_err() {
+                return;
+            }
+        }
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(text) => {
+                        if session.text(text).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                msg = stream.recv() => match msg {
+                    Some(Ok(actix_ws::Message::Text(text))) => match serde_json::from_str::<ClientEvent>(&text) {
+                        // Only LLM hosts report "generating", and they do so themselves
+                        Ok(e) if e.activity != Activity::Generating && valid_client_event(&e) => {
+                            set_local(&e.conversation_id, e.sender.trim(), e.activity).await;
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("API: Ignoring WebSocket message: {}", e),
+                    },
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = session.close(None).await;
+    });
+    Ok(response)
+}
@@ -0,0 +1,218 @@
+// Running behind a reverse proxy. BASE_PATH (e.g. "/meshmind") is the prefix the proxy serves the
+// node under: requests may arrive with it or with the proxy having stripped it, and the embedded
+// UI gets it put in front of its asset links and API calls so the browser stays under the prefix.
+// TRUSTED_PROXIES lists the proxies (IPs or CIDR ranges, separated by commas) whose
+// X-Forwarded-For and X-Forwarded-Proto headers are believed; from anyone else those headers are
+// ignored and the connection's own address is the client.
+use actix_web::dev::ServiceRequest;
+use actix_web::http::Uri;
+use actix_web::HttpRequest;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+// Root-relative URLs in the UI bundle that must move under the base path
+const UI_ROOTS: [&str; 4] = ["/api", "/app/", "/peers", "/public"];
+
+// Clean up a BASE_PATH value: "/" and "" mean none, otherwise a leading slash and no trailing one
+pub fn normalize_base(raw: &str) -> Result<String, String> {
+    let trimmed = raw.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    if trimmed.split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+        return Err(format!("{:?} is not a valid path", raw));
+    }
+    if !trimmed.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~' | '/')) {
+        return Err(format!("{:?} may only contain letters, digits, '-', '_', '.', '~' and '/'", raw));
+    }
+    Ok(format!("/{}", trimmed))
+}
+
+// The configured prefix, e.g. "/meshmind"; empty when the node is served at the root
+pub fn base_path() -> &'static str {
+    static BASE_PATH: OnceLock<String> = OnceLock::new();
+    BASE_PATH.get_or_init(|| {
+        let value = std::env::var("BASE_PATH").unwrap_or_default();
+        normalize_base(&value).unwrap_or_else(|e| {
+            eprintln!("BASE_PATH: {}, serving at the root", e);
+            String::new()
+        })
+    })
+}
+
+// `path` under the base path, for links and redirects sent to the browser
+pub fn with_base(path: &str) -> String {
+    format!("{}{}", base_path(), path)
+}
+
+// `path` without the `base` prefix; None when it is not under it
+pub fn strip_base<'a>(path: &'a str, base: &str) -> Option<&'a str> {
+    match path.strip_prefix(base)? {
+        "" => Some("/"),
+        rest if rest.starts_with('/') => Some(rest),
+        _ => None,
+    }
+}
+
+// Route a request that still carries the base path as if it came without it
+pub fn strip_base_path(req: &mut ServiceRequest) {
+    let base = base_path();
+    if base.is_empty() {
+        return;
+    }
+    let Some(path) = strip_base(req.path(), base) else {
+        return;
+    };
+    let path_and_query = match req.query_string() {
+        "" => path.to_string(),
+        query => format!("{}?{}", path, query),
+    };
+    let mut parts = req.head().uri.clone().into_parts();
+    parts.path_and_query = path_and_query.parse().ok();
+    if let Ok(uri) = Uri::from_parts(parts) {
+        req.match_info_mut().get_mut().update(&uri);
+        req.head_mut().uri = uri;
+    }
+}
+
+// Put the base path in front of the root-relative URLs of an embedded UI file (HTML, JS or CSS),
+// and into index.html as a meta tag for URLs the bundle builds at runtime; None when there is no
+// base path or nothing to change
+pub fn rebase(body: &[u8]) -> Option<Vec<u8>> {
+    let base = base_path();
+    if base.is_empty() {
+        return None;
+    }
+    let mut text = String::from_utf8(body.to_vec()).ok()?;
+    if let Some(head) = text.find("<head>") {
+        text.insert_str(head + "<head>".len(), &format!("<meta name=\"base-path\" content=\"{}\">", base));
+    }
+    for root in UI_ROOTS {
+        for opener in ["\"", "'", "`", "url("] {
+            text = text.replace(&format!("{}{}", opener, root), &format!("{}{}{}", opener, base, root));
+        }
+    }
+    (text.as_bytes() != body).then(|| text.into_bytes())
+}
+
+// An IP address or a CIDR range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Net {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Net {
+    pub fn parse(value: &str) -> Result<Net, String> {
+        let value = value.trim();
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("{:?} is not an IP address or CIDR range", value))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().ok().filter(|p| *p <= max).ok_or_else(|| format!("{:?} has an invalid prefix length", value))?,
+            None => max,
+        };
+        Ok(Net { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) if self.addr.is_ipv4() => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            _ => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+fn trusted_proxies() -> &'static [Net] {
+    static TRUSTED: OnceLock<Vec<Net>> = OnceLock::new();
+    TRUSTED.get_or_init(|| {
+        let value = std::env::var("TRUSTED_PROXIES").unwrap_or_default();
+        value
+            .split(',')
+            .filter(|v| !v.trim().is_empty())
+            .filter_map(|v| Net::parse(v).map_err(|e| eprintln!("TRUSTED_PROXIES: ignoring {}", e)).ok())
+            .collect()
+    })
+}
+
+// The client behind `peer`: while the hops are trusted proxies, X-Forwarded-For is followed from
+// the right to the first address that is not one
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted: &[Net]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(peer) {
+        return peer;
+    }
+    let mut client = peer;
+    for hop in forwarded_for.unwrap_or("").rsplit(',') {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+                if !is_trusted(ip) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+// The address of the client that sent `req`, through any trusted proxies
+pub fn request_client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let forwarded_for = req.headers().get("x-forwarded-for").and_then(|v| v.to_str().ok());
+    Some(client_ip(peer, forwarded_for, trusted_proxies()))
+}
+
+// Whether a trusted proxy says the client reached it over HTTPS
+pub fn forwarded_https(req: &HttpRequest) -> bool {
+    let from_proxy = req.peer_addr().is_some_and(|a| trusted_proxies().iter().any(|net| net.contains(a.ip())));
+    from_proxy
+        && req
+            .headers()
+            .get("x-forwarded-proto")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_ip, normalize_base, strip_base, Net};
+
+    // Forwarded headers only count through trusted proxies; base paths are normalized and stripped
+    #[test]
+    fn forwarded_for_is_honored_only_from_trusted_proxies() {
+        let trusted = [Net::parse("10.0.0.0/8").unwrap(), Net::parse("::1").unwrap()];
+        let ip = |s: &str| s.parse::<std::net::IpAddr>().unwrap();
+        assert_eq!(client_ip(ip("192.168.1.5"), Some("1.2.3.4"), &trusted), ip("192.168.1.5"));
+        assert_eq!(client_ip(ip("10.0.0.2"), Some("1.2.3.4"), &trusted), ip("1.2.3.4"));
+        assert_eq!(client_ip(ip("10.0.0.2"), Some("6.6.6.6, 1.2.3.4, 10.0.0.3"), &trusted), ip("1.2.3.4"));
+        assert_eq!(client_ip(ip("10.0.0.2"), Some("garbage"), &trusted), ip("10.0.0.2"));
+        assert_eq!(client_ip(ip("::ffff:10.1.2.3"), Some("1.2.3.4"), &trusted), ip("1.2.3.4"));
+        assert!(Net::parse("10.0.0.0/33").is_err() && Net::parse("proxy").is_err());
+
+        assert_eq!(normalize_base("").unwrap(), "");
+        assert_eq!(normalize_base("/").unwrap(), "");
+        assert_eq!(normalize_base("meshmind/").unwrap(), "/meshmind");
+        assert!(normalize_base("/a/../b").is_err() && normalize_base("/a b").is_err());
+        assert_eq!(strip_base("/meshmind/api/status", "/meshmind"), Some("/api/status"));
+        assert_eq!(strip_base("/meshmind", "/meshmind"), Some("/"));
+        assert_eq!(strip_base("/meshmindx/api", "/meshmind"), None);
+        assert_eq!(strip_base("/api/status", "/meshmind"), None);
+    }
+}
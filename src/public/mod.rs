@@ -0,0 +1,215 @@
+// Read-only page for a wall dashboard: GET /public shows the pinned messages and selected files
+// without logging in, /public/data serves the same as JSON and /public/files/{name} downloads the
+// selected files. Everything is off unless `public.enabled` is set; what is shown is curated in the
+// `public` settings, and nothing else on the node becomes reachable without a session.
+use actix_web::{get, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use crate::conversation::CONVERSATION_STORE;
+use crate::persistence;
+
+const MAX_PINNED: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublicSettings {
+    pub enabled: bool,
+    pub title: String,
+    // Ids of messages from any conversation, shown in this order
+    pub pinned_messages: Vec<String>,
+    // Names of our own uploads that may be downloaded from the page
+    pub files: Vec<String>,
+    // How often the page reloads itself; 0 never
+    pub refresh_secs: u32,
+}
+
+impl Default for PublicSettings {
+    fn default() -> Self {
+        PublicSettings {
+            enabled: false,
+            title: "MeshMind".to_string(),
+            pinned_messages: Vec::new(),
+            files: Vec::new(),
+            refresh_secs: 60,
+        }
+    }
+}
+
+impl PublicSettings {
+    pub fn normalize(&mut self) {
+        self.title = self.title.trim().to_string();
+        for list in [&mut self.pinned_messages, &mut self.files] {
+            let mut seen = HashSet::new();
+            *list = list.iter().map(|v| v.trim().to_string()).filter(|v| !v.is_empty() && seen.insert(v.clone())).collect();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pinned_messages.len() > MAX_PINNED || self.files.len() > MAX_PINNED {
+            return Err(format!("public allows at most {} pinned messages and {} files", MAX_PINNED, MAX_PINNED));
+        }
+        if let Some(bad) = self.files.iter().find(|f| f.contains(['/', '\\'])) {
+            return Err(format!("public.files takes file names, not paths: {:?}", bad));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct PinnedMessage {
+    id: String,
+    sender: String,
+    node: String,
+    content: String,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct PublicFile {
+    filename: String,
+    file_type: String,
+    file_size: u64,
+    upload_time: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct PublicView {
+    title: String,
+    messages: Vec<PinnedMessage>,
+    files: Vec<PublicFile>,
+}
+
+// What the page shows, or None while it is disabled
+async fn view() -> Option<(PublicView, u32)> {
+    let settings = crate::settings::current().await.public;
+    if !settings.enabled {
+        return None;
+    }
+    let mut found: HashMap<String, PinnedMessage> = HashMap::new();
+    // Pins in private conversations stay off the public page
+    for conversation in CONVERSATION_STORE.all_conversations().await.into_iter().filter(|c| c.privacy.is_shared()) {
+        for m in conversation.messages {
+            if settings.pinned_messages.contains(&m.id) && !found.contains_key(&m.id) {
+                found.insert(m.id.clone(), PinnedMessage {
+                    id: m.id,
+                    sender: m.sender,
+                    node: m.host_info.hostname,
+                    content: m.content,
+                    timestamp: m.timestamp,
+                });
+            }
+        }
+    }
+    let messages = settings.pinned_messages.iter().filter_map(|id| found.remove(id)).collect();
+    let uploads = persistence::list_uploaded_files().await.unwrap_or_default();
+    let files = settings
+        .files
+        .iter()
+        .filter_map(|name| uploads.iter().find(|f| &f.filename == name))
+        // Files kept to this node's users stay off the page
+        .filter(|f| f.visibility.is_mesh())
+        .map(|f| PublicFile {
+            filename: f.filename.clone(),
+            file_type: f.file_type.clone(),
+            file_size: f.file_size,
+            upload_time: f.upload_time,
+        })
+        .collect();
+    Some((PublicView { title: settings.title, messages, files }, settings.refresh_secs))
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn render(view: &PublicView, refresh_secs: u32) -> String {
+    let mut html = String::from("<!doctype html>\n<html><head><meta charset=\"utf-8\">");
+    if refresh_secs > 0 {
+        html.push_str(&format!("<meta http-equiv=\"refresh\" content=\"{}\">", refresh_secs));
+    }
+    html.push_str(&format!(
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"><title>{}</title><style>\
+         body{{font-family:sans-serif;margin:2rem;background:#111;color:#eee}}\
+         article{{border-left:4px solid #4a9;padding:.5rem 1rem;margin:1rem 0;white-space:pre-wrap}}\
+         small{{color:#999}}a{{color:#6cf}}</style></head><body><h1>{}</h1>",
+        html_escape(&view.title),
+        html_escape(&view.title)
+    ));
+    if !view.messages.is_empty() {
+        html.push_str("<h2>Pinned</h2>");
+    }
+    for m in &view.messages {
+        html.push_str(&format!(
+            "<article><small>{} on {}, {}</small>\n{}</article>",
+            html_escape(&m.sender),
+            html_escape(&m.node),
+            m.timestamp.format("%Y-%m-%d %H:%M UTC"),
+            html_escape(&m.content)
+        ));
+    }
+    if !view.files.is_empty() {
+        html.push_str("<h2>Files</h2><ul>");
+    }
+    for f in &view.files {
+        html.push_str(&format!(
+            "<li><a href=\"/public/files/{}\">{}</a> <small>{} bytes</small></li>",
+            crate::webdav::encode_href(&f.filename),
+            html_escape(&f.filename),
+            f.file_size
+        ));
+    }
+    if !view.files.is_empty() {
+        html.push_str("</ul>");
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Not found" }))
+}
+
+#[get("/public")]
+pub async fn public_page() -> Result<HttpResponse, Error> {
+    Ok(match view().await {
+        Some((view, refresh_secs)) => HttpResponse::Ok().content_type("text/html; charset=utf-8").body(render(&view, refresh_secs)),
+        None => not_found(),
+    })
+}
+
+#[get("/public/data")]
+pub async fn public_data() -> Result<HttpResponse, Error> {
+    Ok(match view().await {
+        Some((view, _)) => HttpResponse::Ok().json(view),
+        None => not_found(),
+    })
+}
+
+#[get("/public/files/{filename}")]
+pub async fn public_file(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let filename = path.into_inner();
+    let settings = crate::settings::current().await.public;
+    if !settings.enabled || !settings.files.contains(&filename) {
+        return Ok(not_found());
+    }
+    if persistence::get_file_info(&filename).await.ok().flatten().is_some_and(|f| !f.visibility.is_mesh()) {
+        return Ok(not_found());
+    }
+    match persistence::get_file_content(&filename).await {
+        Ok(Some(content)) => {
+            let file_type = persistence::get_file_info(&filename)
+                .await
+                .ok()
+                .flatten()
+                .map(|f| f.file_type)
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            Ok(HttpResponse::Ok().content_type(file_type).body(content))
+        }
+        Ok(None) => Ok(not_found()),
+        Err(e) => {
+            println!("API: Failed to read public file {}: {}", filename, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({ "success": false, "message": e.to_string() })))
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// Redaction of secrets in chat text. Regex rules (API keys, e-mail addresses, ...) are applied to
+// prompts before they are stored or sent to a model, to answers before they are stored, and to
+// conversations again before they are shared with peers. Rules live in redaction_rules.json and
+// start out with a built-in set; messages that had something removed carry `redacted: true`.
+use actix_web::{delete, get, post, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::conversation::Conversation;
+
+const RULES_FILE: &str = "redaction_rules.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionRule {
+    pub id: String,
+    pub name: String,
+    pub pattern: String,
+    // Replacement text; "[REDACTED:<name>]" when empty. May refer to capture groups ($1)
+    #[serde(default)]
+    pub replacement: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct NewRedactionRule {
+    name: String,
+    pattern: String,
+    #[serde(default)]
+    replacement: String,
+}
+
+#[derive(Deserialize)]
+pub struct RedactionTest {
+    text: String,
+}
+
+struct CompiledRule {
+    rule: RedactionRule,
+    regex: Regex,
+}
+
+lazy_static! {
+    static ref RULES: Arc<Mutex<Option<Vec<CompiledRule>>>> = Arc::new(Mutex::new(None));
+}
+
+fn builtin_rules() -> Vec<RedactionRule> {
+    [
+        ("openai-key", r"\bsk-[A-Za-z0-9_-]{20,}"),
+        ("aws-access-key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+        ("github-token", r"\bgh[pousr]_[A-Za-z0-9]{36,}\b"),
+        ("bearer-token", r"(?i)\bbearer\s+[A-Za-z0-9._~+/=-]{20,}"),
+        ("private-key", r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----"),
+        ("email", r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b"),
+    ]
+    .iter()
+    .map(|(name, pattern)| RedactionRule {
+        id: format!("builtin-{}", name),
+        name: name.to_string(),
+        pattern: pattern.to_string(),
+        replacement: String::new(),
+        enabled: true,
+        created_at: Utc::now(),
+    })
+    .collect()
+}
+
+fn compile(rule: RedactionRule) -> Result<CompiledRule, String> {
+    let regex = Regex::new(&rule.pattern).map_err(|e| format!("invalid pattern for {}: {}", rule.name, e))?;
+    Ok(CompiledRule { rule, regex })
+}
+
+async fn load_rules() -> Vec<CompiledRule> {
+    let rules = match tokio::fs::read_to_string(RULES_FILE).await {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("REDACT: Failed to parse {}, using built-in rules: {}", RULES_FILE, e);
+            builtin_rules()
+        }),
+        Err(_) => builtin_rules(),
+    };
+    rules
+        .into_iter()
+        .filter_map(|r| compile(r).map_err(|e| eprintln!("REDACT: Skipping rule: {}", e)).ok())
+        .collect()
+}
+
+async fn save_rules(rules: &[RedactionRule]) {
+    match serde_json::to_string_pretty(rules) {
+        Ok(s) => {
+            if let Err(e) = tokio::fs::write(RULES_FILE, s).await {
+                eprintln!("REDACT: Failed to save {}: {}", RULES_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("REDACT: Failed to serialize rules: {}", e),
+    }
+}
+
+// Run `f` on the loaded rules
+async fn with_rules<R>(f: impl FnOnce(&mut Vec<CompiledRule>) -> R) -> R {
+    let mut guard = RULES.lock().await;
+    if guard.is_none() {
+        *guard = Some(load_rules().await);
+    }
+    f(guard.get_or_insert_with(Vec::new))
+}
+
+fn plain(rules: &[CompiledRule]) -> Vec<RedactionRule> {
+    rules.iter().map(|c| c.rule.clone()).collect()
+}
+
+fn apply(rules: &[CompiledRule], text: &str) -> (String, Vec<String>) {
+    let mut out = text.to_string();
+    let mut matched = Vec::new();
+    for c in rules.iter().filter(|c| c.rule.enabled) {
+        if c.regex.is_match(&out) {
+            let replacement = if c.rule.replacement.is_empty() {
+                format!("[REDACTED:{}]", c.rule.name)
+            } else {
+                c.rule.replacement.clone()
+            };
+            out = c.regex.replace_all(&out, replacement.as_str()).into_owned();
+            matched.push(c.rule.name.clone());
+        }
+    }
+    (out, matched)
+}
+
+// Text with every enabled rule applied, plus the names of the rules that matched
+pub async fn redact(text: &str) -> (String, Vec<String>) {
+    with_rules(|rules| apply(rules, text)).await
+}
+
+// Copy of a conversation that is safe to hand to peers
+pub async fn for_peers(conversation: &Conversation) -> Conversation {
+    let mut out = conversation.clone();
+    with_rules(|rules| {
+        for m in out.messages.iter_mut() {
+            let (content, matched) = apply(rules, &m.content);
+            if !matched.is_empty() {
+                m.content = content;
+                m.redacted = true;
+            }
+        }
+    })
+    .await;
+    out
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can change redaction rules" }))
+}
+
+#[get("/redaction/rules")]
+pub async fn list_rules() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(with_rules(|rules| plain(rules)).await))
+}
+
+#[post("/redaction/rules")]
+pub async fn create_rule(body: web::Json<NewRedactionRule>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let body = body.into_inner();
+    if body.name.trim().is_empty() || body.pattern.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "name and pattern are required"
+        })));
+    }
+    let rule = RedactionRule {
+        id: format!("redact-{}", Utc::now().timestamp_millis()),
+        name: body.name.trim().to_string(),
+        pattern: body.pattern,
+        replacement: body.replacement,
+        enabled: true,
+        created_at: Utc::now(),
+    };
+    let compiled = match compile(rule.clone()) {
+        Ok(c) => c,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": e })));
+        }
+    };
+    let rules = with_rules(|rules| {
+        rules.push(compiled);
+        plain(rules)
+    })
+    .await;
+    save_rules(&rules).await;
+    println!("REDACT: Added rule {} ({})", rule.id, rule.name);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "rule": rule })))
+}
+
+#[delete("/redaction/rules/{id}")]
+pub async fn delete_rule(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let remaining = with_rules(|rules| {
+        let before = rules.len();
+        rules.retain(|c| c.rule.id != id);
+        (rules.len() != before).then(|| plain(rules))
+    })
+    .await;
+    match remaining {
+        Some(rules) => save_rules(&rules).await,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Rule not found" })));
+        }
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true })))
+}
+
+// Preview what the current rules do to a piece of text
+#[post("/redaction/test")]
+pub async fn test_rules(body: web::Json<RedactionTest>) -> Result<HttpResponse, Error> {
+    let (text, matched) = redact(&body.text).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "text": text, "matched": matched })))
+}
@@ -0,0 +1,287 @@
+// One-way file replication: rules like "push every file tagged 'datasets' to 192.168.1.20 at 02:00"
+// are stored in replication_rules.json, checked by a scheduler and executed as jobs. Files are sent
+// over the chunked TCP transfer; anything whose hash the target already holds is skipped.
+use actix_web::{delete, get, post, web, HttpResponse, Error};
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use crate::persistence;
+
+const RULES_FILE: &str = "replication_rules.json";
+const SCHEDULER_TICK: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationRule {
+    pub id: String,
+    pub tag: String,
+    pub target_peer: String,
+    // Local time "HH:MM" to run once a day (e.g. "02:00" for nightly)
+    #[serde(default)]
+    pub daily_at: Option<String>,
+    // Alternatively run every N seconds
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_job_id: Option<u64>,
+}
+
+fn default_enabled() -> bool { true }
+
+#[derive(Deserialize)]
+pub struct NewRule {
+    tag: String,
+    target_peer: String,
+    #[serde(default)]
+    daily_at: Option<String>,
+    #[serde(default)]
+    interval_secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HashList {
+    hashes: Vec<String>,
+}
+
+lazy_static! {
+    static ref RULES: Arc<Mutex<Vec<ReplicationRule>>> = Arc::new(Mutex::new(Vec::new()));
+}
+
+async fn load_rules() -> Vec<ReplicationRule> {
+    match tokio::fs::read_to_string(RULES_FILE).await {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("REPL: Failed to parse {}: {}", RULES_FILE, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn save_rules(rules: &[ReplicationRule]) {
+    match serde_json::to_string_pretty(rules) {
+        Ok(s) => {
+            if let Err(e) = tokio::fs::write(RULES_FILE, s).await {
+                eprintln!("REPL: Failed to save {}: {}", RULES_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("REPL: Failed to serialize rules: {}", e),
+    }
+}
+
+fn parse_daily_at(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()
+}
+
+fn is_due(rule: &ReplicationRule, now: DateTime<Utc>) -> bool {
+    if !rule.enabled {
+        return false;
+    }
+    let last = rule.last_run.unwrap_or(rule.created_at);
+    if let Some(secs) = rule.interval_secs {
+        return now.signed_duration_since(last).num_seconds() >= secs as i64;
+    }
+    if let Some(at) = rule.daily_at.as_deref().and_then(parse_daily_at) {
+        let today = now.with_timezone(&Local).date_naive().and_time(at);
+        if let Some(scheduled) = Local.from_local_datetime(&today).earliest() {
+            let scheduled = scheduled.with_timezone(&Utc);
+            return now >= scheduled && last < scheduled;
+        }
+    }
+    false
+}
+
+async fn fetch_remote_hashes(peer_ip: &str) -> Result<HashSet<String>, String> {
+    let url = format!("{}/api/replication/hashes", crate::tls::peer_origin(peer_ip, 8080));
+    let resp = crate::client::peer()
+        .get(&url)
+        .timeout(Duration::from_secs(120))
+        .header(crate::tcp::PEER_REQUEST_HEADER, crate::tcp::sign_peer_request("GET", &url).await)
+        .send()
+        .await
+        .map_err(|e| format!("hash list from {} failed: {}", peer_ip, e))?;
+    if !resp.status().is_success() {
+        return Err(format!("hash list from {} returned {}", peer_ip, resp.status()));
+    }
+    let list: HashList = resp.json().await.map_err(|e| e.to_string())?;
+    Ok(list.hashes.into_iter().map(|h| h.to_lowercase()).collect())
+}
+
+async fn run_rule(rule: ReplicationRule) -> Result<String, String> {
+    if !crate::tcp::connected_peer_ips().await.contains(&rule.target_peer) {
+        return Err(format!("peer {} is not connected", rule.target_peer));
+    }
+    let files: Vec<_> = persistence::list_uploaded_files()
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|f| f.visibility.is_mesh() && f.tags.iter().any(|t| t.eq_ignore_ascii_case(&rule.tag)))
+        .collect();
+    if files.is_empty() {
+        return Ok(format!("no files tagged '{}'", rule.tag));
+    }
+    let remote = fetch_remote_hashes(&rule.target_peer).await?;
+
+    let (mut sent, mut skipped, mut failed) = (0usize, 0usize, 0usize);
+    for f in files {
+        let content = match persistence::get_file_content(&f.filename).await {
+            Ok(Some(c)) => c,
+            _ => {
+                failed += 1;
+                continue;
+            }
+        };
+        let sha = persistence::sha256_hex(&content);
+        if remote.contains(&sha) {
+            skipped += 1;
+            continue;
+        }
+        match crate::tcp::send_file_chunked(&rule.target_peer, &f.filename, &f.file_type, &content).await {
+            Ok(_) => sent += 1,
+            Err(e) => {
+                eprintln!("REPL: Failed to send {} to {}: {}", f.filename, rule.target_peer, e);
+                failed += 1;
+            }
+        }
+    }
+    let summary = format!("sent {}, skipped {} already present, failed {}", sent, skipped, failed);
+    if failed > 0 { Err(summary) } else { Ok(summary) }
+}
+
+async fn start_rule(rule: &mut ReplicationRule) -> u64 {
+    rule.last_run = Some(Utc::now());
+    let description = format!("replicate tag '{}' to {}", rule.tag, rule.target_peer);
+    let job_id = crate::jobs::submit("replication", description, run_rule(rule.clone())).await;
+    rule.last_job_id = Some(job_id);
+    job_id
+}
+
+pub async fn scheduler() {
+    {
+        let mut rules = RULES.lock().await;
+        *rules = load_rules().await;
+        if !rules.is_empty() {
+            println!("REPL: Loaded {} replication rules", rules.len());
+        }
+    }
+    let mut interval = tokio::time::interval(SCHEDULER_TICK);
+    loop {
+        interval.tick().await;
+        let mut rules = RULES.lock().await;
+        let now = Utc::now();
+        let mut changed = false;
+        for rule in rules.iter_mut() {
+            if is_due(rule, now) {
+                start_rule(rule).await;
+                changed = true;
+            }
+        }
+        if changed {
+            save_rules(&rules).await;
+        }
+    }
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can manage replication rules" }))
+}
+
+#[get("/replication/rules")]
+pub async fn list_rules() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(RULES.lock().await.clone()))
+}
+
+#[post("/replication/rules")]
+pub async fn create_rule(body: web::Json<NewRule>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let body = body.into_inner();
+    let tag = body.tag.trim().to_string();
+    let target_peer = body.target_peer.trim().to_string();
+    if tag.is_empty() || target_peer.parse::<std::net::IpAddr>().is_err() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "tag and a valid target_peer IP are required"
+        })));
+    }
+    let valid_schedule = match (&body.daily_at, body.interval_secs) {
+        (Some(at), None) => parse_daily_at(at).is_some(),
+        (None, Some(secs)) => secs >= 60,
+        _ => false,
+    };
+    if !valid_schedule {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "set exactly one of daily_at (\"HH:MM\") or interval_secs (>= 60)"
+        })));
+    }
+    let rule = ReplicationRule {
+        id: format!("rule-{}", Utc::now().timestamp_millis()),
+        tag,
+        target_peer,
+        daily_at: body.daily_at,
+        interval_secs: body.interval_secs,
+        enabled: true,
+        created_at: Utc::now(),
+        last_run: None,
+        last_job_id: None,
+    };
+    let mut rules = RULES.lock().await;
+    rules.push(rule.clone());
+    save_rules(&rules).await;
+    println!("REPL: Added rule {} (tag '{}' -> {})", rule.id, rule.tag, rule.target_peer);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "rule": rule })))
+}
+
+#[delete("/replication/rules/{id}")]
+pub async fn delete_rule(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let mut rules = RULES.lock().await;
+    let before = rules.len();
+    rules.retain(|r| r.id != id);
+    if rules.len() == before {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Rule not found" })));
+    }
+    save_rules(&rules).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true })))
+}
+
+// Run a rule immediately, outside its schedule
+#[post("/replication/rules/{id}/run")]
+pub async fn run_rule_now(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let id = path.into_inner();
+    let mut rules = RULES.lock().await;
+    let rule = match rules.iter_mut().find(|r| r.id == id) {
+        Some(r) => r,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Rule not found" }))),
+    };
+    let job_id = start_rule(rule).await;
+    save_rules(&rules).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
+
+// Hashes of everything this node holds, so a replicating peer can skip files already present
+#[get("/replication/hashes")]
+pub async fn list_hashes() -> Result<HttpResponse, Error> {
+    match persistence::stored_file_hashes().await {
+        Ok(hashes) => Ok(HttpResponse::Ok().json(HashList { hashes: hashes.into_iter().collect() })),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": e.to_string()
+        }))),
+    }
+}
@@ -0,0 +1,168 @@
+// Virus scanning of incoming files through clamd (ClamAV). Off unless `scan.clamd` is set in the
+// settings; local uploads and files received from peers are then streamed to clamd (INSTREAM)
+// before they are stored. Infected files are refused or moved to quarantine/, every detection is
+// written to the audit log, and uploads keep their verdict in their metadata.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const QUARANTINE_DIR: &str = "quarantine";
+// clamd accepts INSTREAM data in chunks prefixed with their length
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InfectedAction {
+    Reject,
+    Quarantine,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanSettings {
+    // "host:port" of clamd's TCP socket, or "unix:/run/clamav/clamd.ctl"; unset disables scanning
+    pub clamd: Option<String>,
+    pub action: InfectedAction,
+    // Store files unscanned while clamd cannot be reached instead of refusing them
+    pub fail_open: bool,
+    pub timeout_secs: u64,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        ScanSettings { clamd: None, action: InfectedAction::Reject, fail_open: false, timeout_secs: 30 }
+    }
+}
+
+impl ScanSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(address) = &self.clamd {
+            let valid = match address.strip_prefix("unix:") {
+                Some(path) => !path.is_empty(),
+                None => address.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok()),
+            };
+            if !valid {
+                return Err(format!("scan.clamd must be host:port or unix:/path, not {:?}", address));
+            }
+        }
+        if self.timeout_secs == 0 {
+            return Err("scan.timeout_secs must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanResult {
+    Clean,
+    Infected,
+    // clamd could not be asked and fail_open let the file through
+    Unscanned,
+}
+
+// Stored with an upload's metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanVerdict {
+    pub result: ScanResult,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    pub scanned_at: DateTime<Utc>,
+}
+
+enum Outcome {
+    Clean,
+    Infected(String),
+}
+
+async fn instream<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, content: &[u8]) -> std::io::Result<String> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in content.chunks(STREAM_CHUNK_SIZE) {
+        stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await?;
+    Ok(String::from_utf8_lossy(&reply).trim_end_matches(['\0', '\n']).to_string())
+}
+
+async fn ask_clamd(address: &str, content: &[u8]) -> Result<Outcome, String> {
+    let reply = match address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        Some(path) => instream(tokio::net::UnixStream::connect(path).await.map_err(|e| e.to_string())?, content).await,
+        #[cfg(not(unix))]
+        Some(_) => return Err("unix sockets are not supported on this platform".to_string()),
+        None => instream(tokio::net::TcpStream::connect(address).await.map_err(|e| e.to_string())?, content).await,
+    }
+    .map_err(|e| e.to_string())?;
+    // "stream: OK", "stream: Eicar-Signature FOUND" or "... ERROR"
+    let status = reply.strip_prefix("stream: ").unwrap_or(&reply);
+    if status == "OK" {
+        Ok(Outcome::Clean)
+    } else if let Some(signature) = status.strip_suffix(" FOUND") {
+        Ok(Outcome::Infected(signature.to_string()))
+    } else {
+        Err(format!("clamd answered {:?}", reply))
+    }
+}
+
+async fn quarantine(name: &str, content: &[u8]) -> std::io::Result<()> {
+    let dir = Path::new(QUARANTINE_DIR);
+    tokio::fs::create_dir_all(dir).await?;
+    let safe = name.replace(['/', '\\'], "_");
+    crate::persistence::write_atomic(&dir.join(format!("{}_{}", Utc::now().timestamp_millis(), safe)), content).await
+}
+
+// Decide whether a file from `source` (uploader or peer IP) may be stored. Ok carries the verdict
+// to keep (None while scanning is off); Err says why the file was refused.
+pub async fn check(name: &str, content: &[u8], source: &str) -> Result<Option<ScanVerdict>, String> {
+    let settings = crate::settings::current().await.scan;
+    let address = match &settings.clamd {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    let verdict = |result, signature| Some(ScanVerdict { result, signature, scanned_at: Utc::now() });
+    let outcome = tokio::time::timeout(Duration::from_secs(settings.timeout_secs), ask_clamd(address, content))
+        .await
+        .unwrap_or_else(|_| Err("timed out".to_string()));
+    match outcome {
+        Ok(Outcome::Clean) => Ok(verdict(ScanResult::Clean, None)),
+        Ok(Outcome::Infected(signature)) => {
+            let quarantined = settings.action == InfectedAction::Quarantine;
+            if quarantined {
+                if let Err(e) = quarantine(name, content).await {
+                    eprintln!("SCAN: Failed to quarantine {}: {}", name, e);
+                }
+            }
+            crate::events::error(
+                crate::events::Category::Security,
+                format!("{} from {} is infected ({}), {}", name, source, signature, if quarantined { "quarantined" } else { "rejected" }),
+                serde_json::json!({ "filename": name, "source": source, "signature": signature }),
+            );
+            crate::audit::record(
+                "file_infected",
+                source,
+                serde_json::json!({ "filename": name, "signature": signature, "action": settings.action, "bytes": content.len() }),
+            )
+            .await;
+            Err(format!("{} is infected ({})", name, signature))
+        }
+        Err(e) if settings.fail_open => {
+            crate::events::warn(
+                crate::events::Category::Security,
+                format!("clamd unavailable ({}), storing {} unscanned", e, name),
+                serde_json::json!({ "filename": name, "source": source }),
+            );
+            crate::audit::record("file_unscanned", source, serde_json::json!({ "filename": name, "error": e })).await;
+            Ok(verdict(ScanResult::Unscanned, None))
+        }
+        Err(e) => {
+            eprintln!("SCAN: clamd unavailable ({}), refusing {}", e, name);
+            Err(format!("virus scan failed: {}", e))
+        }
+    }
+}
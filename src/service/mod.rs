@@ -0,0 +1,260 @@
+// `install-service` / `uninstall-service` subcommands: register the node to start at boot without
+// anyone launching it. Generates a systemd unit on Linux, a launchd plist on macOS and a boot-time
+// scheduled task (running a small .cmd launcher) on Windows. The node runs in the config directory,
+// where it keeps settings.json, conversations and secrets, with stdout/stderr appended to the log
+// file and NO_BROWSER=1 so no browser tab is opened.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const USAGE: &str = "usage: instance install-service [--name NAME] [--config-dir DIR] [--log FILE] [--user] [--print]
+       instance uninstall-service [--name NAME] [--user]
+
+  --name        service name (default meshmind)
+  --config-dir  working directory with settings.json, secrets and data (default: current directory)
+  --log         file stdout/stderr are appended to (default: <config-dir>/meshmind.log)
+  --user        install for the current user instead of system-wide (systemd/launchd only)
+  --print       only print the generated definition";
+
+struct ServiceSpec {
+    name: String,
+    exe: PathBuf,
+    config_dir: PathBuf,
+    log: PathBuf,
+    user: bool,
+    print: bool,
+}
+
+fn parse_args(args: &[String]) -> Result<ServiceSpec, String> {
+    let mut name = "meshmind".to_string();
+    let mut config_dir = None;
+    let mut log = None;
+    let (mut user, mut print) = (false, false);
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--name" => name = value()?,
+            "--config-dir" => config_dir = Some(PathBuf::from(value()?)),
+            "--log" => log = Some(PathBuf::from(value()?)),
+            "--user" => user = true,
+            "--print" => print = true,
+            other => return Err(format!("unknown option {}", other)),
+        }
+    }
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') {
+        return Err("--name may only contain letters, digits, '-', '_' and '.'".to_string());
+    }
+    let config_dir = match config_dir {
+        Some(dir) => dir,
+        None => std::env::current_dir().map_err(|e| e.to_string())?,
+    };
+    let config_dir = config_dir.canonicalize().map_err(|e| format!("config dir {}: {}", config_dir.display(), e))?;
+    let log = match log {
+        Some(l) if l.is_absolute() => l,
+        Some(l) => config_dir.join(l),
+        None => config_dir.join("meshmind.log"),
+    };
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    Ok(ServiceSpec { name, exe, config_dir, log, user, print })
+}
+
+fn systemd_unit(spec: &ServiceSpec) -> String {
+    format!(
+        "[Unit]
+Description=MeshMind node ({name})
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+ExecStart=\"{exe}\"
+WorkingDirectory={dir}
+Environment=NO_BROWSER=1
+StandardOutput=append:{log}
+StandardError=append:{log}
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy={target}
+",
+        name = spec.name,
+        exe = spec.exe.display(),
+        dir = spec.config_dir.display(),
+        log = spec.log.display(),
+        target = if spec.user { "default.target" } else { "multi-user.target" },
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn launchd_label(spec: &ServiceSpec) -> String {
+    format!("com.meshmind.{}", spec.name)
+}
+
+fn launchd_plist(spec: &ServiceSpec) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{exe}</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{dir}</string>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>NO_BROWSER</key>
+        <string>1</string>
+    </dict>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+</dict>
+</plist>
+"#,
+        label = xml_escape(&launchd_label(spec)),
+        exe = xml_escape(&spec.exe.display().to_string()),
+        dir = xml_escape(&spec.config_dir.display().to_string()),
+        log = xml_escape(&spec.log.display().to_string()),
+    )
+}
+
+// Task Scheduler cannot set a working directory or redirect output, so the task runs this launcher
+fn windows_launcher(spec: &ServiceSpec) -> String {
+    format!(
+        "@echo off\r\nrem Generated by install-service; started at boot by the {name} scheduled task\r\ncd /d \"{dir}\"\r\nset \"NO_BROWSER=1\"\r\n\"{exe}\" >> \"{log}\" 2>&1\r\n",
+        name = spec.name,
+        dir = spec.config_dir.display(),
+        exe = spec.exe.display(),
+        log = spec.log.display(),
+    )
+}
+
+fn home_dir() -> Result<PathBuf, String> {
+    std::env::var_os("HOME").map(PathBuf::from).ok_or_else(|| "HOME is not set".to_string())
+}
+
+// Where the definition goes, and its content
+fn definition(spec: &ServiceSpec) -> Result<(PathBuf, String), String> {
+    match std::env::consts::OS {
+        "linux" => {
+            let dir = if spec.user { home_dir()?.join(".config/systemd/user") } else { PathBuf::from("/etc/systemd/system") };
+            Ok((dir.join(format!("{}.service", spec.name)), systemd_unit(spec)))
+        }
+        "macos" => {
+            let dir = if spec.user { home_dir()?.join("Library/LaunchAgents") } else { PathBuf::from("/Library/LaunchDaemons") };
+            Ok((dir.join(format!("{}.plist", launchd_label(spec))), launchd_plist(spec)))
+        }
+        "windows" => Ok((spec.config_dir.join(format!("{}-service.cmd", spec.name)), windows_launcher(spec))),
+        other => Err(format!("install-service is not supported on {}", other)),
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), String> {
+    let status = Command::new(program).args(args).status().map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} {} exited with {}", program, args.join(" "), status))
+    }
+}
+
+fn register(spec: &ServiceSpec, path: &Path) -> Result<(), String> {
+    let path_str = path.display().to_string();
+    match std::env::consts::OS {
+        "linux" => {
+            let scope: &[&str] = if spec.user { &["--user"] } else { &[] };
+            run("systemctl", &[scope, &["daemon-reload"]].concat())?;
+            run("systemctl", &[scope, &["enable", "--now", &format!("{}.service", spec.name)]].concat())
+        }
+        "macos" => run("launchctl", &["load", "-w", &path_str]),
+        _ => {
+            let task_command = format!("\"{}\"", path_str);
+            run("schtasks", &["/Create", "/TN", &spec.name, "/TR", &task_command, "/SC", "ONSTART", "/RU", "SYSTEM", "/RL", "HIGHEST", "/F"])
+        }
+    }
+}
+
+fn install(spec: &ServiceSpec) -> Result<(), String> {
+    let (path, content) = definition(spec)?;
+    if spec.print {
+        println!("# {}\n{}", path.display(), content);
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    }
+    if let Some(dir) = spec.log.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(&path, content).map_err(|e| format!("failed to write {} (run as administrator/root, or use --user): {}", path.display(), e))?;
+    println!("SERVICE: Wrote {}", path.display());
+    register(spec, &path)?;
+    println!(
+        "SERVICE: {} starts at boot from {} (data in {}, log {})",
+        spec.name,
+        spec.exe.display(),
+        spec.config_dir.display(),
+        spec.log.display()
+    );
+    Ok(())
+}
+
+fn uninstall(spec: &ServiceSpec) -> Result<(), String> {
+    let (path, _) = definition(spec)?;
+    let path_str = path.display().to_string();
+    // Stopping may fail if it never ran; removing the definition is what matters
+    let stopped = match std::env::consts::OS {
+        "linux" => {
+            let scope: &[&str] = if spec.user { &["--user"] } else { &[] };
+            run("systemctl", &[scope, &["disable", "--now", &format!("{}.service", spec.name)]].concat())
+        }
+        "macos" => run("launchctl", &["unload", "-w", &path_str]),
+        _ => run("schtasks", &["/Delete", "/TN", &spec.name, "/F"]),
+    };
+    if let Err(e) = stopped {
+        eprintln!("SERVICE: {}", e);
+    }
+    match std::fs::remove_file(&path) {
+        Ok(()) => println!("SERVICE: Removed {}", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("failed to remove {}: {}", path.display(), e)),
+    }
+    if std::env::consts::OS == "linux" {
+        let scope: &[&str] = if spec.user { &["--user"] } else { &[] };
+        let _ = run("systemctl", &[scope, &["daemon-reload"]].concat());
+    }
+    Ok(())
+}
+
+// Handle a service subcommand; None when the arguments are not one, so the node starts normally
+pub fn handle_command(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        "install-service" => parse_args(rest).and_then(|spec| install(&spec)),
+        "uninstall-service" => parse_args(rest).and_then(|spec| uninstall(&spec)),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            return Some(0);
+        }
+        _ => return None,
+    };
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("SERVICE: {}\n\n{}", e, USAGE);
+            Some(1)
+        }
+    }
+}
@@ -0,0 +1,345 @@
+// Node settings editable at runtime through GET/PUT /api/settings and persisted in settings.json.
+// Holds the file policy (which MIME types may be stored and how large they may be, applied to
+// local uploads and to files received from peers), the default LLM generation parameters, the
+// chat language handling, the moderation policy, the self-update configuration, virus scanning,
+// the security headers sent with HTTP responses, what the public page shows, whether peers may
+// fetch this node's diagnostics, how verbose the event log is, which LLM server answers prompts and
+// how calls to it and to peers are timed out and retried, the context window of each model, single
+// sign-on through OpenID Connect, the request body size and concurrency limits, and when the
+// knowledge base answers or adds context.
+// Only the admin may change them.
+use actix_web::{get, put, web, HttpResponse, Error};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::diagnostics::DiagnosticsSettings;
+use crate::events::EventSettings;
+use crate::headers::SecurityHeaderSettings;
+use crate::knowledge::KnowledgeSettings;
+use crate::limits::LimitSettings;
+use crate::llm::backend::BackendSettings;
+use crate::llm::policy::CallPolicy;
+use crate::llm::tokens::ContextSettings;
+use crate::llm::routing::Routing;
+use crate::moderation::ModerationSettings;
+use crate::persistence::MAX_FILE_SIZE;
+use crate::public::PublicSettings;
+use crate::scan::ScanSettings;
+use crate::update::UpdateSettings;
+use crate::users::oidc::OidcSettings;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Exact MIME types or "type/*" wildcards; "*/*" allows everything
+    pub allowed_file_types: Vec<String>,
+    // Upper bound for any single file
+    pub max_file_size: u64,
+    // Tighter limits for particular types or wildcards, e.g. {"video/*": 2147483648}
+    pub file_type_limits: BTreeMap<String, u64>,
+    // Defaults for chat requests that do not set their own generation parameters
+    pub generation: GenerationOptions,
+    pub language: LanguageSettings,
+    pub moderation: ModerationSettings,
+    pub update: UpdateSettings,
+    // Virus scanning of uploads and peer files through clamd
+    pub scan: ScanSettings,
+    pub security_headers: SecurityHeaderSettings,
+    // Pinned messages and files on the unauthenticated /public page
+    pub public: PublicSettings,
+    // Which peers may fetch this node's version, config summary and recent events
+    pub diagnostics: DiagnosticsSettings,
+    // Coalescing of repeated events and verbosity per event category
+    pub events: EventSettings,
+    // The model server this node answers prompts with: Ollama, OpenAI-compatible or llama.cpp
+    pub backend: BackendSettings,
+    // Timeouts, retries and circuit breaking for calls to the local backend and to peers
+    pub llm_calls: CallPolicy,
+    // Default routing of prompts between this node's model and peers; local_only keeps them here
+    pub routing: Routing,
+    // Context window of each model and the tokens kept free for answers, which prompts are fit into
+    pub context: ContextSettings,
+    // Sign-in through an OpenID Connect provider, with roles from the user's groups
+    pub oidc: OidcSettings,
+    // Largest JSON body, and how many uploads and WebSocket connections are handled at once
+    pub limits: LimitSettings,
+    // How similar a saved answer's question must be to reuse it or pass it to the model as context
+    pub knowledge: KnowledgeSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanguageSettings {
+    // Detect the question's language and ask for an answer in it
+    pub auto_detect: bool,
+    // ISO 639-3 code every answer should use regardless of the question, e.g. "deu"
+    pub response_language: Option<String>,
+    // Localized system prompts keyed by ISO 639-3 code, e.g. {"spa": "Eres un asistente..."}
+    pub system_prompts: BTreeMap<String, String>,
+}
+
+impl Default for LanguageSettings {
+    fn default() -> Self {
+        LanguageSettings { auto_detect: true, response_language: None, system_prompts: BTreeMap::new() }
+    }
+}
+
+// Sampling parameters forwarded to the LLM backend; unset values use the model defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl GenerationOptions {
+    // Fill unset values from `defaults`
+    pub fn or(&self, defaults: &GenerationOptions) -> GenerationOptions {
+        GenerationOptions {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            num_ctx: self.num_ctx.or(defaults.num_ctx),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+            return Err("temperature must be between 0 and 2".to_string());
+        }
+        if self.top_p.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+            return Err("top_p must be between 0 and 1".to_string());
+        }
+        if self.num_ctx == Some(0) || self.max_tokens == Some(0) {
+            return Err("num_ctx and max_tokens must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+
+    // Ollama's `options` object (max_tokens is called num_predict there)
+    pub fn to_ollama(&self) -> Option<serde_json::Value> {
+        let mut options = serde_json::Map::new();
+        if let Some(t) = self.temperature {
+            options.insert("temperature".to_string(), t.into());
+        }
+        if let Some(p) = self.top_p {
+            options.insert("top_p".to_string(), p.into());
+        }
+        if let Some(n) = self.num_ctx {
+            options.insert("num_ctx".to_string(), n.into());
+        }
+        if let Some(n) = self.max_tokens {
+            options.insert("num_predict".to_string(), n.into());
+        }
+        (!options.is_empty()).then_some(serde_json::Value::Object(options))
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            allowed_file_types: [
+                "image/*",
+                "audio/*",
+                "video/*",
+                "text/plain",
+                "text/markdown",
+                "text/csv",
+                "application/json",
+                "application/pdf",
+                "application/octet-stream",
+                "application/x-msdownload",
+                "application/zip",
+                "application/x-zip-compressed",
+                "application/x-7z-compressed",
+                "application/x-rar-compressed",
+            ]
+            .iter()
+            .map(|t| t.to_string())
+            .collect(),
+            max_file_size: MAX_FILE_SIZE,
+            file_type_limits: BTreeMap::new(),
+            generation: GenerationOptions::default(),
+            language: LanguageSettings::default(),
+            moderation: ModerationSettings::default(),
+            update: UpdateSettings::default(),
+            scan: ScanSettings::default(),
+            security_headers: SecurityHeaderSettings::default(),
+            public: PublicSettings::default(),
+            diagnostics: DiagnosticsSettings::default(),
+            events: EventSettings::default(),
+            backend: BackendSettings::default(),
+            llm_calls: CallPolicy::default(),
+            routing: Routing::default(),
+            context: ContextSettings::default(),
+            oidc: OidcSettings::default(),
+            limits: LimitSettings::default(),
+            knowledge: KnowledgeSettings::default(),
+        }
+    }
+}
+
+fn pattern_matches(pattern: &str, file_type: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some("*") => true,
+        Some(prefix) => file_type.split('/').next() == Some(prefix),
+        None => pattern == file_type,
+    }
+}
+
+fn valid_pattern(pattern: &str) -> bool {
+    match pattern.split_once('/') {
+        Some((t, sub)) => !t.is_empty() && !sub.is_empty() && !pattern.contains(char::is_whitespace) && (t != "*" || sub == "*"),
+        None => false,
+    }
+}
+
+impl Settings {
+    // Largest size allowed for this type: "type/subtype" wins over "type/*" over "*/*", capped by max_file_size
+    pub fn size_limit(&self, file_type: &str) -> u64 {
+        let major = file_type.split('/').next().unwrap_or("");
+        [file_type.to_string(), format!("{}/*", major), "*/*".to_string()]
+            .iter()
+            .find_map(|p| self.file_type_limits.get(p))
+            .copied()
+            .unwrap_or(self.max_file_size)
+            .min(self.max_file_size)
+    }
+
+    pub fn check_file(&self, file_type: &str, size: u64) -> Result<(), String> {
+        let file_type = file_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        if !self.allowed_file_types.iter().any(|p| pattern_matches(p, &file_type)) {
+            return Err(format!("File type {} not allowed", file_type));
+        }
+        let limit = self.size_limit(&file_type);
+        if size > limit {
+            return Err(format!("File too large. Maximum size for {} is {} bytes", file_type, limit));
+        }
+        Ok(())
+    }
+
+    fn normalize(mut self) -> Result<Self, String> {
+        self.allowed_file_types = self.allowed_file_types.iter().map(|t| t.trim().to_lowercase()).collect();
+        self.file_type_limits = self.file_type_limits.into_iter().map(|(t, l)| (t.trim().to_lowercase(), l)).collect();
+        if let Some(bad) = self.allowed_file_types.iter().chain(self.file_type_limits.keys()).find(|p| !valid_pattern(p)) {
+            return Err(format!("Invalid file type pattern {:?}", bad));
+        }
+        if self.max_file_size == 0 || self.file_type_limits.values().any(|l| *l == 0) {
+            return Err("Size limits must be greater than zero".to_string());
+        }
+        self.generation.validate()?;
+        self.language.response_language = self.language.response_language.map(|c| c.trim().to_lowercase()).filter(|c| !c.is_empty());
+        self.language.system_prompts = self.language.system_prompts.into_iter().map(|(c, p)| (c.trim().to_lowercase(), p)).collect();
+        if let Some(bad) = self.language.response_language.iter().chain(self.language.system_prompts.keys()).find(|c| !crate::llm::language::is_known(c)) {
+            return Err(format!("Unknown language code {:?} (expected ISO 639-3, e.g. \"deu\")", bad));
+        }
+        self.moderation.validate()?;
+        self.update.public_key = self.update.public_key.map(|k| k.trim().to_lowercase()).filter(|k| !k.is_empty());
+        self.update.release_url = self.update.release_url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+        self.update.validate()?;
+        self.scan.clamd = self.scan.clamd.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+        self.scan.validate()?;
+        self.security_headers.normalize();
+        self.security_headers.validate()?;
+        self.public.normalize();
+        self.public.validate()?;
+        self.diagnostics.normalize();
+        self.diagnostics.validate()?;
+        self.events.validate()?;
+        self.backend.normalize();
+        self.backend.validate()?;
+        self.llm_calls.validate()?;
+        self.routing.normalize();
+        self.routing.validate()?;
+        self.context.normalize();
+        self.context.validate()?;
+        self.oidc.normalize();
+        self.oidc.validate()?;
+        self.limits.validate()?;
+        self.knowledge.validate()?;
+        Ok(self)
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Arc<Mutex<Option<Settings>>> = Arc::new(Mutex::new(None));
+}
+
+async fn load() -> Settings {
+    match tokio::fs::read_to_string(SETTINGS_FILE).await {
+        Ok(s) => serde_json::from_str::<Settings>(&s)
+            .map_err(|e| e.to_string())
+            .and_then(Settings::normalize)
+            .unwrap_or_else(|e| {
+                eprintln!("SETTINGS: Failed to parse {}, using defaults: {}", SETTINGS_FILE, e);
+                Settings::default()
+            }),
+        Err(_) => Settings::default(),
+    }
+}
+
+// Current settings, loaded from settings.json on first use
+pub async fn current() -> Settings {
+    let mut guard = SETTINGS.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    guard.clone().unwrap_or_default()
+}
+
+// Check a file against the policy, logging why a peer transfer was refused
+pub async fn allow_peer_file(peer_ip: &str, filename: &str, file_type: &str, size: u64) -> bool {
+    match current().await.check_file(file_type, size) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("SETTINGS: Refused {} from {}: {}", filename, peer_ip, e);
+            false
+        }
+    }
+}
+
+#[get("/settings")]
+pub async fn get_settings() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(current().await))
+}
+
+#[put("/settings")]
+pub async fn put_settings(body: web::Json<Settings>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Only the admin can change settings"
+        })));
+    }
+    let settings = match body.into_inner().normalize() {
+        Ok(s) => s,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": e
+            })));
+        }
+    };
+    let json = serde_json::to_string_pretty(&settings)?;
+    if let Err(e) = tokio::fs::write(SETTINGS_FILE, json).await {
+        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to save settings: {}", e)
+        })));
+    }
+    *SETTINGS.lock().await = Some(settings.clone());
+    crate::events::configure(&settings.events);
+    crate::users::oidc::configure(&settings.oidc);
+    crate::limits::configure(&settings.limits);
+    println!("SETTINGS: Updated settings ({} allowed file types)", settings.allowed_file_types.len());
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "settings": settings })))
+}
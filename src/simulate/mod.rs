@@ -0,0 +1,163 @@
+// `simulate N` subcommand: runs N nodes of this binary on one machine, so syncing, file transfers
+// and LLM routing can be tried (and scripted against) without more hardware. Node i works in its
+// own directory, simulation/node-<i>/, so conversations, files and settings stay separate, and binds
+// every port to its own loopback address 127.0.0.<10+i> (BIND_IP). Broadcasts do not cross loopback,
+// so each node is given the others as DISCOVERY_PEERS. Output of all nodes is prefixed with the
+// node name; Ctrl-C stops them all, and a node whose simulator has gone away stops by itself.
+//
+// Linux and Windows route all of 127.0.0.0/8 to loopback; on macOS add the addresses first, e.g.
+// `sudo ifconfig lo0 alias 127.0.0.11` for each node.
+use std::io::{BufRead, BufReader, Read};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+const USAGE: &str = "usage: instance simulate N [--fresh]
+
+  N        number of nodes to run (1-200)
+  --fresh  delete the nodes' data from earlier runs first";
+
+const SIMULATION_DIR: &str = "simulation";
+// Set for the nodes we start; they exit when their stdin, held open by the simulator, closes
+const NODE_ENV: &str = "SIMULATION_NODE";
+const MAX_NODES: u8 = 200;
+// Login for every simulated node unless NODE_USERNAME / NODE_PASSWORD are set
+const DEFAULT_USERNAME: &str = "admin";
+const DEFAULT_PASSWORD: &str = "simulate";
+
+fn node_ip(index: u8) -> Ipv4Addr {
+    Ipv4Addr::new(127, 0, 0, 10 + index)
+}
+
+fn node_dir(index: u8) -> PathBuf {
+    Path::new(SIMULATION_DIR).join(format!("node-{}", index))
+}
+
+// Copy a child's output to ours line by line, prefixed with its name
+fn forward<R: Read + Send + 'static>(name: String, output: R, to_stderr: bool) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            if to_stderr {
+                eprintln!("[{}] {}", name, line);
+            } else {
+                println!("[{}] {}", name, line);
+            }
+        }
+    });
+}
+
+fn spawn_node(exe: &Path, index: u8, count: u8, secret: &str) -> Result<Child, String> {
+    let dir = node_dir(index);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let peers: Vec<String> = (1..=count).filter(|i| *i != index).map(|i| node_ip(i).to_string()).collect();
+    let mut command = Command::new(exe);
+    command
+        .current_dir(&dir)
+        .env("BIND_IP", node_ip(index).to_string())
+        .env("DISCOVERY_PEERS", peers.join(","))
+        .env("NO_BROWSER", "1")
+        .env("P2P_HMAC_SECRET", secret)
+        .env(NODE_ENV, index.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for (var, default) in [("NODE_USERNAME", DEFAULT_USERNAME), ("NODE_PASSWORD", DEFAULT_PASSWORD)] {
+        if std::env::var(var).is_err() {
+            command.env(var, default);
+        }
+    }
+    let mut child = command.spawn().map_err(|e| format!("starting node-{}: {}", index, e))?;
+    let name = format!("node-{}", index);
+    if let Some(out) = child.stdout.take() {
+        forward(name.clone(), out, false);
+    }
+    if let Some(err) = child.stderr.take() {
+        forward(name, err, true);
+    }
+    Ok(child)
+}
+
+fn simulate(count: u8, fresh: bool) -> Result<(), String> {
+    if fresh && Path::new(SIMULATION_DIR).exists() {
+        std::fs::remove_dir_all(SIMULATION_DIR).map_err(|e| format!("{}: {}", SIMULATION_DIR, e))?;
+    }
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    // All nodes must share the secret to accept each other's handshakes
+    let secret = std::env::var("P2P_HMAC_SECRET").unwrap_or_else(|_| hex::encode(rand::random::<[u8; 32]>()));
+    let scheme = crate::tls::scheme();
+
+    let mut children = Vec::new();
+    for index in 1..=count {
+        match spawn_node(&exe, index, count, &secret) {
+            Ok(child) => {
+                println!("SIMULATE: node-{} in {} at {}://{}:8080/app/", index, node_dir(index).display(), scheme, node_ip(index));
+                children.push((index, child));
+            }
+            Err(e) => {
+                for (_, child) in children.iter_mut() {
+                    let _ = child.kill();
+                }
+                return Err(e);
+            }
+        }
+    }
+    let username = std::env::var("NODE_USERNAME").unwrap_or_else(|_| DEFAULT_USERNAME.to_string());
+    let password = std::env::var("NODE_PASSWORD").unwrap_or_else(|_| DEFAULT_PASSWORD.to_string());
+    println!("SIMULATE: {} node(s) running, log in as {} / {}; Ctrl-C stops them", count, username, password);
+
+    // Child::wait closes the child's stdin, which would stop the nodes, so hold on to it until the end
+    let _stdins: Vec<_> = children.iter_mut().filter_map(|(_, child)| child.stdin.take()).collect();
+    for (index, mut child) in children {
+        match child.wait() {
+            Ok(status) => println!("SIMULATE: node-{} exited ({})", index, status),
+            Err(e) => eprintln!("SIMULATE: node-{}: {}", index, e),
+        }
+    }
+    Ok(())
+}
+
+// In a simulated node, exit once the simulator that started us is gone
+pub fn exit_with_simulator() {
+    if std::env::var_os(NODE_ENV).is_none() {
+        return;
+    }
+    std::thread::spawn(|| {
+        let _ = std::io::copy(&mut std::io::stdin(), &mut std::io::sink());
+        std::process::exit(0);
+    });
+}
+
+// Runs `simulate` and returns the exit code, or None for any other command
+pub fn handle_command(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    if command != "simulate" && command != "--simulate" {
+        return None;
+    }
+    if rest.first().is_some_and(|a| matches!(a.as_str(), "help" | "--help" | "-h")) {
+        println!("{}", USAGE);
+        return Some(0);
+    }
+    let count = rest.first().and_then(|n| n.parse::<u8>().ok()).filter(|n| (1..=MAX_NODES).contains(n));
+    let fresh = match rest.get(1..).unwrap_or_default() {
+        [] => false,
+        [flag] if flag == "--fresh" => true,
+        _ => {
+            eprintln!("SIMULATE: unexpected arguments: {}\n\n{}", rest.join(" "), USAGE);
+            return Some(1);
+        }
+    };
+    let count = match count {
+        Some(n) => n,
+        None => {
+            eprintln!("SIMULATE: expected a node count between 1 and {}\n\n{}", MAX_NODES, USAGE);
+            return Some(1);
+        }
+    };
+    match simulate(count, fresh) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("SIMULATE: {}", e);
+            Some(1)
+        }
+    }
+}
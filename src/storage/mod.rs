@@ -0,0 +1,247 @@
+// Disk usage over time. Once a day the sizes of files/, received/ (per peer) and conversations/
+// are recorded in storage_history.json; GET /api/analytics/storage reports them with growth per
+// day, the date the disk would fill up at that rate, and how much each peer has sent us.
+use actix_web::{get, web, Error, HttpResponse};
+use chrono::{DateTime, NaiveDate, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use crate::persistence::{CONVERSATIONS_DIR, FILES_DIR, RECEIVED_DIR};
+
+const HISTORY_FILE: &str = "storage_history.json";
+// Two years of daily snapshots
+const MAX_SNAPSHOTS: usize = 730;
+const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+const DEFAULT_TREND_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub date: NaiveDate,
+    pub taken_at: DateTime<Utc>,
+    pub files_bytes: u64,
+    pub received_bytes: u64,
+    pub conversations_bytes: u64,
+    // Bytes under received/<peer ip>/
+    pub peers: BTreeMap<String, u64>,
+}
+
+impl Snapshot {
+    pub fn total_bytes(&self) -> u64 {
+        self.files_bytes + self.received_bytes + self.conversations_bytes
+    }
+}
+
+lazy_static! {
+    static ref HISTORY: Arc<Mutex<Option<Vec<Snapshot>>>> = Arc::new(Mutex::new(None));
+}
+
+async fn load() -> Vec<Snapshot> {
+    match tokio::fs::read_to_string(HISTORY_FILE).await {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("STORAGE: Failed to parse {}: {}", HISTORY_FILE, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn history() -> Vec<Snapshot> {
+    let mut guard = HISTORY.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    guard.clone().unwrap_or_default()
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(t) if t.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+// Measure the data directories now
+pub async fn measure() -> Snapshot {
+    tokio::task::spawn_blocking(|| {
+        let mut peers = BTreeMap::new();
+        if let Ok(entries) = std::fs::read_dir(RECEIVED_DIR) {
+            for entry in entries.flatten().filter(|e| e.file_type().is_ok_and(|t| t.is_dir())) {
+                peers.insert(entry.file_name().to_string_lossy().to_string(), dir_size(&entry.path()));
+            }
+        }
+        let now = Utc::now();
+        Snapshot {
+            date: now.date_naive(),
+            taken_at: now,
+            files_bytes: dir_size(Path::new(FILES_DIR)),
+            received_bytes: dir_size(Path::new(RECEIVED_DIR)),
+            conversations_bytes: dir_size(Path::new(CONVERSATIONS_DIR)),
+            peers,
+        }
+    })
+    .await
+    .unwrap_or_else(|e| {
+        eprintln!("STORAGE: Measuring disk usage failed: {}", e);
+        let now = Utc::now();
+        Snapshot { date: now.date_naive(), taken_at: now, files_bytes: 0, received_bytes: 0, conversations_bytes: 0, peers: BTreeMap::new() }
+    })
+}
+
+// Take today's snapshot unless there is one already
+async fn record_daily() {
+    let today = Utc::now().date_naive();
+    if history().await.last().is_some_and(|s| s.date >= today) {
+        return;
+    }
+    let snapshot = measure().await;
+    let mut guard = HISTORY.lock().await;
+    let history = guard.get_or_insert_with(Vec::new);
+    history.push(snapshot);
+    if history.len() > MAX_SNAPSHOTS {
+        let excess = history.len() - MAX_SNAPSHOTS;
+        history.drain(..excess);
+    }
+    match serde_json::to_string_pretty(history) {
+        Ok(json) => {
+            if let Err(e) = crate::persistence::write_atomic(Path::new(HISTORY_FILE), json.as_bytes()).await {
+                eprintln!("STORAGE: Failed to save {}: {}", HISTORY_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("STORAGE: Failed to serialize history: {}", e),
+    }
+}
+
+pub async fn recorder() {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        record_daily().await;
+    }
+}
+
+// Average change per day of `bytes` between the oldest and newest of `points` (date, bytes)
+pub fn growth_per_day(points: &[(NaiveDate, u64)]) -> Option<f64> {
+    let (first, last) = (points.first()?, points.last()?);
+    let days = (last.0 - first.0).num_days();
+    if days <= 0 {
+        return None;
+    }
+    Some((last.1 as f64 - first.1 as f64) / days as f64)
+}
+
+// When `available` bytes are used up at `per_day`; None while usage is not growing
+pub fn projected_fill(available: u64, per_day: f64, today: NaiveDate) -> Option<NaiveDate> {
+    if per_day <= 0.0 {
+        return None;
+    }
+    let days = (available as f64 / per_day).ceil();
+    if days > 365.0 * 100.0 {
+        return None;
+    }
+    today.checked_add_days(chrono::Days::new(days as u64))
+}
+
+// Total and free space of the disk holding the working directory
+fn disk_space() -> Option<(u64, u64)> {
+    let cwd = std::env::current_dir().ok()?.canonicalize().ok()?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|d| cwd.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(|d| (d.total_space(), d.available_space()))
+}
+
+#[derive(Deserialize)]
+pub struct StorageQuery {
+    // How many days of history the growth rates are worked out over
+    days: Option<i64>,
+}
+
+#[get("/analytics/storage")]
+pub async fn analytics_storage(query: web::Query<StorageQuery>) -> Result<HttpResponse, Error> {
+    let days = query.days.unwrap_or(DEFAULT_TREND_DAYS).clamp(1, MAX_SNAPSHOTS as i64);
+    let current = measure().await;
+    let history = history().await;
+    let since = current.date - chrono::Duration::days(days);
+    // The window's snapshots followed by the live measurement
+    let window: Vec<&Snapshot> = history.iter().filter(|s| s.date >= since && s.date < current.date).chain(std::iter::once(&current)).collect();
+    let growth = |bytes: &dyn Fn(&Snapshot) -> u64| growth_per_day(&window.iter().map(|s| (s.date, bytes(s))).collect::<Vec<_>>());
+    let total_growth = growth(&|s| s.total_bytes());
+    let disk = disk_space();
+    let fill_date = disk.zip(total_growth).and_then(|((_, available), per_day)| projected_fill(available, per_day, current.date));
+
+    let oldest = window.first().copied();
+    let peers: Vec<serde_json::Value> = current
+        .peers
+        .iter()
+        .map(|(ip, bytes)| {
+            let share = if current.received_bytes == 0 { 0.0 } else { *bytes as f64 / current.received_bytes as f64 };
+            serde_json::json!({
+                "peer": ip,
+                "bytes": bytes,
+                "share": share,
+                "growth_bytes": oldest.map(|o| *bytes as i64 - o.peers.get(ip).copied().unwrap_or(0) as i64),
+            })
+        })
+        .collect();
+    let history: Vec<serde_json::Value> = history
+        .iter()
+        .map(|s| serde_json::json!({
+            "date": s.date,
+            "files_bytes": s.files_bytes,
+            "received_bytes": s.received_bytes,
+            "conversations_bytes": s.conversations_bytes,
+            "total_bytes": s.total_bytes(),
+        }))
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "current": {
+            "files_bytes": current.files_bytes,
+            "received_bytes": current.received_bytes,
+            "conversations_bytes": current.conversations_bytes,
+            "total_bytes": current.total_bytes(),
+        },
+        "disk": disk.map(|(total, available)| serde_json::json!({ "total_bytes": total, "available_bytes": available })),
+        "trend": {
+            "days": days,
+            "files_bytes_per_day": growth(&|s| s.files_bytes),
+            "received_bytes_per_day": growth(&|s| s.received_bytes),
+            "conversations_bytes_per_day": growth(&|s| s.conversations_bytes),
+            "total_bytes_per_day": total_growth,
+        },
+        "projected_full_date": fill_date,
+        "peers": peers,
+        "history": history,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{growth_per_day, projected_fill};
+
+    // Storage growth is averaged over the snapshot window and projected onto the free space
+    #[test]
+    fn storage_growth_projects_a_fill_date() {
+        let day = |d: u32| chrono::NaiveDate::from_ymd_opt(2026, 3, d).unwrap();
+        assert_eq!(growth_per_day(&[(day(1), 1_000), (day(5), 1_500), (day(11), 3_000)]), Some(200.0));
+        assert_eq!(growth_per_day(&[(day(1), 1_000)]), None);
+        assert_eq!(growth_per_day(&[]), None);
+        assert_eq!(projected_fill(1_000, 200.0, day(11)), Some(day(16)));
+        assert_eq!(projected_fill(1_001, 200.0, day(11)), Some(day(17)));
+        assert_eq!(projected_fill(1_000, -5.0, day(11)), None);
+        assert_eq!(projected_fill(1_000, 0.0, day(11)), None);
+    }
+}
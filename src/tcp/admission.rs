@@ -0,0 +1,168 @@
+// Which peers get a connection on a large LAN. At most MAX_PEERS (default 64, 0 for no limit)
+// sessions are kept. Once the limit is reached a new peer, dialled or dialling us, only gets in by
+// outranking the lowest-ranked connected peer, whose session is then closed: LLM hosts rank above
+// other peers, and among those the one active most recently ranks higher. Outbound dials that do
+// not get in wait DEFER_TIME without it counting as a failure. Independently of the limit, inbound
+// connections are accepted at ACCEPT_RATE per second (default 5, bursts of twice that); beyond it
+// they are closed straight away, before the handshake costs anything.
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_PEERS: usize = 64;
+pub const DEFAULT_ACCEPT_RATE: f64 = 5.0;
+pub const DEFER_TIME: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Limits {
+    // 0 means no limit
+    pub max_peers: usize,
+    pub accept_rate: f64,
+    pub accept_burst: f64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_peers: DEFAULT_MAX_PEERS, accept_rate: DEFAULT_ACCEPT_RATE, accept_burst: DEFAULT_ACCEPT_RATE * 2.0 }
+    }
+}
+
+impl Limits {
+    pub fn from_env() -> Limits {
+        let mut limits = Limits::default();
+        if let Ok(v) = std::env::var("MAX_PEERS") {
+            match v.trim().parse() {
+                Ok(n) => limits.max_peers = n,
+                Err(_) => eprintln!("MAX_PEERS {:?} is not a number, keeping {}", v, limits.max_peers),
+            }
+        }
+        if let Ok(v) = std::env::var("ACCEPT_RATE") {
+            match v.trim().parse::<f64>() {
+                Ok(rate) if rate > 0.0 => {
+                    limits.accept_rate = rate;
+                    limits.accept_burst = (rate * 2.0).max(1.0);
+                }
+                _ => eprintln!("ACCEPT_RATE {:?} is not a positive number, keeping {}", v, limits.accept_rate),
+            }
+        }
+        limits
+    }
+}
+
+// A peer competing for a session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub ip: String,
+    pub llm_host: bool,
+    // Last frame from the peer, or when its last session ended
+    pub last_active: Option<Instant>,
+}
+
+impl Candidate {
+    // Higher is kept; never-seen peers rank lowest among equals
+    pub fn rank(&self) -> (bool, Option<Instant>) {
+        (self.llm_host, self.last_active)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Admit,
+    // Admit after closing this peer's session
+    Evict(String),
+    Refuse,
+}
+
+// Whether `candidate` may have a session alongside the `connected` peers
+pub fn admit(max_peers: usize, connected: &[Candidate], candidate: &Candidate) -> Verdict {
+    if max_peers == 0 || connected.len() < max_peers || connected.iter().any(|c| c.ip == candidate.ip) {
+        return Verdict::Admit;
+    }
+    match connected.iter().min_by(|a, b| a.rank().cmp(&b.rank()).then_with(|| b.ip.cmp(&a.ip))) {
+        Some(lowest) if candidate.rank() > lowest.rank() => Verdict::Evict(lowest.ip.clone()),
+        _ => Verdict::Refuse,
+    }
+}
+
+// Token bucket for accepted connections
+#[derive(Debug)]
+pub struct AcceptBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl AcceptBucket {
+    pub fn new(rate: f64, burst: f64) -> AcceptBucket {
+        AcceptBucket { rate, burst, tokens: burst, last: None }
+    }
+
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last {
+            self.tokens = (self.tokens + now.saturating_duration_since(last).as_secs_f64() * self.rate).min(self.burst);
+        }
+        self.last = Some(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AdmissionStats {
+    // Inbound connections closed for arriving faster than ACCEPT_RATE
+    pub refused_rate: u64,
+    // Inbound peers refused because the limit was reached
+    pub refused_full: u64,
+    // Outbound dials put off for the same reason
+    pub deferred: u64,
+    // Sessions closed to make room for a higher-ranked peer
+    pub evicted: u64,
+}
+
+pub struct Admission {
+    pub limits: Limits,
+    bucket: AcceptBucket,
+    // When each peer's last session ended, to rank peers that are not connected
+    last_active: HashMap<String, Instant>,
+    pub stats: AdmissionStats,
+}
+
+impl Admission {
+    pub fn new(limits: Limits) -> Admission {
+        Admission { limits, bucket: AcceptBucket::new(limits.accept_rate, limits.accept_burst), last_active: HashMap::new(), stats: AdmissionStats::default() }
+    }
+
+    // An inbound connection arrived: whether to take it at all
+    pub fn accept(&mut self, now: Instant) -> bool {
+        let ok = self.bucket.try_take(now);
+        if !ok {
+            self.stats.refused_rate += 1;
+        }
+        ok
+    }
+
+    pub fn session_ended(&mut self, ip: &str, now: Instant) {
+        self.last_active.insert(ip.to_string(), now);
+    }
+
+    pub fn last_active(&self, ip: &str) -> Option<Instant> {
+        self.last_active.get(ip).copied()
+    }
+
+    // admit() with the outcome counted
+    pub fn check(&mut self, connected: &[Candidate], candidate: &Candidate, outbound: bool) -> Verdict {
+        let verdict = admit(self.limits.max_peers, connected, candidate);
+        match &verdict {
+            Verdict::Admit => {}
+            Verdict::Evict(_) => self.stats.evicted += 1,
+            Verdict::Refuse if outbound => self.stats.deferred += 1,
+            Verdict::Refuse => self.stats.refused_full += 1,
+        }
+        verdict
+    }
+}
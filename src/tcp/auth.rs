@@ -0,0 +1,71 @@
+// Connection handshake. The connecting side's first frame must be AUTH: carrying a timestamp,
+// a random nonce, its crate and protocol versions and an HMAC over all of them (bound to the
+// address it dialled) under the shared P2P secret. Connections that do not authenticate within
+// HANDSHAKE_TIMEOUT are dropped.
+use hmac::Mac;
+use std::collections::HashMap;
+use std::time::Duration;
+use super::HmacSha256;
+use super::version::NodeVersion;
+
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Timestamps further than this from our clock are rejected
+pub const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+pub fn new_nonce() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+// `version` is None for protocol 1 peers, whose handshake carried no versions
+pub fn sign_handshake(secret: &str, timestamp: i64, nonce: &str, target_ip: &str, version: Option<&NodeVersion>) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    let mut payload = format!("AUTH|{}|{}|{}", timestamp, nonce, target_ip);
+    if let Some(v) = version {
+        payload.push_str(&format!("|{}|{}", v.crate_version, v.protocol));
+    }
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn verify_handshake(secret: &str, timestamp: i64, nonce: &str, target_ip: &str, version: Option<&NodeVersion>, hmac_hex: &str) -> bool {
+    sign_handshake(secret, timestamp, nonce, target_ip, version).eq_ignore_ascii_case(hmac_hex)
+}
+
+// Nonces seen within the accepted clock window; anything older is rejected by timestamp anyway
+#[derive(Default)]
+pub struct ReplayWindow {
+    seen: HashMap<String, i64>,
+}
+
+impl ReplayWindow {
+    pub fn check(&mut self, timestamp: i64, nonce: &str, now: i64) -> Result<(), &'static str> {
+        if (now - timestamp).abs() > MAX_CLOCK_SKEW_SECS {
+            return Err("timestamp outside the accepted window");
+        }
+        self.seen.retain(|_, ts| (now - *ts).abs() <= MAX_CLOCK_SKEW_SECS);
+        if self.seen.contains_key(nonce) {
+            return Err("nonce already used");
+        }
+        self.seen.insert(nonce.to_string(), timestamp);
+        Ok(())
+    }
+}
+
+// HTTP requests peers make to our /api without a session carry PEER_REQUEST_HEADER,
+// "<timestamp>.<nonce>.<hmac>" with the HMAC over the method and path under the shared P2P secret
+pub const PEER_REQUEST_HEADER: &str = "x-peer-auth";
+
+pub fn sign_peer_request(secret: &str, timestamp: i64, nonce: &str, method: &str, path: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(format!("HTTP|{}|{}|{}|{}", timestamp, nonce, method.to_uppercase(), path).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Timestamp and nonce of a PEER_REQUEST_HEADER value whose HMAC matches, for the replay check
+pub fn verify_peer_request<'a>(secret: &str, method: &str, path: &str, header: &'a str) -> Option<(i64, &'a str)> {
+    let mut parts = header.splitn(3, '.');
+    let timestamp = parts.next()?.parse().ok()?;
+    let nonce = parts.next()?;
+    let hmac_hex = parts.next()?;
+    sign_peer_request(secret, timestamp, nonce, method, path).eq_ignore_ascii_case(hmac_hex).then_some((timestamp, nonce))
+}
@@ -0,0 +1,73 @@
+// Optional protocol features. Right after the handshake each side sends a CAPS: frame with a
+// bitmap of the features it supports: the connecting side after its AUTH:, the accepting side
+// after its VERS:. A connection uses a feature only when both sides have it, and the features in
+// common are kept with the session (see registry). Peers below protocol 4 send no CAPS: and are
+// taken to have what their protocol version brought. Bits this build does not know, from newer
+// peers, are never in common.
+use std::ops::BitOr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    // ZSTD:/GZIP: envelopes, negotiated with CMPR: offers
+    pub const COMPRESSION: Capabilities = Capabilities(1);
+    // SEQN: envelopes answered with ACKN:/NACK:, see delivery
+    pub const ACKS: Capabilities = Capabilities(1 << 1);
+    // FILE_META v2 announcements whose chunks the receiver fetches itself
+    pub const DEDUP_CHUNKS: Capabilities = Capabilities(1 << 2);
+    // FLSQ:/FLSR: file lists, see crate::catalog
+    pub const FILE_LISTS: Capabilities = Capabilities(1 << 3);
+    // Scoped SYNC: answered with CONV:, see crate::history
+    pub const SCOPED_SYNC: Capabilities = Capabilities(1 << 4);
+
+    const NAMES: [(Capabilities, &'static str); 5] = [
+        (Capabilities::COMPRESSION, "compression"),
+        (Capabilities::ACKS, "acks"),
+        (Capabilities::DEDUP_CHUNKS, "dedup_chunks"),
+        (Capabilities::FILE_LISTS, "file_lists"),
+        (Capabilities::SCOPED_SYNC, "scoped_sync"),
+    ];
+
+    pub fn local() -> Capabilities {
+        Capabilities::NAMES.iter().fold(Capabilities::default(), |all, (c, _)| all | *c)
+    }
+
+    // What a peer that sends no CAPS: supports, going by its protocol version
+    pub fn implied(protocol: u32) -> Capabilities {
+        let mut implied = Capabilities::COMPRESSION | Capabilities::DEDUP_CHUNKS;
+        if protocol >= 3 {
+            implied = implied | Capabilities::ACKS;
+        }
+        implied
+    }
+
+    pub fn from_bits(bits: u64) -> Capabilities {
+        Capabilities(bits)
+    }
+
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub fn contains(self, feature: Capabilities) -> bool {
+        self.0 & feature.0 == feature.0
+    }
+
+    // The features both sides have
+    pub fn common(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & other.0 & Capabilities::local().0)
+    }
+
+    pub fn names(self) -> Vec<&'static str> {
+        Capabilities::NAMES.iter().filter(|(c, _)| self.contains(*c)).map(|(_, name)| *name).collect()
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+}
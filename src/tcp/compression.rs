@@ -0,0 +1,94 @@
+// Optional payload compression for TCP messages. Peers advertise what they can decode in a
+// CMPR: offer; a compressed message is wrapped as <ZSTD:|GZIP:><inner marker><compressed payload>.
+// Decoding is always supported; TCP_COMPRESSION only controls what we offer and send.
+use std::io::Read;
+
+// Payloads smaller than this are sent as-is
+const DEFAULT_MIN_BYTES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Compression> {
+        match name.trim().to_lowercase().as_str() {
+            "zstd" => Some(Compression::Zstd),
+            "gzip" => Some(Compression::Gzip),
+            _ => None,
+        }
+    }
+
+    pub fn marker(&self) -> [u8; 5] {
+        match self {
+            Compression::Zstd => *b"ZSTD:",
+            Compression::Gzip => *b"GZIP:",
+        }
+    }
+
+    pub fn from_marker(marker: &[u8; 5]) -> Option<Compression> {
+        match marker {
+            b"ZSTD:" => Some(Compression::Zstd),
+            b"GZIP:" => Some(Compression::Gzip),
+            _ => None,
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => zstd::bulk::compress(data, 3),
+            Compression::Gzip => {
+                use std::io::Write;
+                let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                enc.write_all(data)?;
+                enc.finish()
+            }
+        }
+    }
+
+    // Bounded by the inner message type's size limit so a small frame cannot expand without limit
+    pub fn decompress(&self, data: &[u8], limit: usize) -> std::io::Result<Vec<u8>> {
+        let limit = limit as u64;
+        let mut out = Vec::new();
+        match self {
+            Compression::Zstd => zstd::stream::read::Decoder::new(data)?.take(limit + 1).read_to_end(&mut out)?,
+            Compression::Gzip => flate2::read::GzDecoder::new(data).take(limit + 1).read_to_end(&mut out)?,
+        };
+        if out.len() as u64 > limit {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Decompressed message too large"));
+        }
+        Ok(out)
+    }
+}
+
+// Algorithms we offer, in order of preference (TCP_COMPRESSION="zstd,gzip" by default, "off" to disable)
+pub fn local_preferences() -> Vec<Compression> {
+    match std::env::var("TCP_COMPRESSION") {
+        Ok(v) if matches!(v.trim().to_lowercase().as_str(), "off" | "none" | "0" | "false") => Vec::new(),
+        Ok(v) if !v.trim().is_empty() => v.split(',').filter_map(Compression::from_name).collect(),
+        _ => vec![Compression::Zstd, Compression::Gzip],
+    }
+}
+
+pub fn min_bytes() -> usize {
+    std::env::var("TCP_COMPRESSION_MIN_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MIN_BYTES)
+}
+
+// First of our preferences the peer also supports
+pub fn negotiate(peer_offer: &[String]) -> Option<Compression> {
+    local_preferences()
+        .into_iter()
+        .find(|c| peer_offer.iter().any(|a| a == c.name()))
+}
@@ -0,0 +1,120 @@
+// Acknowledged delivery. Frames that must not go missing unnoticed, conversation pushes and file
+// transfers, travel inside a SEQN: envelope numbered per session. The peer answers ACKN: once it
+// has taken the frame (for a file's last frame: once the file is saved) or NACK: with the reason
+// it could not, and the outbox keeps each frame until then. A conversation push is sent again
+// after a NACK: or when no answer comes within ACK_TIMEOUT, up to MAX_RESENDS times, and a newer
+// push of the same conversation replaces one still waiting. File frames are never sent again one
+// by one, as the peer appends chunks in order; the transfer waits for their verdicts and is
+// retried as a whole (see send_with_retries). Peers without the acks capability get plain frames.
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+// A deduplicated file is acknowledged after the peer fetched its missing chunks
+pub const FILE_ACK_TIMEOUT: Duration = Duration::from_secs(300);
+pub const MAX_RESENDS: u32 = 2;
+
+// Marker and payload of the frame inside the envelope
+pub type Frame = ([u8; 5], Vec<u8>);
+// Resolves with the peer's answer; dropped unanswered when the session ends
+pub type Verdict = oneshot::Receiver<Result<(), String>>;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeliveryStats {
+    pub sent: u64,
+    pub acked: u64,
+    pub nacked: u64,
+    pub resent: u64,
+    pub failed: u64,
+    pub pending: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_failure: Option<String>,
+}
+
+struct Pending {
+    what: String,
+    // Kept for frames that are sent again
+    frame: Option<Frame>,
+    sent_at: Instant,
+    timeout: Duration,
+    resends: u32,
+    verdict: Option<oneshot::Sender<Result<(), String>>>,
+}
+
+// What became of a frame that was refused or not answered in time
+#[derive(Debug, PartialEq)]
+pub enum Next {
+    Resend(u64, Frame),
+    // A conversation push given up on: what it was and why
+    Failed(String, String),
+}
+
+#[derive(Default)]
+pub struct Outbox {
+    next_seq: u64,
+    pending: BTreeMap<u64, Pending>,
+    stats: DeliveryStats,
+}
+
+impl Outbox {
+    // Number a frame and keep it until the peer answers; `frame` is given for frames sent again
+    pub fn register(&mut self, what: &str, frame: Option<Frame>, timeout: Duration, now: Instant) -> (u64, Verdict) {
+        if frame.is_some() {
+            self.pending.retain(|_, p| p.frame.is_none() || p.what != what);
+        }
+        self.next_seq += 1;
+        let (sender, verdict) = oneshot::channel();
+        self.pending.insert(self.next_seq, Pending { what: what.to_string(), frame, sent_at: now, timeout, resends: 0, verdict: Some(sender) });
+        self.stats.sent += 1;
+        (self.next_seq, verdict)
+    }
+
+    pub fn acked(&mut self, seq: u64) {
+        if let Some(pending) = self.pending.remove(&seq) {
+            self.stats.acked += 1;
+            if let Some(verdict) = pending.verdict {
+                let _ = verdict.send(Ok(()));
+            }
+        }
+    }
+
+    pub fn nacked(&mut self, seq: u64, reason: &str, now: Instant) -> Option<Next> {
+        if !self.pending.contains_key(&seq) {
+            return None;
+        }
+        self.stats.nacked += 1;
+        self.retry_or_fail(seq, reason, now)
+    }
+
+    // Frames whose answer is overdue
+    pub fn overdue(&mut self, now: Instant) -> Vec<Next> {
+        let late: Vec<u64> = self.pending.iter().filter(|(_, p)| now.duration_since(p.sent_at) >= p.timeout).map(|(seq, _)| *seq).collect();
+        late.into_iter().filter_map(|seq| self.retry_or_fail(seq, "no acknowledgement", now)).collect()
+    }
+
+    pub fn stats(&self) -> DeliveryStats {
+        DeliveryStats { pending: self.pending.len(), ..self.stats.clone() }
+    }
+
+    // A file frame's failure reaches its transfer through the verdict; only pushes report back
+    fn retry_or_fail(&mut self, seq: u64, reason: &str, now: Instant) -> Option<Next> {
+        let pending = self.pending.get_mut(&seq)?;
+        if pending.resends < MAX_RESENDS {
+            if let Some(frame) = pending.frame.clone() {
+                pending.resends += 1;
+                pending.sent_at = now;
+                self.stats.resent += 1;
+                return Some(Next::Resend(seq, frame));
+            }
+        }
+        let pending = self.pending.remove(&seq)?;
+        self.stats.failed += 1;
+        self.stats.last_failure = Some(format!("{}: {}", pending.what, reason));
+        if let Some(verdict) = pending.verdict {
+            let _ = verdict.send(Err(reason.to_string()));
+        }
+        pending.frame.map(|_| Next::Failed(pending.what, reason.to_string()))
+    }
+}
@@ -0,0 +1,201 @@
+// Outbound connection state per peer. A peer found by discovery is dialled at once; a failed
+// attempt, or a connection that drops, is retried after an exponential backoff (BASE_DELAY
+// doubling up to MAX_DELAY, give or take JITTER so peers that lost each other at the same moment
+// do not redial in lockstep). A connection that drops within STABLE_AFTER counts as a failed
+// attempt. After MAX_ATTEMPTS failures in a row the peer is parked until discovery finds it again
+// PARK_TIME later. Each attempt's task holds a Lease that settles the peer's state when dropped,
+// even when the task panics or is aborted, and an attempt stuck connecting for STALE_CONNECTING is
+// given up on, so no peer stays wedged as connected or connecting. A peer whose own connection to
+// us is the session we keep (see session) is not dialled, only checked on every COVERED_RECHECK.
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const BASE_DELAY: Duration = Duration::from_secs(2);
+pub const MAX_DELAY: Duration = Duration::from_secs(300);
+pub const MAX_ATTEMPTS: u32 = 10;
+// Fraction of the delay added or taken off at random
+pub const JITTER: f64 = 0.2;
+pub const STABLE_AFTER: Duration = Duration::from_secs(10);
+pub const PARK_TIME: Duration = Duration::from_secs(600);
+pub const STALE_CONNECTING: Duration = Duration::from_secs(120);
+pub const COVERED_RECHECK: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Policy {
+    pub base: Duration,
+    pub max: Duration,
+    pub max_attempts: u32,
+    pub jitter: f64,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy { base: BASE_DELAY, max: MAX_DELAY, max_attempts: MAX_ATTEMPTS, jitter: JITTER }
+    }
+}
+
+impl Policy {
+    // Wait before the next attempt after `failures` failures in a row; `spread` in [-1, 1] picks
+    // where in the jitter range it lands
+    pub fn delay(&self, failures: u32, spread: f64) -> Duration {
+        let doubled = self.base.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)));
+        let delay = doubled.min(self.max).as_secs_f64();
+        Duration::from_secs_f64((delay * (1.0 + self.jitter * spread.clamp(-1.0, 1.0))).max(0.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Connecting { since: Instant },
+    Connected { since: Instant },
+    Backoff { until: Instant },
+    Parked { since: Instant },
+    // The peer's connection to us is the session, checked again at `until`
+    Covered { until: Instant },
+}
+
+#[derive(Debug, Clone)]
+struct PeerDial {
+    phase: Phase,
+    failures: u32,
+    // Attempt the phase belongs to; a lease from an older attempt changes nothing
+    attempt: u64,
+    last_error: Option<String>,
+}
+
+// A peer's state for /api/peers/info
+#[derive(Debug, Clone, Serialize)]
+pub struct DialState {
+    pub state: &'static str,
+    pub failures: u32,
+    // Seconds until the next attempt while backing off
+    pub retry_in_secs: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Dialer {
+    policy: Policy,
+    peers: HashMap<String, PeerDial>,
+    attempts: u64,
+}
+
+impl Dialer {
+    pub fn new(policy: Policy) -> Dialer {
+        Dialer { policy, peers: HashMap::new(), attempts: 0 }
+    }
+
+    // Discovery saw the peer: dial it now unless it is already being handled
+    pub fn discovered(&mut self, ip: &str, now: Instant) {
+        match self.peers.get_mut(ip) {
+            None => {
+                self.peers.insert(ip.to_string(), PeerDial { phase: Phase::Backoff { until: now }, failures: 0, attempt: 0, last_error: None });
+            }
+            Some(peer) => {
+                if let Phase::Parked { since } = peer.phase {
+                    if now.duration_since(since) >= PARK_TIME {
+                        peer.phase = Phase::Backoff { until: now };
+                        peer.failures = 0;
+                    }
+                }
+            }
+        }
+    }
+
+    // Peers to dial now, each with the attempt number its lease must carry. Attempts connecting
+    // for longer than STALE_CONNECTING are counted as failed and tried again.
+    pub fn due(&mut self, now: Instant) -> Vec<(String, u64)> {
+        let mut due = Vec::new();
+        for (ip, peer) in self.peers.iter_mut() {
+            let ready = match peer.phase {
+                Phase::Backoff { until } | Phase::Covered { until } => until <= now,
+                Phase::Connecting { since } if now.duration_since(since) >= STALE_CONNECTING => {
+                    peer.failures += 1;
+                    peer.last_error = Some("connection attempt never finished".to_string());
+                    true
+                }
+                _ => false,
+            };
+            if ready {
+                self.attempts += 1;
+                peer.attempt = self.attempts;
+                peer.phase = Phase::Connecting { since: now };
+                due.push((ip.clone(), self.attempts));
+            }
+        }
+        due.sort();
+        due
+    }
+
+    fn current(&mut self, ip: &str, attempt: u64) -> Option<&mut PeerDial> {
+        self.peers.get_mut(ip).filter(|p| p.attempt == attempt)
+    }
+
+    pub fn connected(&mut self, ip: &str, attempt: u64, now: Instant) {
+        if let Some(peer) = self.current(ip, attempt) {
+            peer.phase = Phase::Connected { since: now };
+        }
+    }
+
+    // No connection was needed: the peer is connected to us
+    pub fn covered(&mut self, ip: &str, attempt: u64, now: Instant) {
+        if let Some(peer) = self.current(ip, attempt) {
+            peer.phase = Phase::Covered { until: now + COVERED_RECHECK };
+            peer.failures = 0;
+            peer.last_error = None;
+        }
+    }
+
+    // Not dialled after all: the peer limit is reached (see admission). Tried again at `until`
+    // without it counting as a failure.
+    pub fn defer(&mut self, ip: &str, attempt: u64, until: Instant, reason: &str) {
+        if let Some(peer) = self.current(ip, attempt) {
+            peer.phase = Phase::Backoff { until };
+            peer.last_error = Some(reason.to_string());
+        }
+    }
+
+    // The attempt ended: connected long enough, or not at all (`error`)
+    pub fn ended(&mut self, ip: &str, attempt: u64, error: Option<String>, now: Instant, spread: f64) {
+        let policy = self.policy;
+        let Some(peer) = self.current(ip, attempt).filter(|p| !matches!(p.phase, Phase::Covered { .. })) else {
+            return;
+        };
+        let stable = matches!(peer.phase, Phase::Connected { since } if now.duration_since(since) >= STABLE_AFTER);
+        if stable {
+            peer.failures = 0;
+        } else {
+            peer.failures += 1;
+        }
+        if error.is_some() {
+            peer.last_error = error;
+        }
+        peer.phase = if peer.failures >= policy.max_attempts {
+            Phase::Parked { since: now }
+        } else {
+            Phase::Backoff { until: now + policy.delay(peer.failures.max(1), spread) }
+        };
+    }
+
+    pub fn state(&self, ip: &str, now: Instant) -> Option<DialState> {
+        let peer = self.peers.get(ip)?;
+        let (state, retry_in_secs) = match peer.phase {
+            Phase::Connecting { .. } => ("connecting", None),
+            Phase::Connected { .. } => ("connected", None),
+            Phase::Backoff { until } => ("backoff", Some(until.saturating_duration_since(now).as_secs())),
+            Phase::Parked { .. } => ("parked", None),
+            Phase::Covered { .. } => ("inbound", None),
+        };
+        Some(DialState { state, failures: peer.failures, retry_in_secs, last_error: peer.last_error.clone() })
+    }
+
+    // Peers with an attempt under way
+    pub fn connecting(&self) -> Vec<String> {
+        self.peers.iter().filter(|(_, p)| matches!(p.phase, Phase::Connecting { .. })).map(|(ip, _)| ip.clone()).collect()
+    }
+
+    pub fn ips(&self) -> Vec<String> {
+        self.peers.keys().cloned().collect()
+    }
+}
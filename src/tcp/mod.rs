@@ -3,57 +3,380 @@ pub async fn set_p2p_secret(secret: String) {
     *s = Some(secret);
 }
 
+// PEER_REQUEST_HEADER value for one HTTP request to a peer's /api, signed over the method and the
+// URL's path
+pub async fn sign_peer_request(method: &str, url: &str) -> String {
+    let path = reqwest::Url::parse(url).map(|u| u.path().to_string()).unwrap_or_default();
+    let secret = P2P_SECRET.lock().await.clone().unwrap_or_default();
+    let timestamp = chrono::Utc::now().timestamp();
+    let nonce = auth::new_nonce();
+    format!("{}.{}.{}", timestamp, nonce, auth::sign_peer_request(&secret, timestamp, &nonce, method, &path))
+}
+
+// Whether a request to our /api came from a peer holding the P2P secret: its PEER_REQUEST_HEADER
+// matches the method and path, is recent and was not seen before
+pub fn peer_request_authentic(secret: &str, method: &str, path: &str, header: Option<&str>) -> bool {
+    let Some((timestamp, nonce)) = header.and_then(|h| auth::verify_peer_request(secret, method, path, h)) else {
+        return false;
+    };
+    let mut nonces = PEER_REQUEST_NONCES.lock().unwrap();
+    nonces.check(timestamp, nonce, chrono::Utc::now().timestamp()).is_ok()
+}
+
 pub async fn add_announced_file(info: FileInfo) {
     let mut v = ANNOUNCED_FILES.lock().await;
-    // de-duplicate by filename + uploader_ip
-    if !v.iter().any(|f| f.filename == info.filename && f.uploader_ip == info.uploader_ip) {
-        v.push(info);
+    // de-duplicate by filename + uploader_ip; a re-announcement refreshes the entry
+    match v.iter_mut().find(|f| f.info.filename == info.filename && f.info.uploader_ip == info.uploader_ip) {
+        Some(existing) => {
+            existing.info = info;
+            existing.refreshed_at = Instant::now();
+        }
+        None => v.push(AnnouncedFile { info, refreshed_at: Instant::now() }),
     }
 }
 
 pub async fn get_announced_files() -> Vec<FileInfo> {
-    ANNOUNCED_FILES.lock().await.clone()
+    ANNOUNCED_FILES.lock().await.iter().map(|f| f.info.clone()).collect()
+}
+
+// Drop everything a peer announced, e.g. once it disconnects
+async fn forget_peer_files(peer_ip: &str) {
+    let mut v = ANNOUNCED_FILES.lock().await;
+    let before = v.len();
+    v.retain(|f| f.info.uploader_ip != peer_ip);
+    let removed = before - v.len();
+    if removed > 0 {
+        EVICTED_DISCONNECTED.fetch_add(removed as u64, Ordering::Relaxed);
+        println!("TCP: Forgot {} announced file(s) from {}", removed, peer_ip);
+    }
+}
+
+async fn forget_announced_file(peer_ip: &str, filename: &str) {
+    let mut v = ANNOUNCED_FILES.lock().await;
+    let before = v.len();
+    v.retain(|f| !(f.info.uploader_ip == peer_ip && f.info.filename == filename));
+    let removed = before - v.len();
+    if removed > 0 {
+        EVICTED_DELETED.fetch_add(removed as u64, Ordering::Relaxed);
+        println!("TCP: {} deleted {}", peer_ip, filename);
+    }
+}
+
+// Entries of connected peers stay fresh; anything not refreshed within ANNOUNCED_FILE_TTL_SECS
+// (default one day) belongs to a peer that is gone and is dropped
+pub async fn announced_files_gc() {
+    let ttl = Duration::from_secs(
+        std::env::var("ANNOUNCED_FILE_TTL_SECS").ok().and_then(|v| v.trim().parse().ok()).filter(|n| *n > 0).unwrap_or(24 * 3600),
+    );
+    let mut interval = tokio::time::interval(ANNOUNCED_FILES_GC_INTERVAL.min(ttl));
+    loop {
+        interval.tick().await;
+        let connected: HashSet<String> = connected_peer_ips().await.into_iter().collect();
+        let mut v = ANNOUNCED_FILES.lock().await;
+        let now = Instant::now();
+        for f in v.iter_mut().filter(|f| connected.contains(&f.info.uploader_ip)) {
+            f.refreshed_at = now;
+        }
+        let before = v.len();
+        v.retain(|f| now.duration_since(f.refreshed_at) < ttl);
+        let expired = before - v.len();
+        if expired > 0 {
+            EVICTED_EXPIRED.fetch_add(expired as u64, Ordering::Relaxed);
+            println!("TCP: Expired {} stale announced file(s)", expired);
+        }
+    }
+}
+
+// Size of the announced-file list and how many entries were evicted, by reason
+pub async fn announced_file_stats() -> serde_json::Value {
+    serde_json::json!({
+        "entries": ANNOUNCED_FILES.lock().await.len(),
+        "evicted": {
+            "expired": EVICTED_EXPIRED.load(Ordering::Relaxed),
+            "disconnected": EVICTED_DISCONNECTED.load(Ordering::Relaxed),
+            "deleted": EVICTED_DELETED.load(Ordering::Relaxed)
+        }
+    })
+}
+
+// Peers we currently hold a session with
+pub async fn connected_peer_ips() -> Vec<String> {
+    REGISTRY.lock().unwrap().ips()
+}
+
+// The optional protocol features this node supports, for /api/peers/info
+pub fn local_capabilities() -> Vec<&'static str> {
+    Capabilities::local().names()
+}
+
+// Each connected peer's session and traffic, for /api/peers/info
+pub fn connections() -> HashMap<String, ConnectionInfo> {
+    REGISTRY.lock().unwrap().connections()
+}
+
+// The peer limit, inbound accept rate and what they turned away, for /api/peers/info
+pub fn admission_status() -> serde_json::Value {
+    let admission = ADMISSION.lock().unwrap();
+    serde_json::json!({ "limits": admission.limits, "stats": admission.stats })
 }
 
-fn sign_file_meta(secret: &str, filename: &str, file_type: &str, file_size: u64, sha256_hex: &str, uploaded_at: &str) -> String {
-    let payload = format!("{}|{}|{}|{}|{}", filename, file_type, file_size, sha256_hex, uploaded_at);
+// Outbound connection state of every peer discovery has found
+pub fn dial_states() -> HashMap<String, DialState> {
+    let dialer = DIALER.lock().unwrap();
+    let now = Instant::now();
+    dialer.ips().into_iter().filter_map(|ip| dialer.state(&ip, now).map(|state| (ip, state))).collect()
+}
+
+// Peers that announced LLM capability
+pub async fn llm_peer_ips() -> Vec<String> {
+    REGISTRY.lock().unwrap().llm_hosts()
+}
+
+// Connected peers that granted us access to their LLM, and the address to reach it at
+pub fn llm_connections() -> Vec<(String, (String, i32))> {
+    REGISTRY.lock().unwrap().llm_endpoints()
+}
+
+pub fn llm_connection(peer_ip: &str) -> Option<(String, i32)> {
+    REGISTRY.lock().unwrap().get(peer_ip).filter(|p| p.authorized).and_then(|p| p.llm_endpoint.clone())
+}
+
+// Copied out so the registry lock is not held while writing
+fn broadcast_writers() -> Vec<(String, registry::Writer)> {
+    REGISTRY.lock().unwrap().writers()
+}
+
+// Fields covered by a FILE_META signature, in wire order. signed_at and nonce make every
+// announcement unique so a captured one cannot be replayed.
+fn file_meta_payload(filename: &str, file_type: &str, file_size: u64, sha256_hex: &str, uploaded_at: &str, signed_at: i64, nonce: &str) -> String {
+    format!("{}|{}|{}|{}|{}|{}|{}", filename, file_type, file_size, sha256_hex, uploaded_at, signed_at, nonce)
+}
+
+fn sign_file_meta(secret: &str, payload: &str) -> String {
     let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
     mac.update(payload.as_bytes());
     let res = mac.finalize().into_bytes();
     hex::encode(res)
 }
 
-fn verify_file_meta(secret: &str, filename: &str, file_type: &str, file_size: u64, sha256_hex: &str, uploaded_at: &str, hmac_hex: &str) -> bool {
-    let expected = sign_file_meta(secret, filename, file_type, file_size, sha256_hex, uploaded_at);
+fn verify_file_meta(secret: &str, payload: &str, hmac_hex: &str) -> bool {
+    let expected = sign_file_meta(secret, payload);
     expected.eq_ignore_ascii_case(hmac_hex)
 }
 
+// Fresh signing time, nonce and HMAC for an outgoing announcement
+async fn stamp_file_meta(filename: &str, file_type: &str, file_size: u64, sha256_hex: &str, uploaded_at: &str) -> (i64, String, String) {
+    let signed_at = chrono::Utc::now().timestamp();
+    let nonce = auth::new_nonce();
+    let hmac_hex = P2P_SECRET
+        .lock()
+        .await
+        .as_ref()
+        .map(|s| sign_file_meta(s, &file_meta_payload(filename, file_type, file_size, sha256_hex, uploaded_at, signed_at, &nonce)))
+        .unwrap_or_default();
+    (signed_at, nonce, hmac_hex)
+}
+
+// Reject announcements from this peer that are stale or reuse a nonce
+async fn accept_file_meta(peer_ip: &str, filename: &str, signed_at: i64, nonce: &str) -> bool {
+    let mut windows = FILE_META_NONCES.lock().await;
+    match windows.entry(peer_ip.to_string()).or_default().check(signed_at, nonce, chrono::Utc::now().timestamp()) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("TCP: Rejected FILE_META {} from {}: {}", filename, peer_ip, e);
+            false
+        }
+    }
+}
+
+// Check an announcement's HMAC against the shared secret; without a secret every announcement passes
+async fn file_meta_authentic(payload: &str, hmac_hex: &str) -> bool {
+    match P2P_SECRET.lock().await.as_deref() {
+        Some(secret) => verify_file_meta(secret, payload, hmac_hex),
+        None => true,
+    }
+}
+
+// Record a FILE_META announcement so the file shows up before (or without) its transfer. The
+// error, why it was rejected, is what the peer is told.
+async fn handle_file_meta(peer_ip: &str, meta: Message) -> Result<(), String> {
+    let Message::FileMeta { filename, file_type, file_size, sha256_hex, uploaded_at, signed_at, nonce, hmac_hex } = meta else {
+        return Err("not a file announcement".to_string());
+    };
+    let payload = file_meta_payload(&filename, &file_type, file_size, &sha256_hex, &uploaded_at, signed_at, &nonce);
+    if !file_meta_authentic(&payload, &hmac_hex).await {
+        events::warn(Category::Security, format!("Invalid HMAC for FILE_META {} from {}, ignoring it", filename, peer_ip), serde_json::json!({ "peer": peer_ip, "filename": filename }));
+        return Err(INVALID_HMAC.to_string());
+    }
+    if !accept_file_meta(peer_ip, &filename, signed_at, &nonce).await {
+        return Err(STALE_ANNOUNCEMENT.to_string());
+    }
+    if !crate::settings::allow_peer_file(peer_ip, &filename, &file_type, file_size).await {
+        return Err(REFUSED_BY_SETTINGS.to_string());
+    }
+    ANNOUNCED_HASHES.lock().await.insert((peer_ip.to_string(), filename.clone()), sha256_hex.clone());
+    let ts = match chrono::DateTime::parse_from_rfc3339(&uploaded_at) {
+        Ok(dt) => dt.with_timezone(&chrono::Utc),
+        Err(_) => chrono::Utc::now(),
+    };
+    add_announced_file(FileInfo {
+        filename,
+        file_type,
+        file_size,
+        uploader_ip: peer_ip.to_string(),
+        upload_time: ts,
+        tags: Vec::new(),
+        sha256: Some(sha256_hex),
+        scan: None,
+        owner: None,
+        visibility: Default::default(),
+        group: None,
+    }).await;
+    Ok(())
+}
+
 use tokio::net::{TcpStream, TcpListener};
 use tokio::io::{AsyncWriteExt, AsyncReadExt};
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use std::path::Path;
 use std::collections::{HashSet, HashMap};
 use tokio::fs;
-use crate::conversation::{Conversation, CONVERSATION_STORE};
+use crate::conversation::{Conversation, Privacy, CONVERSATION_STORE};
 use crate::persistence::FileInfo;
+use crate::chunkstore::{self, ChunkRef};
+use crate::events::{self, Category};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
 type HmacSha256 = Hmac<Sha256>;
 
 use lazy_static::lazy_static;
-use reqwest::Client;
+
+mod admission;
+mod auth;
+mod capability;
+mod compression;
+mod delivery;
+mod dialer;
+mod registry;
+mod session;
+mod version;
+mod wire;
+#[cfg(test)]
+mod tests;
+use capability::Capabilities;
+use compression::Compression;
+use delivery::{Frame, Next, Verdict};
+use version::{NodeVersion, PeerVersion};
+pub use dialer::DialState;
+pub use registry::ConnectionInfo;
+pub use auth::PEER_REQUEST_HEADER;
+pub use version::{is_newer, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
 
 const RECEIVED_DIR: &str = "received";
 const PORT: i32 = 7878;
 const SYNC_INTERVAL: Duration = Duration::from_secs(30);
-const OLLAMA_PORT: i32 = 11434;
-const OLLAMA_CHECK_URL: &str = "http://127.0.0.1:11434/api/tags";
+// A connection with no frame for this long is taken as dead; peers are pinged and synced every 30s
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+// How often the connector looks for peers that are due to be dialled
+const DIAL_TICK: Duration = Duration::from_secs(1);
+const ANNOUNCED_FILES_GC_INTERVAL: Duration = Duration::from_secs(600);
+const FILE_CHUNK_SIZE: usize = 1024 * 1024;
+// Attempts at sending a file to a peer before its transfer counts as failed
+const MAX_SEND_ATTEMPTS: u32 = 3;
+const SEND_RETRY_DELAY: Duration = Duration::from_secs(2);
+// Ping interval on a connection whose file transfer is paused
+const PAUSED_KEEPALIVE: Duration = Duration::from_secs(2);
+// How often unanswered sequenced frames are looked at, see delivery
+const DELIVERY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// How long shutdown waits on each peer for its goodbye to be sent
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(2);
+// Marker and length, counted with the payload in peer traffic stats
+const FRAME_HEADER_LEN: usize = 13;
+const MAX_MESSAGE_SIZE: usize = 50 * 1024 * 1024;
+const MAX_CONTROL_SIZE: usize = 1024;
+const MAX_REQUEST_SIZE: usize = 16 * 1024;
+const MAX_META_SIZE: usize = 64 * 1024;
+// A peer's answer to a diagnostics request, see crate::diagnostics
+pub const MAX_DIAGNOSTICS_SIZE: usize = 1024 * 1024;
+// A peer's file list, see crate::catalog
+const MAX_FILE_LIST_SIZE: usize = 8 * 1024 * 1024;
+// A FILE_META v2 chunk index for a maximum-size file
+const MAX_CHUNK_INDEX_SIZE: usize = 1024 * 1024;
+const DEFAULT_MAX_CONVERSATION_SIZE: usize = 16 * 1024 * 1024;
+
+fn env_size_limit(key: &str, default: usize) -> usize {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default)
+        .min(MAX_MESSAGE_SIZE)
+}
+
+// Largest payload accepted for each message type; anything bigger drops the connection
+fn max_payload_size(marker: &[u8; 5]) -> usize {
+    static CONVERSATION: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    static TRANSFER: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    let conversation = *CONVERSATION.get_or_init(|| env_size_limit("TCP_MAX_CONVERSATION_BYTES", DEFAULT_MAX_CONVERSATION_SIZE));
+    let transfer = *TRANSFER.get_or_init(|| env_size_limit("TCP_MAX_TRANSFER_BYTES", MAX_MESSAGE_SIZE));
+    match marker {
+        b"AUTH:" | b"CAPS:" | b"SYNC:" | b"LLMC:" | b"CMPR:" | b"VERS:" | b"TYPE:" | b"VOTE:" | b"DELF:" | b"PING:" | b"PONG:" | b"DIAQ:" | b"TIMQ:"
+        | b"TIMR:" | b"FLSQ:" | b"ACKN:" => MAX_CONTROL_SIZE,
+        b"LREQ:" | b"LRES:" | b"MNTN:" | b"GBYE:" | b"NACK:" | b"GRPS:" => MAX_REQUEST_SIZE,
+        b"FMTA:" | b"MESH:" | b"LOAD:" => MAX_META_SIZE,
+        b"FMT2:" => MAX_CHUNK_INDEX_SIZE,
+        b"DIAR:" => MAX_DIAGNOSTICS_SIZE,
+        b"FLSR:" => MAX_FILE_LIST_SIZE,
+        b"FILE:" | b"RESP:" | b"CONV:" | b"KNOW:" => conversation,
+        b"FTRS:" => transfer,
+        b"CHNK:" => FILE_CHUNK_SIZE + MAX_META_SIZE,
+        // Envelopes are checked again against the inner type after unwrapping
+        b"ZSTD:" | b"GZIP:" => transfer.max(conversation),
+        b"SEQN:" => transfer.max(conversation) + MAX_CONTROL_SIZE,
+        // Unknown (newer) types are skipped without being buffered, see read_frame
+        _ => transfer,
+    }
+}
+
+// Frame types this build understands; anything else comes from a newer peer
+fn is_known_marker(marker: &[u8; 5]) -> bool {
+    matches!(
+        marker,
+        b"AUTH:" | b"VERS:" | b"CAPS:" | b"FILE:" | b"SYNC:" | b"RESP:" | b"LLMC:" | b"LREQ:" | b"LRES:" | b"FTRS:" | b"CHNK:"
+            | b"FMTA:" | b"FMT2:" | b"CMPR:" | b"TYPE:" | b"VOTE:" | b"DELF:" | b"PING:" | b"PONG:" | b"DIAQ:" | b"DIAR:"
+            | b"MESH:" | b"TIMQ:" | b"TIMR:" | b"LOAD:" | b"MNTN:" | b"GBYE:" | b"CONV:" | b"FLSQ:" | b"FLSR:" | b"GRPS:"
+            | b"KNOW:" | b"SEQN:" | b"ACKN:" | b"NACK:" | b"ZSTD:" | b"GZIP:"
+    )
+}
+
+// A SYNC: asking for the peer's local conversation from since_ms (on the peer's clock) on
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SyncScope {
+    request_id: u64,
+    since_ms: i64,
+}
 
 #[derive(Debug)]
 enum Message {
+    Handshake {
+        timestamp: i64,
+        nonce: String,
+        hmac_hex: String,
+        // None from protocol 1 peers
+        version: Option<NodeVersion>,
+    },
+    // The accepting side's versions, sent back after the handshake
+    Version {
+        crate_version: String,
+        protocol_version: u32,
+        node_id: String,
+    },
+    // The optional features the sender supports, sent once right after the handshake
+    Capabilities(Capabilities),
     ConversationFile {
         name: String,
         content: String,
@@ -76,10 +399,98 @@ enum Message {
         file_size: u64,
         sha256_hex: String,
         uploaded_at: String,
+        signed_at: i64,
+        nonce: String,
         hmac_hex: String,
     },
-    SyncRequest,
+    FileMetaV2(FileMetaV2),
+    CompressionOffer {
+        algorithms: Vec<String>,
+    },
+    // Ephemeral typing/generating indicator; "local" means the sender's own conversation
+    Activity {
+        conversation_id: String,
+        sender: String,
+        activity: crate::presence::Activity,
+    },
+    // A vote on an answer: 1, -1, or 0 to withdraw; the newest vote per voter wins
+    Vote {
+        message_id: String,
+        voter: String,
+        value: i8,
+        voted_at_ms: i64,
+    },
+    // The sender deleted one of its uploads
+    FileDeleted {
+        filename: String,
+    },
+    // Liveness probe; the peer answers with a Pong carrying the same token
+    Ping {
+        token: u64,
+    },
+    Pong {
+        token: u64,
+    },
+    // Asks for the peer's version, config summary and up to `limit` recent events
+    DiagnosticsRequest {
+        request_id: u64,
+        limit: u32,
+    },
+    // JSON object answering the request with the same id, possibly a refusal
+    DiagnosticsResponse {
+        request_id: u64,
+        report: String,
+    },
+    // The sender's own connections, gossiped for the mesh map
+    PeerList(crate::topology::PeerList),
+    // Asks for the files the peer holds, see crate::catalog
+    FileListRequest {
+        request_id: u64,
+    },
+    // The files answering the request with the same id
+    FileListResponse {
+        request_id: u64,
+        files: Vec<FileInfo>,
+    },
+    // A conversation push or file frame the receiver answers with Ack or Nack, see delivery
+    Sequenced {
+        seq: u64,
+        message: Box<Message>,
+    },
+    Ack {
+        seq: u64,
+    },
+    Nack {
+        seq: u64,
+        reason: String,
+    },
+    // Clock probe (unix milliseconds on the sender's clock), answered with a TimeReply
+    TimeRequest {
+        origin_ms: i64,
+    },
+    TimeReply {
+        origin_ms: i64,
+        peer_ms: i64,
+    },
+    // The sender's CPU, memory and GPU load; only LLM hosts send it
+    ComputeLoad(crate::telemetry::ComputeLoad),
+    // The sender announced (Some) or called off (None) a maintenance window
+    Maintenance(Option<crate::maintenance::Window>),
+    // The sender is stopping gracefully and closes the connection after this frame
+    Goodbye(crate::maintenance::Goodbye),
+    // Every group the sender is in, see crate::groups
+    Groups(Vec<String>),
+    // Knowledge base entries, new or removed ones as they change and all of them after connecting
+    Knowledge(Vec<crate::knowledge::Entry>),
+    // None for the periodic request, answered with our broadcast conversations
+    SyncRequest(Option<SyncScope>),
     SyncResponse(Vec<Conversation>),
+    // Answer to a scoped SYNC:: the sender's local conversation from the requested time on, None
+    // while it is private
+    ConversationSince {
+        request_id: u64,
+        conversation: Option<Conversation>,
+    },
     LLMCapability {
         has_llm: bool,
     },
@@ -95,444 +506,1733 @@ enum Message {
     },
 }
 
-// Store LLM-capable peers, authorized peers, and LLM connection details
+// A file a peer announced, and when we last had reason to believe it is still there
+struct AnnouncedFile {
+    info: FileInfo,
+    refreshed_at: Instant,
+}
+
+// Announced-file entries evicted since startup, by reason
+static EVICTED_EXPIRED: AtomicU64 = AtomicU64::new(0);
+static EVICTED_DISCONNECTED: AtomicU64 = AtomicU64::new(0);
+static EVICTED_DELETED: AtomicU64 = AtomicU64::new(0);
+
+// FILE_META v2: announces a file by its content-defined chunk index instead of sending it whole;
+// the receiver downloads only the chunks it does not already hold.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct FileMetaV2 {
+    filename: String,
+    file_type: String,
+    file_size: u64,
+    sha256_hex: String,
+    uploaded_at: String,
+    signed_at: i64,
+    nonce: String,
+    hmac_hex: String,
+    chunks: Vec<ChunkRef>,
+}
+
 lazy_static! {
-    static ref LLM_PEERS: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
-    static ref AUTHORIZED_PEERS: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
-    pub static ref LLM_CONNECTIONS: Arc<Mutex<HashMap<String, (String, i32)>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref CONNECTED_PEERS: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
-    static ref ACTIVE_STREAMS: Arc<Mutex<HashMap<String, TcpStream>>> = Arc::new(Mutex::new(HashMap::new()));
+    // Every connected peer: its session, stream, LLM access, compression and traffic
+    static ref REGISTRY: std::sync::Mutex<registry::Registry> = std::sync::Mutex::new(registry::Registry::default());
+    // Outbound connection state and backoff per peer
+    static ref DIALER: std::sync::Mutex<dialer::Dialer> =
+        std::sync::Mutex::new(dialer::Dialer::new(dialer::Policy { max: crate::power::interval(dialer::MAX_DELAY), ..Default::default() }));
+    // Peer limit and inbound accept rate
+    static ref ADMISSION: std::sync::Mutex<admission::Admission> = std::sync::Mutex::new(admission::Admission::new(admission::Limits::from_env()));
     static ref P2P_SECRET: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
-    static ref ANNOUNCED_FILES: Arc<Mutex<Vec<FileInfo>>> = Arc::new(Mutex::new(Vec::new()));
+    static ref ANNOUNCED_FILES: Arc<Mutex<Vec<AnnouncedFile>>> = Arc::new(Mutex::new(Vec::new()));
+    // Expected SHA-256 per (peer ip, filename) from FILE_META, checked when a chunked transfer completes
+    static ref ANNOUNCED_HASHES: Arc<Mutex<HashMap<(String, String), String>>> = Arc::new(Mutex::new(HashMap::new()));
+    // Handshake nonces already accepted, so a captured AUTH: frame cannot be replayed
+    static ref HANDSHAKE_NONCES: Arc<Mutex<auth::ReplayWindow>> = Arc::new(Mutex::new(auth::ReplayWindow::default()));
+    // Versions peers reported in their handshake or VERS: frame, by peer IP
+    static ref PEER_VERSIONS: Arc<Mutex<HashMap<String, PeerVersion>>> = Arc::new(Mutex::new(HashMap::new()));
+    // FILE_META nonces accepted from each peer
+    static ref FILE_META_NONCES: Arc<Mutex<HashMap<String, auth::ReplayWindow>>> = Arc::new(Mutex::new(HashMap::new()));
+    // Nonces of signed HTTP requests from peers; checked in the HTTP auth guard, so not async
+    static ref PEER_REQUEST_NONCES: std::sync::Mutex<auth::ReplayWindow> = std::sync::Mutex::new(auth::ReplayWindow::default());
 }
 
+// Starts sending a file to every connected peer, regardless of who initiated the TCP connection
 pub async fn broadcast_file_to_peers(filename: String, file_type: String, content: Vec<u8>) {
-    // Send to all active streams regardless of who initiated the TCP connection
-    let mut streams = ACTIVE_STREAMS.lock().await;
-    let targets: Vec<String> = streams.keys().cloned().collect();
-    // Pre-compute meta
-    let file_size = content.len() as u64;
-    let sha = {
-        let mut hasher = Sha256::new();
-        use sha2::Digest;
-        hasher.update(&content);
-        hex::encode(hasher.finalize())
+    share_file_with(connected_peer_ips().await, filename, file_type, content).await;
+}
+
+// The same for a file scoped to a group: only connected peers in it get it
+pub async fn broadcast_file_to_group(group: &str, filename: String, file_type: String, content: Vec<u8>) {
+    share_file_with(crate::groups::members(group).await, filename, file_type, content).await;
+}
+
+async fn share_file_with(peers: Vec<String>, filename: String, file_type: String, content: Vec<u8>) {
+    if !crate::power::push_files() {
+        announce_file_to_peers(peers, filename, file_type, content).await;
+        return;
+    }
+    send_file_to_peers(peers, filename, file_type, content).await;
+}
+
+// Pull-only sharing for the low-power profile: peers get the FILE_META announcement, so the file
+// is listed for them, and fetch the content over HTTP only when someone opens it
+async fn announce_file_to_peers(peers: Vec<String>, filename: String, file_type: String, content: Vec<u8>) {
+    let file = OutgoingFile {
+        filename,
+        file_type,
+        file_size: content.len() as u64,
+        sha256_hex: crate::persistence::sha256_hex(&content),
+        uploaded_at: chrono::Utc::now().to_rfc3339(),
+        chunks: None,
     };
-    let uploaded_at = chrono::Utc::now().to_rfc3339();
-    let secret_opt = P2P_SECRET.lock().await.clone();
-    let hmac_hex = secret_opt
-        .as_ref()
-        .map(|s| sign_file_meta(s, &filename, &file_type, file_size, &sha, &uploaded_at))
-        .unwrap_or_else(|| "".to_string());
-
-    for peer_ip in targets.iter() {
-        if let Some(stream) = streams.get_mut(peer_ip) {
-            // Send FILE_META first (best-effort)
-            let meta = Message::FileMeta {
-                filename: filename.clone(),
-                file_type: file_type.clone(),
-                file_size,
-                sha256_hex: sha.clone(),
-                uploaded_at: uploaded_at.clone(),
-                hmac_hex: hmac_hex.clone(),
-            };
-            if let Err(e) = meta.send(stream).await {
-                eprintln!("TCP: Failed to send FILE_META to {}: {}", peer_ip, e);
-            }
-            let msg = Message::FileTransfer {
-                filename: filename.clone(),
-                file_type: file_type.clone(),
-                file_size,
-                content: content.clone(),
-            };
-            match msg.send(stream).await {
-                Ok(_) => println!("TCP: Broadcasted file {} to peer {}", filename, peer_ip),
-                Err(e) => eprintln!("TCP: Failed to broadcast file {} to peer {}: {}", filename, peer_ip, e),
-            }
+    for peer_ip in peers {
+        match send_to_peer(&peer_ip, file_meta(&file).await).await {
+            Ok(()) => println!("TCP: Announced {} to {} without sending it", file.filename, peer_ip),
+            Err(e) => eprintln!("TCP: Failed to announce {} to {}: {}", file.filename, peer_ip, e),
         }
     }
 }
 
-impl Message {
-    async fn send(&self, stream: &mut TcpStream) -> std::io::Result<()> {
-        match self {
-            Message::ConversationFile { name, content } => {
-                println!("TCP: Sending file {} with size {} bytes", name, content.len());
-
-                // Send marker
-                stream.write_all(b"FILE:").await?;
-
-                // Calculate and send total length
-                let full_content = format!("{}|{}", name, content);
-                let len = full_content.len() as u64;
-                stream.write_all(&len.to_le_bytes()).await?;
-
-                // Send data in chunks
-                let data = full_content.as_bytes();
-                const CHUNK_SIZE: usize = 8192;
-
-                for chunk in data.chunks(CHUNK_SIZE) {
-                    match tokio::time::timeout(Duration::from_secs(30), stream.write_all(chunk)).await {
-                        Ok(Ok(_)) => {
-                            stream.flush().await?;
-                        },
-                        Ok(Err(e)) => {
-                            eprintln!("TCP: Error sending chunk: {}", e);
-                            return Err(e);
-                        },
-                        Err(_) => {
-                            let err = std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout sending chunk");
-                            eprintln!("TCP: {}", err);
-                            return Err(err);
-                        }
-                    }
+// Starts sending a file to the given peers. Each peer gets its own task, so a slow or paused
+// transfer holds up neither the others nor the caller; a peer that is not connected shows up as
+// a failed transfer.
+pub async fn send_file_to_peers(peers: Vec<String>, filename: String, file_type: String, content: Vec<u8>) {
+    let file = Arc::new(OutgoingFile::prepare(&filename, &file_type, &content).await);
+    let content = Arc::new(content);
+    for peer_ip in peers {
+        let (file, content) = (file.clone(), content.clone());
+        tokio::spawn(async move {
+            match send_with_retries(&peer_ip, &file, &content).await {
+                Err(e) if e.kind() != std::io::ErrorKind::Interrupted => {
+                    events::warn(Category::Transfer, format!("Failed to send {} to {}: {}", file.filename, peer_ip, e), serde_json::json!({ "peer": peer_ip, "filename": file.filename }));
                 }
-
-                println!("TCP: Successfully sent file {}", name);
-                return Ok(());
-            },
-            Message::SyncRequest => {
-                stream.write_all(b"SYNC:").await?;
-                let len = 0u64;
-                stream.write_all(&len.to_le_bytes()).await?;
-                return Ok(());
-            },
-            Message::SyncResponse(conversations) => {
-                stream.write_all(b"RESP:").await?;
-                let data = serde_json::to_string(conversations)?;
-                let len = data.len() as u64;
-                stream.write_all(&len.to_le_bytes()).await?;
-                stream.write_all(data.as_bytes()).await?;
-                return Ok(());
-            },
-            Message::LLMCapability { has_llm } => {
-                stream.write_all(b"LLMC:").await?;
-                let data = has_llm.to_string();
-                let len = data.len() as u64;
-                stream.write_all(&len.to_le_bytes()).await?;
-                stream.write_all(data.as_bytes()).await?;
-                return Ok(());
-            },
-            Message::LLMAccessRequest { peer_name, reason } => {
-                stream.write_all(b"LREQ:").await?;
-                let data = format!("{}|{}", peer_name, reason);
-                let len = data.len() as u64;
-                stream.write_all(&len.to_le_bytes()).await?;
-                stream.write_all(data.as_bytes()).await?;
-                return Ok(());
-            },
-            Message::LLMAccessResponse { granted, message, llm_host, llm_port } => {
-                stream.write_all(b"LRES:").await?;
-                let host_str = llm_host.as_deref().unwrap_or("");
-                let port_str = llm_port.map(|p| p.to_string()).unwrap_or_default();
-                let data = format!("{}|{}|{}|{}", granted, message, host_str, port_str);
-                let len = data.len() as u64;
-                stream.write_all(&len.to_le_bytes()).await?;
-                stream.write_all(data.as_bytes()).await?;
-                return Ok(());
-            },
-            Message::FileTransfer { filename, file_type, file_size, content } => {
-                // Use a 5-byte marker to match other message markers (e.g. "FILE:")
-                stream.write_all(b"FTRS:").await?;
-
-                // Calculate and send total length
-                let header = format!("{}|{}|{}", filename, file_type, file_size);
-                let header_len = header.len() as u64;
-                let total_len = header_len + content.len() as u64;
-                stream.write_all(&total_len.to_le_bytes()).await?;
-
-                // Send header and data
-                stream.write_all(header.as_bytes()).await?;
-                stream.write_all(&content).await?;
-                return Ok(());
-            },
-            Message::FileChunk { filename, chunk_index, total_chunks, content } => {
-                stream.write_all(b"CHNK:").await?;
-                let header = format!("{}|{}|{}", filename, chunk_index, total_chunks);
-                let header_len = header.len() as u64;
-                let total_len = header_len + content.len() as u64;
-                stream.write_all(&total_len.to_le_bytes()).await?;
-                stream.write_all(header.as_bytes()).await?;
-                stream.write_all(&content).await?;
-                return Ok(());
-            },
-            Message::FileMeta { filename, file_type, file_size, sha256_hex, uploaded_at, hmac_hex } => {
-                stream.write_all(b"FMTA:").await?;
-                let data = format!("{}|{}|{}|{}|{}", filename, file_type, file_size, sha256_hex, uploaded_at);
-                let payload = format!("{}|{}", data, hmac_hex);
-                let len = payload.len() as u64;
-                stream.write_all(&len.to_le_bytes()).await?;
-                stream.write_all(payload.as_bytes()).await?;
-                return Ok(());
+                _ => {}
             }
-        }
+        });
     }
+}
 
-    async fn receive(stream: &mut TcpStream) -> std::io::Result<Option<Message>> {
-        let mut marker = [0u8; 5];
+// Our local conversation as peers get to see it, with redaction rules applied; None while private
+async fn shared_local_conversation() -> Option<Conversation> {
+    let conversation = CONVERSATION_STORE.get_local_conversation().await.filter(|c| c.privacy.is_shared())?;
+    Some(crate::redact::for_peers(&conversation).await)
+}
 
-        // Read marker with timeout
-        match tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut marker)).await {
-            Ok(Ok(_)) => (),
-            Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
-            Ok(Err(e)) => return Err(e),
-            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout reading marker")),
-        }
+// Whether a conversation may go to a peer: always, unless it is scoped to a group the peer is not in
+fn shared_with(conversation: &Conversation, peer_ip: &str) -> bool {
+    crate::groups::shares_with(peer_ip, conversation.group.as_deref())
+}
 
-        // Read length with timeout
-        let mut len_bytes = [0u8; 8];
-        match tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut len_bytes)).await {
-            Ok(Ok(_)) => (),
-            Ok(Err(e)) => {
-                eprintln!("TCP: Failed to read message length: {}", e);
-                return Err(e);
-            }
-            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout reading length")),
-        }
+// Our local conversation as one peer gets to see it
+async fn local_conversation_for(peer_ip: &str) -> Option<Conversation> {
+    shared_local_conversation().await.filter(|c| shared_with(c, peer_ip))
+}
 
-        let len = u64::from_le_bytes(len_bytes) as usize;
-        if len > 1024 * 1024 * 50 { // 50MB limit
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("Message too large: {} bytes", len)
-            ));
+// Push our local conversation to every connected peer now instead of waiting for the periodic share
+pub async fn broadcast_local_conversation() {
+    let conversation = match shared_local_conversation().await {
+        Some(c) => c,
+        None => return,
+    };
+    let content = match serde_json::to_string(&conversation) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("TCP: Failed to serialize local conversation: {}", e);
+            return;
         }
+    };
+    for (peer_ip, writer) in broadcast_writers().into_iter().filter(|(ip, _)| shared_with(&conversation, ip)) {
+        let stream = &mut *writer.lock().await;
+        let message = Message::ConversationFile { name: "local.json".to_string(), content: content.clone() };
+        if let Err(e) = send_acknowledged(stream, &peer_ip, "local.json", message, true).await {
+            eprintln!("TCP: Failed to push local conversation to {}: {}", peer_ip, e);
+        }
+    }
+}
 
-        // Read data in chunks with timeout
-        let mut data = Vec::with_capacity(len);
-        let mut remaining = len;
-        const CHUNK_SIZE: usize = 8192;
-
-        while remaining > 0 {
-            let chunk_size = remaining.min(CHUNK_SIZE);
-            let mut chunk = vec![0u8; chunk_size];
+// Push one of our forks to every connected peer; it is sent as "<fork-id>.json" so receivers keep
+// it apart from our main conversation
+pub async fn broadcast_fork(fork: &Conversation) {
+    if !fork.privacy.is_shared() {
+        return;
+    }
+    let content = match serde_json::to_string(&crate::redact::for_peers(fork).await) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("TCP: Failed to serialize fork {}: {}", fork.id, e);
+            return;
+        }
+    };
+    for (peer_ip, writer) in broadcast_writers().into_iter().filter(|(ip, _)| shared_with(fork, ip)) {
+        let stream = &mut *writer.lock().await;
+        let name = format!("{}.json", fork.id);
+        let message = Message::ConversationFile { name: name.clone(), content: content.clone() };
+        if let Err(e) = send_acknowledged(stream, &peer_ip, &name, message, true).await {
+            eprintln!("TCP: Failed to push fork {} to {}: {}", fork.id, peer_ip, e);
+        }
+    }
+}
 
-            match tokio::time::timeout(Duration::from_secs(30), stream.read_exact(&mut chunk)).await {
-                Ok(Ok(_)) => {
-                    data.extend_from_slice(&chunk);
-                    remaining -= chunk_size;
-                }
-                Ok(Err(e)) => {
-                    eprintln!("TCP: Failed to read chunk: {}", e);
-                    return Err(e);
-                }
-                Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout reading chunk")),
-            }
+// Tell every connected peer someone here is typing or generating (best-effort, never retried)
+pub async fn broadcast_activity(conversation_id: &str, sender: &str, activity: crate::presence::Activity) {
+    for (peer_ip, writer) in broadcast_writers() {
+        let stream = &mut *writer.lock().await;
+        let message = Message::Activity { conversation_id: conversation_id.to_string(), sender: sender.to_string(), activity };
+        if let Err(e) = message.send(stream).await {
+            eprintln!("TCP: Failed to send activity to {}: {}", peer_ip, e);
         }
+    }
+}
 
-        match &marker {
-            b"FILE:" => {
-                let content = String::from_utf8_lossy(&data);
-                if let Some((name, content)) = content.split_once('|') {
-                    println!("TCP: Received file {} with size {} bytes", name, content.len());
-                    Ok(Some(Message::ConversationFile {
-                        name: name.to_string(),
-                        content: content.to_string(),
-                    }))
-                } else {
-                    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file format"))
-                }
-            },
-            b"SYNC:" => Ok(Some(Message::SyncRequest)),
-            b"RESP:" => {
-                let conversations = serde_json::from_slice(&data)?;
-                Ok(Some(Message::SyncResponse(conversations)))
-            },
-            b"LLMC:" => {
-                let has_llm = String::from_utf8_lossy(&data).parse::<bool>().unwrap_or(false);
-                Ok(Some(Message::LLMCapability { has_llm }))
-            },
-            b"LREQ:" => {
-                let content = String::from_utf8_lossy(&data);
-                if let Some((peer_name, reason)) = content.split_once('|') {
-                    Ok(Some(Message::LLMAccessRequest {
-                        peer_name: peer_name.to_string(),
-                        reason: reason.to_string(),
-                    }))
-                } else {
-                    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid LLM request format"))
-                }
-            },
-            b"LRES:" => {
-                let content = String::from_utf8_lossy(&data);
-                let parts: Vec<&str> = content.split('|').collect();
-                if parts.len() == 4 {
-                    let granted = parts[0].parse().unwrap_or(false);
-                    let message = parts[1].to_string();
-                    let llm_host = if !parts[2].is_empty() { Some(parts[2].to_string()) } else { None };
-                    let llm_port = if !parts[3].is_empty() { parts[3].parse().ok() } else { None };
-                    Ok(Some(Message::LLMAccessResponse {
-                        granted,
-                        message,
-                        llm_host,
-                        llm_port,
-                    }))
-                } else {
-                    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid LLM response format"))
-                }
-            },
-            b"FTRS:" => {
-                // Parse header: filename|file_type|file_size followed by binary content
-                let content_str = String::from_utf8_lossy(&data);
-                let parts: Vec<&str> = content_str.split('|').collect();
-                if parts.len() >= 3 {
-                    let filename = parts[0].to_string();
-                    let file_type = parts[1].to_string();
-                    let file_size_str = parts[2];
-                    if let Ok(file_size) = file_size_str.parse::<u64>() {
-                        // Find the end of header: filename|file_type|file_size|
-                        let header_end = filename.len() + 1 + file_type.len() + 1 + file_size_str.len() + 1;
-                        if data.len() >= header_end {
-                            let content = data[header_end..].to_vec();
-                            println!("TCP: Received file transfer {} ({} bytes)", filename, content.len());
-                            Ok(Some(Message::FileTransfer {
-                                filename,
-                                file_type,
-                                file_size,
-                                content,
-                            }))
-                        } else {
-                            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "File content too short"))
-                        }
-                    } else {
-                        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file size"))
-                    }
-                } else {
-                    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file transfer format"))
-                }
-            },
-            b"CHNK:" => {
-                let content = String::from_utf8_lossy(&data);
-                if let Some(header_end) = content.find('\0') {
-                    let header = &content[..header_end];
-                    let file_data = &data[header_end + 1..];
-                    let parts: Vec<&str> = header.split('|').collect();
-                    if parts.len() == 3 {
-                        let filename = parts[0].to_string();
-                        let chunk_index = parts[1].parse().unwrap_or(0);
-                        let total_chunks = parts[2].parse().unwrap_or(1);
-                        Ok(Some(Message::FileChunk {
-                            filename,
-                            chunk_index,
-                            total_chunks,
-                            content: file_data.to_vec(),
-                        }))
-                    } else {
-                        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file chunk format"))
-                    }
-                } else {
-                    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file chunk format"))
-                }
-            },
-            b"FMTA:" => {
-                let content = String::from_utf8_lossy(&data);
-                // format: filename|file_type|file_size|sha256|uploaded_at|hmac
-                let parts: Vec<&str> = content.split('|').collect();
-                if parts.len() >= 6 {
-                    let filename = parts[0].to_string();
-                    let file_type = parts[1].to_string();
-                    let file_size: u64 = parts[2].parse().unwrap_or(0);
-                    let sha256_hex = parts[3].to_string();
-                    let uploaded_at = parts[4].to_string();
-                    let hmac_hex = parts[5].to_string();
-                    let ok = if let Some(secret) = P2P_SECRET.blocking_lock().clone() { // blocking_lock ok in non-async context
-                        verify_file_meta(&secret, &filename, &file_type, file_size, &sha256_hex, &uploaded_at, &hmac_hex)
-                    } else { true };
-                    if !ok {
-                        eprintln!("TCP: Invalid HMAC for FILE_META {} — ignoring", filename);
-                        // Still return Some to consume the message but not act on metadata persistently
-                    } else {
-                        println!("TCP: Received FILE_META {} ({} bytes) sha={}", filename, file_size, sha256_hex);
-                    }
-                    Ok(Some(Message::FileMeta { filename, file_type, file_size, sha256_hex, uploaded_at, hmac_hex }))
-                } else {
-                    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid FILE_META format"))
-                }
-            },
-            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Unknown message type")),
+pub async fn broadcast_vote(message_id: &str, voter: &str, value: i8, voted_at_ms: i64) {
+    for (peer_ip, writer) in broadcast_writers() {
+        let stream = &mut *writer.lock().await;
+        let message = Message::Vote { message_id: message_id.to_string(), voter: voter.to_string(), value, voted_at_ms };
+        if let Err(e) = message.send(stream).await {
+            eprintln!("TCP: Failed to send vote to {}: {}", peer_ip, e);
         }
     }
 }
 
-// Make the function public
-pub async fn is_ollama_available() -> bool {
-    if let Ok(client) = Client::builder()
-        .timeout(Duration::from_secs(2))
-        .build() 
-    {
-        // First check if Ollama is running locally
-        let local_available = match client.get(OLLAMA_CHECK_URL).send().await {
-            Ok(response) => response.status().is_success(),
-            Err(_) => false,
-        };
+pub async fn broadcast_file_deleted(filename: &str) {
+    for (peer_ip, writer) in broadcast_writers() {
+        let stream = &mut *writer.lock().await;
+        let message = Message::FileDeleted { filename: filename.to_string() };
+        if let Err(e) = message.send(stream).await {
+            eprintln!("TCP: Failed to send deletion of {} to {}: {}", filename, peer_ip, e);
+        }
+    }
+}
 
-        if !local_available {
-            return false;
+// Tell every connected peer about our maintenance window, or that it is called off
+pub async fn broadcast_maintenance(window: Option<crate::maintenance::Window>) {
+    for (peer_ip, writer) in broadcast_writers() {
+        let stream = &mut *writer.lock().await;
+        if let Err(e) = Message::Maintenance(window.clone()).send(stream).await {
+            eprintln!("TCP: Failed to send maintenance notice to {}: {}", peer_ip, e);
         }
+    }
+}
 
-        // Then check if it's accessible externally
-        let local_addr = match tokio::net::TcpStream::connect(format!("127.0.0.1:{}", OLLAMA_PORT)).await {
-            Ok(stream) => stream.local_addr().ok(),
-            Err(_) => None,
-        };
+// Tell every connected peer which groups we are in, after joining or leaving one
+pub async fn broadcast_groups() {
+    let groups = crate::groups::local();
+    for (peer_ip, writer) in broadcast_writers() {
+        let stream = &mut *writer.lock().await;
+        if let Err(e) = Message::Groups(groups.clone()).send(stream).await {
+            eprintln!("TCP: Failed to send groups to {}: {}", peer_ip, e);
+        }
+    }
+}
 
-        if let Some(addr) = local_addr {
-            // Try to connect using the external IP
-            match tokio::net::TcpStream::connect(format!("{}:{}", addr.ip(), OLLAMA_PORT)).await {
-                Ok(_) => {
-                    println!("TCP: Ollama is accessible externally");
-                    true
-                },
-                Err(e) => {
-                    println!("TCP: Ollama is not accessible externally: {}", e);
-                    println!("TCP: Please configure Ollama to listen on 0.0.0.0 by setting OLLAMA_HOST=0.0.0.0 in the environment");
-                    false
-                }
-            }
-        } else {
-            false
+// Send every connected peer knowledge base entries that were just saved or removed
+pub async fn broadcast_knowledge(entries: Vec<crate::knowledge::Entry>) {
+    for (peer_ip, writer) in broadcast_writers() {
+        let stream = &mut *writer.lock().await;
+        if let Err(e) = Message::Knowledge(entries.clone()).send(stream).await {
+            eprintln!("TCP: Failed to send knowledge entries to {}: {}", peer_ip, e);
         }
-    } else {
-        false
     }
 }
 
-pub async fn listen_for_connections() -> std::io::Result<()> {
-    // Create received directory if it doesn't exist
-    let received_path = Path::new(RECEIVED_DIR);
-    if !received_path.exists() {
-        fs::create_dir_all(received_path).await?;
+// Our whole knowledge base, in frames of at most knowledge::BATCH entries
+async fn send_knowledge(stream: &mut TcpStream) -> std::io::Result<()> {
+    for batch in crate::knowledge::all().chunks(crate::knowledge::BATCH) {
+        Message::Knowledge(batch.to_vec()).send(stream).await?;
+    }
+    Ok(())
+}
+
+// Tell every connected peer we are stopping, so it marks us offline now rather than when the
+// connection times out. A peer that does not answer within GOODBYE_TIMEOUT is left to find out.
+pub async fn say_goodbye() {
+    let goodbye = crate::maintenance::goodbye();
+    for (peer_ip, writer) in broadcast_writers() {
+        let stream = &mut *writer.lock().await;
+        match tokio::time::timeout(GOODBYE_TIMEOUT, Message::Goodbye(goodbye.clone()).send(stream)).await {
+            Ok(Ok(())) => println!("TCP: Said goodbye to {}", peer_ip),
+            Ok(Err(e)) => eprintln!("TCP: Failed to say goodbye to {}: {}", peer_ip, e),
+            Err(_) => eprintln!("TCP: Timed out saying goodbye to {}", peer_ip),
+        }
     }
+}
 
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", PORT)).await?;
-    println!("TCP: Listening on port {}", PORT);
+// A peer said goodbye: it is offline from now on, without waiting for the connection to drop
+async fn peer_left(peer_ip: &str, goodbye: crate::maintenance::Goodbye) {
+    let back_at_ms = goodbye.back_at_ms;
+    crate::maintenance::goodbye_received(peer_ip, goodbye).await;
+    events::info(Category::Peer, format!("{} is shutting down", peer_ip), serde_json::json!({ "peer": peer_ip, "back_at_ms": back_at_ms }));
+    forget_peer_files(peer_ip).await;
+    events::publish("peer", serde_json::json!({ "event": "disconnected", "ip": peer_ip, "reason": "goodbye", "back_at_ms": back_at_ms }));
+}
 
-    loop {
-        let (stream, addr) = listener.accept().await?;
-        println!("TCP: New connection from {}", addr);
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream).await {
-                eprintln!("TCP: Connection error with {}: {}", addr, e);
-            }
-        });
+// Our maintenance window, for a peer that just connected
+async fn send_maintenance(stream: &mut TcpStream) -> std::io::Result<()> {
+    match crate::maintenance::local() {
+        Some(window) => Message::Maintenance(Some(window)).send(stream).await,
+        None => Ok(()),
     }
 }
 
-// Add this new function for periodic conversation sharing
-async fn periodic_conversation_share(mut stream: TcpStream, addr: std::net::SocketAddr) {
-    let mut interval = tokio::time::interval(Duration::from_secs(30));
+// Store a conversation file received from a peer: a fork, or the peer's main conversation. The
+// error is what the peer is told when it asked for an acknowledgement.
+async fn receive_conversation_file(peer_ip: &str, peer_dir: &Path, name: &str, content: &str) -> Result<(), String> {
+    let parsed = serde_json::from_str::<Conversation>(content).ok();
+    // A private conversation should never have left its node; it is neither kept nor written out
+    if parsed.as_ref().is_some_and(|c| !c.privacy.is_shared()) {
+        events::warn(Category::Security, format!("Dropped private conversation {} sent by {}", name, peer_ip), serde_json::json!({ "peer": peer_ip, "name": name }));
+        return Err("private conversations are not accepted".to_string());
+    }
+    // Nor is one scoped to a group we are not in
+    if let Some(group) = parsed.and_then(|c| c.group).filter(|g| !crate::groups::is_member(g)) {
+        println!("TCP: Dropped {} from {}, scoped to group {} we are not in", name, peer_ip, group);
+        return Err(format!("not a member of group {}", group));
+    }
+    if let Some(fork_id) = crate::conversation::fork_id_from_file(name) {
+        return match serde_json::from_str::<Conversation>(content) {
+            Ok(mut fork) => {
+                fork.id = fork_id.to_string();
+                fork.ensure_message_ids();
+                crate::clock::correct_conversation(peer_ip, &mut fork).await;
+                println!("TCP: Received fork {} from {}", fork_id, peer_ip);
+                CONVERSATION_STORE.add_peer_fork(peer_ip.to_string(), fork).await;
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("TCP: Invalid fork {} from {}: {}", name, peer_ip, e);
+                Err(format!("invalid fork: {}", e))
+            }
+        };
+    }
+    let file_path = peer_dir.join(name);
+    if let Err(e) = crate::persistence::write_atomic(&file_path, content.as_bytes()).await {
+        eprintln!("TCP: Failed to save received file {}: {}", name, e);
+        Err(format!("could not save {}: {}", name, e))
+    } else {
+        println!("TCP: Received and saved conversation file {} from {}", name, peer_ip);
+        if let Ok(mut conversation) = serde_json::from_str::<Conversation>(content) {
+            conversation.ensure_message_ids();
+            crate::clock::correct_conversation(peer_ip, &mut conversation).await;
+            let new_messages = CONVERSATION_STORE.add_peer_conversation(peer_ip.to_string(), conversation).await;
+            if let Some(last) = new_messages.last() {
+                events::publish("chat", serde_json::json!({
+                    "event": "peer_messages",
+                    "peer": peer_ip,
+                    "count": new_messages.len(),
+                    "sender": last.sender,
+                    "last": last.content
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Broadcast conversations for a peer that just connected or asked with SYNC:, except those it
+// passed to us itself
+async fn send_broadcast_conversations(stream: &mut TcpStream, peer_ip: &str) -> std::io::Result<()> {
+    let mut conversations = Vec::new();
+    for conversation in CONVERSATION_STORE.broadcast_conversations().await {
+        if conversation.relayed_from.as_deref() != Some(peer_ip) && shared_with(&conversation, peer_ip) {
+            conversations.push(crate::redact::for_peers(&conversation).await);
+        }
+    }
+    Message::SyncResponse(conversations).send(stream).await
+}
+
+// A periodic SYNC: gets our broadcast conversations; a scoped one, asked through
+// /api/peers/{ip}/conversation, our local conversation from the requested time on
+async fn answer_sync_request(stream: &mut TcpStream, peer_ip: &str, scope: Option<SyncScope>) -> std::io::Result<()> {
+    let Some(scope) = scope else {
+        return send_broadcast_conversations(stream, peer_ip).await;
+    };
+    let conversation = local_conversation_for(peer_ip).await.map(|mut c| {
+        c.messages.retain(|m| m.timestamp.timestamp_millis() >= scope.since_ms);
+        c
+    });
+    Message::ConversationSince { request_id: scope.request_id, conversation }.send(stream).await
+}
+
+// Our files for a peer's FLSQ:, leaving out any whose name the peer would refuse
+async fn answer_file_list_request(stream: &mut TcpStream, peer_ip: &str, request_id: u64) -> std::io::Result<()> {
+    let mut files = crate::catalog::listing(peer_ip).await;
+    files.retain(|f| wire::filename(&f.filename).is_ok());
+    Message::FileListResponse { request_id, files }.send(stream).await
+}
+
+// A peer's answer to our scoped SYNC:, merged into our copy of its conversation like a pushed one
+async fn receive_conversation_since(peer_ip: &str, request_id: u64, conversation: Option<Conversation>) {
+    let conversation = match conversation {
+        Some(c) if !c.privacy.is_shared() => {
+            events::warn(Category::Security, format!("Dropped private conversation sent by {}", peer_ip), serde_json::json!({ "peer": peer_ip }));
+            None
+        }
+        Some(c) if !crate::groups::accepts(c.group.as_deref()) => None,
+        Some(mut c) => {
+            c.ensure_message_ids();
+            crate::clock::correct_conversation(peer_ip, &mut c).await;
+            println!("TCP: Received {} message(s) of {}'s conversation on request", c.messages.len(), peer_ip);
+            CONVERSATION_STORE.add_peer_conversation(peer_ip.to_string(), c.clone()).await;
+            Some(c)
+        }
+        None => None,
+    };
+    crate::history::response_received(peer_ip, request_id, conversation).await;
+}
+
+// Broadcast conversations from a peer, its own and those it relays, each stored under the node it
+// came from. Anything not marked broadcast, and copies of our own, are left out.
+async fn receive_sync_response(peer_ip: &str, conversations: Vec<Conversation>) {
+    for mut conversation in conversations {
+        let origin = conversation.relayed_from.take().unwrap_or_else(|| peer_ip.to_string());
+        if conversation.privacy != Privacy::Broadcast || crate::ip::is_my_ip(&origin) || !crate::groups::accepts(conversation.group.as_deref()) {
+            continue;
+        }
+        conversation.ensure_message_ids();
+        // Relayed copies are already in the relaying peer's time
+        crate::clock::correct_conversation(peer_ip, &mut conversation).await;
+        if crate::conversation::is_fork_id(&conversation.id) {
+            CONVERSATION_STORE.add_peer_fork(origin, conversation).await;
+        } else {
+            CONVERSATION_STORE.add_peer_conversation(origin, conversation).await;
+        }
+    }
+}
+
+// Send one file to one connected peer as FILE_META followed by CHNK messages, so large files
+// never have to fit in a single frame on either side.
+pub async fn send_file_chunked(peer_ip: &str, filename: &str, file_type: &str, content: &[u8]) -> std::io::Result<()> {
+    let file = OutgoingFile::prepare(filename, file_type, content).await;
+    send_with_retries(peer_ip, &file, content).await
+}
+
+// What FILE_META says about a file we send, worked out once however many peers get it
+struct OutgoingFile {
+    filename: String,
+    file_type: String,
+    file_size: u64,
+    sha256_hex: String,
+    uploaded_at: String,
+    // Chunk index when the file goes out deduplicated
+    chunks: Option<Vec<ChunkRef>>,
+}
+
+impl OutgoingFile {
+    async fn prepare(filename: &str, file_type: &str, content: &[u8]) -> OutgoingFile {
+        OutgoingFile {
+            filename: filename.to_string(),
+            file_type: file_type.to_string(),
+            file_size: content.len() as u64,
+            sha256_hex: crate::persistence::sha256_hex(content),
+            uploaded_at: chrono::Utc::now().to_rfc3339(),
+            chunks: dedup_chunks(content).await,
+        }
+    }
+}
+
+// Send a file to a peer, tracked in crate::transfers. A failed attempt is retried from the start,
+// on a new connection if the peer has reconnected in the meantime.
+async fn send_with_retries(peer_ip: &str, file: &OutgoingFile, content: &[u8]) -> std::io::Result<()> {
+    let id = crate::transfers::start(&file.filename, peer_ip, file.file_size).await;
+    let mut attempt = 1;
+    loop {
+        match send_file(peer_ip, file, content, id).await {
+            Ok(()) => {
+                crate::transfers::finish(id).await;
+                return Ok(());
+            }
+            // Cancelled by the user; the transfer already says so
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                println!("TCP: Cancelled sending {} to {}", file.filename, peer_ip);
+                return Err(e);
+            }
+            Err(e) if attempt < MAX_SEND_ATTEMPTS => {
+                eprintln!("TCP: Sending {} to {} failed (attempt {}), retrying: {}", file.filename, peer_ip, attempt, e);
+                crate::transfers::retry(id, &e.to_string()).await;
+                sleep(SEND_RETRY_DELAY).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                crate::transfers::fail(id, &e.to_string()).await;
+                return Err(e);
+            }
+        }
+    }
+}
+
+// Hold a paused transfer. Peers drop connections that stay silent for a few seconds, so keep
+// pinging while we wait for the transfer to be resumed or cancelled. A lost connection only
+// matters once the transfer goes on, which then counts as a failed attempt.
+async fn wait_while_paused(peer_ip: &str, transfer_id: u64) -> std::io::Result<()> {
+    while crate::transfers::wait_while_paused(transfer_id, PAUSED_KEEPALIVE).await? {
+        let _ = send_to_peer(peer_ip, Message::Ping { token: rand::random() }).await;
+    }
+    Ok(())
+}
+
+// Ask a connected peer for its diagnostics; the answer arrives as a DIAR: frame
+pub async fn request_diagnostics(peer_ip: &str, request_id: u64, limit: u32) -> std::io::Result<()> {
+    send_to_peer(peer_ip, Message::DiagnosticsRequest { request_id, limit }).await
+}
+
+// Ask a connected peer for the files it holds; the answer arrives as a FLSR: frame
+pub async fn request_file_list(peer_ip: &str, request_id: u64) -> std::io::Result<()> {
+    if !peer_supports(peer_ip, Capabilities::FILE_LISTS) {
+        return Err(unsupported(peer_ip, "file lists"));
+    }
+    send_to_peer(peer_ip, Message::FileListRequest { request_id }).await
+}
+
+// Ask a connected peer for its conversation from `since_ms` (on our clock) on; the answer arrives
+// as a CONV: frame
+pub async fn request_conversation(peer_ip: &str, request_id: u64, since_ms: i64) -> std::io::Result<()> {
+    if !peer_supports(peer_ip, Capabilities::SCOPED_SYNC) {
+        return Err(unsupported(peer_ip, "conversation requests"));
+    }
+    let since_ms = (since_ms - crate::clock::correction_ms(peer_ip).await.unwrap_or(0)).max(0);
+    send_to_peer(peer_ip, Message::SyncRequest(Some(SyncScope { request_id, since_ms }))).await
+}
+
+fn not_connected(peer_ip: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotConnected, format!("Peer {} is not connected", peer_ip))
+}
+
+fn unsupported(peer_ip: &str, feature: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, format!("Peer {} does not support {}", peer_ip, feature))
+}
+
+fn peer_writer(peer_ip: &str) -> std::io::Result<registry::Writer> {
+    REGISTRY.lock().unwrap().writer(peer_ip).ok_or_else(|| not_connected(peer_ip))
+}
+
+// Send one frame to a connected peer
+async fn send_to_peer(peer_ip: &str, message: Message) -> std::io::Result<()> {
+    let writer = peer_writer(peer_ip)?;
+    let stream = &mut *writer.lock().await;
+    message.send(stream).await
+}
+
+// Whether the session with the peer may use an optional feature, see capability
+fn peer_supports(peer_ip: &str, feature: Capabilities) -> bool {
+    REGISTRY.lock().unwrap().supports(peer_ip, feature)
+}
+
+// Send a frame the peer acknowledges (see delivery); `what` names it in delivery failures. A
+// conversation push (`resend`) is kept to be sent again. The verdict resolves once the peer has
+// answered, or at once for a peer that does not acknowledge.
+async fn send_acknowledged(stream: &mut TcpStream, peer_ip: &str, what: &str, message: Message, resend: bool) -> std::io::Result<Verdict> {
+    if !peer_supports(peer_ip, Capabilities::ACKS) {
+        message.send(stream).await?;
+        let (sender, verdict) = tokio::sync::oneshot::channel();
+        let _ = sender.send(Ok(()));
+        return Ok(verdict);
+    }
+    let frame = message.encode()?;
+    let timeout = if resend { delivery::ACK_TIMEOUT } else { delivery::FILE_ACK_TIMEOUT };
+    let registered = REGISTRY.lock().unwrap().get_mut(peer_ip).map(|p| p.outbox.register(what, resend.then(|| frame.clone()), timeout, Instant::now()));
+    let (seq, verdict) = registered.ok_or_else(|| not_connected(peer_ip))?;
+    send_frame(stream, sequenced_frame(seq, frame)).await?;
+    Ok(verdict)
+}
+
+// One frame of a file transfer, acknowledged but never sent again on its own
+async fn send_file_frame(peer_ip: &str, what: &str, message: Message) -> std::io::Result<Verdict> {
+    let writer = peer_writer(peer_ip)?;
+    let stream = &mut *writer.lock().await;
+    send_acknowledged(stream, peer_ip, what, message, false).await
+}
+
+// ACKN: or NACK: for a sequenced frame, once it has been handled
+fn delivery_answer(seq: u64, handled: Result<(), String>) -> Message {
+    match handled {
+        Ok(()) => Message::Ack { seq },
+        Err(reason) => Message::Nack { seq, reason },
+    }
+}
+
+// The peer's ACKN: or NACK: for a frame we sent sequenced
+async fn delivery_answered(peer_ip: &str, seq: u64, answer: Result<(), String>) {
+    let next = match REGISTRY.lock().unwrap().get_mut(peer_ip) {
+        Some(peer) => match answer {
+            Ok(()) => {
+                peer.outbox.acked(seq);
+                None
+            }
+            Err(reason) => peer.outbox.nacked(seq, &reason, Instant::now()),
+        },
+        None => None,
+    };
+    if let Some(next) = next {
+        follow_up_delivery(peer_ip, next).await;
+    }
+}
+
+async fn follow_up_delivery(peer_ip: &str, next: Next) {
+    match next {
+        Next::Resend(seq, frame) => {
+            let sent = match peer_writer(peer_ip) {
+                Ok(writer) => send_frame(&mut *writer.lock().await, sequenced_frame(seq, frame)).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                eprintln!("TCP: Failed to resend frame {} to {}: {}", seq, peer_ip, e);
+            }
+        }
+        Next::Failed(what, reason) => events::warn(
+            Category::Peer,
+            format!("{} was not delivered to {}: {}", what, peer_ip, reason),
+            serde_json::json!({ "peer": peer_ip, "what": what, "reason": reason }),
+        ),
+    }
+}
+
+// Send again, or give up on, frames peers have not answered in time
+pub async fn delivery_checker() {
+    let mut interval = tokio::time::interval(DELIVERY_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let overdue = REGISTRY.lock().unwrap().overdue(Instant::now());
+        for (peer_ip, next) in overdue {
+            follow_up_delivery(&peer_ip, next).await;
+        }
+    }
+}
+
+// The connection is only held for one frame at a time, so other traffic to the peer goes on
+// between chunks and a paused transfer blocks nothing. A resumed transfer carries on with the next
+// chunk, which the peer appends to what it has. The transfer is done once the peer has
+// acknowledged every frame; a refused one fails the attempt with the peer's reason.
+async fn send_file(peer_ip: &str, file: &OutgoingFile, content: &[u8], transfer_id: u64) -> std::io::Result<()> {
+    wait_while_paused(peer_ip, transfer_id).await?;
+    // Each attempt is signed anew; peers reject a FILE_META nonce they have already seen. Peers
+    // without the dedup capability get the file whole.
+    if let Some(chunks) = file.chunks.as_ref().filter(|_| peer_supports(peer_ip, Capabilities::DEDUP_CHUNKS)) {
+        let (signed_at, nonce, hmac_hex) = stamp_file_meta(&file.filename, &file.file_type, file.file_size, &file.sha256_hex, &file.uploaded_at).await;
+        let meta = Message::FileMetaV2(FileMetaV2 {
+            filename: file.filename.clone(),
+            file_type: file.file_type.clone(),
+            file_size: file.file_size,
+            sha256_hex: file.sha256_hex.clone(),
+            uploaded_at: file.uploaded_at.clone(),
+            signed_at,
+            nonce,
+            hmac_hex,
+            chunks: chunks.clone(),
+        });
+        let verdict = send_file_frame(peer_ip, &file.filename, meta).await?;
+        // The peer fetches the chunks it lacks over HTTP; the announcement is all we send
+        crate::transfers::progress(transfer_id, file.file_size).await;
+        println!("TCP: Announced {} to {} as {} chunks", file.filename, peer_ip, chunks.len());
+        return delivered(peer_ip, &file.filename, vec![verdict]).await;
+    }
+    let mut verdicts = vec![send_file_frame(peer_ip, &format!("{} announcement", file.filename), file_meta(file).await).await?];
+
+    let total_chunks = content.chunks(FILE_CHUNK_SIZE).len().max(1) as u32;
+    if content.is_empty() {
+        let msg = Message::FileChunk { filename: file.filename.clone(), chunk_index: 0, total_chunks, content: Vec::new() };
+        verdicts.push(send_file_frame(peer_ip, &file.filename, msg).await?);
+    }
+    let mut sent = 0u64;
+    for (i, chunk) in content.chunks(FILE_CHUNK_SIZE).enumerate() {
+        wait_while_paused(peer_ip, transfer_id).await?;
+        let msg = Message::FileChunk {
+            filename: file.filename.clone(),
+            chunk_index: i as u32,
+            total_chunks,
+            content: chunk.to_vec(),
+        };
+        let what = format!("{} chunk {}/{}", file.filename, i + 1, total_chunks);
+        verdicts.push(send_file_frame(peer_ip, &what, msg).await?);
+        sent += chunk.len() as u64;
+        crate::transfers::progress(transfer_id, sent).await;
+    }
+    println!("TCP: Sent {} to {} in {} chunks", file.filename, peer_ip, total_chunks);
+    delivered(peer_ip, &file.filename, verdicts).await
+}
+
+// Wait for the peer's answers to a file's frames
+async fn delivered(peer_ip: &str, filename: &str, verdicts: Vec<Verdict>) -> std::io::Result<()> {
+    for verdict in verdicts {
+        match verdict.await {
+            Ok(Ok(())) => {}
+            Ok(Err(reason)) => {
+                return Err(std::io::Error::other(format!("{} did not take {}: {}", peer_ip, filename, reason)));
+            }
+            Err(_) => {
+                return Err(std::io::Error::new(std::io::ErrorKind::ConnectionAborted, format!("Connection to {} ended before it confirmed {}", peer_ip, filename)));
+            }
+        }
+    }
+    Ok(())
+}
+
+// A freshly signed FILE_META for a file sent whole
+async fn file_meta(file: &OutgoingFile) -> Message {
+    let (signed_at, nonce, hmac_hex) = stamp_file_meta(&file.filename, &file.file_type, file.file_size, &file.sha256_hex, &file.uploaded_at).await;
+    Message::FileMeta {
+        filename: file.filename.clone(),
+        file_type: file.file_type.clone(),
+        file_size: file.file_size,
+        sha256_hex: file.sha256_hex.clone(),
+        uploaded_at: file.uploaded_at.clone(),
+        signed_at,
+        nonce,
+        hmac_hex,
+    }
+}
+
+async fn dedup_chunks(content: &[u8]) -> Option<Vec<ChunkRef>> {
+    if !chunkstore::dedup_enabled() || content.len() < chunkstore::MIN_DEDUP_FILE_SIZE {
+        return None;
+    }
+    match chunkstore::store_chunks(content).await {
+        Ok(chunks) => Some(chunks),
+        Err(e) => {
+            eprintln!("TCP: Failed to chunk file for dedup transfer, sending whole: {}", e);
+            None
+        }
+    }
+}
+
+// Reasons a peer is given when its file is not taken
+const INVALID_HMAC: &str = "invalid HMAC";
+const STALE_ANNOUNCEMENT: &str = "stale or replayed announcement";
+const REFUSED_BY_SETTINGS: &str = "refused by this node's file settings";
+const REFUSED_BY_SCAN: &str = "refused by the virus scan";
+
+// Virus-scan a file received from a peer before it is stored; false when it must be dropped
+async fn scan_received(peer_ip: &str, filename: &str, content: &[u8]) -> bool {
+    match crate::scan::check(filename, content, peer_ip).await {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("TCP: Refused {} from {}: {}", filename, peer_ip, e);
+            false
+        }
+    }
+}
+
+// Rebuild a file announced with FILE_META v2, fetching only the chunks missing locally. The
+// error is what the peer is told.
+async fn handle_file_meta_v2(peer_dir: std::path::PathBuf, peer_ip: String, meta: FileMetaV2) -> Result<(), String> {
+    let payload = file_meta_payload(&meta.filename, &meta.file_type, meta.file_size, &meta.sha256_hex, &meta.uploaded_at, meta.signed_at, &meta.nonce);
+    if !file_meta_authentic(&payload, &meta.hmac_hex).await {
+        events::warn(Category::Security, format!("Invalid HMAC for FILE_META v2 {} from {}, ignoring it", meta.filename, peer_ip), serde_json::json!({ "peer": peer_ip, "filename": meta.filename }));
+        return Err(INVALID_HMAC.to_string());
+    }
+    if !accept_file_meta(&peer_ip, &meta.filename, meta.signed_at, &meta.nonce).await {
+        return Err(STALE_ANNOUNCEMENT.to_string());
+    }
+    if !crate::settings::allow_peer_file(&peer_ip, &meta.filename, &meta.file_type, meta.file_size).await {
+        return Err(REFUSED_BY_SETTINGS.to_string());
+    }
+    let name = match Path::new(&meta.filename).file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return Err("invalid filename".to_string()),
+    };
+    let (data, fetched) = match chunkstore::assemble_from_peer(&peer_ip, &meta.chunks).await {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("TCP: Failed to assemble {} from {}: {}", name, peer_ip, e);
+            return Err(format!("could not fetch its chunks: {}", e));
+        }
+    };
+    if data.len() as u64 != meta.file_size || !crate::persistence::sha256_hex(&data).eq_ignore_ascii_case(&meta.sha256_hex) {
+        events::warn(Category::Security, format!("Assembled {} from {} does not match its FILE_META, discarding it", name, peer_ip), serde_json::json!({ "peer": peer_ip, "filename": name }));
+        return Err("hash mismatch".to_string());
+    }
+    if !scan_received(&peer_ip, &name, &data).await {
+        return Err(REFUSED_BY_SCAN.to_string());
+    }
+    if let Err(e) = crate::persistence::write_atomic(&peer_dir.join(&name), &data).await {
+        eprintln!("TCP: Failed to save {} from {}: {}", name, peer_ip, e);
+        return Err(format!("could not save the file: {}", e));
+    }
+    events::info(
+        Category::File,
+        format!("Saved {} ({} bytes) from {}; fetched {} of {} chunks", name, data.len(), peer_ip, fetched, meta.chunks.len()),
+        serde_json::json!({ "peer": peer_ip, "filename": name, "bytes": data.len() }),
+    );
+    events::publish("file", serde_json::json!({
+        "event": "received",
+        "filename": name,
+        "bytes": data.len(),
+        "peer": peer_ip
+    }));
+    let ts = match chrono::DateTime::parse_from_rfc3339(&meta.uploaded_at) {
+        Ok(dt) => dt.with_timezone(&chrono::Utc),
+        Err(_) => chrono::Utc::now(),
+    };
+    add_announced_file(FileInfo {
+        filename: name,
+        file_type: meta.file_type,
+        file_size: meta.file_size,
+        uploader_ip: peer_ip,
+        upload_time: ts,
+        tags: Vec::new(),
+        sha256: Some(meta.sha256_hex),
+        scan: None,
+        owner: None,
+        visibility: Default::default(),
+        group: None,
+    }).await;
+    Ok(())
+}
+
+// A FILE_META v2 arrives on the connection's read loop; the file is rebuilt off it and, if the
+// peer asked, acknowledged once saved
+async fn receive_file_meta_v2(peer_dir: std::path::PathBuf, peer_ip: String, meta: FileMetaV2, seq: Option<u64>) {
+    let handled = handle_file_meta_v2(peer_dir, peer_ip.clone(), meta).await;
+    if let Some(seq) = seq {
+        if let Err(e) = send_to_peer(&peer_ip, delivery_answer(seq, handled)).await {
+            eprintln!("TCP: Failed to acknowledge frame {} from {}: {}", seq, peer_ip, e);
+        }
+    }
+}
+
+// Append a received chunk to <peer_dir>/<filename>.part; the last chunk is verified against the
+// announced hash (if any) and renamed into place. The error is what the peer is told.
+async fn handle_file_chunk(peer_dir: &Path, peer_ip: &str, filename: &str, chunk_index: u32, total_chunks: u32, content: &[u8]) -> Result<(), String> {
+    let name = match Path::new(filename).file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => {
+            eprintln!("TCP: Ignoring chunk with invalid filename {:?} from {}", filename, peer_ip);
+            return Err("invalid filename".to_string());
+        }
+    };
+    let part_path = peer_dir.join(format!("{}.part", name));
+    let file_type = mime_guess::from_path(&name).first_or_octet_stream().to_string();
+    let write_result = if chunk_index == 0 {
+        // Every chunk but the last is full, which bounds the size from below
+        let min_size = (total_chunks as u64 - 1) * FILE_CHUNK_SIZE as u64 + content.len() as u64;
+        if !crate::settings::allow_peer_file(peer_ip, &name, &file_type, min_size).await {
+            return Err(REFUSED_BY_SETTINGS.to_string());
+        }
+        fs::write(&part_path, content).await
+    } else {
+        match fs::OpenOptions::new().append(true).open(&part_path).await {
+            Ok(mut f) => f.write_all(content).await,
+            // The first chunk was refused or never arrived
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err("the first chunk was refused or never arrived".to_string()),
+            Err(e) => Err(e),
+        }
+    };
+    if let Err(e) = write_result {
+        eprintln!("TCP: Failed to write chunk {}/{} of {} from {}: {}", chunk_index + 1, total_chunks, name, peer_ip, e);
+        return Err(format!("could not write chunk: {}", e));
+    }
+    if chunk_index + 1 < total_chunks {
+        return Ok(());
+    }
+
+    let data = match fs::read(&part_path).await {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("TCP: Failed to read assembled {} from {}: {}", name, peer_ip, e);
+            return Err(format!("could not read the assembled file: {}", e));
+        }
+    };
+    if !crate::settings::allow_peer_file(peer_ip, &name, &file_type, data.len() as u64).await {
+        let _ = fs::remove_file(&part_path).await;
+        return Err(REFUSED_BY_SETTINGS.to_string());
+    }
+    let sha = crate::persistence::sha256_hex(&data);
+    let expected = ANNOUNCED_HASHES.lock().await.remove(&(peer_ip.to_string(), filename.to_string()));
+    if let Some(expected) = expected {
+        if !sha.eq_ignore_ascii_case(&expected) {
+            events::warn(Category::Security, format!("Hash mismatch for {} from {}, discarding it", name, peer_ip), serde_json::json!({ "peer": peer_ip, "filename": name }));
+            let _ = fs::remove_file(&part_path).await;
+            return Err("hash mismatch".to_string());
+        }
+    }
+    if !scan_received(peer_ip, &name, &data).await {
+        let _ = fs::remove_file(&part_path).await;
+        return Err(REFUSED_BY_SCAN.to_string());
+    }
+    if let Err(e) = fs::rename(&part_path, peer_dir.join(&name)).await {
+        eprintln!("TCP: Failed to finalize {} from {}: {}", name, peer_ip, e);
+        return Err(format!("could not save the file: {}", e));
+    }
+    events::info(Category::File, format!("Saved {} ({} bytes) from {}", name, data.len(), peer_ip), serde_json::json!({ "peer": peer_ip, "filename": name, "bytes": data.len() }));
+    events::publish("file", serde_json::json!({
+        "event": "received",
+        "filename": name,
+        "bytes": data.len(),
+        "peer": peer_ip
+    }));
+    add_announced_file(FileInfo {
+        filename: name.clone(),
+        file_type: mime_guess::from_path(&name).first_or_octet_stream().to_string(),
+        file_size: data.len() as u64,
+        uploader_ip: peer_ip.to_string(),
+        upload_time: chrono::Utc::now(),
+        tags: Vec::new(),
+        sha256: Some(sha),
+        scan: None,
+        owner: None,
+        visibility: Default::default(),
+        group: None,
+    }).await;
+    Ok(())
+}
+
+// Wrap the payload in a compression envelope if the peer negotiated one and it actually helps
+fn compress_for_peer(peer_ip: Option<&str>, marker: [u8; 5], payload: Vec<u8>) -> ([u8; 5], Vec<u8>) {
+    if payload.len() < compression::min_bytes() || &marker == b"CMPR:" {
+        return (marker, payload);
+    }
+    let algorithm = match peer_ip.and_then(|ip| REGISTRY.lock().unwrap().get(ip).and_then(|p| p.compression)) {
+        Some(a) => a,
+        None => return (marker, payload),
+    };
+    match algorithm.compress(&payload) {
+        Ok(compressed) if compressed.len() + 5 < payload.len() => {
+            let mut wrapped = Vec::with_capacity(compressed.len() + 5);
+            wrapped.extend_from_slice(&marker);
+            wrapped.extend_from_slice(&compressed);
+            (algorithm.marker(), wrapped)
+        }
+        _ => (marker, payload),
+    }
+}
+
+// An encoded frame on its way out, compressed if the peer negotiated it and counted in its stats
+async fn send_frame(stream: &mut TcpStream, (marker, payload): Frame) -> std::io::Result<()> {
+    let peer_ip = stream.peer_addr().ok().map(|a| a.ip().to_string());
+    let (marker, mut payload) = compress_for_peer(peer_ip.as_deref(), marker, payload);
+    if !crate::chaos::disturb(crate::chaos::Channel::Tcp, &mut payload).await {
+        return Ok(());
+    }
+    write_frame(stream, &marker, &payload).await?;
+    if let Some(ip) = &peer_ip {
+        REGISTRY.lock().unwrap().sent(ip, FRAME_HEADER_LEN + payload.len());
+    }
+    crate::bandwidth::record(crate::bandwidth::Channel::Tcp, peer_ip.as_deref(), crate::bandwidth::Direction::Up, FRAME_HEADER_LEN + payload.len());
+    Ok(())
+}
+
+// SEQN: envelope: <seq>|<inner marker><inner payload>
+fn sequenced_frame(seq: u64, (marker, payload): Frame) -> Frame {
+    let mut wrapped = format!("{}|", seq).into_bytes();
+    wrapped.extend_from_slice(&marker);
+    wrapped.extend_from_slice(&payload);
+    (*b"SEQN:", wrapped)
+}
+
+// Marker, little-endian length, then the payload in timed 8KB writes
+async fn write_frame(stream: &mut TcpStream, marker: &[u8; 5], payload: &[u8]) -> std::io::Result<()> {
+    stream.write_all(marker).await?;
+    stream.write_all(&(payload.len() as u64).to_le_bytes()).await?;
+
+    const CHUNK_SIZE: usize = 8192;
+    for chunk in payload.chunks(CHUNK_SIZE) {
+        match tokio::time::timeout(Duration::from_secs(30), stream.write_all(chunk)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                eprintln!("TCP: Error sending chunk: {}", e);
+                return Err(e);
+            }
+            Err(_) => {
+                let err = std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout sending chunk");
+                eprintln!("TCP: {}", err);
+                return Err(err);
+            }
+        }
+    }
+    stream.flush().await
+}
+
+// First frames on an outbound connection, AUTH: and our CAPS:; target_ip is the address we dialled
+async fn send_handshake(stream: &mut TcpStream, target_ip: &str) -> std::io::Result<()> {
+    let secret = P2P_SECRET.lock().await.clone().unwrap_or_default();
+    let timestamp = chrono::Utc::now().timestamp();
+    let nonce = auth::new_nonce();
+    let version = NodeVersion::local();
+    let hmac_hex = auth::sign_handshake(&secret, timestamp, &nonce, target_ip, Some(&version));
+    (Message::Handshake { timestamp, nonce, hmac_hex, version: Some(version) }).send(stream).await?;
+    (Message::Capabilities(Capabilities::local())).send(stream).await
+}
+
+// A peer speaking `protocol` sends its CAPS: right after its handshake frame; older peers send none
+async fn read_capabilities(stream: &mut TcpStream, protocol: u32) -> std::io::Result<Capabilities> {
+    if protocol < version::CAPS_PROTOCOL_VERSION {
+        return Ok(Capabilities::implied(protocol));
+    }
+    let message = match tokio::time::timeout(auth::HANDSHAKE_TIMEOUT, Message::receive(stream)).await {
+        Ok(message) => message?,
+        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "no capabilities received")),
+    };
+    match message {
+        Some(Message::Capabilities(capabilities)) => Ok(capabilities),
+        Some(_) => Err(wire::invalid("expected CAPS: after the handshake")),
+        None => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "closed during the handshake")),
+    }
+}
+
+async fn send_version(stream: &mut TcpStream) -> std::io::Result<()> {
+    let local = NodeVersion::local();
+    let message = Message::Version {
+        crate_version: local.crate_version,
+        protocol_version: local.protocol,
+        node_id: crate::persistence::node_id().to_string(),
+    };
+    message.send(stream).await
+}
+
+async fn record_peer_version(peer_ip: &str, version: PeerVersion) {
+    if let Some(advisory) = &version.advisory {
+        println!("TCP: Peer {} version notice: {}", peer_ip, advisory);
+    }
+    PEER_VERSIONS.lock().await.insert(peer_ip.to_string(), version);
+}
+
+// Versions and upgrade advisories of every peer we have talked to
+pub async fn peer_versions() -> HashMap<String, PeerVersion> {
+    PEER_VERSIONS.lock().await.clone()
+}
+
+// Require a valid AUTH: frame before anything else is read from an inbound connection; the
+// features the peer supports come with it
+async fn authenticate_peer(stream: &mut TcpStream) -> std::io::Result<Capabilities> {
+    let frame = match tokio::time::timeout(auth::HANDSHAKE_TIMEOUT, read_frame(stream)).await {
+        Ok(frame) => frame?,
+        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "no handshake received")),
+    };
+    let (timestamp, nonce, hmac_hex, version) = match frame {
+        Some((marker, data)) if &marker == b"AUTH:" => match Message::decode(&marker, data)? {
+            Some(Message::Handshake { timestamp, nonce, hmac_hex, version }) => (timestamp, nonce, hmac_hex, version),
+            _ => return Err(wire::invalid("malformed handshake")),
+        },
+        Some((marker, _)) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("{} frame before handshake", String::from_utf8_lossy(&marker)),
+            ))
+        }
+        None => return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "closed before handshake")),
+    };
+    let local_ip = stream.local_addr()?.ip().to_string();
+    let secret = P2P_SECRET.lock().await.clone().unwrap_or_default();
+    if !auth::verify_handshake(&secret, timestamp, &nonce, &local_ip, version.as_ref(), &hmac_hex) {
+        return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "handshake HMAC mismatch"));
+    }
+    HANDSHAKE_NONCES
+        .lock()
+        .await
+        .check(timestamp, &nonce, chrono::Utc::now().timestamp())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::PermissionDenied, e))?;
+
+    let peer_ip = stream.peer_addr()?.ip().to_string();
+    let (crate_version, protocol) = match version {
+        Some(v) => (Some(v.crate_version), v.protocol),
+        None => (None, 1),
+    };
+    record_peer_version(&peer_ip, version::peer_version(crate_version, protocol, None)).await;
+    crate::clock::handshake_received(&peer_ip, timestamp).await;
+    // Tell the peer our versions either way, so a rejected one knows why
+    send_version(stream).await?;
+    if !version::is_compatible(protocol) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("peer speaks protocol {}, at least {} required", protocol, version::MIN_PROTOCOL_VERSION),
+        ));
+    }
+    if protocol >= version::CAPS_PROTOCOL_VERSION {
+        (Message::Capabilities(Capabilities::local())).send(stream).await?;
+    }
+    let capabilities = read_capabilities(stream, protocol).await?;
+    // A precise clock sample to follow up the handshake's whole seconds
+    (Message::TimeRequest { origin_ms: crate::clock::now_ms() }).send(stream).await?;
+    Ok(capabilities)
+}
+
+async fn send_compression_offer(stream: &mut TcpStream, peer_ip: &str) -> std::io::Result<()> {
+    let algorithms: Vec<String> = compression::local_preferences().iter().map(|c| c.name().to_string()).collect();
+    if algorithms.is_empty() || !peer_supports(peer_ip, Capabilities::COMPRESSION) {
+        return Ok(());
+    }
+    (Message::CompressionOffer { algorithms }).send(stream).await
+}
+
+fn record_compression_offer(peer_ip: &str, algorithms: &[String]) {
+    let negotiated = compression::negotiate(algorithms);
+    if let Some(c) = negotiated {
+        println!("TCP: Using {} compression for {}", c.name(), peer_ip);
+    }
+    if let Some(peer) = REGISTRY.lock().unwrap().get_mut(peer_ip) {
+        peer.compression = negotiated;
+    }
+}
+
+impl Message {
+    // Marker and payload as written on the wire (before optional compression)
+    fn encode(&self) -> std::io::Result<([u8; 5], Vec<u8>)> {
+        let framed = match self {
+            Message::Handshake { timestamp, nonce, hmac_hex, version } => {
+                let mut payload = format!("{}|{}|{}", timestamp, nonce, hmac_hex);
+                if let Some(v) = version {
+                    payload.push_str(&format!("|{}|{}", v.crate_version, v.protocol));
+                }
+                (*b"AUTH:", payload.into_bytes())
+            },
+            Message::Version { crate_version, protocol_version, node_id } => {
+                (*b"VERS:", format!("{}|{}|{}", crate_version, protocol_version, node_id).into_bytes())
+            },
+            Message::Capabilities(capabilities) => (*b"CAPS:", format!("{:x}", capabilities.bits()).into_bytes()),
+            Message::ConversationFile { name, content } => (*b"FILE:", format!("{}|{}", name, content).into_bytes()),
+            Message::SyncRequest(None) => (*b"SYNC:", Vec::new()),
+            Message::SyncRequest(Some(scope)) => (*b"SYNC:", format!("{}|{}", scope.request_id, scope.since_ms).into_bytes()),
+            Message::SyncResponse(conversations) => (*b"RESP:", serde_json::to_vec(conversations)?),
+            Message::ConversationSince { request_id, conversation } => {
+                let mut payload = format!("{}|", request_id).into_bytes();
+                payload.extend(serde_json::to_vec(conversation)?);
+                (*b"CONV:", payload)
+            }
+            Message::LLMCapability { has_llm } => (*b"LLMC:", has_llm.to_string().into_bytes()),
+            Message::LLMAccessRequest { peer_name, reason } => (*b"LREQ:", format!("{}|{}", peer_name, reason).into_bytes()),
+            Message::LLMAccessResponse { granted, message, llm_host, llm_port } => {
+                let host_str = llm_host.as_deref().unwrap_or("");
+                let port_str = llm_port.map(|p| p.to_string()).unwrap_or_default();
+                (*b"LRES:", format!("{}|{}|{}|{}", granted, message, host_str, port_str).into_bytes())
+            },
+            Message::FileTransfer { filename, file_type, file_size, content } => {
+                // Header fields are '|'-terminated, followed by the binary content
+                let mut data = format!("{}|{}|{}|", filename, file_type, file_size).into_bytes();
+                data.extend_from_slice(content);
+                (*b"FTRS:", data)
+            },
+            Message::FileChunk { filename, chunk_index, total_chunks, content } => {
+                // Header and binary chunk are separated by a NUL byte
+                let mut data = format!("{}|{}|{}", filename, chunk_index, total_chunks).into_bytes();
+                data.push(0);
+                data.extend_from_slice(content);
+                (*b"CHNK:", data)
+            },
+            Message::FileMeta { filename, file_type, file_size, sha256_hex, uploaded_at, signed_at, nonce, hmac_hex } => {
+                let data = file_meta_payload(filename, file_type, *file_size, sha256_hex, uploaded_at, *signed_at, nonce);
+                (*b"FMTA:", format!("{}|{}", data, hmac_hex).into_bytes())
+            },
+            Message::FileMetaV2(meta) => (*b"FMT2:", serde_json::to_vec(meta)?),
+            Message::CompressionOffer { algorithms } => (*b"CMPR:", algorithms.join(",").into_bytes()),
+            Message::Activity { conversation_id, sender, activity } => {
+                (*b"TYPE:", format!("{}|{}|{}", activity.as_str(), conversation_id, sender).into_bytes())
+            },
+            Message::Vote { message_id, voter, value, voted_at_ms } => {
+                (*b"VOTE:", format!("{}|{}|{}|{}", value, message_id, voted_at_ms, voter).into_bytes())
+            },
+            Message::FileDeleted { filename } => (*b"DELF:", filename.clone().into_bytes()),
+            Message::Ping { token } => (*b"PING:", token.to_string().into_bytes()),
+            Message::Pong { token } => (*b"PONG:", token.to_string().into_bytes()),
+            Message::DiagnosticsRequest { request_id, limit } => (*b"DIAQ:", format!("{}|{}", request_id, limit).into_bytes()),
+            Message::DiagnosticsResponse { request_id, report } => (*b"DIAR:", format!("{}|{}", request_id, report).into_bytes()),
+            Message::PeerList(list) => (*b"MESH:", serde_json::to_vec(list)?),
+            Message::FileListRequest { request_id } => (*b"FLSQ:", request_id.to_string().into_bytes()),
+            Message::FileListResponse { request_id, files } => {
+                let mut payload = format!("{}|", request_id).into_bytes();
+                payload.extend(serde_json::to_vec(files)?);
+                (*b"FLSR:", payload)
+            }
+            Message::Sequenced { seq, message } => sequenced_frame(*seq, message.encode()?),
+            Message::Ack { seq } => (*b"ACKN:", seq.to_string().into_bytes()),
+            Message::Nack { seq, reason } => (*b"NACK:", format!("{}|{}", seq, reason).into_bytes()),
+            Message::ComputeLoad(load) => (*b"LOAD:", serde_json::to_vec(load)?),
+            Message::Maintenance(window) => (*b"MNTN:", serde_json::to_vec(window)?),
+            Message::Goodbye(goodbye) => (*b"GBYE:", serde_json::to_vec(goodbye)?),
+            Message::Groups(groups) => (*b"GRPS:", serde_json::to_vec(groups)?),
+            Message::Knowledge(entries) => (*b"KNOW:", serde_json::to_vec(entries)?),
+            Message::TimeRequest { origin_ms } => (*b"TIMQ:", origin_ms.to_string().into_bytes()),
+            Message::TimeReply { origin_ms, peer_ms } => (*b"TIMR:", format!("{}|{}", origin_ms, peer_ms).into_bytes()),
+        };
+        Ok(framed)
+    }
+
+    async fn send(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let (marker, payload) = self.encode()?;
+        if let Message::ConversationFile { name, content } = self {
+            println!("TCP: Sending file {} with size {} bytes", name, content.len());
+        }
+        send_frame(stream, (marker, payload)).await?;
+        if let Message::ConversationFile { name, .. } = self {
+            println!("TCP: Successfully sent file {}", name);
+        }
+        Ok(())
+    }
+
+    // The sequence number to answer, if the message came in a SEQN: envelope, and its content
+    fn unsequenced(self) -> (Option<u64>, Message) {
+        match self {
+            Message::Sequenced { seq, message } => (Some(seq), *message),
+            message => (None, message),
+        }
+    }
+
+    // Next well-formed message. Frames whose payload fails to decode (or whose type is unknown)
+    // are logged and skipped; only transport errors and lost framing end the connection.
+    async fn receive(stream: &mut TcpStream) -> std::io::Result<Option<Message>> {
+        let peer_ip = stream.peer_addr().ok().map(|a| a.ip().to_string());
+        loop {
+            let (marker, data) = match read_frame(stream).await? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            if let Some(ip) = &peer_ip {
+                REGISTRY.lock().unwrap().received(ip, FRAME_HEADER_LEN + data.len());
+            }
+            crate::bandwidth::record(crate::bandwidth::Channel::Tcp, peer_ip.as_deref(), crate::bandwidth::Direction::Down, FRAME_HEADER_LEN + data.len());
+            match Self::unwrap_frame(marker, data).and_then(|(m, d)| Self::decode(&m, d)) {
+                Ok(Some(message)) => return Ok(Some(message)),
+                Ok(None) => continue,
+                Err(e) => eprintln!("TCP: Skipping malformed {} frame: {}", String::from_utf8_lossy(&marker), e),
+            }
+        }
+    }
+
+    // Compressed envelope: <algorithm marker><inner marker><compressed inner payload>
+    fn unwrap_frame(marker: [u8; 5], data: Vec<u8>) -> std::io::Result<([u8; 5], Vec<u8>)> {
+        match Compression::from_marker(&marker) {
+            Some(algorithm) => {
+                if data.len() < 5 {
+                    return Err(wire::invalid("compressed message too short"));
+                }
+                let mut inner = [0u8; 5];
+                inner.copy_from_slice(&data[..5]);
+                if Compression::from_marker(&inner).is_some() {
+                    return Err(wire::invalid("nested compression"));
+                }
+                Ok((inner, algorithm.decompress(&data[5..], max_payload_size(&inner))?))
+            }
+            None => Ok((marker, data)),
+        }
+    }
+
+    fn decode(marker: &[u8; 5], data: Vec<u8>) -> std::io::Result<Option<Message>> {
+        match marker {
+            b"AUTH:" => {
+                // timestamp|nonce|hmac|crate_version|protocol_version (protocol 1: first three only)
+                let content = wire::utf8(&data, "handshake")?;
+                let parts: Vec<&str> = content.split('|').collect();
+                if parts.len() != 3 && parts.len() != 5 {
+                    return Err(wire::invalid("handshake needs 3 or 5 fields"));
+                }
+                let timestamp = wire::unix_time(parts[0])?;
+                let nonce = wire::nonce(parts[1])?;
+                let hmac_hex = wire::sha256_hex(parts[2])?;
+                let version = match parts.len() {
+                    5 => Some(NodeVersion {
+                        crate_version: wire::version(parts[3])?,
+                        protocol: wire::parse_u32(parts[4], "protocol version")?,
+                    }),
+                    _ => None,
+                };
+                Ok(Some(Message::Handshake { timestamp, nonce, hmac_hex, version }))
+            },
+            b"VERS:" => {
+                let content = wire::utf8(&data, "version")?;
+                let parts: Vec<&str> = content.split('|').collect();
+                if parts.len() != 3 {
+                    return Err(wire::invalid("version needs 3 fields"));
+                }
+                let crate_version = wire::version(parts[0])?;
+                let protocol_version = wire::parse_u32(parts[1], "protocol version")?;
+                let node_id = wire::name(parts[2])?;
+                Ok(Some(Message::Version { crate_version, protocol_version, node_id }))
+            },
+            b"CAPS:" => {
+                let bits = wire::utf8(&data, "capabilities")?;
+                if !(1..=16).contains(&bits.len()) || !bits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err(wire::invalid("malformed capabilities"));
+                }
+                let bits = u64::from_str_radix(&bits, 16).map_err(|_| wire::invalid("malformed capabilities"))?;
+                Ok(Some(Message::Capabilities(Capabilities::from_bits(bits))))
+            },
+            b"FILE:" => {
+                let (fields, content) = wire::split_fields(&data, 1)?;
+                let name = wire::filename(fields[0])?;
+                let content = wire::utf8(content, "conversation content")?;
+                println!("TCP: Received file {} with size {} bytes", name, content.len());
+                Ok(Some(Message::ConversationFile { name, content }))
+            },
+            b"SYNC:" => {
+                if data.is_empty() {
+                    return Ok(Some(Message::SyncRequest(None)));
+                }
+                let content = wire::utf8(&data, "sync request")?;
+                let (request_id, since) = content.split_once('|').ok_or_else(|| wire::invalid("scoped sync request needs id|since"))?;
+                let since_ms = i64::try_from(wire::parse_u64(since, "since")?).map_err(|_| wire::invalid("since out of range"))?;
+                Ok(Some(Message::SyncRequest(Some(SyncScope { request_id: wire::parse_u64(request_id, "request id")?, since_ms }))))
+            },
+            b"CONV:" => {
+                let (fields, content) = wire::split_fields(&data, 1)?;
+                let request_id = wire::parse_u64(fields[0], "request id")?;
+                let conversation: Option<Conversation> = serde_json::from_slice(content)?;
+                if conversation.as_ref().is_some_and(|c| c.id != "local") {
+                    return Err(wire::invalid("unknown conversation id"));
+                }
+                Ok(Some(Message::ConversationSince { request_id, conversation }))
+            },
+            b"RESP:" => {
+                let conversations: Vec<Conversation> = serde_json::from_slice(&data)?;
+                for c in &conversations {
+                    if c.id != "local" && !crate::conversation::is_fork_id(&c.id) {
+                        return Err(wire::invalid("unknown conversation id"));
+                    }
+                    if c.relayed_from.as_deref().is_some_and(|ip| ip.parse::<std::net::IpAddr>().is_err()) {
+                        return Err(wire::invalid("relayed_from is not an IP address"));
+                    }
+                }
+                Ok(Some(Message::SyncResponse(conversations)))
+            },
+            b"LLMC:" => {
+                let has_llm = wire::parse_bool(&wire::utf8(&data, "capability")?, "capability")?;
+                Ok(Some(Message::LLMCapability { has_llm }))
+            },
+            b"LREQ:" => {
+                let (fields, reason) = wire::split_fields(&data, 1)?;
+                Ok(Some(Message::LLMAccessRequest {
+                    peer_name: wire::name(fields[0])?,
+                    reason: wire::utf8(reason, "reason")?,
+                }))
+            },
+            b"LRES:" => {
+                // granted|message|host|port — the message may itself contain '|'
+                let content = wire::utf8(&data, "LLM response")?;
+                let (granted, rest) = content.split_once('|').ok_or_else(|| wire::invalid("missing message"))?;
+                let (rest, port) = rest.rsplit_once('|').ok_or_else(|| wire::invalid("missing port"))?;
+                let (message, host) = rest.rsplit_once('|').ok_or_else(|| wire::invalid("missing host"))?;
+                let llm_port = if port.is_empty() {
+                    None
+                } else {
+                    Some(i32::try_from(wire::parse_u32(port, "port")?).ok().filter(|p| *p <= 65535).ok_or_else(|| wire::invalid("port out of range"))?)
+                };
+                Ok(Some(Message::LLMAccessResponse {
+                    granted: wire::parse_bool(granted, "granted")?,
+                    message: message.to_string(),
+                    llm_host: if host.is_empty() { None } else { Some(wire::name(host)?) },
+                    llm_port,
+                }))
+            },
+            b"FTRS:" => {
+                // filename|file_type|file_size| followed by exactly file_size bytes
+                let (fields, content) = wire::split_fields(&data, 3)?;
+                let filename = wire::filename(fields[0])?;
+                let file_type = wire::mime(fields[1])?;
+                let file_size = wire::parse_u64(fields[2], "file size")?;
+                if content.len() as u64 != file_size {
+                    return Err(wire::invalid(format!("file content is {} bytes, header says {}", content.len(), file_size)));
+                }
+                println!("TCP: Received file transfer {} ({} bytes)", filename, content.len());
+                Ok(Some(Message::FileTransfer { filename, file_type, file_size, content: content.to_vec() }))
+            },
+            b"CHNK:" => {
+                let header_end = data.iter().position(|b| *b == 0).ok_or_else(|| wire::invalid("missing chunk header terminator"))?;
+                let header = std::str::from_utf8(&data[..header_end]).map_err(|_| wire::invalid("chunk header is not UTF-8"))?;
+                let parts: Vec<&str> = header.split('|').collect();
+                if parts.len() != 3 {
+                    return Err(wire::invalid("chunk header needs filename|index|total"));
+                }
+                let filename = wire::filename(parts[0])?;
+                let chunk_index = wire::parse_u32(parts[1], "chunk index")?;
+                let total_chunks = wire::parse_u32(parts[2], "total chunks")?;
+                if total_chunks == 0 || total_chunks > wire::MAX_TOTAL_CHUNKS || chunk_index >= total_chunks {
+                    return Err(wire::invalid(format!("chunk {} of {} out of range", chunk_index, total_chunks)));
+                }
+                Ok(Some(Message::FileChunk { filename, chunk_index, total_chunks, content: data[header_end + 1..].to_vec() }))
+            },
+            b"FMTA:" => {
+                // filename|file_type|file_size|sha256|uploaded_at|signed_at|nonce|hmac
+                let content = wire::utf8(&data, "FILE_META")?;
+                let parts: Vec<&str> = content.split('|').collect();
+                if parts.len() != 8 {
+                    return Err(wire::invalid("FILE_META needs 8 fields"));
+                }
+                let filename = wire::filename(parts[0])?;
+                let file_type = wire::mime(parts[1])?;
+                let file_size = wire::parse_u64(parts[2], "file size")?;
+                let sha256_hex = wire::sha256_hex(parts[3])?;
+                let uploaded_at = wire::rfc3339(parts[4])?;
+                let signed_at = wire::unix_time(parts[5])?;
+                let nonce = wire::nonce(parts[6])?;
+                let hmac_hex = wire::hmac_hex(parts[7])?;
+                // The HMAC is checked by handle_file_meta, which can wait for the secret
+                println!("TCP: Received FILE_META {} ({} bytes) sha={}", filename, file_size, sha256_hex);
+                Ok(Some(Message::FileMeta { filename, file_type, file_size, sha256_hex, uploaded_at, signed_at, nonce, hmac_hex }))
+            },
+            b"FMT2:" => {
+                let meta: FileMetaV2 = serde_json::from_slice(&data)?;
+                wire::filename(&meta.filename)?;
+                wire::mime(&meta.file_type)?;
+                wire::sha256_hex(&meta.sha256_hex)?;
+                wire::rfc3339(&meta.uploaded_at)?;
+                wire::nonce(&meta.nonce)?;
+                wire::hmac_hex(&meta.hmac_hex)?;
+                for c in &meta.chunks {
+                    wire::sha256_hex(&c.hash)?;
+                }
+                if meta.chunks.iter().map(|c| c.len as u64).sum::<u64>() != meta.file_size {
+                    return Err(wire::invalid("chunk lengths do not add up to the file size"));
+                }
+                println!("TCP: Received FILE_META v2 {} ({} bytes, {} chunks)", meta.filename, meta.file_size, meta.chunks.len());
+                Ok(Some(Message::FileMetaV2(meta)))
+            },
+            b"CMPR:" => {
+                let offer = wire::utf8(&data, "compression offer")?;
+                let algorithms: Vec<String> = offer
+                    .split(',')
+                    .map(|a| a.trim().to_lowercase())
+                    .filter(|a| !a.is_empty())
+                    .collect();
+                if algorithms.len() > 16 || algorithms.iter().any(|a| a.len() > 16 || !a.chars().all(|c| c.is_ascii_alphanumeric())) {
+                    return Err(wire::invalid("malformed compression offer"));
+                }
+                Ok(Some(Message::CompressionOffer { algorithms }))
+            },
+            b"TYPE:" => {
+                // activity|conversation_id|sender — the sender may itself contain '|'
+                let content = wire::utf8(&data, "activity")?;
+                let parts: Vec<&str> = content.splitn(3, '|').collect();
+                if parts.len() != 3 {
+                    return Err(wire::invalid("activity needs 3 fields"));
+                }
+                let activity = crate::presence::Activity::parse(parts[0]).ok_or_else(|| wire::invalid(format!("unknown activity {:?}", parts[0])))?;
+                let conversation_id = wire::conversation_id(parts[1])?;
+                let sender = wire::name(parts[2])?;
+                Ok(Some(Message::Activity { conversation_id, sender, activity }))
+            },
+            b"VOTE:" => {
+                // value|message_id|voted_at_ms|voter — the voter may itself contain '|'
+                let content = wire::utf8(&data, "vote")?;
+                let parts: Vec<&str> = content.splitn(4, '|').collect();
+                if parts.len() != 4 {
+                    return Err(wire::invalid("vote needs 4 fields"));
+                }
+                let value = match parts[0] {
+                    "1" => 1,
+                    "0" => 0,
+                    "-1" => -1,
+                    other => return Err(wire::invalid(format!("illegal vote value {:?}", other))),
+                };
+                let message_id = wire::message_id(parts[1])?;
+                let voted_at_ms = i64::try_from(wire::parse_u64(parts[2], "vote time")?).map_err(|_| wire::invalid("vote time out of range"))?;
+                let voter = wire::name(parts[3])?;
+                Ok(Some(Message::Vote { message_id, voter, value, voted_at_ms }))
+            },
+            b"DELF:" => {
+                let filename = wire::filename(&wire::utf8(&data, "filename")?)?;
+                Ok(Some(Message::FileDeleted { filename }))
+            },
+            b"PING:" | b"PONG:" => {
+                let token = wire::parse_u64(&wire::utf8(&data, "token")?, "token")?;
+                Ok(Some(if marker == b"PING:" { Message::Ping { token } } else { Message::Pong { token } }))
+            },
+            b"DIAQ:" => {
+                let content = wire::utf8(&data, "diagnostics request")?;
+                let (request_id, limit) = content.split_once('|').ok_or_else(|| wire::invalid("diagnostics request needs id|limit"))?;
+                Ok(Some(Message::DiagnosticsRequest {
+                    request_id: wire::parse_u64(request_id, "request id")?,
+                    limit: wire::parse_u32(limit, "event limit")?,
+                }))
+            },
+            b"DIAR:" => {
+                let (fields, report) = wire::split_fields(&data, 1)?;
+                let request_id = wire::parse_u64(fields[0], "request id")?;
+                let report = wire::utf8(report, "diagnostics report")?;
+                serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&report)
+                    .map_err(|_| wire::invalid("diagnostics report is not a JSON object"))?;
+                Ok(Some(Message::DiagnosticsResponse { request_id, report }))
+            },
+            b"SEQN:" => {
+                let (fields, inner) = wire::split_fields(&data, 1)?;
+                let seq = wire::parse_u64(fields[0], "sequence number")?;
+                if inner.len() < 5 {
+                    return Err(wire::invalid("sequenced frame too short"));
+                }
+                let mut marker = [0u8; 5];
+                marker.copy_from_slice(&inner[..5]);
+                if !matches!(&marker, b"FILE:" | b"FMTA:" | b"FMT2:" | b"CHNK:") {
+                    return Err(wire::invalid(format!("{} frames are not sequenced", String::from_utf8_lossy(&marker))));
+                }
+                if inner.len() - 5 > max_payload_size(&marker) {
+                    return Err(wire::invalid("sequenced frame too large"));
+                }
+                Ok(Self::decode(&marker, inner[5..].to_vec())?.map(|message| Message::Sequenced { seq, message: Box::new(message) }))
+            },
+            b"ACKN:" => {
+                let seq = wire::parse_u64(&wire::utf8(&data, "acknowledgement")?, "sequence number")?;
+                Ok(Some(Message::Ack { seq }))
+            },
+            b"NACK:" => {
+                let (fields, reason) = wire::split_fields(&data, 1)?;
+                let seq = wire::parse_u64(fields[0], "sequence number")?;
+                Ok(Some(Message::Nack { seq, reason: wire::utf8(reason, "reason")? }))
+            },
+            b"FLSQ:" => {
+                let request_id = wire::parse_u64(&wire::utf8(&data, "file list request")?, "request id")?;
+                Ok(Some(Message::FileListRequest { request_id }))
+            },
+            b"FLSR:" => {
+                let (fields, content) = wire::split_fields(&data, 1)?;
+                let request_id = wire::parse_u64(fields[0], "request id")?;
+                let files: Vec<FileInfo> = serde_json::from_slice(content)?;
+                if files.len() > crate::catalog::MAX_FILES {
+                    return Err(wire::invalid("too many files in file list"));
+                }
+                for file in &files {
+                    wire::filename(&file.filename)?;
+                    file.uploader_ip.parse::<std::net::IpAddr>().map_err(|_| wire::invalid("invalid uploader address"))?;
+                }
+                Ok(Some(Message::FileListResponse { request_id, files }))
+            },
+            b"MESH:" => {
+                let list: crate::topology::PeerList = serde_json::from_slice(&data)?;
+                wire::name(&list.node_id)?;
+                wire::name(&list.hostname)?;
+                if list.peers.len() > crate::topology::MAX_LINKS {
+                    return Err(wire::invalid(format!("peer list has {} entries", list.peers.len())));
+                }
+                if let Some(bad) = list.peers.iter().find(|p| p.ip.parse::<std::net::IpAddr>().is_err()) {
+                    return Err(wire::invalid(format!("peer list entry {:?} is not an IP address", bad.ip)));
+                }
+                Ok(Some(Message::PeerList(list)))
+            },
+            b"TIMQ:" => {
+                let origin_ms = wire::parse_u64(&wire::utf8(&data, "time")?, "time")?;
+                Ok(Some(Message::TimeRequest { origin_ms: i64::try_from(origin_ms).map_err(|_| wire::invalid("time out of range"))? }))
+            },
+            b"TIMR:" => {
+                let content = wire::utf8(&data, "time reply")?;
+                let (origin, peer) = content.split_once('|').ok_or_else(|| wire::invalid("time reply needs origin|time"))?;
+                let ms = |s: &str| i64::try_from(wire::parse_u64(s, "time")?).map_err(|_| wire::invalid("time out of range"));
+                Ok(Some(Message::TimeReply { origin_ms: ms(origin)?, peer_ms: ms(peer)? }))
+            },
+            b"LOAD:" => {
+                let load: crate::telemetry::ComputeLoad = serde_json::from_slice(&data)?;
+                load.validate().map_err(wire::invalid)?;
+                for gpu in &load.gpus {
+                    wire::name(&gpu.name)?;
+                }
+                Ok(Some(Message::ComputeLoad(load)))
+            },
+            b"MNTN:" => {
+                let window: Option<crate::maintenance::Window> = serde_json::from_slice(&data)?;
+                if let Some(window) = &window {
+                    window.validate().map_err(wire::invalid)?;
+                }
+                Ok(Some(Message::Maintenance(window)))
+            },
+            b"GBYE:" => {
+                let goodbye: crate::maintenance::Goodbye = serde_json::from_slice(&data)?;
+                crate::maintenance::validate_note(&goodbye.note).map_err(wire::invalid)?;
+                Ok(Some(Message::Goodbye(goodbye)))
+            },
+            b"GRPS:" => {
+                let groups: Vec<String> = serde_json::from_slice(&data)?;
+                crate::groups::validate_list(&groups).map_err(wire::invalid)?;
+                Ok(Some(Message::Groups(groups)))
+            },
+            b"KNOW:" => {
+                let entries: Vec<crate::knowledge::Entry> = serde_json::from_slice(&data)?;
+                crate::knowledge::validate_batch(&entries).map_err(wire::invalid)?;
+                Ok(Some(Message::Knowledge(entries)))
+            },
+            _ => Err(wire::invalid("unknown message type")),
+        }
+    }
+}
+
+// Stray bytes tolerated while looking for the next frame marker before the stream is considered lost
+const RESYNC_LIMIT: usize = 64 * 1024;
+
+// Read one frame (marker + length + payload). Returns None on a clean EOF.
+async fn read_frame(stream: &mut TcpStream) -> std::io::Result<Option<([u8; 5], Vec<u8>)>> {
+    let mut marker = [0u8; 5];
+
+    // Read marker with timeout
+    match tokio::time::timeout(crate::power::interval(IDLE_TIMEOUT), stream.read_exact(&mut marker)).await {
+        Ok(Ok(_)) => (),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout reading marker")),
+    }
+
+    // Resynchronize on garbage by sliding one byte at a time until something marker-shaped appears
+    let mut skipped = 0usize;
+    while !wire::is_marker_shaped(&marker) {
+        if skipped >= RESYNC_LIMIT {
+            return Err(wire::invalid("lost frame synchronization"));
+        }
+        let mut next = [0u8; 1];
+        match tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut next)).await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout reading marker")),
+        }
+        marker.rotate_left(1);
+        marker[4] = next[0];
+        skipped += 1;
+    }
+    if skipped > 0 {
+        eprintln!("TCP: Skipped {} stray bytes before {} frame", skipped, String::from_utf8_lossy(&marker));
+    }
+
+    // Read length with timeout
+    let mut len_bytes = [0u8; 8];
+    match tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut len_bytes)).await {
+        Ok(Ok(_)) => (),
+        Ok(Err(e)) => {
+            eprintln!("TCP: Failed to read message length: {}", e);
+            return Err(e);
+        }
+        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout reading length")),
+    }
+
+    let len = u64::from_le_bytes(len_bytes);
+    let limit = max_payload_size(&marker);
+    // A newer peer's frame type: drain it so the stream stays in sync, and let receive() skip it
+    if !is_known_marker(&marker) && len <= limit as u64 {
+        let mut remaining = len;
+        let mut sink = vec![0u8; 8192];
+        while remaining > 0 {
+            let n = remaining.min(sink.len() as u64) as usize;
+            match tokio::time::timeout(Duration::from_secs(30), stream.read_exact(&mut sink[..n])).await {
+                Ok(Ok(_)) => remaining -= n as u64,
+                Ok(Err(e)) => return Err(e),
+                Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout skipping frame")),
+            }
+        }
+        eprintln!("TCP: Skipped unsupported {} frame ({} bytes)", String::from_utf8_lossy(&marker), len);
+        return Ok(Some((marker, Vec::new())));
+    }
+    if len > limit as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} message too large: {} bytes (limit {})", String::from_utf8_lossy(&marker), len, limit)
+        ));
+    }
+    let len = len as usize;
+
+    // Read data in chunks with timeout
+    let mut data = Vec::with_capacity(len);
+    let mut remaining = len;
+    const CHUNK_SIZE: usize = 8192;
+
+    while remaining > 0 {
+        let chunk_size = remaining.min(CHUNK_SIZE);
+        let mut chunk = vec![0u8; chunk_size];
+
+        match tokio::time::timeout(Duration::from_secs(30), stream.read_exact(&mut chunk)).await {
+            Ok(Ok(_)) => {
+                data.extend_from_slice(&chunk);
+                remaining -= chunk_size;
+            }
+            Ok(Err(e)) => {
+                eprintln!("TCP: Failed to read chunk: {}", e);
+                return Err(e);
+            }
+            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Timeout reading chunk")),
+        }
+    }
+
+    Ok(Some((marker, data)))
+}
+
+// Whether this node's LLM backend is up and reachable from other machines
+pub async fn is_llm_available() -> bool {
+    use crate::llm::backend::LlmBackend;
+    let backend = crate::llm::backend::configured().await;
+    if !backend.available().await {
+        return false;
+    }
+
+    // A backend on this machine must also listen beyond loopback
+    let port = match reqwest::Url::parse(backend.base_url()) {
+        Ok(url) if url.host_str().is_some_and(|h| h == "localhost" || h.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())) => url.port_or_known_default(),
+        _ => return true,
+    };
+    let Some(port) = port else {
+        return true;
+    };
+    let local_addr = match tokio::net::TcpStream::connect(format!("127.0.0.1:{}", port)).await {
+        Ok(stream) => stream.local_addr().ok(),
+        Err(_) => None,
+    };
+
+    if let Some(addr) = local_addr {
+        // Try to connect using the external IP
+        match tokio::net::TcpStream::connect(format!("{}:{}", addr.ip(), port)).await {
+            Ok(_) => {
+                println!("TCP: LLM backend ({}) is accessible externally", backend.name());
+                true
+            },
+            Err(e) => {
+                println!("TCP: LLM backend ({}) is not accessible externally: {}", backend.name(), e);
+                println!("TCP: Please configure it to listen on 0.0.0.0 (for Ollama, set OLLAMA_HOST=0.0.0.0 in the environment)");
+                false
+            }
+        }
+    } else {
+        false
+    }
+}
+
+pub async fn listen_for_connections() -> std::io::Result<()> {
+    // Create received directory if it doesn't exist
+    let received_path = Path::new(RECEIVED_DIR);
+    if !received_path.exists() {
+        fs::create_dir_all(received_path).await?;
+    }
+
+    let listener = TcpListener::bind(format!("{}:{}", crate::ip::listen_host(), PORT)).await?;
+    println!("TCP: Listening on port {}", PORT);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        if !ADMISSION.lock().unwrap().accept(Instant::now()) {
+            drop(stream);
+            continue;
+        }
+        println!("TCP: New connection from {}", addr);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream).await {
+                eprintln!("TCP: Connection error with {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+// Add this new function for periodic conversation sharing
+async fn periodic_conversation_share(mut stream: TcpStream, addr: std::net::SocketAddr) {
+    let peer_ip = addr.ip().to_string();
+    let mut interval = tokio::time::interval(crate::power::interval(SYNC_INTERVAL));
     
     loop {
         interval.tick().await;
         
-        // Check if we still have a valid connection
-        if let Err(_) = stream.write_all(&[0u8]).await {
+        // Check if we still have a valid connection; the Pong gives a round-trip sample
+        let token = rand::random();
+        crate::latency::ping_sent(&addr.ip().to_string(), token).await;
+        if (Message::Ping { token }).send(&mut stream).await.is_err() {
             println!("TCP: Lost connection to {} during periodic share", addr);
             break;
         }
+
+        // Our own connections, for the peer's mesh map
+        let report = crate::topology::local_report().await;
+        let llm_host = report.llm_host;
+        if let Err(e) = Message::PeerList(report).send(&mut stream).await {
+            eprintln!("TCP: Periodic share - Failed to send peer list to {}: {}", addr, e);
+            break;
+        }
+        if llm_host {
+            if let Err(e) = Message::ComputeLoad(crate::telemetry::local()).send(&mut stream).await {
+                eprintln!("TCP: Periodic share - Failed to send compute load to {}: {}", addr, e);
+                break;
+            }
+        }
+        if let Err(e) = (Message::TimeRequest { origin_ms: crate::clock::now_ms() }).send(&mut stream).await {
+            eprintln!("TCP: Periodic share - Failed to send time request to {}: {}", addr, e);
+            break;
+        }
         
         // Share our local conversation
-        if let Some(conversation) = CONVERSATION_STORE.get_local_conversation().await {
+        if let Some(conversation) = local_conversation_for(&peer_ip).await {
             match serde_json::to_string(&conversation) {
                 Ok(content) => {
                     let message = Message::ConversationFile {
@@ -555,8 +2255,24 @@ async fn periodic_conversation_share(mut stream: TcpStream, addr: std::net::Sock
             }
         }
 
+        // Forks sync the same way as the main conversation
+        for fork in CONVERSATION_STORE.get_forks().await.into_iter().filter(|f| f.privacy.is_shared() && shared_with(f, &peer_ip)) {
+            let content = match serde_json::to_string(&crate::redact::for_peers(&fork).await) {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("TCP: Periodic share - Failed to serialize fork {}: {}", fork.id, e);
+                    continue;
+                }
+            };
+            let message = Message::ConversationFile { name: format!("{}.json", fork.id), content };
+            if let Err(e) = message.send(&mut stream).await {
+                eprintln!("TCP: Periodic share - Failed to send fork {} to {}: {}", fork.id, addr, e);
+                return;
+            }
+        }
+
         // Request sync from peer to ensure we have their latest conversation
-        let sync_request = Message::SyncRequest;
+        let sync_request = Message::SyncRequest(None);
         if let Err(e) = sync_request.send(&mut stream).await {
             eprintln!("TCP: Periodic share - Failed to send sync request to {}: {}", addr, e);
             break;
@@ -566,7 +2282,27 @@ async fn periodic_conversation_share(mut stream: TcpStream, addr: std::net::Sock
 
 async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
     let addr = stream.peer_addr()?;
-    println!("TCP: Connected to {}", addr);
+    let capabilities = match authenticate_peer(&mut stream).await {
+        Ok(capabilities) => capabilities,
+        Err(e) => {
+            events::warn(Category::Security, format!("Rejected unauthenticated connection from {}: {}", addr, e), serde_json::json!({ "peer": addr.ip().to_string() }));
+            return Ok(());
+        }
+    };
+    if !admit_inbound(&addr.ip().to_string()) {
+        println!("TCP: Peer limit reached, closing the connection from {}", addr.ip());
+        return Ok(());
+    }
+    let (mut stream, session) = match open_session(&addr.ip().to_string(), session::Direction::Inbound, stream, None, capabilities)? {
+        (stream, Some(session)) => (stream, session),
+        (_, None) => {
+            println!("TCP: Already connected to {}, closing its duplicate connection", addr.ip());
+            return Ok(());
+        }
+    };
+    events::info(Category::Peer, format!("Connected to {} (inbound)", addr), serde_json::json!({ "peer": addr.ip().to_string(), "direction": "inbound" }));
+    events::publish("peer", serde_json::json!({ "event": "connected", "ip": addr.ip().to_string(), "direction": "inbound" }));
+    crate::maintenance::peer_connected(&addr.ip().to_string());
 
     // Create received directory if it doesn't exist
     let received_path = Path::new(RECEIVED_DIR);
@@ -584,8 +2320,8 @@ async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
     let local_addr = stream.local_addr()?;
     let local_ip = local_addr.ip().to_string();
 
-    // Check Ollama availability before sending capability
-    let has_llm = is_ollama_available().await;
+    // Check LLM backend availability before sending capability
+    let has_llm = is_llm_available().await;
     
     // Send our LLM capability immediately
     if let Err(e) = (Message::LLMCapability { has_llm }).send(&mut stream).await {
@@ -595,11 +2331,22 @@ async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
     if has_llm {
         println!("TCP: Announced LLM capability to {}", addr);
     } else {
-        println!("TCP: Announced no LLM capability to {} (Ollama not available)", addr);
+        println!("TCP: Announced no LLM capability to {} (LLM backend not available)", addr);
+    }
+
+    send_compression_offer(&mut stream, &addr.ip().to_string()).await?;
+    if let Err(e) = send_maintenance(&mut stream).await {
+        eprintln!("TCP: Failed to send maintenance notice to {}: {}", addr, e);
+    }
+    if let Err(e) = Message::Groups(crate::groups::local()).send(&mut stream).await {
+        eprintln!("TCP: Failed to send groups to {}: {}", addr, e);
+    }
+    if let Err(e) = send_knowledge(&mut stream).await {
+        eprintln!("TCP: Failed to send knowledge entries to {}: {}", addr, e);
     }
 
     // Share our local conversation immediately
-    if let Some(conversation) = CONVERSATION_STORE.get_local_conversation().await {
+    if let Some(conversation) = local_conversation_for(&addr.ip().to_string()).await {
         let content = serde_json::to_string(&conversation)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         
@@ -614,14 +2361,18 @@ async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
             println!("TCP: Sent local conversation to {}", addr);
             
             // Also save the conversation to the peer's directory
-            if let Err(e) = fs::write(peer_dir.join("local.json"), content).await {
+            if let Err(e) = crate::persistence::write_atomic(&peer_dir.join("local.json"), content.as_bytes()).await {
                 eprintln!("TCP: Failed to save conversation for {}: {}", addr, e);
             }
         }
     }
+    // Broadcast conversations reach a joining peer at once, forks and relayed ones included
+    if let Err(e) = send_broadcast_conversations(&mut stream, &addr.ip().to_string()).await {
+        eprintln!("TCP: Failed to send broadcast conversations to {}: {}", addr, e);
+    }
 
     // Before entering the main loop, clone the socket so we have a dedicated writable stream
-    // to use for broadcasts. Store it in ACTIVE_STREAMS keyed by peer IP.
+    // to use for broadcasts. It goes into the peer's registry entry.
     let std_socket = match stream.into_std() {
         Ok(socket) => socket,
         Err(e) => {
@@ -653,8 +2404,7 @@ async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
     let peer_ip_key = addr.ip().to_string();
     match TcpStream::from_std(std_socket_for_broadcast) {
         Ok(bstream) => {
-            let mut map = ACTIVE_STREAMS.lock().await;
-            map.insert(peer_ip_key.clone(), bstream);
+            REGISTRY.lock().unwrap().set_writer(&peer_ip_key, session.id, bstream);
         }
         Err(e) => {
             eprintln!("TCP: Failed to create broadcast stream for {}: {}", addr, e);
@@ -665,31 +2415,110 @@ async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
     loop {
         match Message::receive(&mut stream).await {
             Ok(Some(message)) => {
+                // A sequenced frame is answered once it has been handled
+                let (mut seq, message) = message.unsequenced();
+                let mut handled = Ok(());
                 match message {
                     Message::ConversationFile { name, content } => {
-                        let file_path = peer_dir.join(&name);
-                        if let Err(e) = fs::write(&file_path, content.as_bytes()).await {
-                            eprintln!("TCP: Failed to save received file {}: {}", name, e);
-                        } else {
-                            println!("TCP: Received and saved conversation file {} from {}", name, addr);
-                            if let Ok(conversation) = serde_json::from_str::<Conversation>(&content) {
-                                CONVERSATION_STORE.add_peer_conversation(addr.ip().to_string(), conversation).await;
-                            }
+                        handled = receive_conversation_file(&addr.ip().to_string(), &peer_dir, &name, &content).await;
+                    }
+                    Message::Ack { seq } => {
+                        delivery_answered(&addr.ip().to_string(), seq, Ok(())).await;
+                    }
+                    Message::Nack { seq, reason } => {
+                        delivery_answered(&addr.ip().to_string(), seq, Err(reason)).await;
+                    }
+                    Message::Version { crate_version, protocol_version, node_id } => {
+                        let info = version::peer_version(Some(crate_version), protocol_version, Some(node_id));
+                        record_peer_version(&addr.ip().to_string(), info).await;
+                    }
+                    Message::Activity { conversation_id, sender, activity } => {
+                        crate::presence::receive_from_peer(&addr.ip().to_string(), &conversation_id, &sender, activity).await;
+                    }
+                    Message::Vote { message_id, voter, value, voted_at_ms } => {
+                        let voted_at_ms = voted_at_ms + crate::clock::correction_ms(&addr.ip().to_string()).await.unwrap_or(0);
+                        crate::votes::receive_from_peer(&addr.ip().to_string(), &message_id, &voter, value, voted_at_ms).await;
+                    }
+                    Message::FileDeleted { filename } => {
+                        forget_announced_file(&addr.ip().to_string(), &filename).await;
+                    }
+                    Message::Ping { token } => {
+                        if let Err(e) = (Message::Pong { token }).send(&mut stream).await {
+                            eprintln!("TCP: Failed to answer ping from {}: {}", addr, e);
+                        }
+                    }
+                    Message::Pong { token } => {
+                        crate::latency::pong_received(&addr.ip().to_string(), token).await;
+                    }
+                    Message::DiagnosticsRequest { request_id, limit } => {
+                        let report = crate::diagnostics::report(&addr.ip().to_string(), limit).await;
+                        if let Err(e) = (Message::DiagnosticsResponse { request_id, report }).send(&mut stream).await {
+                            eprintln!("TCP: Failed to answer diagnostics request from {}: {}", addr, e);
+                        }
+                    }
+                    Message::DiagnosticsResponse { request_id, report } => {
+                        crate::diagnostics::response_received(&addr.ip().to_string(), request_id, report).await;
+                    }
+                    Message::PeerList(list) => {
+                        crate::topology::received(&addr.ip().to_string(), list).await;
+                    }
+                    Message::FileListRequest { request_id } => {
+                        if let Err(e) = answer_file_list_request(&mut stream, &addr.ip().to_string(), request_id).await {
+                            eprintln!("TCP: Failed to answer file list request from {}: {}", addr, e);
+                        }
+                    }
+                    Message::FileListResponse { request_id, files } => {
+                        crate::catalog::response_received(&addr.ip().to_string(), request_id, files).await;
+                    }
+                    Message::SyncRequest(scope) => {
+                        if let Err(e) = answer_sync_request(&mut stream, &addr.ip().to_string(), scope).await {
+                            eprintln!("TCP: Failed to answer sync request from {}: {}", addr, e);
+                        }
+                    }
+                    Message::SyncResponse(conversations) => {
+                        receive_sync_response(&addr.ip().to_string(), conversations).await;
+                    }
+                    Message::ConversationSince { request_id, conversation } => {
+                        receive_conversation_since(&addr.ip().to_string(), request_id, conversation).await;
+                    }
+                    Message::ComputeLoad(load) => {
+                        crate::telemetry::received(&addr.ip().to_string(), load).await;
+                    }
+                    Message::Maintenance(window) => {
+                        crate::maintenance::window_received(&addr.ip().to_string(), window).await;
+                    }
+                    Message::Goodbye(goodbye) => {
+                        peer_left(&addr.ip().to_string(), goodbye).await;
+                        break;
+                    }
+                    Message::Groups(groups) => {
+                        crate::groups::peer_groups_received(&addr.ip().to_string(), groups);
+                    }
+                    Message::Knowledge(entries) => {
+                        crate::knowledge::received(&addr.ip().to_string(), entries).await;
+                    }
+                    Message::TimeRequest { origin_ms } => {
+                        let reply = Message::TimeReply { origin_ms, peer_ms: crate::clock::now_ms() };
+                        if let Err(e) = reply.send(&mut stream).await {
+                            eprintln!("TCP: Failed to answer time request from {}: {}", addr, e);
                         }
                     }
+                    Message::TimeReply { origin_ms, peer_ms } => {
+                        crate::clock::reply_received(&addr.ip().to_string(), origin_ms, peer_ms).await;
+                    }
                     Message::LLMCapability { has_llm } => {
-                        let mut llm_peers = LLM_PEERS.lock().await;
+                        if let Some(peer) = REGISTRY.lock().unwrap().get_mut(&addr.ip().to_string()) {
+                            peer.has_llm = has_llm;
+                        }
                         if has_llm {
-                            llm_peers.insert(addr.ip().to_string());
                             println!("TCP: Peer {} has LLM capability", addr);
                         } else {
-                            llm_peers.remove(&addr.ip().to_string());
                             println!("TCP: Peer {} does not have LLM capability", addr);
                         }
                     }
                     Message::LLMAccessRequest { peer_name, reason } => {
                         println!("TCP: Received LLM access request from {} ({}): {}", addr, peer_name, reason);
-                        let has_llm = is_ollama_available().await;
+                        let has_llm = is_llm_available().await;
                         if has_llm {
                             // Use the local bind IP of this TCP socket so the peer can reach us
                             let lan_ip = local_ip.clone();
@@ -714,28 +2543,37 @@ async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
                             }
                         }
                     }
-                    Message::FileMeta { filename, file_type, file_size, sha256_hex: _, uploaded_at, hmac_hex: _ } => {
-                        // Store announced peer file so UI can show immediately
-                        let ts = match chrono::DateTime::parse_from_rfc3339(&uploaded_at) {
-                            Ok(dt) => dt.with_timezone(&chrono::Utc),
-                            Err(_) => chrono::Utc::now(),
-                        };
-                        let info = FileInfo {
-                            filename: filename.clone(),
-                            file_type: file_type.clone(),
-                            file_size: file_size,
-                            uploader_ip: addr.ip().to_string(),
-                            upload_time: ts,
-                        };
-                        add_announced_file(info).await;
+                    meta @ Message::FileMeta { .. } => {
+                        handled = handle_file_meta(&addr.ip().to_string(), meta).await;
+                    }
+                    Message::FileChunk { filename, chunk_index, total_chunks, content } => {
+                        handled = handle_file_chunk(&peer_dir, &addr.ip().to_string(), &filename, chunk_index, total_chunks, &content).await;
+                    }
+                    Message::FileMetaV2(meta) => {
+                        tokio::spawn(receive_file_meta_v2(peer_dir.clone(), addr.ip().to_string(), meta, seq.take()));
+                    }
+                    Message::CompressionOffer { algorithms } => {
+                        record_compression_offer(&addr.ip().to_string(), &algorithms);
                     }
                     Message::FileTransfer { filename, file_type, file_size: _, content } => {
+                        if !crate::settings::allow_peer_file(&addr.ip().to_string(), &filename, &file_type, content.len() as u64).await
+                            || !scan_received(&addr.ip().to_string(), &filename, &content).await
+                        {
+                            continue;
+                        }
                         // Save received binary content to peer dir
                         let out_path = peer_dir.join(&filename);
-                        if let Err(e) = fs::write(&out_path, &content).await {
+                        if let Err(e) = crate::persistence::write_atomic(&out_path, &content).await {
                             eprintln!("TCP: Failed to save received binary {} from {}: {}", filename, addr, e);
                         } else {
-                            println!("TCP: Saved received binary {} from {}", filename, addr);
+                            events::info(Category::File, format!("Saved {} ({} bytes) from {}", filename, content.len(), addr.ip()), serde_json::json!({ "peer": addr.ip().to_string(), "filename": filename, "bytes": content.len() }));
+                            events::publish("file", serde_json::json!({
+                                "event": "received",
+                                "filename": filename,
+                                "file_type": file_type,
+                                "bytes": content.len(),
+                                "peer": addr.ip().to_string()
+                            }));
                             // Ensure it appears in /api/files immediately even if FILE_META was missed
                             let info = FileInfo {
                                 filename: filename.clone(),
@@ -743,23 +2581,38 @@ async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
                                 file_size: content.len() as u64,
                                 uploader_ip: addr.ip().to_string(),
                                 upload_time: chrono::Utc::now(),
+                                tags: Vec::new(),
+                                sha256: Some(crate::persistence::sha256_hex(&content)),
+                                scan: None,
+                                owner: None,
+                                visibility: Default::default(),
+                                group: None,
                             };
                             add_announced_file(info).await;
                         }
                     }
                     _ => {}
                 }
+                if let Some(seq) = seq {
+                    if let Err(e) = delivery_answer(seq, handled).send(&mut stream).await {
+                        eprintln!("TCP: Failed to acknowledge frame {} from {}: {}", seq, addr, e);
+                    }
+                }
             }
+            Ok(None) if !session.is_current() => break,
+            Err(_) if !session.is_current() => break,
             Ok(None) => {
-                println!("TCP: Connection closed by {}", addr);
-                let mut map = ACTIVE_STREAMS.lock().await;
-                map.remove(&addr.ip().to_string());
+                events::info(Category::Peer, format!("Connection closed by {}", addr), serde_json::json!({ "peer": addr.ip().to_string() }));
+                session.end();
+                forget_peer_files(&addr.ip().to_string()).await;
+                events::publish("peer", serde_json::json!({ "event": "disconnected", "ip": addr.ip().to_string() }));
                 break;
             }
             Err(e) => {
-                eprintln!("TCP: Error reading from {}: {}", addr, e);
-                let mut map = ACTIVE_STREAMS.lock().await;
-                map.remove(&addr.ip().to_string());
+                events::warn(Category::Peer, format!("Lost connection to {}: {}", addr, e), serde_json::json!({ "peer": addr.ip().to_string() }));
+                session.end();
+                forget_peer_files(&addr.ip().to_string()).await;
+                events::publish("peer", serde_json::json!({ "event": "disconnected", "ip": addr.ip().to_string() }));
                 break;
             }
         }
@@ -768,282 +2621,558 @@ async fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
     Ok(())
 }
 
+// Outgoing peer connection, made from BIND_IP when set so the peer sees our address
+async fn connect_peer(addr: &str) -> std::io::Result<TcpStream> {
+    let bind_ip = match crate::ip::bind_ip() {
+        Some(ip) => ip,
+        None => return TcpStream::connect(addr).await,
+    };
+    let target = tokio::net::lookup_host(addr)
+        .await?
+        .find(|a| a.is_ipv4())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::AddrNotAvailable, format!("no IPv4 address for {}", addr)))?;
+    let socket = tokio::net::TcpSocket::new_v4()?;
+    socket.bind((bind_ip, 0).into())?;
+    socket.connect(target).await
+}
+
+// Dial peers found by discovery, each on its own task, and dial again those whose connection
+// failed or dropped once their backoff is over (see dialer)
 pub async fn connect_to_peers(received_ips: Arc<Mutex<HashSet<String>>>) {
+    let mut tick = tokio::time::interval(DIAL_TICK);
     loop {
-        let mut ips = received_ips.lock().await;
-        for ip in ips.drain() {
-            // Skip if we're already connected to this peer
-            let mut connected = CONNECTED_PEERS.lock().await;
-            if connected.contains(&ip) {
-                println!("TCP: Already connected to {}, skipping", ip);
-                continue;
+        tick.tick().await;
+        let discovered: Vec<String> = received_ips.lock().await.drain().collect();
+        let (due, in_flight) = {
+            let mut dialer = DIALER.lock().unwrap();
+            let now = Instant::now();
+            for ip in &discovered {
+                dialer.discovered(ip, now);
+            }
+            let due = dialer.due(now);
+            let in_flight: Vec<String> = dialer.connecting().into_iter().filter(|ip| !due.iter().any(|(d, _)| d == ip)).collect();
+            (due, in_flight)
+        };
+        // Sessions and attempts under way hold their places; the most wanted peers take what is left
+        let mut competing = REGISTRY.lock().unwrap().candidates();
+        for ip in in_flight {
+            if !competing.iter().any(|c| c.ip == ip) {
+                competing.push(candidate(&ip));
+            }
+        }
+        let mut ranked: Vec<(admission::Candidate, u64)> = due.into_iter().map(|(ip, attempt)| (candidate(&ip), attempt)).collect();
+        ranked.sort_by(|a, b| b.0.rank().cmp(&a.0.rank()).then_with(|| a.0.ip.cmp(&b.0.ip)));
+        for (peer, attempt) in ranked {
+            let verdict = ADMISSION.lock().unwrap().check(&competing, &peer, true);
+            match verdict {
+                admission::Verdict::Admit => {}
+                admission::Verdict::Evict(victim) => {
+                    println!("TCP: Peer limit reached, dropping {} to make room for {}", victim, peer.ip);
+                    REGISTRY.lock().unwrap().shutdown(&victim);
+                    competing.retain(|c| c.ip != victim);
+                }
+                admission::Verdict::Refuse => {
+                    DIALER.lock().unwrap().defer(&peer.ip, attempt, Instant::now() + admission::DEFER_TIME, "peer limit reached");
+                    continue;
+                }
+            }
+            if !competing.iter().any(|c| c.ip == peer.ip) {
+                competing.push(peer.clone());
+            }
+            tokio::spawn(dial_peer(Lease { ip: peer.ip, attempt, error: None }));
+        }
+    }
+}
+
+// A peer competing for a session, ranked by what we know of it before it connects
+fn candidate(ip: &str) -> admission::Candidate {
+    let last_active = ADMISSION.lock().unwrap().last_active(ip);
+    admission::Candidate { ip: ip.to_string(), llm_host: crate::udp::announced_llm(ip), last_active }
+}
+
+// Whether a peer that connected to us may have a session, closing a lower-ranked peer's to make
+// room (see admission)
+fn admit_inbound(ip: &str) -> bool {
+    let competing = REGISTRY.lock().unwrap().candidates();
+    let peer = candidate(ip);
+    let verdict = ADMISSION.lock().unwrap().check(&competing, &peer, false);
+    match verdict {
+        admission::Verdict::Admit => true,
+        admission::Verdict::Evict(victim) => {
+            println!("TCP: Peer limit reached, dropping {} to make room for {}", victim, ip);
+            REGISTRY.lock().unwrap().shutdown(&victim);
+            true
+        }
+        admission::Verdict::Refuse => false,
+    }
+}
+
+// An outbound connection attempt's hold on the peer's dial state, settled when it is dropped
+struct Lease {
+    ip: String,
+    attempt: u64,
+    error: Option<String>,
+}
+
+impl Lease {
+    fn connected(&self) {
+        DIALER.lock().unwrap().connected(&self.ip, self.attempt, Instant::now());
+    }
+
+    fn fail(&mut self, error: impl std::fmt::Display) {
+        self.error = Some(error.to_string());
+    }
+
+    fn covered(&self) {
+        DIALER.lock().unwrap().covered(&self.ip, self.attempt, Instant::now());
+    }
+}
+
+// A connection's place as its peer's session, given up when dropped
+struct SessionGuard {
+    ip: String,
+    id: u64,
+}
+
+impl SessionGuard {
+    // Whether this is still the peer's session; a replaced one must leave the peer's state alone
+    fn is_current(&self) -> bool {
+        REGISTRY.lock().unwrap().is_current(&self.ip, self.id)
+    }
+
+    // The peer is gone; its registry entry goes with the session
+    fn end(&self) {
+        REGISTRY.lock().unwrap().close(&self.ip, self.id);
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.end();
+        ADMISSION.lock().unwrap().session_ended(&self.ip, Instant::now());
+    }
+}
+
+// Make the connection the peer's session, or None when the peer's current one is kept instead.
+// The session uses the optional features both sides have.
+fn open_session(
+    peer_ip: &str,
+    direction: session::Direction,
+    stream: TcpStream,
+    peer_id: Option<&str>,
+    capabilities: Capabilities,
+) -> std::io::Result<(TcpStream, Option<SessionGuard>)> {
+    let socket = stream.into_std()?;
+    let handle = socket.try_clone()?;
+    let stream = TcpStream::from_std(socket)?;
+    let mut registry = REGISTRY.lock().unwrap();
+    let id = registry.open(peer_ip, direction, crate::persistence::node_id(), peer_id, Some(handle));
+    if let (Some(_), Some(peer)) = (id, registry.get_mut(peer_ip)) {
+        peer.capabilities = Capabilities::local().common(capabilities);
+    }
+    Ok((stream, id.map(|id| SessionGuard { ip: peer_ip.to_string(), id })))
+}
+
+// The accepting side answers the handshake with VERS:, which tells us its node ID, and the
+// features it supports
+async fn read_version(stream: &mut TcpStream, peer_ip: &str) -> std::io::Result<(String, Capabilities)> {
+    let message = match tokio::time::timeout(auth::HANDSHAKE_TIMEOUT, Message::receive(stream)).await {
+        Ok(message) => message?,
+        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "no version received")),
+    };
+    match message {
+        Some(Message::Version { crate_version, protocol_version, node_id }) => {
+            record_peer_version(peer_ip, version::peer_version(Some(crate_version), protocol_version, Some(node_id.clone()))).await;
+            Ok((node_id, read_capabilities(stream, protocol_version).await?))
+        }
+        Some(_) => Err(wire::invalid("expected VERS: after the handshake")),
+        None => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "closed during the handshake")),
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let spread = rand::random::<f64>() * 2.0 - 1.0;
+        DIALER.lock().unwrap().ended(&self.ip, self.attempt, self.error.take(), Instant::now(), spread);
+    }
+}
+
+async fn dial_peer(mut lease: Lease) {
+    let ip = lease.ip.clone();
+    if REGISTRY.lock().unwrap().has(&ip) {
+        lease.covered();
+        return;
+    }
+    let addr = format!("{}:{}", ip, PORT);
+    match connect_peer(&addr).await {
+        Ok(mut stream) => {
+            if let Err(e) = send_handshake(&mut stream, &ip).await {
+                events::warn(Category::Peer, format!("Failed to send handshake to {}: {}", addr, e), serde_json::json!({ "peer": ip }));
+                lease.fail(e);
+                return;
             }
-            connected.insert(ip.clone());
-            drop(connected);
+            let (peer_id, capabilities) = match read_version(&mut stream, &ip).await {
+                Ok(answer) => answer,
+                Err(e) => {
+                    events::warn(Category::Peer, format!("Handshake with {} failed: {}", addr, e), serde_json::json!({ "peer": ip }));
+                    lease.fail(e);
+                    return;
+                }
+            };
+            let (mut stream, session) = match open_session(&ip, session::Direction::Outbound, stream, Some(&peer_id), capabilities) {
+                Ok((stream, Some(session))) => (stream, session),
+                Ok((_, None)) => {
+                    println!("TCP: {} is already connected to us, closing our duplicate connection", ip);
+                    lease.covered();
+                    return;
+                }
+                Err(e) => {
+                    lease.fail(e);
+                    return;
+                }
+            };
+            events::info(Category::Peer, format!("Connected to {} (outbound)", addr), serde_json::json!({ "peer": ip, "direction": "outbound" }));
+            events::publish("peer", serde_json::json!({ "event": "connected", "ip": ip, "direction": "outbound" }));
+            crate::maintenance::peer_connected(&ip);
+            lease.connected();
             
-            let addr = format!("{}:{}", ip, PORT);
-            match TcpStream::connect(&addr).await {
-                Ok(mut stream) => {
-                    println!("TCP: Connected to {}", addr);
-                    
-                    // Create received directory if it doesn't exist
-                    let received_path = Path::new(RECEIVED_DIR);
-                    if !received_path.exists() {
-                        if let Err(e) = fs::create_dir_all(received_path).await {
-                            eprintln!("TCP: Failed to create received directory: {}", e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        }
-                    }
+            // Create received directory if it doesn't exist
+            let received_path = Path::new(RECEIVED_DIR);
+            if !received_path.exists() {
+                if let Err(e) = fs::create_dir_all(received_path).await {
+                    eprintln!("TCP: Failed to create received directory: {}", e);
+                    return;
+                }
+            }
 
-                    // Create a directory for this peer's conversations
-                    let peer_dir = received_path.join(&ip);
-                    if !peer_dir.exists() {
-                        if let Err(e) = fs::create_dir_all(&peer_dir).await {
-                            eprintln!("TCP: Failed to create peer directory: {}", e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        }
-                    }
-                    
-                    // Check Ollama availability before sending capability
-                    let has_llm = is_ollama_available().await;
-                    
-                    // Send our LLM capability
-                    if let Err(e) = (Message::LLMCapability { has_llm }).send(&mut stream).await {
-                        eprintln!("TCP: Failed to send LLM capability to {}: {}", addr, e);
-                        let mut connected = CONNECTED_PEERS.lock().await;
-                        connected.remove(&ip);
-                        continue;
-                    }
+            // Create a directory for this peer's conversations
+            let peer_dir = received_path.join(&ip);
+            if !peer_dir.exists() {
+                if let Err(e) = fs::create_dir_all(&peer_dir).await {
+                    eprintln!("TCP: Failed to create peer directory: {}", e);
+                    return;
+                }
+            }
+            
+            // Check LLM backend availability before sending capability
+            let has_llm = is_llm_available().await;
+            
+            // Send our LLM capability
+            if let Err(e) = (Message::LLMCapability { has_llm }).send(&mut stream).await {
+                eprintln!("TCP: Failed to send LLM capability to {}: {}", addr, e);
+                return;
+            }
 
-                    if has_llm {
-                        println!("TCP: Announced LLM capability to {}", addr);
-                    } else {
-                        println!("TCP: Announced no LLM capability to {} (Ollama not available)", addr);
-                    }
+            if has_llm {
+                println!("TCP: Announced LLM capability to {}", addr);
+            } else {
+                println!("TCP: Announced no LLM capability to {} (LLM backend not available)", addr);
+            }
 
-                    // Share our local conversation
-                    if let Some(conversation) = CONVERSATION_STORE.get_local_conversation().await {
-                        let content = match serde_json::to_string(&conversation) {
-                            Ok(content) => content,
-                            Err(e) => {
-                                eprintln!("TCP: Failed to serialize conversation: {}", e);
-                                let mut connected = CONNECTED_PEERS.lock().await;
-                                connected.remove(&ip);
-                                continue;
-                            }
-                        };
-                        
-                        let message = Message::ConversationFile {
-                            name: "local.json".to_string(),
-                            content,
-                        };
-                        
-                        if let Err(e) = message.send(&mut stream).await {
-                            eprintln!("TCP: Failed to send local conversation to {}: {}", addr, e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        } else {
-                            println!("TCP: Sent local conversation to {}", addr);
-                        }
+            if let Err(e) = send_compression_offer(&mut stream, &ip).await {
+                eprintln!("TCP: Failed to send compression offer to {}: {}", addr, e);
+                return;
+            }
+            if let Err(e) = send_maintenance(&mut stream).await {
+                eprintln!("TCP: Failed to send maintenance notice to {}: {}", addr, e);
+            }
+            if let Err(e) = Message::Groups(crate::groups::local()).send(&mut stream).await {
+                eprintln!("TCP: Failed to send groups to {}: {}", addr, e);
+            }
+            if let Err(e) = send_knowledge(&mut stream).await {
+                eprintln!("TCP: Failed to send knowledge entries to {}: {}", addr, e);
+            }
+
+            // Share our local conversation
+            if let Some(conversation) = local_conversation_for(&ip).await {
+                let content = match serde_json::to_string(&conversation) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        eprintln!("TCP: Failed to serialize conversation: {}", e);
+                        return;
                     }
+                };
+                
+                let message = Message::ConversationFile {
+                    name: "local.json".to_string(),
+                    content,
+                };
+                
+                if let Err(e) = message.send(&mut stream).await {
+                    eprintln!("TCP: Failed to send local conversation to {}: {}", addr, e);
+                    return;
+                } else {
+                    println!("TCP: Sent local conversation to {}", addr);
+                }
+            }
+            // Broadcast conversations reach a joining peer at once, forks and relayed ones included
+            if let Err(e) = send_broadcast_conversations(&mut stream, &ip).await {
+                eprintln!("TCP: Failed to send broadcast conversations to {}: {}", addr, e);
+            }
 
-                    // Register a dedicated writable stream for broadcasts by cloning the std socket
-                    let std_socket = match stream.into_std() {
-                        Ok(s) => s,
-                        Err(e) => {
-                            eprintln!("TCP: Failed to get std socket for {}: {}", addr, e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        }
-                    };
+            // Register a dedicated writable stream for broadcasts by cloning the std socket
+            let std_socket = match stream.into_std() {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("TCP: Failed to get std socket for {}: {}", addr, e);
+                    return;
+                }
+            };
 
-                    // One clone for periodic sharing, one for main handler, one for broadcasting
-                    let share_socket = match std_socket.try_clone() {
-                        Ok(s) => s,
-                        Err(e) => {
-                            eprintln!("TCP: Failed to clone share socket for {}: {}", addr, e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        }
-                    };
-                    let handler_socket = match std_socket.try_clone() {
-                        Ok(s) => s,
-                        Err(e) => {
-                            eprintln!("TCP: Failed to clone handler socket for {}: {}", addr, e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        }
-                    };
-                    let broadcast_socket = match std_socket.try_clone() {
-                        Ok(s) => s,
-                        Err(e) => {
-                            eprintln!("TCP: Failed to clone broadcast socket for {}: {}", addr, e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        }
-                    };
+            // One clone for periodic sharing, one for main handler, one for broadcasting
+            let share_socket = match std_socket.try_clone() {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("TCP: Failed to clone share socket for {}: {}", addr, e);
+                    return;
+                }
+            };
+            let handler_socket = match std_socket.try_clone() {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("TCP: Failed to clone handler socket for {}: {}", addr, e);
+                    return;
+                }
+            };
+            let broadcast_socket = match std_socket.try_clone() {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("TCP: Failed to clone broadcast socket for {}: {}", addr, e);
+                    return;
+                }
+            };
 
-                    let mut stream = match TcpStream::from_std(handler_socket) {
-                        Ok(s) => s,
-                        Err(e) => {
-                            eprintln!("TCP: Failed to make tokio handler stream for {}: {}", addr, e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        }
-                    };
-                    let share_stream = match TcpStream::from_std(share_socket) {
-                        Ok(s) => s,
-                        Err(e) => {
-                            eprintln!("TCP: Failed to make tokio share stream for {}: {}", addr, e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
-                            continue;
-                        }
-                    };
-                    match TcpStream::from_std(broadcast_socket) {
-                        Ok(bstream) => {
-                            let mut map = ACTIVE_STREAMS.lock().await;
-                            map.insert(ip.clone(), bstream);
-                        }
-                        Err(e) => eprintln!("TCP: Failed to make tokio broadcast stream for {}: {}", addr, e),
-                    }
+            let mut stream = match TcpStream::from_std(handler_socket) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("TCP: Failed to make tokio handler stream for {}: {}", addr, e);
+                    return;
+                }
+            };
+            let share_stream = match TcpStream::from_std(share_socket) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("TCP: Failed to make tokio share stream for {}: {}", addr, e);
+                    return;
+                }
+            };
+            match TcpStream::from_std(broadcast_socket) {
+                Ok(bstream) => {
+                    REGISTRY.lock().unwrap().set_writer(&ip, session.id, bstream);
+                }
+                Err(e) => eprintln!("TCP: Failed to make tokio broadcast stream for {}: {}", addr, e),
+            }
 
-                    // Set up periodic sharing
-                    match setup_periodic_sharing(share_stream, &addr, &ip).await {
-                        Ok((mut _unused, share_handle)) => {
-                            // Keep connection alive and handle messages
-                            loop {
-                                match Message::receive(&mut stream).await {
-                                    Ok(Some(message)) => {
-                                        match message {
-                                            Message::ConversationFile { name, content } => {
-                                                // Save the conversation in the peer's directory
-                                                let file_path = peer_dir.join(&name);
-                                                if let Err(e) = fs::write(&file_path, content.as_bytes()).await {
-                                                    eprintln!("TCP: Failed to save received file {}: {}", name, e);
-                                                } else {
-                                                    println!("TCP: Received and saved conversation file {} from {}", name, addr);
-                                                    
-                                                    // Try to load the received conversation
-                                                    if let Ok(conversation) = serde_json::from_str::<Conversation>(&content) {
-                                                        CONVERSATION_STORE.add_peer_conversation(ip.clone(), conversation).await;
-                                                    }
-                                                }
-                                            }
-                                            Message::LLMCapability { has_llm } => {
-                                                let mut llm_peers = LLM_PEERS.lock().await;
-                                                if has_llm {
-                                                    llm_peers.insert(ip.clone());
-                                                    println!("TCP: Peer {} has LLM capability", addr);
-                                                    
-                                                    // Check if we need to request access
-                                                    let authorized = AUTHORIZED_PEERS.lock().await;
-                                                    if !authorized.contains(&ip) {
-                                                        drop(authorized);
-                                                        drop(llm_peers);
-                                                        if let Err(e) = request_llm_access(&mut stream, &addr).await {
-                                                            eprintln!("TCP: Failed to request LLM access: {}", e);
-                                                            break;
-                                                        }
-                                                    }
-                                                } else {
-                                                    llm_peers.remove(&ip);
-                                                    println!("TCP: Peer {} does not have LLM capability", addr);
-                                                }
+            // Set up periodic sharing
+            match setup_periodic_sharing(share_stream, &addr).await {
+                Ok((mut _unused, share_handle)) => {
+                    // Keep connection alive and handle messages
+                    loop {
+                        match Message::receive(&mut stream).await {
+                            Ok(Some(message)) => {
+                                // A sequenced frame is answered once it has been handled
+                                let (mut seq, message) = message.unsequenced();
+                                let mut handled = Ok(());
+                                match message {
+                                    Message::ConversationFile { name, content } => {
+                                        // Save the conversation in the peer's directory
+                                        handled = receive_conversation_file(&ip, &peer_dir, &name, &content).await;
+                                    }
+                                    Message::Ack { seq } => {
+                                        delivery_answered(&ip, seq, Ok(())).await;
+                                    }
+                                    Message::Nack { seq, reason } => {
+                                        delivery_answered(&ip, seq, Err(reason)).await;
+                                    }
+                                    Message::Version { crate_version, protocol_version, node_id } => {
+                                        let info = version::peer_version(Some(crate_version), protocol_version, Some(node_id));
+                                        record_peer_version(&ip, info).await;
+                                    }
+                                    Message::Activity { conversation_id, sender, activity } => {
+                                        crate::presence::receive_from_peer(&ip, &conversation_id, &sender, activity).await;
+                                    }
+                                    Message::Vote { message_id, voter, value, voted_at_ms } => {
+                                        let voted_at_ms = voted_at_ms + crate::clock::correction_ms(&ip).await.unwrap_or(0);
+                                        crate::votes::receive_from_peer(&ip, &message_id, &voter, value, voted_at_ms).await;
+                                    }
+                                    Message::FileDeleted { filename } => {
+                                        forget_announced_file(&ip, &filename).await;
+                                    }
+                                    Message::Ping { token } => {
+                                        if let Err(e) = (Message::Pong { token }).send(&mut stream).await {
+                                            eprintln!("TCP: Failed to answer ping from {}: {}", addr, e);
+                                        }
+                                    }
+                                    Message::Pong { token } => {
+                                        crate::latency::pong_received(&ip, token).await;
+                                    }
+                                    Message::DiagnosticsRequest { request_id, limit } => {
+                                        let report = crate::diagnostics::report(&ip, limit).await;
+                                        if let Err(e) = (Message::DiagnosticsResponse { request_id, report }).send(&mut stream).await {
+                                            eprintln!("TCP: Failed to answer diagnostics request from {}: {}", addr, e);
+                                        }
+                                    }
+                                    Message::DiagnosticsResponse { request_id, report } => {
+                                        crate::diagnostics::response_received(&ip, request_id, report).await;
+                                    }
+                                    Message::PeerList(list) => {
+                                        crate::topology::received(&ip, list).await;
+                                    }
+                                    Message::FileListRequest { request_id } => {
+                                        if let Err(e) = answer_file_list_request(&mut stream, &ip, request_id).await {
+                                            eprintln!("TCP: Failed to answer file list request from {}: {}", addr, e);
+                                        }
+                                    }
+                                    Message::FileListResponse { request_id, files } => {
+                                        crate::catalog::response_received(&ip, request_id, files).await;
+                                    }
+                                    Message::SyncRequest(scope) => {
+                                        if let Err(e) = answer_sync_request(&mut stream, &ip, scope).await {
+                                            eprintln!("TCP: Failed to answer sync request from {}: {}", addr, e);
+                                        }
+                                    }
+                                    Message::SyncResponse(conversations) => {
+                                        receive_sync_response(&ip, conversations).await;
+                                    }
+                                    Message::ConversationSince { request_id, conversation } => {
+                                        receive_conversation_since(&ip, request_id, conversation).await;
+                                    }
+                                    Message::ComputeLoad(load) => {
+                                        crate::telemetry::received(&ip, load).await;
+                                    }
+                                    Message::Maintenance(window) => {
+                                        crate::maintenance::window_received(&ip, window).await;
+                                    }
+                                    Message::Goodbye(goodbye) => {
+                                        peer_left(&ip, goodbye).await;
+                                        break;
+                                    }
+                                    Message::Groups(groups) => {
+                                        crate::groups::peer_groups_received(&ip, groups);
+                                    }
+                                    Message::Knowledge(entries) => {
+                                        crate::knowledge::received(&ip, entries).await;
+                                    }
+                                    Message::TimeRequest { origin_ms } => {
+                                        let reply = Message::TimeReply { origin_ms, peer_ms: crate::clock::now_ms() };
+                                        if let Err(e) = reply.send(&mut stream).await {
+                                            eprintln!("TCP: Failed to answer time request from {}: {}", addr, e);
+                                        }
+                                    }
+                                    Message::TimeReply { origin_ms, peer_ms } => {
+                                        crate::clock::reply_received(&ip, origin_ms, peer_ms).await;
+                                    }
+                                    Message::LLMCapability { has_llm } => {
+                                        let authorized = match REGISTRY.lock().unwrap().get_mut(&ip) {
+                                            Some(peer) => {
+                                                peer.has_llm = has_llm;
+                                                peer.authorized
                                             }
-                                            Message::LLMAccessResponse { granted, message, llm_host, llm_port } => {
-                                                if granted {
-                                                    let mut authorized = AUTHORIZED_PEERS.lock().await;
-                                                    authorized.insert(ip.clone());
-                                                    
-                                                    // Store LLM connection details if provided
-                                                    if let (Some(host), Some(port)) = (llm_host.clone(), llm_port) {
-                                                        let mut connections = LLM_CONNECTIONS.lock().await;
-                                                        connections.insert(ip.clone(), (host.clone(), port));
-                                                        println!("TCP: LLM access granted by {} - {} (LLM available at {}:{})", 
-                                                               addr, message, host, port);
-                                                    } else {
-                                                        println!("TCP: LLM access granted by {} - {}", addr, message);
-                                                    }
-                                                } else {
-                                                    println!("TCP: LLM access denied by {} - {}", addr, message);
+                                            None => false,
+                                        };
+                                        if has_llm {
+                                            println!("TCP: Peer {} has LLM capability", addr);
+                                            
+                                            // Check if we need to request access
+                                            if !authorized {
+                                                if let Err(e) = request_llm_access(&mut stream, &addr).await {
+                                                    eprintln!("TCP: Failed to request LLM access: {}", e);
+                                                    break;
                                                 }
                                             }
-                                            Message::FileMeta { filename, file_type, file_size, sha256_hex: _, uploaded_at, hmac_hex: _ } => {
-                                                // Record announced peer file to show in UI immediately
-                                                let ts = match chrono::DateTime::parse_from_rfc3339(&uploaded_at) {
-                                                    Ok(dt) => dt.with_timezone(&chrono::Utc),
-                                                    Err(_) => chrono::Utc::now(),
-                                                };
-                                                let info = FileInfo {
-                                                    filename: filename.clone(),
-                                                    file_type: file_type.clone(),
-                                                    file_size: file_size,
-                                                    uploader_ip: ip.clone(),
-                                                    upload_time: ts,
-                                                };
-                                                add_announced_file(info).await;
+                                        } else {
+                                            println!("TCP: Peer {} does not have LLM capability", addr);
+                                        }
+                                    }
+                                    Message::LLMAccessResponse { granted, message, llm_host, llm_port } => {
+                                        if granted {
+                                            // Store LLM connection details if provided
+                                            let endpoint = llm_host.clone().zip(llm_port);
+                                            if let Some(peer) = REGISTRY.lock().unwrap().get_mut(&ip) {
+                                                peer.authorized = true;
+                                                peer.llm_endpoint = endpoint.clone();
                                             }
-                                            Message::FileTransfer { filename, file_type: _, file_size: _, content } => {
-                                                // Save received binary into peer_dir
-                                                let out_path = peer_dir.join(&filename);
-                                                if let Err(e) = fs::write(&out_path, &content).await {
-                                                    eprintln!("TCP: Failed to save received binary {} from {}: {}", filename, addr, e);
-                                                } else {
-                                                    println!("TCP: Saved received binary {} from {}", filename, addr);
-                                                }
+                                            if let Some((host, port)) = endpoint {
+                                                println!("TCP: LLM access granted by {} - {} (LLM available at {}:{})", 
+                                                       addr, message, host, port);
+                                            } else {
+                                                println!("TCP: LLM access granted by {} - {}", addr, message);
                                             }
-                                            _ => continue,
+                                        } else {
+                                            println!("TCP: LLM access denied by {} - {}", addr, message);
                                         }
                                     }
-                                    Ok(None) => {
-                                        println!("TCP: Connection closed by {}", addr);
-                                        let mut connected = CONNECTED_PEERS.lock().await;
-                                        connected.remove(&ip);
-                                        let mut map = ACTIVE_STREAMS.lock().await;
-                                        map.remove(&ip);
-                                        break;
+                                    meta @ Message::FileMeta { .. } => {
+                                        handled = handle_file_meta(&ip, meta).await;
                                     }
-                                    Err(e) => {
-                                        eprintln!("TCP: Error reading from {}: {}", addr, e);
-                                        let mut connected = CONNECTED_PEERS.lock().await;
-                                        connected.remove(&ip);
-                                        let mut map = ACTIVE_STREAMS.lock().await;
-                                        map.remove(&ip);
-                                        break;
+                                    Message::FileChunk { filename, chunk_index, total_chunks, content } => {
+                                        handled = handle_file_chunk(&peer_dir, &ip, &filename, chunk_index, total_chunks, &content).await;
+                                    }
+                                    Message::FileMetaV2(meta) => {
+                                        tokio::spawn(receive_file_meta_v2(peer_dir.clone(), ip.clone(), meta, seq.take()));
+                                    }
+                                    Message::CompressionOffer { algorithms } => {
+                                        record_compression_offer(&ip, &algorithms);
+                                    }
+                                    Message::FileTransfer { filename, file_type: _, file_size: _, content } => {
+                                        if !scan_received(&ip, &filename, &content).await {
+                                            continue;
+                                        }
+                                        // Save received binary into peer_dir
+                                        let out_path = peer_dir.join(&filename);
+                                        if let Err(e) = crate::persistence::write_atomic(&out_path, &content).await {
+                                            eprintln!("TCP: Failed to save received binary {} from {}: {}", filename, addr, e);
+                                        } else {
+                                            events::info(Category::File, format!("Saved {} ({} bytes) from {}", filename, content.len(), ip), serde_json::json!({ "peer": ip, "filename": filename, "bytes": content.len() }));
+                                            events::publish("file", serde_json::json!({
+                                                "event": "received",
+                                                "filename": filename,
+                                                "bytes": content.len(),
+                                                "peer": ip
+                                            }));
+                                        }
+                                    }
+                                    _ => continue,
+                                }
+                                if let Some(seq) = seq {
+                                    if let Err(e) = delivery_answer(seq, handled).send(&mut stream).await {
+                                        eprintln!("TCP: Failed to acknowledge frame {} from {}: {}", seq, addr, e);
                                     }
                                 }
                             }
-
-                            // Cancel the periodic sharing task when the connection ends
-                            share_handle.abort();
-                        }
-                        Err(e) => {
-                            eprintln!("TCP: Failed to setup periodic sharing for {}: {}", addr, e);
-                            let mut connected = CONNECTED_PEERS.lock().await;
-                            connected.remove(&ip);
+                            Ok(None) if !session.is_current() => break,
+                            Err(_) if !session.is_current() => break,
+                            Ok(None) => {
+                                events::info(Category::Peer, format!("Connection closed by {}", addr), serde_json::json!({ "peer": ip }));
+                                session.end();
+                                forget_peer_files(&ip).await;
+                                events::publish("peer", serde_json::json!({ "event": "disconnected", "ip": ip }));
+                                break;
+                            }
+                            Err(e) => {
+                                events::warn(Category::Peer, format!("Lost connection to {}: {}", addr, e), serde_json::json!({ "peer": ip }));
+                                lease.fail(e);
+                                session.end();
+                                forget_peer_files(&ip).await;
+                                events::publish("peer", serde_json::json!({ "event": "disconnected", "ip": ip }));
+                                break;
+                            }
                         }
                     }
+
+                    // Cancel the periodic sharing task when the connection ends
+                    share_handle.abort();
                 }
                 Err(e) => {
-                    eprintln!("TCP: Failed to connect to {}: {}", addr, e);
-                    let mut connected = CONNECTED_PEERS.lock().await;
-                    connected.remove(&ip);
+                    eprintln!("TCP: Failed to setup periodic sharing for {}: {}", addr, e);
+                    lease.fail(e);
                 }
             }
         }
-        drop(ips);
-        sleep(SYNC_INTERVAL).await;
+        Err(e) => {
+            events::warn(Category::Peer, format!("Failed to connect to {}: {}", addr, e), serde_json::json!({ "peer": ip }));
+            lease.fail(e);
+        }
     }
 }
 
@@ -1051,28 +3180,21 @@ pub async fn connect_to_peers(received_ips: Arc<Mutex<HashSet<String>>>) {
 async fn setup_periodic_sharing(
     stream: TcpStream,
     addr: &str,
-    ip: &str,
 ) -> std::io::Result<(TcpStream, tokio::task::JoinHandle<()>)> {
     let socket = match stream.into_std() {
         Ok(socket) => socket,
         Err(e) => {
-            let mut connected = CONNECTED_PEERS.lock().await;
-            connected.remove(ip);
             return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to get standard socket: {}", e)));
         }
     };
 
     if let Err(e) = socket.set_nonblocking(true) {
-        let mut connected = CONNECTED_PEERS.lock().await;
-        connected.remove(ip);
         return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to set nonblocking: {}", e)));
     }
 
     let share_socket = match socket.try_clone() {
         Ok(socket) => socket,
         Err(e) => {
-            let mut connected = CONNECTED_PEERS.lock().await;
-            connected.remove(ip);
             return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to clone socket: {}", e)));
         }
     };
@@ -1080,8 +3202,6 @@ async fn setup_periodic_sharing(
     let stream = match TcpStream::from_std(socket) {
         Ok(stream) => stream,
         Err(e) => {
-            let mut connected = CONNECTED_PEERS.lock().await;
-            connected.remove(ip);
             return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create tokio stream: {}", e)));
         }
     };
@@ -1089,8 +3209,6 @@ async fn setup_periodic_sharing(
     let share_stream = match TcpStream::from_std(share_socket) {
         Ok(stream) => stream,
         Err(e) => {
-            let mut connected = CONNECTED_PEERS.lock().await;
-            connected.remove(ip);
             return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create share stream: {}", e)));
         }
     };
@@ -1099,8 +3217,6 @@ async fn setup_periodic_sharing(
     let socket_addr = match addr.parse() {
         Ok(addr) => addr,
         Err(e) => {
-            let mut connected = CONNECTED_PEERS.lock().await;
-            connected.remove(ip);
             return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to parse address: {}", e)));
         }
     };
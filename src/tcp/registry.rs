@@ -0,0 +1,240 @@
+// Every connected peer in one place. A peer's entry is its session (see session for which of two
+// connections is kept) and everything that belongs to it: the writable stream broadcasts go out
+// on, whether it has an LLM and granted us access to it, the optional features both sides have
+// (see capability), the compression it accepted, frame counts and when the last one arrived (see
+// admission), and the frames still waiting for the peer's acknowledgement (see delivery). The
+// entry goes when the session ends, so nothing is left behind for a peer that is gone. The registry sits behind one lock that is never held across an await; each stream has its
+// own lock, so a slow peer holds up nobody else's writes.
+use super::admission::Candidate;
+use super::capability::Capabilities;
+use super::compression::Compression;
+use super::delivery::{DeliveryStats, Next, Outbox};
+use super::session::{preferred, Direction};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::Shutdown;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpStream;
+
+pub type Writer = Arc<tokio::sync::Mutex<TcpStream>>;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    pub frames_sent: u64,
+    pub bytes_sent: u64,
+    pub frames_received: u64,
+    pub bytes_received: u64,
+}
+
+pub struct PeerConnection {
+    id: u64,
+    pub direction: Direction,
+    peer_id: Option<String>,
+    // A clone of the socket, to shut the connection down when another session replaces it
+    socket: Option<std::net::TcpStream>,
+    pub connected_at: DateTime<Utc>,
+    // Set once the initial sync is done; broadcasts skip the peer until then
+    writer: Option<Writer>,
+    pub has_llm: bool,
+    // The peer granted us access to its LLM, reachable at llm_endpoint
+    pub authorized: bool,
+    pub llm_endpoint: Option<(String, i32)>,
+    // Set from the handshake when the session opens
+    pub capabilities: Capabilities,
+    pub compression: Option<Compression>,
+    pub stats: Stats,
+    pub outbox: Outbox,
+    // Last frame from the peer, for ranking it against others when the peer limit is reached
+    last_received: Option<Instant>,
+}
+
+// A peer's connection for /api/peers/info
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    pub direction: Direction,
+    pub connected_at: DateTime<Utc>,
+    pub llm_access: bool,
+    pub capabilities: Vec<&'static str>,
+    pub compression: Option<&'static str>,
+    pub stats: Stats,
+    pub delivery: DeliveryStats,
+}
+
+impl PeerConnection {
+    pub fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            direction: self.direction,
+            connected_at: self.connected_at,
+            llm_access: self.authorized,
+            capabilities: self.capabilities.names(),
+            compression: self.compression.map(|c| c.name()),
+            stats: self.stats.clone(),
+            delivery: self.outbox.stats(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Registry {
+    peers: HashMap<String, PeerConnection>,
+    next_id: u64,
+}
+
+impl Registry {
+    // Register a connection with `peer_ip`: its session id, or None when the peer's current session
+    // is the one to keep and this connection must be closed. A session this one replaces is shut
+    // down; one in the same direction is replaced as well, being left over from before a reconnect.
+    pub fn open(&mut self, peer_ip: &str, direction: Direction, local_id: &str, peer_id: Option<&str>, socket: Option<std::net::TcpStream>) -> Option<u64> {
+        let mut peer_id = peer_id.map(str::to_string);
+        if let Some(current) = self.peers.get(peer_ip) {
+            if peer_id.is_none() {
+                peer_id = current.peer_id.clone();
+            }
+            let replace = current.direction == direction || peer_id.as_deref().is_some_and(|id| preferred(local_id, id) == direction);
+            if !replace {
+                return None;
+            }
+            if let Some(socket) = &current.socket {
+                let _ = socket.shutdown(Shutdown::Both);
+            }
+        }
+        self.next_id += 1;
+        let connection = PeerConnection {
+            id: self.next_id,
+            direction,
+            peer_id,
+            socket,
+            connected_at: Utc::now(),
+            writer: None,
+            has_llm: false,
+            authorized: false,
+            llm_endpoint: None,
+            capabilities: Capabilities::default(),
+            compression: None,
+            stats: Stats::default(),
+            outbox: Outbox::default(),
+            last_received: None,
+        };
+        self.peers.insert(peer_ip.to_string(), connection);
+        Some(self.next_id)
+    }
+
+    pub fn is_current(&self, peer_ip: &str, id: u64) -> bool {
+        self.peers.get(peer_ip).is_some_and(|p| p.id == id)
+    }
+
+    pub fn has(&self, peer_ip: &str) -> bool {
+        self.peers.contains_key(peer_ip)
+    }
+
+    // The session ended; a replaced one leaves its successor in place
+    pub fn close(&mut self, peer_ip: &str, id: u64) {
+        if self.is_current(peer_ip, id) {
+            self.peers.remove(peer_ip);
+        }
+    }
+
+    // Every session ranked as admission sees it
+    pub fn candidates(&self) -> Vec<Candidate> {
+        self.peers
+            .iter()
+            .map(|(ip, p)| Candidate { ip: ip.clone(), llm_host: p.has_llm, last_active: p.last_received })
+            .collect()
+    }
+
+    // Close the peer's session from outside; its reader sees the connection end and cleans up
+    pub fn shutdown(&self, peer_ip: &str) {
+        if let Some(socket) = self.peers.get(peer_ip).and_then(|p| p.socket.as_ref()) {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+    }
+
+    pub fn get(&self, peer_ip: &str) -> Option<&PeerConnection> {
+        self.peers.get(peer_ip)
+    }
+
+    // Whether the peer's session may use an optional feature
+    pub fn supports(&self, peer_ip: &str, feature: Capabilities) -> bool {
+        self.peers.get(peer_ip).is_some_and(|p| p.capabilities.contains(feature))
+    }
+
+    pub fn get_mut(&mut self, peer_ip: &str) -> Option<&mut PeerConnection> {
+        self.peers.get_mut(peer_ip)
+    }
+
+    // Hand the session its broadcast stream; false when the session was replaced meanwhile
+    pub fn set_writer(&mut self, peer_ip: &str, id: u64, stream: TcpStream) -> bool {
+        match self.peers.get_mut(peer_ip).filter(|p| p.id == id) {
+            Some(peer) => {
+                peer.writer = Some(Arc::new(tokio::sync::Mutex::new(stream)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn writer(&self, peer_ip: &str) -> Option<Writer> {
+        self.peers.get(peer_ip).and_then(|p| p.writer.clone())
+    }
+
+    // Streams of every peer broadcasts reach, in address order
+    pub fn writers(&self) -> Vec<(String, Writer)> {
+        let mut writers: Vec<(String, Writer)> = self.peers.iter().filter_map(|(ip, p)| p.writer.clone().map(|w| (ip.clone(), w))).collect();
+        writers.sort_by(|a, b| a.0.cmp(&b.0));
+        writers
+    }
+
+    pub fn connections(&self) -> HashMap<String, ConnectionInfo> {
+        self.peers.iter().map(|(ip, p)| (ip.clone(), p.info())).collect()
+    }
+
+    pub fn ips(&self) -> Vec<String> {
+        let mut ips: Vec<String> = self.peers.keys().cloned().collect();
+        ips.sort();
+        ips
+    }
+
+    pub fn llm_hosts(&self) -> Vec<String> {
+        let mut ips: Vec<String> = self.peers.iter().filter(|(_, p)| p.has_llm).map(|(ip, _)| ip.clone()).collect();
+        ips.sort();
+        ips
+    }
+
+    // Peers whose LLM we may use, and where it is
+    pub fn llm_endpoints(&self) -> Vec<(String, (String, i32))> {
+        let mut endpoints: Vec<(String, (String, i32))> = self
+            .peers
+            .iter()
+            .filter(|(_, p)| p.authorized)
+            .filter_map(|(ip, p)| p.llm_endpoint.clone().map(|e| (ip.clone(), e)))
+            .collect();
+        endpoints.sort();
+        endpoints
+    }
+
+    pub fn sent(&mut self, peer_ip: &str, bytes: usize) {
+        if let Some(peer) = self.peers.get_mut(peer_ip) {
+            peer.stats.frames_sent += 1;
+            peer.stats.bytes_sent += bytes as u64;
+        }
+    }
+
+    // Acknowledgements overdue on any connection
+    pub fn overdue(&mut self, now: Instant) -> Vec<(String, Next)> {
+        let mut overdue = Vec::new();
+        for (ip, peer) in self.peers.iter_mut() {
+            overdue.extend(peer.outbox.overdue(now).into_iter().map(|next| (ip.clone(), next)));
+        }
+        overdue
+    }
+
+    pub fn received(&mut self, peer_ip: &str, bytes: usize) {
+        if let Some(peer) = self.peers.get_mut(peer_ip) {
+            peer.stats.frames_received += 1;
+            peer.stats.bytes_received += bytes as u64;
+            peer.last_received = Some(Instant::now());
+        }
+    }
+}
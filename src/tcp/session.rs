@@ -0,0 +1,23 @@
+// One TCP session per pair of peers. Discovery is symmetric, so two nodes often dial each other at
+// the same moment and both connections get through. The connection dialled by the node with the
+// lower node ID is kept, decided the same way at both ends: the dialling side checks once the
+// peer's VERS: frame has told it the peer's ID, the accepting side when a connection arrives while
+// its own one to the peer is up. The other connection is closed before anything is synced over it.
+// The sessions themselves live in the registry.
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+// Which of the two connections between us and a peer is kept
+pub fn preferred(local_id: &str, peer_id: &str) -> Direction {
+    if local_id < peer_id {
+        Direction::Outbound
+    } else {
+        Direction::Inbound
+    }
+}
@@ -0,0 +1,745 @@
+use super::*;
+
+fn roundtrip(msg: &Message) -> Message {
+    let (marker, payload) = msg.encode().expect("encode");
+    Message::decode(&marker, payload).expect("decode").expect("message")
+}
+
+fn decode_raw(marker: &[u8; 5], payload: &[u8]) -> std::io::Result<Option<Message>> {
+    Message::decode(marker, payload.to_vec())
+}
+
+// Small deterministic xorshift generator so fuzz runs are reproducible without extra dependencies
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len = (self.next() as usize) % (max_len + 1);
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+const MARKERS: [&[u8; 5]; 36] = [
+    b"AUTH:", b"FILE:", b"SYNC:", b"RESP:", b"LLMC:", b"LREQ:", b"LRES:",
+    b"FTRS:", b"CHNK:", b"FMTA:", b"FMT2:", b"CMPR:", b"VERS:", b"CAPS:", b"TYPE:", b"VOTE:", b"DELF:", b"PING:", b"PONG:",
+    b"DIAQ:", b"DIAR:", b"MESH:", b"TIMQ:", b"TIMR:", b"LOAD:", b"MNTN:", b"GBYE:", b"CONV:", b"FLSQ:", b"FLSR:",
+    b"SEQN:", b"ACKN:", b"NACK:", b"GRPS:", b"KNOW:", b"XXXX:",
+];
+
+fn sample_messages() -> Vec<Message> {
+    vec![
+        Message::Handshake { timestamp: 1_700_000_000, nonce: "0f".repeat(16), hmac_hex: "b".repeat(64), version: Some(NodeVersion::local()) },
+        Message::Handshake { timestamp: 1_700_000_000, nonce: "0f".repeat(16), hmac_hex: "b".repeat(64), version: None },
+        Message::Version { crate_version: "0.1.0".into(), protocol_version: 2, node_id: "0123456789abcdef".into() },
+        Message::Capabilities(super::capability::Capabilities::local()),
+        Message::ConversationFile { name: "local.json".into(), content: "{\"id\":\"local\"}".into() },
+        Message::SyncRequest(None),
+        Message::SyncRequest(Some(SyncScope { request_id: u64::MAX, since_ms: 0 })),
+        Message::ConversationSince { request_id: 7, conversation: None },
+        Message::LLMCapability { has_llm: true },
+        Message::LLMAccessRequest { peer_name: "host-a".into(), reason: "needs | pipes".into() },
+        Message::LLMAccessResponse { granted: true, message: "ok | fine".into(), llm_host: Some("10.0.0.2".into()), llm_port: Some(8080) },
+        Message::FileTransfer { filename: "a b.bin".into(), file_type: "application/octet-stream".into(), file_size: 5, content: b"1|2|3".to_vec() },
+        Message::FileChunk { filename: "big.zip".into(), chunk_index: 1, total_chunks: 3, content: vec![0, 1, 0, 2] },
+        Message::FileMeta {
+            filename: "report.pdf".into(),
+            file_type: "application/pdf".into(),
+            file_size: 42,
+            sha256_hex: "a".repeat(64),
+            uploaded_at: "2024-01-01T00:00:00+00:00".into(),
+            signed_at: 1_700_000_000,
+            nonce: "1".repeat(32),
+            hmac_hex: String::new(),
+        },
+        Message::CompressionOffer { algorithms: vec!["zstd".into(), "gzip".into()] },
+        Message::Activity { conversation_id: "local".into(), sender: "alice|laptop".into(), activity: crate::presence::Activity::Typing },
+        Message::Activity { conversation_id: "fork-0123456789abcdef".into(), sender: "LLM".into(), activity: crate::presence::Activity::Generating },
+        Message::Vote { message_id: "0123456789abcdef".into(), voter: "alice|laptop".into(), value: -1, voted_at_ms: 1_700_000_000_000 },
+        Message::Vote { message_id: "1700000000000-3".into(), voter: "bob".into(), value: 0, voted_at_ms: 0 },
+        Message::FileDeleted { filename: "report.pdf".into() },
+        Message::Ping { token: u64::MAX },
+        Message::Pong { token: 0 },
+        Message::DiagnosticsRequest { request_id: 7, limit: 50 },
+        Message::DiagnosticsResponse { request_id: 7, report: "{\"shared\":false,\"message\":\"a | b\"}".into() },
+        Message::Sequenced { seq: 1, message: Box::new(Message::ConversationFile { name: "local.json".into(), content: "{\"id\":\"local\"}".into() }) },
+        Message::Sequenced { seq: u64::MAX, message: Box::new(Message::FileChunk { filename: "big.zip".into(), chunk_index: 0, total_chunks: 1, content: b"1|2".to_vec() }) },
+        Message::Ack { seq: 1 },
+        Message::Nack { seq: 2, reason: "hash mismatch | retry".into() },
+        Message::FileListRequest { request_id: u64::MAX },
+        Message::FileListResponse { request_id: 7, files: Vec::new() },
+        Message::FileListResponse {
+            request_id: 7,
+            files: vec![FileInfo {
+                filename: "a b.bin".into(),
+                file_type: "application/octet-stream".into(),
+                file_size: 5,
+                uploader_ip: "fe80::1".into(),
+                upload_time: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+                tags: vec!["x|y".into()],
+                sha256: Some("a".repeat(64)),
+                scan: None,
+                owner: None,
+                visibility: Default::default(),
+                group: None,
+            }],
+        },
+        Message::PeerList(crate::topology::PeerList {
+            node_id: "0123456789abcdef".into(),
+            hostname: "host-a".into(),
+            llm_host: true,
+            peers: vec![
+                crate::topology::PeerLink { ip: "10.0.0.2".into(), latency_ms: Some(3) },
+                crate::topology::PeerLink { ip: "fe80::1".into(), latency_ms: None },
+            ],
+        }),
+        Message::TimeRequest { origin_ms: 1_700_000_000_000 },
+        Message::TimeReply { origin_ms: 1_700_000_000_000, peer_ms: 1_700_000_002_500 },
+        Message::ComputeLoad(crate::telemetry::ComputeLoad {
+            cpu_percent: 12.5,
+            memory_used_bytes: 1 << 30,
+            memory_total_bytes: 1 << 34,
+            gpus: vec![crate::telemetry::GpuLoad { name: "NVIDIA RTX | 4090".into(), utilization_percent: 97, memory_used_bytes: 20 << 30, memory_total_bytes: 24 << 30 }],
+        }),
+        Message::Maintenance(Some(crate::maintenance::Window { starts_at_ms: 1_700_000_000_000, back_at_ms: Some(1_700_003_600_000), note: "back at 14:00 | ups".into() })),
+        Message::Maintenance(None),
+        Message::Goodbye(crate::maintenance::Goodbye { back_at_ms: None, note: String::new() }),
+        Message::Groups(vec!["ops".into(), "research-2".into()]),
+        Message::Groups(Vec::new()),
+        Message::Knowledge(vec![knowledge_entry("0123456789abcdef", "What is a Lamport clock?", 1_704_067_200)]),
+        Message::Knowledge(Vec::new()),
+    ]
+}
+
+fn knowledge_entry(id: &str, question: &str, secs: i64) -> crate::knowledge::Entry {
+    crate::knowledge::Entry {
+        id: id.into(),
+        question: question.into(),
+        answer: "A counter | ✓".into(),
+        model: Some("llama3".into()),
+        node: "laptop".into(),
+        saved_by: None,
+        message_id: "fedcba9876543210".into(),
+        saved_at: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+        updated_at: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+        deleted: false,
+    }
+}
+
+#[test]
+fn chunk_roundtrip_keeps_header_and_binary_separate() {
+    let msg = Message::FileChunk { filename: "x.bin".into(), chunk_index: 0, total_chunks: 1, content: vec![0, b'|', 0, 255] };
+    match roundtrip(&msg) {
+        Message::FileChunk { filename, chunk_index, total_chunks, content } => {
+            assert_eq!(filename, "x.bin");
+            assert_eq!((chunk_index, total_chunks), (0, 1));
+            assert_eq!(content, vec![0, b'|', 0, 255]);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn file_transfer_roundtrip_allows_pipes_in_content() {
+    let msg = Message::FileTransfer { filename: "notes.txt".into(), file_type: "text/plain".into(), file_size: 7, content: b"a|b|c|d".to_vec() };
+    match roundtrip(&msg) {
+        Message::FileTransfer { filename, file_type, file_size, content } => {
+            assert_eq!((filename.as_str(), file_type.as_str(), file_size), ("notes.txt", "text/plain", 7));
+            assert_eq!(content, b"a|b|c|d");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn access_response_message_may_contain_pipes() {
+    let msg = Message::LLMAccessResponse { granted: false, message: "no | not now".into(), llm_host: None, llm_port: None };
+    match roundtrip(&msg) {
+        Message::LLMAccessResponse { granted, message, llm_host, llm_port } => {
+            assert!(!granted);
+            assert_eq!(message, "no | not now");
+            assert_eq!((llm_host, llm_port), (None, None));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn all_sample_messages_roundtrip() {
+    for msg in sample_messages() {
+        let (marker, _) = msg.encode().unwrap();
+        let decoded = roundtrip(&msg);
+        assert_eq!(decoded.encode().unwrap().0, marker);
+    }
+}
+
+#[test]
+fn rejects_malformed_fields() {
+    assert!(decode_raw(b"LLMC:", b"maybe").is_err());
+    assert!(decode_raw(b"SYNC:", b"junk").is_err());
+    assert!(decode_raw(b"RESP:", br#"[{"id":"../etc","messages":[],"host_info":{"hostname":"h","ip_address":"1.2.3.4","is_llm_host":false}}]"#).is_err());
+    assert!(decode_raw(b"FILE:", b"../../etc/passwd|x").is_err());
+    assert!(decode_raw(b"FILE:", b"no-separator").is_err());
+    assert!(decode_raw(b"FTRS:", b"a.bin|application/octet-stream|10|short").is_err());
+    assert!(decode_raw(b"FTRS:", b"a.bin|not a mime|1|x").is_err());
+    assert!(decode_raw(b"FTRS:", b"a.bin|text/plain|-1|").is_err());
+    assert!(decode_raw(b"CHNK:", b"a.bin|0|1").is_err(), "missing NUL terminator");
+    assert!(decode_raw(b"CHNK:", b"a.bin|5|2\0data").is_err(), "index past total");
+    assert!(decode_raw(b"CHNK:", b"a.bin|0|0\0").is_err(), "zero chunks");
+    assert!(decode_raw(b"CHNK:", b"a.bin|x|2\0data").is_err());
+    assert!(decode_raw(b"FMTA:", b"a|text/plain|1|nothex|2024-01-01T00:00:00Z|1700000000|00112233445566778899aabbccddeeff|").is_err());
+    assert!(decode_raw(b"FMTA:", format!("a|text/plain|1|{}|2024-01-01T00:00:00Z|", "a".repeat(64)).as_bytes()).is_err(), "missing nonce");
+    assert!(decode_raw(b"FMTA:", b"a|text/plain|1").is_err());
+    assert!(decode_raw(b"LRES:", b"true|ok|host|99999").is_err());
+    assert!(decode_raw(b"CMPR:", b"zstd,g zip").is_err());
+    assert!(decode_raw(b"AUTH:", b"-5|00112233445566778899aabbccddeeff|").is_err());
+    assert!(decode_raw(b"AUTH:", b"1700000000|short|").is_err());
+    assert!(decode_raw(b"TYPE:", b"dancing|local|bob").is_err());
+    assert!(decode_raw(b"TYPE:", b"typing|../local|bob").is_err());
+    assert!(decode_raw(b"TYPE:", b"typing|local").is_err());
+    assert!(decode_raw(b"VOTE:", b"2|0123456789abcdef|1700000000000|bob").is_err());
+    assert!(decode_raw(b"VOTE:", b"1|../votes|1700000000000|bob").is_err());
+    assert!(decode_raw(b"VOTE:", b"1|0123456789abcdef|-5|bob").is_err());
+    assert!(decode_raw(b"VOTE:", b"1|0123456789abcdef|1700000000000").is_err());
+    assert!(decode_raw(b"DELF:", b"../p2p_secret.txt").is_err());
+    assert!(decode_raw(b"DELF:", b"").is_err());
+    assert!(decode_raw(b"PING:", b"").is_err());
+    assert!(decode_raw(b"PONG:", b"-1").is_err());
+    assert!(decode_raw(b"DIAQ:", b"7").is_err());
+    assert!(decode_raw(b"DIAQ:", b"7|-1").is_err());
+    assert!(decode_raw(b"DIAR:", b"7|[1,2]").is_err(), "report must be an object");
+    assert!(decode_raw(b"DIAR:", b"{}").is_err(), "missing request id");
+    assert!(decode_raw(b"MESH:", br#"{"node_id":"a","hostname":"b","llm_host":false,"peers":[{"ip":"not-an-ip","latency_ms":null}]}"#).is_err());
+    assert!(decode_raw(b"MESH:", br#"{"node_id":"a","hostname":"b","llm_host":false}"#).is_err());
+    assert!(decode_raw(b"TIMQ:", b"-1").is_err());
+    assert!(decode_raw(b"TIMR:", b"1700000000000").is_err());
+    assert!(decode_raw(b"TIMR:", b"1700000000000|18446744073709551615").is_err(), "time out of range");
+    assert!(decode_raw(b"LOAD:", br#"{"cpu_percent":101,"memory_used_bytes":0,"memory_total_bytes":0}"#).is_err());
+    assert!(decode_raw(b"LOAD:", br#"{"cpu_percent":1,"memory_used_bytes":2,"memory_total_bytes":1}"#).is_err(), "more memory used than there is");
+    assert!(decode_raw(b"XXXX:", b"").is_err());
+}
+
+#[test]
+fn compressed_envelope_roundtrip_and_limits() {
+    let (marker, payload) = Message::ConversationFile { name: "local.json".into(), content: "x".repeat(10_000) }.encode().unwrap();
+    for algorithm in [Compression::Zstd, Compression::Gzip] {
+        let mut wrapped = marker.to_vec();
+        wrapped.extend_from_slice(&algorithm.compress(&payload).unwrap());
+        let (inner, data) = Message::unwrap_frame(algorithm.marker(), wrapped).unwrap();
+        assert_eq!(&inner, b"FILE:");
+        assert_eq!(data, payload);
+    }
+    assert!(Message::unwrap_frame(*b"ZSTD:", b"FIL".to_vec()).is_err());
+    assert!(Message::unwrap_frame(*b"GZIP:", b"FILE:not gzip".to_vec()).is_err());
+    assert!(Message::unwrap_frame(*b"ZSTD:", b"ZSTD:x".to_vec()).is_err());
+}
+
+#[test]
+fn marker_shape() {
+    assert!(wire::is_marker_shaped(b"FMT2:"));
+    assert!(!wire::is_marker_shaped(&[0, b'F', b'I', b'L', b'E']));
+    assert!(!wire::is_marker_shaped(b"file:"));
+}
+
+#[test]
+fn fuzz_random_payloads_never_panic() {
+    let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+    for marker in MARKERS {
+        for _ in 0..2_000 {
+            let payload = rng.bytes(256);
+            let _ = Message::decode(marker, payload);
+        }
+    }
+}
+
+#[test]
+fn fuzz_mutated_valid_frames_never_panic() {
+    let mut rng = XorShift(0xDEAD_BEEF_CAFE_F00D);
+    for msg in sample_messages() {
+        let (marker, payload) = msg.encode().unwrap();
+        for _ in 0..2_000 {
+            let mut mutated = payload.clone();
+            match rng.next() % 4 {
+                0 if !mutated.is_empty() => {
+                    let i = rng.next() as usize % mutated.len();
+                    mutated[i] ^= 1 << (rng.next() % 8);
+                }
+                1 => {
+                    let keep = rng.next() as usize % (mutated.len() + 1);
+                    mutated.truncate(keep);
+                }
+                2 => {
+                    let i = rng.next() as usize % (mutated.len() + 1);
+                    mutated.insert(i, if rng.next() & 1 == 0 { b'|' } else { 0 });
+                }
+                _ => mutated.extend(rng.bytes(16)),
+            }
+            let _ = Message::decode(&marker, mutated.clone());
+            let _ = Message::unwrap_frame(*b"ZSTD:", mutated);
+        }
+    }
+}
+
+#[test]
+fn per_type_size_limits() {
+    assert_eq!(max_payload_size(b"LLMC:"), 1024);
+    assert_eq!(max_payload_size(b"FMTA:"), 64 * 1024);
+    assert!(max_payload_size(b"CHNK:") > FILE_CHUNK_SIZE);
+    assert!(max_payload_size(b"FTRS:") <= MAX_MESSAGE_SIZE);
+    // A compressed capability must not inflate past the capability limit
+    let bomb = Compression::Zstd.compress(&vec![b'a'; 64 * 1024]).unwrap();
+    let mut wrapped = b"LLMC:".to_vec();
+    wrapped.extend_from_slice(&bomb);
+    assert!(Message::unwrap_frame(*b"ZSTD:", wrapped).is_err());
+}
+
+#[test]
+fn handshake_is_bound_to_secret_and_target() {
+    let nonce = auth::new_nonce();
+    let v = NodeVersion::local();
+    let sig = auth::sign_handshake("secret", 1_700_000_000, &nonce, "10.0.0.5", Some(&v));
+    assert!(auth::verify_handshake("secret", 1_700_000_000, &nonce, "10.0.0.5", Some(&v), &sig));
+    assert!(!auth::verify_handshake("other", 1_700_000_000, &nonce, "10.0.0.5", Some(&v), &sig));
+    assert!(!auth::verify_handshake("secret", 1_700_000_000, &nonce, "10.0.0.6", Some(&v), &sig));
+    assert!(!auth::verify_handshake("secret", 1_700_000_001, &nonce, "10.0.0.5", Some(&v), &sig));
+}
+
+#[test]
+fn handshake_signature_covers_versions() {
+    let nonce = auth::new_nonce();
+    let v = NodeVersion::local();
+    let sig = auth::sign_handshake("secret", 1_700_000_000, &nonce, "10.0.0.5", Some(&v));
+    let downgraded = NodeVersion { protocol: 1, ..v.clone() };
+    assert!(!auth::verify_handshake("secret", 1_700_000_000, &nonce, "10.0.0.5", Some(&downgraded), &sig));
+    assert!(!auth::verify_handshake("secret", 1_700_000_000, &nonce, "10.0.0.5", None, &sig));
+}
+
+// Peer HTTP requests are bound to the secret, method and path, and each signature works once
+#[test]
+fn peer_requests_are_signed_for_method_and_path() {
+    let now = chrono::Utc::now().timestamp();
+    let header = |secret: &str, method: &str, path: &str| {
+        let nonce = auth::new_nonce();
+        format!("{}.{}.{}", now, nonce, auth::sign_peer_request(secret, now, &nonce, method, path))
+    };
+    let signed = header("secret", "GET", "/api/replication/hashes");
+    assert!(auth::verify_peer_request("other", "GET", "/api/replication/hashes", &signed).is_none());
+    assert!(auth::verify_peer_request("secret", "HEAD", "/api/replication/hashes", &signed).is_none());
+    assert!(auth::verify_peer_request("secret", "GET", "/api/replica/abc", &signed).is_none());
+    assert!(!peer_request_authentic("secret", "GET", "/api/replication/hashes", Some("garbage")));
+    assert!(!peer_request_authentic("secret", "GET", "/api/replication/hashes", None));
+    assert!(peer_request_authentic("secret", "GET", "/api/replication/hashes", Some(&signed)));
+    assert!(!peer_request_authentic("secret", "GET", "/api/replication/hashes", Some(&signed)), "replayed");
+    let stale = format!("{}.n1.{}", now - 600, auth::sign_peer_request("secret", now - 600, "n1", "GET", "/api/update/ui"));
+    assert!(!peer_request_authentic("secret", "GET", "/api/update/ui", Some(&stale)));
+}
+
+#[test]
+fn version_advisories() {
+    assert!(!version::is_compatible(1));
+    assert!(version::is_compatible(version::PROTOCOL_VERSION));
+    assert!(version::advisory(Some(env!("CARGO_PKG_VERSION")), version::PROTOCOL_VERSION).is_none());
+    assert!(version::advisory(Some("999.0.0"), version::PROTOCOL_VERSION).unwrap().contains("upgrading this node"));
+    assert!(version::advisory(Some("0.0.1"), version::PROTOCOL_VERSION).unwrap().contains("upgrading the peer"));
+    assert!(version::advisory(None, 1).unwrap().contains("protocol 1"));
+}
+
+#[test]
+fn dialer_backs_off_parks_and_heals() {
+    use super::dialer::{Dialer, Policy, PARK_TIME, STABLE_AFTER, STALE_CONNECTING};
+    let policy = Policy { base: Duration::from_secs(2), max: Duration::from_secs(60), max_attempts: 4, jitter: 0.2 };
+    assert_eq!(policy.delay(1, 0.0), Duration::from_secs(2));
+    assert_eq!(policy.delay(3, 0.0), Duration::from_secs(8));
+    assert_eq!(policy.delay(30, 0.0), Duration::from_secs(60));
+    assert_eq!(policy.delay(1, 1.0), Duration::from_secs_f64(2.4));
+    assert_eq!(policy.delay(1, -1.0), Duration::from_secs_f64(1.6));
+
+    let mut dialer = Dialer::new(policy);
+    let t0 = Instant::now();
+    dialer.discovered("10.0.0.2", t0);
+    let due = dialer.due(t0);
+    assert_eq!(due.len(), 1);
+    assert!(dialer.due(t0).is_empty(), "an attempt in progress is not dialled twice");
+    let (ip, attempt) = due[0].clone();
+
+    // A failure waits out the backoff before the next attempt
+    dialer.ended(&ip, attempt, Some("refused".into()), t0, 0.0);
+    let state = dialer.state(&ip, t0).unwrap();
+    assert_eq!((state.state, state.failures, state.retry_in_secs, state.last_error.as_deref()), ("backoff", 1, Some(2), Some("refused")));
+    assert!(dialer.due(t0 + Duration::from_secs(1)).is_empty());
+    let (_, attempt) = dialer.due(t0 + Duration::from_secs(2))[0].clone();
+
+    // A connection that drops at once counts as a failure, a stable one starts over
+    let t1 = t0 + Duration::from_secs(2);
+    dialer.connected(&ip, attempt, t1);
+    dialer.ended(&ip, attempt, None, t1 + Duration::from_secs(1), 0.0);
+    assert_eq!(dialer.state(&ip, t1).unwrap().failures, 2);
+    let t2 = t1 + Duration::from_secs(10);
+    let (_, attempt) = dialer.due(t2)[0].clone();
+    dialer.connected(&ip, attempt, t2);
+    // A lease from an earlier attempt changes nothing
+    dialer.ended(&ip, attempt - 1, Some("old".into()), t2, 0.0);
+    assert_eq!(dialer.state(&ip, t2).unwrap().state, "connected");
+    dialer.ended(&ip, attempt, None, t2 + STABLE_AFTER, 0.0);
+    assert_eq!(dialer.state(&ip, t2).unwrap().failures, 0);
+
+    // An attempt that never finishes is given up on and dialled again
+    let t3 = t2 + Duration::from_secs(60);
+    let (_, stuck) = dialer.due(t3)[0].clone();
+    let (_, retried) = dialer.due(t3 + STALE_CONNECTING)[0].clone();
+    assert!(retried > stuck);
+    assert_eq!(dialer.state(&ip, t3).unwrap().failures, 1);
+
+    // After max_attempts failures in a row the peer is parked until discovery finds it again later
+    let mut now = t3 + STALE_CONNECTING;
+    let mut attempt = retried;
+    for _ in 0..3 {
+        dialer.ended(&ip, attempt, Some("refused".into()), now, 0.0);
+        now += Duration::from_secs(120);
+        if let Some((_, next)) = dialer.due(now).first().cloned() {
+            attempt = next;
+        }
+    }
+    assert_eq!(dialer.state(&ip, now).unwrap().state, "parked");
+    dialer.discovered(&ip, now);
+    assert!(dialer.due(now).is_empty());
+    dialer.discovered(&ip, now + PARK_TIME);
+    assert_eq!(dialer.due(now + PARK_TIME).len(), 1);
+}
+
+// Both ends of a simultaneous dial keep the same connection, whichever one got through first
+#[test]
+fn duplicate_sessions_keep_the_lower_ids_connection() {
+    use super::registry::Registry;
+    use super::session::{preferred, Direction};
+    let (low, high) = ("0123", "abcd");
+    assert_eq!(preferred(low, high), Direction::Outbound);
+    assert_eq!(preferred(high, low), Direction::Inbound);
+
+    for outbound_first in [true, false] {
+        // The lower node: its outbound connection is kept
+        let mut sessions = Registry::default();
+        let (kept, dropped) = if outbound_first {
+            let kept = sessions.open("10.0.0.2", Direction::Outbound, low, Some(high), None);
+            (kept, sessions.open("10.0.0.2", Direction::Inbound, low, None, None))
+        } else {
+            let inbound = sessions.open("10.0.0.2", Direction::Inbound, low, None, None);
+            let kept = sessions.open("10.0.0.2", Direction::Outbound, low, Some(high), None);
+            assert!(!sessions.is_current("10.0.0.2", inbound.unwrap()), "the inbound one is replaced");
+            (kept, None)
+        };
+        assert!(kept.is_some_and(|id| sessions.is_current("10.0.0.2", id)));
+        assert_eq!(dropped, None);
+
+        // The higher node: the inbound connection from the lower one is kept
+        let mut sessions = Registry::default();
+        let kept = if outbound_first {
+            let outbound = sessions.open("10.0.0.1", Direction::Outbound, high, Some(low), None);
+            let kept = sessions.open("10.0.0.1", Direction::Inbound, high, None, None);
+            assert!(!sessions.is_current("10.0.0.1", outbound.unwrap()));
+            kept
+        } else {
+            let kept = sessions.open("10.0.0.1", Direction::Inbound, high, None, None);
+            assert_eq!(sessions.open("10.0.0.1", Direction::Outbound, high, Some(low), None), None);
+            kept
+        };
+        assert!(kept.is_some_and(|id| sessions.is_current("10.0.0.1", id)));
+    }
+
+    // A reconnect in the same direction replaces a leftover session, whose end changes nothing
+    let mut sessions = Registry::default();
+    let old = sessions.open("10.0.0.2", Direction::Inbound, low, None, None).unwrap();
+    let new = sessions.open("10.0.0.2", Direction::Inbound, low, None, None).unwrap();
+    sessions.close("10.0.0.2", old);
+    assert!(sessions.is_current("10.0.0.2", new));
+    sessions.close("10.0.0.2", new);
+    assert!(!sessions.has("10.0.0.2"));
+}
+
+// A peer's LLM access, compression and traffic live and die with its session
+#[test]
+fn registry_forgets_a_peer_with_its_session() {
+    use super::compression::Compression;
+    use super::registry::Registry;
+    use super::session::Direction;
+    let mut registry = Registry::default();
+    let id = registry.open("10.0.0.2", Direction::Inbound, "0123", None, None).unwrap();
+    let peer = registry.get_mut("10.0.0.2").unwrap();
+    peer.has_llm = true;
+    peer.llm_endpoint = Some(("10.0.0.2".to_string(), 8080));
+    assert!(registry.llm_endpoints().is_empty(), "no access until it is granted");
+    let peer = registry.get_mut("10.0.0.2").unwrap();
+    peer.authorized = true;
+    peer.compression = Some(Compression::Zstd);
+    registry.sent("10.0.0.2", 100);
+    registry.received("10.0.0.2", 40);
+    registry.received("10.0.0.9", 40);
+    assert_eq!(registry.llm_hosts(), vec!["10.0.0.2".to_string()]);
+    assert_eq!(registry.llm_endpoints(), vec![("10.0.0.2".to_string(), ("10.0.0.2".to_string(), 8080))]);
+    let info = &registry.connections()["10.0.0.2"];
+    assert_eq!((info.stats.frames_sent, info.stats.bytes_sent, info.stats.frames_received), (1, 100, 1));
+    assert_eq!(info.compression, Some(Compression::Zstd.name()));
+    assert_eq!(registry.ips(), vec!["10.0.0.2".to_string()]);
+
+    // A reconnect starts from nothing, and the old session's end leaves it alone
+    let new = registry.open("10.0.0.2", Direction::Inbound, "0123", None, None).unwrap();
+    assert!(registry.llm_hosts().is_empty() && registry.llm_endpoints().is_empty());
+    registry.close("10.0.0.2", id);
+    assert!(registry.has("10.0.0.2"));
+    registry.close("10.0.0.2", new);
+    assert!(registry.ips().is_empty() && registry.connections().is_empty());
+}
+
+// A session uses the features both sides have; older peers get what their protocol version brought
+#[test]
+fn capabilities_in_common() {
+    use super::capability::Capabilities;
+    use super::registry::Registry;
+    use super::session::Direction;
+    let local = Capabilities::local();
+    assert_eq!(local.names(), vec!["compression", "acks", "dedup_chunks", "file_lists", "scoped_sync"]);
+    let v2 = local.common(Capabilities::implied(2));
+    assert!(v2.contains(Capabilities::COMPRESSION) && v2.contains(Capabilities::DEDUP_CHUNKS));
+    assert!(!v2.contains(Capabilities::ACKS) && !v2.contains(Capabilities::FILE_LISTS));
+    assert!(local.common(Capabilities::implied(3)).contains(Capabilities::ACKS));
+    // Features of newer peers this build does not know are never in common
+    assert_eq!(local.common(Capabilities::from_bits(u64::MAX)), local);
+    assert_eq!(Capabilities::from_bits(1 << 63).common(Capabilities::from_bits(1 << 63)), Capabilities::default());
+
+    let mut registry = Registry::default();
+    registry.open("10.0.0.2", Direction::Inbound, "0123", None, None).unwrap();
+    registry.get_mut("10.0.0.2").unwrap().capabilities = local.common(Capabilities::FILE_LISTS | Capabilities::ACKS);
+    assert!(registry.supports("10.0.0.2", Capabilities::FILE_LISTS));
+    assert!(!registry.supports("10.0.0.2", Capabilities::SCOPED_SYNC));
+    assert!(!registry.supports("10.0.0.9", Capabilities::FILE_LISTS));
+    assert_eq!(registry.connections()["10.0.0.2"].capabilities, vec!["acks", "file_lists"]);
+}
+
+#[test]
+fn outbox_resends_pushes_and_reports_file_frames() {
+    use super::delivery::{Next, Outbox, ACK_TIMEOUT, FILE_ACK_TIMEOUT, MAX_RESENDS};
+    let start = Instant::now();
+    let mut outbox = Outbox::default();
+    let frame = (*b"FILE:", b"local.json|{}".to_vec());
+    let (old, mut old_verdict) = outbox.register("local.json", Some(frame.clone()), ACK_TIMEOUT, start);
+    // A newer push of the same conversation replaces the one still waiting
+    let (push, mut push_verdict) = outbox.register("local.json", Some(frame.clone()), ACK_TIMEOUT, start);
+    assert!(old_verdict.try_recv().is_err() && old != push);
+    assert_eq!(outbox.nacked(old, "late", start), None);
+    let (chunk, mut chunk_verdict) = outbox.register("a.bin chunk 1/1", None, FILE_ACK_TIMEOUT, start);
+    assert_eq!(outbox.stats().pending, 2);
+
+    assert_eq!(outbox.nacked(push, "disk full", start), Some(Next::Resend(push, frame.clone())));
+    assert!(outbox.overdue(start + ACK_TIMEOUT / 2).is_empty());
+    let later = start + ACK_TIMEOUT;
+    assert_eq!(outbox.overdue(later), vec![Next::Resend(push, frame)]);
+    assert_eq!(MAX_RESENDS, 2);
+    let failed = outbox.overdue(later + ACK_TIMEOUT);
+    assert_eq!(failed, vec![Next::Failed("local.json".into(), "no acknowledgement".into())]);
+    assert_eq!(push_verdict.try_recv().unwrap(), Err("no acknowledgement".to_string()));
+
+    // File frames are never sent again; their verdict carries the refusal
+    assert_eq!(outbox.nacked(chunk, "hash mismatch", later), None);
+    assert_eq!(chunk_verdict.try_recv().unwrap(), Err("hash mismatch".to_string()));
+    let (last, mut last_verdict) = outbox.register("a.bin chunk 1/1", None, FILE_ACK_TIMEOUT, later);
+    outbox.acked(last);
+    outbox.acked(last);
+    assert_eq!(last_verdict.try_recv().unwrap(), Ok(()));
+
+    let stats = outbox.stats();
+    assert_eq!((stats.sent, stats.acked, stats.nacked, stats.resent, stats.failed, stats.pending), (4, 1, 2, 2, 2, 0));
+    assert_eq!(stats.last_failure.as_deref(), Some("a.bin chunk 1/1: hash mismatch"));
+}
+
+#[test]
+fn replay_window_rejects_stale_and_duplicate_nonces() {
+    let mut window = auth::ReplayWindow::default();
+    let now = 1_700_000_000;
+    assert!(window.check(now, "aa", now).is_ok());
+    assert!(window.check(now, "aa", now + 1).is_err());
+    assert!(window.check(now - auth::MAX_CLOCK_SKEW_SECS - 1, "bb", now).is_err());
+    assert!(window.check(now + auth::MAX_CLOCK_SKEW_SECS + 1, "cc", now).is_err());
+    // Entries expire once their timestamp leaves the window
+    assert!(window.check(now + 2 * auth::MAX_CLOCK_SKEW_SECS, "aa", now + 2 * auth::MAX_CLOCK_SKEW_SECS).is_ok());
+}
+
+#[test]
+fn file_meta_signature_covers_nonce_and_time() {
+    let payload = file_meta_payload("a.txt", "text/plain", 3, &"a".repeat(64), "2024-01-01T00:00:00Z", 1_700_000_000, "ab");
+    let sig = sign_file_meta("secret", &payload);
+    assert!(verify_file_meta("secret", &payload, &sig));
+    let replayed = file_meta_payload("a.txt", "text/plain", 3, &"a".repeat(64), "2024-01-01T00:00:00Z", 1_700_000_500, "ab");
+    assert!(!verify_file_meta("secret", &replayed, &sig));
+    let renonced = file_meta_payload("a.txt", "text/plain", 3, &"a".repeat(64), "2024-01-01T00:00:00Z", 1_700_000_000, "cd");
+    assert!(!verify_file_meta("secret", &renonced, &sig));
+}
+
+// FILE_META frames arriving back to back over a real socket on a multi-threaded runtime: receiving
+// must never block on the secret (it used to panic), and only correctly signed announcements count
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn file_meta_receipt_under_load() {
+    const COUNT: usize = 200;
+    const PEER: &str = "192.0.2.77";
+    set_p2p_secret("load-test-secret".to_string()).await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("addr");
+
+    let sender = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await.expect("connect");
+        for i in 0..COUNT {
+            let filename = format!("load-{}.txt", i);
+            let sha256_hex = "c".repeat(64);
+            let uploaded_at = "2024-01-01T00:00:00+00:00".to_string();
+            let (signed_at, nonce, mut hmac_hex) = stamp_file_meta(&filename, "text/plain", i as u64, &sha256_hex, &uploaded_at).await;
+            // Every tenth announcement is forged
+            if i % 10 == 0 {
+                hmac_hex = "0".repeat(64);
+            }
+            let meta = Message::FileMeta { filename, file_type: "text/plain".into(), file_size: i as u64, sha256_hex, uploaded_at, signed_at, nonce, hmac_hex };
+            meta.send(&mut stream).await.expect("send");
+        }
+    });
+
+    let (mut stream, _) = listener.accept().await.expect("accept");
+    let mut handlers = Vec::new();
+    while let Some(message) = Message::receive(&mut stream).await.expect("receive") {
+        handlers.push(tokio::spawn(handle_file_meta(PEER, message)));
+    }
+    sender.await.expect("sender");
+    let mut accepted = 0;
+    for handler in handlers {
+        if handler.await.expect("handler").is_ok() {
+            accepted += 1;
+        }
+    }
+    assert_eq!(accepted, COUNT - COUNT / 10);
+    let announced = get_announced_files().await.into_iter().filter(|f| f.uploader_ip == PEER).count();
+    assert_eq!(announced, accepted);
+}
+
+
+struct Vector {
+    name: String,
+    frame: Vec<u8>,
+    decoded: String,
+    canonical: Option<Vec<u8>>,
+}
+
+fn parse_vectors(text: &str) -> Vec<Vector> {
+    let hex_field = |value: &str| hex::decode(value.split_whitespace().collect::<String>()).expect("hex frame");
+    let mut vectors: Vec<Vector> = Vec::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            vectors.push(Vector { name: name.to_string(), frame: Vec::new(), decoded: String::new(), canonical: None });
+            continue;
+        }
+        let vector = vectors.last_mut().expect("field before the first [name]");
+        let (key, value) = line.split_once(" = ").unwrap_or_else(|| panic!("{}: malformed line {:?}", vector.name, line));
+        match key {
+            "frame" => vector.frame = hex_field(value),
+            "decoded" => vector.decoded = value.to_string(),
+            "canonical" => vector.canonical = Some(hex_field(value)),
+            other => panic!("{}: unknown field {}", vector.name, other),
+        }
+    }
+    vectors
+}
+
+// Frames in vectors.txt keep decoding the same way, so protocol changes stay backward compatible
+#[test]
+fn conformance_vectors() {
+    let vectors = parse_vectors(include_str!("vectors.txt"));
+    let mut covered = HashSet::new();
+    for v in &vectors {
+        assert!(v.frame.len() >= 13, "{}: frame shorter than its header", v.name);
+        let marker: [u8; 5] = v.frame[..5].try_into().unwrap();
+        let len = u64::from_le_bytes(v.frame[5..13].try_into().unwrap());
+        let payload = v.frame[13..].to_vec();
+        assert_eq!(len, payload.len() as u64, "{}: length field", v.name);
+        let result = Message::decode(&marker, payload);
+        if v.decoded == "error" {
+            assert!(result.is_err(), "{}: should be rejected, got {:?}", v.name, result);
+            continue;
+        }
+        let message = result.unwrap_or_else(|e| panic!("{}: {}", v.name, e)).expect("message");
+        assert_eq!(format!("{:?}", message), v.decoded, "{}: decoded message", v.name);
+        let (marker, payload) = message.encode().unwrap();
+        let mut encoded = marker.to_vec();
+        encoded.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        encoded.extend_from_slice(&payload);
+        assert_eq!(&encoded, v.canonical.as_ref().unwrap_or(&v.frame), "{}: re-encoded frame", v.name);
+        covered.insert(marker);
+    }
+    for marker in MARKERS.iter().filter(|m| is_known_marker(m)) {
+        assert!(covered.contains(*marker), "no valid vector for {}", String::from_utf8_lossy(*marker));
+    }
+}
+
+// Payloads exactly at a type's limit are read, one byte more drops the connection, and unknown
+// types are skipped whole
+#[tokio::test]
+async fn frames_at_size_limits() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("addr");
+    let reason = "é".repeat((MAX_REQUEST_SIZE - 2) / 2);
+    let at_limit = format!("a|{}", reason).into_bytes();
+    assert_eq!(at_limit.len(), MAX_REQUEST_SIZE);
+    let sender = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await.expect("connect");
+        write_frame(&mut stream, b"NEWT:", &[7u8; 100]).await.expect("unknown");
+        write_frame(&mut stream, b"LREQ:", &at_limit).await.expect("at limit");
+        write_frame(&mut stream, b"PING:", &vec![b'1'; MAX_CONTROL_SIZE + 1]).await.expect("over limit");
+    });
+    let (mut stream, _) = listener.accept().await.expect("accept");
+
+    let (marker, data) = read_frame(&mut stream).await.expect("unknown frame").expect("frame");
+    assert_eq!((&marker, data.len()), (b"NEWT:", 0));
+    let (marker, data) = read_frame(&mut stream).await.expect("frame at limit").expect("frame");
+    match Message::decode(&marker, data).expect("decode") {
+        Some(Message::LLMAccessRequest { peer_name, reason: r }) => assert_eq!((peer_name.as_str(), r), ("a", reason)),
+        other => panic!("unexpected {:?}", other),
+    }
+    let err = read_frame(&mut stream).await.expect_err("oversized frame");
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    sender.await.expect("sender");
+}
+
+// At the peer limit only a higher-ranked peer gets in, in place of the lowest-ranked one: LLM hosts
+// first, then the most recently active
+#[test]
+fn peer_limit_admits_by_rank() {
+    let now = Instant::now();
+    let peer = |ip: &str, llm_host: bool, ago: Option<u64>| admission::Candidate {
+        ip: ip.to_string(),
+        llm_host,
+        last_active: ago.map(|s| now - Duration::from_secs(s)),
+    };
+    let connected = vec![peer("10.0.0.1", true, Some(500)), peer("10.0.0.2", false, Some(10)), peer("10.0.0.3", false, Some(300))];
+    assert_eq!(admission::admit(4, &connected, &peer("10.0.0.9", false, None)), admission::Verdict::Admit);
+    assert_eq!(admission::admit(0, &connected, &peer("10.0.0.9", false, None)), admission::Verdict::Admit);
+    assert_eq!(admission::admit(3, &connected, &peer("10.0.0.2", false, None)), admission::Verdict::Admit);
+    assert_eq!(admission::admit(3, &connected, &peer("10.0.0.9", false, None)), admission::Verdict::Refuse);
+    assert_eq!(admission::admit(3, &connected, &peer("10.0.0.9", false, Some(300))), admission::Verdict::Refuse);
+    assert_eq!(admission::admit(3, &connected, &peer("10.0.0.9", false, Some(60))), admission::Verdict::Evict("10.0.0.3".into()));
+    assert_eq!(admission::admit(3, &connected, &peer("10.0.0.9", true, None)), admission::Verdict::Evict("10.0.0.3".into()));
+}
+
+// Accepts are let through in bursts up to the limit and refilled at the configured rate
+#[test]
+fn accept_rate_refills_over_time() {
+    let start = Instant::now();
+    let mut admission = admission::Admission::new(admission::Limits { max_peers: 0, accept_rate: 2.0, accept_burst: 3.0 });
+    assert!((0..3).all(|_| admission.accept(start)));
+    assert!(!admission.accept(start));
+    assert!(admission.accept(start + Duration::from_millis(500)));
+    assert!(!admission.accept(start + Duration::from_millis(600)));
+    assert!((0..3).all(|_| admission.accept(start + Duration::from_secs(10))));
+    assert!(!admission.accept(start + Duration::from_secs(10)));
+    assert_eq!(admission.stats.refused_rate, 3);
+}
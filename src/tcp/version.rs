@@ -0,0 +1,80 @@
+// Version negotiation. Each side states its crate version and protocol version: the connecting
+// side inside the AUTH: handshake, the accepting side in a VERS: frame sent back after it. Peers
+// below MIN_PROTOCOL_VERSION are turned away at the handshake; everything learned is kept for
+// /api/peers/info together with an upgrade advisory when versions differ.
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+// 1: unversioned handshake (timestamp|nonce|hmac). 2: handshake and VERS: carry versions.
+// 3: SEQN: envelopes answered with ACKN:/NACK: (see delivery). 4: CAPS: after the handshake (see
+// capability)
+pub const PROTOCOL_VERSION: u32 = 4;
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+pub const CAPS_PROTOCOL_VERSION: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeVersion {
+    pub crate_version: String,
+    pub protocol: u32,
+}
+
+impl NodeVersion {
+    pub fn local() -> NodeVersion {
+        NodeVersion { crate_version: env!("CARGO_PKG_VERSION").to_string(), protocol: PROTOCOL_VERSION }
+    }
+}
+
+pub fn is_compatible(protocol: u32) -> bool {
+    protocol >= MIN_PROTOCOL_VERSION
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerVersion {
+    pub crate_version: Option<String>,
+    pub protocol_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    pub compatible: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub advisory: Option<String>,
+    pub seen_at: DateTime<Utc>,
+}
+
+// "1.2.3-beta" -> (1, 2, 3); missing or non-numeric parts count as 0
+fn parse_semver(v: &str) -> (u64, u64, u64) {
+    let mut parts = v.split(['-', '+']).next().unwrap_or("").split('.').map(|p| p.parse::<u64>().unwrap_or(0));
+    (parts.next().unwrap_or(0), parts.next().unwrap_or(0), parts.next().unwrap_or(0))
+}
+
+// Whether crate version `candidate` is later than `current`
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    parse_semver(candidate) > parse_semver(current)
+}
+
+// What, if anything, should be upgraded given a peer's versions
+pub fn advisory(peer_crate: Option<&str>, peer_protocol: u32) -> Option<String> {
+    if peer_protocol < MIN_PROTOCOL_VERSION {
+        return Some(format!(
+            "peer speaks protocol {} but this node needs at least {}; upgrade the peer",
+            peer_protocol, MIN_PROTOCOL_VERSION
+        ));
+    }
+    let ours = env!("CARGO_PKG_VERSION");
+    let theirs = peer_crate?;
+    match parse_semver(theirs).cmp(&parse_semver(ours)) {
+        std::cmp::Ordering::Greater => Some(format!("peer runs {} (this node {}); consider upgrading this node", theirs, ours)),
+        std::cmp::Ordering::Less => Some(format!("peer runs older {} (this node {}); consider upgrading the peer", theirs, ours)),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+pub fn peer_version(crate_version: Option<String>, protocol_version: u32, node_id: Option<String>) -> PeerVersion {
+    PeerVersion {
+        advisory: advisory(crate_version.as_deref(), protocol_version),
+        compatible: is_compatible(protocol_version),
+        crate_version,
+        protocol_version,
+        node_id,
+        seen_at: Utc::now(),
+    }
+}
@@ -0,0 +1,147 @@
+// Field-level parsing helpers for TCP frames. Every parser validates bounds and format and
+// returns InvalidData instead of guessing defaults, so a malformed frame is rejected as a whole.
+use std::io::{Error, ErrorKind};
+
+const MAX_FILENAME_LEN: usize = 255;
+const MAX_MIME_LEN: usize = 127;
+const MAX_NAME_LEN: usize = 255;
+// A chunked transfer can never need more chunks than this
+pub const MAX_TOTAL_CHUNKS: u32 = 1_000_000;
+
+pub fn invalid(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+// Markers are four uppercase ASCII letters/digits followed by ':'
+pub fn is_marker_shaped(marker: &[u8; 5]) -> bool {
+    marker[4] == b':' && marker[..4].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+// Split the first `n` '|'-terminated UTF-8 fields off `data`, returning them and the remaining bytes
+pub fn split_fields(data: &[u8], n: usize) -> Result<(Vec<&str>, &[u8]), Error> {
+    let mut fields = Vec::with_capacity(n);
+    let mut rest = data;
+    for i in 0..n {
+        let pos = rest
+            .iter()
+            .position(|b| *b == b'|')
+            .ok_or_else(|| invalid(format!("missing field {} of {}", i + 1, n)))?;
+        let field = std::str::from_utf8(&rest[..pos]).map_err(|_| invalid(format!("field {} is not UTF-8", i + 1)))?;
+        fields.push(field);
+        rest = &rest[pos + 1..];
+    }
+    Ok((fields, rest))
+}
+
+pub fn utf8(data: &[u8], what: &str) -> Result<String, Error> {
+    String::from_utf8(data.to_vec()).map_err(|_| invalid(format!("{} is not UTF-8", what)))
+}
+
+// A bare file name: no path components, separators, control characters or '|'
+pub fn filename(s: &str) -> Result<String, Error> {
+    if s.is_empty() || s.len() > MAX_FILENAME_LEN {
+        return Err(invalid("filename length out of range"));
+    }
+    if s == "." || s == ".." || s.contains(['/', '\\', '|']) || s.chars().any(|c| c.is_control()) {
+        return Err(invalid(format!("illegal filename {:?}", s)));
+    }
+    Ok(s.to_string())
+}
+
+// "type/subtype" with token characters only
+pub fn mime(s: &str) -> Result<String, Error> {
+    let valid_token = |t: &str| !t.is_empty() && t.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&^_.+-".contains(c));
+    match s.split_once('/') {
+        Some((t, sub)) if s.len() <= MAX_MIME_LEN && valid_token(t) && valid_token(sub) => Ok(s.to_string()),
+        _ => Err(invalid(format!("illegal file type {:?}", s))),
+    }
+}
+
+// Short human-readable name (hostname etc.)
+pub fn name(s: &str) -> Result<String, Error> {
+    if s.len() > MAX_NAME_LEN || s.chars().any(|c| c.is_control()) {
+        return Err(invalid("illegal name"));
+    }
+    Ok(s.to_string())
+}
+
+// "local", a fork id or a peer address
+pub fn conversation_id(s: &str) -> Result<String, Error> {
+    if s.is_empty() || s.len() > 64 || !s.chars().all(|c| c.is_ascii_alphanumeric() || ".:-".contains(c)) {
+        return Err(invalid(format!("illegal conversation id {:?}", s)));
+    }
+    Ok(s.to_string())
+}
+
+// Message ids are random hex, or "<millis>-<index>" for messages saved before ids existed
+pub fn message_id(s: &str) -> Result<String, Error> {
+    if s.is_empty() || s.len() > 64 || !s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(invalid(format!("illegal message id {:?}", s)));
+    }
+    Ok(s.to_string())
+}
+
+pub fn parse_u64(s: &str, what: &str) -> Result<u64, Error> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid(format!("{} is not a number", what)));
+    }
+    s.parse().map_err(|_| invalid(format!("{} out of range", what)))
+}
+
+pub fn parse_u32(s: &str, what: &str) -> Result<u32, Error> {
+    let v = parse_u64(s, what)?;
+    u32::try_from(v).map_err(|_| invalid(format!("{} out of range", what)))
+}
+
+// Unix seconds; negative values are never valid on the wire
+pub fn unix_time(s: &str) -> Result<i64, Error> {
+    i64::try_from(parse_u64(s, "timestamp")?).map_err(|_| invalid("timestamp out of range"))
+}
+
+pub fn parse_bool(s: &str, what: &str) -> Result<bool, Error> {
+    match s {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(invalid(format!("{} is not a boolean", what))),
+    }
+}
+
+pub fn sha256_hex(s: &str) -> Result<String, Error> {
+    if s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(s.to_ascii_lowercase())
+    } else {
+        Err(invalid("malformed SHA-256"))
+    }
+}
+
+// Hex nonce of 16 to 64 characters
+pub fn nonce(s: &str) -> Result<String, Error> {
+    if (16..=64).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(s.to_ascii_lowercase())
+    } else {
+        Err(invalid("malformed nonce"))
+    }
+}
+
+// HMAC-SHA256 hex, or empty when the sender has no shared secret
+pub fn hmac_hex(s: &str) -> Result<String, Error> {
+    if s.is_empty() {
+        return Ok(String::new());
+    }
+    sha256_hex(s)
+}
+
+// Crate version such as "0.1.0" or "1.2.0-beta.1"
+pub fn version(s: &str) -> Result<String, Error> {
+    if (1..=32).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_alphanumeric() || b".+-".contains(&b)) {
+        Ok(s.to_string())
+    } else {
+        Err(invalid("malformed version"))
+    }
+}
+
+pub fn rfc3339(s: &str) -> Result<String, Error> {
+    chrono::DateTime::parse_from_rfc3339(s)
+        .map(|_| s.to_string())
+        .map_err(|_| invalid("malformed timestamp"))
+}
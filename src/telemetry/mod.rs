@@ -0,0 +1,206 @@
+// Compute load of LLM hosts. A node whose backend is up measures its CPU and memory (sysinfo) and
+// its NVIDIA GPUs (NVML, when the driver is installed) and sends the numbers to every peer with the
+// periodic share (LOAD: frames). GET /api/llm/peers lists each LLM host with its latest load, so
+// users can see why answers are slow, and prompts go to the least loaded host first.
+use actix_web::{get, Error, HttpResponse};
+use nvml_wrapper::Nvml;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+use sysinfo::System;
+use tokio::sync::Mutex;
+
+// Most GPUs one report may list
+pub const MAX_GPUS: usize = 16;
+// Reports come every 30s; a host silent for three rounds has no known load
+const REPORT_TTL: Duration = Duration::from_secs(90);
+// A host this busy (CPU or any GPU, in percent) is asked only when no other host is free
+const OVERLOADED_PERCENT: f32 = 90.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuLoad {
+    pub name: String,
+    pub utilization_percent: u32,
+    pub memory_used_bytes: u64,
+    pub memory_total_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComputeLoad {
+    // Across all cores, since the previous measurement
+    pub cpu_percent: f32,
+    pub memory_used_bytes: u64,
+    pub memory_total_bytes: u64,
+    #[serde(default)]
+    pub gpus: Vec<GpuLoad>,
+}
+
+impl ComputeLoad {
+    // The busiest of the CPU and the GPUs; models run on the GPU when there is one
+    pub fn busiest_percent(&self) -> f32 {
+        self.gpus.iter().map(|g| g.utilization_percent as f32).fold(self.cpu_percent, f32::max)
+    }
+
+    pub fn overloaded(&self) -> bool {
+        self.busiest_percent() >= OVERLOADED_PERCENT
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=100.0).contains(&self.cpu_percent) {
+            return Err(format!("CPU load {} out of range", self.cpu_percent));
+        }
+        if self.memory_used_bytes > self.memory_total_bytes {
+            return Err("memory used exceeds the total".to_string());
+        }
+        if self.gpus.len() > MAX_GPUS {
+            return Err(format!("{} GPUs listed", self.gpus.len()));
+        }
+        for gpu in &self.gpus {
+            if gpu.utilization_percent > 100 || gpu.memory_used_bytes > gpu.memory_total_bytes {
+                return Err(format!("GPU {:?} load out of range", gpu.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Kept between measurements, which CPU usage is computed across
+static SYSTEM: Lazy<StdMutex<System>> = Lazy::new(|| StdMutex::new(System::new()));
+// None without an NVIDIA driver
+static NVML: Lazy<Option<Nvml>> = Lazy::new(|| Nvml::init().ok());
+// Peer IP -> (its latest load, when it arrived)
+static LOADS: Lazy<Mutex<HashMap<String, (ComputeLoad, Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn gpus() -> Vec<GpuLoad> {
+    let Some(nvml) = NVML.as_ref() else {
+        return Vec::new();
+    };
+    let count = nvml.device_count().unwrap_or(0).min(MAX_GPUS as u32);
+    (0..count)
+        .filter_map(|i| {
+            let device = nvml.device_by_index(i).ok()?;
+            let memory = device.memory_info().ok()?;
+            Some(GpuLoad {
+                name: device.name().unwrap_or_default(),
+                utilization_percent: device.utilization_rates().map(|u| u.gpu.min(100)).unwrap_or(0),
+                memory_used_bytes: memory.used.min(memory.total),
+                memory_total_bytes: memory.total,
+            })
+        })
+        .collect()
+}
+
+// This node's load right now
+pub fn local() -> ComputeLoad {
+    let mut system = SYSTEM.lock().unwrap();
+    system.refresh_cpu_usage();
+    system.refresh_memory();
+    ComputeLoad {
+        cpu_percent: system.global_cpu_usage().clamp(0.0, 100.0),
+        memory_used_bytes: system.used_memory().min(system.total_memory()),
+        memory_total_bytes: system.total_memory(),
+        gpus: gpus(),
+    }
+}
+
+pub async fn received(peer_ip: &str, load: ComputeLoad) {
+    let mut loads = LOADS.lock().await;
+    loads.retain(|_, (_, at)| at.elapsed() < REPORT_TTL);
+    loads.insert(peer_ip.to_string(), (load, Instant::now()));
+}
+
+// A peer's latest load and its age, unless it is too old to trust
+pub async fn peer_load(peer_ip: &str) -> Option<(ComputeLoad, Duration)> {
+    LOADS.lock().await.get(peer_ip).filter(|(_, at)| at.elapsed() < REPORT_TTL).map(|(load, at)| (load.clone(), at.elapsed()))
+}
+
+// Peers in the order to ask them: free hosts from least to most loaded, then hosts with no recent
+// report, then overloaded ones
+pub async fn by_load(mut peers: Vec<String>) -> Vec<String> {
+    let loads = LOADS.lock().await;
+    let rank = |ip: &String| match loads.get(ip).filter(|(_, at)| at.elapsed() < REPORT_TTL) {
+        Some((load, _)) if load.overloaded() => (2, load.busiest_percent()),
+        Some((load, _)) => (0, load.busiest_percent()),
+        None => (1, 0.0),
+    };
+    peers.sort_by(|a, b| {
+        let (ra, rb) = (rank(a), rank(b));
+        ra.0.cmp(&rb.0).then(ra.1.total_cmp(&rb.1)).then_with(|| a.cmp(b))
+    });
+    peers
+}
+
+#[derive(Serialize)]
+struct HostLoad {
+    ip: String,
+    local: bool,
+    // Peers that granted us access to their model
+    access: bool,
+    load: Option<ComputeLoad>,
+    overloaded: bool,
+    // Seconds since the load was measured; None when unknown
+    load_age_secs: Option<u64>,
+    // Seconds until prompts are sent to the host again after repeated failures
+    circuit_open_secs: Option<u64>,
+    // Average time to a full answer, which the fastest routing orders hosts by
+    answer_ms: Option<u64>,
+}
+
+// LLM hosts of the mesh with their compute load, this node first when it hosts one
+#[get("/llm/peers")]
+pub async fn llm_peers() -> Result<HttpResponse, Error> {
+    let mut hosts = Vec::new();
+    if crate::llm::backend::available().await {
+        let load = local();
+        hosts.push(HostLoad {
+            ip: "local".to_string(),
+            local: true,
+            access: true,
+            overloaded: load.overloaded(),
+            load: Some(load),
+            load_age_secs: Some(0),
+            circuit_open_secs: crate::llm::policy::open_for("local"),
+            answer_ms: crate::llm::routing::latency("local").map(|d| d.as_millis() as u64),
+        });
+    }
+    let access: Vec<String> = crate::tcp::llm_connections().into_iter().map(|(peer, _)| peer).collect();
+    let mut ips = crate::tcp::llm_peer_ips().await;
+    ips.extend(access.iter().filter(|ip| !ips.contains(ip)).cloned().collect::<Vec<_>>());
+    for ip in by_load(ips).await {
+        let (load, age) = peer_load(&ip).await.unzip();
+        hosts.push(HostLoad {
+            access: access.contains(&ip),
+            circuit_open_secs: crate::llm::policy::open_for(&ip),
+            answer_ms: crate::llm::routing::latency(&ip).map(|d| d.as_millis() as u64),
+            ip,
+            local: false,
+            overloaded: load.as_ref().is_some_and(ComputeLoad::overloaded),
+            load,
+            load_age_secs: age.map(|a| a.as_secs()),
+        });
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "hosts": hosts })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{by_load, received, ComputeLoad, GpuLoad};
+
+    // Free hosts go first, least loaded first; overloaded hosts come after those with no report
+    #[tokio::test]
+    async fn llm_hosts_are_ordered_by_load() {
+        let load = |cpu_percent, gpu: Option<u32>| ComputeLoad {
+            cpu_percent,
+            memory_used_bytes: 0,
+            memory_total_bytes: 0,
+            gpus: gpu.map(|u| GpuLoad { name: "g".into(), utilization_percent: u, memory_used_bytes: 0, memory_total_bytes: 0 }).into_iter().collect(),
+        };
+        received("198.51.100.21", load(10.0, Some(95))).await;
+        received("198.51.100.22", load(60.0, None)).await;
+        received("198.51.100.23", load(5.0, Some(20))).await;
+        let peers = ["198.51.100.21", "198.51.100.22", "198.51.100.23", "198.51.100.24"].map(String::from).to_vec();
+        assert_eq!(by_load(peers).await, ["198.51.100.23", "198.51.100.22", "198.51.100.24", "198.51.100.21"]);
+    }
+}
@@ -0,0 +1,126 @@
+// Optional HTTPS for the web UI and API on port 8080. Off unless TLS=1, which serves a self-signed
+// certificate generated on first start (kept in tls/ and reused; delete it to get a new one), or
+// TLS_CERT_FILE and TLS_KEY_FILE, which point at your own PEM certificate chain and private key.
+// Nodes put their scheme in UDP announcements, so peers call each other's API with the right one.
+use once_cell::sync::Lazy;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex as StdMutex, OnceLock};
+
+const CERT_DIR: &str = "tls";
+const CERT_FILE: &str = "tls/cert.pem";
+const KEY_FILE: &str = "tls/key.pem";
+
+// Peers whose last announcement said they serve HTTPS
+static HTTPS_PEERS: Lazy<StdMutex<HashSet<String>>> = Lazy::new(|| StdMutex::new(HashSet::new()));
+
+fn user_files() -> Option<(String, String)> {
+    let var = |name| std::env::var(name).ok().filter(|v: &String| !v.trim().is_empty());
+    Some((var("TLS_CERT_FILE")?, var("TLS_KEY_FILE")?))
+}
+
+pub fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        user_files().is_some() || std::env::var("TLS").map(|v| v == "1" || v.eq_ignore_ascii_case("true")).unwrap_or(false)
+    })
+}
+
+pub fn scheme() -> &'static str {
+    if enabled() { "https" } else { "http" }
+}
+
+// Where the browser reaches our own UI
+pub fn ui_url() -> String {
+    format!("{}://localhost:8080/app/", scheme())
+}
+
+// Names the self-signed certificate is valid for: localhost, the hostname and every local address
+fn subject_alt_names() -> Vec<String> {
+    let mut names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+    if let Ok(host) = hostname::get() {
+        let host = host.to_string_lossy().to_string();
+        if !host.is_empty() && host.is_ascii() {
+            names.push(host);
+        }
+    }
+    names.extend(crate::ip::local_ipv4s().iter().map(|ip| ip.to_string()));
+    names.dedup();
+    names
+}
+
+fn generate_self_signed() -> Result<(), String> {
+    let names = subject_alt_names();
+    let generated = rcgen::generate_simple_self_signed(names.clone()).map_err(|e| format!("generating certificate: {}", e))?;
+    std::fs::create_dir_all(CERT_DIR).map_err(|e| e.to_string())?;
+    std::fs::write(CERT_FILE, generated.cert.pem()).map_err(|e| format!("{}: {}", CERT_FILE, e))?;
+    std::fs::write(KEY_FILE, generated.key_pair.serialize_pem()).map_err(|e| format!("{}: {}", KEY_FILE, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(KEY_FILE, std::fs::Permissions::from_mode(0o600));
+    }
+    println!("TLS: Generated a self-signed certificate for {} in {}", names.join(", "), CERT_FILE);
+    Ok(())
+}
+
+fn read_pem(cert_path: &str, key_path: &str) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), String> {
+    let cert_pem = std::fs::read(cert_path).map_err(|e| format!("{}: {}", cert_path, e))?;
+    let key_pem = std::fs::read(key_path).map_err(|e| format!("{}: {}", key_path, e))?;
+    let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", cert_path));
+    }
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .map_err(|e| format!("{}: {}", key_path, e))?
+        .ok_or_else(|| format!("{}: no private key found", key_path))?;
+    Ok((certs, key))
+}
+
+// Server configuration for the HTTPS listener, generating the self-signed certificate if needed
+pub fn server_config() -> Result<rustls::ServerConfig, String> {
+    let (cert_path, key_path) = match user_files() {
+        Some(files) => files,
+        None => {
+            if !Path::new(CERT_FILE).is_file() || !Path::new(KEY_FILE).is_file() {
+                generate_self_signed()?;
+            }
+            (CERT_FILE.to_string(), KEY_FILE.to_string())
+        }
+    };
+    let (certs, key) = read_pem(&cert_path, &key_path)?;
+    println!("TLS: Serving HTTPS with {}", cert_path);
+    rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("{}: {}", cert_path, e))
+}
+
+// Remember the scheme a peer announced
+pub fn set_peer_scheme(ip: &str, scheme: &str) {
+    let mut peers = HTTPS_PEERS.lock().unwrap();
+    if scheme == "https" {
+        peers.insert(ip.to_string());
+    } else {
+        peers.remove(ip);
+    }
+}
+
+// Base URL of a peer's HTTP API, e.g. "https://10.0.0.5:8080"
+pub fn peer_origin(host: &str, port: impl std::fmt::Display) -> String {
+    let scheme = if HTTPS_PEERS.lock().unwrap().contains(host) { "https" } else { "http" };
+    format!("{}://{}:{}", scheme, host, port)
+}
+
+// Client builder for requests to peers. Their certificates are usually self-signed and cannot be
+// verified, so the connection is encrypted but, as over plain HTTP, the peer is known by address only.
+pub fn peer_client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .local_address(crate::ip::bind_ip().map(std::net::IpAddr::V4))
+}
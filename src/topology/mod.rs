@@ -0,0 +1,156 @@
+// Map of the whole mesh. Every connection gossips the sender's own view (MESH: frames, sent with
+// the periodic share): its node id, hostname, whether it hosts an LLM and the peers it is connected
+// to with their round-trip times. GET /api/mesh/topology joins the latest report from each peer with
+// our own connections into one graph of nodes and edges. Reports expire, so nodes that left fade
+// out of the map.
+use actix_web::{get, Error, HttpResponse};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// Most peers one report may list
+pub const MAX_LINKS: usize = 256;
+// Reports are resent every 30s; one missing three rounds belongs to a node that is gone
+const REPORT_TTL: Duration = Duration::from_secs(90);
+// Id of this node in the graph when it does not listen on a single address
+const LOCAL_ID: &str = "local";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerLink {
+    pub ip: String,
+    // Median TCP round trip as measured by the reporting node
+    pub latency_ms: Option<i64>,
+}
+
+// One node's view of its own connections
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerList {
+    pub node_id: String,
+    pub hostname: String,
+    pub llm_host: bool,
+    pub peers: Vec<PeerLink>,
+}
+
+// Peer IP -> (its latest report, when it arrived)
+static REPORTS: Lazy<Mutex<HashMap<String, (PeerList, Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// What we tell our peers about ourselves
+pub async fn local_report() -> PeerList {
+    let mut ips = crate::tcp::connected_peer_ips().await;
+    ips.sort();
+    ips.truncate(MAX_LINKS);
+    let mut peers = Vec::with_capacity(ips.len());
+    for ip in ips {
+        let latency_ms = crate::latency::tcp_median_ms(&ip).await;
+        peers.push(PeerLink { ip, latency_ms });
+    }
+    PeerList {
+        node_id: crate::persistence::node_id().to_string(),
+        hostname: hostname::get().map(|h| h.to_string_lossy().to_string()).unwrap_or_default(),
+        llm_host: crate::tcp::is_llm_available().await,
+        peers,
+    }
+}
+
+pub async fn received(peer_ip: &str, report: PeerList) {
+    let mut reports = REPORTS.lock().await;
+    reports.retain(|_, (_, at)| at.elapsed() < REPORT_TTL);
+    reports.insert(peer_ip.to_string(), (report, Instant::now()));
+}
+
+#[derive(Serialize)]
+struct Node {
+    id: String,
+    node_id: Option<String>,
+    hostname: Option<String>,
+    llm_host: bool,
+    local: bool,
+    // Directly connected to this node
+    connected: bool,
+    // Seconds since its last report; None for nodes only others mention
+    report_age_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Edge {
+    source: String,
+    target: String,
+    // Mean of the round trips both ends reported
+    latency_ms: Option<i64>,
+}
+
+#[get("/mesh/topology")]
+pub async fn mesh_topology() -> Result<HttpResponse, Error> {
+    let local_id = crate::ip::bind_ip().map(|ip| ip.to_string()).unwrap_or_else(|| LOCAL_ID.to_string());
+    // Peers name us by whichever of our addresses they connected to
+    let node_key = |ip: &str| if crate::ip::is_my_ip(ip) { local_id.clone() } else { ip.to_string() };
+    let own = local_report().await;
+    let connected = crate::tcp::connected_peer_ips().await;
+    let llm_hosts = crate::tcp::llm_peer_ips().await;
+
+    let mut nodes: BTreeMap<String, Node> = BTreeMap::new();
+    let node = |id: String, nodes: &mut BTreeMap<String, Node>| {
+        nodes.entry(id.clone()).or_insert_with(|| Node {
+            connected: connected.contains(&id),
+            llm_host: llm_hosts.contains(&id),
+            id,
+            node_id: None,
+            hostname: None,
+            local: false,
+            report_age_secs: None,
+        });
+    };
+    node(local_id.clone(), &mut nodes);
+    if let Some(n) = nodes.get_mut(&local_id) {
+        n.node_id = Some(own.node_id.clone());
+        n.hostname = Some(own.hostname.clone());
+        n.llm_host = own.llm_host;
+        n.local = true;
+        n.connected = false;
+    }
+
+    // Unordered pair -> latencies reported for it
+    let mut links: BTreeMap<(String, String), Vec<i64>> = BTreeMap::new();
+    let mut link = |a: String, b: String, latency_ms: Option<i64>| {
+        if a == b {
+            return;
+        }
+        let key = if a < b { (a, b) } else { (b, a) };
+        let samples = links.entry(key).or_default();
+        samples.extend(latency_ms);
+    };
+    for peer in &own.peers {
+        node(peer.ip.clone(), &mut nodes);
+        link(local_id.clone(), peer.ip.clone(), peer.latency_ms);
+    }
+
+    let reports = REPORTS.lock().await;
+    for (ip, (report, at)) in reports.iter().filter(|(_, (_, at))| at.elapsed() < REPORT_TTL) {
+        node(ip.clone(), &mut nodes);
+        if let Some(n) = nodes.get_mut(ip) {
+            n.node_id = Some(report.node_id.clone());
+            n.hostname = Some(report.hostname.clone());
+            n.llm_host = report.llm_host;
+            n.report_age_secs = Some(at.elapsed().as_secs());
+        }
+        for peer in &report.peers {
+            let target = node_key(&peer.ip);
+            node(target.clone(), &mut nodes);
+            link(ip.clone(), target, peer.latency_ms);
+        }
+    }
+    drop(reports);
+
+    let edges: Vec<Edge> = links
+        .into_iter()
+        .map(|((source, target), samples)| Edge {
+            source,
+            target,
+            latency_ms: (!samples.is_empty()).then(|| samples.iter().sum::<i64>() / samples.len() as i64),
+        })
+        .collect();
+    let nodes: Vec<Node> = nodes.into_values().collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "nodes": nodes, "edges": edges })))
+}
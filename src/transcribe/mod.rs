@@ -0,0 +1,111 @@
+// Optional speech-to-text for shared audio. When WHISPER_URL points at a whisper.cpp server
+// (/inference) or an OpenAI-compatible /v1/audio/transcriptions endpoint, uploaded and fetched
+// audio files are transcribed as background jobs. The transcript is stored next to the file as
+// <file>.transcript.txt and used in place of the audio when the file is attached to a chat.
+use actix_web::{get, post, web, HttpResponse, Error};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::persistence::{self, TRANSCRIPT_SUFFIX};
+
+fn whisper_url() -> Option<String> {
+    std::env::var("WHISPER_URL").ok().map(|u| u.trim().to_string()).filter(|u| !u.is_empty())
+}
+
+pub fn transcript_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(TRANSCRIPT_SUFFIX);
+    PathBuf::from(name)
+}
+
+async fn transcribe_file(url: &str, path: &Path, filename: &str) -> Result<String, String> {
+    let content = tokio::fs::read(path).await.map_err(|e| e.to_string())?;
+    let file_type = mime_guess::from_path(filename).first_or_octet_stream().to_string();
+    let part = reqwest::multipart::Part::bytes(content)
+        .file_name(filename.to_string())
+        .mime_str(&file_type)
+        .map_err(|e| e.to_string())?;
+    let mut form = reqwest::multipart::Form::new()
+        .part("file", part)
+        .text("model", std::env::var("WHISPER_MODEL").unwrap_or_else(|_| "whisper-1".to_string()))
+        .text("response_format", "json");
+    if let Ok(lang) = std::env::var("WHISPER_LANGUAGE") {
+        form = form.text("language", lang);
+    }
+    let resp = crate::client::local().post(url).timeout(Duration::from_secs(30 * 60)).multipart(form).send().await.map_err(|e| format!("whisper request failed: {}", e))?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("whisper returned {}: {}", status, body));
+    }
+    let body: serde_json::Value = resp.json().await.map_err(|e| format!("invalid whisper response: {}", e))?;
+    body["text"]
+        .as_str()
+        .map(|t| t.trim().to_string())
+        .ok_or_else(|| "whisper response has no text".to_string())
+}
+
+async fn run(url: String, path: PathBuf, filename: String) -> Result<String, String> {
+    let text = transcribe_file(&url, &path, &filename).await?;
+    tokio::fs::write(transcript_path(&path), &text).await.map_err(|e| e.to_string())?;
+    println!("WHISPER: Transcribed {} ({} chars)", filename, text.len());
+    Ok(format!("transcribed {} ({} chars)", filename, text.len()))
+}
+
+// Queue transcription of a stored audio file. Returns the job id, or None when Whisper is not
+// configured or the file is not audio.
+pub async fn queue(path: PathBuf, filename: &str, file_type: &str) -> Option<u64> {
+    if !file_type.starts_with("audio/") {
+        return None;
+    }
+    let url = whisper_url()?;
+    let job = crate::jobs::submit("transcribe", format!("transcribe {}", filename), run(url, path, filename.to_string())).await;
+    Some(job)
+}
+
+// Transcript of a local upload, if one has been produced
+pub async fn load_transcript(filename: &str) -> Option<String> {
+    let path = persistence::get_file_path(filename).await.ok()??;
+    tokio::fs::read_to_string(transcript_path(&path)).await.ok()
+}
+
+#[get("/files/{filename}/transcript")]
+pub async fn get_transcript(path: web::Path<String>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    let filename = path.into_inner();
+    let user = user.map(|u| u.into_inner());
+    let visible = persistence::get_file_info(&filename).await?.is_none_or(|f| f.visible_to(user.as_ref()));
+    match load_transcript(&filename).await.filter(|_| visible) {
+        Some(text) => Ok(HttpResponse::Ok().json(serde_json::json!({ "filename": filename, "transcript": text }))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "No transcript for this file"
+        }))),
+    }
+}
+
+// (Re)transcribe a local upload on demand
+#[post("/files/{filename}/transcript")]
+pub async fn create_transcript(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let filename = path.into_inner();
+    let (file_path, info) = match (persistence::get_file_path(&filename).await?, persistence::get_file_info(&filename).await?) {
+        (Some(p), Some(i)) => (p, i),
+        _ => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "File not found"
+            })));
+        }
+    };
+    if whisper_url().is_none() {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "success": false,
+            "message": "Transcription is not configured (set WHISPER_URL)"
+        })));
+    }
+    match queue(file_path, &filename, &info.file_type).await {
+        Some(job_id) => Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "job_id": job_id }))),
+        None => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Only audio files can be transcribed"
+        }))),
+    }
+}
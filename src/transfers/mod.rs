@@ -0,0 +1,229 @@
+// Progress of files we send to peers, one transfer per file and peer. Every change goes out to
+// WebSocket clients (/api/ws) as a {"type": "transfer", ...} event, and the latest state of recent
+// transfers, failed ones included, is listed by GET /api/transfers. Nothing is persisted.
+//
+// A transfer in progress can be paused, resumed or cancelled (POST /api/transfers/{id}/<action>).
+// The sender checks in between chunks, so a pause or cancel takes effect after at most one more
+// chunk. A cancelled file never completes on the peer; its partial copy is replaced by the next
+// transfer of the same name.
+use actix_web::{get, post, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+
+// Finished transfers beyond this are dropped (oldest first)
+const MAX_TRANSFER_HISTORY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferStatus {
+    Sending,
+    Paused,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl TransferStatus {
+    fn finished(self) -> bool {
+        matches!(self, TransferStatus::Done | TransferStatus::Failed | TransferStatus::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transfer {
+    pub id: u64,
+    pub filename: String,
+    pub peer: String,
+    pub bytes_sent: u64,
+    pub total_bytes: u64,
+    pub status: TransferStatus,
+    // Attempts after the first; each starts over from byte 0
+    pub retries: u32,
+    // Why the last attempt failed
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+lazy_static! {
+    static ref TRANSFERS: Arc<Mutex<VecDeque<Transfer>>> = Arc::new(Mutex::new(VecDeque::new()));
+    // Woken whenever a transfer is resumed or cancelled, for senders waiting while paused
+    static ref CONTROL_CHANGED: Notify = Notify::new();
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn publish(transfer: &Transfer) {
+    let mut event = serde_json::to_value(transfer).unwrap_or_default();
+    event["type"] = "transfer".into();
+    crate::presence::publish(event);
+}
+
+async fn update(id: u64, f: impl FnOnce(&mut Transfer)) {
+    let mut transfers = TRANSFERS.lock().await;
+    if let Some(transfer) = transfers.iter_mut().find(|t| t.id == id) {
+        f(transfer);
+        transfer.updated_at = Utc::now();
+        publish(transfer);
+    }
+}
+
+// A new transfer of `total_bytes` to `peer`; returns its id
+pub async fn start(filename: &str, peer: &str, total_bytes: u64) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let now = Utc::now();
+    let transfer = Transfer {
+        id,
+        filename: filename.to_string(),
+        peer: peer.to_string(),
+        bytes_sent: 0,
+        total_bytes,
+        status: TransferStatus::Sending,
+        retries: 0,
+        error: None,
+        started_at: now,
+        updated_at: now,
+    };
+    publish(&transfer);
+    let mut transfers = TRANSFERS.lock().await;
+    transfers.push_back(transfer);
+    while transfers.len() > MAX_TRANSFER_HISTORY {
+        match transfers.iter().position(|t| t.status.finished()) {
+            Some(pos) => { transfers.remove(pos); }
+            None => break,
+        }
+    }
+    id
+}
+
+pub async fn progress(id: u64, bytes_sent: u64) {
+    update(id, |t| t.bytes_sent = bytes_sent).await;
+}
+
+// An attempt failed and the transfer starts over
+pub async fn retry(id: u64, error: &str) {
+    update(id, |t| {
+        t.retries += 1;
+        t.bytes_sent = 0;
+        t.error = Some(error.to_string());
+    }).await;
+}
+
+pub async fn finish(id: u64) {
+    update(id, |t| {
+        t.status = TransferStatus::Done;
+        t.bytes_sent = t.total_bytes;
+        t.error = None;
+    }).await;
+}
+
+pub async fn fail(id: u64, error: &str) {
+    update(id, |t| {
+        t.status = TransferStatus::Failed;
+        t.error = Some(error.to_string());
+    }).await;
+}
+
+// Called by the sender before each chunk. Returns Ok(false) when the transfer may go on and
+// Ok(true) when it is still paused after waiting up to `at_most`; fails with ErrorKind::Interrupted
+// once it is cancelled.
+pub async fn wait_while_paused(id: u64, at_most: Duration) -> std::io::Result<bool> {
+    // Created before the check, so a resume in between is not missed
+    let changed = CONTROL_CHANGED.notified();
+    let status = || async { TRANSFERS.lock().await.iter().find(|t| t.id == id).map(|t| t.status) };
+    if status().await == Some(TransferStatus::Paused) {
+        let _ = tokio::time::timeout(at_most, changed).await;
+    }
+    match status().await {
+        Some(TransferStatus::Paused) => Ok(true),
+        Some(TransferStatus::Cancelled) => Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "transfer cancelled")),
+        _ => Ok(false),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Pause,
+    Resume,
+    Cancel,
+}
+
+// Apply a user's action to a transfer that is still in progress
+async fn control(id: u64, action: Action) -> HttpResponse {
+    let mut transfers = TRANSFERS.lock().await;
+    let transfer = match transfers.iter_mut().find(|t| t.id == id) {
+        Some(t) => t,
+        None => return HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "Transfer not found" })),
+    };
+    let status = match (action, transfer.status) {
+        (Action::Pause, TransferStatus::Sending | TransferStatus::Paused) => TransferStatus::Paused,
+        (Action::Resume, TransferStatus::Sending | TransferStatus::Paused) => TransferStatus::Sending,
+        (Action::Cancel, TransferStatus::Sending | TransferStatus::Paused) => TransferStatus::Cancelled,
+        _ => {
+            return HttpResponse::Conflict().json(serde_json::json!({ "success": false, "message": "Transfer has already finished" }));
+        }
+    };
+    transfer.status = status;
+    transfer.updated_at = Utc::now();
+    publish(transfer);
+    println!("API: Transfer #{} of {} to {} is now {:?}", id, transfer.filename, transfer.peer, status);
+    let body = serde_json::json!({ "success": true, "transfer": transfer });
+    drop(transfers);
+    CONTROL_CHANGED.notify_waiters();
+    HttpResponse::Ok().json(body)
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can control transfers" }))
+}
+
+#[post("/transfers/{id}/pause")]
+pub async fn pause_transfer(path: web::Path<u64>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    Ok(control(path.into_inner(), Action::Pause).await)
+}
+
+#[post("/transfers/{id}/resume")]
+pub async fn resume_transfer(path: web::Path<u64>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    Ok(control(path.into_inner(), Action::Resume).await)
+}
+
+#[post("/transfers/{id}/cancel")]
+pub async fn cancel_transfer(path: web::Path<u64>, user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    Ok(control(path.into_inner(), Action::Cancel).await)
+}
+
+#[derive(Deserialize)]
+pub struct TransferQuery {
+    pub peer: Option<String>,
+    pub status: Option<TransferStatus>,
+}
+
+// Recent transfers, newest first; ?peer= and ?status= narrow the list
+#[get("/transfers")]
+pub async fn list_transfers(query: web::Query<TransferQuery>) -> Result<HttpResponse, Error> {
+    let transfers: Vec<Transfer> = TRANSFERS
+        .lock()
+        .await
+        .iter()
+        .rev()
+        .filter(|t| query.peer.as_ref().is_none_or(|p| &t.peer == p))
+        .filter(|t| query.status.is_none_or(|s| t.status == s))
+        .cloned()
+        .collect();
+    Ok(HttpResponse::Ok().json(transfers))
+}
@@ -1,21 +1,17 @@
 use tokio::net::UdpSocket;
 use tokio::time::{Duration, interval};
-use std::collections::{HashSet, HashMap};
+use std::collections::{BTreeMap, HashSet, HashMap};
 use std::str;
 use tokio::sync::Mutex;
 use std::sync::Arc;
-use ipconfig::get_adapters;
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::Ipv4Addr;
 use serde::{Serialize, Deserialize};
-use reqwest::Client;
 use chrono::{DateTime, Utc};
 use crate::ip::is_my_ip;
 use once_cell::sync::Lazy;
 
 const BROADCAST_PORT: u16 = 5000;
 const BROADCAST_INTERVAL: Duration = Duration::from_secs(30);
-const LISTEN_ADDR: &str = "0.0.0.0:5000";
-const OLLAMA_CHECK_URL: &str = "http://127.0.0.1:11434/api/tags";
 const PEER_TIMEOUT: Duration = Duration::from_secs(60);
 
 // Replace lazy_static with once_cell for async Mutex
@@ -26,40 +22,87 @@ static LAST_SEEN: Lazy<Arc<Mutex<HashMap<String, DateTime<Utc>>>>> =
 static LAST_BROADCAST: Lazy<Arc<Mutex<Option<DateTime<Utc>>>>> = 
     Lazy::new(|| Arc::new(Mutex::new(None)));
 
+// Discovery health for /api/status: when we last announced ourselves and last heard a peer
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiscoveryHealth {
+    pub last_broadcast_sent: Option<DateTime<Utc>>,
+    pub last_broadcast_received: Option<DateTime<Utc>>,
+    pub last_received_from: Option<String>,
+    pub last_error: Option<String>,
+    // Where we announce ourselves, most preferred first (see ip::interfaces)
+    pub interfaces: Vec<crate::ip::Interface>,
+    // Nodes heard on more than one address, by node id
+    pub multi_homed: BTreeMap<String, NodeAddresses>,
+}
+
+// Every address a node announced, and the one we dial it on
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeAddresses {
+    pub addresses: Vec<String>,
+    pub preferred: String,
+}
+
+static HEALTH: Lazy<Arc<Mutex<DiscoveryHealth>>> = Lazy::new(|| Arc::new(Mutex::new(DiscoveryHealth::default())));
+
+// Peers whose last announcement said they have an LLM, for ranking them before they connect
+static LLM_ANNOUNCED: Lazy<std::sync::Mutex<HashSet<String>>> = Lazy::new(|| std::sync::Mutex::new(HashSet::new()));
+
+pub fn announced_llm(ip: &str) -> bool {
+    LLM_ANNOUNCED.lock().unwrap().contains(ip)
+}
+
+static NODE_ADDRESSES: Lazy<Mutex<HashMap<String, NodeAddresses>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub async fn discovery_health() -> DiscoveryHealth {
+    let mut health = HEALTH.lock().await.clone();
+    health.interfaces = crate::ip::interfaces();
+    health.multi_homed = NODE_ADDRESSES
+        .lock()
+        .await
+        .iter()
+        .filter(|(_, n)| n.addresses.len() > 1)
+        .map(|(id, n)| (id.clone(), n.clone()))
+        .collect();
+    health
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct BroadcastMessage {
     message_type: String,
     has_llm: bool,
     timestamp: DateTime<Utc>,
+    // "http" or "https" for our API on port 8080; missing from older builds, which only speak http
+    #[serde(default = "default_scheme")]
+    scheme: String,
+    // The sender's node id and every address it announces on, so a node heard on several networks
+    // is dialed once, on the address we prefer; missing from older builds
+    #[serde(default)]
+    node_id: Option<String>,
+    #[serde(default)]
+    addresses: Vec<String>,
 }
 
-// Check if Ollama is running
-async fn is_ollama_available() -> bool {
-    if let Ok(client) = Client::builder()
-        .timeout(Duration::from_secs(2))
-        .build() 
-    {
-        match client.get(OLLAMA_CHECK_URL).send().await {
-            Ok(response) => response.status().is_success(),
-            Err(_) => false,
-        }
-    } else {
-        false
-    }
+fn default_scheme() -> String {
+    "http".to_string()
 }
 
-async fn send_broadcast(broadcast_addr: String) -> Result<(), std::io::Error> {
-    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+// Announce ourselves to `broadcast_addr`, sending from `from` so the packet leaves on that adapter
+async fn send_broadcast(from: Option<Ipv4Addr>, broadcast_addr: String, addresses: &[String]) -> Result<(), std::io::Error> {
+    let host = from.map(|ip| ip.to_string()).unwrap_or_else(crate::ip::listen_host);
+    let socket = UdpSocket::bind(format!("{}:0", host)).await?;
     socket.set_broadcast(true)?;
     
-    let has_llm = is_ollama_available().await;
+    let has_llm = crate::llm::backend::available().await;
     let message = BroadcastMessage {
         message_type: "ONLINE".to_string(),
         has_llm,
         timestamp: Utc::now(),
+        scheme: crate::tls::scheme().to_string(),
+        node_id: Some(crate::persistence::node_id().to_string()),
+        addresses: addresses.to_vec(),
     };
     
-    let message_bytes = serde_json::to_string(&message)
+    let mut message_bytes = serde_json::to_string(&message)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
         .into_bytes();
     
@@ -68,64 +111,120 @@ async fn send_broadcast(broadcast_addr: String) -> Result<(), std::io::Error> {
     let now = Utc::now();
     if last_broadcast.is_none() || 
        now.signed_duration_since(last_broadcast.unwrap()).num_seconds() >= BROADCAST_INTERVAL.as_secs() as i64 {
-        println!("UDP: Broadcasting to {} (LLM available: {})", broadcast_addr, has_llm);
+        crate::events::info(
+            crate::events::Category::Discovery,
+            format!("Broadcasting to {} (LLM available: {})", broadcast_addr, has_llm),
+            serde_json::json!({ "address": broadcast_addr, "has_llm": has_llm }),
+        );
         *last_broadcast = Some(now);
     }
     
+    if !crate::chaos::disturb(crate::chaos::Channel::Udp, &mut message_bytes).await {
+        return Ok(());
+    }
     socket.send_to(&message_bytes, broadcast_addr).await?;
+    HEALTH.lock().await.last_broadcast_sent = Some(Utc::now());
     Ok(())
 }
 
+// DISCOVERY_PEERS: addresses that get every announcement directly, for networks (or BIND_IP and
+// `simulate` nodes) that broadcasts do not reach
+fn discovery_peers() -> Vec<String> {
+    std::env::var("DISCOVERY_PEERS")
+        .map(|v| v.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+// Send one ONLINE announcement on every selected adapter and to each DISCOVERY_PEERS address
+pub async fn announce_now() {
+    let interfaces = crate::ip::interfaces();
+    let addresses: Vec<String> = interfaces.iter().map(|i| i.ip.to_string()).collect();
+    for peer in discovery_peers() {
+        if let Err(e) = send_broadcast(None, format!("{}:{}", peer, BROADCAST_PORT), &addresses).await {
+            eprintln!("UDP: Announcement to {} failed: {}", peer, e);
+            HEALTH.lock().await.last_error = Some(e.to_string());
+        }
+    }
+    for interface in &interfaces {
+        let broadcast_addr = format!("{}:{}", interface.broadcast, BROADCAST_PORT);
+        if let Err(e) = send_broadcast(Some(interface.ip), broadcast_addr, &addresses).await {
+            eprintln!("UDP: Broadcast error on {} ({}): {}", interface.name, interface.ip, e);
+            HEALTH.lock().await.last_error = Some(e.to_string());
+        }
+    }
+}
+
+// The address to reach a node at, given the one its announcement came from: of all the addresses
+// it announced, the one on our most preferred interface, the sender's address when that is as good
+async fn preferred_address(node_id: Option<&str>, src: &str, announced: &[String]) -> String {
+    let Some(node_id) = node_id else {
+        return src.to_string();
+    };
+    let interfaces = crate::ip::interfaces();
+    let mut nodes = NODE_ADDRESSES.lock().await;
+    let entry = nodes.entry(node_id.to_string()).or_insert_with(|| NodeAddresses { addresses: Vec::new(), preferred: src.to_string() });
+    entry.addresses = announced.to_vec();
+    if !entry.addresses.iter().any(|a| a == src) {
+        entry.addresses.push(src.to_string());
+    }
+    let best = entry
+        .addresses
+        .iter()
+        .min_by_key(|a| (crate::ip::preference(&interfaces, a), *a != src))
+        .cloned()
+        .unwrap_or_else(|| src.to_string());
+    entry.preferred = best.clone();
+    best
+}
+
 pub async fn periodic_broadcast() {
-    let mut interval = interval(BROADCAST_INTERVAL);
+    let mut interval = interval(crate::power::interval(BROADCAST_INTERVAL));
     loop {
         interval.tick().await;
-        if let Ok(adapters) = get_adapters() {
-            for adapter in adapters {
-                if adapter.oper_status() == ipconfig::OperStatus::IfOperStatusUp {
-                    for ip_addr in adapter.ip_addresses() {
-                        if let IpAddr::V4(_ipv4_addr) = ip_addr {
-                            let subnet_mask = match adapter.ip_addresses().iter().find_map(|ip| match ip {
-                                IpAddr::V4(ipv4) => Some(ipv4),
-                                _ => None,
-                            }) {
-                                Some(ipv4) => match ipv4.octets() {
-                                    [a, b, c, _] => Some(Ipv4Addr::new(a, b, c, 255)),
-                                },
-                                None => None,
-                            };
-                            if let Some(broadcast_addr) = subnet_mask {
-                                let broadcast_addr = format!("{}:{}", broadcast_addr, BROADCAST_PORT);
-                                if let Err(e) = send_broadcast(broadcast_addr).await {
-                                    eprintln!("UDP: Broadcast error: {}", e);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        announce_now().await;
     }
 }
 
 pub async fn receive_broadcast(received_ips: Arc<Mutex<HashSet<String>>>) -> Result<(), std::io::Error> {
-    println!("UDP: Listening on {}", LISTEN_ADDR);
-    let socket = UdpSocket::bind(LISTEN_ADDR).await?;
+    println!("UDP: Listening on {}:{}", crate::ip::listen_host(), BROADCAST_PORT);
+    let socket = UdpSocket::bind(format!("{}:{}", crate::ip::listen_host(), BROADCAST_PORT)).await?;
     let mut buf = [0; 1024];
 
     loop {
         let (size, src) = socket.recv_from(&mut buf).await?;
         if let Ok(message_str) = String::from_utf8(buf[..size].to_vec()) {
             if let Ok(broadcast_msg) = serde_json::from_str::<BroadcastMessage>(&message_str) {
-                let ip = src.ip().to_string();
-                if !is_my_ip(&ip) {
+                let src_ip = src.ip().to_string();
+                let from_us = broadcast_msg.node_id.as_deref() == Some(crate::persistence::node_id());
+                if !is_my_ip(&src_ip) && !from_us {
+                    let ip = preferred_address(broadcast_msg.node_id.as_deref(), &src_ip, &broadcast_msg.addresses).await;
+                    crate::tls::set_peer_scheme(&ip, &broadcast_msg.scheme);
+                    if broadcast_msg.has_llm {
+                        LLM_ANNOUNCED.lock().unwrap().insert(ip.clone());
+                    } else {
+                        LLM_ANNOUNCED.lock().unwrap().remove(&ip);
+                    }
+                    {
+                        let mut health = HEALTH.lock().await;
+                        health.last_broadcast_received = Some(Utc::now());
+                        health.last_received_from = Some(src_ip);
+                    }
                     let mut last_seen = LAST_SEEN.lock().await;
                     let now = Utc::now();
                     
                     // Only process if we haven't seen this peer recently
                     if !last_seen.contains_key(&ip) || 
-                       now.signed_duration_since(*last_seen.get(&ip).unwrap()).num_seconds() >= PEER_TIMEOUT.as_secs() as i64 {
-                        println!("UDP: Discovered peer {} (LLM available: {})", ip, broadcast_msg.has_llm);
+                       now.signed_duration_since(*last_seen.get(&ip).unwrap()).num_seconds() >= crate::power::interval(PEER_TIMEOUT).as_secs() as i64 {
+                        crate::events::info(
+                            crate::events::Category::Discovery,
+                            format!("Discovered peer {} (LLM available: {})", ip, broadcast_msg.has_llm),
+                            serde_json::json!({ "peer": ip, "has_llm": broadcast_msg.has_llm }),
+                        );
+                        crate::events::publish("peer", serde_json::json!({
+                            "event": "discovered",
+                            "ip": ip,
+                            "has_llm": broadcast_msg.has_llm
+                        }));
                         last_seen.insert(ip.clone(), now);
                         
                         let mut ips = received_ips.lock().await;
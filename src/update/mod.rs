@@ -0,0 +1,470 @@
+// Optional self-update. When enabled in settings the node periodically looks for a newer build of
+// itself: first in the release manifest at `release_url`, then at peers that reported a newer crate
+// version during the handshake. Nothing is kept unless it carries a valid Ed25519 signature from the
+// configured release key. Verified builds are stored in updates/ (and served to other peers by hash
+// over /api/replica); POST /api/update/apply swaps the staged build in and it runs after a restart.
+// Signed web UI bundles travel the same way, see ui.
+use actix_web::{get, post, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, VerifyingKey};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use crate::persistence;
+
+pub mod ui;
+
+const UPDATES_DIR: &str = "updates";
+const RELEASE_FILE: &str = "updates/release.json";
+const MAX_BINARY_SIZE: u64 = 512 * 1024 * 1024;
+const MAX_VERSION_LEN: usize = 64;
+// For the release manifest and for the build it points at
+const RELEASE_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateSettings {
+    // Check periodically; POST /api/update/check works either way
+    pub enabled: bool,
+    // JSON manifest {"version", "assets": {"<os>-<arch>": {"url", "sha256", "size", "signature"}}}
+    pub release_url: Option<String>,
+    // Hex Ed25519 key releases are signed with; without it nothing is ever staged
+    pub public_key: Option<String>,
+    // Also take newer builds from peers (they must carry the same signature)
+    pub from_peers: bool,
+    // Take newer signed web UI bundles from peers
+    pub ui_from_peers: bool,
+    pub check_interval_secs: u64,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        UpdateSettings { enabled: false, release_url: None, public_key: None, from_peers: true, ui_from_peers: true, check_interval_secs: 6 * 60 * 60 }
+    }
+}
+
+impl UpdateSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(key) = &self.public_key {
+            parse_key(key)?;
+        }
+        if self.release_url.as_deref().is_some_and(|u| !u.starts_with("http://") && !u.starts_with("https://")) {
+            return Err("update release_url must be an http(s) URL".to_string());
+        }
+        if self.check_interval_secs < 60 {
+            return Err("update check_interval_secs must be at least 60".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReleaseAsset {
+    url: String,
+    sha256: String,
+    size: u64,
+    // Hex Ed25519 signature over signed_message(version, target, sha256)
+    signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseManifest {
+    version: String,
+    assets: BTreeMap<String, ReleaseAsset>,
+}
+
+// A verified build for this platform, kept in updates/release.json and offered to peers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Release {
+    pub version: String,
+    pub target: String,
+    pub sha256: String,
+    pub size: u64,
+    pub signature: String,
+    // "release" or the IP of the peer it came from
+    pub source: String,
+    pub staged_at: DateTime<Utc>,
+    #[serde(default)]
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct CheckStatus {
+    last_check: Option<DateTime<Utc>>,
+    last_result: Option<String>,
+    last_error: Option<String>,
+}
+
+lazy_static! {
+    static ref STATUS: Arc<Mutex<CheckStatus>> = Arc::new(Mutex::new(CheckStatus::default()));
+    // One check or apply at a time
+    static ref UPDATE_LOCK: Arc<Mutex<()>> = Arc::new(Mutex::new(()));
+}
+
+// "<os>-<arch>" of this build, e.g. "windows-x86_64"
+pub fn target() -> String {
+    format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+fn parse_key(hex_key: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| "update public_key must be 32 bytes of hex".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("invalid update public_key: {}", e))
+}
+
+// Binds the signature to the version and platform so a signed build cannot be passed off as another
+fn signed_message(version: &str, target: &str, sha256: &str) -> String {
+    format!("meshmind-update|{}|{}|{}", version, target, sha256.to_lowercase())
+}
+
+fn verify_signature(key: &VerifyingKey, version: &str, target: &str, sha256: &str, signature_hex: &str) -> Result<(), String> {
+    let bytes: [u8; 64] = hex::decode(signature_hex.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| "signature must be 64 bytes of hex".to_string())?;
+    key.verify_strict(signed_message(version, target, sha256).as_bytes(), &Signature::from_bytes(&bytes))
+        .map_err(|_| format!("signature check failed for {} ({})", version, target))
+}
+
+// Versions and targets go into file names, so they are kept to something like "1.4.0-rc.1+build"
+fn valid_version(version: &str) -> bool {
+    (1..=MAX_VERSION_LEN).contains(&version.len())
+        && version.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && version.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+' | '_'))
+        && !version.contains("..")
+}
+
+fn binary_path(release: &Release) -> PathBuf {
+    PathBuf::from(UPDATES_DIR).join(format!("meshmind-{}-{}{}", release.version, release.target, std::env::consts::EXE_SUFFIX))
+}
+
+async fn load_release() -> Option<Release> {
+    let s = tokio::fs::read_to_string(RELEASE_FILE).await.ok()?;
+    let release: Release = serde_json::from_str(&s).map_err(|e| eprintln!("UPDATE: Failed to parse {}: {}", RELEASE_FILE, e)).ok()?;
+    (valid_version(&release.version) && valid_version(&release.target)).then_some(release)
+}
+
+async fn save_release(release: &Release) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(release).map_err(|e| e.to_string())?;
+    tokio::fs::write(RELEASE_FILE, json).await.map_err(|e| format!("failed to save {}: {}", RELEASE_FILE, e))
+}
+
+// Path of the kept build or UI bundle if it has this hash, so /api/replica can serve it to peers
+pub async fn binary_for_hash(sha: &str) -> Option<PathBuf> {
+    if let Some(release) = load_release().await {
+        let path = binary_path(&release);
+        if release.sha256.eq_ignore_ascii_case(sha) && path.exists() {
+            return Some(path);
+        }
+    }
+    ui::bundle_for_hash(sha)
+}
+
+// Newest version this node runs or has staged; only builds past it are worth fetching
+async fn newest_known() -> String {
+    let current = env!("CARGO_PKG_VERSION").to_string();
+    match load_release().await {
+        Some(r) if crate::tcp::is_newer(&r.version, &current) => r.version,
+        _ => current,
+    }
+}
+
+// Verify a downloaded build and keep it as the staged release
+async fn stage(key: &VerifyingKey, mut release: Release, content: &[u8]) -> Result<String, String> {
+    if !valid_version(&release.version) || !valid_version(&release.target) {
+        return Err(format!("invalid version {:?} or target {:?}", release.version, release.target));
+    }
+    if content.len() as u64 != release.size || !persistence::sha256_hex(content).eq_ignore_ascii_case(&release.sha256) {
+        return Err(format!("download of {} does not match its size and SHA-256", release.version));
+    }
+    verify_signature(key, &release.version, &release.target, &release.sha256, &release.signature)?;
+    tokio::fs::create_dir_all(UPDATES_DIR).await.map_err(|e| e.to_string())?;
+    let previous = load_release().await;
+    release.sha256 = release.sha256.to_lowercase();
+    release.staged_at = Utc::now();
+    release.applied = false;
+    let path = binary_path(&release);
+    tokio::fs::write(&path, content).await.map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).await;
+    }
+    save_release(&release).await?;
+    if let Some(old) = previous.map(|p| binary_path(&p)).filter(|p| *p != path) {
+        let _ = tokio::fs::remove_file(old).await;
+    }
+    println!("UPDATE: Staged {} for {} from {}", release.version, release.target, release.source);
+    crate::audit::record(
+        "update_staged",
+        &release.source,
+        serde_json::json!({ "version": release.version, "target": release.target, "sha256": release.sha256 }),
+    )
+    .await;
+    Ok(format!("staged {} from {}; POST /api/update/apply and restart to run it", release.version, release.source))
+}
+
+async fn check_release_url(key: &VerifyingKey, url: &str) -> Result<Option<String>, String> {
+    let client = crate::client::external();
+    let manifest: ReleaseManifest = client
+        .get(url)
+        .timeout(RELEASE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("release manifest request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("invalid release manifest: {}", e))?;
+    if !crate::tcp::is_newer(&manifest.version, &newest_known().await) {
+        return Ok(None);
+    }
+    let target = target();
+    let asset = match manifest.assets.get(&target) {
+        Some(a) => a.clone(),
+        None => return Ok(Some(format!("release {} has no build for {}", manifest.version, target))),
+    };
+    if asset.size > MAX_BINARY_SIZE {
+        return Err(format!("release build is {} bytes, more than the {} allowed", asset.size, MAX_BINARY_SIZE));
+    }
+    // Reject a bad signature before spending the bandwidth
+    verify_signature(key, &manifest.version, &target, &asset.sha256, &asset.signature)?;
+    let resp = client.get(&asset.url).timeout(RELEASE_TIMEOUT).send().await.map_err(|e| format!("download failed: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("download returned {}", resp.status()));
+    }
+    let content = resp.bytes().await.map_err(|e| format!("download failed: {}", e))?;
+    let release = Release {
+        version: manifest.version,
+        target,
+        sha256: asset.sha256,
+        size: asset.size,
+        signature: asset.signature,
+        source: "release".to_string(),
+        staged_at: Utc::now(),
+        applied: false,
+    };
+    stage(key, release, &content).await.map(Some)
+}
+
+async fn check_peers(key: &VerifyingKey) -> Result<Option<String>, String> {
+    let target = target();
+    let newest = newest_known().await;
+    let mut ahead: Vec<(String, String)> = crate::tcp::peer_versions()
+        .await
+        .into_iter()
+        .filter_map(|(ip, v)| v.crate_version.filter(|cv| crate::tcp::is_newer(cv, &newest)).map(|cv| (ip, cv)))
+        .collect();
+    ahead.sort_by(|a, b| crate::tcp::is_newer(&b.1, &a.1).cmp(&crate::tcp::is_newer(&a.1, &b.1)));
+    for (ip, _) in ahead {
+        let url = format!("{}/api/update/release", crate::tls::peer_origin(&ip, 8080));
+        let release: Release = match crate::client::peer()
+            .get(&url)
+            .timeout(Duration::from_secs(10))
+            .header(crate::tcp::PEER_REQUEST_HEADER, crate::tcp::sign_peer_request("GET", &url).await)
+            .send()
+            .await
+        {
+            Ok(resp) if resp.status().is_success() => match resp.json().await {
+                Ok(r) => r,
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+        if release.target != target || !crate::tcp::is_newer(&release.version, &newest) || release.size > MAX_BINARY_SIZE {
+            continue;
+        }
+        if let Err(e) = verify_signature(key, &release.version, &release.target, &release.sha256, &release.signature) {
+            eprintln!("UPDATE: Ignoring build offered by {}: {}", ip, e);
+            continue;
+        }
+        // Any connected peer holding the same build can serve segments
+        let mut sources = vec![ip.clone()];
+        sources.extend(crate::tcp::connected_peer_ips().await.into_iter().filter(|p| *p != ip));
+        let label = format!("update {}", release.version);
+        let size = release.size;
+        let (content, _) = crate::fetch::fetch_segmented(&release.sha256, sources, &label, |s| {
+            if s == size { Ok(()) } else { Err(format!("peer reports {} bytes, release says {}", s, size)) }
+        })
+        .await?;
+        let release = Release { source: ip, ..release };
+        return stage(key, release, &content).await.map(Some);
+    }
+    Ok(None)
+}
+
+// One update check: the release URL first, then peers
+pub async fn check_now() -> Result<String, String> {
+    let _guard = UPDATE_LOCK.lock().await;
+    let settings = crate::settings::current().await.update;
+    let result: Result<String, String> = async {
+        let key = parse_key(settings.public_key.as_deref().ok_or_else(|| "no update public_key configured".to_string())?)?;
+        if let Some(url) = settings.release_url.as_deref() {
+            if let Some(outcome) = check_release_url(&key, url).await? {
+                return Ok(outcome);
+            }
+        }
+        let mut outcome = None;
+        if settings.from_peers {
+            outcome = check_peers(&key).await?;
+        }
+        let build = match outcome {
+            Some(o) => o,
+            None => format!("no newer build than {}", newest_known().await),
+        };
+        if !settings.ui_from_peers {
+            return Ok(build);
+        }
+        match ui::check_peers(&key).await {
+            Ok(Some(ui)) => Ok(format!("{}; {}", build, ui)),
+            Ok(None) => Ok(build),
+            Err(e) => Ok(format!("{}; UI check failed: {}", build, e)),
+        }
+    }
+    .await;
+    let mut status = STATUS.lock().await;
+    status.last_check = Some(Utc::now());
+    match &result {
+        Ok(r) => {
+            status.last_result = Some(r.clone());
+            status.last_error = None;
+        }
+        Err(e) => status.last_error = Some(e.clone()),
+    }
+    result
+}
+
+// Background checker; idle while updates are disabled
+pub async fn checker() {
+    loop {
+        let settings = crate::settings::current().await.update;
+        tokio::time::sleep(Duration::from_secs(settings.check_interval_secs.max(60))).await;
+        if !crate::settings::current().await.update.enabled {
+            continue;
+        }
+        match check_now().await {
+            Ok(r) => println!("UPDATE: {}", r),
+            Err(e) => eprintln!("UPDATE: Check failed: {}", e),
+        }
+    }
+}
+
+fn previous_exe(exe: &std::path::Path) -> PathBuf {
+    let mut name = exe.as_os_str().to_owned();
+    name.push(".old");
+    PathBuf::from(name)
+}
+
+// Remove the executable an applied update replaced (it stays locked until that process exits)
+pub fn cleanup_previous() {
+    if let Ok(exe) = std::env::current_exe() {
+        let old = previous_exe(&exe);
+        if old.exists() {
+            match std::fs::remove_file(&old) {
+                Ok(()) => println!("UPDATE: Removed previous executable {}", old.display()),
+                Err(e) => eprintln!("UPDATE: Failed to remove {}: {}", old.display(), e),
+            }
+        }
+    }
+}
+
+async fn apply() -> Result<String, String> {
+    let _guard = UPDATE_LOCK.lock().await;
+    let mut release = load_release().await.filter(|r| !r.applied).ok_or_else(|| "no staged update".to_string())?;
+    // The file sat on disk since staging; check it again before it becomes the executable
+    let settings = crate::settings::current().await.update;
+    let key = parse_key(settings.public_key.as_deref().ok_or_else(|| "no update public_key configured".to_string())?)?;
+    let content = tokio::fs::read(binary_path(&release)).await.map_err(|e| format!("staged build missing: {}", e))?;
+    if !persistence::sha256_hex(&content).eq_ignore_ascii_case(&release.sha256) {
+        return Err("staged build no longer matches its SHA-256".to_string());
+    }
+    verify_signature(&key, &release.version, &release.target, &release.sha256, &release.signature)?;
+
+    // A running executable can be renamed but not overwritten (Windows), so move it aside first
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let old = previous_exe(&exe);
+    let _ = tokio::fs::remove_file(&old).await;
+    tokio::fs::rename(&exe, &old).await.map_err(|e| format!("failed to move {} aside: {}", exe.display(), e))?;
+    if let Err(e) = tokio::fs::copy(binary_path(&release), &exe).await {
+        let _ = tokio::fs::rename(&old, &exe).await;
+        return Err(format!("failed to install {}: {}", release.version, e));
+    }
+    release.applied = true;
+    save_release(&release).await?;
+    println!("UPDATE: Installed {} to {}; restart to run it", release.version, exe.display());
+    crate::audit::record("update_applied", "system", serde_json::json!({ "version": release.version, "sha256": release.sha256 })).await;
+    Ok(format!("installed {}; restart the node to run it", release.version))
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can update this node" }))
+}
+
+#[get("/update")]
+pub async fn update_status() -> Result<HttpResponse, Error> {
+    let settings = crate::settings::current().await.update;
+    let status = STATUS.lock().await.clone();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "enabled": settings.enabled,
+        "current_version": env!("CARGO_PKG_VERSION"),
+        "target": target(),
+        "release": load_release().await,
+        "ui": ui::current(),
+        "last_check": status.last_check,
+        "last_result": status.last_result,
+        "last_error": status.last_error
+    })))
+}
+
+#[post("/update/check")]
+pub async fn check_update(user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let job_id = crate::jobs::submit("update", "update check".to_string(), check_now()).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
+
+#[post("/update/apply")]
+pub async fn apply_update(user: Option<web::ReqData<crate::users::SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    match apply().await {
+        Ok(message) => Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "message": message }))),
+        Err(e) => {
+            eprintln!("UPDATE: Apply failed: {}", e);
+            Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": e })))
+        }
+    }
+}
+
+// The verified build this node can hand to peers; they fetch the bytes by hash over /api/replica
+#[get("/update/release")]
+pub async fn get_release() -> Result<HttpResponse, Error> {
+    match load_release().await.filter(|r| binary_path(r).exists()) {
+        Some(release) => Ok(HttpResponse::Ok().json(release)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "No release available" }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::valid_version;
+
+    // Versions become file names under updates/, so nothing that could leave the directory passes
+    #[test]
+    fn versions_are_safe_file_name_parts() {
+        assert!(valid_version("1.4.0"));
+        assert!(valid_version("2.0.0-rc.1+build5"));
+        assert!(valid_version("x86_64-unknown-linux-gnu"));
+        for bad in ["", "../1.0", "1..0", ".1", "1/0", "1\\0", "1.0 ", &"9".repeat(65)] {
+            assert!(!valid_version(bad), "{:?}", bad);
+        }
+    }
+}
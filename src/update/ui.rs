@@ -0,0 +1,349 @@
+// Web UI bundles shared over the mesh, so a UI fix reaches every node without rebuilding the binary.
+// A bundle is the contents of webpage/build/ packed with `pack-ui` and signed with the same release
+// key as builds; the signed message also names the oldest node version its API calls work with.
+// The admin publishes one on any node with POST /api/update/ui, and other nodes pick it up from
+// peers during the update check. A node serves a bundle instead of its embedded UI only while the
+// bundle is newer than the node itself and the node is at least the bundle's `min_node_version`,
+// so upgrading the binary past a bundle brings the embedded UI back.
+use actix_web::{delete, get, post, web, HttpResponse, Error};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use crate::persistence;
+use crate::users::SessionUser;
+
+const UI_FILE: &str = "updates/ui.json";
+const MAX_BUNDLE_SIZE: u64 = 64 * 1024 * 1024;
+
+const USAGE: &str = "\
+Usage: instance pack-ui <dir> <version> [--min-node <version>] [--key <hex signing key>]
+
+Packs a built web UI (e.g. webpage/build) into meshmind-ui-<version>.bin and prints its SHA-256.
+With --key the bundle is signed and the signature printed; otherwise the message to sign is.
+Publish it with POST /api/update/ui?version=..&min_node_version=..&signature=.. and the file as body.";
+
+// A verified bundle, kept in updates/ui.json next to the packed files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiBundle {
+    pub version: String,
+    pub min_node_version: String,
+    pub sha256: String,
+    pub size: u64,
+    // Hex Ed25519 signature over signed_message(version, min_node_version, sha256)
+    pub signature: String,
+    // "admin" or the IP of the peer it came from
+    pub source: String,
+    pub staged_at: DateTime<Utc>,
+}
+
+// The bundle being served, unpacked: file path (e.g. "assets/index-abc.js") to content
+struct Active {
+    bundle: UiBundle,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+static ACTIVE: Lazy<RwLock<Option<Arc<Active>>>> = Lazy::new(|| RwLock::new(None));
+
+fn signed_message(version: &str, min_node_version: &str, sha256: &str) -> String {
+    format!("meshmind-ui|{}|{}|{}", version, min_node_version, sha256.to_lowercase())
+}
+
+fn verify_signature(key: &VerifyingKey, bundle: &UiBundle) -> Result<(), String> {
+    let bytes: [u8; 64] = hex::decode(bundle.signature.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| "signature must be 64 bytes of hex".to_string())?;
+    key.verify_strict(signed_message(&bundle.version, &bundle.min_node_version, &bundle.sha256).as_bytes(), &Signature::from_bytes(&bytes))
+        .map_err(|_| format!("signature check failed for UI {}", bundle.version))
+}
+
+// Whether this node should serve `bundle` rather than its embedded UI
+fn applies(bundle: &UiBundle) -> bool {
+    let current = env!("CARGO_PKG_VERSION");
+    crate::tcp::is_newer(&bundle.version, current) && !crate::tcp::is_newer(&bundle.min_node_version, current)
+}
+
+fn bundle_path(bundle: &UiBundle) -> Result<PathBuf, String> {
+    if !super::valid_version(&bundle.version) {
+        return Err(format!("invalid UI version {:?}", bundle.version));
+    }
+    Ok(PathBuf::from(super::UPDATES_DIR).join(format!("meshmind-ui-{}.bin", bundle.version)))
+}
+
+fn unpack(content: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, String> {
+    let files: BTreeMap<String, Vec<u8>> = bincode::deserialize(content).map_err(|e| format!("not a UI bundle: {}", e))?;
+    if !files.contains_key("index.html") {
+        return Err("UI bundle has no index.html".to_string());
+    }
+    if files.keys().any(|p| p.starts_with('/') || p.split('/').any(|s| s.is_empty() || s == "..")) {
+        return Err("UI bundle contains an invalid path".to_string());
+    }
+    Ok(files)
+}
+
+fn load_manifest() -> Option<UiBundle> {
+    let s = std::fs::read_to_string(UI_FILE).ok()?;
+    serde_json::from_str(&s).map_err(|e| eprintln!("UPDATE: Failed to parse {}: {}", UI_FILE, e)).ok()
+}
+
+// The kept bundle, if there is one
+pub fn current() -> Option<UiBundle> {
+    if let Some(active) = ACTIVE.read().unwrap().as_ref() {
+        return Some(active.bundle.clone());
+    }
+    load_manifest()
+}
+
+// Path of the kept bundle if it has this hash, so /api/replica can serve it to peers
+pub fn bundle_for_hash(sha: &str) -> Option<PathBuf> {
+    let bundle = current()?;
+    let path = bundle_path(&bundle).ok()?;
+    (bundle.sha256.eq_ignore_ascii_case(sha) && path.exists()).then_some(path)
+}
+
+// A file of the served bundle; None falls back to the embedded UI
+pub fn asset(path: &str) -> Option<Vec<u8>> {
+    ACTIVE.read().unwrap().as_ref()?.files.get(path).cloned()
+}
+
+// Serve the kept bundle if it still verifies and applies to this version; called at startup
+pub async fn load() {
+    let Some(bundle) = load_manifest() else {
+        return;
+    };
+    let result: Result<(), String> = async {
+        let key = super::parse_key(crate::settings::current().await.update.public_key.as_deref().ok_or_else(|| "no update public_key configured".to_string())?)?;
+        let content = tokio::fs::read(bundle_path(&bundle)?).await.map_err(|e| format!("bundle missing: {}", e))?;
+        if !persistence::sha256_hex(&content).eq_ignore_ascii_case(&bundle.sha256) {
+            return Err("bundle no longer matches its SHA-256".to_string());
+        }
+        verify_signature(&key, &bundle)?;
+        if !applies(&bundle) {
+            return Err(format!("needs a node between {} and {}, this is {}", bundle.min_node_version, bundle.version, env!("CARGO_PKG_VERSION")));
+        }
+        let files = unpack(&content)?;
+        println!("UPDATE: Serving UI {} from {}", bundle.version, bundle.source);
+        *ACTIVE.write().unwrap() = Some(Arc::new(Active { bundle: bundle.clone(), files }));
+        Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        eprintln!("UPDATE: Serving the embedded UI, not UI {}: {}", bundle.version, e);
+    }
+}
+
+// Verify a bundle and start serving it in place of the previous one
+async fn stage(key: &VerifyingKey, mut bundle: UiBundle, content: &[u8]) -> Result<String, String> {
+    let path = bundle_path(&bundle)?;
+    if content.len() as u64 != bundle.size || !persistence::sha256_hex(content).eq_ignore_ascii_case(&bundle.sha256) {
+        return Err(format!("UI {} does not match its size and SHA-256", bundle.version));
+    }
+    verify_signature(key, &bundle)?;
+    if !applies(&bundle) {
+        return Err(format!("UI {} needs a node between {} and {}", bundle.version, bundle.min_node_version, bundle.version));
+    }
+    let files = unpack(content)?;
+    tokio::fs::create_dir_all(super::UPDATES_DIR).await.map_err(|e| e.to_string())?;
+    let previous = current();
+    bundle.sha256 = bundle.sha256.to_lowercase();
+    bundle.staged_at = Utc::now();
+    persistence::write_atomic(&path, content).await.map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    let json = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    persistence::write_atomic(Path::new(UI_FILE), json.as_bytes()).await.map_err(|e| format!("failed to save {}: {}", UI_FILE, e))?;
+    *ACTIVE.write().unwrap() = Some(Arc::new(Active { bundle: bundle.clone(), files }));
+    if let Some(old) = previous.and_then(|p| bundle_path(&p).ok()).filter(|p| *p != path) {
+        let _ = tokio::fs::remove_file(old).await;
+    }
+    println!("UPDATE: Serving UI {} from {}", bundle.version, bundle.source);
+    crate::audit::record("ui_staged", &bundle.source, serde_json::json!({ "version": bundle.version, "sha256": bundle.sha256 })).await;
+    Ok(format!("serving UI {} from {}", bundle.version, bundle.source))
+}
+
+// Take a newer bundle from the first connected peer offering one that verifies
+pub async fn check_peers(key: &VerifyingKey) -> Result<Option<String>, String> {
+    let newest = current().map(|b| b.version).unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string());
+    let peers = crate::tcp::connected_peer_ips().await;
+    for ip in &peers {
+        let url = format!("{}/api/update/ui", crate::tls::peer_origin(ip, 8080));
+        let bundle: UiBundle = match crate::client::peer()
+            .get(&url)
+            .timeout(Duration::from_secs(10))
+            .header(crate::tcp::PEER_REQUEST_HEADER, crate::tcp::sign_peer_request("GET", &url).await)
+            .send()
+            .await
+        {
+            Ok(resp) if resp.status().is_success() => match resp.json().await {
+                Ok(b) => b,
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+        if !crate::tcp::is_newer(&bundle.version, &newest) || !applies(&bundle) || bundle.size > MAX_BUNDLE_SIZE {
+            continue;
+        }
+        if let Err(e) = verify_signature(key, &bundle) {
+            eprintln!("UPDATE: Ignoring UI offered by {}: {}", ip, e);
+            continue;
+        }
+        let mut sources = vec![ip.clone()];
+        sources.extend(peers.iter().filter(|p| *p != ip).cloned());
+        let label = format!("UI {}", bundle.version);
+        let size = bundle.size;
+        let (content, _) = crate::fetch::fetch_segmented(&bundle.sha256, sources, &label, |s| {
+            if s == size { Ok(()) } else { Err(format!("peer reports {} bytes, bundle says {}", s, size)) }
+        })
+        .await?;
+        let bundle = UiBundle { source: ip.clone(), ..bundle };
+        return stage(key, bundle, &content).await.map(Some);
+    }
+    Ok(None)
+}
+
+fn pack(dir: &Path) -> Result<Vec<u8>, String> {
+    fn walk(root: &Path, dir: &Path, files: &mut BTreeMap<String, Vec<u8>>) -> Result<(), String> {
+        for entry in std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.is_dir() {
+                walk(root, &path, files)?;
+            } else {
+                let rel = path.strip_prefix(root).map_err(|e| e.to_string())?;
+                let name = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+                files.insert(name, std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?);
+            }
+        }
+        Ok(())
+    }
+    let mut files = BTreeMap::new();
+    walk(dir, dir, &mut files)?;
+    let content = bincode::serialize(&files).map_err(|e| e.to_string())?;
+    unpack(&content)?;
+    Ok(content)
+}
+
+fn pack_ui(rest: &[String]) -> Result<(), String> {
+    let [dir, version, options @ ..] = rest else {
+        return Err("expected a directory and a version".to_string());
+    };
+    if !super::valid_version(version) {
+        return Err(format!("invalid version {:?}", version));
+    }
+    let (mut min_node, mut key) = (env!("CARGO_PKG_VERSION").to_string(), None);
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match (option.as_str(), options.next()) {
+            ("--min-node", Some(v)) => min_node = v.clone(),
+            ("--key", Some(k)) => key = Some(k.clone()),
+            _ => return Err(format!("unexpected argument: {}", option)),
+        }
+    }
+    let content = pack(Path::new(dir))?;
+    let sha = persistence::sha256_hex(&content);
+    let out = format!("meshmind-ui-{}.bin", version);
+    std::fs::write(&out, &content).map_err(|e| format!("{}: {}", out, e))?;
+    println!("Wrote {} ({} bytes, sha256 {})", out, content.len(), sha);
+    let message = signed_message(version, &min_node, &sha);
+    match key {
+        Some(k) => {
+            let seed: [u8; 32] = hex::decode(k.trim()).ok().and_then(|b| b.try_into().ok()).ok_or_else(|| "--key must be 32 bytes of hex".to_string())?;
+            println!("signature {}", hex::encode(SigningKey::from_bytes(&seed).sign(message.as_bytes()).to_bytes()));
+        }
+        None => println!("Sign this message with the release key: {}", message),
+    }
+    Ok(())
+}
+
+// Runs `pack-ui` and returns the exit code, or None for any other command
+pub fn handle_command(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    if command != "pack-ui" {
+        return None;
+    }
+    if rest.first().is_some_and(|a| matches!(a.as_str(), "help" | "--help" | "-h")) {
+        println!("{}", USAGE);
+        return Some(0);
+    }
+    match pack_ui(rest) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("PACK-UI: {}\n\n{}", e, USAGE);
+            Some(1)
+        }
+    }
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can change the UI" }))
+}
+
+// The bundle this node can hand to peers; they fetch the bytes by hash over /api/replica
+#[get("/update/ui")]
+pub async fn get_ui() -> Result<HttpResponse, Error> {
+    match current().filter(|b| bundle_path(b).is_ok_and(|p| p.exists())) {
+        Some(bundle) => Ok(HttpResponse::Ok().json(bundle)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "No UI bundle available" }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PublishQuery {
+    version: String,
+    min_node_version: String,
+    signature: String,
+}
+
+// Publish a packed bundle (the request body) on this node; peers take it on their next check
+#[post("/update/ui")]
+pub async fn publish_ui(query: web::Query<PublishQuery>, mut body: web::Payload, user: Option<web::ReqData<SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let mut content = Vec::new();
+    while let Some(chunk) = body.next().await {
+        content.extend_from_slice(&chunk?);
+        if content.len() as u64 > MAX_BUNDLE_SIZE {
+            return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({ "success": false, "message": format!("UI bundles are limited to {} bytes", MAX_BUNDLE_SIZE) })));
+        }
+    }
+    let query = query.into_inner();
+    let bundle = UiBundle {
+        version: query.version,
+        min_node_version: query.min_node_version,
+        sha256: persistence::sha256_hex(&content),
+        size: content.len() as u64,
+        signature: query.signature,
+        source: "admin".to_string(),
+        staged_at: Utc::now(),
+    };
+    let result = async {
+        let key = super::parse_key(crate::settings::current().await.update.public_key.as_deref().ok_or_else(|| "no update public_key configured".to_string())?)?;
+        stage(&key, bundle, &content).await
+    }
+    .await;
+    match result {
+        Ok(message) => Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "message": message }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": e }))),
+    }
+}
+
+// Go back to the embedded UI; peers keep any copy they already took
+#[delete("/update/ui")]
+pub async fn remove_ui(user: Option<web::ReqData<SessionUser>>) -> Result<HttpResponse, Error> {
+    if !user.is_some_and(|u| u.admin) {
+        return Ok(forbidden());
+    }
+    let previous = ACTIVE.write().unwrap().take().map(|a| a.bundle.clone()).or_else(load_manifest);
+    let _ = tokio::fs::remove_file(UI_FILE).await;
+    if let Some(bundle) = &previous {
+        if let Ok(path) = bundle_path(bundle) {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        crate::audit::record("ui_removed", "admin", serde_json::json!({ "version": bundle.version })).await;
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "removed": previous.map(|b| b.version) })))
+}
@@ -0,0 +1,277 @@
+// Signed-in users. The node's own credential pair (NODE_USERNAME/NODE_PASSWORD) is the admin;
+// the admin can add accounts for further users, kept in users.json with salted password hashes.
+// Users may also sign in through an OpenID Connect provider (see oidc), their role coming from
+// their groups. Any number of users may be signed in at once. The auth guard in main puts the user named by
+// the session JWT into each request it lets through; handlers take
+// `Option<web::ReqData<SessionUser>>` to attribute what they store to that user and to limit what
+// they show: a user's default view of the conversation holds only their own questions, while the
+// admin sees everyone's, and files follow their visibility (see persistence::Visibility). These
+// are views: users share the one "local" conversation and the files/ store, and a user's file space
+// is the uploads they own. Requests from peers (x-peer-llm) carry no session and so no user.
+use actix_web::{delete, get, post, web, Error, HttpResponse};
+use chrono::{DateTime, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+use crate::conversation::{MessageType, CONVERSATION_STORE};
+
+pub mod oidc;
+
+const USERS_FILE: &str = "users.json";
+const MAX_USERNAME_LEN: usize = 64;
+const MIN_PASSWORD_LEN: usize = 8;
+// A user seen within this long counts as signed in
+const ACTIVE_SECS: i64 = 15 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionUser {
+    pub name: String,
+    pub admin: bool,
+}
+
+// Role granted by single sign-on, carried in the session token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    User,
+}
+
+// A signed-in user's latest request
+#[derive(Debug, Clone, Copy)]
+struct Seen {
+    at: DateTime<Utc>,
+    admin: bool,
+    sso: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Account {
+    username: String,
+    salt: String,
+    password_hash: String,
+    created_at: DateTime<Utc>,
+}
+
+static ADMIN: OnceCell<String> = OnceCell::new();
+static ACCOUNTS: Lazy<StdMutex<Vec<Account>>> = Lazy::new(|| StdMutex::new(load()));
+// Last request per signed-in user
+static SEEN: Lazy<StdMutex<HashMap<String, Seen>>> = Lazy::new(|| StdMutex::new(HashMap::new()));
+
+fn load() -> Vec<Account> {
+    match std::fs::read_to_string(USERS_FILE) {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("USERS: Failed to parse {}: {}", USERS_FILE, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn save(accounts: &[Account]) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(accounts).map_err(|e| e.to_string())?;
+    std::fs::write(USERS_FILE, json).map_err(|e| format!("Failed to save {}: {}", USERS_FILE, e))
+}
+
+fn hash(salt: &str, password: &str) -> String {
+    crate::persistence::sha256_hex(format!("{}:{}", salt, password).as_bytes())
+}
+
+// Name of the node's admin, from its credential pair; set once at startup
+pub fn set_admin(name: &str) {
+    let _ = ADMIN.set(name.to_string());
+}
+
+pub fn is_admin(name: &str) -> bool {
+    ADMIN.get().is_some_and(|admin| admin == name)
+}
+
+// Whether `name` may still hold a session: the admin or an account that was not removed
+pub fn exists(name: &str) -> bool {
+    is_admin(name) || ACCOUNTS.lock().unwrap().iter().any(|a| a.username == name)
+}
+
+// Check an account's password; the admin's is checked against the node credentials in main
+pub fn verify(username: &str, password: &str) -> bool {
+    ACCOUNTS.lock().unwrap().iter().any(|a| a.username == username && a.password_hash == hash(&a.salt, password))
+}
+
+// The user a session token names, noting that they are active; None once the account was removed
+// or, for single sign-on sessions (`role`), once sign-on was turned off or if the name is not
+// one sign-on gives out
+pub fn session(name: String, role: Option<Role>) -> Option<SessionUser> {
+    let user = match role {
+        None if exists(&name) => SessionUser { admin: is_admin(&name), name },
+        Some(role) if oidc::enabled() && name.starts_with(oidc::NAME_PREFIX) && !exists(&name) => SessionUser { admin: role == Role::Admin, name },
+        _ => return None,
+    };
+    SEEN.lock().unwrap().insert(user.name.clone(), Seen { at: Utc::now(), admin: user.admin, sso: role.is_some() });
+    Some(user)
+}
+
+// The signed-in user's name, if the request had a session
+pub fn name(user: Option<web::ReqData<SessionUser>>) -> Option<String> {
+    user.map(|u| u.into_inner().name)
+}
+
+// Whose questions a request sees when it asks for `requested` (None: everyone's).
+// Users see only their own; the admin and session-less peer requests may ask for anyone's.
+pub fn view(user: Option<SessionUser>, requested: Option<String>) -> Result<Option<String>, String> {
+    match user {
+        Some(user) if !user.admin => match requested {
+            Some(other) if other != user.name => Err(format!("Only the admin can view {}'s messages", other)),
+            _ => Ok(Some(user.name)),
+        },
+        _ => Ok(requested),
+    }
+}
+
+fn validate_username(username: &str) -> Result<(), String> {
+    if username.is_empty() || username.len() > MAX_USERNAME_LEN {
+        return Err(format!("username must be 1 to {} characters", MAX_USERNAME_LEN));
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')) {
+        return Err("username may only contain letters, digits, '.', '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+fn add(username: &str, password: &str) -> Result<(), String> {
+    validate_username(username)?;
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!("password must be at least {} characters", MIN_PASSWORD_LEN));
+    }
+    let mut accounts = ACCOUNTS.lock().unwrap();
+    if is_admin(username) || accounts.iter().any(|a| a.username == username) {
+        return Err(format!("User {} already exists", username));
+    }
+    let salt = hex::encode(rand::random::<[u8; 16]>());
+    let mut updated = accounts.clone();
+    updated.push(Account { username: username.to_string(), password_hash: hash(&salt, password), salt, created_at: Utc::now() });
+    save(&updated)?;
+    *accounts = updated;
+    Ok(())
+}
+
+// False when there was no such account
+fn remove(username: &str) -> Result<bool, String> {
+    let mut accounts = ACCOUNTS.lock().unwrap();
+    let updated: Vec<Account> = accounts.iter().filter(|a| a.username != username).cloned().collect();
+    if updated.len() == accounts.len() {
+        return Ok(false);
+    }
+    save(&updated)?;
+    *accounts = updated;
+    SEEN.lock().unwrap().remove(username);
+    Ok(true)
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "success": false, "message": "Only the admin can manage users" }))
+}
+
+fn is_admin_session(user: Option<web::ReqData<SessionUser>>) -> bool {
+    user.is_some_and(|u| u.admin)
+}
+
+// The admin's view across all users: each one's role, whether they are signed in now, and how
+// many questions and uploads they have on this node. Single sign-on users are listed once they
+// have been seen since the node started.
+#[get("/users")]
+pub async fn list_users(user: Option<web::ReqData<SessionUser>>) -> Result<HttpResponse, Error> {
+    if !is_admin_session(user) {
+        return Ok(forbidden());
+    }
+    let seen = SEEN.lock().unwrap().clone();
+    // (name, admin, source, created_at)
+    let mut names: Vec<(String, bool, &str, Option<DateTime<Utc>>)> = ADMIN.get().map(|a| (a.clone(), true, "node", None)).into_iter().collect();
+    names.extend(ACCOUNTS.lock().unwrap().iter().map(|a| (a.username.clone(), false, "account", Some(a.created_at))));
+    let mut sso: Vec<_> = seen.iter().filter(|(_, s)| s.sso).map(|(name, s)| (name.clone(), s.admin, "oidc", None)).collect();
+    sso.sort();
+    names.extend(sso);
+    let local = CONVERSATION_STORE.get_local_conversation().await;
+    let files = crate::persistence::list_uploaded_files().await.unwrap_or_default();
+    let now = Utc::now();
+    let users: Vec<serde_json::Value> = names
+        .into_iter()
+        .map(|(name, admin, source, created_at)| {
+            let questions = local.as_ref().map_or(0, |c| {
+                c.messages.iter().filter(|m| matches!(m.message_type, MessageType::Question) && m.host_info.user.as_deref() == Some(&name)).count()
+            });
+            let owned: Vec<_> = files.iter().filter(|f| f.owner.as_deref() == Some(&name)).collect();
+            let last_seen = seen.get(&name).map(|s| s.at);
+            serde_json::json!({
+                "username": name,
+                "role": if admin { Role::Admin } else { Role::User },
+                "source": source,
+                "created_at": created_at,
+                "active": last_seen.is_some_and(|t| (now - t).num_seconds() < ACTIVE_SECS),
+                "last_seen": last_seen,
+                "questions": questions,
+                "files": owned.len(),
+                "file_bytes": owned.iter().map(|f| f.file_size).sum::<u64>(),
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "users": users })))
+}
+
+#[derive(Deserialize)]
+pub struct NewUser {
+    username: String,
+    password: String,
+}
+
+#[post("/users")]
+pub async fn add_user(user: Option<web::ReqData<SessionUser>>, body: web::Json<NewUser>) -> Result<HttpResponse, Error> {
+    if !is_admin_session(user) {
+        return Ok(forbidden());
+    }
+    let username = body.username.trim();
+    match add(username, &body.password) {
+        Ok(()) => {
+            crate::events::info(crate::events::Category::Security, format!("User {} added", username), serde_json::json!({ "username": username }));
+            Ok(HttpResponse::Created().json(serde_json::json!({ "success": true, "username": username })))
+        }
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": e }))),
+    }
+}
+
+// Remove an account; its sessions stop working at once. What the user wrote and uploaded stays.
+#[delete("/users/{username}")]
+pub async fn remove_user(user: Option<web::ReqData<SessionUser>>, path: web::Path<String>) -> Result<HttpResponse, Error> {
+    if !is_admin_session(user) {
+        return Ok(forbidden());
+    }
+    let username = path.into_inner();
+    if is_admin(&username) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "success": false, "message": "The admin account comes from the node credentials and cannot be removed" })));
+    }
+    match remove(&username) {
+        Ok(true) => {
+            crate::events::info(crate::events::Category::Security, format!("User {} removed", username), serde_json::json!({ "username": username }));
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true })))
+        }
+        Ok(false) => Ok(HttpResponse::NotFound().json(serde_json::json!({ "success": false, "message": "User not found" }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({ "success": false, "message": e }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{view, SessionUser};
+
+    // Users only ever see their own messages and files; the admin and peers may ask for anyone's
+    #[test]
+    fn users_see_only_their_own_view() {
+        let alice = || Some(SessionUser { name: "alice".into(), admin: false });
+        assert_eq!(view(alice(), None), Ok(Some("alice".into())));
+        assert_eq!(view(alice(), Some("alice".into())), Ok(Some("alice".into())));
+        assert!(view(alice(), Some("bob".into())).is_err());
+        let admin = Some(SessionUser { name: "root".into(), admin: true });
+        assert_eq!(view(admin.clone(), None), Ok(None));
+        assert_eq!(view(admin, Some("bob".into())), Ok(Some("bob".into())));
+        assert_eq!(view(None, Some("bob".into())), Ok(Some("bob".into())));
+    }
+}
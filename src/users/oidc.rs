@@ -0,0 +1,363 @@
+// OpenID Connect sign-on. With `oidc.enabled` set, GET /api/auth/oidc/login sends the browser to
+// the provider and /api/auth/oidc/callback turns its answer into the usual session cookie: the
+// authorization code is exchanged at the token endpoint, the ID token's signature is checked
+// against the provider's JWKS, and its groups decide the user's role. Members of `admin_groups`
+// are admins; members of `user_groups` (anyone, when it is empty) are users; everyone else is
+// turned away. The node credentials and local accounts keep working alongside it. Users signed
+// in this way are named "oidc:<username>", so an identity at the provider never takes over the
+// files and messages of a local account or the admin with the same name.
+use actix_web::{get, web, Error, HttpResponse};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+use super::Role;
+
+// How long a sign-in may take at the provider
+const LOGIN_TTL: Duration = Duration::from_secs(600);
+const MAX_PENDING_LOGINS: usize = 1000;
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(15);
+// Local account names can't contain ':', so no SSO user is named like one
+pub const NAME_PREFIX: &str = "oidc:";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OidcSettings {
+    pub enabled: bool,
+    // Issuer URL; its /.well-known/openid-configuration names the provider's endpoints
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    // This node's callback as registered with the provider, e.g. https://node:8080/api/auth/oidc/callback
+    pub redirect_url: String,
+    // Scopes asked for besides "openid", e.g. ["profile", "groups"]
+    pub scopes: Vec<String>,
+    // ID token claim holding the username; "sub" when it is missing
+    pub username_claim: String,
+    // ID token claim holding the user's groups, a list or a single name
+    pub groups_claim: String,
+    pub admin_groups: Vec<String>,
+    pub user_groups: Vec<String>,
+}
+
+impl Default for OidcSettings {
+    fn default() -> Self {
+        OidcSettings {
+            enabled: false,
+            issuer: String::new(),
+            client_id: String::new(),
+            client_secret: None,
+            redirect_url: String::new(),
+            scopes: vec!["profile".to_string(), "email".to_string()],
+            username_claim: "preferred_username".to_string(),
+            groups_claim: "groups".to_string(),
+            admin_groups: Vec::new(),
+            user_groups: Vec::new(),
+        }
+    }
+}
+
+impl OidcSettings {
+    pub fn normalize(&mut self) {
+        self.issuer = self.issuer.trim().trim_end_matches('/').to_string();
+        self.client_id = self.client_id.trim().to_string();
+        self.client_secret = self.client_secret.take().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        self.redirect_url = self.redirect_url.trim().to_string();
+        for list in [&mut self.scopes, &mut self.admin_groups, &mut self.user_groups] {
+            *list = list.iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+            list.dedup();
+        }
+        self.username_claim = self.username_claim.trim().to_string();
+        self.groups_claim = self.groups_claim.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        for (name, url) in [("oidc.issuer", &self.issuer), ("oidc.redirect_url", &self.redirect_url)] {
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err(format!("{} must be an http(s) URL", name));
+            }
+        }
+        if self.client_id.is_empty() {
+            return Err("oidc.client_id is required".to_string());
+        }
+        if self.username_claim.is_empty() || self.groups_claim.is_empty() {
+            return Err("oidc.username_claim and oidc.groups_claim must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    // The role of a member of `groups`; None when they may not sign in
+    pub fn role_for(&self, groups: &[String]) -> Option<Role> {
+        let member = |of: &[String]| groups.iter().any(|g| of.contains(g));
+        if member(&self.admin_groups) {
+            Some(Role::Admin)
+        } else if self.user_groups.is_empty() || member(&self.user_groups) {
+            Some(Role::User)
+        } else {
+            None
+        }
+    }
+}
+
+static SETTINGS: Lazy<StdMutex<OidcSettings>> = Lazy::new(|| StdMutex::new(OidcSettings::default()));
+// Sign-ins sent to the provider, by state: the nonce the ID token must carry and when it started
+static PENDING: Lazy<StdMutex<HashMap<String, (String, Instant)>>> = Lazy::new(|| StdMutex::new(HashMap::new()));
+
+// Apply the `oidc` settings; called at startup and whenever the settings change
+pub fn configure(settings: &OidcSettings) {
+    *SETTINGS.lock().unwrap() = settings.clone();
+}
+
+// Whether sign-on is on; sessions it issued stop working when it is turned off
+pub fn enabled() -> bool {
+    SETTINGS.lock().unwrap().enabled
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+async fn discover(issuer: &str) -> Result<Discovery, String> {
+    let url = format!("{}/.well-known/openid-configuration", issuer);
+    let response = crate::client::external().get(&url).timeout(PROVIDER_TIMEOUT).send().await.map_err(|e| format!("Could not reach {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("{} answered {}", url, response.status()));
+    }
+    response.json().await.map_err(|e| format!("Invalid discovery document from {}: {}", url, e))
+}
+
+fn error(status: actix_web::http::StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({ "success": false, "message": message }))
+}
+
+#[get("/auth/oidc/login")]
+pub async fn oidc_login() -> Result<HttpResponse, Error> {
+    let settings = SETTINGS.lock().unwrap().clone();
+    if !settings.enabled {
+        return Ok(error(actix_web::http::StatusCode::NOT_FOUND, "Single sign-on is not enabled".to_string()));
+    }
+    let discovery = match discover(&settings.issuer).await {
+        Ok(d) => d,
+        Err(e) => return Ok(error(actix_web::http::StatusCode::BAD_GATEWAY, e)),
+    };
+    let state = hex::encode(rand::random::<[u8; 16]>());
+    let nonce = hex::encode(rand::random::<[u8; 16]>());
+    {
+        let mut pending = PENDING.lock().unwrap();
+        pending.retain(|_, (_, started)| started.elapsed() < LOGIN_TTL);
+        if pending.len() >= MAX_PENDING_LOGINS {
+            return Ok(error(actix_web::http::StatusCode::TOO_MANY_REQUESTS, "Too many sign-ins in progress".to_string()));
+        }
+        pending.insert(state.clone(), (nonce.clone(), Instant::now()));
+    }
+    let scope = std::iter::once("openid").chain(settings.scopes.iter().map(String::as_str).filter(|s| *s != "openid")).collect::<Vec<_>>().join(" ");
+    let query = serde_urlencoded::to_string([
+        ("response_type", "code"),
+        ("client_id", settings.client_id.as_str()),
+        ("redirect_uri", settings.redirect_url.as_str()),
+        ("scope", scope.as_str()),
+        ("state", state.as_str()),
+        ("nonce", nonce.as_str()),
+    ])
+    .unwrap_or_default();
+    let separator = if discovery.authorization_endpoint.contains('?') { '&' } else { '?' };
+    let location = format!("{}{}{}", discovery.authorization_endpoint, separator, query);
+    Ok(HttpResponse::Found().insert_header(("Location", location)).finish())
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+// Exchange the code and check the ID token; the username and role it grants
+async fn sign_in(settings: &OidcSettings, code: &str, nonce: &str) -> Result<(String, Role), (actix_web::http::StatusCode, String)> {
+    use actix_web::http::StatusCode;
+    let gateway = |e: String| (StatusCode::BAD_GATEWAY, e);
+    let client = crate::client::external();
+    let discovery = discover(&settings.issuer).await.map_err(gateway)?;
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", settings.redirect_url.as_str()),
+        ("client_id", settings.client_id.as_str()),
+    ];
+    if let Some(secret) = settings.client_secret.as_deref() {
+        form.push(("client_secret", secret));
+    }
+    let response = client.post(&discovery.token_endpoint).timeout(PROVIDER_TIMEOUT).form(&form).send().await.map_err(|e| gateway(format!("Token request failed: {}", e)))?;
+    if !response.status().is_success() {
+        return Err(gateway(format!("Token endpoint answered {}", response.status())));
+    }
+    let tokens: TokenResponse = response.json().await.map_err(|e| gateway(format!("Invalid token response: {}", e)))?;
+    let jwks: JwkSet = client
+        .get(&discovery.jwks_uri)
+        .timeout(PROVIDER_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| gateway(format!("Could not fetch the provider's keys: {}", e)))?
+        .json()
+        .await
+        .map_err(|e| gateway(format!("Invalid JWKS: {}", e)))?;
+    let claims = verify_id_token(&tokens.id_token, &jwks, settings, nonce).map_err(|e| (StatusCode::UNAUTHORIZED, e))?;
+    let claimed = claims
+        .get(&settings.username_claim)
+        .or_else(|| claims.get("sub"))
+        .and_then(|v| v.as_str())
+        .filter(|name| !name.is_empty())
+        .ok_or((StatusCode::UNAUTHORIZED, "The ID token names no user".to_string()))?;
+    let username = session_name(claimed, super::exists).ok_or_else(|| (StatusCode::FORBIDDEN, format!("{} is already a local user of this node", claimed)))?;
+    let groups = groups(&claims, &settings.groups_claim);
+    let role = settings
+        .role_for(&groups)
+        .ok_or_else(|| (StatusCode::FORBIDDEN, format!("{} is not in a group allowed to sign in", username)))?;
+    Ok((username, role))
+}
+
+// Name of the session for the user the provider calls `claimed`; None if it is taken by a local
+// user (`exists`), which only the admin's name can be
+pub fn session_name(claimed: &str, exists: impl Fn(&str) -> bool) -> Option<String> {
+    let name = format!("{}{}", NAME_PREFIX, claimed);
+    (!exists(&name)).then_some(name)
+}
+
+// Claims of an ID token signed by one of the provider's keys for this client, carrying `nonce`
+pub fn verify_id_token(token: &str, jwks: &JwkSet, settings: &OidcSettings, nonce: &str) -> Result<serde_json::Value, String> {
+    let header = decode_header(token).map_err(|e| format!("Malformed ID token: {}", e))?;
+    // Provider keys are public; a shared-secret algorithm would let anyone holding them sign
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err(format!("ID tokens signed with {:?} are not accepted", header.alg));
+    }
+    let jwk = match header.kid.as_deref() {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }
+    .ok_or("The ID token's signing key is not among the provider's keys")?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| format!("Unusable signing key: {}", e))?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&settings.client_id]);
+    validation.set_issuer(&[&settings.issuer]);
+    let claims = decode::<serde_json::Value>(token, &key, &validation).map_err(|e| format!("Invalid ID token: {}", e))?.claims;
+    if claims.get("nonce").and_then(|n| n.as_str()) != Some(nonce) {
+        return Err("The ID token does not belong to this sign-in".to_string());
+    }
+    Ok(claims)
+}
+
+fn groups(claims: &serde_json::Value, claim: &str) -> Vec<String> {
+    match claims.get(claim) {
+        Some(serde_json::Value::Array(items)) => items.iter().filter_map(|g| g.as_str().map(str::to_string)).collect(),
+        Some(serde_json::Value::String(group)) => vec![group.clone()],
+        _ => Vec::new(),
+    }
+}
+
+#[get("/auth/oidc/callback")]
+pub async fn oidc_callback(auth: web::Data<crate::NodeAuth>, query: web::Query<CallbackQuery>) -> Result<HttpResponse, Error> {
+    use actix_web::http::StatusCode;
+    let settings = SETTINGS.lock().unwrap().clone();
+    if !settings.enabled {
+        return Ok(error(StatusCode::NOT_FOUND, "Single sign-on is not enabled".to_string()));
+    }
+    let query = query.into_inner();
+    if let Some(e) = query.error {
+        return Ok(error(StatusCode::UNAUTHORIZED, format!("The provider refused the sign-in: {}", e)));
+    }
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        return Ok(error(StatusCode::BAD_REQUEST, "Missing code or state".to_string()));
+    };
+    let nonce = PENDING.lock().unwrap().remove(&state).filter(|(_, started)| started.elapsed() < LOGIN_TTL).map(|(nonce, _)| nonce);
+    let Some(nonce) = nonce else {
+        return Ok(error(StatusCode::BAD_REQUEST, "Unknown or expired sign-in; start again".to_string()));
+    };
+    match sign_in(&settings, &code, &nonce).await {
+        Ok((username, role)) => {
+            crate::events::info(
+                crate::events::Category::Security,
+                format!("{} signed in through {}", username, settings.issuer),
+                serde_json::json!({ "username": username, "role": role }),
+            );
+            let cookie = crate::session_cookie(&auth, &username, Some(role))?;
+            Ok(HttpResponse::Found().cookie(cookie).insert_header(("Location", crate::proxy::with_base("/app/"))).finish())
+        }
+        Err((status, message)) => {
+            crate::events::warn(crate::events::Category::Security, format!("Single sign-on failed: {}", message), serde_json::json!({ "issuer": settings.issuer }));
+            Ok(error(status, message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{session_name, verify_id_token, OidcSettings};
+    use crate::users::Role;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+
+    // ID tokens must be signed by a provider key for our client and sign-in; groups pick the role
+    #[test]
+    fn oidc_id_tokens_are_verified_and_mapped_to_roles() {
+        let settings = OidcSettings {
+            enabled: true,
+            issuer: "https://idp.example".into(),
+            client_id: "meshmind".into(),
+            admin_groups: vec!["it".into()],
+            user_groups: vec!["staff".into()],
+            ..Default::default()
+        };
+        let key = rcgen::KeyPair::generate().unwrap();
+        let raw = key.public_key_raw();
+        let jwks: jsonwebtoken::jwk::JwkSet = serde_json::from_value(serde_json::json!({ "keys": [{
+            "kty": "EC", "crv": "P-256", "kid": "k1", "alg": "ES256",
+            "x": URL_SAFE_NO_PAD.encode(&raw[1..33]), "y": URL_SAFE_NO_PAD.encode(&raw[33..65]),
+        }] }))
+        .unwrap();
+        let sign = |aud: &str, nonce: &str| {
+            let mut header = Header::new(Algorithm::ES256);
+            header.kid = Some("k1".into());
+            let claims = serde_json::json!({
+                "iss": "https://idp.example", "aud": aud, "exp": chrono::Utc::now().timestamp() + 300,
+                "nonce": nonce, "sub": "u-1", "preferred_username": "alice", "groups": ["staff"],
+            });
+            encode(&header, &claims, &EncodingKey::from_ec_pem(key.serialize_pem().as_bytes()).unwrap()).unwrap()
+        };
+        let claims = verify_id_token(&sign("meshmind", "n1"), &jwks, &settings, "n1").expect("valid token");
+        assert_eq!(claims["preferred_username"], "alice");
+        assert!(verify_id_token(&sign("meshmind", "n1"), &jwks, &settings, "n2").is_err());
+        assert!(verify_id_token(&sign("other-app", "n1"), &jwks, &settings, "n1").is_err());
+        let shared_secret = encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(b"k")).unwrap();
+        assert!(verify_id_token(&shared_secret, &jwks, &settings, "n1").is_err());
+
+        assert_eq!(settings.role_for(&["it".into(), "staff".into()]), Some(Role::Admin));
+        assert_eq!(settings.role_for(&["staff".into()]), Some(Role::User));
+        assert_eq!(settings.role_for(&["guests".into()]), None);
+        assert_eq!(OidcSettings::default().role_for(&[]), Some(Role::User));
+    }
+
+    // Provider identities never share a name with the admin or local accounts
+    #[test]
+    fn sso_users_are_named_apart_from_local_ones() {
+        let local = |name: &str| ["admin", "alice", "oidc:root"].contains(&name);
+        assert_eq!(session_name("alice", local).as_deref(), Some("oidc:alice"));
+        assert_eq!(session_name("admin", local).as_deref(), Some("oidc:admin"));
+        assert_eq!(session_name("root", local), None);
+        assert!(crate::users::view(Some(crate::users::SessionUser { name: session_name("alice", local).unwrap(), admin: false }), Some("alice".into())).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+// Votes on LLM answers. An answer in any conversation we hold (ours, a peer's or a fork) can be
+// voted up or down; votes are kept in votes.json and sent to connected peers as VOTE: frames, so
+// every node ranks answers alike. Among a question's answers (regenerated or fan-out) the best is
+// the highest-scoring one, and GET /api/analytics/models ranks models by the votes they received.
+use actix_web::{get, post, web, HttpResponse, Error};
+use chrono::{DateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::conversation::{ChatMessage, Conversation, CONVERSATION_STORE, MessageType};
+
+const VOTES_FILE: &str = "votes.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vote {
+    pub message_id: String,
+    pub voter: String,
+    // "local" or the IP of the peer the vote came from
+    pub node: String,
+    // 1 or -1; 0 withdraws an earlier vote
+    pub value: i8,
+    pub voted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Tally {
+    pub up: usize,
+    pub down: usize,
+    pub score: i64,
+}
+
+#[derive(Deserialize)]
+pub struct VoteBody {
+    voter: String,
+    value: i8,
+}
+
+// (message, node, voter)
+type VoteKey = (String, String, String);
+
+lazy_static! {
+    static ref VOTES: Arc<Mutex<Option<HashMap<VoteKey, Vote>>>> = Arc::new(Mutex::new(None));
+}
+
+async fn load() -> HashMap<VoteKey, Vote> {
+    let votes: Vec<Vote> = match tokio::fs::read_to_string(VOTES_FILE).await {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("VOTES: Failed to parse {}: {}", VOTES_FILE, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+    votes.into_iter().map(|v| ((v.message_id.clone(), v.node.clone(), v.voter.clone()), v)).collect()
+}
+
+// Keep the vote unless the same voter already cast a newer one; false when it was ignored
+async fn record(vote: Vote) -> bool {
+    let mut guard = VOTES.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    let votes = guard.get_or_insert_with(HashMap::new);
+    let key = (vote.message_id.clone(), vote.node.clone(), vote.voter.clone());
+    if votes.get(&key).is_some_and(|existing| existing.voted_at >= vote.voted_at) {
+        return false;
+    }
+    votes.insert(key, vote);
+    let list: Vec<&Vote> = votes.values().collect();
+    match serde_json::to_string_pretty(&list) {
+        Ok(json) => {
+            if let Err(e) = tokio::fs::write(VOTES_FILE, json).await {
+                eprintln!("VOTES: Failed to save {}: {}", VOTES_FILE, e);
+            }
+        }
+        Err(e) => eprintln!("VOTES: Failed to serialize votes: {}", e),
+    }
+    true
+}
+
+// Up/down counts per message id
+pub async fn tallies() -> HashMap<String, Tally> {
+    let mut guard = VOTES.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await);
+    }
+    let mut out: HashMap<String, Tally> = HashMap::new();
+    for vote in guard.get_or_insert_with(HashMap::new).values() {
+        let tally = out.entry(vote.message_id.clone()).or_default();
+        match vote.value {
+            1 => tally.up += 1,
+            -1 => tally.down += 1,
+            _ => continue,
+        }
+        tally.score += vote.value as i64;
+    }
+    out
+}
+
+// The highest-scoring answer, if any scored above zero; ties go to the earlier answer
+pub fn best_answer(answers: &[&ChatMessage], tallies: &HashMap<String, Tally>) -> Option<String> {
+    let mut best: Option<(&str, i64)> = None;
+    for answer in answers {
+        let score = tallies.get(&answer.id).map(|t| t.score).unwrap_or(0);
+        if score > 0 && best.is_none_or(|(_, s)| score > s) {
+            best = Some((&answer.id, score));
+        }
+    }
+    best.map(|(id, _)| id.to_string())
+}
+
+// A vote cast here: stored and sent to every connected peer
+pub async fn cast_local(message_id: &str, voter: &str, value: i8) {
+    let voted_at = Utc::now();
+    record(Vote {
+        message_id: message_id.to_string(),
+        voter: voter.to_string(),
+        node: "local".to_string(),
+        value,
+        voted_at,
+    })
+    .await;
+    crate::tcp::broadcast_vote(message_id, voter, value, voted_at.timestamp_millis()).await;
+}
+
+pub async fn receive_from_peer(peer_ip: &str, message_id: &str, voter: &str, value: i8, voted_at_ms: i64) {
+    let voted_at = match Utc.timestamp_millis_opt(voted_at_ms).single() {
+        Some(t) => t,
+        None => return,
+    };
+    let vote = Vote {
+        message_id: message_id.to_string(),
+        voter: voter.to_string(),
+        node: peer_ip.to_string(),
+        value,
+        voted_at,
+    };
+    if record(vote).await {
+        println!("VOTES: {} from {} voted {} on {}", voter, peer_ip, value, message_id);
+    }
+}
+
+// The conversation holding a message, and the message
+fn find_message<'a>(conversations: &'a [Conversation], message_id: &str) -> Option<(&'a Conversation, &'a ChatMessage)> {
+    conversations.iter().find_map(|c| c.messages.iter().find(|m| m.id == message_id).map(|m| (c, m)))
+}
+
+#[post("/messages/{id}/vote")]
+pub async fn vote_message(path: web::Path<String>, body: web::Json<VoteBody>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let voter = body.voter.trim();
+    if voter.is_empty() || voter.len() > 64 || voter.chars().any(|c| c.is_control()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "voter must be 1-64 characters"
+        })));
+    }
+    if !(-1..=1).contains(&body.value) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "value must be 1 (up), -1 (down) or 0 (withdraw)"
+        })));
+    }
+    let conversations = CONVERSATION_STORE.all_conversations().await;
+    let (conversation, message) = match find_message(&conversations, &id) {
+        Some(found) => found,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "Message not found"
+            })))
+        }
+    };
+    if !matches!(message.message_type, MessageType::Response) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Only answers can be voted on"
+        })));
+    }
+    cast_local(&id, voter, body.value).await;
+
+    let tallies = tallies().await;
+    let best = conversation
+        .question_for(&id)
+        .and_then(|q| best_answer(&conversation.answers_to(&q.id), &tallies));
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message_id": id,
+        "votes": tallies.get(&id).copied().unwrap_or_default(),
+        "best": best
+    })))
+}
+
+// One model on one node in the leaderboard
+#[derive(Debug, Default, Serialize)]
+struct Standing {
+    model: String,
+    node: String,
+    answers: usize,
+    up: usize,
+    down: usize,
+    score: i64,
+    // Questions with several answers where this one scored best
+    wins: usize,
+}
+
+#[get("/analytics/models")]
+pub async fn model_leaderboard() -> Result<HttpResponse, Error> {
+    let conversations = CONVERSATION_STORE.all_conversations().await;
+    let tallies = tallies().await;
+    let mut standings: HashMap<(String, String), Standing> = HashMap::new();
+    let key = |m: &ChatMessage| (m.model.clone().unwrap_or_else(|| "unknown".to_string()), m.host_info.hostname.clone());
+
+    // Forks repeat their parent's messages, so each message and question is only counted once
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut winners: Vec<(String, String)> = Vec::new();
+    for conversation in &conversations {
+        for m in &conversation.messages {
+            if !seen.insert(&m.id) {
+                continue;
+            }
+            match m.message_type {
+                MessageType::Response => {
+                    let (model, node) = key(m);
+                    let standing = standings.entry((model.clone(), node.clone())).or_insert_with(|| Standing { model, node, ..Default::default() });
+                    let tally = tallies.get(&m.id).copied().unwrap_or_default();
+                    standing.answers += 1;
+                    standing.up += tally.up;
+                    standing.down += tally.down;
+                    standing.score += tally.score;
+                }
+                MessageType::Question => {
+                    let answers = conversation.answers_to(&m.id);
+                    if answers.len() < 2 {
+                        continue;
+                    }
+                    if let Some(winner) = best_answer(&answers, &tallies).and_then(|id| answers.iter().find(|a| a.id == id).copied()) {
+                        winners.push(key(winner));
+                    }
+                }
+            }
+        }
+    }
+
+    for winner in winners {
+        if let Some(standing) = standings.get_mut(&winner) {
+            standing.wins += 1;
+        }
+    }
+
+    let mut leaderboard: Vec<Standing> = standings.into_values().collect();
+    leaderboard.sort_by(|a, b| b.score.cmp(&a.score).then(b.wins.cmp(&a.wins)).then(b.answers.cmp(&a.answers)));
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "models": leaderboard })))
+}
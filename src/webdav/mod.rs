@@ -0,0 +1,398 @@
+// WebDAV view of the mesh file store so it can be mounted as a network drive.
+//
+//   /webdav/                      root collection
+//   /webdav/files/<name>          local uploads (read/write; writes go through save_uploaded_file
+//                                 so the same type/size limits apply, and are broadcast to peers)
+//   /webdav/received/<ip>/<name>  binaries received from peers (read-only)
+//
+// Lives outside /api so it can answer 401 with a Basic challenge, which WebDAV clients
+// (Explorer, Finder, davfs2) expect. The admin's session cookie is accepted as well; as the mount
+// shows every upload whatever its visibility, other users cannot use it.
+use actix_web::{web, HttpRequest, HttpResponse, Error};
+use actix_web::http::StatusCode;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures_util::StreamExt;
+use std::path::Path;
+use crate::persistence::{self, FileInfo, RECEIVED_DIR};
+
+const PREFIX: &str = "/webdav";
+
+enum DavPath {
+    Root,
+    FilesDir,
+    File(String),
+    ReceivedDir,
+    PeerDir(String),
+    PeerFile(String, String),
+}
+
+fn is_safe_segment(s: &str) -> bool {
+    !s.is_empty() && s != "." && s != ".." && !s.contains('/') && !s.contains('\\')
+}
+
+fn parse_path(tail: &str) -> Option<DavPath> {
+    let parts: Vec<&str> = tail.split('/').filter(|p| !p.is_empty()).collect();
+    if parts.iter().any(|p| !is_safe_segment(p)) {
+        return None;
+    }
+    match parts.as_slice() {
+        [] => Some(DavPath::Root),
+        ["files"] => Some(DavPath::FilesDir),
+        ["files", name] => Some(DavPath::File(name.to_string())),
+        ["received"] => Some(DavPath::ReceivedDir),
+        ["received", ip] => Some(DavPath::PeerDir(ip.to_string())),
+        ["received", ip, name] => Some(DavPath::PeerFile(ip.to_string(), name.to_string())),
+        _ => None,
+    }
+}
+
+fn is_authorized(req: &HttpRequest, auth: &crate::NodeAuth) -> bool {
+    if let Some(c) = req.cookie("session") {
+        return crate::session_user(&auth.password, c.value()).is_some_and(|u| u.admin);
+    }
+    let basic = req
+        .headers()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|b64| STANDARD.decode(b64.trim()).ok())
+        .and_then(|raw| String::from_utf8(raw).ok());
+    match basic.as_deref().and_then(|s| s.split_once(':')) {
+        Some((user, pass)) => user == auth.username && pass == auth.password,
+        None => false,
+    }
+}
+
+// Percent-encode a path for use in an href, keeping '/' separators
+pub(crate) fn encode_href(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn http_date(dt: &chrono::DateTime<chrono::Utc>) -> String {
+    dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn collection_entry(href: &str, name: &str) -> String {
+    format!(
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop><D:displayname>{}</D:displayname><D:resourcetype><D:collection/></D:resourcetype></D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+        encode_href(href),
+        xml_escape(name)
+    )
+}
+
+fn file_entry(href: &str, info: &FileInfo) -> String {
+    format!(
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop><D:displayname>{}</D:displayname><D:resourcetype/><D:getcontentlength>{}</D:getcontentlength><D:getcontenttype>{}</D:getcontenttype><D:getlastmodified>{}</D:getlastmodified></D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+        encode_href(href),
+        xml_escape(&info.filename),
+        info.file_size,
+        xml_escape(&info.file_type),
+        http_date(&info.upload_time)
+    )
+}
+
+fn multistatus(entries: Vec<String>) -> HttpResponse {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?><D:multistatus xmlns:D=\"DAV:\">{}</D:multistatus>",
+        entries.join("")
+    );
+    HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+async fn peer_dirs() -> Vec<String> {
+    let mut out = Vec::new();
+    if let Ok(mut rd) = tokio::fs::read_dir(RECEIVED_DIR).await {
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            if entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false) {
+                out.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+async fn peer_files(ip: &str) -> Vec<FileInfo> {
+    persistence::list_received_files()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|f| f.uploader_ip == ip)
+        .collect()
+}
+
+async fn propfind(path: &DavPath, depth_one: bool) -> HttpResponse {
+    let mut entries = Vec::new();
+    match path {
+        DavPath::Root => {
+            entries.push(collection_entry(&format!("{}/", PREFIX), "webdav"));
+            if depth_one {
+                entries.push(collection_entry(&format!("{}/files/", PREFIX), "files"));
+                entries.push(collection_entry(&format!("{}/received/", PREFIX), "received"));
+            }
+        }
+        DavPath::FilesDir => {
+            entries.push(collection_entry(&format!("{}/files/", PREFIX), "files"));
+            if depth_one {
+                for f in persistence::list_uploaded_files().await.unwrap_or_default() {
+                    entries.push(file_entry(&format!("{}/files/{}", PREFIX, f.filename), &f));
+                }
+            }
+        }
+        DavPath::File(name) => match persistence::get_file_info(name).await {
+            Ok(Some(info)) => entries.push(file_entry(&format!("{}/files/{}", PREFIX, name), &info)),
+            _ => return HttpResponse::NotFound().finish(),
+        },
+        DavPath::ReceivedDir => {
+            entries.push(collection_entry(&format!("{}/received/", PREFIX), "received"));
+            if depth_one {
+                for ip in peer_dirs().await {
+                    entries.push(collection_entry(&format!("{}/received/{}/", PREFIX, ip), &ip));
+                }
+            }
+        }
+        DavPath::PeerDir(ip) => {
+            if !Path::new(RECEIVED_DIR).join(ip).is_dir() {
+                return HttpResponse::NotFound().finish();
+            }
+            entries.push(collection_entry(&format!("{}/received/{}/", PREFIX, ip), ip));
+            if depth_one {
+                for f in peer_files(ip).await {
+                    entries.push(file_entry(&format!("{}/received/{}/{}", PREFIX, ip, f.filename), &f));
+                }
+            }
+        }
+        DavPath::PeerFile(ip, name) => match peer_files(ip).await.into_iter().find(|f| &f.filename == name) {
+            Some(info) => entries.push(file_entry(&format!("{}/received/{}/{}", PREFIX, ip, name), &info)),
+            None => return HttpResponse::NotFound().finish(),
+        },
+    }
+    multistatus(entries)
+}
+
+async fn get_file(path: &DavPath, head_only: bool) -> HttpResponse {
+    let (content, content_type) = match path {
+        DavPath::File(name) => {
+            let content = match persistence::get_file_content(name).await {
+                Ok(Some(c)) => c,
+                _ => return HttpResponse::NotFound().finish(),
+            };
+            let ct = match persistence::get_file_info(name).await {
+                Ok(Some(info)) => info.file_type,
+                _ => "application/octet-stream".to_string(),
+            };
+            (content, ct)
+        }
+        DavPath::PeerFile(_, name) if name == "local.json" || name.ends_with(".meta") => {
+            return HttpResponse::NotFound().finish();
+        }
+        DavPath::PeerFile(ip, name) => match tokio::fs::read(Path::new(RECEIVED_DIR).join(ip).join(name)).await {
+            Ok(c) => (c, mime_guess::from_path(name).first_or_octet_stream().to_string()),
+            Err(_) => return HttpResponse::NotFound().finish(),
+        },
+        _ => return HttpResponse::MethodNotAllowed().finish(),
+    };
+    if head_only {
+        return HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header(("Content-Length", content.len().to_string()))
+            .finish();
+    }
+    HttpResponse::Ok().content_type(content_type).body(content)
+}
+
+async fn read_body(mut payload: web::Payload) -> Result<Option<Vec<u8>>, Error> {
+    let max_size = crate::settings::current().await.max_file_size;
+    let mut data = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if (data.len() + chunk.len()) as u64 > max_size {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(data))
+}
+
+// Store (or overwrite) a local file and share it with peers. An overwrite keeps the file's owner,
+// visibility and group, replaces the old content only once the new one is stored, and is only
+// shared with those who could already see the file.
+async fn store_file(name: &str, content: Vec<u8>, uploader_ip: &str) -> HttpResponse {
+    let file_type = mime_guess::from_path(name).first_or_octet_stream().to_string();
+    // Check the policy before an existing copy is removed
+    if let Err(e) = crate::settings::current().await.check_file(&file_type, content.len() as u64) {
+        println!("WEBDAV: Rejected {}: {}", name, e);
+        return HttpResponse::Forbidden().body(e);
+    }
+    let existing = match persistence::get_file_info(name).await {
+        Ok(existing) => existing,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    let (owner, visibility, group) = match &existing {
+        Some(info) => (info.owner.clone(), info.visibility, info.group.clone()),
+        None => (None, persistence::Visibility::Mesh, None),
+    };
+    match persistence::replace_uploaded_file(name, &file_type, &content, uploader_ip, owner.as_deref(), visibility, group.as_deref()).await {
+        Ok(_) => {
+            println!("WEBDAV: Stored {} ({} bytes)", name, content.len());
+            match (visibility.is_mesh(), group) {
+                (false, _) => {}
+                (true, Some(group)) => crate::tcp::broadcast_file_to_group(&group, name.to_string(), file_type, content).await,
+                (true, None) => crate::tcp::broadcast_file_to_peers(name.to_string(), file_type, content).await,
+            }
+            if existing.is_some() { HttpResponse::NoContent().finish() } else { HttpResponse::Created().finish() }
+        }
+        Err(e) => {
+            println!("WEBDAV: Rejected {}: {}", name, e);
+            HttpResponse::Forbidden().body(e.to_string())
+        }
+    }
+}
+
+fn lock_response() -> HttpResponse {
+    // Locks are advisory only; clients (notably Explorer and Finder) refuse to write without them.
+    let token = format!("opaquelocktoken:meshmind-{}", chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default());
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?><D:prop xmlns:D=\"DAV:\"><D:lockdiscovery><D:activelock><D:locktype><D:write/></D:locktype><D:lockscope><D:exclusive/></D:lockscope><D:depth>0</D:depth><D:timeout>Second-3600</D:timeout><D:locktoken><D:href>{}</D:href></D:locktoken></D:activelock></D:lockdiscovery></D:prop>",
+        token
+    );
+    HttpResponse::Ok()
+        .insert_header(("Lock-Token", format!("<{}>", token)))
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+fn client_ip(req: &HttpRequest) -> String {
+    crate::proxy::request_client_ip(req).map(|ip| ip.to_string()).unwrap_or_else(|| "127.0.0.1".to_string())
+}
+
+pub async fn webdav_handler(
+    req: HttpRequest,
+    payload: web::Payload,
+    auth: web::Data<crate::NodeAuth>,
+) -> Result<HttpResponse, Error> {
+    let method = req.method().as_str().to_string();
+    if method == "OPTIONS" {
+        return Ok(HttpResponse::Ok()
+            .insert_header(("DAV", "1, 2"))
+            .insert_header(("Allow", "OPTIONS, PROPFIND, PROPPATCH, GET, HEAD, PUT, DELETE, MOVE, LOCK, UNLOCK"))
+            .insert_header(("MS-Author-Via", "DAV"))
+            .finish());
+    }
+    if !is_authorized(&req, &auth) {
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Basic realm=\"MeshMind\""))
+            .finish());
+    }
+
+    let tail = req.match_info().query("tail").to_string();
+    let path = match parse_path(&tail) {
+        Some(p) => p,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let resp = match method.as_str() {
+        "PROPFIND" => {
+            let depth = req.headers().get("depth").and_then(|v| v.to_str().ok()).unwrap_or("1");
+            propfind(&path, depth != "0").await
+        }
+        "PROPPATCH" => multistatus(vec![format!(
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop/><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+            encode_href(req.path())
+        )]),
+        "GET" => {
+            let resp = get_file(&path, false).await;
+            if let (DavPath::File(name), true) = (&path, resp.status().is_success()) {
+                crate::popularity::record(name, crate::popularity::Via::Local).await;
+            }
+            resp
+        }
+        "HEAD" => get_file(&path, true).await,
+        "PUT" => match path {
+            DavPath::File(name) => match read_body(payload).await? {
+                Some(content) => store_file(&name, content, &client_ip(&req)).await,
+                None => HttpResponse::PayloadTooLarge().finish(),
+            },
+            _ => HttpResponse::Forbidden().body("Only /webdav/files/ is writable"),
+        },
+        "DELETE" => match path {
+            DavPath::File(name) => match persistence::remove_uploaded_file(&name).await {
+                Ok(0) => HttpResponse::NotFound().finish(),
+                Ok(_) => {
+                    println!("WEBDAV: Deleted {}", name);
+                    crate::popularity::forget(&name).await;
+                    crate::tcp::broadcast_file_deleted(&name).await;
+                    HttpResponse::NoContent().finish()
+                }
+                Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+            },
+            _ => HttpResponse::Forbidden().body("Only /webdav/files/ is writable"),
+        },
+        "MOVE" => {
+            let dest = req
+                .headers()
+                .get("destination")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| reqwest::Url::parse(v).ok().map(|u| u.path().to_string()).or_else(|| Some(v.to_string())))
+                .and_then(|p| percent_decode(&p))
+                .and_then(|p| p.strip_prefix(PREFIX).map(|s| s.to_string()))
+                .and_then(|p| parse_path(&p));
+            match (path, dest) {
+                (DavPath::File(from), Some(DavPath::File(to))) => match persistence::get_file_content(&from).await {
+                    Ok(Some(content)) => {
+                        let resp = store_file(&to, content, &client_ip(&req)).await;
+                        if resp.status().is_success() {
+                            if let Ok(n) = persistence::remove_uploaded_file(&from).await {
+                                if n > 0 {
+                                    crate::tcp::broadcast_file_deleted(&from).await;
+                                }
+                            }
+                        }
+                        resp
+                    }
+                    _ => HttpResponse::NotFound().finish(),
+                },
+                _ => HttpResponse::Forbidden().body("Only moves within /webdav/files/ are supported"),
+            }
+        }
+        "LOCK" => lock_response(),
+        "UNLOCK" => HttpResponse::NoContent().finish(),
+        _ => HttpResponse::MethodNotAllowed().finish(),
+    };
+    Ok(resp)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(format!("{}{{tail:.*}}", PREFIX)).route(web::route().to(webdav_handler)));
+}
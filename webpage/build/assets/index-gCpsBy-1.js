@@ -20,4 +20,4 @@ import{r as b,a as Zo}from"./react-Bkz_NVCf.js";import{U as Qo,B as _e,a as Jt,S
 `)}get[Symbol.toStringTag](){return"AxiosHeaders"}static from(t){return t instanceof this?t:new this(t)}static concat(t,...n){const s=new this(t);return n.forEach(i=>s.set(i)),s}static accessor(t){const s=(this[Oi]=this[Oi]={accessors:{}}).accessors,i=this.prototype;function o(r){const a=Me(r);s[a]||(Qf(i,r),s[a]=!0)}return x.isArray(t)?t.forEach(o):o(t),this}}U.accessor(["Content-Type","Content-Length","Accept","Accept-Encoding","User-Agent","Authorization"]);x.reduceDescriptors(U.prototype,({value:e},t)=>{let n=t[0].toUpperCase()+t.slice(1);return{get:()=>e,set(s){this[n]=s}}});x.freezeMethods(U);function Xt(e,t){const n=this||Ze,s=t||n,i=U.from(s.headers);let o=s.data;return x.forEach(e,function(a){o=a.call(n,o,i.normalize(),t?t.status:void 0)}),i.normalize(),o}function _o(e){return!!(e&&e.__CANCEL__)}function De(e,t,n){j.call(this,e??"canceled",j.ERR_CANCELED,t,n),this.name="CanceledError"}x.inherits(De,j,{__CANCEL__:!0});function Uo(e,t,n){const s=n.config.validateStatus;!n.status||!s||s(n.status)?e(n):t(new j("Request failed with status code "+n.status,[j.ERR_BAD_REQUEST,j.ERR_BAD_RESPONSE][Math.floor(n.status/100)-4],n.config,n.request,n))}function eh(e){const t=/^([-+\w]{1,25})(:?\/\/|:)/.exec(e);return t&&t[1]||""}function th(e,t){e=e||10;const n=new Array(e),s=new Array(e);let i=0,o=0,r;return t=t!==void 0?t:1e3,function(l){const c=Date.now(),u=s[o];r||(r=c),n[i]=l,s[i]=c;let d=o,f=0;for(;d!==i;)f+=n[d++],d=d%e;if(i=(i+1)%e,i===o&&(o=(o+1)%e),c-r<t)return;const m=u&&c-u;return m?Math.round(f*1e3/m):void 0}}function nh(e,t){let n=0,s=1e3/t,i,o;const r=(c,u=Date.now())=>{n=u,i=null,o&&(clearTimeout(o),o=null),e.apply(null,c)};return[(...c)=>{const u=Date.now(),d=u-n;d>=s?r(c,u):(i=c,o||(o=setTimeout(()=>{o=null,r(i)},s-d)))},()=>i&&r(i)]}const xt=(e,t,n=3)=>{let s=0;const i=th(50,250);return nh(o=>{const r=o.loaded,a=o.lengthComputable?o.total:void 0,l=r-s,c=i(l),u=r<=a;s=r;const d={loaded:r,total:a,progress:a?r/a:void 0,bytes:l,rate:c||void 0,estimated:c&&a&&u?(a-r)/c:void 0,event:o,lengthComputable:a!=null,[t?"download":"upload"]:!0};e(d)},n)},ki=(e,t)=>{const n=e!=null;return[s=>t[0]({lengthComputable:n,total:e,loaded:s}),t[1]]},Fi=e=>(...t)=>x.asap(()=>e(...t)),sh=B.hasStandardBrowserEnv?((e,t)=>n=>(n=new URL(n,B.origin),e.protocol===n.protocol&&e.host===n.host&&(t||e.port===n.port)))(new URL(B.origin),B.navigator&&/(msie|trident)/i.test(B.navigator.userAgent)):()=>!0,ih=B.hasStandardBrowserEnv?{write(e,t,n,s,i,o){const r=[e+"="+encodeURIComponent(t)];x.isNumber(n)&&r.push("expires="+new Date(n).toGMTString()),x.isString(s)&&r.push("path="+s),x.isString(i)&&r.push("domain="+i),o===!0&&r.push("secure"),document.cookie=r.join("; ")},read(e){const t=document.cookie.match(new RegExp("(^|;\\s*)("+e+")=([^;]*)"));return t?decodeURIComponent(t[3]):null},remove(e){this.write(e,"",Date.now()-864e5)}}:{write(){},read(){return null},remove(){}};function rh(e){return/^([a-z][a-z\d+\-.]*:)?\/\//i.test(e)}function oh(e,t){return t?e.replace(/\/?\/$/,"")+"/"+t.replace(/^\/+/,""):e}function $o(e,t,n){let s=!rh(t);return e&&s||n==!1?oh(e,t):t}const Bi=e=>e instanceof U?{...e}:e;function ge(e,t){t=t||{};const n={};function s(c,u,d,f){return x.isPlainObject(c)&&x.isPlainObject(u)?x.merge.call({caseless:f},c,u):x.isPlainObject(u)?x.merge({},u):x.isArray(u)?u.slice():u}function i(c,u,d,f){if(x.isUndefined(u)){if(!x.isUndefined(c))return s(void 0,c,d,f)}else return s(c,u,d,f)}function o(c,u){if(!x.isUndefined(u))return s(void 0,u)}function r(c,u){if(x.isUndefined(u)){if(!x.isUndefined(c))return s(void 0,c)}else return s(void 0,u)}function a(c,u,d){if(d in t)return s(c,u);if(d in e)return s(void 0,c)}const l={url:o,method:o,data:o,baseURL:r,transformRequest:r,transformResponse:r,paramsSerializer:r,timeout:r,timeoutMessage:r,withCredentials:r,withXSRFToken:r,adapter:r,responseType:r,xsrfCookieName:r,xsrfHeaderName:r,onUploadProgress:r,onDownloadProgress:r,decompress:r,maxContentLength:r,maxBodyLength:r,beforeRedirect:r,transport:r,httpAgent:r,httpsAgent:r,cancelToken:r,socketPath:r,responseEncoding:r,validateStatus:a,headers:(c,u,d)=>i(Bi(c),Bi(u),d,!0)};return x.forEach(Object.keys(Object.assign({},e,t)),function(u){const d=l[u]||i,f=d(e[u],t[u],u);x.isUndefined(f)&&d!==a||(n[u]=f)}),n}const zo=e=>{const t=ge({},e);let{data:n,withXSRFToken:s,xsrfHeaderName:i,xsrfCookieName:o,headers:r,auth:a}=t;t.headers=r=U.from(r),t.url=Fo($o(t.baseURL,t.url,t.allowAbsoluteUrls),e.params,e.paramsSerializer),a&&r.set("Authorization","Basic "+btoa((a.username||"")+":"+(a.password?unescape(encodeURIComponent(a.password)):"")));let l;if(x.isFormData(n)){if(B.hasStandardBrowserEnv||B.hasStandardBrowserWebWorkerEnv)r.setContentType(void 0);else if((l=r.getContentType())!==!1){const[c,...u]=l?l.split(";").map(d=>d.trim()).filter(Boolean):[];r.setContentType([c||"multipart/form-data",...u].join("; "))}}if(B.hasStandardBrowserEnv&&(s&&x.isFunction(s)&&(s=s(t)),s||s!==!1&&sh(t.url))){const c=i&&o&&ih.read(o);c&&r.set(i,c)}return t},ah=typeof XMLHttpRequest<"u",lh=ah&&function(e){return new Promise(function(n,s){const i=zo(e);let o=i.data;const r=U.from(i.headers).normalize();let{responseType:a,onUploadProgress:l,onDownloadProgress:c}=i,u,d,f,m,p;function g(){m&&m(),p&&p(),i.cancelToken&&i.cancelToken.unsubscribe(u),i.signal&&i.signal.removeEventListener("abort",u)}let y=new XMLHttpRequest;y.open(i.method.toUpperCase(),i.url,!0),y.timeout=i.timeout;function w(){if(!y)return;const T=U.from("getAllResponseHeaders"in y&&y.getAllResponseHeaders()),S={data:!a||a==="text"||a==="json"?y.responseText:y.response,status:y.status,statusText:y.statusText,headers:T,config:e,request:y};Uo(function(E){n(E),g()},function(E){s(E),g()},S),y=null}"onloadend"in y?y.onloadend=w:y.onreadystatechange=function(){!y||y.readyState!==4||y.status===0&&!(y.responseURL&&y.responseURL.indexOf("file:")===0)||setTimeout(w)},y.onabort=function(){y&&(s(new j("Request aborted",j.ECONNABORTED,e,y)),y=null)},y.onerror=function(){s(new j("Network Error",j.ERR_NETWORK,e,y)),y=null},y.ontimeout=function(){let P=i.timeout?"timeout of "+i.timeout+"ms exceeded":"timeout exceeded";const S=i.transitional||Bo;i.timeoutErrorMessage&&(P=i.timeoutErrorMessage),s(new j(P,S.clarifyTimeoutError?j.ETIMEDOUT:j.ECONNABORTED,e,y)),y=null},o===void 0&&r.setContentType(null),"setRequestHeader"in y&&x.forEach(r.toJSON(),function(P,S){y.setRequestHeader(S,P)}),x.isUndefined(i.withCredentials)||(y.withCredentials=!!i.withCredentials),a&&a!=="json"&&(y.responseType=i.responseType),c&&([f,p]=xt(c,!0),y.addEventListener("progress",f)),l&&y.upload&&([d,m]=xt(l),y.upload.addEventListener("progress",d),y.upload.addEventListener("loadend",m)),(i.cancelToken||i.signal)&&(u=T=>{y&&(s(!T||T.type?new De(null,e,y):T),y.abort(),y=null)},i.cancelToken&&i.cancelToken.subscribe(u),i.signal&&(i.signal.aborted?u():i.signal.addEventListener("abort",u)));const v=eh(i.url);if(v&&B.protocols.indexOf(v)===-1){s(new j("Unsupported protocol "+v+":",j.ERR_BAD_REQUEST,e));return}y.send(o||null)})},ch=(e,t)=>{const{length:n}=e=e?e.filter(Boolean):[];if(t||n){let s=new AbortController,i;const o=function(c){if(!i){i=!0,a();const u=c instanceof Error?c:this.reason;s.abort(u instanceof j?u:new De(u instanceof Error?u.message:u))}};let r=t&&setTimeout(()=>{r=null,o(new j(`timeout ${t} of ms exceeded`,j.ETIMEDOUT))},t);const a=()=>{e&&(r&&clearTimeout(r),r=null,e.forEach(c=>{c.unsubscribe?c.unsubscribe(o):c.removeEventListener("abort",o)}),e=null)};e.forEach(c=>c.addEventListener("abort",o));const{signal:l}=s;return l.unsubscribe=()=>x.asap(a),l}},uh=function*(e,t){let n=e.byteLength;if(n<t){yield e;return}let s=0,i;for(;s<n;)i=s+t,yield e.slice(s,i),s=i},dh=async function*(e,t){for await(const n of fh(e))yield*uh(n,t)},fh=async function*(e){if(e[Symbol.asyncIterator]){yield*e;return}const t=e.getReader();try{for(;;){const{done:n,value:s}=await t.read();if(n)break;yield s}}finally{await t.cancel()}},Ii=(e,t,n,s)=>{const i=dh(e,t);let o=0,r,a=l=>{r||(r=!0,s&&s(l))};return new ReadableStream({async pull(l){try{const{done:c,value:u}=await i.next();if(c){a(),l.close();return}let d=u.byteLength;if(n){let f=o+=d;n(f)}l.enqueue(new Uint8Array(u))}catch(c){throw a(c),c}},cancel(l){return a(l),i.return()}},{highWaterMark:2})},Nt=typeof fetch=="function"&&typeof Request=="function"&&typeof Response=="function",qo=Nt&&typeof ReadableStream=="function",hh=Nt&&(typeof TextEncoder=="function"?(e=>t=>e.encode(t))(new TextEncoder):async e=>new Uint8Array(await new Response(e).arrayBuffer())),Wo=(e,...t)=>{try{return!!e(...t)}catch{return!1}},ph=qo&&Wo(()=>{let e=!1;const t=new Request(B.origin,{body:new ReadableStream,method:"POST",get duplex(){return e=!0,"half"}}).headers.has("Content-Type");return e&&!t}),_i=64*1024,bn=qo&&Wo(()=>x.isReadableStream(new Response("").body)),vt={stream:bn&&(e=>e.body)};Nt&&(e=>{["text","arrayBuffer","blob","formData","stream"].forEach(t=>{!vt[t]&&(vt[t]=x.isFunction(e[t])?n=>n[t]():(n,s)=>{throw new j(`Response type '${t}' is not supported`,j.ERR_NOT_SUPPORT,s)})})})(new Response);const mh=async e=>{if(e==null)return 0;if(x.isBlob(e))return e.size;if(x.isSpecCompliantForm(e))return(await new Request(B.origin,{method:"POST",body:e}).arrayBuffer()).byteLength;if(x.isArrayBufferView(e)||x.isArrayBuffer(e))return e.byteLength;if(x.isURLSearchParams(e)&&(e=e+""),x.isString(e))return(await hh(e)).byteLength},gh=async(e,t)=>{const n=x.toFiniteNumber(e.getContentLength());return n??mh(t)},yh=Nt&&(async e=>{let{url:t,method:n,data:s,signal:i,cancelToken:o,timeout:r,onDownloadProgress:a,onUploadProgress:l,responseType:c,headers:u,withCredentials:d="same-origin",fetchOptions:f}=zo(e);c=c?(c+"").toLowerCase():"text";let m=ch([i,o&&o.toAbortSignal()],r),p;const g=m&&m.unsubscribe&&(()=>{m.unsubscribe()});let y;try{if(l&&ph&&n!=="get"&&n!=="head"&&(y=await gh(u,s))!==0){let S=new Request(t,{method:"POST",body:s,duplex:"half"}),A;if(x.isFormData(s)&&(A=S.headers.get("content-type"))&&u.setContentType(A),S.body){const[E,C]=ki(y,xt(Fi(l)));s=Ii(S.body,_i,E,C)}}x.isString(d)||(d=d?"include":"omit");const w="credentials"in Request.prototype;p=new Request(t,{...f,signal:m,method:n.toUpperCase(),headers:u.normalize().toJSON(),body:s,duplex:"half",credentials:w?d:void 0});let v=await fetch(p);const T=bn&&(c==="stream"||c==="response");if(bn&&(a||T&&g)){const S={};["status","statusText","headers"].forEach(O=>{S[O]=v[O]});const A=x.toFiniteNumber(v.headers.get("content-length")),[E,C]=a&&ki(A,xt(Fi(a),!0))||[];v=new Response(Ii(v.body,_i,E,()=>{C&&C(),g&&g()}),S)}c=c||"text";let P=await vt[x.findKey(vt,c)||"text"](v,e);return!T&&g&&g(),await new Promise((S,A)=>{Uo(S,A,{data:P,headers:U.from(v.headers),status:v.status,statusText:v.statusText,config:e,request:p})})}catch(w){throw g&&g(),w&&w.name==="TypeError"&&/fetch/i.test(w.message)?Object.assign(new j("Network Error",j.ERR_NETWORK,e,p),{cause:w.cause||w}):j.from(w,w&&w.code,e,p)}}),wn={http:Mf,xhr:lh,fetch:yh};x.forEach(wn,(e,t)=>{if(e){try{Object.defineProperty(e,"name",{value:t})}catch{}Object.defineProperty(e,"adapterName",{value:t})}});const Ui=e=>`- ${e}`,xh=e=>x.isFunction(e)||e===null||e===!1,Ko={getAdapter:e=>{e=x.isArray(e)?e:[e];const{length:t}=e;let n,s;const i={};for(let o=0;o<t;o++){n=e[o];let r;if(s=n,!xh(n)&&(s=wn[(r=String(n)).toLowerCase()],s===void 0))throw new j(`Unknown adapter '${r}'`);if(s)break;i[r||"#"+o]=s}if(!s){const o=Object.entries(i).map(([a,l])=>`adapter ${a} `+(l===!1?"is not supported by the environment":"is not available in the build"));let r=t?o.length>1?`since :
 `+o.map(Ui).join(`
 `):" "+Ui(o[0]):"as no adapter specified";throw new j("There is no suitable adapter to dispatch the request "+r,"ERR_NOT_SUPPORT")}return s},adapters:wn};function Yt(e){if(e.cancelToken&&e.cancelToken.throwIfRequested(),e.signal&&e.signal.aborted)throw new De(null,e)}function $i(e){return Yt(e),e.headers=U.from(e.headers),e.data=Xt.call(e,e.transformRequest),["post","put","patch"].indexOf(e.method)!==-1&&e.headers.setContentType("application/x-www-form-urlencoded",!1),Ko.getAdapter(e.adapter||Ze.adapter)(e).then(function(s){return Yt(e),s.data=Xt.call(e,e.transformResponse,s),s.headers=U.from(s.headers),s},function(s){return _o(s)||(Yt(e),s&&s.response&&(s.response.data=Xt.call(e,e.transformResponse,s.response),s.response.headers=U.from(s.response.headers))),Promise.reject(s)})}const Ho="1.8.3",Mt={};["object","boolean","number","function","string","symbol"].forEach((e,t)=>{Mt[e]=function(s){return typeof s===e||"a"+(t<1?"n ":" ")+e}});const zi={};Mt.transitional=function(t,n,s){function i(o,r){return"[Axios v"+Ho+"] Transitional option '"+o+"'"+r+(s?". "+s:"")}return(o,r,a)=>{if(t===!1)throw new j(i(r," has been removed"+(n?" in "+n:"")),j.ERR_DEPRECATED);return n&&!zi[r]&&(zi[r]=!0,console.warn(i(r," has been deprecated since v"+n+" and will be removed in the near future"))),t?t(o,r,a):!0}};Mt.spelling=function(t){return(n,s)=>(console.warn(`${s} is likely a misspelling of ${t}`),!0)};function vh(e,t,n){if(typeof e!="object")throw new j("options must be an object",j.ERR_BAD_OPTION_VALUE);const s=Object.keys(e);let i=s.length;for(;i-- >0;){const o=s[i],r=t[o];if(r){const a=e[o],l=a===void 0||r(a,o,e);if(l!==!0)throw new j("option "+o+" must be "+l,j.ERR_BAD_OPTION_VALUE);continue}if(n!==!0)throw new j("Unknown option "+o,j.ERR_BAD_OPTION)}}const ct={assertOptions:vh,validators:Mt},J=ct.validators;class me{constructor(t){this.defaults=t,this.interceptors={request:new Li,response:new Li}}async request(t,n){try{return await this._request(t,n)}catch(s){if(s instanceof Error){let i={};Error.captureStackTrace?Error.captureStackTrace(i):i=new Error;const o=i.stack?i.stack.replace(/^.+\n/,""):"";try{s.stack?o&&!String(s.stack).endsWith(o.replace(/^.+\n.+\n/,""))&&(s.stack+=`
-`+o):s.stack=o}catch{}}throw s}}_request(t,n){typeof t=="string"?(n=n||{},n.url=t):n=t||{},n=ge(this.defaults,n);const{transitional:s,paramsSerializer:i,headers:o}=n;s!==void 0&&ct.assertOptions(s,{silentJSONParsing:J.transitional(J.boolean),forcedJSONParsing:J.transitional(J.boolean),clarifyTimeoutError:J.transitional(J.boolean)},!1),i!=null&&(x.isFunction(i)?n.paramsSerializer={serialize:i}:ct.assertOptions(i,{encode:J.function,serialize:J.function},!0)),n.allowAbsoluteUrls!==void 0||(this.defaults.allowAbsoluteUrls!==void 0?n.allowAbsoluteUrls=this.defaults.allowAbsoluteUrls:n.allowAbsoluteUrls=!0),ct.assertOptions(n,{baseUrl:J.spelling("baseURL"),withXsrfToken:J.spelling("withXSRFToken")},!0),n.method=(n.method||this.defaults.method||"get").toLowerCase();let r=o&&x.merge(o.common,o[n.method]);o&&x.forEach(["delete","get","head","post","put","patch","common"],p=>{delete o[p]}),n.headers=U.concat(r,o);const a=[];let l=!0;this.interceptors.request.forEach(function(g){typeof g.runWhen=="function"&&g.runWhen(n)===!1||(l=l&&g.synchronous,a.unshift(g.fulfilled,g.rejected))});const c=[];this.interceptors.response.forEach(function(g){c.push(g.fulfilled,g.rejected)});let u,d=0,f;if(!l){const p=[$i.bind(this),void 0];for(p.unshift.apply(p,a),p.push.apply(p,c),f=p.length,u=Promise.resolve(n);d<f;)u=u.then(p[d++],p[d++]);return u}f=a.length;let m=n;for(d=0;d<f;){const p=a[d++],g=a[d++];try{m=p(m)}catch(y){g.call(this,y);break}}try{u=$i.call(this,m)}catch(p){return Promise.reject(p)}for(d=0,f=c.length;d<f;)u=u.then(c[d++],c[d++]);return u}getUri(t){t=ge(this.defaults,t);const n=$o(t.baseURL,t.url,t.allowAbsoluteUrls);return Fo(n,t.params,t.paramsSerializer)}}x.forEach(["delete","get","head","options"],function(t){me.prototype[t]=function(n,s){return this.request(ge(s||{},{method:t,url:n,data:(s||{}).data}))}});x.forEach(["post","put","patch"],function(t){function n(s){return function(o,r,a){return this.request(ge(a||{},{method:t,headers:s?{"Content-Type":"multipart/form-data"}:{},url:o,data:r}))}}me.prototype[t]=n(),me.prototype[t+"Form"]=n(!0)});class os{constructor(t){if(typeof t!="function")throw new TypeError("executor must be a function.");let n;this.promise=new Promise(function(o){n=o});const s=this;this.promise.then(i=>{if(!s._listeners)return;let o=s._listeners.length;for(;o-- >0;)s._listeners[o](i);s._listeners=null}),this.promise.then=i=>{let o;const r=new Promise(a=>{s.subscribe(a),o=a}).then(i);return r.cancel=function(){s.unsubscribe(o)},r},t(function(o,r,a){s.reason||(s.reason=new De(o,r,a),n(s.reason))})}throwIfRequested(){if(this.reason)throw this.reason}subscribe(t){if(this.reason){t(this.reason);return}this._listeners?this._listeners.push(t):this._listeners=[t]}unsubscribe(t){if(!this._listeners)return;const n=this._listeners.indexOf(t);n!==-1&&this._listeners.splice(n,1)}toAbortSignal(){const t=new AbortController,n=s=>{t.abort(s)};return this.subscribe(n),t.signal.unsubscribe=()=>this.unsubscribe(n),t.signal}static source(){let t;return{token:new os(function(i){t=i}),cancel:t}}}function bh(e){return function(n){return e.apply(null,n)}}function wh(e){return x.isObject(e)&&e.isAxiosError===!0}const Sn={Continue:100,SwitchingProtocols:101,Processing:102,EarlyHints:103,Ok:200,Created:201,Accepted:202,NonAuthoritativeInformation:203,NoContent:204,ResetContent:205,PartialContent:206,MultiStatus:207,AlreadyReported:208,ImUsed:226,MultipleChoices:300,MovedPermanently:301,Found:302,SeeOther:303,NotModified:304,UseProxy:305,Unused:306,TemporaryRedirect:307,PermanentRedirect:308,BadRequest:400,Unauthorized:401,PaymentRequired:402,Forbidden:403,NotFound:404,MethodNotAllowed:405,NotAcceptable:406,ProxyAuthenticationRequired:407,RequestTimeout:408,Conflict:409,Gone:410,LengthRequired:411,PreconditionFailed:412,PayloadTooLarge:413,UriTooLong:414,UnsupportedMediaType:415,RangeNotSatisfiable:416,ExpectationFailed:417,ImATeapot:418,MisdirectedRequest:421,UnprocessableEntity:422,Locked:423,FailedDependency:424,TooEarly:425,UpgradeRequired:426,PreconditionRequired:428,TooManyRequests:429,RequestHeaderFieldsTooLarge:431,UnavailableForLegalReasons:451,InternalServerError:500,NotImplemented:501,BadGateway:502,ServiceUnavailable:503,GatewayTimeout:504,HttpVersionNotSupported:505,VariantAlsoNegotiates:506,InsufficientStorage:507,LoopDetected:508,NotExtended:510,NetworkAuthenticationRequired:511};Object.entries(Sn).forEach(([e,t])=>{Sn[t]=e});function Go(e){const t=new me(e),n=Eo(me.prototype.request,t);return x.extend(n,me.prototype,t,{allOwnKeys:!0}),x.extend(n,t,null,{allOwnKeys:!0}),n.create=function(i){return Go(ge(e,i))},n}const D=Go(Ze);D.Axios=me;D.CanceledError=De;D.CancelToken=os;D.isCancel=_o;D.VERSION=Ho;D.toFormData=Dt;D.AxiosError=j;D.Cancel=D.CanceledError;D.all=function(t){return Promise.all(t)};D.spread=bh;D.isAxiosError=wh;D.mergeConfig=ge;D.AxiosHeaders=U;D.formToJSON=e=>Io(x.isHTMLForm(e)?new FormData(e):e);D.getAdapter=Ko.getAdapter;D.HttpStatusCode=Sn;D.default=D;async function Xo(){return(await D.get(`${Qe}/auth/status`)).data}async function Sh(e,t){return(await D.post(`${Qe}/auth/login`,{username:e,password:t})).data}async function Th(){await D.post(`${Qe}/auth/logout`)}async function Ph(){try{return(await D.get(`${Qe}/files`)).data}catch(e){throw console.error("Error fetching shared files:",e),new Error("Failed to fetch shared files")}}const X="",Qe="/api";async function qi(e,t){try{const n={message:e,sender:"user"};return t&&(n.filename=t),(await D.post(`${Qe}/chat`,n)).data.content}catch(n){throw console.error("Error sending message to LLM:",n),new Error("Failed to get response from LLM")}}async function Ah(){var e,t,n,s,i,o,r;try{console.log("Fetching peer conversations from:","/peers");const a=await D.get("/peers",{headers:{Accept:"application/json","Content-Type":"application/json"}});if(console.log("Response status:",a.status),console.log("Response headers:",a.headers),console.log("Received peer conversations:",a.data),!a.data||typeof a.data!="object")throw console.error("Invalid response data:",a.data),new Error("Invalid response format");return a.data}catch(a){throw D.isAxiosError(a)?console.error("Error fetching peer conversations:",{status:(e=a.response)==null?void 0:e.status,statusText:(t=a.response)==null?void 0:t.statusText,data:(n=a.response)==null?void 0:n.data,headers:(s=a.response)==null?void 0:s.headers,error:a.message,config:{url:(i=a.config)==null?void 0:i.url,method:(o=a.config)==null?void 0:o.method,headers:(r=a.config)==null?void 0:r.headers}}):console.error("Error fetching peer conversations:",a),new Error("Failed to get peer conversations")}}async function Eh(){try{const e=await D.get(`${X}/api/local`);return console.log("Received local conversation:",e.data),e.data}catch(e){return console.error("Error fetching local conversation:",e),null}}function Rh({onFileUploaded:e}){const[t,n]=b.useState(!1),[s,i]=b.useState(!1),[o,r]=b.useState(0),[a,l]=b.useState(null),c=b.useRef(null),u=g=>{g.preventDefault(),n(!0)},d=g=>{g.preventDefault(),n(!1)},f=g=>{g.preventDefault(),n(!1);const y=Array.from(g.dataTransfer.files);y.length>0&&p(y[0])},m=g=>{const y=g.target.files;y&&y.length>0&&p(y[0])},p=async g=>{if(g.size>50*1024*1024){alert("File too large. Maximum size is 50MB.");return}if(!["image/jpeg","image/png","image/gif","image/webp","text/plain","application/pdf","text/markdown"].includes(g.type)){alert("File type not supported. Allowed types: Images (JPEG, PNG, GIF, WebP), Text files, PDF, Markdown");return}i(!0),r(0);const w=new FormData;w.append("file",g),l(null);try{const v=[`${X}/api/upload`,`${X}/upload`,`${X}/app/api/upload`];let T=null,P=null;for(const A of v)try{console.log("Attempting upload to",A);const E=await fetch(A,{method:"POST",body:w});if(T=E,P=A,E.status!==404)break}catch(E){console.warn("Network error when trying",A,E)}if(!T){l("Upload failed: could not reach server (network error)"),alert("Upload failed: could not reach server (network error)");return}if(T.status===404){const A=await T.text().catch(()=>"[non-plaintext response]");console.error("Upload failed (404) from",P,A),l(`Upload failed (404) from ${P}: ${A}`),alert(`Upload failed (404): ${A}`);return}if(!T.ok){const A=await T.text().catch(()=>"[non-plaintext response]");console.error("Upload failed",T.status,A),l(`Upload failed (${T.status}): ${A}`),alert(`Upload failed (${T.status}): ${A}`);return}let S;try{S=await T.json()}catch(A){const E=await T.text().catch(()=>"[non-plaintext response]");console.error("Upload JSON parse failed:",A,E),l("Upload failed: invalid server response"),alert("Upload failed: invalid server response");return}if(S.success)e(S.file_info),r(100);else{console.error("Upload error from server:",S);const A=S.message||"server error";l(`Upload failed: ${A}`),alert(`Upload failed: ${A}`)}}catch(v){console.error("Upload error:",v),l("Upload failed. Please try again. - "+((v==null?void 0:v.message)||String(v))),alert("Upload failed. Please try again. - "+((v==null?void 0:v.message)||String(v)))}finally{i(!1),r(0),c.current&&(c.current.value="")}};return h.jsxs("div",{className:"relative",children:[h.jsxs("div",{className:`border-2 border-dashed rounded-lg p-6 text-center transition-colors cursor-pointer ${t?"border-blue-400 bg-blue-400/10":"border-gray-600 hover:border-gray-500"} ${s?"pointer-events-none opacity-50":""}`,onDragOver:u,onDragLeave:d,onDrop:f,onClick:()=>{var g;return(g=c.current)==null?void 0:g.click()},children:[h.jsx("input",{ref:c,type:"file",className:"hidden",onChange:m,accept:"image/*,.txt,.pdf,.md"}),s?h.jsxs("div",{className:"space-y-2",children:[h.jsx("div",{className:"w-8 h-8 mx-auto",children:h.jsx("div",{className:"animate-spin rounded-full h-8 w-8 border-t-2 border-b-2 border-blue-500"})}),h.jsx("p",{className:"text-sm text-gray-400",children:"Uploading..."}),h.jsx("div",{className:"w-full bg-gray-700 rounded-full h-2",children:h.jsx("div",{className:"bg-blue-500 h-2 rounded-full transition-all duration-300",style:{width:`${o}%`}})})]}):h.jsxs("div",{className:"space-y-2",children:[h.jsx(Qo,{className:"w-8 h-8 mx-auto text-gray-400"}),h.jsxs("div",{children:[h.jsxs("p",{className:"text-sm text-gray-300",children:["Drag and drop files here, or ",h.jsx("span",{className:"text-blue-400",children:"click to browse"})]}),h.jsx("p",{className:"text-xs text-gray-500 mt-1",children:"Images, PDF, Text files (Max 50MB)"})]})]})]}),a&&h.jsx("div",{className:"mt-3 text-sm text-red-400 bg-red-900/20 p-3 rounded",children:a})]})}const Yo=()=>{const e=b.useRef(null),t=b.useRef([]),n=b.useRef();return b.useEffect(()=>{const s=e.current;if(!s)return;const i=s.getContext("2d");if(!i)return;const o=()=>{s.width=window.innerWidth,s.height=window.innerHeight};o(),window.addEventListener("resize",o);const r=50;t.current=Array.from({length:r},()=>({x:Math.random()*s.width,y:Math.random()*s.height,vx:(Math.random()-.5)*.5,vy:(Math.random()-.5)*.5}));const a=()=>{i.clearRect(0,0,s.width,s.height),i.fillStyle="#39FFDA",i.strokeStyle="#39FFDA";const l=t.current;l.forEach((c,u)=>{c.x+=c.vx,c.y+=c.vy,(c.x<0||c.x>s.width)&&(c.vx*=-1),(c.y<0||c.y>s.height)&&(c.vy*=-1),i.globalAlpha=.2,i.beginPath(),i.arc(c.x,c.y,2,0,Math.PI*2),i.fill(),i.globalAlpha=.05,l.forEach((d,f)=>{if(u===f)return;const m=c.x-d.x,p=c.y-d.y;Math.sqrt(m*m+p*p)<150&&(i.beginPath(),i.moveTo(c.x,c.y),i.lineTo(d.x,d.y),i.stroke())})}),n.current=requestAnimationFrame(a)};return a(),()=>{window.removeEventListener("resize",o),n.current&&cancelAnimationFrame(n.current)}},[]),h.jsx("canvas",{ref:e,className:"absolute inset-0 -z-10",style:{background:"transparent"}})};function Ch({peerCount:e,isLLMHost:t,onNavigatePeers:n,onNavigateAnalytics:s,username:i,onLogout:o}){return h.jsx("div",{className:"fixed top-0 left-0 right-0 z-50 bg-surface-2/75 backdrop-blur-md border-b border-divider",children:h.jsxs("div",{className:"max-w-7xl mx-auto px-4 h-16 flex items-center justify-between",children:[h.jsxs("div",{className:"flex items-center gap-2",children:[h.jsx(_e,{className:"h-7 w-7 text-accent"}),h.jsx("h1",{className:"text-2xl font-semibold text-bright",children:"MeshMind"})]}),h.jsxs("div",{className:"flex items-center gap-4",children:[h.jsxs("div",{className:"flex items-center gap-1 text-sm text-accent",children:[h.jsx(Jt,{className:"h-4 w-4"}),h.jsxs("span",{children:[e," peers"]})]}),h.jsxs("div",{className:"flex items-center gap-1 text-sm",children:[h.jsx(ea,{className:`h-4 w-4 ${t?"text-accent":"text-dim"}`}),h.jsx("span",{className:"text-bright/90",children:t?"LLM Host":"LLM Client"})]}),h.jsx("button",{onClick:n,className:"px-3 py-1.5 rounded-md text-sm bg-panel hover:bg-panel-hover border border-divider text-bright transition-colors",title:"Peer Conversations",children:"Peer Conversations"}),s&&h.jsx("button",{onClick:s,className:"px-3 py-1.5 rounded-md text-sm bg-panel hover:bg-panel-hover border border-divider text-bright transition-colors",title:"Analytics Dashboard",children:"Analytics"}),i&&h.jsxs("div",{className:"flex items-center gap-3 pl-4 ml-4 border-l border-divider text-sm",children:[h.jsx("span",{className:"text-accent font-medium",children:i}),o&&h.jsxs("button",{onClick:o,className:"px-3 py-1.5 rounded-md text-sm bg-panel hover:bg-panel-hover border border-divider text-bright transition-colors flex items-center gap-1",title:"Logout",children:[h.jsx(ta,{className:"h-4 w-4"}),"Logout"]})]})]})]})})}const jh=()=>{const[e,t]=b.useState(""),n="Welcome to MeshMind🧠";return b.useEffect(()=>{let s=0;const i=setInterval(()=>{s<=n.length?(t(n.slice(0,s)),s++):clearInterval(i)},100);return()=>clearInterval(i)},[]),h.jsxs("h2",{className:"text-3xl font-bold text-bright mb-2 tracking-wide text-center",children:[e,h.jsx("span",{className:"animate-blink",children:"|"})]})};function Dh({onLoadSaved:e,savedCount:t,onCardClick:n}){const s=[{icon:na,title:"Greeting",description:"Start with a friendly hello",question:"Hello! How are you doing today? Can you tell me a bit about yourself and what you can help me with?"},{icon:sa,title:"Learn",description:"Ask about complex topics",question:"I'd like to learn something new today. Can you explain a complex topic in a simple and engaging way? Feel free to choose something interesting."},{icon:ia,title:"Code",description:"Get help with programming",question:"I need help with programming. Can you help me write, debug, or explain code? I'm working on a project and would appreciate your assistance."},{icon:ra,title:"General",description:"Ask anything you want",question:"I have a question for you. Can you help me with anything you think might be useful or interesting?"}];return h.jsx("div",{className:"w-full h-full flex items-center justify-center",children:h.jsxs("div",{className:"px-8 py-8 rounded-2xl bg-[#111111]/60 shadow-[0_0_20px_rgba(0,245,212,0.06)] border border-[#1a1a1a] backdrop-blur-md",style:{width:"640px",maxWidth:"90vw"},children:[h.jsx(_e,{className:"h-16 w-16 text-[#00f5d4] mx-auto mb-4 drop-shadow-[0_0_8px_#00f5d4] animate-float"}),h.jsx(jh,{}),h.jsx("p",{className:"text-gray-400 mb-4 text-lg text-center",children:"Start a conversation with your AI assistant. Your message will be processed by the best available LLM in the network."}),h.jsx("div",{className:"grid grid-cols-2 gap-3 mb-6 mt-6",children:s.map((i,o)=>{const r=i.icon;return h.jsxs("div",{onClick:()=>n==null?void 0:n(i.question),className:"p-4 rounded-lg bg-[#0a0a0a]/80 border border-[#1a1a1a] hover:border-[#00f5d4]/30 transition-all cursor-pointer hover:shadow-[0_0_12px_rgba(0,245,212,0.1)] active:scale-95",children:[h.jsx(r,{className:"h-6 w-6 text-[#00f5d4] mb-2"}),h.jsx("h3",{className:"text-sm font-semibold text-white mb-1",children:i.title}),h.jsx("p",{className:"text-xs text-gray-400",children:i.description})]},o)})}),h.jsxs("div",{className:"flex flex-col items-center",children:[h.jsx("button",{onClick:e,disabled:!(t>0),className:`px-5 py-2 rounded-lg shadow-md transition-transform ${t>0?"bg-gradient-to-r from-[#00f5d4] to-[#ff4ff8] text-black hover:scale-[1.02]":"bg-[#222] text-gray-400 cursor-not-allowed opacity-60"}`,children:"Load previous conversation"}),h.jsx("div",{className:"mt-2 text-xs text-gray-400",children:t>0?h.jsxs("span",{children:[t," messages available"]}):h.jsx("span",{children:"No saved conversation found"})})]})]})})}function Nh(){const[e,t]=b.useState([]),[n,s]=b.useState(!0),[i,o]=b.useState(null),[r,a]=b.useState(!1),[l,c]=b.useState(null),[u,d]=b.useState({});if(b.useEffect(()=>{fetch(`${X}/api/local`).then(v=>v.json()).then(v=>{var T;(T=v==null?void 0:v.host_info)!=null&&T.ip_address&&c(v.host_info.ip_address)}).catch(()=>{});async function p(){s(!0),o(null);try{const v=new AbortController,T=setTimeout(()=>v.abort(),5e3),P=await Ph();clearTimeout(T),t(P)}catch(v){v.name==="AbortError"?o("Request timeout"):o(v.message||"Failed to fetch files")}s(!1)}async function g(){try{const v=await Ah(),T={};Object.entries(v).forEach(([P,S])=>{var E;const A=(E=S==null?void 0:S.host_info)==null?void 0:E.hostname;A&&typeof A=="string"&&A.trim().length>0&&(T[P]=A)}),d(T)}catch{}}p(),g();const y=setInterval(p,5e3),w=setInterval(g,1e4);return()=>{clearInterval(y),clearInterval(w)}},[]),i&&e.length===0)return h.jsx("div",{className:"p-4 text-red-500",children:i});if(e.length===0&&n)return h.jsx("div",{className:"p-4 text-center text-gray-400",children:"Loading shared files..."});const f=l?e.filter(p=>p.uploader_ip===l||p.uploader_ip==="127.0.0.1"):[],m=l?e.filter(p=>p.uploader_ip!==l&&p.uploader_ip!=="127.0.0.1"):e;return h.jsx("div",{className:"w-full mb-3",children:h.jsxs("div",{className:"bg-panel border border-divider rounded-lg shadow-soft overflow-hidden",style:{maxHeight:r?"60px":"400px",transition:"max-height 0.3s ease"},children:[h.jsxs("button",{onClick:()=>a(!r),className:"w-full flex items-center justify-between p-4 bg-surface/40 border-b border-divider hover:bg-surface/60 transition-colors",children:[h.jsxs("h2",{className:"text-lg font-semibold text-accent flex items-center gap-2",children:[h.jsx(Jt,{className:"w-5 h-5"}),"Shared Files (",e.length,")"]}),r?h.jsx(oa,{className:"w-5 h-5 text-dim"}):h.jsx(aa,{className:"w-5 h-5 text-dim"})]}),!r&&h.jsx("div",{className:"p-4 overflow-y-auto overflow-x-hidden",style:{maxHeight:"340px"},children:e.length===0?h.jsx("div",{className:"text-dim text-center py-4",children:"No files shared yet."}):h.jsxs("div",{className:"space-y-4",children:[m.length>0&&h.jsxs("div",{children:[h.jsxs("h3",{className:"text-sm font-semibold text-accent mb-2 flex items-center gap-2",children:[h.jsx(Jt,{className:"w-4 h-4"}),"From Peers (",m.length,")"]}),h.jsx("ul",{className:"space-y-2",children:m.map(p=>h.jsxs("li",{className:"flex items-center justify-between gap-3 bg-surface border border-divider rounded-lg px-3 py-2 hover:bg-surface/80 transition-colors min-w-0",children:[h.jsxs("div",{className:"min-w-0",children:[h.jsx("div",{className:"font-mono text-sm text-bright truncate",title:p.filename,children:p.filename}),h.jsxs("div",{className:"text-[11px] text-dim mt-0.5 flex items-center gap-2",children:[h.jsxs("span",{children:[(p.file_size/1024).toFixed(1)," KB"]}),h.jsxs("span",{className:"hidden md:inline-flex items-center gap-1 text-accent",children:[h.jsx(cs,{className:"w-3 h-3"})," ",u[p.uploader_ip]||p.uploader_ip]})]})]}),h.jsxs("div",{className:"flex items-center gap-2 flex-shrink-0",children:[h.jsx("button",{onClick:()=>window.dispatchEvent(new CustomEvent("meshmind:ask-file",{detail:{filename:p.filename}})),className:"h-7 w-7 inline-flex items-center justify-center bg-panel border border-divider rounded hover:bg-panel/80",title:"Ask about this file",children:h.jsx(Zt,{className:"w-4 h-4 text-accent"})}),(()=>{const y=p.uploader_ip===l||p.uploader_ip==="127.0.0.1"?`${X}/api/files/${encodeURIComponent(p.filename)}`:`${X}/api/peer-file/${p.uploader_ip}/${encodeURIComponent(p.filename)}`;return h.jsx("a",{href:y,download:p.filename,className:"h-7 w-7 inline-flex items-center justify-center bg-accent text-black rounded hover:bg-accent/90",title:"Download",children:h.jsx(us,{className:"w-4 h-4"})})})(),p.file_type.startsWith("image/")&&(()=>{const y=p.uploader_ip===l||p.uploader_ip==="127.0.0.1"?`${X}/api/files/${encodeURIComponent(p.filename)}`:`${X}/api/peer-file/${p.uploader_ip}/${encodeURIComponent(p.filename)}`;return h.jsx("a",{href:y,target:"_blank",rel:"noopener noreferrer",className:"h-7 w-7 inline-flex items-center justify-center bg-bright text-black rounded hover:bg-accent/90",title:"Preview image",children:h.jsx(ds,{className:"w-4 h-4"})})})()]})]},p.filename+p.upload_time))})]}),f.length>0&&h.jsxs("div",{children:[h.jsxs("h3",{className:"text-sm font-semibold text-bright mb-2 flex items-center gap-2",children:[h.jsx(cs,{className:"w-4 h-4"}),"My Files (",f.length,")"]}),h.jsx("ul",{className:"space-y-2",children:f.map(p=>h.jsxs("li",{className:"flex items-center justify-between gap-3 bg-surface border border-divider rounded-lg px-3 py-2 hover:bg-surface/80 transition-colors min-w-0",children:[h.jsxs("div",{className:"min-w-0",children:[h.jsx("div",{className:"font-mono text-sm text-bright truncate",title:p.filename,children:p.filename}),h.jsxs("div",{className:"text-[11px] text-dim mt-0.5",children:[(p.file_size/1024).toFixed(1)," KB • by me"]})]}),h.jsxs("div",{className:"flex items-center gap-2 flex-shrink-0",children:[h.jsx("button",{onClick:()=>window.dispatchEvent(new CustomEvent("meshmind:ask-file",{detail:{filename:p.filename}})),className:"h-7 w-7 inline-flex items-center justify-center bg-panel border border-divider rounded hover:bg-panel/80",title:"Ask about this file",children:h.jsx(Zt,{className:"w-4 h-4 text-accent"})}),h.jsx("a",{href:`${X}/api/files/${encodeURIComponent(p.filename)}`,download:p.filename,className:"h-7 w-7 inline-flex items-center justify-center bg-accent text-black rounded hover:bg-accent/90",title:"Download",children:h.jsx(us,{className:"w-4 h-4"})}),p.file_type.startsWith("image/")&&h.jsx("a",{href:`${X}/api/files/${encodeURIComponent(p.filename)}`,target:"_blank",rel:"noopener noreferrer",className:"h-7 w-7 inline-flex items-center justify-center bg-bright text-black rounded hover:bg-accent/90",title:"Preview image",children:h.jsx(ds,{className:"w-4 h-4"})})]})]},p.filename+p.upload_time))})]})]})})]})})}function Mh(){return h.jsxs("aside",{className:"w-full bg-surface/70 backdrop-blur-sm",children:[h.jsx("div",{className:"h-16 border-b border-divider flex items-center px-4 text-sm text-bright/80",children:"Shared Files"}),h.jsx("div",{className:"p-3",children:h.jsx(Nh,{})})]})}const Vh=b.lazy(()=>Gi(()=>import("./PeersConversation-CpTOaqGB.js"),__vite__mapDeps([0,1,2])).then(e=>({default:e.PeersConversation}))),Lh=b.lazy(()=>Gi(()=>import("./AnalyticsDashboard-Cvv0TozQ.js"),__vite__mapDeps([3,1,2,4])));function Oh(){const[e,t]=b.useState(0),[n,s]=b.useState(!1);return b.useEffect(()=>{const i=async()=>{try{const a=await(await fetch("/api/status")).json();typeof a.peer_count=="number"&&t(a.peer_count),typeof a.is_llm_host=="boolean"&&s(a.is_llm_host)}catch{t(0),s(!1)}};i();const o=setInterval(i,5e3);return()=>clearInterval(o)},[]),{peerCount:e,isLLMHost:n}}function kh({onNavigate:e,conversation:t,setConversation:n,isTyping:s,setIsTyping:i,savedLocal:o,loadSavedLocal:r,username:a,onLogout:l}){const{peerCount:c,isLLMHost:u}=Oh(),[d,f]=b.useState(""),[m,p]=b.useState(!1),g=b.useRef(null),y=()=>{var P;(P=g.current)==null||P.scrollIntoView({behavior:"smooth"})};b.useEffect(()=>{y()},[t]),b.useEffect(()=>{},[t.length]);const w=async()=>{if(d.trim()===""||s)return;const P={role:"user",content:d},S=[...t,P];n(S),f(""),i(!0);try{const A=await qi(d);i(!1),n([...S,{role:"assistant",content:A}])}catch(A){console.error("Error getting LLM response:",A),i(!1),n([...S,{role:"assistant",content:"Sorry, I encountered an error while processing your request."}])}},v=async P=>{if(d.trim()===""||s)return;const S={role:"user",content:d},A=[...t,S];n(A),f(""),i(!0);try{const E=await qi(S.content,P);i(!1),n([...A,{role:"assistant",content:E}])}catch(E){console.error("Error getting LLM response:",E),i(!1),n([...A,{role:"assistant",content:"Sorry, I encountered an error while processing your request."}])}};b.useEffect(()=>{const P=S=>{var C;const E=(C=S.detail)==null?void 0:C.filename;E&&(f(`Explain the contents of ${E}`),setTimeout(()=>v(E),50))};return window.addEventListener("meshmind:ask-file",P),()=>window.removeEventListener("meshmind:ask-file",P)},[t,s,d]);const T=P=>{const S={role:"user",content:`📎 Shared file: ${P.filename}`,timestamp:new Date().toISOString(),fileInfo:P};n([...t,S]),p(!1)};return h.jsxs("div",{className:"h-screen w-full text-white font-inter flex flex-col overflow-hidden",style:{background:"#0b0b0b"},children:[h.jsx("div",{className:"flex-shrink-0 relative z-50 h-16",children:h.jsx(Ch,{peerCount:c,isLLMHost:u,onNavigatePeers:()=>e("peers"),onNavigateAnalytics:()=>e("analytics"),username:a,onLogout:l})}),h.jsxs(te.div,{className:"flex-1 flex gap-4 overflow-hidden relative",initial:"initial",animate:"animate",variants:Wd(.08),children:[h.jsx(te.div,{variants:qd,className:"w-[320px] flex-shrink-0 flex flex-col border-r border-divider bg-surface/40 transition-all duration-300 will-change-transform will-change-opacity z-20",children:h.jsx("div",{className:"flex-1 overflow-auto p-4 h-[calc(100vh-4rem)]",style:{scrollbarWidth:"auto",scrollbarColor:"#00bfa6 #1a1a1a"},children:h.jsx(Mh,{})})}),h.jsx(te.div,{variants:Ao,className:"flex-1 flex flex-col items-center justify-start px-4 py-6 overflow-hidden will-change-transform will-change-opacity",children:h.jsxs("div",{className:"w-full h-full flex flex-col rounded-2xl border border-divider bg-panel/80 backdrop-blur-sm shadow-soft overflow-hidden z-0",style:{maxWidth:"calc(100% - 2rem)"},children:[h.jsx("div",{className:"flex-1 flex flex-col overflow-hidden",children:t.length===0?h.jsxs("div",{className:"flex-1 relative overflow-y-auto",children:[h.jsx(Yo,{}),h.jsx("div",{className:"absolute inset-0 flex flex-col items-center justify-center",children:h.jsx(Dh,{onLoadSaved:r,savedCount:o&&o.messages?o.messages.length:0,onCardClick:P=>{f(P)}})})]}):h.jsxs("div",{className:"flex-1 overflow-y-auto px-4 py-4 space-y-3 scroll-smooth min-h-0",children:[h.jsx(Ji,{initial:!1,children:t.map((P,S)=>{var A;return h.jsxs(te.div,{initial:{opacity:0,y:10},animate:{opacity:1,y:0,transition:{duration:.25,ease:"easeInOut"}},exit:{opacity:0,y:6,transition:{duration:.2,ease:"easeInOut"}},className:"flex items-start gap-3 py-3 px-4 rounded-lg transition-all duration-200 bg-surface border border-divider",children:[P.role==="assistant"?h.jsx(fs,{className:"w-6 h-6 text-accent mt-1 flex-shrink-0"}):h.jsx("div",{className:"w-6 h-6 rounded-full bg-surface-2 border border-divider flex items-center justify-center flex-shrink-0",children:h.jsx("span",{className:"text-xs text-white font-medium",children:"You"})}),h.jsxs("div",{className:"flex-1 min-w-0",children:[h.jsx("p",{className:"text-bright whitespace-pre-wrap leading-relaxed text-sm",children:P.content}),((A=P.fileInfo)==null?void 0:A.filename)&&h.jsx("div",{className:"mt-2",children:h.jsxs("button",{onClick:()=>{const E=P.fileInfo.filename;f(`Explain the contents of ${E}`),setTimeout(()=>v(E),50)},className:"inline-flex items-center gap-1 px-2 py-1 rounded-md text-xs bg-panel hover:bg-panel-hover border border-divider text-bright/90",children:[h.jsx(Zt,{className:"h-3.5 w-3.5 text-accent"}),"Ask about this file"]})})]})]},S)})}),s&&h.jsxs("div",{className:"flex items-center gap-3 py-3 px-4 rounded-lg bg-surface border border-divider",children:[h.jsx(fs,{className:"w-6 h-6 text-accent flex-shrink-0"}),h.jsxs("div",{className:"flex items-center gap-2",children:[h.jsx(la,{className:"w-4 h-4 text-gray-400 animate-spin"}),h.jsx("span",{className:"text-gray-400 text-sm",children:"Thinking..."})]})]}),h.jsx("div",{ref:g})]})}),h.jsxs("div",{className:"flex-shrink-0 border-t border-divider bg-panel/80 p-4 space-y-2 z-10 backdrop-blur-sm",children:[m&&h.jsx("div",{className:"mb-3",children:h.jsx(Rh,{onFileUploaded:T})}),h.jsxs("form",{onSubmit:P=>{P.preventDefault(),w()},className:"space-y-2",children:[h.jsxs("div",{className:"relative",children:[h.jsx("textarea",{value:d,onChange:P=>f(P.target.value),onKeyDown:P=>{P.key==="Enter"&&!P.shiftKey&&(P.preventDefault(),w())},placeholder:"Ask anything... (Shift+Enter for new line)",className:"w-full bg-surface border border-divider text-bright px-4 py-3 pr-20 rounded-lg focus:outline-none focus:border-accent focus:ring-1 focus:ring-accent/20 resize-none min-h-[40px] max-h-20 shadow transition-all duration-200 overflow-hidden",style:{fontSize:"0.95rem",height:"auto"},rows:1,onInput:P=>{const S=P.target;S.style.height="auto",S.style.height=Math.min(S.scrollHeight,128)+"px"}}),h.jsx(te.button,{type:"button",onClick:()=>p(!m),className:"absolute right-12 bottom-3 h-8 w-8 flex items-center justify-center text-gray-400 hover:text-accent transition-colors duration-200",title:"Attach file",whileHover:{scale:1.05},whileTap:{scale:.98},children:h.jsx(ca,{className:"h-5 w-5"})}),h.jsx(te.button,{type:"submit",disabled:s||!d.trim(),className:"absolute right-3 bottom-3 h-8 w-8 flex items-center justify-center bg-accent hover:bg-accent/90 disabled:opacity-50 disabled:hover:bg-accent rounded-full transition-all duration-200 shadow-lg",whileTap:{scale:.95},children:h.jsx(ua,{className:"h-5 w-5 text-black"})})]}),h.jsxs("div",{className:"flex justify-between items-center text-xs text-gray-500",children:[h.jsx("span",{children:"Press Enter to send, Shift+Enter for new line"}),h.jsxs("span",{className:d.length>1e3?"text-accent":"",children:[d.length,"/2000"]})]})]})]})]})})]})]})}function Fh({username:e,onLogout:t}){const[n,s]=b.useState("chat"),[i,o]=b.useState([]),[r,a]=b.useState(!1),[l,c]=b.useState(null);b.useEffect(()=>{(async()=>{try{const f=await Eh();c(f)}catch(f){console.error("Failed to fetch local conversation:",f)}})()},[]);const u=()=>{if(!l||!l.messages)return;const d=l.messages.map(f=>({role:f.message_type==="Response"?"assistant":"user",content:f.content,timestamp:f.timestamp,hostInfo:f.host_info}));o(d)};return h.jsx(Ji,{mode:"wait",children:h.jsx(te.div,{variants:Kd,initial:"initial",animate:"animate",exit:"exit",className:"bg-dark",children:n==="chat"?h.jsx(kh,{onNavigate:s,conversation:i,setConversation:o,isTyping:r,setIsTyping:a,savedLocal:l,loadSavedLocal:u,username:e,onLogout:t}):n==="analytics"?h.jsxs("div",{className:"min-h-screen text-white",children:[h.jsx("div",{className:"fixed top-4 left-4 z-10",children:h.jsxs("button",{onClick:()=>s("chat"),className:"flex items-center gap-2 px-4 py-2 bg-panel hover:bg-panel-hover rounded-lg text-white border border-[#232323]",children:[h.jsx(_e,{className:"h-5 w-5"}),h.jsx("span",{children:"Back to Chat"})]})}),h.jsx("div",{className:"pt-16",children:h.jsx(b.Suspense,{fallback:h.jsx("div",{className:"p-6 text-gray-400",children:"Loading analytics…"}),children:h.jsx(Lh,{})})})]}):h.jsxs("div",{children:[h.jsx("div",{className:"fixed top-4 left-4 z-10",children:h.jsxs("button",{onClick:()=>s("chat"),className:"flex items-center gap-2 px-4 py-2 bg-panel hover:bg-panel-hover rounded-lg text-white",children:[h.jsx(_e,{className:"h-5 w-5"}),h.jsx("span",{children:"Back to Chat"})]})}),h.jsx(b.Suspense,{fallback:h.jsx("div",{className:"p-6 text-gray-400",children:"Loading peers…"}),children:h.jsx(Vh,{})})]})},n)})}function Bh({onAuthenticated:e}){const[t,n]=b.useState(""),[s,i]=b.useState(""),[o,r]=b.useState(null),[a,l]=b.useState(!0);b.useEffect(()=>{(async()=>{try{const u=await Xo();u.authenticated&&u.username&&e(u.username)}catch{}l(!1)})()},[e]);const c=async u=>{u.preventDefault(),r(null);try{const d=await Sh(t,s);d.authenticated&&d.username?e(d.username):r("Invalid credentials")}catch{r("Login failed")}};return a?h.jsx("div",{className:"min-h-screen flex items-center justify-center text-gray-300",children:"Loading…"}):h.jsxs("div",{className:"min-h-screen relative flex items-center justify-center bg-dark overflow-hidden",children:[h.jsx("div",{className:"absolute inset-0",children:h.jsx(Yo,{})}),h.jsx("div",{className:"pointer-events-none absolute inset-0 bg-[radial-gradient(600px_circle_at_center,rgba(0,191,166,0.08),transparent_60%)]"}),h.jsx("div",{className:"pointer-events-none absolute inset-0 bg-black/10"}),h.jsxs(te.form,{initial:"initial",animate:"animate",variants:Ao,onSubmit:c,className:"relative z-10 w-full max-w-sm p-6 rounded-xl bg-white/5 backdrop-blur-md border border-white/10 shadow-soft will-change-transform will-change-opacity",children:[h.jsxs("div",{className:"mb-4 flex items-center justify-center gap-2",children:[h.jsx(_e,{className:"h-7 w-7 text-accent"}),h.jsx("span",{className:"text-2xl font-semibold text-bright",children:"MeshMind"})]}),h.jsx("p",{className:"text-center text-dim text-sm mb-5",children:"Enterprise AI Network"}),h.jsx("h1",{className:"text-base font-medium text-bright/90 mb-3",children:"Sign in"}),h.jsxs("div",{className:"space-y-3",children:[h.jsxs("div",{children:[h.jsx("label",{className:"block text-sm text-dim mb-1",children:"Username"}),h.jsx("input",{value:t,onChange:u=>n(u.target.value),className:"w-full bg-surface border border-divider text-bright px-3 py-2 rounded-md focus:outline-none focus:border-accent focus:ring-1 focus:ring-accent/20"})]}),h.jsxs("div",{children:[h.jsx("label",{className:"block text-sm text-dim mb-1",children:"Password"}),h.jsx("input",{type:"password",value:s,onChange:u=>i(u.target.value),className:"w-full bg-surface border border-divider text-bright px-3 py-2 rounded-md focus:outline-none focus:border-accent focus:ring-1 focus:ring-accent/20"})]})]}),o&&h.jsx("div",{className:"mt-3 text-sm text-red-400",children:o}),h.jsx(te.button,{whileHover:{scale:1.05},whileTap:{scale:.98},type:"submit",className:"mt-5 w-full py-2 bg-gradient-to-r from-accent to-accent-secondary text-black rounded-md shadow-soft hover:shadow-glow transition-shadow",children:"Login"})]})]})}function Ih(){const[e,t]=b.useState(!1),[n,s]=b.useState(null);if(b.useEffect(()=>{(async()=>{try{const o=await Xo();t(o.authenticated),s(o.username??null)}catch{}})()},[]),!e)return h.jsx(Bh,{onAuthenticated:o=>{t(!0),s(o)}});const i=async()=>{try{await Th()}catch{}t(!1),s(null)};return h.jsx(Fh,{username:n??void 0,onLogout:i})}Hi(document.getElementById("root")).render(h.jsx(b.StrictMode,{children:h.jsx(Ih,{})}));export{Ah as g,h as j};
+`+o):s.stack=o}catch{}}throw s}}_request(t,n){typeof t=="string"?(n=n||{},n.url=t):n=t||{},n=ge(this.defaults,n);const{transitional:s,paramsSerializer:i,headers:o}=n;s!==void 0&&ct.assertOptions(s,{silentJSONParsing:J.transitional(J.boolean),forcedJSONParsing:J.transitional(J.boolean),clarifyTimeoutError:J.transitional(J.boolean)},!1),i!=null&&(x.isFunction(i)?n.paramsSerializer={serialize:i}:ct.assertOptions(i,{encode:J.function,serialize:J.function},!0)),n.allowAbsoluteUrls!==void 0||(this.defaults.allowAbsoluteUrls!==void 0?n.allowAbsoluteUrls=this.defaults.allowAbsoluteUrls:n.allowAbsoluteUrls=!0),ct.assertOptions(n,{baseUrl:J.spelling("baseURL"),withXsrfToken:J.spelling("withXSRFToken")},!0),n.method=(n.method||this.defaults.method||"get").toLowerCase();let r=o&&x.merge(o.common,o[n.method]);o&&x.forEach(["delete","get","head","post","put","patch","common"],p=>{delete o[p]}),n.headers=U.concat(r,o);const a=[];let l=!0;this.interceptors.request.forEach(function(g){typeof g.runWhen=="function"&&g.runWhen(n)===!1||(l=l&&g.synchronous,a.unshift(g.fulfilled,g.rejected))});const c=[];this.interceptors.response.forEach(function(g){c.push(g.fulfilled,g.rejected)});let u,d=0,f;if(!l){const p=[$i.bind(this),void 0];for(p.unshift.apply(p,a),p.push.apply(p,c),f=p.length,u=Promise.resolve(n);d<f;)u=u.then(p[d++],p[d++]);return u}f=a.length;let m=n;for(d=0;d<f;){const p=a[d++],g=a[d++];try{m=p(m)}catch(y){g.call(this,y);break}}try{u=$i.call(this,m)}catch(p){return Promise.reject(p)}for(d=0,f=c.length;d<f;)u=u.then(c[d++],c[d++]);return u}getUri(t){t=ge(this.defaults,t);const n=$o(t.baseURL,t.url,t.allowAbsoluteUrls);return Fo(n,t.params,t.paramsSerializer)}}x.forEach(["delete","get","head","options"],function(t){me.prototype[t]=function(n,s){return this.request(ge(s||{},{method:t,url:n,data:(s||{}).data}))}});x.forEach(["post","put","patch"],function(t){function n(s){return function(o,r,a){return this.request(ge(a||{},{method:t,headers:s?{"Content-Type":"multipart/form-data"}:{},url:o,data:r}))}}me.prototype[t]=n(),me.prototype[t+"Form"]=n(!0)});class os{constructor(t){if(typeof t!="function")throw new TypeError("executor must be a function.");let n;this.promise=new Promise(function(o){n=o});const s=this;this.promise.then(i=>{if(!s._listeners)return;let o=s._listeners.length;for(;o-- >0;)s._listeners[o](i);s._listeners=null}),this.promise.then=i=>{let o;const r=new Promise(a=>{s.subscribe(a),o=a}).then(i);return r.cancel=function(){s.unsubscribe(o)},r},t(function(o,r,a){s.reason||(s.reason=new De(o,r,a),n(s.reason))})}throwIfRequested(){if(this.reason)throw this.reason}subscribe(t){if(this.reason){t(this.reason);return}this._listeners?this._listeners.push(t):this._listeners=[t]}unsubscribe(t){if(!this._listeners)return;const n=this._listeners.indexOf(t);n!==-1&&this._listeners.splice(n,1)}toAbortSignal(){const t=new AbortController,n=s=>{t.abort(s)};return this.subscribe(n),t.signal.unsubscribe=()=>this.unsubscribe(n),t.signal}static source(){let t;return{token:new os(function(i){t=i}),cancel:t}}}function bh(e){return function(n){return e.apply(null,n)}}function wh(e){return x.isObject(e)&&e.isAxiosError===!0}const Sn={Continue:100,SwitchingProtocols:101,Processing:102,EarlyHints:103,Ok:200,Created:201,Accepted:202,NonAuthoritativeInformation:203,NoContent:204,ResetContent:205,PartialContent:206,MultiStatus:207,AlreadyReported:208,ImUsed:226,MultipleChoices:300,MovedPermanently:301,Found:302,SeeOther:303,NotModified:304,UseProxy:305,Unused:306,TemporaryRedirect:307,PermanentRedirect:308,BadRequest:400,Unauthorized:401,PaymentRequired:402,Forbidden:403,NotFound:404,MethodNotAllowed:405,NotAcceptable:406,ProxyAuthenticationRequired:407,RequestTimeout:408,Conflict:409,Gone:410,LengthRequired:411,PreconditionFailed:412,PayloadTooLarge:413,UriTooLong:414,UnsupportedMediaType:415,RangeNotSatisfiable:416,ExpectationFailed:417,ImATeapot:418,MisdirectedRequest:421,UnprocessableEntity:422,Locked:423,FailedDependency:424,TooEarly:425,UpgradeRequired:426,PreconditionRequired:428,TooManyRequests:429,RequestHeaderFieldsTooLarge:431,UnavailableForLegalReasons:451,InternalServerError:500,NotImplemented:501,BadGateway:502,ServiceUnavailable:503,GatewayTimeout:504,HttpVersionNotSupported:505,VariantAlsoNegotiates:506,InsufficientStorage:507,LoopDetected:508,NotExtended:510,NetworkAuthenticationRequired:511};Object.entries(Sn).forEach(([e,t])=>{Sn[t]=e});function Go(e){const t=new me(e),n=Eo(me.prototype.request,t);return x.extend(n,me.prototype,t,{allOwnKeys:!0}),x.extend(n,t,null,{allOwnKeys:!0}),n.create=function(i){return Go(ge(e,i))},n}const D=Go(Ze);D.Axios=me;D.CanceledError=De;D.CancelToken=os;D.isCancel=_o;D.VERSION=Ho;D.toFormData=Dt;D.AxiosError=j;D.Cancel=D.CanceledError;D.all=function(t){return Promise.all(t)};D.spread=bh;D.isAxiosError=wh;D.mergeConfig=ge;D.AxiosHeaders=U;D.formToJSON=e=>Io(x.isHTMLForm(e)?new FormData(e):e);D.getAdapter=Ko.getAdapter;D.HttpStatusCode=Sn;D.default=D;async function Xo(){return(await D.get(`${Qe}/auth/status`)).data}async function Sh(e,t){return(await D.post(`${Qe}/auth/login`,{username:e,password:t})).data}async function Th(){await D.post(`${Qe}/auth/logout`)}async function Ph(){try{return(await D.get(`${Qe}/files`)).data}catch(e){throw console.error("Error fetching shared files:",e),new Error("Failed to fetch shared files")}}const X=document.querySelector('meta[name="base-path"]')?.content??"",Qe="/api";async function qi(e,t){try{const n={message:e,sender:"user"};return t&&(n.filename=t),(await D.post(`${Qe}/chat`,n)).data.content}catch(n){throw console.error("Error sending message to LLM:",n),new Error("Failed to get response from LLM")}}async function Ah(){var e,t,n,s,i,o,r;try{console.log("Fetching peer conversations from:","/peers");const a=await D.get("/peers",{headers:{Accept:"application/json","Content-Type":"application/json"}});if(console.log("Response status:",a.status),console.log("Response headers:",a.headers),console.log("Received peer conversations:",a.data),!a.data||typeof a.data!="object")throw console.error("Invalid response data:",a.data),new Error("Invalid response format");return a.data}catch(a){throw D.isAxiosError(a)?console.error("Error fetching peer conversations:",{status:(e=a.response)==null?void 0:e.status,statusText:(t=a.response)==null?void 0:t.statusText,data:(n=a.response)==null?void 0:n.data,headers:(s=a.response)==null?void 0:s.headers,error:a.message,config:{url:(i=a.config)==null?void 0:i.url,method:(o=a.config)==null?void 0:o.method,headers:(r=a.config)==null?void 0:r.headers}}):console.error("Error fetching peer conversations:",a),new Error("Failed to get peer conversations")}}async function Eh(){try{const e=await D.get(`${X}/api/local`);return console.log("Received local conversation:",e.data),e.data}catch(e){return console.error("Error fetching local conversation:",e),null}}function Rh({onFileUploaded:e}){const[t,n]=b.useState(!1),[s,i]=b.useState(!1),[o,r]=b.useState(0),[a,l]=b.useState(null),c=b.useRef(null),u=g=>{g.preventDefault(),n(!0)},d=g=>{g.preventDefault(),n(!1)},f=g=>{g.preventDefault(),n(!1);const y=Array.from(g.dataTransfer.files);y.length>0&&p(y[0])},m=g=>{const y=g.target.files;y&&y.length>0&&p(y[0])},p=async g=>{if(g.size>50*1024*1024){alert("File too large. Maximum size is 50MB.");return}if(!["image/jpeg","image/png","image/gif","image/webp","text/plain","application/pdf","text/markdown"].includes(g.type)){alert("File type not supported. Allowed types: Images (JPEG, PNG, GIF, WebP), Text files, PDF, Markdown");return}i(!0),r(0);const w=new FormData;w.append("file",g),l(null);try{const v=[`${X}/api/upload`,`${X}/upload`,`${X}/app/api/upload`];let T=null,P=null;for(const A of v)try{console.log("Attempting upload to",A);const E=await fetch(A,{method:"POST",body:w});if(T=E,P=A,E.status!==404)break}catch(E){console.warn("Network error when trying",A,E)}if(!T){l("Upload failed: could not reach server (network error)"),alert("Upload failed: could not reach server (network error)");return}if(T.status===404){const A=await T.text().catch(()=>"[non-plaintext response]");console.error("Upload failed (404) from",P,A),l(`Upload failed (404) from ${P}: ${A}`),alert(`Upload failed (404): ${A}`);return}if(!T.ok){const A=await T.text().catch(()=>"[non-plaintext response]");console.error("Upload failed",T.status,A),l(`Upload failed (${T.status}): ${A}`),alert(`Upload failed (${T.status}): ${A}`);return}let S;try{S=await T.json()}catch(A){const E=await T.text().catch(()=>"[non-plaintext response]");console.error("Upload JSON parse failed:",A,E),l("Upload failed: invalid server response"),alert("Upload failed: invalid server response");return}if(S.success)e(S.file_info),r(100);else{console.error("Upload error from server:",S);const A=S.message||"server error";l(`Upload failed: ${A}`),alert(`Upload failed: ${A}`)}}catch(v){console.error("Upload error:",v),l("Upload failed. Please try again. - "+((v==null?void 0:v.message)||String(v))),alert("Upload failed. Please try again. - "+((v==null?void 0:v.message)||String(v)))}finally{i(!1),r(0),c.current&&(c.current.value="")}};return h.jsxs("div",{className:"relative",children:[h.jsxs("div",{className:`border-2 border-dashed rounded-lg p-6 text-center transition-colors cursor-pointer ${t?"border-blue-400 bg-blue-400/10":"border-gray-600 hover:border-gray-500"} ${s?"pointer-events-none opacity-50":""}`,onDragOver:u,onDragLeave:d,onDrop:f,onClick:()=>{var g;return(g=c.current)==null?void 0:g.click()},children:[h.jsx("input",{ref:c,type:"file",className:"hidden",onChange:m,accept:"image/*,.txt,.pdf,.md"}),s?h.jsxs("div",{className:"space-y-2",children:[h.jsx("div",{className:"w-8 h-8 mx-auto",children:h.jsx("div",{className:"animate-spin rounded-full h-8 w-8 border-t-2 border-b-2 border-blue-500"})}),h.jsx("p",{className:"text-sm text-gray-400",children:"Uploading..."}),h.jsx("div",{className:"w-full bg-gray-700 rounded-full h-2",children:h.jsx("div",{className:"bg-blue-500 h-2 rounded-full transition-all duration-300",style:{width:`${o}%`}})})]}):h.jsxs("div",{className:"space-y-2",children:[h.jsx(Qo,{className:"w-8 h-8 mx-auto text-gray-400"}),h.jsxs("div",{children:[h.jsxs("p",{className:"text-sm text-gray-300",children:["Drag and drop files here, or ",h.jsx("span",{className:"text-blue-400",children:"click to browse"})]}),h.jsx("p",{className:"text-xs text-gray-500 mt-1",children:"Images, PDF, Text files (Max 50MB)"})]})]})]}),a&&h.jsx("div",{className:"mt-3 text-sm text-red-400 bg-red-900/20 p-3 rounded",children:a})]})}const Yo=()=>{const e=b.useRef(null),t=b.useRef([]),n=b.useRef();return b.useEffect(()=>{const s=e.current;if(!s)return;const i=s.getContext("2d");if(!i)return;const o=()=>{s.width=window.innerWidth,s.height=window.innerHeight};o(),window.addEventListener("resize",o);const r=50;t.current=Array.from({length:r},()=>({x:Math.random()*s.width,y:Math.random()*s.height,vx:(Math.random()-.5)*.5,vy:(Math.random()-.5)*.5}));const a=()=>{i.clearRect(0,0,s.width,s.height),i.fillStyle="#39FFDA",i.strokeStyle="#39FFDA";const l=t.current;l.forEach((c,u)=>{c.x+=c.vx,c.y+=c.vy,(c.x<0||c.x>s.width)&&(c.vx*=-1),(c.y<0||c.y>s.height)&&(c.vy*=-1),i.globalAlpha=.2,i.beginPath(),i.arc(c.x,c.y,2,0,Math.PI*2),i.fill(),i.globalAlpha=.05,l.forEach((d,f)=>{if(u===f)return;const m=c.x-d.x,p=c.y-d.y;Math.sqrt(m*m+p*p)<150&&(i.beginPath(),i.moveTo(c.x,c.y),i.lineTo(d.x,d.y),i.stroke())})}),n.current=requestAnimationFrame(a)};return a(),()=>{window.removeEventListener("resize",o),n.current&&cancelAnimationFrame(n.current)}},[]),h.jsx("canvas",{ref:e,className:"absolute inset-0 -z-10",style:{background:"transparent"}})};function Ch({peerCount:e,isLLMHost:t,onNavigatePeers:n,onNavigateAnalytics:s,username:i,onLogout:o}){return h.jsx("div",{className:"fixed top-0 left-0 right-0 z-50 bg-surface-2/75 backdrop-blur-md border-b border-divider",children:h.jsxs("div",{className:"max-w-7xl mx-auto px-4 h-16 flex items-center justify-between",children:[h.jsxs("div",{className:"flex items-center gap-2",children:[h.jsx(_e,{className:"h-7 w-7 text-accent"}),h.jsx("h1",{className:"text-2xl font-semibold text-bright",children:"MeshMind"})]}),h.jsxs("div",{className:"flex items-center gap-4",children:[h.jsxs("div",{className:"flex items-center gap-1 text-sm text-accent",children:[h.jsx(Jt,{className:"h-4 w-4"}),h.jsxs("span",{children:[e," peers"]})]}),h.jsxs("div",{className:"flex items-center gap-1 text-sm",children:[h.jsx(ea,{className:`h-4 w-4 ${t?"text-accent":"text-dim"}`}),h.jsx("span",{className:"text-bright/90",children:t?"LLM Host":"LLM Client"})]}),h.jsx("button",{onClick:n,className:"px-3 py-1.5 rounded-md text-sm bg-panel hover:bg-panel-hover border border-divider text-bright transition-colors",title:"Peer Conversations",children:"Peer Conversations"}),s&&h.jsx("button",{onClick:s,className:"px-3 py-1.5 rounded-md text-sm bg-panel hover:bg-panel-hover border border-divider text-bright transition-colors",title:"Analytics Dashboard",children:"Analytics"}),i&&h.jsxs("div",{className:"flex items-center gap-3 pl-4 ml-4 border-l border-divider text-sm",children:[h.jsx("span",{className:"text-accent font-medium",children:i}),o&&h.jsxs("button",{onClick:o,className:"px-3 py-1.5 rounded-md text-sm bg-panel hover:bg-panel-hover border border-divider text-bright transition-colors flex items-center gap-1",title:"Logout",children:[h.jsx(ta,{className:"h-4 w-4"}),"Logout"]})]})]})]})})}const jh=()=>{const[e,t]=b.useState(""),n="Welcome to MeshMind🧠";return b.useEffect(()=>{let s=0;const i=setInterval(()=>{s<=n.length?(t(n.slice(0,s)),s++):clearInterval(i)},100);return()=>clearInterval(i)},[]),h.jsxs("h2",{className:"text-3xl font-bold text-bright mb-2 tracking-wide text-center",children:[e,h.jsx("span",{className:"animate-blink",children:"|"})]})};function Dh({onLoadSaved:e,savedCount:t,onCardClick:n}){const s=[{icon:na,title:"Greeting",description:"Start with a friendly hello",question:"Hello! How are you doing today? Can you tell me a bit about yourself and what you can help me with?"},{icon:sa,title:"Learn",description:"Ask about complex topics",question:"I'd like to learn something new today. Can you explain a complex topic in a simple and engaging way? Feel free to choose something interesting."},{icon:ia,title:"Code",description:"Get help with programming",question:"I need help with programming. Can you help me write, debug, or explain code? I'm working on a project and would appreciate your assistance."},{icon:ra,title:"General",description:"Ask anything you want",question:"I have a question for you. Can you help me with anything you think might be useful or interesting?"}];return h.jsx("div",{className:"w-full h-full flex items-center justify-center",children:h.jsxs("div",{className:"px-8 py-8 rounded-2xl bg-[#111111]/60 shadow-[0_0_20px_rgba(0,245,212,0.06)] border border-[#1a1a1a] backdrop-blur-md",style:{width:"640px",maxWidth:"90vw"},children:[h.jsx(_e,{className:"h-16 w-16 text-[#00f5d4] mx-auto mb-4 drop-shadow-[0_0_8px_#00f5d4] animate-float"}),h.jsx(jh,{}),h.jsx("p",{className:"text-gray-400 mb-4 text-lg text-center",children:"Start a conversation with your AI assistant. Your message will be processed by the best available LLM in the network."}),h.jsx("div",{className:"grid grid-cols-2 gap-3 mb-6 mt-6",children:s.map((i,o)=>{const r=i.icon;return h.jsxs("div",{onClick:()=>n==null?void 0:n(i.question),className:"p-4 rounded-lg bg-[#0a0a0a]/80 border border-[#1a1a1a] hover:border-[#00f5d4]/30 transition-all cursor-pointer hover:shadow-[0_0_12px_rgba(0,245,212,0.1)] active:scale-95",children:[h.jsx(r,{className:"h-6 w-6 text-[#00f5d4] mb-2"}),h.jsx("h3",{className:"text-sm font-semibold text-white mb-1",children:i.title}),h.jsx("p",{className:"text-xs text-gray-400",children:i.description})]},o)})}),h.jsxs("div",{className:"flex flex-col items-center",children:[h.jsx("button",{onClick:e,disabled:!(t>0),className:`px-5 py-2 rounded-lg shadow-md transition-transform ${t>0?"bg-gradient-to-r from-[#00f5d4] to-[#ff4ff8] text-black hover:scale-[1.02]":"bg-[#222] text-gray-400 cursor-not-allowed opacity-60"}`,children:"Load previous conversation"}),h.jsx("div",{className:"mt-2 text-xs text-gray-400",children:t>0?h.jsxs("span",{children:[t," messages available"]}):h.jsx("span",{children:"No saved conversation found"})})]})]})})}function Nh(){const[e,t]=b.useState([]),[n,s]=b.useState(!0),[i,o]=b.useState(null),[r,a]=b.useState(!1),[l,c]=b.useState(null),[u,d]=b.useState({});if(b.useEffect(()=>{fetch(`${X}/api/local`).then(v=>v.json()).then(v=>{var T;(T=v==null?void 0:v.host_info)!=null&&T.ip_address&&c(v.host_info.ip_address)}).catch(()=>{});async function p(){s(!0),o(null);try{const v=new AbortController,T=setTimeout(()=>v.abort(),5e3),P=await Ph();clearTimeout(T),t(P)}catch(v){v.name==="AbortError"?o("Request timeout"):o(v.message||"Failed to fetch files")}s(!1)}async function g(){try{const v=await Ah(),T={};Object.entries(v).forEach(([P,S])=>{var E;const A=(E=S==null?void 0:S.host_info)==null?void 0:E.hostname;A&&typeof A=="string"&&A.trim().length>0&&(T[P]=A)}),d(T)}catch{}}p(),g();const y=setInterval(p,5e3),w=setInterval(g,1e4);return()=>{clearInterval(y),clearInterval(w)}},[]),i&&e.length===0)return h.jsx("div",{className:"p-4 text-red-500",children:i});if(e.length===0&&n)return h.jsx("div",{className:"p-4 text-center text-gray-400",children:"Loading shared files..."});const f=l?e.filter(p=>p.uploader_ip===l||p.uploader_ip==="127.0.0.1"):[],m=l?e.filter(p=>p.uploader_ip!==l&&p.uploader_ip!=="127.0.0.1"):e;return h.jsx("div",{className:"w-full mb-3",children:h.jsxs("div",{className:"bg-panel border border-divider rounded-lg shadow-soft overflow-hidden",style:{maxHeight:r?"60px":"400px",transition:"max-height 0.3s ease"},children:[h.jsxs("button",{onClick:()=>a(!r),className:"w-full flex items-center justify-between p-4 bg-surface/40 border-b border-divider hover:bg-surface/60 transition-colors",children:[h.jsxs("h2",{className:"text-lg font-semibold text-accent flex items-center gap-2",children:[h.jsx(Jt,{className:"w-5 h-5"}),"Shared Files (",e.length,")"]}),r?h.jsx(oa,{className:"w-5 h-5 text-dim"}):h.jsx(aa,{className:"w-5 h-5 text-dim"})]}),!r&&h.jsx("div",{className:"p-4 overflow-y-auto overflow-x-hidden",style:{maxHeight:"340px"},children:e.length===0?h.jsx("div",{className:"text-dim text-center py-4",children:"No files shared yet."}):h.jsxs("div",{className:"space-y-4",children:[m.length>0&&h.jsxs("div",{children:[h.jsxs("h3",{className:"text-sm font-semibold text-accent mb-2 flex items-center gap-2",children:[h.jsx(Jt,{className:"w-4 h-4"}),"From Peers (",m.length,")"]}),h.jsx("ul",{className:"space-y-2",children:m.map(p=>h.jsxs("li",{className:"flex items-center justify-between gap-3 bg-surface border border-divider rounded-lg px-3 py-2 hover:bg-surface/80 transition-colors min-w-0",children:[h.jsxs("div",{className:"min-w-0",children:[h.jsx("div",{className:"font-mono text-sm text-bright truncate",title:p.filename,children:p.filename}),h.jsxs("div",{className:"text-[11px] text-dim mt-0.5 flex items-center gap-2",children:[h.jsxs("span",{children:[(p.file_size/1024).toFixed(1)," KB"]}),h.jsxs("span",{className:"hidden md:inline-flex items-center gap-1 text-accent",children:[h.jsx(cs,{className:"w-3 h-3"})," ",u[p.uploader_ip]||p.uploader_ip]})]})]}),h.jsxs("div",{className:"flex items-center gap-2 flex-shrink-0",children:[h.jsx("button",{onClick:()=>window.dispatchEvent(new CustomEvent("meshmind:ask-file",{detail:{filename:p.filename}})),className:"h-7 w-7 inline-flex items-center justify-center bg-panel border border-divider rounded hover:bg-panel/80",title:"Ask about this file",children:h.jsx(Zt,{className:"w-4 h-4 text-accent"})}),(()=>{const y=p.uploader_ip===l||p.uploader_ip==="127.0.0.1"?`${X}/api/files/${encodeURIComponent(p.filename)}`:`${X}/api/peer-file/${p.uploader_ip}/${encodeURIComponent(p.filename)}`;return h.jsx("a",{href:y,download:p.filename,className:"h-7 w-7 inline-flex items-center justify-center bg-accent text-black rounded hover:bg-accent/90",title:"Download",children:h.jsx(us,{className:"w-4 h-4"})})})(),p.file_type.startsWith("image/")&&(()=>{const y=p.uploader_ip===l||p.uploader_ip==="127.0.0.1"?`${X}/api/files/${encodeURIComponent(p.filename)}`:`${X}/api/peer-file/${p.uploader_ip}/${encodeURIComponent(p.filename)}`;return h.jsx("a",{href:y,target:"_blank",rel:"noopener noreferrer",className:"h-7 w-7 inline-flex items-center justify-center bg-bright text-black rounded hover:bg-accent/90",title:"Preview image",children:h.jsx(ds,{className:"w-4 h-4"})})})()]})]},p.filename+p.upload_time))})]}),f.length>0&&h.jsxs("div",{children:[h.jsxs("h3",{className:"text-sm font-semibold text-bright mb-2 flex items-center gap-2",children:[h.jsx(cs,{className:"w-4 h-4"}),"My Files (",f.length,")"]}),h.jsx("ul",{className:"space-y-2",children:f.map(p=>h.jsxs("li",{className:"flex items-center justify-between gap-3 bg-surface border border-divider rounded-lg px-3 py-2 hover:bg-surface/80 transition-colors min-w-0",children:[h.jsxs("div",{className:"min-w-0",children:[h.jsx("div",{className:"font-mono text-sm text-bright truncate",title:p.filename,children:p.filename}),h.jsxs("div",{className:"text-[11px] text-dim mt-0.5",children:[(p.file_size/1024).toFixed(1)," KB • by me"]})]}),h.jsxs("div",{className:"flex items-center gap-2 flex-shrink-0",children:[h.jsx("button",{onClick:()=>window.dispatchEvent(new CustomEvent("meshmind:ask-file",{detail:{filename:p.filename}})),className:"h-7 w-7 inline-flex items-center justify-center bg-panel border border-divider rounded hover:bg-panel/80",title:"Ask about this file",children:h.jsx(Zt,{className:"w-4 h-4 text-accent"})}),h.jsx("a",{href:`${X}/api/files/${encodeURIComponent(p.filename)}`,download:p.filename,className:"h-7 w-7 inline-flex items-center justify-center bg-accent text-black rounded hover:bg-accent/90",title:"Download",children:h.jsx(us,{className:"w-4 h-4"})}),p.file_type.startsWith("image/")&&h.jsx("a",{href:`${X}/api/files/${encodeURIComponent(p.filename)}`,target:"_blank",rel:"noopener noreferrer",className:"h-7 w-7 inline-flex items-center justify-center bg-bright text-black rounded hover:bg-accent/90",title:"Preview image",children:h.jsx(ds,{className:"w-4 h-4"})})]})]},p.filename+p.upload_time))})]})]})})]})})}function Mh(){return h.jsxs("aside",{className:"w-full bg-surface/70 backdrop-blur-sm",children:[h.jsx("div",{className:"h-16 border-b border-divider flex items-center px-4 text-sm text-bright/80",children:"Shared Files"}),h.jsx("div",{className:"p-3",children:h.jsx(Nh,{})})]})}const Vh=b.lazy(()=>Gi(()=>import("./PeersConversation-CpTOaqGB.js"),__vite__mapDeps([0,1,2])).then(e=>({default:e.PeersConversation}))),Lh=b.lazy(()=>Gi(()=>import("./AnalyticsDashboard-Cvv0TozQ.js"),__vite__mapDeps([3,1,2,4])));function Oh(){const[e,t]=b.useState(0),[n,s]=b.useState(!1);return b.useEffect(()=>{const i=async()=>{try{const a=await(await fetch("/api/status")).json();typeof a.peer_count=="number"&&t(a.peer_count),typeof a.is_llm_host=="boolean"&&s(a.is_llm_host)}catch{t(0),s(!1)}};i();const o=setInterval(i,5e3);return()=>clearInterval(o)},[]),{peerCount:e,isLLMHost:n}}function kh({onNavigate:e,conversation:t,setConversation:n,isTyping:s,setIsTyping:i,savedLocal:o,loadSavedLocal:r,username:a,onLogout:l}){const{peerCount:c,isLLMHost:u}=Oh(),[d,f]=b.useState(""),[m,p]=b.useState(!1),g=b.useRef(null),y=()=>{var P;(P=g.current)==null||P.scrollIntoView({behavior:"smooth"})};b.useEffect(()=>{y()},[t]),b.useEffect(()=>{},[t.length]);const w=async()=>{if(d.trim()===""||s)return;const P={role:"user",content:d},S=[...t,P];n(S),f(""),i(!0);try{const A=await qi(d);i(!1),n([...S,{role:"assistant",content:A}])}catch(A){console.error("Error getting LLM response:",A),i(!1),n([...S,{role:"assistant",content:"Sorry, I encountered an error while processing your request."}])}},v=async P=>{if(d.trim()===""||s)return;const S={role:"user",content:d},A=[...t,S];n(A),f(""),i(!0);try{const E=await qi(S.content,P);i(!1),n([...A,{role:"assistant",content:E}])}catch(E){console.error("Error getting LLM response:",E),i(!1),n([...A,{role:"assistant",content:"Sorry, I encountered an error while processing your request."}])}};b.useEffect(()=>{const P=S=>{var C;const E=(C=S.detail)==null?void 0:C.filename;E&&(f(`Explain the contents of ${E}`),setTimeout(()=>v(E),50))};return window.addEventListener("meshmind:ask-file",P),()=>window.removeEventListener("meshmind:ask-file",P)},[t,s,d]);const T=P=>{const S={role:"user",content:`📎 Shared file: ${P.filename}`,timestamp:new Date().toISOString(),fileInfo:P};n([...t,S]),p(!1)};return h.jsxs("div",{className:"h-screen w-full text-white font-inter flex flex-col overflow-hidden",style:{background:"#0b0b0b"},children:[h.jsx("div",{className:"flex-shrink-0 relative z-50 h-16",children:h.jsx(Ch,{peerCount:c,isLLMHost:u,onNavigatePeers:()=>e("peers"),onNavigateAnalytics:()=>e("analytics"),username:a,onLogout:l})}),h.jsxs(te.div,{className:"flex-1 flex gap-4 overflow-hidden relative",initial:"initial",animate:"animate",variants:Wd(.08),children:[h.jsx(te.div,{variants:qd,className:"w-[320px] flex-shrink-0 flex flex-col border-r border-divider bg-surface/40 transition-all duration-300 will-change-transform will-change-opacity z-20",children:h.jsx("div",{className:"flex-1 overflow-auto p-4 h-[calc(100vh-4rem)]",style:{scrollbarWidth:"auto",scrollbarColor:"#00bfa6 #1a1a1a"},children:h.jsx(Mh,{})})}),h.jsx(te.div,{variants:Ao,className:"flex-1 flex flex-col items-center justify-start px-4 py-6 overflow-hidden will-change-transform will-change-opacity",children:h.jsxs("div",{className:"w-full h-full flex flex-col rounded-2xl border border-divider bg-panel/80 backdrop-blur-sm shadow-soft overflow-hidden z-0",style:{maxWidth:"calc(100% - 2rem)"},children:[h.jsx("div",{className:"flex-1 flex flex-col overflow-hidden",children:t.length===0?h.jsxs("div",{className:"flex-1 relative overflow-y-auto",children:[h.jsx(Yo,{}),h.jsx("div",{className:"absolute inset-0 flex flex-col items-center justify-center",children:h.jsx(Dh,{onLoadSaved:r,savedCount:o&&o.messages?o.messages.length:0,onCardClick:P=>{f(P)}})})]}):h.jsxs("div",{className:"flex-1 overflow-y-auto px-4 py-4 space-y-3 scroll-smooth min-h-0",children:[h.jsx(Ji,{initial:!1,children:t.map((P,S)=>{var A;return h.jsxs(te.div,{initial:{opacity:0,y:10},animate:{opacity:1,y:0,transition:{duration:.25,ease:"easeInOut"}},exit:{opacity:0,y:6,transition:{duration:.2,ease:"easeInOut"}},className:"flex items-start gap-3 py-3 px-4 rounded-lg transition-all duration-200 bg-surface border border-divider",children:[P.role==="assistant"?h.jsx(fs,{className:"w-6 h-6 text-accent mt-1 flex-shrink-0"}):h.jsx("div",{className:"w-6 h-6 rounded-full bg-surface-2 border border-divider flex items-center justify-center flex-shrink-0",children:h.jsx("span",{className:"text-xs text-white font-medium",children:"You"})}),h.jsxs("div",{className:"flex-1 min-w-0",children:[h.jsx("p",{className:"text-bright whitespace-pre-wrap leading-relaxed text-sm",children:P.content}),((A=P.fileInfo)==null?void 0:A.filename)&&h.jsx("div",{className:"mt-2",children:h.jsxs("button",{onClick:()=>{const E=P.fileInfo.filename;f(`Explain the contents of ${E}`),setTimeout(()=>v(E),50)},className:"inline-flex items-center gap-1 px-2 py-1 rounded-md text-xs bg-panel hover:bg-panel-hover border border-divider text-bright/90",children:[h.jsx(Zt,{className:"h-3.5 w-3.5 text-accent"}),"Ask about this file"]})})]})]},S)})}),s&&h.jsxs("div",{className:"flex items-center gap-3 py-3 px-4 rounded-lg bg-surface border border-divider",children:[h.jsx(fs,{className:"w-6 h-6 text-accent flex-shrink-0"}),h.jsxs("div",{className:"flex items-center gap-2",children:[h.jsx(la,{className:"w-4 h-4 text-gray-400 animate-spin"}),h.jsx("span",{className:"text-gray-400 text-sm",children:"Thinking..."})]})]}),h.jsx("div",{ref:g})]})}),h.jsxs("div",{className:"flex-shrink-0 border-t border-divider bg-panel/80 p-4 space-y-2 z-10 backdrop-blur-sm",children:[m&&h.jsx("div",{className:"mb-3",children:h.jsx(Rh,{onFileUploaded:T})}),h.jsxs("form",{onSubmit:P=>{P.preventDefault(),w()},className:"space-y-2",children:[h.jsxs("div",{className:"relative",children:[h.jsx("textarea",{value:d,onChange:P=>f(P.target.value),onKeyDown:P=>{P.key==="Enter"&&!P.shiftKey&&(P.preventDefault(),w())},placeholder:"Ask anything... (Shift+Enter for new line)",className:"w-full bg-surface border border-divider text-bright px-4 py-3 pr-20 rounded-lg focus:outline-none focus:border-accent focus:ring-1 focus:ring-accent/20 resize-none min-h-[40px] max-h-20 shadow transition-all duration-200 overflow-hidden",style:{fontSize:"0.95rem",height:"auto"},rows:1,onInput:P=>{const S=P.target;S.style.height="auto",S.style.height=Math.min(S.scrollHeight,128)+"px"}}),h.jsx(te.button,{type:"button",onClick:()=>p(!m),className:"absolute right-12 bottom-3 h-8 w-8 flex items-center justify-center text-gray-400 hover:text-accent transition-colors duration-200",title:"Attach file",whileHover:{scale:1.05},whileTap:{scale:.98},children:h.jsx(ca,{className:"h-5 w-5"})}),h.jsx(te.button,{type:"submit",disabled:s||!d.trim(),className:"absolute right-3 bottom-3 h-8 w-8 flex items-center justify-center bg-accent hover:bg-accent/90 disabled:opacity-50 disabled:hover:bg-accent rounded-full transition-all duration-200 shadow-lg",whileTap:{scale:.95},children:h.jsx(ua,{className:"h-5 w-5 text-black"})})]}),h.jsxs("div",{className:"flex justify-between items-center text-xs text-gray-500",children:[h.jsx("span",{children:"Press Enter to send, Shift+Enter for new line"}),h.jsxs("span",{className:d.length>1e3?"text-accent":"",children:[d.length,"/2000"]})]})]})]})]})})]})]})}function Fh({username:e,onLogout:t}){const[n,s]=b.useState("chat"),[i,o]=b.useState([]),[r,a]=b.useState(!1),[l,c]=b.useState(null);b.useEffect(()=>{(async()=>{try{const f=await Eh();c(f)}catch(f){console.error("Failed to fetch local conversation:",f)}})()},[]);const u=()=>{if(!l||!l.messages)return;const d=l.messages.map(f=>({role:f.message_type==="Response"?"assistant":"user",content:f.content,timestamp:f.timestamp,hostInfo:f.host_info}));o(d)};return h.jsx(Ji,{mode:"wait",children:h.jsx(te.div,{variants:Kd,initial:"initial",animate:"animate",exit:"exit",className:"bg-dark",children:n==="chat"?h.jsx(kh,{onNavigate:s,conversation:i,setConversation:o,isTyping:r,setIsTyping:a,savedLocal:l,loadSavedLocal:u,username:e,onLogout:t}):n==="analytics"?h.jsxs("div",{className:"min-h-screen text-white",children:[h.jsx("div",{className:"fixed top-4 left-4 z-10",children:h.jsxs("button",{onClick:()=>s("chat"),className:"flex items-center gap-2 px-4 py-2 bg-panel hover:bg-panel-hover rounded-lg text-white border border-[#232323]",children:[h.jsx(_e,{className:"h-5 w-5"}),h.jsx("span",{children:"Back to Chat"})]})}),h.jsx("div",{className:"pt-16",children:h.jsx(b.Suspense,{fallback:h.jsx("div",{className:"p-6 text-gray-400",children:"Loading analytics…"}),children:h.jsx(Lh,{})})})]}):h.jsxs("div",{children:[h.jsx("div",{className:"fixed top-4 left-4 z-10",children:h.jsxs("button",{onClick:()=>s("chat"),className:"flex items-center gap-2 px-4 py-2 bg-panel hover:bg-panel-hover rounded-lg text-white",children:[h.jsx(_e,{className:"h-5 w-5"}),h.jsx("span",{children:"Back to Chat"})]})}),h.jsx(b.Suspense,{fallback:h.jsx("div",{className:"p-6 text-gray-400",children:"Loading peers…"}),children:h.jsx(Vh,{})})]})},n)})}function Bh({onAuthenticated:e}){const[t,n]=b.useState(""),[s,i]=b.useState(""),[o,r]=b.useState(null),[a,l]=b.useState(!0);b.useEffect(()=>{(async()=>{try{const u=await Xo();u.authenticated&&u.username&&e(u.username)}catch{}l(!1)})()},[e]);const c=async u=>{u.preventDefault(),r(null);try{const d=await Sh(t,s);d.authenticated&&d.username?e(d.username):r("Invalid credentials")}catch{r("Login failed")}};return a?h.jsx("div",{className:"min-h-screen flex items-center justify-center text-gray-300",children:"Loading…"}):h.jsxs("div",{className:"min-h-screen relative flex items-center justify-center bg-dark overflow-hidden",children:[h.jsx("div",{className:"absolute inset-0",children:h.jsx(Yo,{})}),h.jsx("div",{className:"pointer-events-none absolute inset-0 bg-[radial-gradient(600px_circle_at_center,rgba(0,191,166,0.08),transparent_60%)]"}),h.jsx("div",{className:"pointer-events-none absolute inset-0 bg-black/10"}),h.jsxs(te.form,{initial:"initial",animate:"animate",variants:Ao,onSubmit:c,className:"relative z-10 w-full max-w-sm p-6 rounded-xl bg-white/5 backdrop-blur-md border border-white/10 shadow-soft will-change-transform will-change-opacity",children:[h.jsxs("div",{className:"mb-4 flex items-center justify-center gap-2",children:[h.jsx(_e,{className:"h-7 w-7 text-accent"}),h.jsx("span",{className:"text-2xl font-semibold text-bright",children:"MeshMind"})]}),h.jsx("p",{className:"text-center text-dim text-sm mb-5",children:"Enterprise AI Network"}),h.jsx("h1",{className:"text-base font-medium text-bright/90 mb-3",children:"Sign in"}),h.jsxs("div",{className:"space-y-3",children:[h.jsxs("div",{children:[h.jsx("label",{className:"block text-sm text-dim mb-1",children:"Username"}),h.jsx("input",{value:t,onChange:u=>n(u.target.value),className:"w-full bg-surface border border-divider text-bright px-3 py-2 rounded-md focus:outline-none focus:border-accent focus:ring-1 focus:ring-accent/20"})]}),h.jsxs("div",{children:[h.jsx("label",{className:"block text-sm text-dim mb-1",children:"Password"}),h.jsx("input",{type:"password",value:s,onChange:u=>i(u.target.value),className:"w-full bg-surface border border-divider text-bright px-3 py-2 rounded-md focus:outline-none focus:border-accent focus:ring-1 focus:ring-accent/20"})]})]}),o&&h.jsx("div",{className:"mt-3 text-sm text-red-400",children:o}),h.jsx(te.button,{whileHover:{scale:1.05},whileTap:{scale:.98},type:"submit",className:"mt-5 w-full py-2 bg-gradient-to-r from-accent to-accent-secondary text-black rounded-md shadow-soft hover:shadow-glow transition-shadow",children:"Login"})]})]})}function Ih(){const[e,t]=b.useState(!1),[n,s]=b.useState(null);if(b.useEffect(()=>{(async()=>{try{const o=await Xo();t(o.authenticated),s(o.username??null)}catch{}})()},[]),!e)return h.jsx(Bh,{onAuthenticated:o=>{t(!0),s(o)}});const i=async()=>{try{await Th()}catch{}t(!1),s(null)};return h.jsx(Fh,{username:n??void 0,onLogout:i})}Hi(document.getElementById("root")).render(h.jsx(b.StrictMode,{children:h.jsx(Ih,{})}));export{Ah as g,h as j};
@@ -34,8 +34,9 @@ export async function getAllSharedFiles(): Promise<FileInfo[]> {
 }
 import axios from 'axios';
 
-// Use same-origin URLs so it works in production and dev (proxied)
-export const API_BASE_URL = '';
+// Use same-origin URLs so it works in production and dev (proxied); behind a reverse proxy the
+// node puts its base path in a meta tag
+export const API_BASE_URL = document.querySelector<HTMLMetaElement>('meta[name="base-path"]')?.content ?? '';
 export const API_ENDPOINT = `/api`;
 
 export interface Message {